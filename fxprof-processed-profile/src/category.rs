@@ -108,7 +108,7 @@ impl From<CategoryHandle> for SubcategoryHandle {
 }
 
 /// The information about a category.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct InternalCategory {
     name: String,
     color: CategoryColor,
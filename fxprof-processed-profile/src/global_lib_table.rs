@@ -7,7 +7,7 @@ use crate::fast_hash_map::{FastHashMap, FastIndexSet};
 use crate::string_table::ProfileStringTable;
 use crate::{LibraryInfo, StringHandle, SymbolTable};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct GlobalLibTable {
     /// All libraries added via `Profile::handle_for_lib`. May or may not be used.
     /// Indexed by `LibraryHandle.0`.
@@ -60,6 +60,7 @@ mod native_symbols;
 mod process;
 mod profile;
 mod profile_symbol_info;
+mod profiler_overhead;
 mod reference_timestamp;
 mod resource_table;
 mod sample_table;
@@ -93,6 +94,7 @@ pub use profile::{
     FrameHandle, FrameSymbolInfo, Profile, SamplingInterval, SourceLocation, StackHandle,
     TimelineUnit,
 };
+pub use profiler_overhead::ProfilerOverhead;
 pub use reference_timestamp::{PlatformSpecificReferenceTimestamp, ReferenceTimestamp};
 pub use sample_table::WeightType;
 pub use string_table::StringHandle;
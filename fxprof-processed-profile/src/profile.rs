@@ -28,6 +28,7 @@ use crate::markers::{
 use crate::native_symbols::NativeSymbolHandle;
 use crate::process::{Process, ThreadHandle};
 use crate::profile_symbol_info::{LibSymbolInfo, ProfileSymbolInfo};
+use crate::profiler_overhead::ProfilerOverhead;
 use crate::reference_timestamp::ReferenceTimestamp;
 use crate::sample_table::WeightType;
 use crate::string_table::{ProfileStringTable, StringHandle};
@@ -165,7 +166,7 @@ pub enum TimelineUnit {
 /// # Ok(())
 /// # }
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Profile {
     pub(crate) product: String,
     pub(crate) os_name: Option<String>,
@@ -185,6 +186,7 @@ pub struct Profile {
     pub(crate) marker_schemas: Vec<InternalMarkerSchema>,
     static_schema_marker_types: FastHashMap<&'static str, MarkerTypeHandle>,
     pub(crate) symbolicated: bool,
+    pub(crate) profiler_overhead: Option<ProfilerOverhead>,
     used_pids: FastHashMap<u32, u32>,
     used_tids: FastHashMap<u32, u32>,
 }
@@ -221,6 +223,7 @@ impl Profile {
             categories,
             static_schema_marker_types: FastHashMap::default(),
             symbolicated: false,
+            profiler_overhead: None,
             used_pids: FastHashMap::default(),
             used_tids: FastHashMap::default(),
             counters: Vec::new(),
@@ -1249,6 +1252,7 @@ impl Profile {
             marker_schemas,
             static_schema_marker_types,
             symbolicated,
+            profiler_overhead,
             used_pids,
             used_tids,
         } = self;
@@ -1280,6 +1284,7 @@ impl Profile {
             marker_schemas,
             static_schema_marker_types,
             symbolicated,
+            profiler_overhead,
             used_pids,
             used_tids,
         }
@@ -1361,6 +1366,16 @@ impl Profile {
         self.symbolicated = v;
     }
 
+    /// Record how many resources the profiler itself used during the recording.
+    pub fn set_profiler_overhead(&mut self, overhead: ProfilerOverhead) {
+        self.profiler_overhead = Some(overhead);
+    }
+
+    /// The profiler overhead set with [`Profile::set_profiler_overhead`], if any.
+    pub fn profiler_overhead(&self) -> Option<&ProfilerOverhead> {
+        self.profiler_overhead.as_ref()
+    }
+
     /// Returns a flattened list of `ThreadHandle`s in the right order.
     ///
     // The processed profile format has all threads from all processes in a flattened threads list.
@@ -1498,6 +1513,9 @@ impl Serialize for SerializableProfileMeta<'_> {
             None => {}
         }
         map.serialize_entry("symbolicated", &self.0.symbolicated)?;
+        if let Some(overhead) = &self.0.profiler_overhead {
+            map.serialize_entry("recordingOverhead", overhead)?;
+        }
         map.serialize_entry("pausedRanges", &[] as &[()])?;
         map.serialize_entry("version", &24)?; // this version is ignored, only "preprocessedProfileVersion" is used
         map.serialize_entry("usesOnlyOneStackType", &(!self.0.contains_js_frame()))?;
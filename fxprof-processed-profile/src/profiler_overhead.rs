@@ -0,0 +1,64 @@
+use std::fmt;
+use std::time::Duration;
+
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+/// Resources used by the profiler itself while it was recording.
+///
+/// This lets consumers of the profile check that the observations aren't
+/// dominated by the observer. In the profile JSON, this is stored as
+/// `meta.recordingOverhead`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProfilerOverhead {
+    /// The wall-clock duration of the recording.
+    pub duration: Duration,
+    /// The CPU time that the profiler process used during the recording,
+    /// summed over all of its threads.
+    pub cpu_time: Duration,
+    /// The peak resident memory of the profiler process, if known.
+    pub peak_memory_bytes: Option<u64>,
+    /// The number of events (or event chunks) which were lost because the
+    /// profiler couldn't keep up.
+    pub lost_events: u64,
+}
+
+impl ProfilerOverhead {
+    /// The profiler's CPU usage as a percentage of one core, averaged over the
+    /// recording duration.
+    pub fn cpu_percent_of_one_core(&self) -> f64 {
+        if self.duration.is_zero() {
+            return 0.0;
+        }
+        self.cpu_time.as_secs_f64() / self.duration.as_secs_f64() * 100.0
+    }
+}
+
+/// A one-line summary, e.g.
+/// "samply overhead: 2.1% of one core, 40 MB peak memory, 0 lost events".
+impl fmt::Display for ProfilerOverhead {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "samply overhead: {:.1}% of one core",
+            self.cpu_percent_of_one_core()
+        )?;
+        if let Some(bytes) = self.peak_memory_bytes {
+            write!(f, ", {} MB peak memory", bytes / 1_000_000)?;
+        }
+        write!(f, ", {} lost events", self.lost_events)
+    }
+}
+
+impl Serialize for ProfilerOverhead {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("durationMs", &(self.duration.as_secs_f64() * 1000.0))?;
+        map.serialize_entry("cpuTimeMs", &(self.cpu_time.as_secs_f64() * 1000.0))?;
+        map.serialize_entry("cpuPercentOfOneCore", &self.cpu_percent_of_one_core())?;
+        if let Some(peak_memory_bytes) = self.peak_memory_bytes {
+            map.serialize_entry("peakMemoryBytes", &peak_memory_bytes)?;
+        }
+        map.serialize_entry("lostEvents", &self.lost_events)?;
+        map.end()
+    }
+}
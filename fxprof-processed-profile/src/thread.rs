@@ -22,7 +22,7 @@ use crate::{DynamicSchemaMarker, MarkerHandle, MarkerTiming, MarkerTypeHandle, S
 #[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
 pub struct ProcessHandle(pub(crate) usize);
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Thread {
     process: ProcessHandle,
     tid: String,
@@ -0,0 +1,242 @@
+//! Python bindings for samply-analysis.
+//!
+//! This is a thin wrapper: each method runs the corresponding
+//! [`ProfileAnalyzer`] query and converts the result into Python objects.
+//! Tabular results (hotspots, callers, callees, diff) are returned as lists of
+//! flat dicts with the same keys in every row, so that `pandas.DataFrame(rows)`
+//! gives one column per field. Missing values are `None`.
+
+use std::path::PathBuf;
+
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use samply_analysis::{AnalysisError, CalleeEntry, CallerEntry, ProfileAnalyzer, TimeRange};
+use serde_json::{json, Value};
+
+fn to_py_err(err: AnalysisError) -> PyErr {
+    match err {
+        AnalysisError::IoError(e) => PyOSError::new_err(e.to_string()),
+        e => PyValueError::new_err(e.to_string()),
+    }
+}
+
+fn to_py(py: Python<'_>, value: &Value) -> PyResult<PyObject> {
+    Ok(match value {
+        Value::Null => py.None(),
+        Value::Bool(b) => b.into_py(py),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                i.into_py(py)
+            } else if let Some(u) = n.as_u64() {
+                u.into_py(py)
+            } else {
+                n.as_f64().unwrap_or(f64::NAN).into_py(py)
+            }
+        }
+        Value::String(s) => s.into_py(py),
+        Value::Array(items) => {
+            let list = PyList::empty_bound(py);
+            for item in items {
+                list.append(to_py(py, item)?)?;
+            }
+            list.into_py(py)
+        }
+        Value::Object(map) => {
+            let dict = PyDict::new_bound(py);
+            for (key, value) in map {
+                dict.set_item(key, to_py(py, value)?)?;
+            }
+            dict.into_py(py)
+        }
+    })
+}
+
+fn rows_to_py(py: Python<'_>, rows: Vec<Value>) -> PyResult<PyObject> {
+    to_py(py, &Value::Array(rows))
+}
+
+/// Flatten a caller tree into rows; `parent` is the function each row's
+/// function calls (for depth 1, the queried function).
+fn flatten_callers(entries: &[CallerEntry], parent: &str, depth: usize, rows: &mut Vec<Value>) {
+    for entry in entries {
+        rows.push(json!({
+            "depth": depth,
+            "name": entry.name,
+            "parent": parent,
+            "library": entry.library,
+            "file_path": entry.file_path,
+            "line_number": entry.line_number,
+            "call_count": entry.call_count,
+            "percent": entry.percent,
+        }));
+        flatten_callers(&entry.callers, &entry.name, depth + 1, rows);
+    }
+}
+
+/// Flatten a callee tree into rows; `parent` is the function which calls each
+/// row's function (for depth 1, the queried function).
+fn flatten_callees(entries: &[CalleeEntry], parent: &str, depth: usize, rows: &mut Vec<Value>) {
+    for entry in entries {
+        rows.push(json!({
+            "depth": depth,
+            "name": entry.name,
+            "parent": parent,
+            "library": entry.library,
+            "file_path": entry.file_path,
+            "line_number": entry.line_number,
+            "call_count": entry.call_count,
+            "percent": entry.percent,
+        }));
+        flatten_callees(&entry.callees, &entry.name, depth + 1, rows);
+    }
+}
+
+/// A loaded profile. Create one with `pysamply.load(path)`.
+#[pyclass(frozen)]
+struct Profile {
+    analyzer: ProfileAnalyzer,
+}
+
+#[pymethods]
+impl Profile {
+    /// Profile overview: product, sample counts, threads and time range, as a dict.
+    #[pyo3(signature = (start_ms=None, end_ms=None))]
+    fn summary(
+        &self,
+        py: Python<'_>,
+        start_ms: Option<f64>,
+        end_ms: Option<f64>,
+    ) -> PyResult<PyObject> {
+        let summary = self.analyzer.get_summary(TimeRange { start_ms, end_ms });
+        to_py(
+            py,
+            &serde_json::to_value(summary).map_err(|e| PyValueError::new_err(e.to_string()))?,
+        )
+    }
+
+    /// The functions with the most self time, one row per function.
+    #[pyo3(signature = (limit=20, thread=None, start_ms=None, end_ms=None))]
+    fn hotspots(
+        &self,
+        py: Python<'_>,
+        limit: usize,
+        thread: Option<&str>,
+        start_ms: Option<f64>,
+        end_ms: Option<f64>,
+    ) -> PyResult<PyObject> {
+        let hotspots = py.allow_threads(|| {
+            self.analyzer.compute_hotspots(
+                limit,
+                thread,
+                false,
+                false,
+                TimeRange { start_ms, end_ms },
+            )
+        });
+        let rows = hotspots
+            .into_iter()
+            .map(|h| {
+                json!({
+                    "rank": h.rank,
+                    "function": h.function.name,
+                    "library": h.function.library,
+                    "file_path": h.function.file_path,
+                    "line_number": h.function.line_number,
+                    "self_samples": h.self_samples,
+                    "total_samples": h.total_samples,
+                    "self_percent": h.self_percent,
+                    "total_percent": h.total_percent,
+                })
+            })
+            .collect();
+        rows_to_py(py, rows)
+    }
+
+    /// The callers of the functions matching `function`, one row per caller
+    /// per level, with `depth` and `parent` columns describing the tree.
+    #[pyo3(signature = (function, depth=5, limit=20, start_ms=None, end_ms=None))]
+    fn callers(
+        &self,
+        py: Python<'_>,
+        function: &str,
+        depth: usize,
+        limit: usize,
+        start_ms: Option<f64>,
+        end_ms: Option<f64>,
+    ) -> PyResult<PyObject> {
+        let response = py.allow_threads(|| {
+            self.analyzer
+                .find_callers(function, depth, limit, TimeRange { start_ms, end_ms })
+        });
+        let mut rows = Vec::new();
+        flatten_callers(&response.callers, &response.function, 1, &mut rows);
+        rows_to_py(py, rows)
+    }
+
+    /// The callees of the functions matching `function`, in the same shape as
+    /// `callers`.
+    #[pyo3(signature = (function, depth=5, limit=20, start_ms=None, end_ms=None))]
+    fn callees(
+        &self,
+        py: Python<'_>,
+        function: &str,
+        depth: usize,
+        limit: usize,
+        start_ms: Option<f64>,
+        end_ms: Option<f64>,
+    ) -> PyResult<PyObject> {
+        let response = py.allow_threads(|| {
+            self.analyzer
+                .find_callees(function, depth, limit, TimeRange { start_ms, end_ms })
+        });
+        let mut rows = Vec::new();
+        flatten_callees(&response.callees, &response.function, 1, &mut rows);
+        rows_to_py(py, rows)
+    }
+
+    /// Per-function self/total time compared against `baseline`, one row per
+    /// function, sorted by the largest change first.
+    #[pyo3(signature = (baseline, limit=20))]
+    fn diff(&self, py: Python<'_>, baseline: &Profile, limit: usize) -> PyResult<PyObject> {
+        let diff = py.allow_threads(|| self.analyzer.compute_diff(&baseline.analyzer, limit));
+        let rows = diff
+            .functions
+            .iter()
+            .map(|f| {
+                let mut row = serde_json::to_value(f).unwrap_or(Value::Null);
+                // Keep the same columns in every row.
+                if let Value::Object(map) = &mut row {
+                    map.entry("self_change_percent").or_insert(Value::Null);
+                }
+                row
+            })
+            .collect();
+        rows_to_py(py, rows)
+    }
+}
+
+/// Load a profile (`.json` or `.json.gz`). With `low_memory=True`, an on-disk
+/// index is built next to the profile and memory-mapped, as with
+/// `samply-for-ai analyze --low-memory`.
+#[pyfunction]
+#[pyo3(signature = (path, low_memory=false))]
+fn load(py: Python<'_>, path: PathBuf, low_memory: bool) -> PyResult<Profile> {
+    let analyzer = py
+        .allow_threads(|| {
+            if low_memory {
+                ProfileAnalyzer::from_file_low_memory(&path, |_| {})
+            } else {
+                ProfileAnalyzer::from_file(&path)
+            }
+        })
+        .map_err(to_py_err)?;
+    Ok(Profile { analyzer })
+}
+
+#[pymodule]
+fn pysamply(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Profile>()?;
+    m.add_function(wrap_pyfunction!(load, m)?)?;
+    Ok(())
+}
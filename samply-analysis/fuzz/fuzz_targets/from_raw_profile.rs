@@ -0,0 +1,45 @@
+//! Parse arbitrary input as a profile (`from_json`, which converts the parsed
+//! profile with `from_raw_profile`), and run the queries which follow the
+//! indexes in its tables on whatever loaded. Neither may panic or hang.
+//!
+//! Run with `cargo +nightly fuzz run from_raw_profile` in `samply-analysis`;
+//! profiles from `samply record` make a good seed corpus.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use samply_analysis::{ExportFormat, ProfileAnalyzer, TimeRange};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(json) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(analyzer) = ProfileAnalyzer::from_json(json) else {
+        return;
+    };
+
+    let range = TimeRange::default();
+    analyzer.get_summary(range);
+    let hotspots = analyzer.compute_hotspots(10, None, true, true, range);
+    analyzer.compute_thread_hotspots(10, None, range);
+    analyzer.get_markers(None, None, 10, range);
+    analyzer.build_flamegraph(None, false, 0.0);
+    analyzer.build_flamegraph(None, true, 0.0);
+    analyzer.stack_stats(None, range);
+    analyzer.library_symbolication_status();
+    for format in [
+        ExportFormat::Collapsed,
+        ExportFormat::Speedscope,
+        ExportFormat::Callgrind,
+        ExportFormat::Pprof,
+    ] {
+        analyzer.export_bytes(format, None, range);
+    }
+    if let Some(hotspot) = hotspots.first() {
+        let function = &hotspot.function.name;
+        analyzer.find_callers(function, 5, 10, range);
+        analyzer.find_callees(function, 5, 10, range);
+        analyzer.drilldown(function, 5, 1.0);
+    }
+    analyzer.compute_diff(&analyzer, 10);
+});
@@ -0,0 +1,189 @@
+//! Classification of samples as running, blocked or idle, so that a thread
+//! which spends its time waiting isn't mistaken for one which burns CPU.
+//!
+//! A thread waiting for events (`epoll_wait`, `kevent`, `mach_msg`) is idle,
+//! one waiting for a lock, a condition variable or another thread (futexes,
+//! `pthread_cond_wait`, `WaitForSingleObject`) is blocked, and anything else
+//! is running. A sample is classified by the innermost function on its stack
+//! which contains one of the patterns, so that the kernel frames above a
+//! blocking system call don't hide it. Samples without a stack count as
+//! running.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::{ProfileAnalyzer, ThreadData, TimeRange};
+
+/// Functions which wait for work or events
+pub const DEFAULT_IDLE_FUNCTIONS: &[&str] = &[
+    "epoll_wait",
+    "epoll_pwait",
+    "kevent",
+    "mach_msg",
+    "__select",
+    "ppoll",
+    "__poll",
+    "__workq_kernreturn",
+    "GetQueuedCompletionStatus",
+    "WaitForWorkViaWorkerFactory",
+    "nanosleep",
+    "NtDelayExecution",
+    "SleepEx",
+];
+
+/// Functions which wait for a lock, a condition variable or another thread
+pub const DEFAULT_BLOCKED_FUNCTIONS: &[&str] = &[
+    "futex",
+    "__lll_lock_wait",
+    "pthread_cond_wait",
+    "pthread_cond_timedwait",
+    "pthread_join",
+    "sem_wait",
+    "__psynch_cvwait",
+    "__psynch_mutexwait",
+    "__ulock_wait",
+    "WaitForSingleObject",
+    "WaitForMultipleObjects",
+    "SleepConditionVariable",
+    "NtWaitForAlertByThreadId",
+    "thread::park",
+];
+
+/// The function name patterns which classify samples as idle or blocked. A
+/// function matches a pattern if its name contains it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActivityPatterns {
+    pub idle: Vec<String>,
+    pub blocked: Vec<String>,
+}
+
+impl Default for ActivityPatterns {
+    fn default() -> Self {
+        let strings = |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect();
+        ActivityPatterns {
+            idle: strings(DEFAULT_IDLE_FUNCTIONS),
+            blocked: strings(DEFAULT_BLOCKED_FUNCTIONS),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Activity {
+    Running,
+    Blocked,
+    Idle,
+}
+
+impl ActivityPatterns {
+    pub(crate) fn classify(&self, function: &str) -> Option<Activity> {
+        let matches = |patterns: &[String]| patterns.iter().any(|p| function.contains(p.as_str()));
+        if matches(&self.blocked) {
+            Some(Activity::Blocked)
+        } else if matches(&self.idle) {
+            Some(Activity::Idle)
+        } else {
+            None
+        }
+    }
+}
+
+/// How samples split into running, blocked and idle
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ActivitySplit {
+    pub running_samples: i64,
+    pub blocked_samples: i64,
+    pub idle_samples: i64,
+    pub running_percent: f64,
+    pub blocked_percent: f64,
+    pub idle_percent: f64,
+}
+
+impl ActivitySplit {
+    fn add(&mut self, activity: Activity, weight: i64) {
+        match activity {
+            Activity::Running => self.running_samples += weight,
+            Activity::Blocked => self.blocked_samples += weight,
+            Activity::Idle => self.idle_samples += weight,
+        }
+    }
+
+    /// Fill in the percentages from the sample counts
+    fn with_percentages(mut self) -> Self {
+        let total = self.running_samples + self.blocked_samples + self.idle_samples;
+        if total > 0 {
+            let percent = |samples: i64| 100.0 * samples as f64 / total as f64;
+            self.running_percent = percent(self.running_samples);
+            self.blocked_percent = percent(self.blocked_samples);
+            self.idle_percent = percent(self.idle_samples);
+        }
+        self
+    }
+}
+
+impl ThreadData {
+    /// The activity of every stack: that of its innermost matching function
+    fn stack_activities(&self, strings: &[String], patterns: &ActivityPatterns) -> Vec<Activity> {
+        let mut func_activities: HashMap<usize, Option<Activity>> = HashMap::new();
+        let mut activities: Vec<Activity> = Vec::with_capacity(self.stack_frame.len());
+        for stack_idx in 0..self.stack_frame.len() {
+            let func_idx = self.get_frame_func(self.get_stack_frame(stack_idx));
+            let own = *func_activities
+                .entry(func_idx)
+                .or_insert_with(|| patterns.classify(&self.get_func_name(func_idx, strings)));
+            // A stack's prefix is an earlier stack, so its activity is known.
+            let activity = own.unwrap_or_else(|| {
+                self.get_stack_prefix(stack_idx)
+                    .and_then(|prefix| activities.get(prefix).copied())
+                    .unwrap_or(Activity::Running)
+            });
+            activities.push(activity);
+        }
+        activities
+    }
+}
+
+impl ProfileAnalyzer {
+    /// Replace the patterns which classify samples as idle or blocked
+    pub fn set_activity_patterns(&mut self, patterns: ActivityPatterns) {
+        self.activity_patterns = Arc::new(patterns);
+    }
+
+    /// How the samples in `range` of the threads for which `include_thread`
+    /// returns true split into running, blocked and idle
+    pub(crate) fn activity_split(
+        &self,
+        include_thread: impl Fn(&ThreadData) -> bool,
+        range: TimeRange,
+    ) -> ActivitySplit {
+        let mut split = ActivitySplit::default();
+        for thread in self.threads.iter().filter(|t| include_thread(t)) {
+            let mut stack_weights: HashMap<usize, i64> = HashMap::new();
+            for (stack_idx, weight) in thread.samples_in(range) {
+                match stack_idx {
+                    Some(stack_idx) => *stack_weights.entry(stack_idx).or_default() += weight,
+                    None => split.add(Activity::Running, weight),
+                }
+            }
+            if stack_weights.is_empty() {
+                continue;
+            }
+            let activities = thread.stack_activities(&self.global_strings, &self.activity_patterns);
+            for (stack_idx, weight) in stack_weights {
+                let activity = activities
+                    .get(stack_idx)
+                    .copied()
+                    .unwrap_or(Activity::Running);
+                split.add(activity, weight);
+            }
+        }
+        split.with_percentages()
+    }
+
+    /// How the samples in `range` of the threads whose name contains
+    /// `thread_filter` split into running, blocked and idle
+    pub fn thread_activity(&self, thread_filter: Option<&str>, range: TimeRange) -> ActivitySplit {
+        self.activity_split(|thread| thread.matches_filter(thread_filter), range)
+    }
+}
@@ -0,0 +1,190 @@
+//! Top allocation sites, from the native allocations table: a byte-weighted
+//! stack per allocation, recorded by `samply record --alloc` and shown as the
+//! "native allocations" summary in the Firefox Profiler. The table is kept
+//! apart from the samples, on the main thread of each process.
+//!
+//! An allocation's site is the first function on its stack, from the leaf,
+//! which isn't part of the allocator: the caller of malloc, of `operator new`
+//! or of Rust's `alloc::` functions.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{ProfileAnalyzer, ThreadData, TimeRange};
+
+/// Allocator functions, which are skipped to find an allocation's site
+pub const ALLOCATOR_FUNCTIONS: &[&str] = &[
+    "malloc",
+    "calloc",
+    "realloc",
+    "reallocarray",
+    "posix_memalign",
+    "aligned_alloc",
+    "memalign",
+    "valloc",
+    "__libc_malloc",
+    "__libc_calloc",
+    "__libc_realloc",
+    "_int_malloc",
+    "_int_realloc",
+    "malloc_zone_malloc",
+    "_malloc_zone_malloc",
+    "RtlAllocateHeap",
+    "HeapAlloc",
+];
+
+/// Prefixes of allocator functions: C++'s allocation operators and Rust's
+/// allocation functions and collections
+const ALLOCATOR_PREFIXES: &[&str] = &[
+    "operator new",
+    "__rust_alloc",
+    "__rust_realloc",
+    "__rdl_",
+    "alloc::",
+    "<alloc::",
+];
+
+/// An allocation site, from an allocations query
+#[derive(Debug, Clone, Serialize)]
+pub struct AllocationSite {
+    pub function: String,
+    pub bytes: i64,
+    pub count: usize,
+    /// Share of all allocated bytes
+    pub percent: f64,
+}
+
+/// Response for allocations query
+#[derive(Debug, Clone, Serialize)]
+pub struct AllocationsResponse {
+    pub total_bytes: i64,
+    pub total_count: usize,
+    /// Bytes of the frees in the table, if it has any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub freed_bytes: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread: Option<String>,
+    /// By bytes, largest first
+    pub by_bytes: Vec<AllocationSite>,
+    /// By allocation count, largest first
+    pub by_count: Vec<AllocationSite>,
+    /// Why there are no allocations, if there are none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+fn is_allocator_function(name: &str) -> bool {
+    ALLOCATOR_FUNCTIONS.contains(&name)
+        || ALLOCATOR_PREFIXES
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+}
+
+impl ThreadData {
+    /// The first function from the leaf of the stack which isn't part of the
+    /// allocator, or the root function if they all are
+    fn allocation_site(&self, stack_idx: usize, global_strings: &[String]) -> String {
+        let mut name = String::new();
+        let mut stack = Some(stack_idx);
+        while let Some(idx) = stack {
+            let func_idx = self.get_frame_func(self.get_stack_frame(idx));
+            name = self.get_func_name(func_idx, global_strings);
+            if !is_allocator_function(&name) {
+                break;
+            }
+            stack = self.get_stack_prefix(idx);
+        }
+        name
+    }
+}
+
+impl ProfileAnalyzer {
+    /// The top `limit` allocation sites by bytes and by count, of the
+    /// allocations in `range` in the threads whose name contains
+    /// `thread_filter`
+    pub fn allocation_sites(
+        &self,
+        limit: usize,
+        thread_filter: Option<&str>,
+        range: TimeRange,
+    ) -> AllocationsResponse {
+        let mut total_bytes = 0;
+        let mut total_count = 0;
+        let mut freed_bytes = 0;
+        let mut has_frees = false;
+        // site -> (bytes, count)
+        let mut sites: HashMap<String, (i64, usize)> = HashMap::new();
+
+        for thread in &self.threads {
+            if !thread.matches_filter(thread_filter) {
+                continue;
+            }
+            let mut site_names: HashMap<usize, String> = HashMap::new();
+            for allocation in &thread.allocations {
+                if !range.is_unbounded() && !allocation.time_ms.is_some_and(|t| range.contains(t)) {
+                    continue;
+                }
+                if allocation.bytes < 0 {
+                    has_frees = true;
+                    freed_bytes -= allocation.bytes;
+                    continue;
+                }
+                total_bytes += allocation.bytes;
+                total_count += 1;
+                let site = match allocation.stack {
+                    Some(stack_idx) => site_names
+                        .entry(stack_idx)
+                        .or_insert_with(|| thread.allocation_site(stack_idx, &self.global_strings))
+                        .clone(),
+                    None => "<no stack>".to_string(),
+                };
+                let entry = sites.entry(site).or_default();
+                entry.0 += allocation.bytes;
+                entry.1 += 1;
+            }
+        }
+
+        let mut by_bytes: Vec<AllocationSite> = sites
+            .into_iter()
+            .map(|(function, (bytes, count))| AllocationSite {
+                function,
+                bytes,
+                count,
+                percent: if total_bytes > 0 {
+                    100.0 * bytes as f64 / total_bytes as f64
+                } else {
+                    0.0
+                },
+            })
+            .collect();
+        let mut by_count = by_bytes.clone();
+        by_bytes.sort_by(|a, b| {
+            b.bytes
+                .cmp(&a.bytes)
+                .then_with(|| a.function.cmp(&b.function))
+        });
+        by_bytes.truncate(limit);
+        by_count.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.function.cmp(&b.function))
+        });
+        by_count.truncate(limit);
+
+        AllocationsResponse {
+            total_bytes,
+            total_count,
+            freed_bytes: has_frees.then_some(freed_bytes),
+            thread: thread_filter.map(|t| t.to_string()),
+            by_bytes,
+            by_count,
+            note: (total_count == 0).then(|| {
+                "The profile has no native allocations. Record them with \
+                 'samply record --alloc' on Linux. Allocations are on the main \
+                 thread of each process, so a thread filter needs to match it."
+                    .to_string()
+            }),
+        }
+    }
+}
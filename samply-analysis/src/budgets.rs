@@ -0,0 +1,223 @@
+//! Performance budgets: limits on how much of a profile's time functions or
+//! components (libraries) may take, e.g. from a checked-in
+//! `perf-budgets.toml`. `samply analyze assert --budgets` checks a profile
+//! against all of them, so that CI fails when a change makes code slower.
+//!
+//! A budget covers the functions whose names match its `function` pattern,
+//! the functions of its `library`, or the functions of the library which
+//! match the pattern if it has both. Its self time is the samples whose leaf
+//! function it covers, and its total time the samples with a covered function
+//! anywhere on the stack. Times in milliseconds are estimated from the sample
+//! count and the sampling interval.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::function_match::{normalize_function_name, FunctionMatcher};
+use crate::{MatchMode, ProfileAnalyzer, TimeRange};
+
+/// The budgets of a budgets file, in its order
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Budgets {
+    #[serde(default, rename = "budget")]
+    pub budgets: Vec<Budget>,
+}
+
+/// One budget, with at least one limit
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Budget {
+    /// Defaults to the function pattern and the library
+    pub name: Option<String>,
+    /// Pattern for the names of the functions which the budget covers
+    pub function: Option<String>,
+    /// How `function` is matched; substring by default
+    #[serde(default, rename = "match")]
+    pub match_mode: MatchMode,
+    /// Name of the library whose functions the budget covers
+    pub library: Option<String>,
+    /// Only count the threads whose name contains this
+    pub thread: Option<String>,
+    pub self_percent: Option<f64>,
+    pub total_percent: Option<f64>,
+    pub self_ms: Option<f64>,
+    pub total_ms: Option<f64>,
+}
+
+/// One limit of a budget, with the profile's value for it
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetCheck {
+    /// "self_percent", "total_percent", "self_ms" or "total_ms"
+    pub metric: &'static str,
+    pub limit: f64,
+    pub actual: f64,
+    pub passed: bool,
+}
+
+/// The result of one budget
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetResult {
+    pub name: String,
+    pub passed: bool,
+    pub self_samples: i64,
+    pub total_samples: i64,
+    pub checks: Vec<BudgetCheck>,
+}
+
+/// Response for checking a profile against budgets
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetsResponse {
+    /// Whether every budget passed
+    pub passed: bool,
+    pub failed: usize,
+    pub sampling_interval_ms: f64,
+    /// In the order of the budgets file
+    pub budgets: Vec<BudgetResult>,
+}
+
+impl Budget {
+    fn display_name(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+        match (&self.function, &self.library) {
+            (Some(function), Some(library)) => format!("{function} in {library}"),
+            (Some(function), None) => function.clone(),
+            (None, Some(library)) => library.clone(),
+            (None, None) => String::new(),
+        }
+    }
+
+    /// The limits which are set, by metric name
+    fn limits(&self) -> Vec<(&'static str, f64)> {
+        [
+            ("self_percent", self.self_percent),
+            ("total_percent", self.total_percent),
+            ("self_ms", self.self_ms),
+            ("total_ms", self.total_ms),
+        ]
+        .into_iter()
+        .filter_map(|(metric, limit)| Some((metric, limit?)))
+        .collect()
+    }
+}
+
+impl ProfileAnalyzer {
+    /// Check the samples in `range` against every budget. Fails if a budget
+    /// covers nothing, has no limits, or has an invalid pattern.
+    pub fn check_budgets(
+        &self,
+        budgets: &Budgets,
+        range: TimeRange,
+    ) -> Result<BudgetsResponse, String> {
+        let results = budgets
+            .budgets
+            .iter()
+            .enumerate()
+            .map(|(index, budget)| {
+                self.check_budget(budget, range)
+                    .map_err(|e| format!("Budget {} ({}): {e}", index + 1, budget.display_name()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let failed = results.iter().filter(|result| !result.passed).count();
+        Ok(BudgetsResponse {
+            passed: failed == 0,
+            failed,
+            sampling_interval_ms: self.sampling_interval_ms,
+            budgets: results,
+        })
+    }
+
+    fn check_budget(&self, budget: &Budget, range: TimeRange) -> Result<BudgetResult, String> {
+        if budget.function.is_none() && budget.library.is_none() {
+            return Err("needs a function or a library".to_string());
+        }
+        let limits = budget.limits();
+        if limits.is_empty() {
+            return Err(
+                "needs a limit: self_percent, total_percent, self_ms or total_ms".to_string(),
+            );
+        }
+        let matcher = budget
+            .function
+            .as_deref()
+            .map(|pattern| FunctionMatcher::new(pattern, budget.match_mode))
+            .transpose()?;
+
+        let mut total_weight = 0;
+        let mut self_samples = 0;
+        let mut total_samples = 0;
+        for thread in &self.threads {
+            if !budget
+                .thread
+                .as_deref()
+                .is_none_or(|filter| thread.name.contains(filter))
+            {
+                continue;
+            }
+            let mut covered: HashMap<usize, bool> = HashMap::new();
+            let mut covers = |func_idx: usize| {
+                *covered.entry(func_idx).or_insert_with(|| {
+                    let in_library = budget.library.as_deref().is_none_or(|library| {
+                        thread
+                            .get_func_lib_index(func_idx)
+                            .and_then(|lib_idx| self.libs.get(lib_idx))
+                            .is_some_and(|lib| lib.name == library)
+                    });
+                    in_library
+                        && matcher.as_ref().is_none_or(|matcher| {
+                            let name = thread.get_func_name(func_idx, &self.global_strings);
+                            matcher.matches(&name)
+                                || matcher.matches_normalized(&normalize_function_name(&name))
+                        })
+                })
+            };
+            for (stack_idx, weight) in thread.samples_in(range) {
+                total_weight += weight;
+                let Some(stack_idx) = stack_idx else {
+                    continue;
+                };
+                let funcs = thread.walk_stack(stack_idx);
+                if funcs.first().is_some_and(|&leaf| covers(leaf)) {
+                    self_samples += weight;
+                }
+                if funcs.iter().any(|&func_idx| covers(func_idx)) {
+                    total_samples += weight;
+                }
+            }
+        }
+
+        let percent = |samples: i64| {
+            if total_weight > 0 {
+                100.0 * samples as f64 / total_weight as f64
+            } else {
+                0.0
+            }
+        };
+        let checks: Vec<BudgetCheck> = limits
+            .into_iter()
+            .map(|(metric, limit)| {
+                let actual = match metric {
+                    "self_percent" => percent(self_samples),
+                    "total_percent" => percent(total_samples),
+                    "self_ms" => self_samples as f64 * self.sampling_interval_ms,
+                    _ => total_samples as f64 * self.sampling_interval_ms,
+                };
+                BudgetCheck {
+                    metric,
+                    limit,
+                    actual,
+                    passed: actual <= limit,
+                }
+            })
+            .collect();
+        Ok(BudgetResult {
+            name: budget.display_name(),
+            passed: checks.iter().all(|check| check.passed),
+            self_samples,
+            total_samples,
+            checks,
+        })
+    }
+}
@@ -0,0 +1,241 @@
+//! The top-down call tree: a prefix tree of the sampled stacks, root first,
+//! with the self and total samples of every call path.
+//!
+//! The tree is built from the threads' stack tables instead of walking the
+//! stack of every sample: each stack's node is the child of its prefix's node
+//! for the stack's function, so every stack is visited once, and a sample only
+//! adds its weight to the node of its stack. Threads share the nodes of call
+//! paths with the same function names.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{ProfileAnalyzer, TimeRange};
+
+/// Children of a node which were left out of the call tree
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PrunedNodes {
+    pub count: usize,
+    /// Total samples of the pruned children
+    pub samples: i64,
+}
+
+/// One call path of the call tree
+#[derive(Debug, Clone, Serialize)]
+pub struct CallTreeNode {
+    pub name: String,
+    /// Samples whose stack ends at this node
+    pub self_samples: i64,
+    /// Samples in this node and all its descendants
+    pub total_samples: i64,
+    pub self_percent: f64,
+    pub total_percent: f64,
+    /// By total samples, largest first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<CallTreeNode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pruned: Option<PrunedNodes>,
+}
+
+/// Response for calltree query
+#[derive(Debug, Clone, Serialize)]
+pub struct CallTreeResponse {
+    pub total_samples: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread: Option<String>,
+    /// The root functions, by total samples, largest first
+    pub roots: Vec<CallTreeNode>,
+    /// Root functions which were left out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pruned: Option<PrunedNodes>,
+    /// Nodes in the returned tree
+    pub node_count: usize,
+}
+
+struct TreeNode {
+    name: String,
+    parent: usize,
+    self_samples: i64,
+    total_samples: i64,
+    children: Vec<usize>,
+}
+
+/// How the call tree is pruned
+struct Pruning {
+    min_samples: f64,
+    max_depth: usize,
+    max_children: usize,
+}
+
+/// Converts the aggregated tree into response nodes
+struct TreeBuilder<'a> {
+    nodes: &'a [TreeNode],
+    pruning: Pruning,
+    total_samples: i64,
+    node_count: usize,
+}
+
+impl TreeBuilder<'_> {
+    fn percent(&self, samples: i64) -> f64 {
+        if self.total_samples > 0 {
+            100.0 * samples as f64 / self.total_samples as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// The kept children of node `idx`, which is at `depth` (0 for the root
+    /// above the root functions), and what was pruned of them.
+    fn children(&mut self, idx: usize, depth: usize) -> (Vec<CallTreeNode>, Option<PrunedNodes>) {
+        let nodes = self.nodes;
+        let mut candidates: Vec<usize> = nodes[idx]
+            .children
+            .iter()
+            .copied()
+            .filter(|&child| nodes[child].total_samples > 0)
+            .collect();
+        candidates.sort_by(|&a, &b| {
+            nodes[b]
+                .total_samples
+                .cmp(&nodes[a].total_samples)
+                .then_with(|| nodes[a].name.cmp(&nodes[b].name))
+        });
+
+        let mut children = Vec::new();
+        let mut pruned = PrunedNodes {
+            count: 0,
+            samples: 0,
+        };
+        for child in candidates {
+            let node = &nodes[child];
+            let keep = depth < self.pruning.max_depth
+                && children.len() < self.pruning.max_children
+                && node.total_samples as f64 >= self.pruning.min_samples;
+            if keep {
+                children.push(self.build(child, depth + 1));
+            } else {
+                pruned.count += 1;
+                pruned.samples += node.total_samples;
+            }
+        }
+        (children, (pruned.count > 0).then_some(pruned))
+    }
+
+    fn build(&mut self, idx: usize, depth: usize) -> CallTreeNode {
+        self.node_count += 1;
+        let (children, pruned) = self.children(idx, depth);
+        let node = &self.nodes[idx];
+        CallTreeNode {
+            name: node.name.clone(),
+            self_samples: node.self_samples,
+            total_samples: node.total_samples,
+            self_percent: self.percent(node.self_samples),
+            total_percent: self.percent(node.total_samples),
+            children,
+            pruned,
+        }
+    }
+}
+
+impl ProfileAnalyzer {
+    /// The top-down call tree of the threads whose name contains
+    /// `thread_filter`, from the samples in `range`. Nodes below `min_percent`
+    /// of the samples and nodes deeper than `max_depth` (the root functions
+    /// are at depth 1) are pruned, and each node keeps at most its
+    /// `max_children` largest children.
+    pub fn call_tree(
+        &self,
+        thread_filter: Option<&str>,
+        min_percent: f64,
+        max_depth: usize,
+        max_children: usize,
+        range: TimeRange,
+    ) -> CallTreeResponse {
+        // Node 0 is the root above the root functions.
+        let mut nodes = vec![TreeNode {
+            name: String::new(),
+            parent: 0,
+            self_samples: 0,
+            total_samples: 0,
+            children: Vec::new(),
+        }];
+        let mut node_indexes: HashMap<(usize, String), usize> = HashMap::new();
+        let mut total_samples = 0;
+
+        for thread in &self.threads {
+            if !thread.matches_filter(thread_filter) {
+                continue;
+            }
+            let mut stack_weights: HashMap<usize, i64> = HashMap::new();
+            for (stack_idx, weight) in thread.samples_in(range) {
+                total_samples += weight;
+                if let Some(stack_idx) = stack_idx {
+                    *stack_weights.entry(stack_idx).or_default() += weight;
+                }
+            }
+            if stack_weights.is_empty() {
+                continue;
+            }
+
+            // A stack's prefix is an earlier stack, so its node already exists.
+            let mut stack_nodes: Vec<usize> = Vec::with_capacity(thread.stack_frame.len());
+            for stack_idx in 0..thread.stack_frame.len() {
+                let parent = thread
+                    .get_stack_prefix(stack_idx)
+                    .and_then(|prefix| stack_nodes.get(prefix).copied())
+                    .unwrap_or(0);
+                let func_idx = thread.get_frame_func(thread.get_stack_frame(stack_idx));
+                let name = thread.get_func_name(func_idx, &self.global_strings);
+                let node = *node_indexes
+                    .entry((parent, name))
+                    .or_insert_with_key(|(_, name)| {
+                        nodes.push(TreeNode {
+                            name: name.clone(),
+                            parent,
+                            self_samples: 0,
+                            total_samples: 0,
+                            children: Vec::new(),
+                        });
+                        let node = nodes.len() - 1;
+                        nodes[parent].children.push(node);
+                        node
+                    });
+                stack_nodes.push(node);
+            }
+            for (stack_idx, weight) in stack_weights {
+                if let Some(&node) = stack_nodes.get(stack_idx) {
+                    nodes[node].self_samples += weight;
+                }
+            }
+        }
+
+        // Children come after their parents, so a node's total is complete
+        // when it's reached.
+        for idx in (1..nodes.len()).rev() {
+            nodes[idx].total_samples += nodes[idx].self_samples;
+            let (parent, total) = (nodes[idx].parent, nodes[idx].total_samples);
+            nodes[parent].total_samples += total;
+        }
+
+        let mut builder = TreeBuilder {
+            nodes: &nodes,
+            pruning: Pruning {
+                min_samples: total_samples as f64 * min_percent / 100.0,
+                max_depth,
+                max_children,
+            },
+            total_samples,
+            node_count: 0,
+        };
+        let (roots, pruned) = builder.children(0, 0);
+
+        CallTreeResponse {
+            total_samples,
+            thread: thread_filter.map(|t| t.to_string()),
+            roots,
+            pruned,
+            node_count: builder.node_count,
+        }
+    }
+}
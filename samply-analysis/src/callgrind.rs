@@ -0,0 +1,130 @@
+//! Exporting the call graph in Valgrind's callgrind format
+//! (<https://valgrind.org/docs/manual/cl-format.html>), for KCachegrind and
+//! QCacheGrind.
+//!
+//! Functions are merged by name and file across the exported threads. The
+//! only event is `Samples`: a function's own cost is the samples where it is
+//! the leaf, and each call edge costs the samples which went through it, so
+//! KCachegrind can derive inclusive costs. There are no call counts in a
+//! sampled profile, so `calls=` also gives the samples through the edge.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{ProfileAnalyzer, TimeRange};
+
+/// Written for functions without a source file, like Valgrind does
+const UNKNOWN_FILE: &str = "???";
+
+struct Function {
+    name: String,
+    file: Option<String>,
+    self_samples: i64,
+    /// Samples through the calls to each callee, by function index
+    calls: BTreeMap<usize, i64>,
+}
+
+/// Gives the `(id) name` of a file or function the first time it's written,
+/// and only `(id)` afterwards.
+#[derive(Default)]
+struct NameCompression {
+    ids: HashMap<String, usize>,
+}
+
+impl NameCompression {
+    fn get(&mut self, name: &str) -> String {
+        if let Some(id) = self.ids.get(name) {
+            return format!("({id})");
+        }
+        let id = self.ids.len() + 1;
+        self.ids.insert(name.to_string(), id);
+        format!("({id}) {name}")
+    }
+}
+
+impl ProfileAnalyzer {
+    /// A callgrind file with the call graph of the threads whose name
+    /// contains `thread_filter` in `range`.
+    pub(crate) fn export_callgrind(&self, thread_filter: Option<&str>, range: TimeRange) -> String {
+        let mut functions: Vec<Function> = Vec::new();
+        let mut function_indexes: HashMap<(String, Option<String>), usize> = HashMap::new();
+        let mut total = 0;
+
+        for thread in &self.threads {
+            if !thread.matches_filter(thread_filter) {
+                continue;
+            }
+
+            let mut stack_weights: BTreeMap<usize, i64> = BTreeMap::new();
+            for (stack_idx, weight) in thread.samples_in(range) {
+                if let Some(stack_idx) = stack_idx {
+                    *stack_weights.entry(stack_idx).or_default() += weight;
+                }
+            }
+
+            for (stack_idx, weight) in stack_weights {
+                if weight <= 0 {
+                    continue;
+                }
+                let stack: Vec<usize> = thread
+                    .walk_stack(stack_idx)
+                    .into_iter()
+                    .rev()
+                    .map(|func_idx| {
+                        let name = thread.get_func_name(func_idx, &self.global_strings);
+                        let file = thread.get_func_file(func_idx, &self.global_strings);
+                        *function_indexes
+                            .entry((name.clone(), file.clone()))
+                            .or_insert_with(|| {
+                                functions.push(Function {
+                                    name,
+                                    file,
+                                    self_samples: 0,
+                                    calls: BTreeMap::new(),
+                                });
+                                functions.len() - 1
+                            })
+                    })
+                    .collect();
+                let Some(&leaf) = stack.last() else {
+                    continue;
+                };
+                total += weight;
+                functions[leaf].self_samples += weight;
+                // A recursive stack goes through an edge more than once, but
+                // its samples only count once.
+                let mut seen_calls = HashSet::new();
+                for pair in stack.windows(2) {
+                    if seen_calls.insert((pair[0], pair[1])) {
+                        *functions[pair[0]].calls.entry(pair[1]).or_default() += weight;
+                    }
+                }
+            }
+        }
+
+        let mut output = format!(
+            "# callgrind format\nversion: 1\ncreator: samply-for-ai {}\ncmd: {}\n\
+             positions: line\nevents: Samples\nsummary: {total}\n",
+            env!("CARGO_PKG_VERSION"),
+            self.product_name
+        );
+
+        let mut files = NameCompression::default();
+        let mut names = NameCompression::default();
+        for function in &functions {
+            let file = function.file.as_deref().unwrap_or(UNKNOWN_FILE);
+            output.push_str(&format!("\nfl={}\n", files.get(file)));
+            output.push_str(&format!("fn={}\n", names.get(&function.name)));
+            if function.self_samples > 0 {
+                output.push_str(&format!("0 {}\n", function.self_samples));
+            }
+            for (&callee, &samples) in &function.calls {
+                let callee = &functions[callee];
+                let callee_file = callee.file.as_deref().unwrap_or(UNKNOWN_FILE);
+                output.push_str(&format!("cfi={}\n", files.get(callee_file)));
+                output.push_str(&format!("cfn={}\n", names.get(&callee.name)));
+                output.push_str(&format!("calls={samples} 0\n0 {samples}\n"));
+            }
+        }
+        output
+    }
+}
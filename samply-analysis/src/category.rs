@@ -0,0 +1,156 @@
+//! Attribution of samples to the profile's frame categories (e.g. "User",
+//! "Kernel", "GC", "Layout"), so that kernel time can be told apart from user
+//! code.
+//!
+//! A sample's category is the category of its leaf frame. Frames without a
+//! category take the category of their nearest caller which has one, as in
+//! the profiler front-end.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{ProfileAnalyzer, ThreadData, TimeRange};
+
+/// A leaf function's self samples within one category
+#[derive(Debug, Clone, Serialize)]
+pub struct CategoryFunction {
+    pub name: String,
+    pub samples: i64,
+    /// Share of the category's samples
+    pub percent: f64,
+}
+
+/// One category, from a categories query
+#[derive(Debug, Clone, Serialize)]
+pub struct CategoryEntry {
+    pub name: String,
+    pub samples: i64,
+    pub percent: f64,
+    /// The leaf functions with the most samples in this category
+    pub functions: Vec<CategoryFunction>,
+}
+
+/// Response for categories query
+#[derive(Debug, Clone, Serialize)]
+pub struct CategoriesResponse {
+    pub total_samples: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread: Option<String>,
+    /// By samples, largest first
+    pub categories: Vec<CategoryEntry>,
+    /// Samples without a stack or without any frame with a category
+    pub uncategorized_samples: i64,
+}
+
+impl ThreadData {
+    /// The category of a stack: its frame's, or else that of the nearest
+    /// caller frame which has one.
+    pub(crate) fn stack_category(&self, stack_idx: usize) -> Option<usize> {
+        let mut stack = Some(stack_idx);
+        while let Some(idx) = stack {
+            if let Some(category) = self.get_frame_category(self.get_stack_frame(idx)) {
+                return Some(category);
+            }
+            stack = self.get_stack_prefix(idx);
+        }
+        None
+    }
+}
+
+impl ProfileAnalyzer {
+    /// The name of the category with the most samples in `category_samples`
+    pub(crate) fn dominant_category(
+        &self,
+        category_samples: &HashMap<usize, i64>,
+    ) -> Option<String> {
+        category_samples
+            .iter()
+            .filter(|&(_, &samples)| samples > 0)
+            .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+            .and_then(|(&category, _)| self.categories.get(category).cloned())
+    }
+
+    /// The samples of each category in the threads whose name contains
+    /// `thread_filter`, with the top `limit` leaf functions of each. Only
+    /// samples in `range` are counted.
+    pub fn category_breakdown(
+        &self,
+        limit: usize,
+        thread_filter: Option<&str>,
+        range: TimeRange,
+    ) -> CategoriesResponse {
+        let mut total_samples = 0;
+        let mut uncategorized_samples = 0;
+        // Category -> leaf function -> samples
+        let mut categories: HashMap<usize, HashMap<String, i64>> = HashMap::new();
+
+        for thread in &self.threads {
+            if !thread.matches_filter(thread_filter) {
+                continue;
+            }
+            let mut stack_weights: HashMap<usize, i64> = HashMap::new();
+            for (stack_idx, weight) in thread.samples_in(range) {
+                total_samples += weight;
+                match stack_idx {
+                    Some(stack_idx) => *stack_weights.entry(stack_idx).or_default() += weight,
+                    None => uncategorized_samples += weight,
+                }
+            }
+            for (stack_idx, weight) in stack_weights {
+                let category = thread
+                    .stack_category(stack_idx)
+                    .filter(|&category| category < self.categories.len());
+                let Some(category) = category else {
+                    uncategorized_samples += weight;
+                    continue;
+                };
+                let leaf = thread.get_frame_func(thread.get_stack_frame(stack_idx));
+                let name = thread.get_func_name(leaf, &self.global_strings);
+                *categories
+                    .entry(category)
+                    .or_default()
+                    .entry(name)
+                    .or_default() += weight;
+            }
+        }
+
+        let percent = |samples: i64, total: i64| {
+            if total > 0 {
+                100.0 * samples as f64 / total as f64
+            } else {
+                0.0
+            }
+        };
+        let mut categories: Vec<CategoryEntry> = categories
+            .into_iter()
+            .map(|(category, functions)| {
+                let samples: i64 = functions.values().sum();
+                let mut top: Vec<CategoryFunction> = functions
+                    .into_iter()
+                    .map(|(name, function_samples)| CategoryFunction {
+                        name,
+                        samples: function_samples,
+                        percent: percent(function_samples, samples),
+                    })
+                    .collect();
+                top.sort_by(|a, b| b.samples.cmp(&a.samples).then_with(|| a.name.cmp(&b.name)));
+                top.truncate(limit);
+                CategoryEntry {
+                    name: self.categories[category].clone(),
+                    samples,
+                    percent: percent(samples, total_samples),
+                    functions: top,
+                }
+            })
+            .collect();
+        categories.sort_by(|a, b| b.samples.cmp(&a.samples).then_with(|| a.name.cmp(&b.name)));
+
+        CategoriesResponse {
+            total_samples,
+            thread: thread_filter.map(|t| t.to_string()),
+            categories,
+            uncategorized_samples,
+        }
+    }
+}
@@ -0,0 +1,306 @@
+//! Fixed-width columns which are either held in memory or memory-mapped from
+//! an on-disk profile index (see `index.rs`).
+//!
+//! In memory, columns are kept in the same fixed-width encoding as on disk,
+//! which halves the size of the `Option` columns compared to a `Vec`. Profile
+//! JSON arrays are deserialized straight into this encoding.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::marker::PhantomData;
+#[cfg(feature = "native")]
+use std::sync::Arc;
+
+#[cfg(feature = "native")]
+use memmap2::Mmap;
+use serde::de::{Error, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+
+/// A value which can be stored in a fixed-width column file.
+pub(super) trait ColumnValue: Copy {
+    const SIZE: usize;
+
+    fn encode<W: Write>(self, w: &mut W) -> io::Result<()>;
+    fn decode(bytes: &[u8]) -> Self;
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[..8].try_into().unwrap())
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[..4].try_into().unwrap())
+}
+
+impl ColumnValue for usize {
+    const SIZE: usize = 8;
+
+    fn encode<W: Write>(self, w: &mut W) -> io::Result<()> {
+        w.write_all(&(self as u64).to_le_bytes())
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        read_u64(bytes) as usize
+    }
+}
+
+impl ColumnValue for i64 {
+    const SIZE: usize = 8;
+
+    fn encode<W: Write>(self, w: &mut W) -> io::Result<()> {
+        w.write_all(&self.to_le_bytes())
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        read_u64(bytes) as i64
+    }
+}
+
+impl ColumnValue for f64 {
+    const SIZE: usize = 8;
+
+    fn encode<W: Write>(self, w: &mut W) -> io::Result<()> {
+        w.write_all(&self.to_le_bytes())
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        f64::from_bits(read_u64(bytes))
+    }
+}
+
+/// `None` is stored as `u64::MAX`.
+impl ColumnValue for Option<usize> {
+    const SIZE: usize = 8;
+
+    fn encode<W: Write>(self, w: &mut W) -> io::Result<()> {
+        let v = self.map_or(u64::MAX, |v| v as u64);
+        w.write_all(&v.to_le_bytes())
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        match read_u64(bytes) {
+            u64::MAX => None,
+            v => Some(v as usize),
+        }
+    }
+}
+
+/// `None` is stored as `u64::MAX`.
+impl ColumnValue for Option<u64> {
+    const SIZE: usize = 8;
+
+    fn encode<W: Write>(self, w: &mut W) -> io::Result<()> {
+        w.write_all(&self.unwrap_or(u64::MAX).to_le_bytes())
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        match read_u64(bytes) {
+            u64::MAX => None,
+            v => Some(v),
+        }
+    }
+}
+
+/// `None` is stored as `u32::MAX`.
+impl ColumnValue for Option<u32> {
+    const SIZE: usize = 4;
+
+    fn encode<W: Write>(self, w: &mut W) -> io::Result<()> {
+        w.write_all(&self.unwrap_or(u32::MAX).to_le_bytes())
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        match read_u32(bytes) {
+            u32::MAX => None,
+            v => Some(v),
+        }
+    }
+}
+
+/// A column of values, either owned or backed by a memory-mapped file.
+pub(super) enum Column<T> {
+    Owned(Vec<T>),
+    /// Values in their fixed-width encoding
+    Packed {
+        bytes: Vec<u8>,
+        _marker: PhantomData<T>,
+    },
+    #[cfg(feature = "native")]
+    Mapped {
+        map: Arc<Mmap>,
+        /// Byte offset of the first value in `map`
+        offset: usize,
+        /// Number of values
+        len: usize,
+        _marker: PhantomData<T>,
+    },
+}
+
+impl<T: ColumnValue> Column<T> {
+    /// Create a column over `len` values starting at byte `offset` of `map`.
+    /// Returns `None` if the range does not fit into the mapping.
+    #[cfg(feature = "native")]
+    pub fn mapped(map: Arc<Mmap>, offset: usize, len: usize) -> Option<Self> {
+        let end = len.checked_mul(T::SIZE)?.checked_add(offset)?;
+        if end > map.len() {
+            return None;
+        }
+        Some(Column::Mapped {
+            map,
+            offset,
+            len,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Collect values into a packed column.
+    pub fn packed(values: impl IntoIterator<Item = T>) -> Self {
+        let mut bytes = Vec::new();
+        for value in values {
+            value
+                .encode(&mut bytes)
+                .expect("writing to a Vec can't fail");
+        }
+        Column::Packed {
+            bytes,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Column::Owned(v) => v.len(),
+            Column::Packed { bytes, .. } => bytes.len() / T::SIZE,
+            #[cfg(feature = "native")]
+            Column::Mapped { len, .. } => *len,
+        }
+    }
+
+    pub fn get(&self, idx: usize) -> Option<T> {
+        match self {
+            Column::Owned(v) => v.get(idx).copied(),
+            Column::Packed { bytes, .. } => {
+                let start = idx.checked_mul(T::SIZE)?;
+                bytes.get(start..start.checked_add(T::SIZE)?).map(T::decode)
+            }
+            #[cfg(feature = "native")]
+            Column::Mapped {
+                map, offset, len, ..
+            } => {
+                if idx >= *len {
+                    return None;
+                }
+                let start = offset + idx * T::SIZE;
+                Some(T::decode(&map[start..start + T::SIZE]))
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        (0..self.len()).map(move |i| self.get(i).unwrap())
+    }
+
+    /// Keep only the first `len` values.
+    pub fn truncate(&mut self, len: usize) {
+        match self {
+            Column::Owned(v) => v.truncate(len),
+            Column::Packed { bytes, .. } => bytes.truncate(len.saturating_mul(T::SIZE)),
+            #[cfg(feature = "native")]
+            Column::Mapped {
+                len: mapped_len, ..
+            } => *mapped_len = (*mapped_len).min(len),
+        }
+    }
+
+    /// Write all values in their on-disk encoding.
+    #[cfg(feature = "native")]
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        for value in self.iter() {
+            value.encode(w)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T> From<Vec<T>> for Column<T> {
+    fn from(v: Vec<T>) -> Self {
+        Column::Owned(v)
+    }
+}
+
+impl<T> Default for Column<T> {
+    fn default() -> Self {
+        Column::Owned(Vec::new())
+    }
+}
+
+impl<T: ColumnValue> fmt::Debug for Column<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Column").field("len", &self.len()).finish()
+    }
+}
+
+/// Deserialize a JSON array straight into a packed column, converting each
+/// element with `convert`.
+pub(super) fn deserialize_packed<'de, D, V, T>(
+    deserializer: D,
+    convert: fn(V) -> T,
+) -> Result<Column<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    V: Deserialize<'de>,
+    T: ColumnValue,
+{
+    struct PackedVisitor<V, T> {
+        convert: fn(V) -> T,
+    }
+
+    impl<'de, V: Deserialize<'de>, T: ColumnValue> Visitor<'de> for PackedVisitor<V, T> {
+        type Value = Column<T>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("an array")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Column<T>, A::Error> {
+            let capacity = seq.size_hint().unwrap_or(0).min(4096);
+            let mut bytes = Vec::with_capacity(capacity * T::SIZE);
+            while let Some(value) = seq.next_element::<V>()? {
+                (self.convert)(value)
+                    .encode(&mut bytes)
+                    .map_err(A::Error::custom)?;
+            }
+            Ok(Column::Packed {
+                bytes,
+                _marker: PhantomData,
+            })
+        }
+    }
+
+    deserializer.deserialize_seq(PackedVisitor { convert })
+}
+
+impl<'de, T: ColumnValue + Deserialize<'de>> Deserialize<'de> for Column<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_packed(deserializer, |value: T| value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_packed_column() {
+        let mut column: Column<Option<usize>> = serde_json::from_str("[3, null, 7]").unwrap();
+        assert!(matches!(column, Column::Packed { .. }));
+        assert_eq!(
+            column.iter().collect::<Vec<_>>(),
+            vec![Some(3), None, Some(7)]
+        );
+        assert_eq!(column.get(3), None);
+
+        column.truncate(2);
+        assert_eq!(column.iter().collect::<Vec<_>>(), vec![Some(3), None]);
+    }
+}
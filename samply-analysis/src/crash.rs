@@ -0,0 +1,134 @@
+//! The crash of a profile imported from a minidump: which thread crashed,
+//! with which exception, and in which frame.
+//!
+//! The minidump importer gives the crashing thread a "Crash" marker with
+//! the exception and its address, and each thread a single sample with its
+//! stack at the time of the dump. The crashing stack is the crashing
+//! thread's last sample at or before the marker.
+
+use serde::Serialize;
+
+use crate::{ProfileAnalyzer, ThreadData};
+
+/// A frame of the crashing stack
+#[derive(Debug, Clone, Serialize)]
+pub struct CrashFrame {
+    pub function: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub library: Option<String>,
+}
+
+/// Response for crash query
+#[derive(Debug, Clone, Serialize)]
+pub struct CrashResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tid: Option<String>,
+    /// e.g. "EXCEPTION_ACCESS_VIOLATION", "SIGSEGV" or "EXC_BAD_ACCESS"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exception: Option<String>,
+    /// The exception's address, as hex: the faulting instruction for Windows
+    /// exceptions, the accessed address for SIGSEGV and EXC_BAD_ACCESS
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    /// The leaf frame of the crashing stack
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub faulting_frame: Option<CrashFrame>,
+    /// Leaf first, the first `limit` frames
+    pub stack: Vec<CrashFrame>,
+    /// The number of frames of the whole stack
+    pub stack_depth: usize,
+    /// Why there is no crash, if there isn't
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl ThreadData {
+    /// The time and data of the thread's first "Crash" marker
+    fn crash_marker(&self) -> Option<(Option<f64>, &serde_json::Value)> {
+        self.markers.iter().find_map(|marker| {
+            let data = marker.data.as_ref()?;
+            (data.get("type")?.as_str()? == "Crash").then_some((marker.start_ms, data))
+        })
+    }
+
+    /// The stack of the last sample at or before `time`, or of the last sample
+    fn stack_at(&self, time: Option<f64>) -> Option<usize> {
+        let count = self.sample_stack.len();
+        let index = match time {
+            Some(time) if self.sample_time.len() == count => (0..count)
+                .rev()
+                .find(|&i| self.sample_time.get(i).is_some_and(|t| t <= time))
+                .or(count.checked_sub(1)),
+            _ => count.checked_sub(1),
+        }?;
+        self.sample_stack.get(index).flatten()
+    }
+}
+
+impl ProfileAnalyzer {
+    /// The crashing thread, its exception and the first `limit` frames of
+    /// its stack, from the "Crash" marker of an imported minidump
+    pub fn crash_summary(&self, limit: usize) -> CrashResponse {
+        let crash = self
+            .threads
+            .iter()
+            .find_map(|thread| Some((thread, thread.crash_marker()?)));
+        let Some((thread, (time, data))) = crash else {
+            return CrashResponse {
+                thread: None,
+                tid: None,
+                exception: None,
+                address: None,
+                faulting_frame: None,
+                stack: Vec::new(),
+                stack_depth: 0,
+                note: Some(
+                    "No thread crashed: the profile has no Crash marker. Crashes come from \
+                     minidumps imported with 'samply-for-ai import crash.dmp'."
+                        .to_string(),
+                ),
+            };
+        };
+        // String fields are indexes into the string table in profiles written
+        // by samply, and inline strings in others.
+        let field = |key: &str| match data.get(key)? {
+            serde_json::Value::String(s) => Some(s.clone()),
+            serde_json::Value::Number(n) => {
+                Some(thread.get_string(n.as_u64()? as usize, &self.global_strings))
+            }
+            _ => None,
+        };
+
+        let mut stack = Vec::new();
+        let mut stack_idx = thread.stack_at(time);
+        while let Some(idx) = stack_idx {
+            let func_idx = thread.get_frame_func(thread.get_stack_frame(idx));
+            stack.push(CrashFrame {
+                function: thread.get_func_name(func_idx, &self.global_strings),
+                library: thread
+                    .get_func_lib_index(func_idx)
+                    .and_then(|lib_idx| self.libs.get(lib_idx))
+                    .map(|lib| lib.name.clone()),
+            });
+            stack_idx = thread.get_stack_prefix(idx);
+        }
+        let stack_depth = stack.len();
+        let faulting_frame = stack.first().cloned();
+        stack.truncate(limit);
+
+        CrashResponse {
+            thread: Some(thread.name.clone()),
+            tid: Some(thread.tid.clone()),
+            exception: field("exception"),
+            address: field("address"),
+            note: faulting_frame
+                .is_none()
+                .then(|| "The crashing thread has no stack.".to_string()),
+            faulting_frame,
+            stack,
+            stack_depth,
+        }
+    }
+}
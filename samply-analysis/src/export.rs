@@ -0,0 +1,155 @@
+//! Exporting the loaded profile in formats which other tools understand.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::{ProfileAnalyzer, TimeRange};
+
+/// The format of an export query
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// Brendan Gregg's folded stacks (`main;work;compute 123`), as read by
+    /// flamegraph.pl and inferno.
+    #[default]
+    Collapsed,
+    /// speedscope's JSON file format, with a sampled profile per thread.
+    Speedscope,
+    /// Valgrind's callgrind format, as read by KCachegrind and QCacheGrind.
+    Callgrind,
+    /// Google's pprof protobuf, as read by `go tool pprof` and Polar Signals.
+    /// This is a binary format, so it can't be returned by the export query.
+    Pprof,
+}
+
+impl ExportFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExportFormat::Collapsed => "collapsed",
+            ExportFormat::Speedscope => "speedscope",
+            ExportFormat::Callgrind => "callgrind",
+            ExportFormat::Pprof => "pprof",
+        }
+    }
+
+    /// Whether the export is binary data rather than text
+    pub fn is_binary(self) -> bool {
+        match self {
+            ExportFormat::Collapsed | ExportFormat::Speedscope | ExportFormat::Callgrind => false,
+            ExportFormat::Pprof => true,
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "collapsed" => Ok(ExportFormat::Collapsed),
+            "speedscope" => Ok(ExportFormat::Speedscope),
+            "callgrind" => Ok(ExportFormat::Callgrind),
+            "pprof" => Ok(ExportFormat::Pprof),
+            _ => Err(format!(
+                "Unknown export format '{s}', expected 'collapsed', 'speedscope', 'callgrind' or 'pprof'"
+            )),
+        }
+    }
+}
+
+/// Response for export query
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportResponse {
+    pub format: ExportFormat,
+    /// The exported profile
+    pub output: String,
+}
+
+/// Make a function name safe for a folded-stack line, where `;` separates
+/// frames and a line break ends the stack.
+fn collapsed_frame_name(name: &str) -> String {
+    name.replace(';', ":").replace(['\n', '\r'], " ")
+}
+
+impl ProfileAnalyzer {
+    /// Export the samples of the threads whose name contains `thread_filter`
+    /// (all threads if `None`), in `range`, in a text format.
+    pub fn export(
+        &self,
+        format: ExportFormat,
+        thread_filter: Option<&str>,
+        range: TimeRange,
+    ) -> Result<ExportResponse, String> {
+        if format.is_binary() {
+            return Err(format!(
+                "The '{format}' format is binary; use 'samply-for-ai analyze export' to write it to a file"
+            ));
+        }
+        let output = String::from_utf8(self.export_bytes(format, thread_filter, range))
+            .map_err(|e| e.to_string())?;
+        Ok(ExportResponse { format, output })
+    }
+
+    /// Export the samples like [`export`](Self::export), in any format. pprof
+    /// is returned uncompressed.
+    pub fn export_bytes(
+        &self,
+        format: ExportFormat,
+        thread_filter: Option<&str>,
+        range: TimeRange,
+    ) -> Vec<u8> {
+        match format {
+            ExportFormat::Collapsed => self.export_collapsed(thread_filter, range).into_bytes(),
+            ExportFormat::Speedscope => self.export_speedscope(thread_filter, range).into_bytes(),
+            ExportFormat::Callgrind => self.export_callgrind(thread_filter, range).into_bytes(),
+            ExportFormat::Pprof => self.export_pprof(thread_filter, range),
+        }
+    }
+
+    /// One `root;...;leaf <samples>` line per distinct stack, sorted by stack.
+    fn export_collapsed(&self, thread_filter: Option<&str>, range: TimeRange) -> String {
+        let mut folded: BTreeMap<String, i64> = BTreeMap::new();
+
+        for thread in &self.threads {
+            if !thread.matches_filter(thread_filter) {
+                continue;
+            }
+
+            let mut stack_weights: BTreeMap<usize, i64> = BTreeMap::new();
+            for (stack_idx, weight) in thread.samples_in(range) {
+                if let Some(stack_idx) = stack_idx {
+                    *stack_weights.entry(stack_idx).or_default() += weight;
+                }
+            }
+
+            for (stack_idx, weight) in stack_weights {
+                let frames: Vec<String> = thread
+                    .walk_stack(stack_idx)
+                    .into_iter()
+                    .rev()
+                    .map(|func_idx| {
+                        collapsed_frame_name(&thread.get_func_name(func_idx, &self.global_strings))
+                    })
+                    .collect();
+                *folded.entry(frames.join(";")).or_default() += weight;
+            }
+        }
+
+        let mut output = String::new();
+        for (stack, weight) in folded {
+            if weight > 0 {
+                output.push_str(&format!("{stack} {weight}\n"));
+            }
+        }
+        output
+    }
+}
@@ -0,0 +1,84 @@
+//! Consistent number formatting of query responses: floats are rounded to a
+//! fixed number of decimals, and the units of the numeric fields are given by
+//! their names.
+//!
+//! Sample counts are always integers, and the analysis keeps full precision;
+//! rounding is applied to the serialized response only.
+
+use serde_json::{Number, Value};
+
+/// Decimals of the floats in query responses, unless asked otherwise
+pub const DEFAULT_PRECISION: u32 = 3;
+
+/// Beyond this, rounding doesn't change an `f64`
+pub const MAX_PRECISION: u32 = 15;
+
+/// The units of numeric fields, by field name pattern (`*` matches any part
+/// of the name). Fields which match no pattern are plain numbers, e.g. stack
+/// depths and ratios.
+pub fn response_units() -> Value {
+    serde_json::json!({
+        "*percent*": "%",
+        "*samples": "samples",
+        "value": "samples",
+        "call_count": "samples",
+        "*_ms": "ms"
+    })
+}
+
+/// Round every float in `value` to `precision` decimals. Integers are left
+/// alone.
+pub fn round_floats(value: &mut Value, precision: u32) {
+    match value {
+        Value::Number(number) if number.is_f64() => {
+            let scale = 10f64.powi(precision.min(MAX_PRECISION) as i32);
+            let x = number.as_f64().unwrap_or_default();
+            // Beyond 2^52, an f64 has no fractional digits left to round.
+            if (x * scale).abs() < (1u64 << 52) as f64 {
+                if let Some(rounded) = Number::from_f64((x * scale).round() / scale) {
+                    *number = rounded;
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                round_floats(value, precision);
+            }
+        }
+        Value::Object(fields) => {
+            for value in fields.values_mut() {
+                round_floats(value, precision);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_floats() {
+        let mut value = serde_json::json!({
+            "percent": 12.345678901,
+            "samples": 7,
+            "nested": [{ "start_ms": 0.1 + 0.2 }, { "total_percent": 50.0 }],
+            "name": "main"
+        });
+        round_floats(&mut value, 2);
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "percent": 12.35,
+                "samples": 7,
+                "nested": [{ "start_ms": 0.3 }, { "total_percent": 50.0 }],
+                "name": "main"
+            })
+        );
+
+        let mut huge = serde_json::json!(1e300);
+        round_floats(&mut huge, MAX_PRECISION);
+        assert_eq!(huge, serde_json::json!(1e300));
+    }
+}
@@ -0,0 +1,203 @@
+//! Linking functions of a diff whose names changed between the two builds: a
+//! generic parameter changed, or the function was renamed or moved to another
+//! module. Without this, such a function shows up as removed from the
+//! baseline and a new one as added, with all its time moving between them.
+//!
+//! Only functions which one profile has and the other doesn't are candidates.
+//! The evidence for a pair of them:
+//! - equal normalized names (see [`normalize_function_name`]): demangled,
+//!   without generic parameters, argument lists and Rust hashes
+//! - the same source file, with their first lines close to each other
+//! - similar callers: the Jaccard similarity of the normalized names of the
+//!   functions which call them
+//!
+//! Equal normalized names are enough on their own. Otherwise, the functions
+//! need to be in the same file; similar callers alone are also what sibling
+//! functions have. Each function is linked at most once, best pairs first.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::function_match::normalize_function_name;
+use crate::ProfileAnalyzer;
+
+/// Functions whose first lines are further apart than this aren't linked by
+/// their source location
+const MAX_LINE_DISTANCE: u32 = 50;
+/// Weights of the source location and caller evidence
+const LOCATION_WEIGHT: f64 = 0.6;
+const CALLERS_WEIGHT: f64 = 0.4;
+/// Pairs with a lower confidence aren't linked
+const MIN_CONFIDENCE: f64 = 0.6;
+
+/// A function of the current profile which is probably the same as a
+/// differently named function of the baseline
+#[derive(Debug, Clone, Serialize)]
+pub struct FunctionLink {
+    pub function: String,
+    pub baseline_function: String,
+    /// From 0 to 1; 1 for equal normalized names
+    pub confidence: f64,
+    /// The evidence: "normalized_name", "source_location" and "callers"
+    pub reasons: Vec<&'static str>,
+}
+
+/// What a function is matched by, gathered from a profile's tables
+struct FunctionContext {
+    normalized: String,
+    file: Option<String>,
+    line: Option<u32>,
+    /// Normalized names of the direct callers
+    callers: HashSet<String>,
+}
+
+impl FunctionContext {
+    /// How close the source locations are, from 0 (different files) to 1
+    fn location_similarity(&self, other: &FunctionContext) -> f64 {
+        match (&self.file, &other.file) {
+            (Some(file), Some(other_file)) if file == other_file => {}
+            _ => return 0.0,
+        }
+        match (self.line, other.line) {
+            (Some(line), Some(other_line)) => {
+                let distance = line.abs_diff(other_line);
+                if distance > MAX_LINE_DISTANCE {
+                    0.0
+                } else {
+                    1.0 - distance as f64 / (MAX_LINE_DISTANCE + 1) as f64
+                }
+            }
+            // The same file, but no telling where in it
+            _ => 0.5,
+        }
+    }
+
+    fn caller_similarity(&self, other: &FunctionContext) -> f64 {
+        let union = self.callers.union(&other.callers).count();
+        if union == 0 {
+            return 0.0;
+        }
+        self.callers.intersection(&other.callers).count() as f64 / union as f64
+    }
+}
+
+impl ProfileAnalyzer {
+    /// The contexts of the functions named in `names`
+    fn function_contexts(&self, names: &HashSet<&str>) -> HashMap<String, FunctionContext> {
+        let mut contexts: HashMap<String, FunctionContext> = HashMap::new();
+        for thread in &self.threads {
+            let func_names: Vec<String> = (0..thread.func_name_idx.len())
+                .map(|func_idx| thread.get_func_name(func_idx, &self.global_strings))
+                .collect();
+            let mut normalized: HashMap<usize, String> = HashMap::new();
+            for stack_idx in 0..thread.stack_frame.len() {
+                let func_idx = thread.get_frame_func(thread.get_stack_frame(stack_idx));
+                let Some(name) = func_names.get(func_idx) else {
+                    continue;
+                };
+                if !names.contains(name.as_str()) {
+                    continue;
+                }
+                let context = contexts
+                    .entry(name.clone())
+                    .or_insert_with(|| FunctionContext {
+                        normalized: normalize_function_name(name),
+                        file: thread.get_func_file(func_idx, &self.global_strings),
+                        line: thread.get_func_line(func_idx),
+                        callers: HashSet::new(),
+                    });
+                if let Some(prefix) = thread.get_stack_prefix(stack_idx) {
+                    let caller_idx = thread.get_frame_func(thread.get_stack_frame(prefix));
+                    let caller = normalized.entry(caller_idx).or_insert_with(|| {
+                        func_names
+                            .get(caller_idx)
+                            .map_or_else(String::new, |name| normalize_function_name(name))
+                    });
+                    context.callers.insert(caller.clone());
+                }
+            }
+        }
+        contexts
+    }
+}
+
+/// Link the functions `new` of `current` to the functions `removed` of
+/// `baseline` which are probably the same, best pairs first.
+pub(crate) fn link_functions(
+    current: &ProfileAnalyzer,
+    new: &HashSet<&str>,
+    baseline: &ProfileAnalyzer,
+    removed: &HashSet<&str>,
+) -> Vec<FunctionLink> {
+    let new_contexts = current.function_contexts(new);
+    let removed_contexts = baseline.function_contexts(removed);
+
+    // Only pairs with the same normalized name or the same file can be linked.
+    let mut by_name: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut by_file: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (name, context) in &removed_contexts {
+        by_name.entry(&context.normalized).or_default().push(name);
+        if let Some(file) = &context.file {
+            by_file.entry(file).or_default().push(name);
+        }
+    }
+
+    let mut candidates: Vec<FunctionLink> = Vec::new();
+    for (name, context) in &new_contexts {
+        let same_name = by_name.get(context.normalized.as_str());
+        let same_file = context.file.as_deref().and_then(|file| by_file.get(file));
+        let mut baseline_names: Vec<&str> = same_name
+            .into_iter()
+            .chain(same_file)
+            .flatten()
+            .copied()
+            .collect();
+        baseline_names.sort_unstable();
+        baseline_names.dedup();
+
+        for baseline_name in baseline_names {
+            let baseline_context = &removed_contexts[baseline_name];
+            let mut reasons = Vec::new();
+            let location = context.location_similarity(baseline_context);
+            let callers = context.caller_similarity(baseline_context);
+            let confidence = if context.normalized == baseline_context.normalized {
+                reasons.push("normalized_name");
+                1.0
+            } else {
+                LOCATION_WEIGHT * location + CALLERS_WEIGHT * callers
+            };
+            if confidence < MIN_CONFIDENCE {
+                continue;
+            }
+            if location > 0.0 {
+                reasons.push("source_location");
+            }
+            if callers > 0.0 {
+                reasons.push("callers");
+            }
+            candidates.push(FunctionLink {
+                function: name.clone(),
+                baseline_function: baseline_name.to_string(),
+                confidence,
+                reasons,
+            });
+        }
+    }
+
+    candidates.sort_by(|a, b| {
+        b.confidence
+            .total_cmp(&a.confidence)
+            .then_with(|| a.function.cmp(&b.function))
+            .then_with(|| a.baseline_function.cmp(&b.baseline_function))
+    });
+    let mut linked_new = HashSet::new();
+    let mut linked_removed = HashSet::new();
+    candidates.retain(|link| {
+        !linked_new.contains(&link.function)
+            && !linked_removed.contains(&link.baseline_function)
+            && linked_new.insert(link.function.clone())
+            && linked_removed.insert(link.baseline_function.clone())
+    });
+    candidates
+}
@@ -0,0 +1,243 @@
+//! Matching the function names given to queries against the functions in the
+//! profile.
+//!
+//! Besides plain substring / exact / regex matching, names are also compared in
+//! a normalized form: mangled names are demangled, and template / generic
+//! arguments, parameter lists and Rust hash suffixes are removed. This way
+//! `Vec::push` matches `alloc::vec::Vec<T,A>::push`, and `foo::bar` matches
+//! `_ZN3foo3bar17h0123456789abcdefE`.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+use regex::Regex;
+use serde::Deserialize;
+
+/// How the function pattern of a query is matched against function names
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    /// The pattern is contained in the function name; exact matches are preferred.
+    #[default]
+    Substring,
+    /// The pattern is the whole function name.
+    Exact,
+    /// The pattern is a regular expression which matches the function name.
+    Regex,
+}
+
+impl MatchMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MatchMode::Substring => "substring",
+            MatchMode::Exact => "exact",
+            MatchMode::Regex => "regex",
+        }
+    }
+}
+
+impl fmt::Display for MatchMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for MatchMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "substring" => Ok(MatchMode::Substring),
+            "exact" => Ok(MatchMode::Exact),
+            "regex" => Ok(MatchMode::Regex),
+            _ => Err(format!(
+                "Unknown match mode '{s}', expected 'substring', 'exact' or 'regex'"
+            )),
+        }
+    }
+}
+
+/// Demangle Rust (legacy and v0) and Itanium C++ symbols; other names are returned as-is.
+fn demangle(name: &str) -> Cow<'_, str> {
+    if let Ok(demangled) = rustc_demangle::try_demangle(name) {
+        return Cow::Owned(format!("{demangled:#}"));
+    }
+    if name.starts_with("_Z") {
+        let options = cpp_demangle::DemangleOptions::default().no_return_type();
+        if let Ok(symbol) = cpp_demangle::Symbol::new(name) {
+            if let Ok(demangled) = symbol.demangle_with_options(&options) {
+                return Cow::Owned(demangled);
+            }
+        }
+    }
+    Cow::Borrowed(name)
+}
+
+/// The form in which function names are compared when the raw names don't match:
+/// demangled, without `<...>` / `(...)` groups and without a `::h<hash>` suffix.
+pub(crate) fn normalize_function_name(name: &str) -> String {
+    let demangled = demangle(name);
+    let mut normalized = String::with_capacity(demangled.len());
+    let mut angle_depth = 0usize;
+    let mut paren_depth = 0usize;
+    for c in demangled.chars() {
+        match c {
+            '<' => angle_depth += 1,
+            '>' if angle_depth > 0 => angle_depth -= 1,
+            '(' => paren_depth += 1,
+            ')' if paren_depth > 0 => paren_depth -= 1,
+            c if angle_depth == 0 && paren_depth == 0 && !c.is_whitespace() => normalized.push(c),
+            _ => {}
+        }
+    }
+
+    if let Some(pos) = normalized.rfind("::h") {
+        let hash = &normalized[pos + 3..];
+        if hash.len() == 16 && hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            normalized.truncate(pos);
+        }
+    }
+    normalized
+}
+
+pub(crate) enum FunctionMatcher {
+    Exact { pattern: String, normalized: String },
+    Substring { pattern: String, normalized: String },
+    Regex(Regex),
+}
+
+impl FunctionMatcher {
+    pub fn new(pattern: &str, mode: MatchMode) -> Result<Self, String> {
+        Ok(match mode {
+            MatchMode::Exact => FunctionMatcher::Exact {
+                pattern: pattern.to_string(),
+                normalized: normalize_function_name(pattern),
+            },
+            MatchMode::Substring => FunctionMatcher::Substring {
+                pattern: pattern.to_string(),
+                normalized: normalize_function_name(pattern),
+            },
+            MatchMode::Regex => FunctionMatcher::Regex(
+                Regex::new(pattern).map_err(|e| format!("Invalid regex '{pattern}': {e}"))?,
+            ),
+        })
+    }
+
+    /// Whether the function name as it appears in the profile matches.
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            FunctionMatcher::Exact { pattern, .. } => name == pattern,
+            FunctionMatcher::Substring { pattern, .. } => name.contains(pattern.as_str()),
+            FunctionMatcher::Regex(regex) => regex.is_match(name),
+        }
+    }
+
+    /// Whether the normalized function name (see [`normalize_function_name`]) matches.
+    pub fn matches_normalized(&self, normalized_name: &str) -> bool {
+        match self {
+            FunctionMatcher::Exact { normalized, .. } => {
+                !normalized.is_empty() && normalized_name == normalized
+            }
+            FunctionMatcher::Substring { normalized, .. } => {
+                !normalized.is_empty() && normalized_name.contains(normalized.as_str())
+            }
+            FunctionMatcher::Regex(regex) => regex.is_match(normalized_name),
+        }
+    }
+}
+
+/// The last path component, e.g. `push` for `alloc::vec::Vec::push`
+fn last_component(name: &str) -> &str {
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// Function names which look like what the pattern was meant to match: names
+/// which contain it when ignoring case, and names whose last path component is
+/// a few edits away from the pattern's. Closest first.
+pub(crate) fn near_misses<'a>(
+    pattern: &str,
+    names: impl Iterator<Item = &'a str>,
+    limit: usize,
+) -> Vec<String> {
+    let pattern = normalize_function_name(pattern).to_lowercase();
+    let pattern_last = last_component(&pattern);
+    let max_distance = (pattern_last.chars().count() / 3).max(1);
+
+    let mut candidates: Vec<(usize, &str)> = names
+        .filter_map(|name| {
+            let normalized = normalize_function_name(name).to_lowercase();
+            if normalized.contains(&pattern) {
+                return Some((0, name));
+            }
+            let distance = edit_distance(pattern_last, last_component(&normalized));
+            (distance <= max_distance).then_some((distance, name))
+        })
+        .collect();
+    candidates.sort();
+    candidates.dedup_by(|a, b| a.1 == b.1);
+    candidates
+        .into_iter()
+        .take(limit)
+        .map(|(_, name)| name.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_function_name() {
+        assert_eq!(
+            normalize_function_name("alloc::vec::Vec<T,A>::push"),
+            "alloc::vec::Vec::push"
+        );
+        assert_eq!(
+            normalize_function_name("_ZN5alloc3vec12Vec$LT$T$GT$4push17h0123456789abcdefE"),
+            "alloc::vec::Vec::push"
+        );
+        assert_eq!(
+            normalize_function_name(
+                "std::vector<int, std::allocator<int> >::push_back(int const&)"
+            ),
+            "std::vector::push_back"
+        );
+
+        let matcher = FunctionMatcher::new("Vec::push", MatchMode::Substring).unwrap();
+        assert!(!matcher.matches("alloc::vec::Vec<T,A>::push"));
+        assert!(matcher.matches_normalized(&normalize_function_name("alloc::vec::Vec<T,A>::push")));
+    }
+
+    #[test]
+    fn test_near_misses() {
+        let names = ["compute", "Compute_all", "main", "computer"];
+        assert_eq!(
+            near_misses("compte", names.into_iter(), 10),
+            vec!["compute".to_string(), "computer".to_string()]
+        );
+        assert_eq!(
+            near_misses("COMPUTE", names.into_iter(), 1),
+            vec!["Compute_all".to_string()]
+        );
+    }
+}
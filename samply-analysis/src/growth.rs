@@ -0,0 +1,194 @@
+//! Functions whose share of the samples grew over the course of a recording,
+//! for leaks and gradual slowdowns: a cache which is scanned while it keeps
+//! growing, or a queue which falls further and further behind. Only the time
+//! of each sample is needed, so a single recording is enough, without a
+//! baseline.
+//!
+//! The recording is split into equal windows by time, the first and second
+//! half by default. A function's growth is the change of its share of the
+//! samples from the first window to the last, in percentage points; with
+//! more windows, `steady` tells a function which grew from window to window
+//! from one which only happened to be busy at the end.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{ProfileAnalyzer, ThreadData, TimeRange};
+
+/// A window of a growth query
+#[derive(Debug, Clone, Serialize)]
+pub struct GrowthWindow {
+    pub start_ms: f64,
+    pub end_ms: f64,
+    pub samples: i64,
+}
+
+/// A function whose share grew, from a growth query
+#[derive(Debug, Clone, Serialize)]
+pub struct GrowthEntry {
+    pub function: String,
+    /// Self-time percentage in each window
+    pub self_percent: Vec<f64>,
+    /// Total-time percentage in each window
+    pub total_percent: Vec<f64>,
+    /// From the first window to the last, in percentage points
+    pub self_growth: f64,
+    pub total_growth: f64,
+    /// Whether the ranked share never shrank from one window to the next
+    pub steady: bool,
+}
+
+/// Response for growth query
+#[derive(Debug, Clone, Serialize)]
+pub struct GrowthResponse {
+    pub windows: Vec<GrowthWindow>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread: Option<String>,
+    /// By self-time growth, largest first
+    pub by_self: Vec<GrowthEntry>,
+    /// By total-time growth, largest first
+    pub by_total: Vec<GrowthEntry>,
+    /// Why there is nothing to compare, if there isn't
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+fn is_steady(percents: &[f64]) -> bool {
+    percents.windows(2).all(|pair| pair[1] >= pair[0])
+}
+
+impl ProfileAnalyzer {
+    /// The top `limit` functions by the growth of their self and total time
+    /// from the first to the last of `window_count` windows of `range`, in
+    /// the threads whose name contains `thread_filter`
+    pub fn compute_growth(
+        &self,
+        limit: usize,
+        window_count: usize,
+        thread_filter: Option<&str>,
+        range: TimeRange,
+    ) -> GrowthResponse {
+        let window_count = window_count.max(2);
+        let include_thread = |thread: &ThreadData| thread.matches_filter(thread_filter);
+
+        let sample_times = self
+            .threads
+            .iter()
+            .filter(|thread| include_thread(thread))
+            .flat_map(|thread| thread.sample_time.iter())
+            .filter(|&time| range.contains(time));
+        let (first, last) = sample_times.fold((f64::INFINITY, f64::NEG_INFINITY), |(a, b), t| {
+            (a.min(t), b.max(t))
+        });
+        if !first.is_finite() || last <= first {
+            return GrowthResponse {
+                windows: Vec::new(),
+                thread: thread_filter.map(|t| t.to_string()),
+                by_self: Vec::new(),
+                by_total: Vec::new(),
+                note: Some(
+                    "The samples have no times, or span no time, so the recording can't be \
+                     split into windows."
+                        .to_string(),
+                ),
+            };
+        }
+
+        let width = (last - first) / window_count as f64;
+        let mut windows = Vec::with_capacity(window_count);
+        // function -> (self %, total %) per window
+        let mut shares: HashMap<String, Vec<(f64, f64)>> = HashMap::new();
+        for index in 0..window_count {
+            let start_ms = first + index as f64 * width;
+            let end_ms = if index + 1 == window_count {
+                last
+            } else {
+                start_ms + width
+            };
+            let window_range = TimeRange {
+                start_ms: Some(start_ms),
+                // The last sample is in the last window.
+                end_ms: if index + 1 == window_count {
+                    range.end_ms
+                } else {
+                    Some(end_ms)
+                },
+            };
+            let (func_stats, total_weight) = self.aggregate_functions(include_thread, window_range);
+            windows.push(GrowthWindow {
+                start_ms,
+                end_ms,
+                samples: total_weight,
+            });
+            if total_weight == 0 {
+                continue;
+            }
+            for (name, stats) in func_stats {
+                let percents = shares
+                    .entry(name)
+                    .or_insert_with(|| vec![(0.0, 0.0); window_count]);
+                percents[index] = (
+                    100.0 * stats.self_samples as f64 / total_weight as f64,
+                    100.0 * stats.total_samples as f64 / total_weight as f64,
+                );
+            }
+        }
+
+        let entries: Vec<GrowthEntry> = shares
+            .into_iter()
+            .map(|(function, percents)| {
+                let self_percent: Vec<f64> = percents.iter().map(|p| p.0).collect();
+                let total_percent: Vec<f64> = percents.iter().map(|p| p.1).collect();
+                GrowthEntry {
+                    self_growth: self_percent[window_count - 1] - self_percent[0],
+                    total_growth: total_percent[window_count - 1] - total_percent[0],
+                    function,
+                    self_percent,
+                    total_percent,
+                    steady: false,
+                }
+            })
+            .collect();
+
+        let top = |by_self: bool| {
+            let growth = |entry: &GrowthEntry| {
+                if by_self {
+                    entry.self_growth
+                } else {
+                    entry.total_growth
+                }
+            };
+            let mut top: Vec<GrowthEntry> = entries
+                .iter()
+                .filter(|entry| growth(entry) > 0.0)
+                .map(|entry| GrowthEntry {
+                    steady: is_steady(if by_self {
+                        &entry.self_percent
+                    } else {
+                        &entry.total_percent
+                    }),
+                    ..entry.clone()
+                })
+                .collect();
+            top.sort_by(|a, b| {
+                growth(b)
+                    .total_cmp(&growth(a))
+                    .then_with(|| a.function.cmp(&b.function))
+            });
+            top.truncate(limit);
+            top
+        };
+        let by_self = top(true);
+        let by_total = top(false);
+
+        GrowthResponse {
+            windows,
+            thread: thread_filter.map(|t| t.to_string()),
+            note: (by_self.is_empty() && by_total.is_empty())
+                .then(|| "No function's share grew over the recording.".to_string()),
+            by_self,
+            by_total,
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Samples of hardware events, e.g. cache misses, from `samply record
+//! --event`. Each sampled thread gets a track per event, named
+//! "<thread> [<event>]", whose sample weights are the number of events since
+//! the track's previous sample.
+//!
+//! The tracks aren't among the threads which queries see, so that they don't
+//! count as CPU samples. [`ProfileAnalyzer::for_metric`] is a view of the
+//! profile with only the tracks of one event instead, which ranks functions by
+//! that event.
+
+use std::sync::Arc;
+
+use crate::{ProfileAnalyzer, ThreadData};
+
+/// The hardware events which `samply record --event` can sample, by their
+/// names in perf
+pub const HARDWARE_EVENTS: &[&str] = &[
+    "instructions",
+    "cache-references",
+    "cache-misses",
+    "branch-instructions",
+    "branch-misses",
+];
+
+/// The name of the track with the samples of `event` of a thread
+pub fn event_track_name(thread_name: &str, event: &str) -> String {
+    format!("{thread_name} [{event}]")
+}
+
+/// The event of a track whose name is `thread_name`, if it's a track of event
+/// samples
+fn track_event(thread_name: &str) -> Option<&'static str> {
+    let (_, suffix) = thread_name.rsplit_once(" [")?;
+    let event = suffix.strip_suffix(']')?;
+    HARDWARE_EVENTS
+        .iter()
+        .copied()
+        .find(|&known| known == event)
+}
+
+/// A track of hardware event samples, with its index in the profile's threads
+pub(crate) type EventTrack = (usize, Arc<ThreadData>);
+
+/// Split the threads of a profile into the threads of CPU samples and the
+/// tracks of event samples, which keep their index in the profile
+pub(crate) fn split_event_tracks(
+    threads: Vec<Arc<ThreadData>>,
+) -> (Vec<Arc<ThreadData>>, Vec<EventTrack>) {
+    let mut sample_threads = Vec::with_capacity(threads.len());
+    let mut event_tracks = Vec::new();
+    for (index, thread) in threads.into_iter().enumerate() {
+        if track_event(&thread.name).is_some() {
+            event_tracks.push((index, thread));
+        } else {
+            sample_threads.push(thread);
+        }
+    }
+    (sample_threads, event_tracks)
+}
+
+impl ProfileAnalyzer {
+    /// The index in the profile of the thread at `thread_idx` of `threads`
+    pub(crate) fn profile_index(&self, thread_idx: usize) -> usize {
+        let mut index = thread_idx;
+        for (track_index, _) in &self.event_tracks {
+            if *track_index <= index {
+                index += 1;
+            }
+        }
+        index
+    }
+
+    /// The hardware events which the profile has samples of
+    pub fn metrics(&self) -> Vec<&'static str> {
+        HARDWARE_EVENTS
+            .iter()
+            .copied()
+            .filter(|&event| {
+                self.event_tracks
+                    .iter()
+                    .any(|(_, track)| track_event(&track.name) == Some(event))
+            })
+            .collect()
+    }
+
+    /// A view of the profile whose threads are the tracks of the hardware
+    /// event `metric`, so that every query of it counts that event instead of
+    /// CPU samples. The tracks' data is shared, not copied.
+    pub fn for_metric(&self, metric: &str) -> Result<ProfileAnalyzer, String> {
+        if !self.metrics().contains(&metric) {
+            let recorded = match self.metrics().as_slice() {
+                [] => "none".to_string(),
+                metrics => metrics.join(", "),
+            };
+            return Err(format!(
+                "The profile has no '{metric}' samples (recorded events: {recorded}). Record them \
+                 with 'samply record --event {metric}' on Linux."
+            ));
+        }
+        let mut view = self.with_threads(|_| false);
+        view.threads = self
+            .event_tracks
+            .iter()
+            .filter(|(_, track)| track_event(&track.name) == Some(metric))
+            .map(|(_, track)| track.clone())
+            .collect();
+        Ok(view)
+    }
+
+    /// The indexes in the profile of the tracks of `metric` of the threads
+    /// whose name contains `thread_filter`, in the process `process` if given
+    pub fn metric_thread_indexes(
+        &self,
+        metric: &str,
+        thread_filter: &str,
+        process: Option<&str>,
+    ) -> Vec<usize> {
+        self.event_tracks
+            .iter()
+            .filter(|(_, track)| track_event(&track.name) == Some(metric))
+            .filter(|(_, track)| track.name.contains(thread_filter))
+            .filter(|(_, track)| process.is_none_or(|process| track.in_process(process)))
+            .map(|(index, _)| *index)
+            .collect()
+    }
+}
@@ -0,0 +1,82 @@
+//! The heaviest path to each hotspot, so that a flat list of hotspots shows
+//! where each function is called from without a callers query per function.
+//!
+//! Of the stacks in which a hotspot is the leaf frame, the one with the most
+//! samples is its context. Long stacks are shortened to their outermost and
+//! innermost frames: `main → run → … → parse → lex (12 frames elided)`.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{HotspotEntry, ProfileAnalyzer, TimeRange};
+
+/// The frames from the root which a shortened path keeps
+const HEAD_FRAMES: usize = 2;
+/// The frames up to the leaf which a shortened path keeps
+const TAIL_FRAMES: usize = 3;
+
+impl ProfileAnalyzer {
+    /// Set the `context` of the hotspots to their heaviest root-to-leaf path
+    /// in the threads whose name contains `thread_filter`, counting only
+    /// samples in `range`. Hotspots without self time get no context.
+    pub fn add_hotspot_context(
+        &self,
+        hotspots: &mut [HotspotEntry],
+        thread_filter: Option<&str>,
+        range: TimeRange,
+    ) {
+        let names: HashSet<&str> = hotspots.iter().map(|h| h.function.name.as_str()).collect();
+        // The samples of each path, from root to leaf, by leaf function
+        let mut paths: HashMap<String, HashMap<Vec<String>, i64>> = HashMap::new();
+        for thread in &self.threads {
+            if thread_filter.is_some_and(|filter| !thread.name.contains(filter)) {
+                continue;
+            }
+            let mut stack_samples: HashMap<usize, i64> = HashMap::new();
+            for (stack_idx, weight) in thread.samples_in(range) {
+                if let Some(stack_idx) = stack_idx {
+                    *stack_samples.entry(stack_idx).or_insert(0) += weight;
+                }
+            }
+            for (stack_idx, weight) in stack_samples {
+                let funcs = thread.walk_stack(stack_idx);
+                let Some(&leaf) = funcs.first() else {
+                    continue;
+                };
+                let leaf = thread.get_func_name(leaf, &self.global_strings);
+                if !names.contains(leaf.as_str()) {
+                    continue;
+                }
+                let path: Vec<String> = funcs
+                    .iter()
+                    .rev()
+                    .map(|&func| thread.get_func_name(func, &self.global_strings))
+                    .collect();
+                *paths.entry(leaf).or_default().entry(path).or_insert(0) += weight;
+            }
+        }
+
+        for hotspot in hotspots {
+            hotspot.context = paths.get(&hotspot.function.name).and_then(|paths| {
+                paths
+                    .iter()
+                    .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+                    .map(|(path, _)| shorten_path(path))
+            });
+        }
+    }
+}
+
+/// `path` joined with arrows, without the frames between its first
+/// [`HEAD_FRAMES`] and last [`TAIL_FRAMES`]
+pub(crate) fn shorten_path(path: &[String]) -> String {
+    // Eliding a single frame wouldn't make the path any shorter.
+    if path.len() <= HEAD_FRAMES + TAIL_FRAMES + 1 {
+        return path.join(" → ");
+    }
+    let elided = path.len() - HEAD_FRAMES - TAIL_FRAMES;
+    format!(
+        "{} → … → {} ({elided} frames elided)",
+        path[..HEAD_FRAMES].join(" → "),
+        path[path.len() - TAIL_FRAMES..].join(" → ")
+    )
+}
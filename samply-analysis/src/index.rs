@@ -0,0 +1,338 @@
+//! On-disk index for analyzing profiles which don't fit into memory.
+//!
+//! A one-time indexing pass streams through the profile and writes the sample,
+//! stack and frame tables of each thread as fixed-width column files into a
+//! `<profile>.index` directory next to the profile, as soon as the thread is
+//! parsed, so that only one thread is in memory at a time. Subsequent loads
+//! memory-map those files, so only the pages touched by a query need to be
+//! resident. Everything else (strings, func table, libs) is small and kept in
+//! `meta.json`.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+
+use super::column::{Column, ColumnValue};
+use super::hardware_events;
+use super::{
+    open_profile, AllocationData, AnalysisError, LibInfo, MarkerData, NativeSymbolInfo,
+    ParseWarning, ProfileAnalyzer, RawProfile, RecordingOverhead, StringInterner, ThreadData,
+};
+
+/// Bump this whenever the index layout changes; stale indexes are rebuilt.
+const INDEX_VERSION: u32 = 8;
+
+#[derive(Serialize, Deserialize)]
+struct IndexMeta {
+    version: u32,
+    /// Size and modification time of the indexed profile file
+    source_len: u64,
+    source_mtime_ns: u64,
+    product_name: String,
+    sampling_interval_ms: f64,
+    global_strings: Vec<String>,
+    libs: Vec<LibInfo>,
+    recording_overhead: Option<RecordingOverhead>,
+    categories: Vec<String>,
+    parse_warnings: Vec<ParseWarning>,
+    threads: Vec<ThreadMeta>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ThreadMeta {
+    name: String,
+    pid: String,
+    process_name: String,
+    tid: String,
+    is_main_thread: bool,
+    /// Value counts of the columns in `thread-<n>.bin`, in file order:
+    /// sample_stack, sample_weight, sample_time, stack_prefix, stack_frame,
+    /// frame_func, frame_address, frame_line, frame_category
+    column_lens: Vec<usize>,
+    frame_native_symbol: Vec<Option<usize>>,
+    func_name_idx: Vec<usize>,
+    func_file_idx: Vec<Option<usize>>,
+    func_line: Vec<Option<u32>>,
+    func_resource: Vec<Option<i32>>,
+    native_symbols: Vec<NativeSymbolInfo>,
+    resource_lib: Vec<Option<usize>>,
+    string_table: Vec<String>,
+    markers: Vec<MarkerData>,
+    allocations: Vec<AllocationData>,
+}
+
+const COLUMN_COUNT: usize = 9;
+
+fn index_dir(profile_path: &Path) -> PathBuf {
+    let mut name = profile_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".index");
+    profile_path.with_file_name(name)
+}
+
+fn thread_file(dir: &Path, thread_idx: usize) -> PathBuf {
+    dir.join(format!("thread-{thread_idx}.bin"))
+}
+
+/// (size, mtime in ns) of the profile file, used to detect stale indexes
+fn source_stamp(profile_path: &Path) -> Result<(u64, u64), AnalysisError> {
+    let metadata = fs::metadata(profile_path)?;
+    let mtime_ns = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos() as u64);
+    Ok((metadata.len(), mtime_ns))
+}
+
+impl ProfileAnalyzer {
+    /// Load a profile using the on-disk index next to it, building the index
+    /// first if it is missing or out of date. `on_build` is called with the
+    /// index directory before a (potentially slow) build starts.
+    pub fn from_file_low_memory(
+        path: &Path,
+        on_build: impl FnOnce(&Path),
+    ) -> Result<Self, AnalysisError> {
+        let dir = index_dir(path);
+        let stamp = source_stamp(path)?;
+
+        if let Some(analyzer) = Self::open_index(&dir, stamp)? {
+            return Ok(analyzer);
+        }
+
+        on_build(&dir);
+        build_index(path, &dir, stamp)?;
+
+        Self::open_index(&dir, stamp)?.ok_or_else(|| {
+            AnalysisError::InvalidProfile(format!(
+                "Could not open freshly written index at {}",
+                dir.display()
+            ))
+        })
+    }
+
+    /// Open an existing index. Returns `Ok(None)` if there is no index or it
+    /// does not match the profile file.
+    fn open_index(dir: &Path, stamp: (u64, u64)) -> Result<Option<Self>, AnalysisError> {
+        let Ok(meta_file) = File::open(dir.join("meta.json")) else {
+            return Ok(None);
+        };
+        let Ok(meta) = serde_json::from_reader::<_, IndexMeta>(std::io::BufReader::new(meta_file))
+        else {
+            return Ok(None);
+        };
+        if meta.version != INDEX_VERSION || (meta.source_len, meta.source_mtime_ns) != stamp {
+            return Ok(None);
+        }
+
+        let mut strings = StringInterner::default();
+        let mut threads = Vec::with_capacity(meta.threads.len());
+        for (thread_idx, t) in meta.threads.into_iter().enumerate() {
+            let Some(columns) = map_thread_columns(&thread_file(dir, thread_idx), &t.column_lens)?
+            else {
+                return Ok(None);
+            };
+            let [sample_stack, sample_weight, sample_time, stack_prefix, stack_frame, frame_func, frame_address, frame_line, frame_category] =
+                columns;
+
+            threads.push(Arc::new(ThreadData {
+                name: t.name,
+                pid: t.pid,
+                process_name: t.process_name,
+                tid: t.tid,
+                is_main_thread: t.is_main_thread,
+                sample_stack: sample_stack.column()?,
+                sample_weight: sample_weight.column()?,
+                sample_time: sample_time.column()?,
+                stack_prefix: stack_prefix.column()?,
+                stack_frame: stack_frame.column()?,
+                frame_func: frame_func.column()?,
+                frame_address: frame_address.column()?,
+                frame_line: frame_line.column()?,
+                frame_category: frame_category.column()?,
+                frame_native_symbol: t.frame_native_symbol,
+                func_name_idx: t.func_name_idx,
+                func_file_idx: t.func_file_idx,
+                func_line: t.func_line,
+                func_resource: t.func_resource,
+                native_symbols: t.native_symbols,
+                resource_lib: t.resource_lib,
+                string_table: t
+                    .string_table
+                    .into_iter()
+                    .map(|s| strings.intern(s))
+                    .collect(),
+                markers: t.markers,
+                allocations: t.allocations,
+            }));
+        }
+
+        let (threads, event_tracks) = hardware_events::split_event_tracks(threads);
+        Ok(Some(Self {
+            product_name: meta.product_name,
+            sampling_interval_ms: meta.sampling_interval_ms,
+            threads,
+            event_tracks,
+            global_strings: meta.global_strings.into(),
+            libs: meta.libs,
+            recording_overhead: meta.recording_overhead,
+            categories: meta.categories,
+            parse_warnings: meta.parse_warnings,
+            activity_patterns: Default::default(),
+        }))
+    }
+}
+
+/// Stream through the profile at `path` and write its index into `dir`.
+fn build_index(path: &Path, dir: &Path, stamp: (u64, u64)) -> Result<(), AnalysisError> {
+    fs::create_dir_all(dir)?;
+    let _ = fs::remove_file(dir.join("meta.json"));
+
+    let mut threads = Vec::new();
+    let reader = BufReader::new(open_profile(path)?);
+    let raw = RawProfile::parse_streaming(
+        serde_json::Deserializer::from_reader(reader),
+        &mut |thread| {
+            threads.push(write_thread(dir, threads.len(), &thread)?);
+            Ok(())
+        },
+    )?;
+
+    let meta = IndexMeta {
+        version: INDEX_VERSION,
+        source_len: stamp.0,
+        source_mtime_ns: stamp.1,
+        product_name: raw.meta.product,
+        sampling_interval_ms: raw.meta.interval,
+        global_strings: raw.shared.map(|s| s.string_array).unwrap_or_default(),
+        libs: raw.libs.into_iter().map(LibInfo::from).collect(),
+        recording_overhead: raw.meta.recording_overhead,
+        categories: raw.meta.categories.into_iter().map(|c| c.name).collect(),
+        parse_warnings: raw.warnings,
+        threads,
+    };
+
+    // Write meta.json last, so that an interrupted indexing pass leaves no
+    // index which looks valid.
+    let mut w = BufWriter::new(File::create(dir.join("meta.json"))?);
+    serde_json::to_writer(&mut w, &meta)?;
+    w.flush()?;
+    Ok(())
+}
+
+/// Write the column file of one thread and return the rest of its data.
+fn write_thread(dir: &Path, thread_idx: usize, t: &ThreadData) -> io::Result<ThreadMeta> {
+    let mut w = BufWriter::new(File::create(thread_file(dir, thread_idx))?);
+    t.sample_stack.write_to(&mut w)?;
+    t.sample_weight.write_to(&mut w)?;
+    t.sample_time.write_to(&mut w)?;
+    t.stack_prefix.write_to(&mut w)?;
+    t.stack_frame.write_to(&mut w)?;
+    t.frame_func.write_to(&mut w)?;
+    t.frame_address.write_to(&mut w)?;
+    t.frame_line.write_to(&mut w)?;
+    t.frame_category.write_to(&mut w)?;
+    w.flush()?;
+
+    Ok(ThreadMeta {
+        name: t.name.clone(),
+        pid: t.pid.clone(),
+        process_name: t.process_name.clone(),
+        tid: t.tid.clone(),
+        is_main_thread: t.is_main_thread,
+        column_lens: vec![
+            t.sample_stack.len(),
+            t.sample_weight.len(),
+            t.sample_time.len(),
+            t.stack_prefix.len(),
+            t.stack_frame.len(),
+            t.frame_func.len(),
+            t.frame_address.len(),
+            t.frame_line.len(),
+            t.frame_category.len(),
+        ],
+        frame_native_symbol: t.frame_native_symbol.clone(),
+        func_name_idx: t.func_name_idx.clone(),
+        func_file_idx: t.func_file_idx.clone(),
+        func_line: t.func_line.clone(),
+        func_resource: t.func_resource.clone(),
+        native_symbols: t.native_symbols.clone(),
+        resource_lib: t.resource_lib.clone(),
+        string_table: t.string_table.iter().map(|s| s.to_string()).collect(),
+        markers: t.markers.clone(),
+        allocations: t.allocations.clone(),
+    })
+}
+
+/// A mapped column whose value type is only known at the use site
+struct ColumnRange {
+    map: Option<Arc<Mmap>>,
+    offset: usize,
+    len: usize,
+}
+
+impl ColumnRange {
+    fn column<T: ColumnValue>(self) -> Result<Column<T>, AnalysisError> {
+        let Some(map) = self.map else {
+            return Ok(Column::Owned(Vec::new()));
+        };
+        Column::mapped(map, self.offset, self.len).ok_or_else(|| {
+            AnalysisError::InvalidProfile("Profile index column out of range".to_string())
+        })
+    }
+}
+
+/// Map a thread's column file and split it into its columns. Returns
+/// `Ok(None)` if the file is missing or its size doesn't match `column_lens`.
+fn map_thread_columns(
+    path: &Path,
+    column_lens: &[usize],
+) -> Result<Option<[ColumnRange; COLUMN_COUNT]>, AnalysisError> {
+    if column_lens.len() != COLUMN_COUNT {
+        return Ok(None);
+    }
+    let sizes = [
+        <Option<usize>>::SIZE,
+        i64::SIZE,
+        f64::SIZE,
+        <Option<usize>>::SIZE,
+        usize::SIZE,
+        usize::SIZE,
+        <Option<u64>>::SIZE,
+        <Option<u32>>::SIZE,
+        <Option<u32>>::SIZE,
+    ];
+    let expected_len: usize = column_lens.iter().zip(sizes).map(|(len, size)| len * size).sum();
+
+    let Ok(file) = File::open(path) else {
+        return Ok(None);
+    };
+    if file.metadata()?.len() != expected_len as u64 {
+        return Ok(None);
+    }
+
+    // Empty files can't be mapped.
+    let map = if expected_len == 0 {
+        None
+    } else {
+        // SAFETY: The index files are only written by the indexing pass above.
+        // Modifying them while a server has them mapped is not supported.
+        Some(Arc::new(unsafe { Mmap::map(&file)? }))
+    };
+
+    let mut offset = 0;
+    let ranges = std::array::from_fn(|i| {
+        let range = ColumnRange {
+            map: map.clone(),
+            offset,
+            len: column_lens[i],
+        };
+        offset += column_lens[i] * sizes[i];
+        range
+    });
+    Ok(Some(ranges))
+}
@@ -0,0 +1,425 @@
+//! Pseudo-frames which group the frames of JIT and interpreted code by
+//! language, for profiles which mix runtimes.
+//!
+//! In a profile of Node.js calling into native addons, or of a Python
+//! extension calling back into Python, the frames of each runtime alternate
+//! with native frames. The view from [`ProfileAnalyzer::with_language_frames`]
+//! inserts a pseudo-frame like `[JavaScript]` as the caller of every run of
+//! frames of one language, so that call trees show where control passes
+//! between the runtimes, and the total time of a language is the total time
+//! of its pseudo-frame.
+//!
+//! A frame's language comes from the prefix which the runtime gives the
+//! names of its JIT code in perf maps and jitdump files, from its category,
+//! or from the library of the interpreter it's in.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::column::{Column, ColumnValue};
+use crate::{AllocationData, ProfileAnalyzer, ThreadData, TimeRange};
+
+/// The language of the leaf frames which are no runtime's code
+pub const NATIVE: &str = "native";
+
+/// Frames of perf maps and jitdump files which no other rule matches
+const JIT: &str = "JIT";
+
+/// The name prefixes of the functions which runtimes compile or interpret
+const FUNCTION_PREFIXES: &[(&str, &str)] = &[
+    // V8
+    ("JS:", "JavaScript"),
+    ("Script:", "JavaScript"),
+    ("LazyCompile:", "JavaScript"),
+    ("Function:", "JavaScript"),
+    ("InterpretedFunction:", "JavaScript"),
+    ("Builtin:", "JavaScript"),
+    ("BytecodeHandler:", "JavaScript"),
+    // JavaScriptCore
+    ("JSC-", "JavaScript"),
+    // SpiderMonkey
+    ("Interpreter: ", "JavaScript"),
+    ("BaselineInterpreter", "JavaScript"),
+    ("BlinterpOp: ", "JavaScript"),
+    ("Baseline: ", "JavaScript"),
+    ("Ion: ", "JavaScript"),
+    ("Wasm: ", "Wasm"),
+    // The perf trampoline of CPython 3.12
+    ("py::", "CPython"),
+];
+
+/// Frame categories of JIT code
+const CATEGORIES: &[(&str, &str)] = &[
+    ("JavaScript", "JavaScript"),
+    ("Python", "CPython"),
+    ("CoreCLR JIT", ".NET"),
+    ("JIT", JIT),
+];
+
+/// The name prefixes of the interpreters' and runtimes' libraries
+const LIBRARIES: &[(&str, &str)] = &[
+    ("libpython", "CPython"),
+    ("python3", "CPython"),
+    ("libjvm", "Java"),
+    ("jvm.dll", "Java"),
+    ("libcoreclr", ".NET"),
+    ("coreclr.dll", ".NET"),
+    ("libruby", "Ruby"),
+    ("libluajit", "Lua"),
+    ("libphp", "PHP"),
+];
+
+/// The language of a frame, from its function's name, its category and its
+/// library. Pseudo-frames have the language they stand for.
+fn frame_language(
+    function: &str,
+    category: Option<&str>,
+    library: Option<&str>,
+) -> Option<&'static str> {
+    let by_name = FUNCTION_PREFIXES
+        .iter()
+        .find(|(prefix, _)| function.starts_with(prefix))
+        .map(|&(_, language)| language);
+    let by_category = || {
+        CATEGORIES
+            .iter()
+            .find(|&&(name, _)| category == Some(name))
+            .map(|&(_, language)| language)
+    };
+    let by_library = || {
+        let library = library?;
+        let is_jit_file = (library.starts_with("perf-") && library.ends_with(".map"))
+            || (library.starts_with("jit-") && library.ends_with(".dump"));
+        LIBRARIES
+            .iter()
+            .find(|(prefix, _)| library.starts_with(prefix))
+            .map(|&(_, language)| language)
+            .or(is_jit_file.then_some(JIT))
+    };
+    by_name
+        .or_else(|| pseudo_frame_language(function))
+        .or_else(by_category)
+        .or_else(by_library)
+}
+
+/// The language which a pseudo-frame's name stands for
+fn pseudo_frame_language(function: &str) -> Option<&'static str> {
+    let language = function.strip_prefix('[')?.strip_suffix(']')?;
+    FUNCTION_PREFIXES
+        .iter()
+        .chain(CATEGORIES)
+        .chain(LIBRARIES)
+        .map(|&(_, known)| known)
+        .find(|&known| known == language)
+}
+
+/// The values of a frame table column for `frame_count` frames, with `None`
+/// for the frames beyond the column's end
+fn frame_column<T>(column: &Column<Option<T>>, frame_count: usize) -> Column<Option<T>>
+where
+    Option<T>: ColumnValue,
+{
+    Column::packed(
+        column
+            .iter()
+            .chain(std::iter::repeat(None))
+            .take(frame_count),
+    )
+}
+
+/// Self and total time of one language
+#[derive(Debug, Clone, Serialize)]
+pub struct LanguageEntry {
+    pub rank: usize,
+    pub language: String,
+    /// Samples whose leaf frame is in this language
+    pub self_samples: i64,
+    pub self_percent: f64,
+    /// Samples with a frame in this language anywhere on the stack
+    pub total_samples: i64,
+    pub total_percent: f64,
+    /// The leaf functions with the most self samples in this language
+    pub functions: Vec<LanguageFunction>,
+}
+
+/// A leaf function's self samples within one language
+#[derive(Debug, Clone, Serialize)]
+pub struct LanguageFunction {
+    pub name: String,
+    pub self_samples: i64,
+}
+
+/// Response for hotspots query with groupby=language
+#[derive(Debug, Clone, Serialize)]
+pub struct LanguageHotspotsResponse {
+    pub total_samples: i64,
+    /// By self samples, largest first
+    pub languages: Vec<LanguageEntry>,
+}
+
+/// The leaf functions listed per language
+const FUNCTIONS_PER_LANGUAGE: usize = 5;
+
+impl ProfileAnalyzer {
+    /// The language of each of the thread's frames
+    fn frame_languages(&self, thread: &ThreadData) -> Vec<Option<&'static str>> {
+        (0..thread.frame_func.len())
+            .map(|frame_idx| {
+                let func_idx = thread.get_frame_func(frame_idx);
+                let function = thread.get_func_name(func_idx, &self.global_strings);
+                let category = thread
+                    .get_frame_category(frame_idx)
+                    .and_then(|category| self.categories.get(category));
+                let library = thread
+                    .get_func_lib_index(func_idx)
+                    .and_then(|lib_idx| self.libs.get(lib_idx));
+                frame_language(
+                    &function,
+                    category.map(String::as_str),
+                    library.map(|lib| lib.name.as_str()),
+                )
+            })
+            .collect()
+    }
+
+    /// A view of the profile in which a pseudo-frame like `[JavaScript]` is
+    /// the caller of every run of frames of one language. The threads'
+    /// tables are copied.
+    pub fn with_language_frames(&self) -> ProfileAnalyzer {
+        let with_language_frames = |thread: &Arc<ThreadData>| {
+            let frame_languages = self.frame_languages(thread);
+            Arc::new(thread.with_language_frames(&frame_languages, &self.global_strings))
+        };
+        let mut view = self.with_threads(|_| false);
+        view.threads = self.threads.iter().map(with_language_frames).collect();
+        view.event_tracks = self
+            .event_tracks
+            .iter()
+            .map(|(idx, track)| (*idx, with_language_frames(track)))
+            .collect();
+        view
+    }
+
+    /// Self time by the language of the leaf frame, and total time by the
+    /// languages on the stack, with the top leaf functions of each language.
+    /// Leaf frames which are no runtime's code count as [`NATIVE`]. Only
+    /// threads whose name contains `thread_filter` and samples in `range`
+    /// are counted; at most `limit` languages are returned.
+    pub fn language_hotspots(
+        &self,
+        limit: usize,
+        thread_filter: Option<&str>,
+        range: TimeRange,
+    ) -> LanguageHotspotsResponse {
+        let mut total_samples = 0;
+        // language -> (self samples, total samples)
+        let mut language_samples: HashMap<&'static str, (i64, i64)> = HashMap::new();
+        let mut function_samples: HashMap<(&'static str, String), i64> = HashMap::new();
+
+        for thread in &self.threads {
+            if !thread_filter.is_none_or(|filter| thread.name.contains(filter)) {
+                continue;
+            }
+            let frame_languages = self.frame_languages(thread);
+            let language_of = |frame_idx: usize| frame_languages.get(frame_idx).copied().flatten();
+            for (stack_idx, weight) in thread.samples_in(range) {
+                total_samples += weight;
+                let Some(stack_idx) = stack_idx else {
+                    continue;
+                };
+                // Leaf first
+                let frames = thread.walk_stack_with_frames(stack_idx);
+                let Some(&(leaf_func, leaf_frame)) = frames.first() else {
+                    continue;
+                };
+                let leaf_language = language_of(leaf_frame).unwrap_or(NATIVE);
+                language_samples.entry(leaf_language).or_default().0 += weight;
+                let leaf_name = thread.get_func_name(leaf_func, &self.global_strings);
+                *function_samples
+                    .entry((leaf_language, leaf_name))
+                    .or_default() += weight;
+
+                let mut on_stack: Vec<&'static str> = frames
+                    .iter()
+                    .map(|&(_, frame_idx)| language_of(frame_idx).unwrap_or(NATIVE))
+                    .collect();
+                on_stack.sort_unstable();
+                on_stack.dedup();
+                for language in on_stack {
+                    language_samples.entry(language).or_default().1 += weight;
+                }
+            }
+        }
+
+        let percent = |samples: i64| {
+            if total_samples > 0 {
+                100.0 * samples as f64 / total_samples as f64
+            } else {
+                0.0
+            }
+        };
+
+        let mut functions_by_language: HashMap<&'static str, Vec<LanguageFunction>> =
+            HashMap::new();
+        for ((language, name), self_samples) in function_samples {
+            functions_by_language
+                .entry(language)
+                .or_default()
+                .push(LanguageFunction { name, self_samples });
+        }
+
+        let mut languages: Vec<LanguageEntry> = language_samples
+            .into_iter()
+            .map(|(language, (self_samples, samples))| {
+                let mut functions = functions_by_language.remove(language).unwrap_or_default();
+                functions.sort_by(|a, b| {
+                    b.self_samples
+                        .cmp(&a.self_samples)
+                        .then_with(|| a.name.cmp(&b.name))
+                });
+                functions.truncate(FUNCTIONS_PER_LANGUAGE);
+                LanguageEntry {
+                    rank: 0,
+                    language: language.to_string(),
+                    self_samples,
+                    self_percent: percent(self_samples),
+                    total_samples: samples,
+                    total_percent: percent(samples),
+                    functions,
+                }
+            })
+            .collect();
+        languages.sort_by(|a, b| {
+            b.self_samples
+                .cmp(&a.self_samples)
+                .then_with(|| b.total_samples.cmp(&a.total_samples))
+                .then_with(|| a.language.cmp(&b.language))
+        });
+        languages.truncate(limit);
+        for (i, language) in languages.iter_mut().enumerate() {
+            language.rank = i + 1;
+        }
+
+        LanguageHotspotsResponse {
+            total_samples,
+            languages,
+        }
+    }
+}
+
+impl ThreadData {
+    /// A copy of the thread with a pseudo-frame of the language as the
+    /// caller of each run of frames of one language
+    fn with_language_frames(
+        &self,
+        frame_languages: &[Option<&'static str>],
+        global_strings: &[String],
+    ) -> ThreadData {
+        let language_of = |frame_idx: usize| frame_languages.get(frame_idx).copied().flatten();
+        // A view of a view gets no second pseudo-frame above the first.
+        let is_pseudo_frame: Vec<bool> = (0..self.frame_func.len())
+            .map(|frame_idx| {
+                let function = self.get_func_name(self.get_frame_func(frame_idx), global_strings);
+                pseudo_frame_language(&function).is_some()
+            })
+            .collect();
+
+        // The pseudo-frames' names go into the thread's own string table, which
+        // takes precedence over the global one.
+        let mut string_table = self.string_table.clone();
+        if string_table.is_empty() {
+            string_table = global_strings
+                .iter()
+                .map(|s| Arc::from(s.as_str()))
+                .collect();
+        }
+        let mut func_name_idx = self.func_name_idx.clone();
+        let mut func_file_idx = self.func_file_idx.clone();
+        let mut func_line = self.func_line.clone();
+        let mut func_resource = self.func_resource.clone();
+        let mut frame_func: Vec<usize> = self.frame_func.iter().collect();
+        let mut frame_native_symbol = self.frame_native_symbol.clone();
+        frame_native_symbol.resize(frame_func.len(), None);
+        let mut pseudo_frames: HashMap<&'static str, usize> = HashMap::new();
+        let mut pseudo_frame = |language: &'static str| {
+            *pseudo_frames.entry(language).or_insert_with(|| {
+                func_name_idx.push(string_table.len());
+                string_table.push(Arc::from(format!("[{language}]")));
+                func_file_idx.push(None);
+                func_line.push(None);
+                func_resource.push(None);
+                frame_func.push(func_name_idx.len() - 1);
+                frame_native_symbol.push(None);
+                frame_func.len() - 1
+            })
+        };
+
+        // Prefixes come before their stacks, so each stack's prefix is mapped
+        // by the time the stack is.
+        let mut stack_prefix: Vec<Option<usize>> = Vec::new();
+        let mut stack_frame: Vec<usize> = Vec::new();
+        let mut pseudo_stacks: HashMap<(Option<usize>, usize), usize> = HashMap::new();
+        let mut stack_map: Vec<usize> = Vec::with_capacity(self.stack_frame.len());
+        for stack_idx in 0..self.stack_frame.len() {
+            let frame_idx = self.get_stack_frame(stack_idx);
+            let prefix = self.get_stack_prefix(stack_idx);
+            let mut new_prefix = prefix.and_then(|prefix| stack_map.get(prefix).copied());
+            let prefix_language =
+                prefix.and_then(|prefix| language_of(self.get_stack_frame(prefix)));
+            let starts_run = language_of(frame_idx)
+                .filter(|&language| Some(language) != prefix_language)
+                .filter(|_| !is_pseudo_frame.get(frame_idx).copied().unwrap_or_default());
+            if let Some(language) = starts_run {
+                let frame = pseudo_frame(language);
+                let pseudo_stack = *pseudo_stacks.entry((new_prefix, frame)).or_insert_with(|| {
+                    stack_prefix.push(new_prefix);
+                    stack_frame.push(frame);
+                    stack_frame.len() - 1
+                });
+                new_prefix = Some(pseudo_stack);
+            }
+            stack_prefix.push(new_prefix);
+            stack_frame.push(frame_idx);
+            stack_map.push(stack_frame.len() - 1);
+        }
+        let map_stack =
+            |stack: Option<usize>| stack.and_then(|stack| stack_map.get(stack).copied());
+
+        let frame_count = frame_func.len();
+        ThreadData {
+            name: self.name.clone(),
+            pid: self.pid.clone(),
+            process_name: self.process_name.clone(),
+            tid: self.tid.clone(),
+            is_main_thread: self.is_main_thread,
+            sample_stack: Column::packed(self.sample_stack.iter().map(map_stack)),
+            sample_weight: Column::packed(self.sample_weight.iter()),
+            sample_time: Column::packed(self.sample_time.iter()),
+            stack_prefix: Column::packed(stack_prefix),
+            stack_frame: Column::packed(stack_frame),
+            frame_address: frame_column(&self.frame_address, frame_count),
+            frame_line: frame_column(&self.frame_line, frame_count),
+            frame_category: frame_column(&self.frame_category, frame_count),
+            frame_func: Column::packed(frame_func),
+            frame_native_symbol,
+            func_name_idx,
+            func_file_idx,
+            func_line,
+            func_resource,
+            native_symbols: self.native_symbols.clone(),
+            resource_lib: self.resource_lib.clone(),
+            string_table,
+            markers: self.markers.clone(),
+            allocations: self
+                .allocations
+                .iter()
+                .map(|allocation| AllocationData {
+                    stack: map_stack(allocation.stack),
+                    ..allocation.clone()
+                })
+                .collect(),
+        }
+    }
+}
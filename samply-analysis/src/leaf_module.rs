@@ -0,0 +1,268 @@
+//! Hotspots grouped by the library of the leaf frame, for finding the
+//! boundaries between the profiled program and the libraries it calls into:
+//! C libraries behind an FFI, system libraries and the kernel.
+//!
+//! Self time goes to the library of the leaf frame. Time whose leaf is in a
+//! foreign library is also attributed to the innermost function of the
+//! program's own code beneath it, which is where the program called out.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::stack_stats::is_kernel_library;
+use crate::{ProfileAnalyzer, TimeRange};
+
+/// How a hotspots query groups the samples
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HotspotsGroupBy {
+    /// Self and total time of each function
+    #[default]
+    Function,
+    /// Self time of each leaf library, and the program's functions which
+    /// spend time in other libraries
+    LeafModule,
+    /// Self and total time of each language runtime, e.g. JavaScript or
+    /// CPython, and native code
+    Language,
+}
+
+impl HotspotsGroupBy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HotspotsGroupBy::Function => "function",
+            HotspotsGroupBy::LeafModule => "leaf_module",
+            HotspotsGroupBy::Language => "language",
+        }
+    }
+}
+
+impl fmt::Display for HotspotsGroupBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for HotspotsGroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "function" => Ok(HotspotsGroupBy::Function),
+            "leaf_module" => Ok(HotspotsGroupBy::LeafModule),
+            "language" => Ok(HotspotsGroupBy::Language),
+            _ => Err(format!(
+                "Unknown groupby '{s}', expected 'function', 'leaf_module' or 'language'"
+            )),
+        }
+    }
+}
+
+/// Self time of the leaf frames in one library
+#[derive(Debug, Clone, Serialize)]
+pub struct LeafModuleEntry {
+    pub rank: usize,
+    pub library: String,
+    /// Whether the library is the profiled program's own code
+    pub is_own: bool,
+    pub self_samples: i64,
+    pub self_percent: f64,
+}
+
+/// Time which one of the program's functions spends in a foreign library
+#[derive(Debug, Clone, Serialize)]
+pub struct FfiBoundary {
+    pub rank: usize,
+    /// The innermost function of the program's own code on the stack
+    pub function: String,
+    pub library: String,
+    /// The library of the leaf frames
+    pub leaf_library: String,
+    pub samples: i64,
+    pub percent: f64,
+}
+
+/// Response for hotspots query with groupby=leaf_module
+#[derive(Debug, Clone, Serialize)]
+pub struct LeafModuleHotspotsResponse {
+    pub total_samples: i64,
+    /// Libraries which were counted as the program's own code
+    pub own_libraries: Vec<String>,
+    /// Libraries by self time, largest first
+    pub modules: Vec<LeafModuleEntry>,
+    /// The program's functions by time spent in each foreign library,
+    /// largest first
+    pub boundaries: Vec<FfiBoundary>,
+    /// Samples in foreign libraries without any of the program's functions
+    /// on the stack, like threads of a system library
+    pub unattributed_samples: i64,
+}
+
+/// Libraries of the operating system, which aren't the program's own code
+fn is_system_library(name: &str, path: &str) -> bool {
+    const SYSTEM_DIRS: &[&str] = &[
+        "/lib/",
+        "/lib32/",
+        "/lib64/",
+        "/usr/lib",
+        "/usr/libexec/",
+        "/System/",
+        "/apex/",
+        "/system/",
+        "/vendor/",
+    ];
+    is_kernel_library(name)
+        || name.starts_with('[')
+        || SYSTEM_DIRS.iter().any(|dir| path.starts_with(dir))
+        || path
+            .replace('\\', "/")
+            .to_ascii_lowercase()
+            .contains(":/windows/")
+}
+
+const UNKNOWN_LIBRARY: &str = "(unknown)";
+
+impl ProfileAnalyzer {
+    /// Self time per leaf library, and the FFI boundaries: for samples whose
+    /// leaf is in a foreign library, the innermost function of the program's
+    /// own libraries on the stack. `own_libraries` names the program's
+    /// libraries; if it's empty, every library outside of the operating
+    /// system's directories counts as the program's. Only threads whose name
+    /// contains `thread_filter` and samples in `range` are counted; at most
+    /// `limit` modules and boundaries are returned.
+    pub fn leaf_module_hotspots(
+        &self,
+        limit: usize,
+        thread_filter: Option<&str>,
+        own_libraries: &[String],
+        range: TimeRange,
+    ) -> LeafModuleHotspotsResponse {
+        let is_own: Vec<bool> = self
+            .libs
+            .iter()
+            .map(|lib| {
+                if own_libraries.is_empty() {
+                    !is_system_library(&lib.name, &lib.path)
+                } else {
+                    own_libraries.contains(&lib.name)
+                }
+            })
+            .collect();
+        let is_own_lib = |lib_idx: Option<usize>| lib_idx.is_some_and(|i| is_own[i]);
+        let lib_name = |lib_idx: Option<usize>| {
+            lib_idx.map_or(UNKNOWN_LIBRARY.to_string(), |i| self.libs[i].name.clone())
+        };
+
+        let mut total_samples = 0;
+        let mut unattributed_samples = 0;
+        let mut module_samples: HashMap<Option<usize>, i64> = HashMap::new();
+        // (own function, its library, leaf library) -> samples
+        let mut boundary_samples: HashMap<(String, Option<usize>, Option<usize>), i64> =
+            HashMap::new();
+
+        for thread in &self.threads {
+            if !thread.matches_filter(thread_filter) {
+                continue;
+            }
+            for (stack_idx, weight) in thread.samples_in(range) {
+                total_samples += weight;
+                let Some(stack_idx) = stack_idx else {
+                    continue;
+                };
+                // Leaf first
+                let funcs = thread.walk_stack(stack_idx);
+                let libs: Vec<Option<usize>> = funcs
+                    .iter()
+                    .map(|&func_idx| {
+                        thread
+                            .get_func_lib_index(func_idx)
+                            .filter(|&lib_idx| lib_idx < self.libs.len())
+                    })
+                    .collect();
+                let Some(&leaf_lib) = libs.first() else {
+                    continue;
+                };
+                *module_samples.entry(leaf_lib).or_default() += weight;
+                if is_own_lib(leaf_lib) {
+                    continue;
+                }
+                match libs.iter().position(|&lib_idx| is_own_lib(lib_idx)) {
+                    Some(pos) => {
+                        let function = thread.get_func_name(funcs[pos], &self.global_strings);
+                        *boundary_samples
+                            .entry((function, libs[pos], leaf_lib))
+                            .or_default() += weight;
+                    }
+                    None => unattributed_samples += weight,
+                }
+            }
+        }
+
+        let percent = |samples: i64| {
+            if total_samples > 0 {
+                100.0 * samples as f64 / total_samples as f64
+            } else {
+                0.0
+            }
+        };
+
+        let mut modules: Vec<LeafModuleEntry> = module_samples
+            .into_iter()
+            .map(|(lib_idx, samples)| LeafModuleEntry {
+                rank: 0,
+                library: lib_name(lib_idx),
+                is_own: is_own_lib(lib_idx),
+                self_samples: samples,
+                self_percent: percent(samples),
+            })
+            .collect();
+        modules.sort_by(|a, b| {
+            b.self_samples
+                .cmp(&a.self_samples)
+                .then_with(|| a.library.cmp(&b.library))
+        });
+        modules.truncate(limit);
+        for (i, module) in modules.iter_mut().enumerate() {
+            module.rank = i + 1;
+        }
+
+        let mut boundaries: Vec<FfiBoundary> = boundary_samples
+            .into_iter()
+            .map(|((function, lib_idx, leaf_lib), samples)| FfiBoundary {
+                rank: 0,
+                function,
+                library: lib_name(lib_idx),
+                leaf_library: lib_name(leaf_lib),
+                samples,
+                percent: percent(samples),
+            })
+            .collect();
+        boundaries.sort_by(|a, b| {
+            b.samples
+                .cmp(&a.samples)
+                .then_with(|| a.function.cmp(&b.function))
+                .then_with(|| a.leaf_library.cmp(&b.leaf_library))
+        });
+        boundaries.truncate(limit);
+        for (i, boundary) in boundaries.iter_mut().enumerate() {
+            boundary.rank = i + 1;
+        }
+
+        LeafModuleHotspotsResponse {
+            total_samples,
+            own_libraries: self
+                .libs
+                .iter()
+                .zip(&is_own)
+                .filter(|(_, &own)| own)
+                .map(|(lib, _)| lib.name.clone())
+                .collect(),
+            modules,
+            boundaries,
+            unattributed_samples,
+        }
+    }
+}
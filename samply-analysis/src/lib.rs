@@ -0,0 +1,5277 @@
+//! Profile analysis engine for computing hotspots, call trees, and summaries.
+//!
+//! This crate parses Firefox Profiler JSON format and provides analysis capabilities.
+//! It is used by `samply-for-ai`'s query server, and can be built for
+//! `wasm32-unknown-unknown` (without the default `native` feature, with the
+//! `wasm` feature) to run the same queries in the browser.
+//!
+//! Results are deterministic: lists are sorted by their weight, largest first,
+//! with ties broken by name, so the same query on the same profile always
+//! returns the same result.
+
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+mod activity;
+mod allocations;
+mod budgets;
+mod call_tree;
+mod callgrind;
+mod category;
+mod column;
+mod crash;
+mod export;
+mod format;
+mod function_identity;
+mod function_match;
+mod growth;
+mod hardware_events;
+mod hotspot_context;
+#[cfg(feature = "native")]
+mod index;
+mod language;
+mod leaf_module;
+mod md5;
+mod merge;
+mod otlp;
+mod paging;
+mod parse;
+pub mod plugin;
+mod pprof;
+mod process;
+mod signals;
+mod speedscope;
+mod stack_stats;
+mod trace;
+mod wait_graph;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use activity::{
+    ActivityPatterns, ActivitySplit, DEFAULT_BLOCKED_FUNCTIONS, DEFAULT_IDLE_FUNCTIONS,
+};
+pub use allocations::{AllocationSite, AllocationsResponse};
+pub use budgets::{BudgetCheck, BudgetResult, Budgets, BudgetsResponse};
+pub use call_tree::{CallTreeNode, CallTreeResponse, PrunedNodes};
+pub use category::{CategoriesResponse, CategoryEntry, CategoryFunction};
+use column::Column;
+pub use crash::{CrashFrame, CrashResponse};
+pub use export::{ExportFormat, ExportResponse};
+pub use format::{response_units, round_floats, DEFAULT_PRECISION, MAX_PRECISION};
+pub use function_identity::FunctionLink;
+use function_match::{near_misses, normalize_function_name, FunctionMatcher};
+pub use function_match::MatchMode;
+pub use growth::{GrowthEntry, GrowthResponse, GrowthWindow};
+pub use hardware_events::{event_track_name, HARDWARE_EVENTS};
+pub use language::{LanguageEntry, LanguageFunction, LanguageHotspotsResponse, NATIVE};
+pub use leaf_module::{FfiBoundary, HotspotsGroupBy, LeafModuleEntry, LeafModuleHotspotsResponse};
+pub use merge::{MergedThread, MergedThreads, ThreadMerge};
+pub use otlp::OtlpExport;
+pub use paging::{paginate, Page};
+pub use parse::ParseWarning;
+pub use process::{ProcessEntry, ProcessThread, ProcessesResponse};
+pub use signals::{SignalEntry, SignalHandler, SignalsResponse};
+pub use stack_stats::{DepthBucket, DepthStats, StackStats, StackStatsResponse, ThreadStackStats};
+pub use trace::{
+    TraceContext, TraceEntry, TraceSpan, TraceStack, TraceStacksResponse, TracesResponse,
+};
+pub use wait_graph::{Convoy, WaitEdge, WaitGraphResponse, WaitSite};
+
+/// Deserialize an address column, where -1 values are treated as None
+fn deserialize_address_column<'de, D>(deserializer: D) -> Result<Column<Option<u64>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    column::deserialize_packed(deserializer, |v: Option<i64>| match v {
+        Some(n) if n >= 0 => Some(n as u64),
+        _ => None,
+    })
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+/// Stores each distinct string once, so that the names which appear in the
+/// string tables of many threads (functions, libraries, files) are shared.
+#[derive(Default)]
+struct StringInterner(HashSet<Arc<str>>);
+
+impl StringInterner {
+    fn intern(&mut self, s: String) -> Arc<str> {
+        if let Some(existing) = self.0.get(s.as_str()) {
+            return existing.clone();
+        }
+        let s: Arc<str> = s.into();
+        self.0.insert(s.clone());
+        s
+    }
+}
+
+/// Error type for profile analysis operations
+#[derive(Debug)]
+pub enum AnalysisError {
+    IoError(std::io::Error),
+    JsonError(serde_json::Error),
+    InvalidProfile(String),
+}
+
+impl std::fmt::Display for AnalysisError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AnalysisError::IoError(e) => write!(f, "IO error: {}", e),
+            AnalysisError::JsonError(e) => write!(f, "JSON parse error: {}", e),
+            AnalysisError::InvalidProfile(msg) => write!(f, "Invalid profile: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for AnalysisError {}
+
+impl From<std::io::Error> for AnalysisError {
+    fn from(e: std::io::Error) -> Self {
+        AnalysisError::IoError(e)
+    }
+}
+
+impl From<serde_json::Error> for AnalysisError {
+    fn from(e: serde_json::Error) -> Self {
+        AnalysisError::JsonError(e)
+    }
+}
+
+/// Limits on the size of a profile, for loading profiles from untrusted
+/// sources without running out of memory, see
+/// [`ProfileAnalyzer::from_file_with_limits`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// Bytes of JSON, after decompression
+    pub max_bytes: u64,
+    pub max_threads: usize,
+    /// Samples across all threads
+    pub max_samples: usize,
+}
+
+impl ParseLimits {
+    /// The limits of `analyze serve --sandbox`
+    pub const SANDBOX: ParseLimits = ParseLimits {
+        max_bytes: 256 * 1024 * 1024,
+        max_threads: 1024,
+        max_samples: 10_000_000,
+    };
+}
+
+// ============================================================================
+// JSON structures for parsing Firefox Profiler format
+// ============================================================================
+
+/// A parsed profile, see `parse.rs`
+struct RawProfile {
+    meta: RawMeta,
+    libs: Vec<RawLib>,
+    threads: Vec<ThreadData>,
+    shared: Option<RawShared>,
+    /// What was skipped or repaired while parsing
+    warnings: Vec<ParseWarning>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+struct RawLib {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    path: String,
+    #[serde(rename = "debugName", default)]
+    debug_name: String,
+    #[serde(rename = "breakpadId", default)]
+    breakpad_id: String,
+    #[serde(default)]
+    arch: String,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct RawMeta {
+    #[serde(default)]
+    product: String,
+    #[serde(default)]
+    interval: f64,
+    #[serde(rename = "recordingOverhead", default)]
+    recording_overhead: Option<RecordingOverhead>,
+    #[serde(default)]
+    categories: Vec<RawCategory>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawCategory {
+    #[serde(default)]
+    name: String,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct RawShared {
+    #[serde(rename = "stringArray", default)]
+    string_array: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawThread {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    pid: String,
+    #[serde(default)]
+    tid: String,
+    #[serde(rename = "isMainThread", default)]
+    is_main_thread: bool,
+    #[serde(rename = "processName", default)]
+    process_name: String,
+    samples: RawSamples,
+    #[serde(rename = "stackTable")]
+    stack_table: RawStackTable,
+    #[serde(rename = "frameTable")]
+    frame_table: RawFrameTable,
+    #[serde(rename = "funcTable")]
+    func_table: RawFuncTable,
+    #[serde(rename = "nativeSymbols", default)]
+    native_symbols: Option<RawNativeSymbols>,
+    #[serde(rename = "resourceTable", default)]
+    resource_table: Option<RawResourceTable>,
+    #[serde(rename = "stringTable", default)]
+    string_table: Vec<String>,
+    #[serde(default)]
+    markers: Option<RawMarkers>,
+    #[serde(rename = "nativeAllocations", default)]
+    native_allocations: Option<RawNativeAllocations>,
+}
+
+/// The native allocations table: a byte-weighted stack per allocation, and
+/// negative weights for frees
+#[derive(Debug, Deserialize)]
+struct RawNativeAllocations {
+    #[serde(default)]
+    stack: Vec<Option<usize>>,
+    #[serde(default)]
+    weight: Vec<i64>,
+    #[serde(default)]
+    time: Vec<Option<f64>>,
+    #[serde(default)]
+    length: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawMarkers {
+    #[serde(default)]
+    data: Vec<Option<serde_json::Value>>,
+    #[serde(default)]
+    name: Vec<usize>,
+    #[serde(rename = "startTime", default)]
+    start_time: Vec<Option<f64>>,
+    #[serde(rename = "endTime", default)]
+    end_time: Vec<Option<f64>>,
+    #[serde(default)]
+    length: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawSamples {
+    #[serde(default)]
+    stack: Column<Option<usize>>,
+    #[serde(default)]
+    weight: Column<i64>,
+    #[serde(default)]
+    time: Option<Column<f64>>,
+    #[serde(rename = "timeDeltas", default)]
+    time_deltas: Option<Column<f64>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawStackTable {
+    #[serde(default)]
+    prefix: Column<Option<usize>>,
+    #[serde(default)]
+    frame: Column<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawFrameTable {
+    #[serde(default)]
+    func: Column<usize>,
+    #[serde(default)]
+    line: Column<Option<u32>>,
+    #[serde(default, deserialize_with = "deserialize_address_column")]
+    address: Column<Option<u64>>,
+    #[serde(default)]
+    category: Column<Option<u32>>,
+    #[serde(rename = "nativeSymbol", default)]
+    native_symbol: Vec<Option<usize>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawFuncTable {
+    #[serde(default)]
+    name: Vec<usize>, // Indices into string table
+    #[serde(rename = "fileName", default)]
+    file_name: Vec<Option<usize>>,
+    #[serde(rename = "lineNumber", default)]
+    line_number: Vec<Option<u32>>,
+    #[serde(default)]
+    resource: Vec<Option<i32>>, // Resource index (-1 if none)
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct RawNativeSymbols {
+    #[serde(default)]
+    address: Vec<u64>,
+    #[serde(rename = "functionSize", default)]
+    function_size: Vec<Option<u32>>,
+    #[serde(rename = "libIndex", default)]
+    lib_index: Vec<usize>,
+    #[serde(default)]
+    length: usize,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct RawResourceTable {
+    #[serde(default)]
+    lib: Vec<Option<usize>>,
+}
+
+// ============================================================================
+// Analysis result types (for JSON output)
+// ============================================================================
+
+/// Debug information for source/asm lookups
+#[derive(Debug, Clone, Serialize)]
+pub struct DebugInfo {
+    pub debug_name: String,
+    pub debug_id: String,
+}
+
+/// Extended function information with library, address, and size
+#[derive(Debug, Clone, Serialize)]
+pub struct FunctionInfo {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub library: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_number: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>, // Hex string like "0x12340"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u32>,
+}
+
+/// Per-line sample information
+#[derive(Debug, Clone, Serialize)]
+pub struct HotLine {
+    pub line: u32,
+    pub samples: i64,
+    pub percent: f64,
+}
+
+/// Per-address sample information with source line mapping
+#[derive(Debug, Clone, Serialize)]
+pub struct HotAddress {
+    pub offset: u64,  // Offset from function start
+    pub address: String, // Absolute address as hex string
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_line: Option<u32>, // Source line if available
+    pub samples: i64,
+    pub percent: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HotspotEntry {
+    pub rank: usize,
+    pub function: FunctionInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub debug_info: Option<DebugInfo>,
+    pub self_samples: i64,
+    pub total_samples: i64,
+    pub self_percent: f64,
+    pub total_percent: f64,
+    /// Category (e.g. "User", "Kernel") of most of the function's self samples
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    /// The heaviest root-to-leaf path ending in this function, shortened, if
+    /// requested with `context`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hot_lines: Option<Vec<HotLine>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hot_addresses: Option<Vec<HotAddress>>,
+}
+
+/// A thread's sample count and top hotspots, from a threads query
+#[derive(Debug, Clone, Serialize)]
+pub struct ThreadHotspots {
+    pub name: String,
+    pub pid: String,
+    pub tid: String,
+    pub is_main: bool,
+    /// Samples (weighted) in this thread
+    pub samples: i64,
+    /// This thread's share of the samples of all threads in its process
+    pub process_percent: f64,
+    /// Running, blocked and idle samples
+    pub activity: ActivitySplit,
+    /// Top functions by self time; percentages are relative to this thread's samples
+    pub hotspots: Vec<HotspotEntry>,
+    /// For merged threads, each thread's samples
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub merged_threads: Vec<MergedThread>,
+}
+
+/// Response for threads query
+#[derive(Debug, Clone, Serialize)]
+pub struct ThreadsResponse {
+    pub total_samples: i64,
+    /// Sorted by samples, descending
+    pub threads: Vec<ThreadHotspots>,
+}
+
+/// A single marker, from a markers query
+#[derive(Debug, Clone, Serialize)]
+pub struct MarkerEntry {
+    pub name: String,
+    pub thread: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_ms: Option<f64>,
+    /// Only set for interval markers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<f64>,
+    /// The marker's payload, as stored in the profile
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+/// Aggregate statistics over all matching markers with the same name
+#[derive(Debug, Clone, Serialize)]
+pub struct MarkerStats {
+    pub name: String,
+    pub count: usize,
+    /// Interval markers among `count`; the durations below are over these
+    pub interval_count: usize,
+    pub total_duration_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p50_duration_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p95_duration_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_duration_ms: Option<f64>,
+}
+
+/// Response for markers query
+#[derive(Debug, Clone, Serialize)]
+pub struct MarkersResponse {
+    /// Number of matching markers
+    pub total_count: usize,
+    /// Per-name statistics, sorted by total duration, then count
+    pub stats: Vec<MarkerStats>,
+    /// The first `limit` matching markers by start time
+    pub markers: Vec<MarkerEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CallerEntry {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub library: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_number: Option<u32>,
+    pub call_count: i64,
+    pub percent: f64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub callers: Vec<CallerEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CalleeEntry {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub library: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_number: Option<u32>,
+    pub call_count: i64,
+    pub percent: f64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub callees: Vec<CalleeEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ThreadSummary {
+    pub name: String,
+    pub pid: String,
+    pub tid: String,
+    pub is_main: bool,
+    pub sample_count: usize,
+    /// Running, blocked and idle samples
+    pub activity: ActivitySplit,
+}
+
+/// A time range in ms since the profile's start time. Unset bounds are open.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct TimeRange {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_ms: Option<f64>,
+}
+
+impl TimeRange {
+    /// Whether this range covers the whole profile
+    pub fn is_unbounded(&self) -> bool {
+        self.start_ms.is_none() && self.end_ms.is_none()
+    }
+
+    /// Start is inclusive, end is exclusive.
+    pub fn contains(&self, time_ms: f64) -> bool {
+        self.start_ms.is_none_or(|start| time_ms >= start)
+            && self.end_ms.is_none_or(|end| time_ms < end)
+    }
+
+    /// Whether the interval from `start_ms` to `end_ms` overlaps this range.
+    pub fn overlaps(&self, start_ms: f64, end_ms: f64) -> bool {
+        self.start_ms.is_none_or(|start| end_ms >= start)
+            && self.end_ms.is_none_or(|end| start_ms < end)
+    }
+}
+
+/// Resources used by samply itself while recording, from `meta.recordingOverhead`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingOverhead {
+    #[serde(alias = "durationMs")]
+    pub duration_ms: f64,
+    #[serde(alias = "cpuTimeMs")]
+    pub cpu_time_ms: f64,
+    #[serde(alias = "cpuPercentOfOneCore")]
+    pub cpu_percent_of_one_core: f64,
+    #[serde(alias = "peakMemoryBytes", default, skip_serializing_if = "Option::is_none")]
+    pub peak_memory_bytes: Option<u64>,
+    #[serde(alias = "lostEvents", default)]
+    pub lost_events: u64,
+}
+
+impl RecordingOverhead {
+    /// One-line summary, in the same format as the one printed after recording
+    pub fn description(&self) -> String {
+        fxprof_processed_profile::ProfilerOverhead {
+            duration: std::time::Duration::from_secs_f64(self.duration_ms.max(0.0) / 1000.0),
+            cpu_time: std::time::Duration::from_secs_f64(self.cpu_time_ms.max(0.0) / 1000.0),
+            peak_memory_bytes: self.peak_memory_bytes,
+            lost_events: self.lost_events,
+        }
+        .to_string()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileSummary {
+    pub product_name: String,
+    pub total_samples: i64,
+    pub sampling_interval_ms: f64,
+    pub thread_count: usize,
+    pub threads: Vec<ThreadSummary>,
+    /// Whether the profile appears to be symbolicated (function names are readable, not hex addresses)
+    pub is_symbolicated: bool,
+    /// Times of the first and last sample (ms since profile start), for choosing a time range
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_sample_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_sample_ms: Option<f64>,
+    /// The time range the sample counts are restricted to, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_range: Option<TimeRange>,
+    /// samply's own resource usage during the recording, if it was recorded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overhead: Option<RecordingOverhead>,
+    /// e.g. "samply overhead: 2.1% of one core, 40 MB peak memory, 0 lost events"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overhead_summary: Option<String>,
+    /// What was skipped or repaired while loading a malformed profile
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub parse_warnings: Vec<ParseWarning>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CallersResponse {
+    pub function: String,
+    pub callers: Vec<CallerEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CalleesResponse {
+    pub function: String,
+    pub callees: Vec<CalleeEntry>,
+}
+
+/// A single disassembled instruction
+#[derive(Debug, Clone, Serialize)]
+pub struct AsmInstruction {
+    pub address: String,
+    pub asm: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub samples: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percent: Option<f64>,
+}
+
+/// A region of instructions grouped by source line
+#[derive(Debug, Clone, Serialize)]
+pub struct AsmRegion {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_line: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_text: Option<String>,
+    pub instructions: Vec<AsmInstruction>,
+}
+
+/// Response for assembly query
+#[derive(Debug, Clone, Serialize)]
+pub struct AsmResponse {
+    pub function: FunctionInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    /// Regions of instructions with context around hot spots
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub regions: Option<Vec<AsmRegion>>,
+    /// Total self samples in this function
+    pub self_samples: i64,
+    /// Error message if disassembly failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The library and sampled instruction addresses of a function, used to look
+/// up its symbol with the symbol manager before disassembling it
+#[derive(Debug, Clone)]
+pub struct AsmSymbolRequest {
+    pub debug_name: String,
+    pub debug_id: String,
+    /// Relative addresses of the function's sampled instructions
+    pub addresses: Vec<u64>,
+}
+
+/// Symbol information for an asm query, obtained from the symbol manager
+#[derive(Debug, Clone, Default)]
+pub struct AsmSymbolInfo {
+    /// Relative address of the function's symbol
+    pub address: u64,
+    pub size: Option<u32>,
+    pub file_path: Option<String>,
+    /// MD5 of `file_path` when the binary was built, if the debug info has
+    /// it (DWARF 5)
+    pub file_md5: Option<[u8; 16]>,
+    /// Source line of each looked up instruction address
+    pub lines: HashMap<u64, u32>,
+}
+
+/// A source line annotated with sample counts
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceLine {
+    pub line: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Samples where this line was executing in the function itself
+    pub self_samples: i64,
+    /// Samples where this line was on the stack (including time in callees)
+    pub total_samples: i64,
+    pub self_percent: f64,
+    pub total_percent: f64,
+}
+
+/// The checksum of the local source file which a source query read, and of
+/// the file which the function was built from
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceHash {
+    /// "md5"
+    pub algorithm: &'static str,
+    /// Of the local file, in hex
+    pub local: String,
+    /// Recorded in the debug info, in hex, if it has checksums (DWARF 5)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub debug_info: Option<String>,
+    /// Whether the local file is the one which was compiled, if known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matches: Option<bool>,
+}
+
+/// Response for source query
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceResponse {
+    pub function: FunctionInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    /// Total self samples in this function
+    pub self_samples: i64,
+    /// Total samples with this function on the stack
+    pub total_samples: i64,
+    /// Lines of the function (plus context), annotated with per-line sample counts.
+    /// Percentages are relative to the function's total samples.
+    pub lines: Vec<SourceLine>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_hash: Option<SourceHash>,
+    /// Set if the local source file was edited since the function was built,
+    /// so that its line numbers may point at the wrong lines
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+    /// Error message if the function or its source file could not be found
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// ============================================================================
+// Drilldown response types
+// ============================================================================
+
+/// A callee in the drilldown path with time percentage
+#[derive(Debug, Clone, Serialize)]
+pub struct DrilldownCallee {
+    pub name: String,
+    pub percent: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_hottest: Option<bool>,
+}
+
+/// One node in the drilldown path
+#[derive(Debug, Clone, Serialize)]
+pub struct DrilldownNode {
+    pub function: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub library: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_number: Option<u32>,
+    pub total_samples: i64,
+    pub total_percent: f64,
+    pub self_samples: i64,
+    pub self_percent: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_bottleneck: Option<bool>,
+    pub callees: Vec<DrilldownCallee>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hot_lines: Option<Vec<HotLine>>,
+}
+
+/// Summary of detected bottleneck
+#[derive(Debug, Clone, Serialize)]
+pub struct BottleneckSummary {
+    pub function: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub library: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_number: Option<u32>,
+    pub self_percent: f64,
+    pub reason: String,
+}
+
+/// Response for drilldown query
+#[derive(Debug, Clone, Serialize)]
+pub struct DrilldownResponse {
+    pub root: String,
+    pub total_samples: i64,
+    pub path: Vec<DrilldownNode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bottleneck: Option<BottleneckSummary>,
+    /// Error message if function was not found or had no samples
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Top functions as suggestions when the requested function wasn't found
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggestions: Option<Vec<String>>,
+}
+
+/// Returned instead of a query result when the function pattern matched no
+/// function in the profile
+#[derive(Debug, Clone, Serialize)]
+pub struct FunctionNotFound {
+    pub error: String,
+    /// Function names which are similar to the pattern, closest first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub candidates: Vec<String>,
+}
+
+/// What made a query fail, so that clients can tell failures apart without
+/// parsing the error message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum QueryErrorCode {
+    /// A parameter which the query needs is missing
+    MissingParam,
+    /// A parameter has a value which the query doesn't accept
+    InvalidParam,
+    /// No function in the profile matches the `function` parameter
+    UnknownFunction,
+    /// No profile, process, thread or query endpoint has the requested name
+    NotFound,
+    /// The query compares against a baseline profile, and there is none
+    NoBaseline,
+    /// The server has no profile analysis to query
+    NoAnalyzer,
+    /// The query is disabled on this server
+    Disabled,
+    /// The query itself failed
+    QueryFailed,
+}
+
+impl QueryErrorCode {
+    pub const ALL: [QueryErrorCode; 8] = [
+        Self::MissingParam,
+        Self::InvalidParam,
+        Self::UnknownFunction,
+        Self::NotFound,
+        Self::NoBaseline,
+        Self::NoAnalyzer,
+        Self::Disabled,
+        Self::QueryFailed,
+    ];
+
+    /// The HTTP status of the responses with this error
+    pub fn http_status(self) -> u16 {
+        match self {
+            Self::MissingParam | Self::InvalidParam | Self::NoBaseline => 400,
+            Self::Disabled => 403,
+            Self::UnknownFunction | Self::NotFound => 404,
+            Self::NoAnalyzer | Self::QueryFailed => 500,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::MissingParam => "MISSING_PARAM",
+            Self::InvalidParam => "INVALID_PARAM",
+            Self::UnknownFunction => "UNKNOWN_FUNCTION",
+            Self::NotFound => "NOT_FOUND",
+            Self::NoBaseline => "NO_BASELINE",
+            Self::NoAnalyzer => "NO_ANALYZER",
+            Self::Disabled => "DISABLED",
+            Self::QueryFailed => "QUERY_FAILED",
+        }
+    }
+}
+
+impl std::fmt::Display for QueryErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The response of a failed query: `{"success": false, "code": ..., "error":
+/// ...}`, with the `candidates` for an unknown function
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryFailure {
+    pub code: QueryErrorCode,
+    pub error: String,
+    /// Function names which are similar to the requested one, closest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub candidates: Vec<String>,
+}
+
+impl QueryFailure {
+    pub fn new(code: QueryErrorCode, error: impl Into<String>) -> Self {
+        Self {
+            code,
+            error: error.into(),
+            candidates: Vec::new(),
+        }
+    }
+
+    /// The JSON body of the response
+    pub fn to_json(&self) -> String {
+        let mut response = serde_json::to_value(self).unwrap_or_default();
+        response["success"] = serde_json::Value::Bool(false);
+        response.to_string()
+    }
+}
+
+impl From<FunctionNotFound> for QueryFailure {
+    fn from(not_found: FunctionNotFound) -> Self {
+        Self {
+            code: QueryErrorCode::UnknownFunction,
+            error: not_found.error,
+            candidates: not_found.candidates,
+        }
+    }
+}
+
+// ============================================================================
+// Flame graph types
+// ============================================================================
+
+/// A node of a flame graph call tree, in the nested `{name, value, children}`
+/// shape used by d3-flamegraph
+#[derive(Debug, Clone, Serialize)]
+pub struct FlameGraphNode {
+    pub name: String,
+    /// Samples in this node and all its children
+    pub value: i64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<FlameGraphNode>,
+}
+
+/// Response for flamegraph query
+#[derive(Debug, Clone, Serialize)]
+pub struct FlameGraphResponse {
+    /// Whether the tree is rooted at leaf functions (callers are children)
+    pub inverted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread: Option<String>,
+    pub root: FlameGraphNode,
+}
+
+// ============================================================================
+// Profile diff types
+// ============================================================================
+
+/// How a function changed between the baseline and the current profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffStatus {
+    /// Only sampled in the current profile
+    New,
+    /// Only sampled in the baseline profile
+    Removed,
+    /// Sampled in both profiles
+    Changed,
+    /// Probably the same function as one with another name in the baseline,
+    /// which is in `baseline_function`
+    Renamed,
+}
+
+/// Per-function self/total comparison between two profiles.
+///
+/// Percentages are relative to each profile's own total samples, so profiles of
+/// different lengths can be compared. Deltas are in percentage points.
+#[derive(Debug, Clone, Serialize)]
+pub struct FunctionDiff {
+    pub function: String,
+    pub status: DiffStatus,
+    /// The baseline's name of a renamed function
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baseline_function: Option<String>,
+    pub baseline_self_samples: i64,
+    pub self_samples: i64,
+    pub baseline_self_percent: f64,
+    pub self_percent: f64,
+    pub self_percent_delta: f64,
+    pub baseline_total_percent: f64,
+    pub total_percent: f64,
+    pub total_percent_delta: f64,
+    /// Relative change of self_percent, e.g. 50.0 if self-time went from 10% to 15%
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub self_change_percent: Option<f64>,
+}
+
+/// Response for diff query
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffResponse {
+    pub baseline_total_samples: i64,
+    pub total_samples: i64,
+    /// Functions sorted by absolute self_percent_delta, then total_percent_delta
+    pub functions: Vec<FunctionDiff>,
+    /// Hotspots (by self-time) of the current profile which the baseline never sampled
+    pub new_hotspots: Vec<String>,
+    /// Hotspots (by self-time) of the baseline which the current profile never sampled
+    pub removed_hotspots: Vec<String>,
+    /// Functions which only one of the profiles has under their name, but
+    /// which are probably the same function, best matches first. They are
+    /// compared with each other in `functions`, with status `renamed`.
+    pub renamed: Vec<FunctionLink>,
+}
+
+/// A function whose stacks carry a share of the regression, and the leaf
+/// functions which drive it
+#[derive(Debug, Clone, Serialize)]
+pub struct RegressionCluster {
+    pub rank: usize,
+    /// The function which the regressed samples have in common on their stacks
+    pub function: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub library: Option<String>,
+    /// The heaviest call path from the root to `function`
+    pub call_path: Vec<String>,
+    /// Self-time regression explained by this cluster, in percentage points
+    pub regression_percent: f64,
+    /// Share of the total regression, in percent
+    pub share_percent: f64,
+    /// The leaf functions with the largest regression under `function`
+    pub drivers: Vec<RegressionDriver>,
+}
+
+/// A leaf function whose self-time grew under a [`RegressionCluster`]
+#[derive(Debug, Clone, Serialize)]
+pub struct RegressionDriver {
+    pub function: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub library: Option<String>,
+    pub status: DiffStatus,
+    /// Self-time regression of this function under the cluster, in percentage points
+    pub regression_percent: f64,
+}
+
+/// Self-time regression of the functions of one library
+#[derive(Debug, Clone, Serialize)]
+pub struct ModuleRegression {
+    pub library: String,
+    /// Sum of the self-time increases of the library's functions, in percentage points
+    pub regression_percent: f64,
+    /// Share of the total regression, in percent
+    pub share_percent: f64,
+}
+
+/// Response for diff-explain query
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffExplainResponse {
+    pub baseline_total_samples: i64,
+    pub total_samples: i64,
+    /// Sum of the self-time increases of all functions, in percentage points
+    pub regression_percent: f64,
+    /// Sum of the self-time decreases of all functions, in percentage points
+    pub improvement_percent: f64,
+    /// Short ranked explanation of the regression, one sentence per line
+    pub narrative: Vec<String>,
+    /// Regression clusters by shared ancestor, largest first
+    pub clusters: Vec<RegressionCluster>,
+    /// Regression per library, largest first
+    pub modules: Vec<ModuleRegression>,
+}
+
+// ============================================================================
+// Symbolication status types
+// ============================================================================
+
+/// Symbolication state of a library
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolicationState {
+    /// Symbols are available for this library
+    Resolved,
+    /// The server is still looking for symbols
+    Pending,
+    /// No usable symbols were found
+    Failed,
+}
+
+/// Where the symbols for a library came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolSource {
+    /// Function names were already present in the profile (presymbolicated)
+    Profile,
+    /// The `.syms.bin` or `.syms.json` sidecar file next to the profile
+    Precog,
+    /// A local binary or debug file
+    Local,
+    /// A symbol server or debuginfod
+    Server,
+}
+
+/// Symbolication status of a single library
+#[derive(Debug, Clone, Serialize)]
+pub struct LibrarySymbolicationStatus {
+    pub library: String,
+    pub debug_name: String,
+    pub debug_id: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub path: String,
+    pub state: SymbolicationState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<SymbolSource>,
+    /// Distinct functions of this library with readable names in the profile
+    pub resolved_functions: usize,
+    /// Distinct functions of this library whose names are still hex addresses
+    pub unresolved_functions: usize,
+    /// Self samples attributed to this library
+    pub self_samples: i64,
+    /// Addresses looked up in the sidecar which it doesn't list, and which got
+    /// the symbol whose range contains them, without inlined functions or
+    /// lines
+    #[serde(skip_serializing_if = "is_zero")]
+    pub approximate_lookups: usize,
+    /// Short machine-readable failure reason (e.g. "missing_pdb", "hash_mismatch", "not_found")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Response for symbolication status query
+#[derive(Debug, Clone, Serialize)]
+pub struct SymbolicationStatusResponse {
+    pub resolved: usize,
+    pub pending: usize,
+    pub failed: usize,
+    pub libraries: Vec<LibrarySymbolicationStatus>,
+}
+
+// ============================================================================
+// ProfileAnalyzer - main analysis engine
+// ============================================================================
+
+/// Library information
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LibInfo {
+    name: String,
+    path: String,
+    debug_name: String,
+    debug_id: String, // breakpadId
+    arch: String,
+}
+
+impl From<RawLib> for LibInfo {
+    fn from(lib: RawLib) -> Self {
+        LibInfo {
+            name: lib.name,
+            path: lib.path,
+            debug_name: lib.debug_name,
+            debug_id: lib.breakpad_id,
+            arch: lib.arch,
+        }
+    }
+}
+
+/// Native symbol information for a function
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct NativeSymbolInfo {
+    address: u64,
+    size: Option<u32>,
+    lib_index: Option<usize>,
+}
+
+/// A marker of a thread. Instant markers have no end time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MarkerData {
+    name_idx: usize,
+    start_ms: Option<f64>,
+    end_ms: Option<f64>,
+    data: Option<serde_json::Value>,
+}
+
+/// An allocation (positive bytes) or a free (negative bytes) in a thread's
+/// native allocations table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AllocationData {
+    stack: Option<usize>,
+    bytes: i64,
+    time_ms: Option<f64>,
+}
+
+/// Samples of a function, aggregated for a hotspots query
+#[derive(Default)]
+struct FuncStats {
+    self_samples: i64,
+    total_samples: i64,
+    func_idx: Option<usize>,
+    thread_idx: Option<usize>,
+    // Per-line sample counts (line_number -> samples)
+    line_samples: HashMap<u32, i64>,
+    // Per-address sample counts (address -> samples)
+    address_samples: HashMap<u64, i64>,
+    // Per-category self sample counts (category index -> samples)
+    category_samples: HashMap<usize, i64>,
+}
+
+/// Samples of one profile, aggregated for a diff-explain query
+#[derive(Default)]
+struct RegressionTally {
+    total_weight: i64,
+    /// Self samples per function
+    self_samples: HashMap<String, i64>,
+    /// Total samples per function
+    total_samples: HashMap<String, i64>,
+    /// Per function, the self samples of each leaf function on whose stacks it
+    /// appears (including its own)
+    leaves_under: HashMap<String, HashMap<String, i64>>,
+    /// Library name per function
+    libraries: HashMap<String, String>,
+    /// Per function, the samples of each stack node (thread index, stack index)
+    /// which it is the frame of, to find its heaviest call path
+    nodes: HashMap<String, HashMap<(usize, usize), i64>>,
+    /// Deepest position (0 = root) at which each function was seen
+    depth: HashMap<String, usize>,
+}
+
+/// Self samples of a function, aggregated for an asm query
+struct AsmSamples {
+    func_idx: Option<usize>,
+    thread_idx: Option<usize>,
+    lib_idx: Option<usize>,
+    self_samples: i64,
+    /// (samples, source_line) per address
+    address_data: HashMap<u64, (i64, Option<u32>)>,
+}
+
+/// Holds parsed profile data and provides analysis methods
+pub struct ProfileAnalyzer {
+    product_name: String,
+    sampling_interval_ms: f64,
+    /// Shared with the views of [`ProfileAnalyzer::for_process`]
+    threads: Vec<Arc<ThreadData>>,
+    /// The tracks of hardware event samples, with their index in the
+    /// profile's threads; see [`ProfileAnalyzer::for_metric`]
+    event_tracks: Vec<hardware_events::EventTrack>,
+    /// Global string table (from shared.stringArray if present)
+    global_strings: Arc<[String]>,
+    /// Library information
+    libs: Vec<LibInfo>,
+    recording_overhead: Option<RecordingOverhead>,
+    /// Category names (e.g. "User", "Kernel"), indexed by the frames' category
+    categories: Vec<String>,
+    /// What was skipped or repaired while loading the profile
+    parse_warnings: Vec<ParseWarning>,
+    /// The functions which classify samples as idle or blocked
+    activity_patterns: Arc<ActivityPatterns>,
+}
+
+struct ThreadData {
+    name: String,
+    pid: String,
+    /// Empty if the profile doesn't name the thread's process
+    process_name: String,
+    tid: String,
+    is_main_thread: bool,
+    /// Samples: stack index and weight of each sample
+    sample_stack: Column<Option<usize>>,
+    sample_weight: Column<i64>,
+    /// Sample times in ms since the profile's start time; empty if the profile has none
+    sample_time: Column<f64>,
+    /// Stack table: prefix[i] and frame[i] for stack i
+    stack_prefix: Column<Option<usize>>,
+    stack_frame: Column<usize>,
+    /// Frame table: func[i] for frame i
+    frame_func: Column<usize>,
+    /// Frame table: address and line for each frame
+    frame_address: Column<Option<u64>>,
+    frame_line: Column<Option<u32>>,
+    /// Frame table: index into the profile's categories for each frame
+    frame_category: Column<Option<u32>>,
+    frame_native_symbol: Vec<Option<usize>>,
+    /// Func table: name string index, file name index, line number, resource
+    func_name_idx: Vec<usize>,
+    func_file_idx: Vec<Option<usize>>,
+    func_line: Vec<Option<u32>>,
+    func_resource: Vec<Option<i32>>,
+    /// Native symbols: address, size, lib_index per symbol
+    native_symbols: Vec<NativeSymbolInfo>,
+    /// Resource table: lib_index per resource
+    resource_lib: Vec<Option<usize>>,
+    /// Local string table, interned across threads
+    string_table: Vec<Arc<str>>,
+    markers: Vec<MarkerData>,
+    /// Allocations are on the main thread of the process which holds them
+    allocations: Vec<AllocationData>,
+}
+
+impl ThreadData {
+    /// Convert a parsed thread, repairing its tables; see [`ThreadData::repair`].
+    /// Problems with the thread are added to `problems`.
+    fn from_raw(t: RawThread, strings: &mut StringInterner, problems: &mut Vec<String>) -> Self {
+        // Extract native symbols
+        let native_symbols: Vec<NativeSymbolInfo> = t
+            .native_symbols
+            .map(|ns| {
+                (0..ns.length)
+                    .map(|i| NativeSymbolInfo {
+                        address: ns.address.get(i).copied().unwrap_or(0),
+                        size: ns.function_size.get(i).copied().flatten(),
+                        lib_index: ns.lib_index.get(i).copied(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        // Extract resource table (lib mapping)
+        let resource_lib: Vec<Option<usize>> =
+            t.resource_table.map(|rt| rt.lib).unwrap_or_default();
+
+        // Only keep samples which have both a stack and a weight
+        let mut sample_stack = t.samples.stack;
+        let mut sample_weight = t.samples.weight;
+        let sample_count = sample_stack.len().min(sample_weight.len());
+        let dropped = sample_stack.len().max(sample_weight.len()) - sample_count;
+        if dropped > 0 {
+            problems.push(format!(
+                "{dropped} samples without both a stack and a weight were dropped"
+            ));
+        }
+        sample_stack.truncate(sample_count);
+        sample_weight.truncate(sample_count);
+
+        let mut sample_time = match (t.samples.time, t.samples.time_deltas) {
+            (Some(time), _) => time,
+            (None, Some(deltas)) => Column::packed(deltas.iter().scan(0.0, |time, delta| {
+                *time += delta;
+                Some(*time)
+            })),
+            (None, None) => Column::default(),
+        };
+        sample_time.truncate(sample_count);
+
+        let markers: Vec<MarkerData> = t
+            .markers
+            .map(|m| {
+                let mut data = m.data.into_iter();
+                (0..m.length.min(m.name.len()))
+                    .map(|i| MarkerData {
+                        name_idx: m.name[i],
+                        start_ms: m.start_time.get(i).copied().flatten(),
+                        end_ms: m.end_time.get(i).copied().flatten(),
+                        data: data.next().flatten(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let allocations: Vec<AllocationData> = t
+            .native_allocations
+            .map(|a| {
+                let count = a.length.min(a.stack.len()).min(a.weight.len());
+                (0..count)
+                    .map(|i| AllocationData {
+                        stack: a.stack[i],
+                        bytes: a.weight[i],
+                        time_ms: a.time.get(i).copied().flatten(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut thread = ThreadData {
+            name: t.name,
+            pid: t.pid,
+            process_name: t.process_name,
+            tid: t.tid,
+            is_main_thread: t.is_main_thread,
+            sample_stack,
+            sample_weight,
+            sample_time,
+            stack_prefix: t.stack_table.prefix,
+            stack_frame: t.stack_table.frame,
+            frame_func: t.frame_table.func,
+            frame_address: t.frame_table.address,
+            frame_line: t.frame_table.line,
+            frame_category: t.frame_table.category,
+            frame_native_symbol: t.frame_table.native_symbol,
+            func_name_idx: t.func_table.name,
+            func_file_idx: t.func_table.file_name,
+            func_line: t.func_table.line_number,
+            func_resource: t.func_table.resource,
+            native_symbols,
+            resource_lib,
+            string_table: t
+                .string_table
+                .into_iter()
+                .map(|s| strings.intern(s))
+                .collect(),
+            markers,
+            allocations,
+        };
+        thread.repair(problems);
+        thread
+    }
+
+    /// Whether the thread's name contains `thread_filter`; every thread does
+    /// without a filter.
+    fn matches_filter(&self, thread_filter: Option<&str>) -> bool {
+        thread_filter.is_none_or(|filter| self.name.contains(filter))
+    }
+
+    fn get_string(&self, idx: usize, global_strings: &[String]) -> String {
+        // Try local string table first, then global
+        if idx < self.string_table.len() {
+            self.string_table[idx].to_string()
+        } else if idx < global_strings.len() {
+            global_strings[idx].clone()
+        } else {
+            format!("<string {}>", idx)
+        }
+    }
+
+    fn get_func_name(&self, func_idx: usize, global_strings: &[String]) -> String {
+        if func_idx < self.func_name_idx.len() {
+            let name_idx = self.func_name_idx[func_idx];
+            self.get_string(name_idx, global_strings)
+        } else {
+            format!("<func {}>", func_idx)
+        }
+    }
+
+    fn get_func_file(&self, func_idx: usize, global_strings: &[String]) -> Option<String> {
+        if func_idx < self.func_file_idx.len() {
+            self.func_file_idx[func_idx].map(|idx| self.get_string(idx, global_strings))
+        } else {
+            None
+        }
+    }
+
+    fn get_func_line(&self, func_idx: usize) -> Option<u32> {
+        if func_idx < self.func_line.len() {
+            self.func_line[func_idx]
+        } else {
+            None
+        }
+    }
+
+    /// Get library index for a function via resource table
+    fn get_func_lib_index(&self, func_idx: usize) -> Option<usize> {
+        if func_idx < self.func_resource.len() {
+            if let Some(res_idx) = self.func_resource[func_idx] {
+                if res_idx >= 0 && (res_idx as usize) < self.resource_lib.len() {
+                    return self.resource_lib[res_idx as usize];
+                }
+            }
+        }
+        None
+    }
+
+    /// Iterate over (stack_index, weight) pairs
+    fn samples(&self) -> impl Iterator<Item = (Option<usize>, i64)> + '_ {
+        self.sample_stack.iter().zip(self.sample_weight.iter())
+    }
+
+    /// The samples whose time is in `range`. With a bounded range, samples
+    /// without a time are skipped.
+    fn samples_in(&self, range: TimeRange) -> impl Iterator<Item = (Option<usize>, i64)> + '_ {
+        self.samples()
+            .enumerate()
+            .filter(move |(i, _)| {
+                range.is_unbounded() || self.sample_time.get(*i).is_some_and(|t| range.contains(t))
+            })
+            .map(|(_, sample)| sample)
+    }
+
+    fn sample_count(&self) -> usize {
+        self.sample_stack.len().min(self.sample_weight.len())
+    }
+
+    fn get_frame_func(&self, frame_idx: usize) -> usize {
+        self.frame_func.get(frame_idx).unwrap_or(0)
+    }
+
+    fn get_frame_address(&self, frame_idx: usize) -> Option<u64> {
+        self.frame_address.get(frame_idx).flatten()
+    }
+
+    fn get_frame_line(&self, frame_idx: usize) -> Option<u32> {
+        self.frame_line.get(frame_idx).flatten()
+    }
+
+    fn get_frame_category(&self, frame_idx: usize) -> Option<usize> {
+        self.frame_category.get(frame_idx).flatten().map(|c| c as usize)
+    }
+
+    fn get_frame_native_symbol(&self, frame_idx: usize) -> Option<&NativeSymbolInfo> {
+        if frame_idx < self.frame_native_symbol.len() {
+            if let Some(ns_idx) = self.frame_native_symbol[frame_idx] {
+                return self.native_symbols.get(ns_idx);
+            }
+        }
+        None
+    }
+
+    fn get_stack_frame(&self, stack_idx: usize) -> usize {
+        self.stack_frame.get(stack_idx).unwrap_or(0)
+    }
+
+    fn get_stack_prefix(&self, stack_idx: usize) -> Option<usize> {
+        self.stack_prefix.get(stack_idx).flatten()
+    }
+
+    /// Walk the stack from leaf to root, collecting function indices
+    fn walk_stack(&self, stack_idx: usize) -> Vec<usize> {
+        let mut funcs = Vec::new();
+        let mut current = Some(stack_idx);
+
+        while let Some(idx) = current {
+            let frame_idx = self.get_stack_frame(idx);
+            let func_idx = self.get_frame_func(frame_idx);
+            funcs.push(func_idx);
+            current = self.get_stack_prefix(idx);
+        }
+
+        funcs
+    }
+
+    /// Walk the stack from leaf to root, collecting (func_idx, frame_idx) pairs
+    fn walk_stack_with_frames(&self, stack_idx: usize) -> Vec<(usize, usize)> {
+        let mut result = Vec::new();
+        let mut current = Some(stack_idx);
+
+        while let Some(idx) = current {
+            let frame_idx = self.get_stack_frame(idx);
+            let func_idx = self.get_frame_func(frame_idx);
+            result.push((func_idx, frame_idx));
+            current = self.get_stack_prefix(idx);
+        }
+
+        result
+    }
+}
+
+/// Open a profile file for reading, decompressing it if it's gzipped
+#[cfg(feature = "native")]
+fn open_profile(path: &std::path::Path) -> std::io::Result<Box<dyn std::io::Read>> {
+    let file = std::fs::File::open(path)?;
+    if path.extension().is_some_and(|e| e == "gz") {
+        Ok(Box::new(flate2::read::GzDecoder::new(file)))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+impl ProfileAnalyzer {
+    /// Load and parse a profile from a file path
+    #[cfg(feature = "native")]
+    pub fn from_file(path: &std::path::Path) -> Result<Self, AnalysisError> {
+        let reader = std::io::BufReader::new(open_profile(path)?);
+        Self::from_raw_profile(RawProfile::parse(serde_json::Deserializer::from_reader(
+            reader,
+        ))?)
+    }
+
+    /// Load and parse a profile from a file path, rejecting it if it exceeds
+    /// `limits`. The file is read (and decompressed) into memory up to
+    /// `limits.max_bytes`, so a small gzipped file can't expand without bounds.
+    #[cfg(feature = "native")]
+    pub fn from_file_with_limits(
+        path: &std::path::Path,
+        limits: &ParseLimits,
+    ) -> Result<Self, AnalysisError> {
+        use std::fs::File;
+        use std::io::Read;
+
+        let too_large = || {
+            AnalysisError::InvalidProfile(format!(
+                "Profile is larger than the limit of {} MB",
+                limits.max_bytes / (1024 * 1024)
+            ))
+        };
+
+        let file = File::open(path)?;
+        if file.metadata()?.len() > limits.max_bytes {
+            return Err(too_large());
+        }
+        let reader: Box<dyn Read> = if path.extension().is_some_and(|e| e == "gz") {
+            Box::new(flate2::read::GzDecoder::new(file))
+        } else {
+            Box::new(file)
+        };
+        let mut json = Vec::new();
+        reader.take(limits.max_bytes + 1).read_to_end(&mut json)?;
+        if json.len() as u64 > limits.max_bytes {
+            return Err(too_large());
+        }
+
+        let analyzer = Self::from_raw_profile(RawProfile::parse(
+            serde_json::Deserializer::from_slice(&json),
+        )?)?;
+        analyzer.check_limits(limits)?;
+        Ok(analyzer)
+    }
+
+    /// Check the number of threads and samples of a parsed profile
+    fn check_limits(&self, limits: &ParseLimits) -> Result<(), AnalysisError> {
+        if self.threads.len() > limits.max_threads {
+            return Err(AnalysisError::InvalidProfile(format!(
+                "Profile has {} threads, more than the limit of {}",
+                self.threads.len(),
+                limits.max_threads
+            )));
+        }
+        let samples: usize = self.threads.iter().map(|t| t.sample_count()).sum();
+        if samples > limits.max_samples {
+            return Err(AnalysisError::InvalidProfile(format!(
+                "Profile has {} samples, more than the limit of {}",
+                samples, limits.max_samples
+            )));
+        }
+        Ok(())
+    }
+
+    /// Parse a profile from its (uncompressed) JSON text
+    pub fn from_json(json: &str) -> Result<Self, AnalysisError> {
+        Self::from_raw_profile(RawProfile::parse(serde_json::Deserializer::from_str(json))?)
+    }
+
+    fn from_raw_profile(raw: RawProfile) -> Result<Self, AnalysisError> {
+        let global_strings = raw.shared.map(|s| s.string_array).unwrap_or_default();
+
+        // Extract library information
+        let libs: Vec<LibInfo> = raw.libs.into_iter().map(LibInfo::from).collect();
+
+        let (threads, event_tracks) =
+            hardware_events::split_event_tracks(raw.threads.into_iter().map(Arc::new).collect());
+
+        Ok(Self {
+            product_name: raw.meta.product,
+            sampling_interval_ms: raw.meta.interval,
+            threads,
+            event_tracks,
+            global_strings: global_strings.into(),
+            libs,
+            recording_overhead: raw.meta.recording_overhead,
+            categories: raw.meta.categories.into_iter().map(|c| c.name).collect(),
+            parse_warnings: raw.warnings,
+            activity_patterns: Default::default(),
+        })
+    }
+
+    /// What was skipped or repaired while loading the profile
+    pub fn parse_warnings(&self) -> &[ParseWarning] {
+        &self.parse_warnings
+    }
+
+    /// A view of the profile with only the threads for which `include_thread`
+    /// returns true. The threads' data is shared, not copied.
+    fn with_threads(&self, include_thread: impl Fn(&ThreadData) -> bool) -> ProfileAnalyzer {
+        ProfileAnalyzer {
+            product_name: self.product_name.clone(),
+            sampling_interval_ms: self.sampling_interval_ms,
+            threads: self
+                .threads
+                .iter()
+                .filter(|thread| include_thread(thread))
+                .cloned()
+                .collect(),
+            event_tracks: self
+                .event_tracks
+                .iter()
+                .filter(|(_, track)| include_thread(track))
+                .cloned()
+                .collect(),
+            global_strings: self.global_strings.clone(),
+            libs: self.libs.clone(),
+            recording_overhead: self.recording_overhead.clone(),
+            categories: self.categories.clone(),
+            parse_warnings: self.parse_warnings.clone(),
+            activity_patterns: self.activity_patterns.clone(),
+        }
+    }
+
+    /// Compute hotspots across all threads
+    ///
+    /// By default, hot_lines and hot_addresses are NOT included to keep output compact.
+    /// Pass include_lines=true or include_addresses=true to include them.
+    /// Only samples in `range` are counted.
+    pub fn compute_hotspots(
+        &self,
+        limit: usize,
+        thread_filter: Option<&str>,
+        include_lines: bool,
+        include_addresses: bool,
+        range: TimeRange,
+    ) -> Vec<HotspotEntry> {
+        let (func_stats, total_weight) =
+            self.aggregate_functions(|thread| thread.matches_filter(thread_filter), range);
+        self.hotspot_entries(
+            func_stats,
+            total_weight,
+            limit,
+            include_lines,
+            include_addresses,
+        )
+    }
+
+    /// Compute the top `limit` hotspots of every thread, along with each
+    /// thread's sample count and its share of its process's samples. The
+    /// threads which `merge` matches are listed as one thread, named by the
+    /// pattern.
+    ///
+    /// Hotspot percentages are relative to the thread's own samples. Threads
+    /// are sorted by sample count, descending. Only samples in `range` are counted.
+    pub fn compute_thread_hotspots(
+        &self,
+        limit: usize,
+        merge: Option<&ThreadMerge>,
+        range: TimeRange,
+    ) -> ThreadsResponse {
+        let is_merged = |thread: &ThreadData| merge.is_some_and(|merge| merge.matches(thread));
+        let mut threads: Vec<ThreadHotspots> = self
+            .threads
+            .iter()
+            .filter(|thread| !is_merged(thread))
+            .map(|thread| {
+                let (func_stats, total_weight) =
+                    self.aggregate_functions(|t| std::ptr::eq(t, &**thread), range);
+                ThreadHotspots {
+                    name: thread.name.clone(),
+                    pid: thread.pid.clone(),
+                    tid: thread.tid.clone(),
+                    is_main: thread.is_main_thread,
+                    samples: total_weight,
+                    process_percent: 0.0,
+                    activity: self.activity_split(|t| std::ptr::eq(t, &**thread), range),
+                    hotspots: self.hotspot_entries(func_stats, total_weight, limit, false, false),
+                    merged_threads: Vec::new(),
+                }
+            })
+            .collect();
+        let merged: Vec<&Arc<ThreadData>> = self
+            .threads
+            .iter()
+            .filter(|thread| is_merged(thread))
+            .collect();
+        if let (Some(merge), Some(first)) = (merge, merged.first()) {
+            let (func_stats, total_weight) = self.aggregate_functions(is_merged, range);
+            threads.push(ThreadHotspots {
+                name: merge.to_string(),
+                pid: first.pid.clone(),
+                tid: "merged".to_string(),
+                is_main: merged.iter().any(|t| t.is_main_thread),
+                samples: total_weight,
+                process_percent: 0.0,
+                activity: self.activity_split(is_merged, range),
+                hotspots: self.hotspot_entries(func_stats, total_weight, limit, false, false),
+                merged_threads: self.merged_threads(merge, range).threads,
+            });
+        }
+
+        let mut process_samples: HashMap<String, i64> = HashMap::new();
+        for thread in &threads {
+            *process_samples.entry(thread.pid.clone()).or_default() += thread.samples;
+        }
+        for thread in &mut threads {
+            let process_total = process_samples[&thread.pid];
+            if process_total > 0 {
+                thread.process_percent = 100.0 * thread.samples as f64 / process_total as f64;
+            }
+        }
+        threads.sort_by(|a, b| {
+            b.samples
+                .cmp(&a.samples)
+                .then_with(|| a.name.cmp(&b.name))
+                .then_with(|| a.tid.cmp(&b.tid))
+        });
+
+        ThreadsResponse {
+            total_samples: threads.iter().map(|t| t.samples).sum(),
+            threads,
+        }
+    }
+
+    /// Aggregate per-function self/total samples over the threads for which
+    /// `include_thread` returns true. Also returns the total sample weight.
+    fn aggregate_functions(
+        &self,
+        include_thread: impl Fn(&ThreadData) -> bool,
+        range: TimeRange,
+    ) -> (HashMap<String, FuncStats>, i64) {
+        let mut func_stats: HashMap<String, FuncStats> = HashMap::new();
+        let mut total_weight: i64 = 0;
+
+        // Aggregate samples across threads
+        for (thread_idx, thread) in self.threads.iter().enumerate() {
+            if !include_thread(thread) {
+                continue;
+            }
+
+            for (stack_idx_opt, weight) in thread.samples_in(range) {
+                total_weight += weight;
+
+                if let Some(stack_idx) = stack_idx_opt {
+                    // Walk stack with frame info for per-line/address tracking
+                    let stack_with_frames = thread.walk_stack_with_frames(stack_idx);
+
+                    // Self time: only for the leaf function (first in the list)
+                    if let Some(&(leaf_func_idx, leaf_frame_idx)) = stack_with_frames.first() {
+                        let name = thread.get_func_name(leaf_func_idx, &self.global_strings);
+                        let stats = func_stats.entry(name).or_default();
+                        stats.self_samples += weight;
+
+                        // Store func/thread indices for later info lookup
+                        if stats.func_idx.is_none() {
+                            stats.func_idx = Some(leaf_func_idx);
+                            stats.thread_idx = Some(thread_idx);
+                        }
+
+                        // Track per-line samples
+                        if let Some(line) = thread.get_frame_line(leaf_frame_idx) {
+                            *stats.line_samples.entry(line).or_insert(0) += weight;
+                        }
+
+                        // Track per-address samples
+                        if let Some(addr) = thread.get_frame_address(leaf_frame_idx) {
+                            *stats.address_samples.entry(addr).or_insert(0) += weight;
+                        }
+
+                        // Track per-category samples
+                        if let Some(category) = thread.stack_category(stack_idx) {
+                            *stats.category_samples.entry(category).or_insert(0) += weight;
+                        }
+                    }
+
+                    // Total time: for each unique function in stack
+                    let mut seen = std::collections::HashSet::new();
+                    for (func_idx, _frame_idx) in &stack_with_frames {
+                        let name = thread.get_func_name(*func_idx, &self.global_strings);
+                        if seen.insert(name.clone()) {
+                            let stats = func_stats.entry(name).or_default();
+                            stats.total_samples += weight;
+
+                            // Store func/thread indices
+                            if stats.func_idx.is_none() {
+                                stats.func_idx = Some(*func_idx);
+                                stats.thread_idx = Some(thread_idx);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        (func_stats, total_weight)
+    }
+
+    /// Turn aggregated function stats into the top `limit` hotspot entries by self time.
+    fn hotspot_entries(
+        &self,
+        func_stats: HashMap<String, FuncStats>,
+        total_weight: i64,
+        limit: usize,
+        include_lines: bool,
+        include_addresses: bool,
+    ) -> Vec<HotspotEntry> {
+        // Convert to sorted list
+        let mut hotspots: Vec<_> = func_stats.into_iter().collect();
+
+        // Sort by self samples descending, then by name
+        hotspots.sort_by(|a, b| {
+            b.1.self_samples
+                .cmp(&a.1.self_samples)
+                .then_with(|| a.0.cmp(&b.0))
+        });
+
+        // Take top N and convert to HotspotEntry
+        hotspots
+            .into_iter()
+            .take(limit)
+            .enumerate()
+            .map(|(i, (name, stats))| {
+                // Build FunctionInfo with extended fields
+                let (func_info, debug_info) = if let (Some(func_idx), Some(thread_idx)) =
+                    (stats.func_idx, stats.thread_idx)
+                {
+                    let thread = &self.threads[thread_idx];
+
+                    // Get library index via resource table
+                    let lib_index = thread.get_func_lib_index(func_idx);
+                    let library = lib_index.and_then(|idx| self.libs.get(idx).map(|l| l.name.clone()));
+
+                    // Get debug info from library
+                    let debug = lib_index.and_then(|idx| {
+                        self.libs.get(idx).map(|l| DebugInfo {
+                            debug_name: l.debug_name.clone(),
+                            debug_id: l.debug_id.clone(),
+                        })
+                    });
+
+                    // Try to get address/size from native symbols
+                    // We need to find a frame that maps to this function and has native symbol info
+                    let (address, size) = self.find_func_native_symbol_info(thread_idx, func_idx);
+
+                    (
+                        FunctionInfo {
+                            name: name.clone(),
+                            library,
+                            file_path: thread.get_func_file(func_idx, &self.global_strings),
+                            line_number: thread.get_func_line(func_idx),
+                            address,
+                            size,
+                        },
+                        debug,
+                    )
+                } else {
+                    (
+                        FunctionInfo {
+                            name: name.clone(),
+                            library: None,
+                            file_path: None,
+                            line_number: None,
+                            address: None,
+                            size: None,
+                        },
+                        None,
+                    )
+                };
+
+                // Build hot_lines from line_samples (only if requested)
+                let hot_lines = if !include_lines || stats.line_samples.is_empty() {
+                    None
+                } else {
+                    let mut lines: Vec<_> = stats
+                        .line_samples
+                        .into_iter()
+                        .map(|(line, samples)| HotLine {
+                            line,
+                            samples,
+                            percent: if stats.self_samples > 0 {
+                                100.0 * samples as f64 / stats.self_samples as f64
+                            } else {
+                                0.0
+                            },
+                        })
+                        .collect();
+                    lines.sort_by(|a, b| b.samples.cmp(&a.samples).then(a.line.cmp(&b.line)));
+                    Some(lines)
+                };
+
+                // Build hot_addresses from address_samples
+                // For address offset, we need the function base address
+                let func_base_addr = func_info
+                    .address
+                    .as_ref()
+                    .and_then(|s| u64::from_str_radix(s.trim_start_matches("0x"), 16).ok())
+                    .unwrap_or(0);
+
+                // Build hot_addresses from address_samples (only if requested)
+                let hot_addresses = if !include_addresses || stats.address_samples.is_empty() {
+                    None
+                } else {
+                    let mut addrs: Vec<_> = stats
+                        .address_samples
+                        .into_iter()
+                        .map(|(addr, samples)| HotAddress {
+                            offset: addr.saturating_sub(func_base_addr),
+                            address: format!("0x{:x}", addr),
+                            source_line: None, // Not tracked in compute_hotspots
+                            samples,
+                            percent: if stats.self_samples > 0 {
+                                100.0 * samples as f64 / stats.self_samples as f64
+                            } else {
+                                0.0
+                            },
+                        })
+                        .collect();
+                    addrs.sort_by(|a, b| b.samples.cmp(&a.samples).then(a.offset.cmp(&b.offset)));
+                    Some(addrs)
+                };
+
+                HotspotEntry {
+                    rank: i + 1,
+                    function: func_info,
+                    debug_info,
+                    self_samples: stats.self_samples,
+                    total_samples: stats.total_samples,
+                    self_percent: if total_weight > 0 {
+                        100.0 * stats.self_samples as f64 / total_weight as f64
+                    } else {
+                        0.0
+                    },
+                    total_percent: if total_weight > 0 {
+                        100.0 * stats.total_samples as f64 / total_weight as f64
+                    } else {
+                        0.0
+                    },
+                    category: self.dominant_category(&stats.category_samples),
+                    context: None,
+                    hot_lines,
+                    hot_addresses,
+                }
+            })
+            .collect()
+    }
+
+    /// Find native symbol info (address, size) for a function
+    fn find_func_native_symbol_info(
+        &self,
+        thread_idx: usize,
+        func_idx: usize,
+    ) -> (Option<String>, Option<u32>) {
+        let thread = &self.threads[thread_idx];
+
+        // Search frames for one that maps to this function and has native symbol
+        for (frame_idx, fid) in thread.frame_func.iter().enumerate() {
+            if fid == func_idx {
+                if let Some(ns_info) = thread.get_frame_native_symbol(frame_idx) {
+                    return (
+                        Some(format!("0x{:x}", ns_info.address)),
+                        ns_info.size,
+                    );
+                }
+            }
+        }
+
+        (None, None)
+    }
+
+    /// Find callers of a function, counting only samples in `range`
+    pub fn find_callers(
+        &self,
+        function_pattern: &str,
+        depth: usize,
+        limit: usize,
+        range: TimeRange,
+    ) -> CallersResponse {
+        // Build caller graph: callee -> caller -> (count, func_idx, thread_idx)
+        #[derive(Default, Clone)]
+        struct FuncData {
+            count: i64,
+            func_idx: Option<usize>,
+            thread_idx: Option<usize>,
+        }
+        let mut caller_data: HashMap<String, HashMap<String, FuncData>> = HashMap::new();
+
+        for (thread_idx, thread) in self.threads.iter().enumerate() {
+            for (stack_idx_opt, weight) in thread.samples_in(range) {
+                if let Some(stack_idx) = stack_idx_opt {
+                    let funcs = thread.walk_stack(stack_idx);
+                    let func_info: Vec<(String, usize)> = funcs
+                        .iter()
+                        .map(|&idx| (thread.get_func_name(idx, &self.global_strings), idx))
+                        .collect();
+
+                    // For each pair (callee, caller) in the stack
+                    for i in 0..func_info.len().saturating_sub(1) {
+                        let (callee_name, _) = &func_info[i];
+                        let (caller_name, caller_idx) = &func_info[i + 1];
+                        let data = caller_data
+                            .entry(callee_name.clone())
+                            .or_default()
+                            .entry(caller_name.clone())
+                            .or_default();
+                        data.count += weight;
+                        if data.func_idx.is_none() {
+                            data.func_idx = Some(*caller_idx);
+                            data.thread_idx = Some(thread_idx);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Find matching function
+        let target = self.find_matching_function(function_pattern);
+
+        // Build caller tree recursively
+        fn build_caller_tree(
+            analyzer: &ProfileAnalyzer,
+            caller_data: &HashMap<String, HashMap<String, FuncData>>,
+            target: &str,
+            depth: usize,
+            limit: usize,
+            visited: &mut std::collections::HashSet<String>,
+        ) -> Vec<CallerEntry> {
+            if depth == 0 || visited.contains(target) {
+                return vec![];
+            }
+            visited.insert(target.to_string());
+
+            let mut callers: Vec<_> = caller_data
+                .get(target)
+                .map(|callers| {
+                    callers
+                        .iter()
+                        .map(|(caller_name, data)| {
+                            let sub_callers =
+                                build_caller_tree(analyzer, caller_data, caller_name, depth - 1, limit, visited);
+
+                            // Get extended function info
+                            let (library, file_path, line_number) =
+                                if let (Some(func_idx), Some(thread_idx)) = (data.func_idx, data.thread_idx) {
+                                    let thread = &analyzer.threads[thread_idx];
+                                    let lib_idx = thread.get_func_lib_index(func_idx);
+                                    (
+                                        lib_idx.and_then(|idx| analyzer.libs.get(idx).map(|l| l.name.clone())),
+                                        thread.get_func_file(func_idx, &analyzer.global_strings),
+                                        thread.get_func_line(func_idx),
+                                    )
+                                } else {
+                                    (None, None, None)
+                                };
+
+                            CallerEntry {
+                                name: caller_name.clone(),
+                                library,
+                                file_path,
+                                line_number,
+                                call_count: data.count,
+                                percent: 0.0, // Computed later
+                                callers: sub_callers,
+                            }
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            // Compute percentages
+            let total: i64 = callers.iter().map(|c| c.call_count).sum();
+            for caller in &mut callers {
+                caller.percent = if total > 0 {
+                    100.0 * caller.call_count as f64 / total as f64
+                } else {
+                    0.0
+                };
+            }
+
+            callers.sort_by(|a, b| {
+                b.call_count
+                    .cmp(&a.call_count)
+                    .then_with(|| a.name.cmp(&b.name))
+            });
+            callers.truncate(limit);
+            visited.remove(target);
+            callers
+        }
+
+        let callers = build_caller_tree(self, &caller_data, &target, depth, limit, &mut Default::default());
+
+        CallersResponse {
+            function: target,
+            callers,
+        }
+    }
+
+    /// Find callees of a function, counting only samples in `range`
+    pub fn find_callees(
+        &self,
+        function_pattern: &str,
+        depth: usize,
+        limit: usize,
+        range: TimeRange,
+    ) -> CalleesResponse {
+        // Build callee graph: caller -> callee -> (count, func_idx, thread_idx)
+        #[derive(Default, Clone)]
+        struct FuncData {
+            count: i64,
+            func_idx: Option<usize>,
+            thread_idx: Option<usize>,
+        }
+        let mut callee_data: HashMap<String, HashMap<String, FuncData>> = HashMap::new();
+
+        for (thread_idx, thread) in self.threads.iter().enumerate() {
+            for (stack_idx_opt, weight) in thread.samples_in(range) {
+                if let Some(stack_idx) = stack_idx_opt {
+                    let funcs = thread.walk_stack(stack_idx);
+                    let func_info: Vec<(String, usize)> = funcs
+                        .iter()
+                        .map(|&idx| (thread.get_func_name(idx, &self.global_strings), idx))
+                        .collect();
+
+                    // For each pair (callee, caller) in the stack
+                    // In our walk, index 0 is leaf, index n-1 is root
+                    // So caller is at higher index, callee at lower
+                    for i in 0..func_info.len().saturating_sub(1) {
+                        let (callee_name, callee_idx) = &func_info[i];
+                        let (caller_name, _) = &func_info[i + 1];
+                        let data = callee_data
+                            .entry(caller_name.clone())
+                            .or_default()
+                            .entry(callee_name.clone())
+                            .or_default();
+                        data.count += weight;
+                        if data.func_idx.is_none() {
+                            data.func_idx = Some(*callee_idx);
+                            data.thread_idx = Some(thread_idx);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Find matching function
+        let target = self.find_matching_function(function_pattern);
+
+        // Build callee tree recursively
+        fn build_callee_tree(
+            analyzer: &ProfileAnalyzer,
+            callee_data: &HashMap<String, HashMap<String, FuncData>>,
+            target: &str,
+            depth: usize,
+            limit: usize,
+            visited: &mut std::collections::HashSet<String>,
+        ) -> Vec<CalleeEntry> {
+            if depth == 0 || visited.contains(target) {
+                return vec![];
+            }
+            visited.insert(target.to_string());
+
+            let mut callees: Vec<_> = callee_data
+                .get(target)
+                .map(|callees| {
+                    callees
+                        .iter()
+                        .map(|(callee_name, data)| {
+                            let sub_callees =
+                                build_callee_tree(analyzer, callee_data, callee_name, depth - 1, limit, visited);
+
+                            // Get extended function info
+                            let (library, file_path, line_number) =
+                                if let (Some(func_idx), Some(thread_idx)) = (data.func_idx, data.thread_idx) {
+                                    let thread = &analyzer.threads[thread_idx];
+                                    let lib_idx = thread.get_func_lib_index(func_idx);
+                                    (
+                                        lib_idx.and_then(|idx| analyzer.libs.get(idx).map(|l| l.name.clone())),
+                                        thread.get_func_file(func_idx, &analyzer.global_strings),
+                                        thread.get_func_line(func_idx),
+                                    )
+                                } else {
+                                    (None, None, None)
+                                };
+
+                            CalleeEntry {
+                                name: callee_name.clone(),
+                                library,
+                                file_path,
+                                line_number,
+                                call_count: data.count,
+                                percent: 0.0,
+                                callees: sub_callees,
+                            }
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            // Compute percentages
+            let total: i64 = callees.iter().map(|c| c.call_count).sum();
+            for callee in &mut callees {
+                callee.percent = if total > 0 {
+                    100.0 * callee.call_count as f64 / total as f64
+                } else {
+                    0.0
+                };
+            }
+
+            callees.sort_by(|a, b| {
+                b.call_count
+                    .cmp(&a.call_count)
+                    .then_with(|| a.name.cmp(&b.name))
+            });
+            callees.truncate(limit);
+            visited.remove(target);
+            callees
+        }
+
+        let callees = build_callee_tree(self, &callee_data, &target, depth, limit, &mut Default::default());
+
+        CalleesResponse {
+            function: target,
+            callees,
+        }
+    }
+
+    /// Indexes of the threads whose name contains `thread_filter` and which
+    /// belong to `process` (see [`ProfileAnalyzer::for_process`]), in the
+    /// profile's thread order, which is also what the profiler front-end's
+    /// `thread` URL parameter refers to.
+    pub fn thread_indexes(&self, thread_filter: &str, process: Option<&str>) -> Vec<usize> {
+        self.threads
+            .iter()
+            .enumerate()
+            .filter(|(_, thread)| thread.name.contains(thread_filter))
+            .filter(|(_, thread)| process.is_none_or(|process| thread.in_process(process)))
+            .map(|(i, _)| self.profile_index(i))
+            .collect()
+    }
+
+    /// Get profile summary, with sample counts restricted to `range`
+    pub fn get_summary(&self, range: TimeRange) -> ProfileSummary {
+        let threads: Vec<ThreadSummary> = self
+            .threads
+            .iter()
+            .map(|t| ThreadSummary {
+                name: t.name.clone(),
+                pid: t.pid.clone(),
+                tid: t.tid.clone(),
+                is_main: t.is_main_thread,
+                sample_count: if range.is_unbounded() {
+                    t.sample_count()
+                } else {
+                    t.samples_in(range).count()
+                },
+                activity: self.activity_split(|thread| std::ptr::eq(thread, &**t), range),
+            })
+            .collect();
+
+        let total_samples: i64 = self
+            .threads
+            .iter()
+            .flat_map(|t| t.samples_in(range))
+            .map(|(_, w)| w)
+            .sum();
+
+        let sample_times = self.threads.iter().flat_map(|t| t.sample_time.iter());
+        let (first_sample_ms, last_sample_ms) =
+            sample_times.fold((None, None), |(first, last): (Option<f64>, Option<f64>), t| {
+                (
+                    Some(first.map_or(t, |first| first.min(t))),
+                    Some(last.map_or(t, |last| last.max(t))),
+                )
+            });
+
+        ProfileSummary {
+            product_name: self.product_name.clone(),
+            total_samples,
+            sampling_interval_ms: self.sampling_interval_ms,
+            thread_count: threads.len(),
+            threads,
+            is_symbolicated: !self.is_likely_unsymbolicated(),
+            first_sample_ms,
+            last_sample_ms,
+            time_range: (!range.is_unbounded()).then_some(range),
+            overhead: self.recording_overhead.clone(),
+            overhead_summary: self.recording_overhead.as_ref().map(|o| o.description()),
+            parse_warnings: self.parse_warnings.clone(),
+        }
+    }
+
+    /// Get the markers whose name contains `name_filter`, on threads whose name
+    /// contains `thread_filter`, which overlap `range`.
+    ///
+    /// Returns per-name statistics over all of them (count and total / p50 /
+    /// p95 / max duration of the interval markers), and the first `limit`
+    /// markers by start time with their payloads.
+    pub fn get_markers(
+        &self,
+        name_filter: Option<&str>,
+        thread_filter: Option<&str>,
+        limit: usize,
+        range: TimeRange,
+    ) -> MarkersResponse {
+        let mut markers = Vec::new();
+        for thread in &self.threads {
+            if !thread.matches_filter(thread_filter) {
+                continue;
+            }
+            for marker in &thread.markers {
+                let name = thread.get_string(marker.name_idx, &self.global_strings);
+                if name_filter.is_some_and(|filter| !name.contains(filter)) {
+                    continue;
+                }
+                let Some(time) = marker.start_ms.or(marker.end_ms) else {
+                    continue;
+                };
+                if !range.overlaps(time, marker.end_ms.unwrap_or(time)) {
+                    continue;
+                }
+                let duration_ms = marker
+                    .start_ms
+                    .zip(marker.end_ms)
+                    .map(|(start, end)| end - start);
+                markers.push(MarkerEntry {
+                    name,
+                    thread: thread.name.clone(),
+                    start_ms: marker.start_ms,
+                    end_ms: marker.end_ms,
+                    duration_ms,
+                    data: marker.data.clone(),
+                });
+            }
+        }
+
+        let mut durations_by_name: HashMap<&str, (usize, Vec<f64>)> = HashMap::new();
+        for marker in &markers {
+            let (count, durations) = durations_by_name.entry(&marker.name).or_default();
+            *count += 1;
+            durations.extend(marker.duration_ms);
+        }
+        let mut stats: Vec<MarkerStats> = durations_by_name
+            .into_iter()
+            .map(|(name, (count, mut durations))| {
+                durations.sort_by(f64::total_cmp);
+                let percentile = |p: f64| {
+                    let rank = (p * durations.len() as f64).ceil() as usize;
+                    durations.get(rank.saturating_sub(1)).copied()
+                };
+                MarkerStats {
+                    name: name.to_string(),
+                    count,
+                    interval_count: durations.len(),
+                    total_duration_ms: durations.iter().sum(),
+                    p50_duration_ms: percentile(0.5),
+                    p95_duration_ms: percentile(0.95),
+                    max_duration_ms: durations.last().copied(),
+                }
+            })
+            .collect();
+        stats.sort_by(|a, b| {
+            b.total_duration_ms
+                .total_cmp(&a.total_duration_ms)
+                .then(b.count.cmp(&a.count))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+
+        let total_count = markers.len();
+        markers.sort_by(|a, b| {
+            let start = |m: &MarkerEntry| m.start_ms.or(m.end_ms).unwrap_or(0.0);
+            start(a).total_cmp(&start(b))
+        });
+        markers.truncate(limit);
+
+        MarkersResponse {
+            total_count,
+            stats,
+            markers,
+        }
+    }
+
+    /// Build a flame graph call tree from all samples.
+    ///
+    /// With `inverted`, the tree is rooted at the leaf functions and each node's
+    /// children are its callers. Nodes below `min_percent` of the total samples are
+    /// pruned. Children are sorted by value, largest first.
+    pub fn build_flamegraph(
+        &self,
+        thread_filter: Option<&str>,
+        inverted: bool,
+        min_percent: f64,
+    ) -> FlameGraphResponse {
+        struct Node {
+            name: String,
+            value: i64,
+            children: HashMap<String, usize>,
+        }
+
+        let mut nodes = vec![Node {
+            name: "root".to_string(),
+            value: 0,
+            children: HashMap::new(),
+        }];
+
+        for thread in &self.threads {
+            if let Some(filter) = thread_filter {
+                if !thread.name.contains(filter) {
+                    continue;
+                }
+            }
+
+            for (stack_idx_opt, weight) in thread.samples() {
+                nodes[0].value += weight;
+                let Some(stack_idx) = stack_idx_opt else {
+                    continue;
+                };
+
+                let mut funcs = thread.walk_stack(stack_idx);
+                if !inverted {
+                    funcs.reverse();
+                }
+
+                let mut current = 0;
+                for func_idx in funcs {
+                    let name = thread.get_func_name(func_idx, &self.global_strings);
+                    current = match nodes[current].children.get(&name) {
+                        Some(&child) => child,
+                        None => {
+                            let child = nodes.len();
+                            nodes[current].children.insert(name.clone(), child);
+                            nodes.push(Node {
+                                name,
+                                value: 0,
+                                children: HashMap::new(),
+                            });
+                            child
+                        }
+                    };
+                    nodes[current].value += weight;
+                }
+            }
+        }
+
+        let min_value = nodes[0].value as f64 * min_percent / 100.0;
+
+        fn build(nodes: &[Node], idx: usize, min_value: f64) -> FlameGraphNode {
+            let node = &nodes[idx];
+            let mut children: Vec<FlameGraphNode> = node
+                .children
+                .values()
+                .filter(|&&child| nodes[child].value > 0 && nodes[child].value as f64 >= min_value)
+                .map(|&child| build(nodes, child, min_value))
+                .collect();
+            children.sort_by(|a, b| b.value.cmp(&a.value).then_with(|| a.name.cmp(&b.name)));
+            FlameGraphNode {
+                name: node.name.clone(),
+                value: node.value,
+                children,
+            }
+        }
+
+        FlameGraphResponse {
+            inverted,
+            thread: thread_filter.map(|t| t.to_string()),
+            root: build(&nodes, 0, min_value),
+        }
+    }
+
+    /// Per-function (self, total) samples by function name, and the total sample
+    /// weight. Functions appearing several times in a stack count once for total.
+    fn function_sample_counts(&self) -> (HashMap<String, (i64, i64)>, i64) {
+        let mut counts: HashMap<String, (i64, i64)> = HashMap::new();
+        let mut total_weight: i64 = 0;
+
+        for thread in &self.threads {
+            for (stack_idx_opt, weight) in thread.samples() {
+                total_weight += weight;
+                let Some(stack_idx) = stack_idx_opt else {
+                    continue;
+                };
+                let funcs = thread.walk_stack(stack_idx);
+                let mut seen = std::collections::HashSet::new();
+                for (i, &func_idx) in funcs.iter().enumerate() {
+                    let name = thread.get_func_name(func_idx, &self.global_strings);
+                    if i == 0 {
+                        counts.entry(name.clone()).or_default().0 += weight;
+                    }
+                    if seen.insert(name.clone()) {
+                        counts.entry(name).or_default().1 += weight;
+                    }
+                }
+            }
+        }
+
+        (counts, total_weight)
+    }
+
+    /// Compare this profile against a baseline profile, per function.
+    ///
+    /// Returns at most `limit` functions, sorted by absolute change in self-time.
+    pub fn compute_diff(&self, baseline: &ProfileAnalyzer, limit: usize) -> DiffResponse {
+        let (current, total_samples) = self.function_sample_counts();
+        let (before, baseline_total_samples) = baseline.function_sample_counts();
+
+        let percent = |samples: i64, total: i64| {
+            if total > 0 {
+                100.0 * samples as f64 / total as f64
+            } else {
+                0.0
+            }
+        };
+
+        // Link the functions which only one profile has to each other, so
+        // that a renamed function isn't reported as removed and added.
+        let new_names: HashSet<&str> = current
+            .keys()
+            .filter(|name| !before.contains_key(*name))
+            .map(|name| name.as_str())
+            .collect();
+        let removed_names: HashSet<&str> = before
+            .keys()
+            .filter(|name| !current.contains_key(*name))
+            .map(|name| name.as_str())
+            .collect();
+        let renamed = function_identity::link_functions(self, &new_names, baseline, &removed_names);
+        let baseline_names: HashMap<&str, &str> = renamed
+            .iter()
+            .map(|link| (link.function.as_str(), link.baseline_function.as_str()))
+            .collect();
+        let renamed_in_baseline: HashSet<&str> = baseline_names.values().copied().collect();
+
+        let mut names: Vec<&String> = current
+            .keys()
+            .chain(before.keys())
+            .filter(|name| !renamed_in_baseline.contains(name.as_str()))
+            .collect();
+        names.sort();
+        names.dedup();
+
+        let mut functions: Vec<FunctionDiff> = names
+            .into_iter()
+            .map(|name| {
+                let baseline_function = baseline_names.get(name.as_str()).copied();
+                let baseline_name = baseline_function.unwrap_or(name);
+                let (self_samples, total) = current.get(name).copied().unwrap_or_default();
+                let (baseline_self_samples, baseline_total) =
+                    before.get(baseline_name).copied().unwrap_or_default();
+                let status = match (current.contains_key(name), before.contains_key(name)) {
+                    _ if baseline_function.is_some() => DiffStatus::Renamed,
+                    (true, false) => DiffStatus::New,
+                    (false, true) => DiffStatus::Removed,
+                    _ => DiffStatus::Changed,
+                };
+
+                let self_percent = percent(self_samples, total_samples);
+                let baseline_self_percent = percent(baseline_self_samples, baseline_total_samples);
+                let total_percent = percent(total, total_samples);
+                let baseline_total_percent = percent(baseline_total, baseline_total_samples);
+                let self_change_percent = (baseline_self_percent > 0.0).then(|| {
+                    100.0 * (self_percent - baseline_self_percent) / baseline_self_percent
+                });
+
+                FunctionDiff {
+                    function: name.clone(),
+                    status,
+                    baseline_function: baseline_function.map(|name| name.to_string()),
+                    baseline_self_samples,
+                    self_samples,
+                    baseline_self_percent,
+                    self_percent,
+                    self_percent_delta: self_percent - baseline_self_percent,
+                    baseline_total_percent,
+                    total_percent,
+                    total_percent_delta: total_percent - baseline_total_percent,
+                    self_change_percent,
+                }
+            })
+            .filter(|f| f.self_percent_delta != 0.0 || f.total_percent_delta != 0.0)
+            .collect();
+
+        let top_hotspots = |counts: &HashMap<String, (i64, i64)>| -> Vec<String> {
+            let mut by_self: Vec<(&String, i64)> = counts
+                .iter()
+                .filter(|(_, &(self_samples, _))| self_samples > 0)
+                .map(|(name, &(self_samples, _))| (name, self_samples))
+                .collect();
+            by_self.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+            by_self.into_iter().take(limit).map(|(name, _)| name.clone()).collect()
+        };
+        let new_hotspots = top_hotspots(&current)
+            .into_iter()
+            .filter(|name| {
+                !before.contains_key(name) && !baseline_names.contains_key(name.as_str())
+            })
+            .collect();
+        let removed_hotspots = top_hotspots(&before)
+            .into_iter()
+            .filter(|name| {
+                !current.contains_key(name) && !renamed_in_baseline.contains(name.as_str())
+            })
+            .collect();
+
+        functions.sort_by(|a, b| {
+            b.self_percent_delta
+                .abs()
+                .total_cmp(&a.self_percent_delta.abs())
+                .then_with(|| b.total_percent_delta.abs().total_cmp(&a.total_percent_delta.abs()))
+                .then_with(|| a.function.cmp(&b.function))
+        });
+        functions.truncate(limit);
+
+        DiffResponse {
+            baseline_total_samples,
+            total_samples,
+            functions,
+            new_hotspots,
+            removed_hotspots,
+            renamed,
+        }
+    }
+
+    fn regression_tally(&self) -> RegressionTally {
+        let mut tally = RegressionTally::default();
+
+        for (thread_idx, thread) in self.threads.iter().enumerate() {
+            let mut stack_weights: HashMap<usize, i64> = HashMap::new();
+            for (stack_idx_opt, weight) in thread.samples() {
+                tally.total_weight += weight;
+                if let Some(stack_idx) = stack_idx_opt {
+                    *stack_weights.entry(stack_idx).or_default() += weight;
+                }
+            }
+
+            for (stack_idx, weight) in stack_weights {
+                // (name, stack node) from leaf to root
+                let mut frames: Vec<(String, usize)> = Vec::new();
+                let mut current = Some(stack_idx);
+                while let Some(idx) = current {
+                    let func_idx = thread.get_frame_func(thread.get_stack_frame(idx));
+                    let name = thread.get_func_name(func_idx, &self.global_strings);
+                    if !tally.libraries.contains_key(&name) {
+                        if let Some(lib) = thread
+                            .get_func_lib_index(func_idx)
+                            .and_then(|lib_idx| self.libs.get(lib_idx))
+                        {
+                            tally.libraries.insert(name.clone(), lib.name.clone());
+                        }
+                    }
+                    frames.push((name, idx));
+                    current = thread.get_stack_prefix(idx);
+                }
+
+                let Some((leaf, _)) = frames.first() else {
+                    continue;
+                };
+                let leaf = leaf.clone();
+                *tally.self_samples.entry(leaf.clone()).or_default() += weight;
+
+                let frame_count = frames.len();
+                let mut seen = std::collections::HashSet::new();
+                for (i, (name, idx)) in frames.into_iter().enumerate() {
+                    let depth = tally.depth.entry(name.clone()).or_default();
+                    *depth = (*depth).max(frame_count - 1 - i);
+                    if !seen.insert(name.clone()) {
+                        continue;
+                    }
+                    *tally.total_samples.entry(name.clone()).or_default() += weight;
+                    *tally
+                        .nodes
+                        .entry(name.clone())
+                        .or_default()
+                        .entry((thread_idx, idx))
+                        .or_default() += weight;
+                    *tally
+                        .leaves_under
+                        .entry(name)
+                        .or_default()
+                        .entry(leaf.clone())
+                        .or_default() += weight;
+                }
+            }
+        }
+
+        tally
+    }
+
+    /// Explain a regression against a baseline profile.
+    ///
+    /// The self-time increases of the leaf functions are clustered by the
+    /// functions they have in common on their stacks: each cluster is the most
+    /// specific function which explains nearly as much of the remaining
+    /// regression as any other, together with the leaf functions driving it.
+    /// Returns at most `limit` clusters and modules, plus a short narrative.
+    pub fn explain_diff(&self, baseline: &ProfileAnalyzer, limit: usize) -> DiffExplainResponse {
+        /// Clusters explaining less than this share of the regression are left out.
+        const MIN_CLUSTER_SHARE: f64 = 0.05;
+        /// Functions explaining at least this fraction of the best candidate's
+        /// regression compete for a cluster; the most specific one wins.
+        const SPECIFICITY_TOLERANCE: f64 = 0.8;
+        const MAX_DRIVERS: usize = 3;
+
+        let current = self.regression_tally();
+        let before = baseline.regression_tally();
+
+        let percent = |samples: i64, total: i64| {
+            if total > 0 {
+                100.0 * samples as f64 / total as f64
+            } else {
+                0.0
+            }
+        };
+        let current_percent = |samples: i64| percent(samples, current.total_weight);
+        let baseline_percent = |samples: i64| percent(samples, before.total_weight);
+        let library_of = |name: &str| {
+            current
+                .libraries
+                .get(name)
+                .or_else(|| before.libraries.get(name))
+                .cloned()
+        };
+
+        // Self-time change of every leaf function, in percentage points. The
+        // deltas are summed in name order, so that the sums don't depend on
+        // hash order in their last bits.
+        let mut leaf_deltas: BTreeMap<&str, f64> = BTreeMap::new();
+        for (name, &samples) in &current.self_samples {
+            *leaf_deltas.entry(name).or_default() += current_percent(samples);
+        }
+        for (name, &samples) in &before.self_samples {
+            *leaf_deltas.entry(name).or_default() -= baseline_percent(samples);
+        }
+        let regression_percent: f64 = leaf_deltas.values().filter(|d| **d > 0.0).sum();
+        let improvement_percent: f64 = -leaf_deltas.values().filter(|d| **d < 0.0).sum::<f64>();
+
+        // Per candidate function, the growth of each leaf function under it.
+        let mut candidates: Vec<(&str, Vec<(&str, f64)>)> = current
+            .leaves_under
+            .iter()
+            .map(|(ancestor, leaves)| {
+                let baseline_leaves = before.leaves_under.get(ancestor);
+                let mut growth: Vec<(&str, f64)> = leaves
+                    .iter()
+                    .filter_map(|(leaf, &samples)| {
+                        let baseline_samples = baseline_leaves
+                            .and_then(|l| l.get(leaf))
+                            .copied()
+                            .unwrap_or(0);
+                        let delta = current_percent(samples) - baseline_percent(baseline_samples);
+                        (delta > 0.0).then_some((leaf.as_str(), delta))
+                    })
+                    .collect();
+                growth.sort_by(|a, b| a.0.cmp(b.0));
+                (ancestor.as_str(), growth)
+            })
+            .collect();
+        candidates.retain(|(_, growth)| !growth.is_empty());
+        candidates.sort_by(|a, b| a.0.cmp(b.0));
+
+        // Greedily pick clusters, each explaining regression not yet explained
+        // by the previous ones.
+        let mut remaining: HashMap<&str, f64> = leaf_deltas
+            .iter()
+            .filter(|(_, d)| **d > 0.0)
+            .map(|(name, d)| (*name, *d))
+            .collect();
+        let mut clusters = Vec::new();
+        while clusters.len() < limit && regression_percent > 0.0 {
+            let explained = |growth: &[(&str, f64)], remaining: &HashMap<&str, f64>| -> f64 {
+                growth
+                    .iter()
+                    .map(|(leaf, delta)| delta.min(remaining.get(leaf).copied().unwrap_or(0.0)))
+                    .sum()
+            };
+            let coverage: Vec<f64> = candidates
+                .iter()
+                .map(|(_, growth)| explained(growth, &remaining))
+                .collect();
+            let best = coverage.iter().copied().fold(0.0, f64::max);
+            if best < regression_percent * MIN_CLUSTER_SHARE {
+                break;
+            }
+
+            let specificity = |name: &str| {
+                (
+                    current.total_samples.get(name).copied().unwrap_or(0),
+                    std::cmp::Reverse(current.depth.get(name).copied().unwrap_or(0)),
+                )
+            };
+            let (chosen, regression) = candidates
+                .iter()
+                .zip(&coverage)
+                .filter(|(_, c)| **c >= best * SPECIFICITY_TOLERANCE)
+                .min_by_key(|((name, _), _)| specificity(name))
+                .map(|((name, growth), &c)| ((*name, growth), c))
+                .expect("best candidate is within tolerance");
+            let (function, growth) = chosen;
+
+            let mut drivers: Vec<(&str, f64)> = growth
+                .iter()
+                .map(|(leaf, delta)| {
+                    let unexplained = remaining.get(leaf).copied().unwrap_or(0.0);
+                    (*leaf, delta.min(unexplained))
+                })
+                .filter(|(_, d)| *d > 0.0)
+                .collect();
+            drivers.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+            for (leaf, delta) in &drivers {
+                if let Some(r) = remaining.get_mut(leaf) {
+                    *r -= delta;
+                }
+            }
+            drivers.truncate(MAX_DRIVERS);
+
+            let call_path = current
+                .nodes
+                .get(function)
+                .and_then(|nodes| {
+                    nodes
+                        .iter()
+                        .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+                })
+                .map(|(&(thread_idx, stack_idx), _)| {
+                    let thread = &self.threads[thread_idx];
+                    let mut path: Vec<String> = thread
+                        .walk_stack(stack_idx)
+                        .into_iter()
+                        .map(|func_idx| thread.get_func_name(func_idx, &self.global_strings))
+                        .collect();
+                    path.reverse();
+                    path
+                })
+                .unwrap_or_default();
+
+            clusters.push(RegressionCluster {
+                rank: clusters.len() + 1,
+                function: function.to_string(),
+                library: library_of(function),
+                call_path,
+                regression_percent: regression,
+                share_percent: 100.0 * regression / regression_percent,
+                drivers: drivers
+                    .into_iter()
+                    .map(|(leaf, delta)| RegressionDriver {
+                        function: leaf.to_string(),
+                        library: library_of(leaf),
+                        status: if before.self_samples.contains_key(leaf) {
+                            DiffStatus::Changed
+                        } else {
+                            DiffStatus::New
+                        },
+                        regression_percent: delta,
+                    })
+                    .collect(),
+            });
+        }
+
+        let mut module_deltas: HashMap<String, f64> = HashMap::new();
+        for (name, delta) in leaf_deltas.iter().filter(|(_, d)| **d > 0.0) {
+            let library = library_of(name).unwrap_or_else(|| "(unknown)".to_string());
+            *module_deltas.entry(library).or_default() += delta;
+        }
+        let mut modules: Vec<ModuleRegression> = module_deltas
+            .into_iter()
+            .map(|(library, delta)| ModuleRegression {
+                library,
+                regression_percent: delta,
+                share_percent: 100.0 * delta / regression_percent,
+            })
+            .collect();
+        modules.sort_by(|a, b| {
+            b.regression_percent
+                .total_cmp(&a.regression_percent)
+                .then_with(|| a.library.cmp(&b.library))
+        });
+        modules.truncate(limit);
+
+        let mut narrative = vec![format!(
+            "Self time grew by {regression_percent:.1} percentage points in total and shrank by {improvement_percent:.1}."
+        )];
+        for cluster in &clusters {
+            let drivers: Vec<String> = cluster
+                .drivers
+                .iter()
+                .map(|driver| match driver.status {
+                    DiffStatus::New => format!("new calls to {}", driver.function),
+                    _ if driver.function == cluster.function => {
+                        format!("more time in {} itself", driver.function)
+                    }
+                    _ => format!("more time in {}", driver.function),
+                })
+                .collect();
+            let drivers = match drivers.split_last() {
+                Some((last, [])) => last.clone(),
+                Some((last, rest)) => format!("{} and {last}", rest.join(", ")),
+                None => String::new(),
+            };
+            narrative.push(format!(
+                "{:.0}% of the regression (+{:.1} points) is under {}, driven by {drivers}.",
+                cluster.share_percent, cluster.regression_percent, cluster.function
+            ));
+        }
+        if let Some(module) = modules.first() {
+            narrative.push(format!(
+                "{:.0}% of the regression is in functions of {}.",
+                module.share_percent, module.library
+            ));
+        }
+        if regression_percent == 0.0 {
+            narrative.push("No function has more self time than in the baseline.".to_string());
+        }
+
+        DiffExplainResponse {
+            baseline_total_samples: before.total_weight,
+            total_samples: current.total_weight,
+            regression_percent,
+            improvement_percent,
+            narrative,
+            clusters,
+            modules,
+        }
+    }
+
+    /// Check if the profile appears to be unsymbolicated.
+    /// Returns true if >80% of the top 20 function names look like hex addresses (0x...).
+    pub fn is_likely_unsymbolicated(&self) -> bool {
+        // Get top function names by sample count
+        let hotspots = self.compute_hotspots(20, None, false, false, TimeRange::default());
+        if hotspots.is_empty() {
+            return false;
+        }
+
+        // Count how many function names look like hex addresses
+        let hex_pattern_count = hotspots
+            .iter()
+            .filter(|h| Self::looks_like_hex_address(&h.function.name))
+            .count();
+
+        // If >80% are hex addresses, profile is likely unsymbolicated
+        let ratio = hex_pattern_count as f64 / hotspots.len() as f64;
+        ratio > 0.8
+    }
+
+    /// Check if a function name looks like a hex address (e.g., "0x1efcfc")
+    fn looks_like_hex_address(name: &str) -> bool {
+        if !name.starts_with("0x") {
+            return false;
+        }
+        let hex_part = &name[2..];
+        !hex_part.is_empty() && hex_part.chars().all(|c| c.is_ascii_hexdigit())
+    }
+
+    /// Get per-library symbolication coverage as seen in the profile.
+    ///
+    /// Libraries whose functions all have readable names are reported as resolved
+    /// from the profile itself. Libraries with hex-address function names start out
+    /// as pending; the server updates them once it has looked for their symbols.
+    pub fn library_symbolication_status(&self) -> Vec<LibrarySymbolicationStatus> {
+        let mut resolved: HashMap<usize, std::collections::HashSet<String>> = HashMap::new();
+        let mut unresolved: HashMap<usize, std::collections::HashSet<String>> = HashMap::new();
+        let mut self_samples: HashMap<usize, i64> = HashMap::new();
+
+        for thread in &self.threads {
+            for func_idx in 0..thread.func_name_idx.len() {
+                let Some(lib_idx) = thread.get_func_lib_index(func_idx) else {
+                    continue;
+                };
+                let name = thread.get_func_name(func_idx, &self.global_strings);
+                let names = if Self::looks_like_hex_address(&name) {
+                    &mut unresolved
+                } else {
+                    &mut resolved
+                };
+                names.entry(lib_idx).or_default().insert(name);
+            }
+
+            for (stack_idx_opt, weight) in thread.samples() {
+                if let Some(stack_idx) = stack_idx_opt {
+                    let leaf_func_idx = thread.get_frame_func(thread.get_stack_frame(stack_idx));
+                    if let Some(lib_idx) = thread.get_func_lib_index(leaf_func_idx) {
+                        *self_samples.entry(lib_idx).or_insert(0) += weight;
+                    }
+                }
+            }
+        }
+
+        let mut libraries: Vec<LibrarySymbolicationStatus> = self
+            .libs
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| resolved.contains_key(idx) || unresolved.contains_key(idx))
+            .map(|(idx, lib)| {
+                let resolved_functions = resolved.get(&idx).map_or(0, |names| names.len());
+                let unresolved_functions = unresolved.get(&idx).map_or(0, |names| names.len());
+                let (state, source) = if unresolved_functions == 0 {
+                    (SymbolicationState::Resolved, Some(SymbolSource::Profile))
+                } else {
+                    (SymbolicationState::Pending, None)
+                };
+                LibrarySymbolicationStatus {
+                    library: lib.name.clone(),
+                    debug_name: lib.debug_name.clone(),
+                    debug_id: lib.debug_id.clone(),
+                    path: lib.path.clone(),
+                    state,
+                    source,
+                    resolved_functions,
+                    unresolved_functions,
+                    self_samples: self_samples.get(&idx).copied().unwrap_or(0),
+                    approximate_lookups: 0,
+                    error_kind: None,
+                    error: None,
+                }
+            })
+            .collect();
+
+        libraries.sort_by(|a, b| {
+            b.self_samples
+                .cmp(&a.self_samples)
+                .then_with(|| a.library.cmp(&b.library))
+        });
+        libraries
+    }
+
+    /// Get the library and sampled addresses of a function, so that its symbol
+    /// can be looked up before calling [`ProfileAnalyzer::get_asm`].
+    pub fn asm_symbol_request(&self, function_pattern: &str) -> Option<AsmSymbolRequest> {
+        let target = self.find_matching_function(function_pattern);
+        let samples = self.collect_asm_samples(&target);
+        let lib = self.libs.get(samples.lib_idx?)?;
+
+        let mut addresses: Vec<u64> = samples.address_data.keys().copied().collect();
+        addresses.sort_unstable();
+        Some(AsmSymbolRequest {
+            debug_name: lib.debug_name.clone(),
+            debug_id: lib.debug_id.clone(),
+            addresses,
+        })
+    }
+
+    /// Aggregate the self samples of a function per instruction address
+    fn collect_asm_samples(&self, target: &str) -> AsmSamples {
+        let mut func_idx = None;
+        let mut thread_idx = None;
+        let mut lib_idx = None;
+        let mut self_samples: i64 = 0;
+        // Track (samples, source_line) per address
+        let mut address_data: HashMap<u64, (i64, Option<u32>)> = HashMap::new();
+
+        for (tidx, thread) in self.threads.iter().enumerate() {
+            for (stack_idx_opt, weight) in thread.samples() {
+                if let Some(stack_idx) = stack_idx_opt {
+                    let stack_with_frames = thread.walk_stack_with_frames(stack_idx);
+
+                    // Only count self time (leaf function)
+                    if let Some(&(leaf_func_idx, leaf_frame_idx)) = stack_with_frames.first() {
+                        let name = thread.get_func_name(leaf_func_idx, &self.global_strings);
+                        if name == target {
+                            self_samples += weight;
+
+                            if func_idx.is_none() {
+                                func_idx = Some(leaf_func_idx);
+                                thread_idx = Some(tidx);
+                                lib_idx = thread.get_func_lib_index(leaf_func_idx);
+                            }
+
+                            // Track per-address samples with source line
+                            if let Some(addr) = thread.get_frame_address(leaf_frame_idx) {
+                                let line = thread.get_frame_line(leaf_frame_idx);
+                                let entry = address_data.entry(addr).or_insert((0, None));
+                                entry.0 += weight;
+                                // Keep the first line we find for this address
+                                if entry.1.is_none() {
+                                    entry.1 = line;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        AsmSamples {
+            func_idx,
+            thread_idx,
+            lib_idx,
+            self_samples,
+            address_data,
+        }
+    }
+
+    /// Get assembly information for a function with sample annotations
+    ///
+    /// `symbol` fills in what the profile lacks: the function's address and size
+    /// in unsymbolicated profiles, and the source line of each sampled address.
+    pub fn get_asm(&self, function_pattern: &str, symbol: Option<&AsmSymbolInfo>) -> AsmResponse {
+        // Find the function and aggregate its samples
+        let target = self.find_matching_function(function_pattern);
+        let AsmSamples {
+            func_idx,
+            thread_idx,
+            lib_idx,
+            self_samples,
+            mut address_data,
+        } = self.collect_asm_samples(&target);
+
+        if let Some(symbol) = symbol {
+            for (addr, (_, line)) in address_data.iter_mut() {
+                if line.is_none() {
+                    *line = symbol.lines.get(addr).copied();
+                }
+            }
+        }
+
+        // Build function info
+        let (mut func_info, mut file_path) = if let (Some(fidx), Some(tidx)) = (func_idx, thread_idx) {
+            let thread = &self.threads[tidx];
+            let lib_index = thread.get_func_lib_index(fidx);
+            let library = lib_index.and_then(|idx| self.libs.get(idx).map(|l| l.name.clone()));
+            let (address, size) = self.find_func_native_symbol_info(tidx, fidx);
+            let file_path = thread.get_func_file(fidx, &self.global_strings);
+
+            (
+                FunctionInfo {
+                    name: target.clone(),
+                    library,
+                    file_path: file_path.clone(),
+                    line_number: thread.get_func_line(fidx),
+                    address,
+                    size,
+                },
+                file_path,
+            )
+        } else {
+            (
+                FunctionInfo {
+                    name: target.clone(),
+                    library: None,
+                    file_path: None,
+                    line_number: None,
+                    address: None,
+                    size: None,
+                },
+                None,
+            )
+        };
+
+        if let Some(symbol) = symbol {
+            if func_info.address.is_none() {
+                func_info.address = Some(format!("0x{:x}", symbol.address));
+                func_info.size = symbol.size;
+            }
+            if file_path.is_none() {
+                file_path = symbol.file_path.clone();
+                func_info.file_path = file_path.clone();
+            }
+        }
+
+        // Try to disassemble
+        let func_base_addr = func_info
+            .address
+            .as_ref()
+            .and_then(|s| u64::from_str_radix(s.trim_start_matches("0x"), 16).ok());
+
+        let func_size = func_info.size;
+
+        // Get library info for disassembly
+        let lib_info = lib_idx.and_then(|idx| self.libs.get(idx));
+
+        // Try to disassemble
+        let (regions, error) = if let (Some(base_addr), Some(size), Some(lib)) = (func_base_addr, func_size, lib_info) {
+            match self.disassemble_function(lib, base_addr, size, &address_data, self_samples, &file_path) {
+                Ok(regions) => (Some(regions), None),
+                Err(e) => (None, Some(e)),
+            }
+        } else {
+            (None, Some("Missing function address, size, or library info".to_string()))
+        };
+
+        AsmResponse {
+            function: func_info,
+            file_path,
+            regions,
+            self_samples,
+            error,
+        }
+    }
+
+    /// Disassemble a function and return regions with context around hot spots
+    #[cfg(feature = "native")]
+    fn disassemble_function(
+        &self,
+        lib: &LibInfo,
+        base_addr: u64,
+        size: u32,
+        address_samples: &HashMap<u64, (i64, Option<u32>)>,
+        total_samples: i64,
+        source_file: &Option<String>,
+    ) -> Result<Vec<AsmRegion>, String> {
+        use capstone::prelude::*;
+        use std::fs::File;
+        use std::io::{BufRead, BufReader};
+
+        // Read the binary file
+        let binary_path = std::path::Path::new(&lib.path);
+        if !binary_path.exists() {
+            return Err(format!("Binary not found: {}", lib.path));
+        }
+
+        // Parse the binary using the object crate
+        let file_data = std::fs::read(binary_path)
+            .map_err(|e| format!("Failed to read binary: {}", e))?;
+        let obj_file = object::File::parse(&*file_data)
+            .map_err(|e| format!("Failed to parse binary: {}", e))?;
+
+        // Find the section containing the function
+        use object::Object;
+        use object::ObjectSection;
+        use object::ObjectSegment;
+
+        // The profile stores addresses as relative offsets from the library load base.
+        // We need to find the image base (the virtual address where code starts) to
+        // convert to absolute virtual addresses used in the binary.
+        //
+        // Cross-platform approach:
+        // - Mach-O: Use the lowest segment vmaddr that isn't __PAGEZERO (typically __TEXT at 0x100000000)
+        // - ELF: Use the lowest PT_LOAD vaddr (typically 0 or 0x400000)
+        // - PE: Use the image_base from headers
+        let image_base = {
+            let mut min_vaddr: Option<u64> = None;
+            for segment in obj_file.segments() {
+                let vaddr = segment.address();
+                // Check the file range - segments with no file data (like __PAGEZERO) have file_size = 0
+                let (_, file_size) = segment.file_range();
+                let has_file_data = file_size > 0;
+                if has_file_data {
+                    match min_vaddr {
+                        None => min_vaddr = Some(vaddr),
+                        Some(min) if vaddr < min => min_vaddr = Some(vaddr),
+                        _ => {}
+                    }
+                }
+            }
+            min_vaddr.unwrap_or(0)
+        };
+
+        // Convert relative address to absolute virtual address
+        let absolute_addr = base_addr + image_base;
+
+        let mut func_bytes = None;
+
+        for section in obj_file.sections() {
+            if let Ok(name) = section.name() {
+                // Look for executable sections
+                if name == "__text" || name == ".text" || name.contains("text") {
+                    if let Ok(data) = section.data() {
+                        let sec_addr = section.address();
+                        let sec_end = sec_addr + data.len() as u64;
+
+                        // Check if our function is in this section
+                        if absolute_addr >= sec_addr && absolute_addr < sec_end {
+                            let offset = (absolute_addr - sec_addr) as usize;
+                            let end_offset = offset + size as usize;
+                            if end_offset <= data.len() {
+                                func_bytes = Some(&data[offset..end_offset]);
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let code_bytes = func_bytes.ok_or_else(|| {
+            format!(
+                "Function not found in binary. Address 0x{:x} not within executable sections.",
+                base_addr
+            )
+        })?;
+
+        // Create capstone disassembler based on architecture
+        let cs = match lib.arch.as_str() {
+            "aarch64" | "arm64" => {
+                Capstone::new()
+                    .arm64()
+                    .mode(arch::arm64::ArchMode::Arm)
+                    .detail(true)
+                    .build()
+                    .map_err(|e| format!("Failed to create disassembler: {}", e))?
+            }
+            "x86_64" | "x86-64" | "" => {
+                Capstone::new()
+                    .x86()
+                    .mode(arch::x86::ArchMode::Mode64)
+                    .detail(true)
+                    .build()
+                    .map_err(|e| format!("Failed to create disassembler: {}", e))?
+            }
+            "x86" | "i386" => {
+                Capstone::new()
+                    .x86()
+                    .mode(arch::x86::ArchMode::Mode32)
+                    .detail(true)
+                    .build()
+                    .map_err(|e| format!("Failed to create disassembler: {}", e))?
+            }
+            arch => return Err(format!("Unsupported architecture: {}", arch)),
+        };
+
+        // Disassemble
+        let insns = cs.disasm_all(code_bytes, base_addr)
+            .map_err(|e| format!("Disassembly failed: {}", e))?;
+
+        // Read source file lines if available
+        let source_lines: HashMap<u32, String> = if let Some(ref path) = source_file {
+            if let Ok(file) = File::open(path) {
+                BufReader::new(file)
+                    .lines()
+                    .enumerate()
+                    .filter_map(|(i, line)| line.ok().map(|l| ((i + 1) as u32, l)))
+                    .collect()
+            } else {
+                HashMap::new()
+            }
+        } else {
+            HashMap::new()
+        };
+
+        // Build instruction list with annotations: the address, text, samples,
+        // percent and source line of each instruction
+        type AnnotatedInsn = (u64, String, Option<i64>, Option<f64>, Option<u32>);
+        let mut all_insns: Vec<AnnotatedInsn> = Vec::new();
+
+        for insn in insns.iter() {
+            let addr = insn.address();
+            let asm_text = format!("{} {}", insn.mnemonic().unwrap_or(""), insn.op_str().unwrap_or("")).trim().to_string();
+
+            let (samples, percent, source_line) = if let Some(&(s, line)) = address_samples.get(&addr) {
+                let pct = if total_samples > 0 {
+                    100.0 * s as f64 / total_samples as f64
+                } else {
+                    0.0
+                };
+                (Some(s), Some(pct), line)
+            } else {
+                (None, None, None)
+            };
+
+            all_insns.push((addr, asm_text, samples, percent, source_line));
+        }
+
+        // Group into regions: show context around hot instructions
+        const CONTEXT_LINES: usize = 5;
+        let mut regions: Vec<AsmRegion> = Vec::new();
+        let mut hot_indices: Vec<usize> = Vec::new();
+
+        // Find indices of hot instructions
+        for (i, (_, _, samples, _, _)) in all_insns.iter().enumerate() {
+            if samples.is_some() {
+                hot_indices.push(i);
+            }
+        }
+
+        if hot_indices.is_empty() {
+            // No hot instructions, return empty
+            return Ok(vec![]);
+        }
+
+        // Merge overlapping ranges
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for &idx in &hot_indices {
+            let start = idx.saturating_sub(CONTEXT_LINES);
+            let end = (idx + CONTEXT_LINES + 1).min(all_insns.len());
+
+            if let Some(last) = ranges.last_mut() {
+                if start <= last.1 {
+                    // Merge with previous range
+                    last.1 = last.1.max(end);
+                } else {
+                    ranges.push((start, end));
+                }
+            } else {
+                ranges.push((start, end));
+            }
+        }
+
+        // Build regions from ranges
+        let mut last_end = 0;
+        for (start, end) in ranges {
+            // Add gap indicator if needed
+            if start > last_end && !regions.is_empty() {
+                regions.push(AsmRegion {
+                    source_line: None,
+                    source_text: Some("...".to_string()),
+                    instructions: vec![],
+                });
+            }
+
+            // Group instructions by source line within this range
+            let mut current_line: Option<u32> = None;
+            let mut current_insns: Vec<AsmInstruction> = Vec::new();
+
+            for (addr, asm_text, samples, percent, source_line) in &all_insns[start..end] {
+                // If source line changes, start a new region
+                if *source_line != current_line && !current_insns.is_empty() {
+                    let source_text = current_line.and_then(|l| source_lines.get(&l).cloned());
+                    regions.push(AsmRegion {
+                        source_line: current_line,
+                        source_text,
+                        instructions: std::mem::take(&mut current_insns),
+                    });
+                }
+
+                current_line = *source_line;
+                current_insns.push(AsmInstruction {
+                    address: format!("0x{:x}", addr),
+                    asm: asm_text.clone(),
+                    samples: *samples,
+                    percent: *percent,
+                });
+            }
+
+            // Flush remaining instructions
+            if !current_insns.is_empty() {
+                let source_text = current_line.and_then(|l| source_lines.get(&l).cloned());
+                regions.push(AsmRegion {
+                    source_line: current_line,
+                    source_text,
+                    instructions: current_insns,
+                });
+            }
+
+            last_end = end;
+        }
+
+        Ok(regions)
+    }
+
+    #[cfg(not(feature = "native"))]
+    fn disassemble_function(
+        &self,
+        _lib: &LibInfo,
+        _base_addr: u64,
+        _size: u32,
+        _address_samples: &HashMap<u64, (i64, Option<u32>)>,
+        _total_samples: i64,
+        _source_file: &Option<String>,
+    ) -> Result<Vec<AsmRegion>, String> {
+        Err("Disassembly is not available in this build".to_string())
+    }
+
+    /// Get the source of a function annotated with per-line self/total sample counts.
+    ///
+    /// The returned lines span from the function's first line to its last sampled
+    /// line, with `context` extra lines on either side. If `symbol` has the
+    /// checksum of the source file from the debug info, the local file is
+    /// checked against it.
+    pub fn get_source(
+        &self,
+        function_pattern: &str,
+        context: u32,
+        symbol: Option<&AsmSymbolInfo>,
+    ) -> SourceResponse {
+        let target = self.find_matching_function(function_pattern);
+
+        let mut func_idx = None;
+        let mut thread_idx = None;
+        let mut self_samples: i64 = 0;
+        let mut total_samples: i64 = 0;
+        // line -> (self samples, total samples)
+        let mut line_samples: HashMap<u32, (i64, i64)> = HashMap::new();
+
+        for (tidx, thread) in self.threads.iter().enumerate() {
+            for (stack_idx_opt, weight) in thread.samples() {
+                let Some(stack_idx) = stack_idx_opt else {
+                    continue;
+                };
+                let stack_with_frames = thread.walk_stack_with_frames(stack_idx);
+
+                // Count each line at most once per sample, so that recursion
+                // doesn't inflate the total.
+                let mut seen_lines = std::collections::HashSet::new();
+                let mut on_stack = false;
+                for (depth, &(fidx, frame_idx)) in stack_with_frames.iter().enumerate() {
+                    if thread.get_func_name(fidx, &self.global_strings) != target {
+                        continue;
+                    }
+                    if func_idx.is_none() {
+                        func_idx = Some(fidx);
+                        thread_idx = Some(tidx);
+                    }
+                    on_stack = true;
+                    let line = thread.get_frame_line(frame_idx);
+                    if depth == 0 {
+                        self_samples += weight;
+                        if let Some(line) = line {
+                            line_samples.entry(line).or_default().0 += weight;
+                        }
+                    }
+                    if let Some(line) = line {
+                        if seen_lines.insert(line) {
+                            line_samples.entry(line).or_default().1 += weight;
+                        }
+                    }
+                }
+                if on_stack {
+                    total_samples += weight;
+                }
+            }
+        }
+
+        let (func_info, file_path) = if let (Some(fidx), Some(tidx)) = (func_idx, thread_idx) {
+            let thread = &self.threads[tidx];
+            let lib_index = thread.get_func_lib_index(fidx);
+            let library = lib_index.and_then(|idx| self.libs.get(idx).map(|l| l.name.clone()));
+            let (address, size) = self.find_func_native_symbol_info(tidx, fidx);
+            let file_path = thread.get_func_file(fidx, &self.global_strings);
+            (
+                FunctionInfo {
+                    name: target.clone(),
+                    library,
+                    file_path: file_path.clone(),
+                    line_number: thread.get_func_line(fidx),
+                    address,
+                    size,
+                },
+                file_path,
+            )
+        } else {
+            let error = format!(
+                "Function '{}' not found or has no samples",
+                function_pattern
+            );
+            return SourceResponse {
+                function: FunctionInfo {
+                    name: target,
+                    library: None,
+                    file_path: None,
+                    line_number: None,
+                    address: None,
+                    size: None,
+                },
+                file_path: None,
+                self_samples: 0,
+                total_samples: 0,
+                lines: vec![],
+                source_hash: None,
+                warning: None,
+                error: Some(error),
+            };
+        };
+
+        let percent = |samples: i64| {
+            if total_samples > 0 {
+                100.0 * samples as f64 / total_samples as f64
+            } else {
+                0.0
+            }
+        };
+
+        // Determine the line range to show
+        let first_sampled = line_samples.keys().min().copied();
+        let last_sampled = line_samples.keys().max().copied();
+        let start = match (func_info.line_number, first_sampled) {
+            (Some(a), Some(b)) => a.min(b),
+            (a, b) => a.or(b).unwrap_or(1),
+        };
+        let end = last_sampled.unwrap_or(start).max(start);
+        let start = start.saturating_sub(context).max(1);
+        let end = end.saturating_add(context);
+
+        let (source, error) = match &file_path {
+            Some(path) => match std::fs::read(path) {
+                Ok(bytes) => (Some(bytes), None),
+                Err(e) => (None, Some(format!("Could not read source file {}: {}", path, e))),
+            },
+            None => (None, Some("No source file information for this function".to_string())),
+        };
+        let source_hash = source.as_ref().map(|bytes| {
+            let local = md5::md5(bytes);
+            let built = symbol.and_then(|symbol| symbol.file_md5);
+            SourceHash {
+                algorithm: "md5",
+                local: md5::to_hex(&local),
+                debug_info: built.map(|built| md5::to_hex(&built)),
+                matches: built.map(|built| built == local),
+            }
+        });
+        let warning = match (&source_hash, &file_path) {
+            (Some(hash), Some(path)) if hash.matches == Some(false) => Some(format!(
+                "{} has changed since {} was built: its MD5 doesn't match the debug info's, \
+                 so the line numbers may point at the wrong lines",
+                path, target
+            )),
+            _ => None,
+        };
+        let source_text = source.map(|bytes| String::from_utf8_lossy(&bytes).into_owned());
+
+        let lines = match &source_text {
+            Some(text) => {
+                let file_lines: Vec<&str> = text.lines().collect();
+                let end = end.min(file_lines.len() as u32);
+                (start..=end)
+                    .map(|line| {
+                        let (line_self, line_total) =
+                            line_samples.get(&line).copied().unwrap_or((0, 0));
+                        SourceLine {
+                            line,
+                            text: file_lines.get(line as usize - 1).map(|l| l.to_string()),
+                            self_samples: line_self,
+                            total_samples: line_total,
+                            self_percent: percent(line_self),
+                            total_percent: percent(line_total),
+                        }
+                    })
+                    .collect()
+            }
+            None => {
+                // Without the source text, only report the sampled lines.
+                let mut lines: Vec<SourceLine> = line_samples
+                    .iter()
+                    .map(|(&line, &(line_self, line_total))| SourceLine {
+                        line,
+                        text: None,
+                        self_samples: line_self,
+                        total_samples: line_total,
+                        self_percent: percent(line_self),
+                        total_percent: percent(line_total),
+                    })
+                    .collect();
+                lines.sort_by_key(|l| l.line);
+                lines
+            }
+        };
+
+        SourceResponse {
+            function: func_info,
+            file_path,
+            self_samples,
+            total_samples,
+            lines,
+            source_hash,
+            warning,
+            error,
+        }
+    }
+
+    /// Drilldown from a function, following the hottest callee path
+    ///
+    /// This is the key query for performance debugging. Starting from a function,
+    /// it recursively follows the hottest callee until:
+    /// - Max depth is reached
+    /// - Self-time exceeds threshold (bottleneck found)
+    /// - No more callees
+    pub fn drilldown(
+        &self,
+        function_pattern: &str,
+        max_depth: usize,
+        threshold_percent: f64,
+    ) -> DrilldownResponse {
+        // Build comprehensive stats: function -> (self_samples, total_samples, callees, info)
+        #[derive(Default, Clone)]
+        struct FuncStats {
+            self_samples: i64,
+            total_samples: i64,
+            func_idx: Option<usize>,
+            thread_idx: Option<usize>,
+            line_samples: HashMap<u32, i64>,
+        }
+
+        #[derive(Default, Clone)]
+        struct CalleeData {
+            samples: i64,
+        }
+
+        let mut func_stats: HashMap<String, FuncStats> = HashMap::new();
+        let mut callee_map: HashMap<String, HashMap<String, CalleeData>> = HashMap::new();
+        let mut total_weight: i64 = 0;
+
+        // Collect all stats in one pass
+        for (thread_idx, thread) in self.threads.iter().enumerate() {
+            for (stack_idx_opt, weight) in thread.samples() {
+                total_weight += weight;
+
+                if let Some(stack_idx) = stack_idx_opt {
+                    let stack_with_frames = thread.walk_stack_with_frames(stack_idx);
+                    let func_info: Vec<(String, usize, usize)> = stack_with_frames
+                        .iter()
+                        .map(|&(func_idx, frame_idx)| {
+                            (thread.get_func_name(func_idx, &self.global_strings), func_idx, frame_idx)
+                        })
+                        .collect();
+
+                    // Self time: leaf function only
+                    if let Some((ref name, func_idx, frame_idx)) = func_info.first() {
+                        let stats = func_stats.entry(name.clone()).or_default();
+                        stats.self_samples += weight;
+                        if stats.func_idx.is_none() {
+                            stats.func_idx = Some(*func_idx);
+                            stats.thread_idx = Some(thread_idx);
+                        }
+                        // Track per-line samples for hot_lines
+                        if let Some(line) = thread.get_frame_line(*frame_idx) {
+                            *stats.line_samples.entry(line).or_insert(0) += weight;
+                        }
+                    }
+
+                    // Total time: each unique function in stack
+                    let mut seen = std::collections::HashSet::new();
+                    for (name, func_idx, _) in &func_info {
+                        if seen.insert(name.clone()) {
+                            let stats = func_stats.entry(name.clone()).or_default();
+                            stats.total_samples += weight;
+                            if stats.func_idx.is_none() {
+                                stats.func_idx = Some(*func_idx);
+                                stats.thread_idx = Some(thread_idx);
+                            }
+                        }
+                    }
+
+                    // Caller->Callee relationships (sample attributed to the relationship)
+                    // In walk_stack, index 0 is leaf (callee), higher indices are callers
+                    for i in 0..func_info.len().saturating_sub(1) {
+                        let (callee_name, _, _) = &func_info[i];
+                        let (caller_name, _, _) = &func_info[i + 1];
+                        callee_map
+                            .entry(caller_name.clone())
+                            .or_default()
+                            .entry(callee_name.clone())
+                            .or_default()
+                            .samples += weight;
+                    }
+                }
+            }
+        }
+
+        // Find the starting function
+        let root = self.find_matching_function(function_pattern);
+
+        // Follow the hot path
+        let mut path: Vec<DrilldownNode> = Vec::new();
+        let mut current = root.clone();
+        let mut visited = std::collections::HashSet::new();
+        let mut bottleneck: Option<BottleneckSummary> = None;
+
+        for _depth in 0..max_depth {
+            // Skip if we've already visited this function (cycle detection)
+            // but don't stop - we'll find an unvisited callee at the end of the loop
+            if visited.contains(&current) {
+                // Find the first unvisited callee from the previous node's callees
+                // This handles cycles in the call graph (e.g., Rust's catch_unwind pattern)
+                if let Some(prev_node) = path.last() {
+                    // The callees are sorted, hottest first
+                    if let Some(next_callee) = prev_node.callees.iter()
+                        .find(|c| !visited.contains(&c.name))
+                    {
+                        current = next_callee.name.clone();
+                        continue;
+                    }
+                }
+                break; // No unvisited callees, truly stuck in a cycle
+            }
+            visited.insert(current.clone());
+
+            let stats = func_stats.get(&current);
+            let (self_samples, total_samples) = stats
+                .map(|s| (s.self_samples, s.total_samples))
+                .unwrap_or((0, 0));
+
+            let self_percent = if total_weight > 0 {
+                100.0 * self_samples as f64 / total_weight as f64
+            } else {
+                0.0
+            };
+            let total_percent = if total_weight > 0 {
+                100.0 * total_samples as f64 / total_weight as f64
+            } else {
+                0.0
+            };
+
+            // Get function info
+            let (library, file_path, line_number) = if let Some(s) = stats {
+                if let (Some(fidx), Some(tidx)) = (s.func_idx, s.thread_idx) {
+                    let thread = &self.threads[tidx];
+                    let lib_idx = thread.get_func_lib_index(fidx);
+                    (
+                        lib_idx.and_then(|idx| self.libs.get(idx).map(|l| l.name.clone())),
+                        thread.get_func_file(fidx, &self.global_strings),
+                        thread.get_func_line(fidx),
+                    )
+                } else {
+                    (None, None, None)
+                }
+            } else {
+                (None, None, None)
+            };
+
+            // Get callees sorted by samples
+            let callees_data = callee_map.get(&current);
+            let callee_total: i64 = callees_data
+                .map(|c| c.values().map(|d| d.samples).sum())
+                .unwrap_or(0);
+
+            let mut callees: Vec<DrilldownCallee> = callees_data
+                .map(|c| {
+                    c.iter()
+                        .map(|(name, data)| DrilldownCallee {
+                            name: name.clone(),
+                            percent: if callee_total > 0 {
+                                100.0 * data.samples as f64 / callee_total as f64
+                            } else {
+                                0.0
+                            },
+                            is_hottest: None,
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            callees.sort_by(|a, b| {
+                b.percent
+                    .total_cmp(&a.percent)
+                    .then_with(|| a.name.cmp(&b.name))
+            });
+
+            // Mark the hottest callee
+            if let Some(first) = callees.first_mut() {
+                first.is_hottest = Some(true);
+            }
+
+            // Check if this is a bottleneck
+            let is_bottleneck = self_percent > threshold_percent;
+
+            // Build hot_lines if bottleneck
+            let hot_lines = if is_bottleneck {
+                stats.and_then(|s| {
+                    if s.line_samples.is_empty() {
+                        None
+                    } else {
+                        let mut lines: Vec<HotLine> = s.line_samples
+                            .iter()
+                            .map(|(&line, &samples)| HotLine {
+                                line,
+                                samples,
+                                percent: if self_samples > 0 {
+                                    100.0 * samples as f64 / self_samples as f64
+                                } else {
+                                    0.0
+                                },
+                            })
+                            .collect();
+                        lines.sort_by(|a, b| b.samples.cmp(&a.samples).then(a.line.cmp(&b.line)));
+                        Some(lines)
+                    }
+                })
+            } else {
+                None
+            };
+
+            path.push(DrilldownNode {
+                function: current.clone(),
+                library: library.clone(),
+                file_path: file_path.clone(),
+                line_number,
+                total_samples,
+                total_percent,
+                self_samples,
+                self_percent,
+                is_bottleneck: if is_bottleneck { Some(true) } else { None },
+                callees: callees.clone(),
+                hot_lines,
+            });
+
+            // If bottleneck found, record it and stop
+            if is_bottleneck {
+                bottleneck = Some(BottleneckSummary {
+                    function: current.clone(),
+                    library,
+                    file_path,
+                    line_number,
+                    self_percent,
+                    reason: format!(
+                        "High self-time ({:.1}%) indicates this function's own code is the bottleneck",
+                        self_percent
+                    ),
+                });
+                break;
+            }
+
+            // Drill into hottest callee
+            if let Some(hottest) = callees.first() {
+                current = hottest.name.clone();
+            } else {
+                break; // No callees, stop here
+            }
+        }
+
+        // Check if function wasn't found or had no samples
+        let root_has_samples = func_stats.get(&root).map(|s| s.total_samples > 0).unwrap_or(false);
+        let (error, suggestions) = if !root_has_samples {
+            // Get top 5 functions as suggestions
+            let mut top_funcs: Vec<(&String, i64)> = func_stats
+                .iter()
+                .map(|(name, stats)| (name, stats.total_samples))
+                .collect();
+            top_funcs.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+            let suggestions: Vec<String> = top_funcs
+                .iter()
+                .take(5)
+                .map(|(name, samples)| {
+                    let percent = if total_weight > 0 {
+                        100.0 * *samples as f64 / total_weight as f64
+                    } else {
+                        0.0
+                    };
+                    format!("{} ({:.1}%)", name, percent)
+                })
+                .collect();
+
+            let error_msg = format!(
+                "Function '{}' not found or has no samples. Try one of the top functions listed in 'suggestions'.",
+                function_pattern
+            );
+            (Some(error_msg), Some(suggestions))
+        } else {
+            (None, None)
+        };
+
+        DrilldownResponse {
+            root,
+            total_samples: total_weight,
+            path,
+            bottleneck,
+            error,
+            suggestions,
+        }
+    }
+
+    /// Find a function by pattern (substring match)
+    fn find_matching_function(&self, pattern: &str) -> String {
+        // Return the pattern itself if no match found
+        self.resolve_function(pattern, MatchMode::Substring)
+            .unwrap_or_else(|_| pattern.to_string())
+    }
+
+    /// The names of all functions in the profile, in thread order. May contain duplicates.
+    fn function_names(&self) -> impl Iterator<Item = String> + '_ {
+        self.threads.iter().flat_map(move |thread| {
+            thread
+                .func_name_idx
+                .iter()
+                .map(move |&name_idx| thread.get_string(name_idx, &self.global_strings))
+        })
+    }
+
+    /// The first function whose name matches, or failing that, whose normalized
+    /// (demangled, generics-stripped) name matches.
+    fn find_function(&self, matcher: &FunctionMatcher) -> Option<String> {
+        self.function_names()
+            .find(|name| matcher.matches(name))
+            .or_else(|| {
+                self.function_names()
+                    .find(|name| matcher.matches_normalized(&normalize_function_name(name)))
+            })
+    }
+
+    /// Resolve a function pattern to the name of a function in the profile.
+    ///
+    /// In substring mode, an exact match is preferred over a substring match.
+    /// If nothing matches, the error lists similar function names.
+    pub fn resolve_function(
+        &self,
+        pattern: &str,
+        mode: MatchMode,
+    ) -> Result<String, FunctionNotFound> {
+        let matcher = FunctionMatcher::new(pattern, mode).map_err(|error| FunctionNotFound {
+            error,
+            candidates: Vec::new(),
+        })?;
+        if mode == MatchMode::Substring {
+            let exact = FunctionMatcher::new(pattern, MatchMode::Exact)
+                .expect("exact matchers are always valid");
+            if let Some(name) = self.find_function(&exact) {
+                return Ok(name);
+            }
+        }
+        if let Some(name) = self.find_function(&matcher) {
+            return Ok(name);
+        }
+
+        let mut names: Vec<String> = self.function_names().collect();
+        names.sort_unstable();
+        names.dedup();
+        Err(FunctionNotFound {
+            error: format!("No function matches '{pattern}' ({mode} match)"),
+            candidates: near_misses(pattern, names.iter().map(String::as_str), 10),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A single-thread profile: main -> work -> {compute, 0x1efcfc (libc)}
+    const TEST_PROFILE: &str = r#"{
+        "meta": { "product": "test", "interval": 1.0, "startTime": 0 },
+        "libs": [
+            { "name": "app", "path": "/bin/app", "debugName": "app", "breakpadId": "A0" },
+            { "name": "libc.so.6", "path": "/lib/libc.so.6", "debugName": "libc.so.6", "breakpadId": "B0" }
+        ],
+        "threads": [{
+            "name": "main", "pid": "1", "tid": "1", "isMainThread": true,
+            "samples": { "stack": [2, 2, 3, null], "weight": [1, 1, 1, 1], "length": 4 },
+            "stackTable": { "prefix": [null, 0, 1, 1], "frame": [0, 1, 2, 3], "length": 4 },
+            "frameTable": {
+                "func": [0, 1, 2, 3],
+                "line": [null, 10, 20, null],
+                "address": [-1, 4096, 8192, 12288],
+                "length": 4
+            },
+            "funcTable": { "name": [0, 1, 2, 3], "resource": [0, 0, 0, 1], "length": 4 },
+            "resourceTable": { "lib": [0, 1], "name": [4, 5], "length": 2 },
+            "stringTable": ["main", "work", "compute", "0x1efcfc", "app", "libc"]
+        }]
+    }"#;
+
+    fn test_analyzer() -> ProfileAnalyzer {
+        let raw: RawProfile = serde_json::from_str(TEST_PROFILE).unwrap();
+        ProfileAnalyzer::from_raw_profile(raw).unwrap()
+    }
+
+    #[test]
+    fn test_markers() {
+        let profile = TEST_PROFILE.replace(
+            r#""stringTable": ["main""#,
+            r#""markers": {
+                "name": [6, 6, 7],
+                "startTime": [1.0, 5.0, 2.0],
+                "endTime": [3.0, 9.0, null],
+                "data": [null, { "type": "GCMajor" }, null],
+                "length": 3
+            },
+            "stringTable": ["main""#,
+        );
+        let profile = profile.replace(r#""libc"]"#, r#""libc", "GC", "Tick"]"#);
+        let raw: RawProfile = serde_json::from_str(&profile).unwrap();
+        let analyzer = ProfileAnalyzer::from_raw_profile(raw).unwrap();
+
+        let response = analyzer.get_markers(None, None, 10, TimeRange::default());
+        assert_eq!(response.total_count, 3);
+        assert_eq!(response.stats[0].name, "GC");
+        assert_eq!(response.stats[0].count, 2);
+        assert_eq!(response.stats[0].total_duration_ms, 6.0);
+        assert_eq!(response.stats[0].p50_duration_ms, Some(2.0));
+        assert_eq!(response.stats[0].p95_duration_ms, Some(4.0));
+        assert_eq!(response.stats[1].name, "Tick");
+        assert_eq!(response.stats[1].interval_count, 0);
+        let starts: Vec<_> = response.markers.iter().map(|m| m.start_ms).collect();
+        assert_eq!(starts, vec![Some(1.0), Some(2.0), Some(5.0)]);
+        assert_eq!(response.markers[2].data, Some(serde_json::json!({ "type": "GCMajor" })));
+
+        let range = TimeRange {
+            start_ms: Some(4.0),
+            end_ms: None,
+        };
+        let response = analyzer.get_markers(Some("GC"), None, 10, range);
+        assert_eq!(response.total_count, 1);
+    }
+
+    #[test]
+    fn test_stacks_by_trace() {
+        let profile = TEST_PROFILE.replace(
+            r#""weight": [1, 1, 1, 1], "length": 4"#,
+            r#""weight": [1, 1, 1, 1], "timeDeltas": [1.0, 1.0, 1.0, 1.0], "length": 4"#,
+        );
+        let profile = profile.replace(
+            r#""stringTable": ["main""#,
+            r#""markers": {
+                "name": [6, 6, 7],
+                "startTime": [0.5, 2.5, 0.0],
+                "endTime": [2.5, 3.5, 10.0],
+                "data": [
+                    { "type": "SimpleMarker", "name": "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01 GET /users" },
+                    { "type": "TraceSpan", "traceId": "4BF92F3577B34DA6A3CE929D0E0E4736", "spanId": "b7ad6b7169203331" },
+                    null
+                ],
+                "length": 3
+            },
+            "stringTable": ["main""#,
+        );
+        let profile = profile.replace(r#""libc"]"#, r#""libc", "SimpleMarker", "Idle"]"#);
+        let raw: RawProfile = serde_json::from_str(&profile).unwrap();
+        let analyzer = ProfileAnalyzer::from_raw_profile(raw).unwrap();
+
+        let traces = analyzer.list_traces(10, None, TimeRange::default());
+        assert_eq!(traces.traces.len(), 1);
+        assert_eq!(traces.traces[0].spans, 2);
+
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let response = analyzer.stacks_by_trace(traceparent, 10, None, TimeRange::default());
+        assert_eq!(response.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(response.spans.len(), 2);
+        assert_eq!(response.spans[0].name, "GET /users");
+        assert_eq!(response.spans[0].samples, 2);
+        assert_eq!(response.spans[1].span_id.as_deref(), Some("b7ad6b7169203331"));
+        // Samples at 1 and 2 ms are in the first span, 3 ms in the second
+        assert_eq!(response.total_samples, 3);
+        assert_eq!(response.stacks[0].stack, vec!["main", "work", "compute"]);
+        assert_eq!(response.stacks[0].samples, 2);
+
+        let zero_trace_id = "00-00000000000000000000000000000000-00f067aa0ba902b7-01";
+        assert!(TraceContext::parse(zero_trace_id).is_none());
+    }
+
+    #[test]
+    fn test_otlp_export() {
+        let analyzer = test_analyzer();
+        let export = analyzer.otlp_export(Some("app"), 10, None, TimeRange::default(), 1_000);
+
+        let resource_metrics = &export.metrics["resourceMetrics"][0];
+        assert_eq!(
+            resource_metrics["resource"]["attributes"][0]["value"]["stringValue"],
+            "app"
+        );
+        let metrics = resource_metrics["scopeMetrics"][0]["metrics"].as_array().unwrap();
+        let samples = &metrics[0]["gauge"]["dataPoints"][0];
+        assert_eq!(metrics[0]["name"], "profile.samples");
+        assert_eq!(samples["asInt"], "4");
+        assert_eq!(samples["timeUnixNano"], "1000");
+        let self_time = metrics.iter().find(|m| m["name"] == "profile.function.self").unwrap();
+        let top = &self_time["gauge"]["dataPoints"][0];
+        assert_eq!(top["asDouble"], 50.0);
+        assert_eq!(top["attributes"][0]["value"]["stringValue"], "compute");
+
+        let record = &export.logs["resourceLogs"][0]["scopeLogs"][0]["logRecords"][0];
+        assert_eq!(
+            record["body"]["stringValue"],
+            "Profile of test: 4 samples; top self time: compute (50.0%), 0x1efcfc (25.0%)"
+        );
+    }
+
+    #[test]
+    fn test_thread_hotspots() {
+        let analyzer = test_analyzer();
+        let response = analyzer.compute_thread_hotspots(1, None, TimeRange::default());
+        assert_eq!(response.total_samples, 4);
+        assert_eq!(response.threads.len(), 1);
+
+        let main = &response.threads[0];
+        assert_eq!(main.samples, 4);
+        assert_eq!(main.process_percent, 100.0);
+        assert_eq!(main.hotspots.len(), 1);
+        assert_eq!(main.hotspots[0].function.name, "compute");
+        assert_eq!(main.hotspots[0].self_percent, 50.0);
+    }
+
+    #[test]
+    fn test_resolve_function() {
+        let analyzer = test_analyzer();
+        assert_eq!(
+            analyzer
+                .resolve_function("work", MatchMode::Substring)
+                .unwrap(),
+            "work"
+        );
+        assert_eq!(
+            analyzer
+                .resolve_function("^comp.*e$", MatchMode::Regex)
+                .unwrap(),
+            "compute"
+        );
+        assert!(analyzer.resolve_function("wor", MatchMode::Exact).is_err());
+
+        let not_found = analyzer
+            .resolve_function("compte", MatchMode::Substring)
+            .unwrap_err();
+        assert_eq!(not_found.candidates, vec!["compute".to_string()]);
+        assert!(analyzer.resolve_function("(", MatchMode::Regex).is_err());
+
+        let failure = QueryFailure::from(not_found);
+        assert_eq!(failure.code.http_status(), 404);
+        let response: serde_json::Value = serde_json::from_str(&failure.to_json()).unwrap();
+        assert_eq!(response["success"], false);
+        assert_eq!(response["code"], "UNKNOWN_FUNCTION");
+        assert_eq!(response["candidates"], serde_json::json!(["compute"]));
+        let parsed: QueryFailure = serde_json::from_str(&failure.to_json()).unwrap();
+        assert_eq!(parsed.code, QueryErrorCode::UnknownFunction);
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn test_low_memory_index_matches_in_memory() {
+        let dir = std::env::temp_dir().join(format!("samply-index-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let profile_path = dir.join("profile.json");
+        std::fs::write(&profile_path, TEST_PROFILE).unwrap();
+
+        // Functions with the same self time come in no particular order, so
+        // the hotspots are compared by name, without their rank.
+        let hotspots = |analyzer: &ProfileAnalyzer| {
+            let hotspots = analyzer.compute_hotspots(10, None, true, true, TimeRange::default());
+            let mut hotspots: Vec<serde_json::Value> = hotspots
+                .iter()
+                .map(|hotspot| {
+                    let mut hotspot = serde_json::to_value(hotspot).unwrap();
+                    hotspot.as_object_mut().unwrap().remove("rank");
+                    hotspot
+                })
+                .collect();
+            hotspots.sort_by_key(|hotspot| hotspot["function"]["name"].to_string());
+            hotspots
+        };
+
+        // The first load builds the index, the second one reuses it.
+        let mut builds = 0;
+        for _ in 0..2 {
+            let mapped =
+                ProfileAnalyzer::from_file_low_memory(&profile_path, |_| builds += 1).unwrap();
+            let in_memory = test_analyzer();
+            assert_eq!(
+                serde_json::to_value(mapped.get_summary(TimeRange::default())).unwrap(),
+                serde_json::to_value(in_memory.get_summary(TimeRange::default())).unwrap()
+            );
+            assert_eq!(hotspots(&mapped), hotspots(&in_memory));
+        }
+        assert_eq!(builds, 1);
+        assert!(dir.join("profile.json.index/meta.json").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_malformed_profile_loads_partially() {
+        // A thread which isn't one, a stack which is its own prefix, and a
+        // sample of a stack which doesn't exist
+        let profile = TEST_PROFILE
+            .replace(
+                r#""threads": [{"#,
+                r#""threads": [{ "name": "broken", "samples": 5 }, {"#,
+            )
+            .replace(
+                r#""prefix": [null, 0, 1, 1]"#,
+                r#""prefix": [null, 0, 1, 3]"#,
+            )
+            .replace(r#""stack": [2, 2, 3, null]"#, r#""stack": [2, 2, 3, 99]"#);
+        let analyzer = ProfileAnalyzer::from_json(&profile).unwrap();
+
+        let warnings = analyzer.parse_warnings();
+        assert_eq!(warnings.len(), 3);
+        assert_eq!(warnings[0].thread_index, Some(0));
+        assert_eq!(warnings[0].thread.as_deref(), Some("broken"));
+        assert!(warnings[1..]
+            .iter()
+            .all(|w| w.thread.as_deref() == Some("main")));
+        let summary = analyzer.get_summary(TimeRange::default());
+        assert_eq!(summary.thread_count, 1);
+        assert_eq!(summary.parse_warnings, warnings);
+        let hotspots = analyzer.compute_hotspots(10, None, false, false, TimeRange::default());
+        assert!(hotspots.iter().any(|h| h.function.name == "0x1efcfc"));
+
+        // A truncated profile keeps the threads before the end.
+        let end = TEST_PROFILE.rfind("}]").unwrap() + 1;
+        let truncated = format!(r#"{}, {{ "name": "cut"#, &TEST_PROFILE[..end]);
+        let analyzer = ProfileAnalyzer::from_json(&truncated).unwrap();
+        assert_eq!(analyzer.get_summary(TimeRange::default()).thread_count, 1);
+        assert!(analyzer.parse_warnings()[0].message.contains("truncated"));
+
+        assert!(ProfileAnalyzer::from_json(r#"{ "meta": {} }"#).is_err());
+    }
+
+    #[test]
+    fn test_call_tree() {
+        let analyzer = test_analyzer();
+        let tree = analyzer.call_tree(None, 0.0, 20, 10, TimeRange::default());
+        assert_eq!(tree.total_samples, 4);
+        assert_eq!(tree.node_count, 4);
+        assert_eq!(tree.roots.len(), 1);
+        let main = &tree.roots[0];
+        assert_eq!(main.name, "main");
+        assert_eq!(main.total_samples, 3);
+        assert_eq!(main.total_percent, 75.0);
+        let work = &main.children[0];
+        assert_eq!(work.self_samples, 0);
+        assert_eq!(work.children[0].name, "compute");
+        assert_eq!(work.children[0].self_percent, 50.0);
+        assert_eq!(work.children[1].name, "0x1efcfc");
+
+        let tree = analyzer.call_tree(None, 0.0, 20, 1, TimeRange::default());
+        let work = &tree.roots[0].children[0];
+        assert_eq!(work.children.len(), 1);
+        let pruned = work.pruned.as_ref().unwrap();
+        assert_eq!((pruned.count, pruned.samples), (1, 1));
+
+        let tree = analyzer.call_tree(None, 30.0, 2, 10, TimeRange::default());
+        let work = &tree.roots[0].children[0];
+        assert!(work.children.is_empty());
+        let pruned = work.pruned.as_ref().unwrap();
+        assert_eq!((pruned.count, pruned.samples), (2, 3));
+    }
+
+    #[test]
+    fn test_tied_weights_order_by_name() {
+        // "compute" and "0x1efcfc" both have two samples.
+        let profile =
+            TEST_PROFILE.replace(r#""stack": [2, 2, 3, null]"#, r#""stack": [2, 3, 2, 3]"#);
+        let query = |analyzer: &ProfileAnalyzer| {
+            serde_json::json!({
+                "hotspots": analyzer.compute_hotspots(10, None, true, true, TimeRange::default()),
+                "callees": analyzer.find_callees("work", 2, 10, TimeRange::default()),
+                "drilldown": analyzer.drilldown("main", 10, 5.0),
+                "threads": analyzer.compute_thread_hotspots(10, None, TimeRange::default()),
+            })
+        };
+
+        let analyzer = ProfileAnalyzer::from_json(&profile).unwrap();
+        let hotspots = analyzer.compute_hotspots(10, None, false, false, TimeRange::default());
+        assert_eq!(hotspots[0].function.name, "0x1efcfc");
+        assert_eq!(hotspots[1].function.name, "compute");
+        let callees = analyzer
+            .find_callees("work", 1, 10, TimeRange::default())
+            .callees;
+        assert_eq!(callees[0].name, "0x1efcfc");
+        assert_eq!(callees[1].name, "compute");
+        let drilldown = analyzer.drilldown("main", 10, 5.0);
+        assert_eq!(drilldown.path.last().unwrap().function, "0x1efcfc");
+
+        // Each analyzer's hash maps are seeded differently, so this fails if
+        // any ordering depends on hash order.
+        let expected = query(&analyzer);
+        for _ in 0..8 {
+            let analyzer = ProfileAnalyzer::from_json(&profile).unwrap();
+            assert_eq!(query(&analyzer), expected);
+        }
+    }
+
+    #[test]
+    fn test_processes() {
+        let mut profile: serde_json::Value = serde_json::from_str(TEST_PROFILE).unwrap();
+        let threads = profile["threads"].as_array_mut().unwrap();
+        threads[0]["processName"] = "app".into();
+        let mut worker = threads[0].clone();
+        worker["pid"] = "2".into();
+        worker["tid"] = "2".into();
+        worker["processName"] = "worker".into();
+        worker["samples"] = serde_json::json!({ "stack": [2, 2], "weight": [1, 1], "length": 2 });
+        threads.push(worker);
+        let analyzer = ProfileAnalyzer::from_json(&profile.to_string()).unwrap();
+
+        let response = analyzer.processes(5, TimeRange::default());
+        assert_eq!(response.total_samples, 6);
+        let pids: Vec<_> = response.processes.iter().map(|p| p.pid.as_str()).collect();
+        assert_eq!(pids, ["1", "2"]);
+        let worker = &response.processes[1];
+        assert_eq!(worker.name.as_deref(), Some("worker"));
+        assert_eq!(worker.samples, 2);
+        assert_eq!(worker.threads.len(), 1);
+        assert_eq!(worker.hotspots[0].function.name, "compute");
+        assert_eq!(worker.hotspots[0].self_percent, 100.0);
+
+        let summary = |analyzer: ProfileAnalyzer| analyzer.get_summary(TimeRange::default());
+        assert_eq!(summary(analyzer.for_process("work")).total_samples, 2);
+        assert_eq!(summary(analyzer.for_process("1")).total_samples, 4);
+        assert_eq!(summary(analyzer.for_process("3")).thread_count, 0);
+        assert_eq!(analyzer.thread_indexes("", Some("worker")), [1]);
+        let processes = ["3".to_string(), "work".to_string()];
+        let view = analyzer.for_threads(&[], &processes);
+        assert_eq!(summary(view).total_samples, 2);
+        let view = analyzer.for_threads(&["x".to_string()], &[]);
+        assert_eq!(summary(view).thread_count, 0);
+    }
+
+    #[test]
+    fn test_hardware_event_tracks() {
+        let mut profile: serde_json::Value = serde_json::from_str(TEST_PROFILE).unwrap();
+        let threads = profile["threads"].as_array_mut().unwrap();
+        let mut track = threads[0].clone();
+        track["name"] = event_track_name("main", "cache-misses").into();
+        track["samples"] =
+            serde_json::json!({ "stack": [2, 1], "weight": [300, 100], "length": 2 });
+        threads.insert(0, track);
+        let analyzer = ProfileAnalyzer::from_json(&profile.to_string()).unwrap();
+
+        // The track's samples aren't CPU samples.
+        assert_eq!(analyzer.get_summary(TimeRange::default()).total_samples, 4);
+        assert_eq!(analyzer.metrics(), ["cache-misses"]);
+        assert_eq!(analyzer.thread_indexes("main", None), [1]);
+        assert_eq!(analyzer.metric_thread_indexes("cache-misses", "main", None), [0]);
+        // "main" comes after its track in the profile.
+        assert_eq!(analyzer.profile_index(0), 1);
+
+        let cache_misses = analyzer.for_metric("cache-misses").unwrap();
+        let hotspots =
+            cache_misses.compute_hotspots(5, Some("main"), false, false, TimeRange::default());
+        assert_eq!(hotspots[0].function.name, "compute");
+        assert_eq!(hotspots[0].self_samples, 300);
+        assert_eq!(hotspots[0].self_percent, 75.0);
+        assert!(analyzer.for_metric("branch-misses").is_err());
+    }
+
+    #[test]
+    fn test_activity_split() {
+        let mut profile: serde_json::Value = serde_json::from_str(TEST_PROFILE).unwrap();
+        profile["threads"][0]["stringTable"][3] = "epoll_wait".into();
+        let mut analyzer = ProfileAnalyzer::from_json(&profile.to_string()).unwrap();
+
+        // compute -> running, epoll_wait -> idle, no stack -> running
+        let activity = &analyzer.get_summary(TimeRange::default()).threads[0].activity;
+        assert_eq!(activity.running_samples, 3);
+        assert_eq!(activity.idle_samples, 1);
+        assert_eq!(activity.blocked_samples, 0);
+        assert_eq!(activity.idle_percent, 25.0);
+
+        // "work" is the caller of every leaf, so it classifies every stack.
+        analyzer.set_activity_patterns(ActivityPatterns {
+            idle: Vec::new(),
+            blocked: vec!["work".to_string()],
+        });
+        let activity = analyzer.thread_activity(None, TimeRange::default());
+        assert_eq!(activity.blocked_samples, 3);
+        assert_eq!(activity.running_samples, 1);
+    }
+
+    #[test]
+    fn test_merged_threads() {
+        let mut profile: serde_json::Value = serde_json::from_str(TEST_PROFILE).unwrap();
+        let threads = profile["threads"].as_array_mut().unwrap();
+        for (tid, stacks) in [("2", [2, 3]), ("3", [2, 2])] {
+            let mut worker = threads[0].clone();
+            worker["name"] = format!("pool-{tid}").into();
+            worker["tid"] = tid.into();
+            worker["isMainThread"] = false.into();
+            worker["samples"] =
+                serde_json::json!({ "stack": stacks, "weight": [1, 1], "length": 2 });
+            threads.push(worker);
+        }
+        let analyzer = ProfileAnalyzer::from_json(&profile.to_string()).unwrap();
+        let merge: ThreadMerge = "^pool-".parse().unwrap();
+
+        let response = analyzer.compute_thread_hotspots(5, Some(&merge), TimeRange::default());
+        let names: Vec<_> = response.threads.iter().map(|t| t.name.as_str()).collect();
+        // Both have 4 samples, so they're ordered by name.
+        assert_eq!(names, ["^pool-", "main"]);
+        let pool = &response.threads[0];
+        assert_eq!(pool.samples, 4);
+        assert_eq!(pool.hotspots[0].function.name, "compute");
+        assert_eq!(pool.hotspots[0].self_samples, 3);
+        let tids: Vec<_> = pool.merged_threads.iter().map(|t| t.tid.as_str()).collect();
+        assert_eq!(tids, ["2", "3"]);
+
+        let view = analyzer.for_merged_threads(&merge);
+        assert_eq!(view.get_summary(TimeRange::default()).total_samples, 4);
+        let merged = analyzer.merged_threads(&merge, TimeRange::default());
+        assert_eq!(merged.threads[0].percent, 50.0);
+        assert!("(".parse::<ThreadMerge>().is_err());
+    }
+
+    #[test]
+    fn test_wait_graph() {
+        let mut profile: serde_json::Value = serde_json::from_str(TEST_PROFILE).unwrap();
+        let threads = profile["threads"].as_array_mut().unwrap();
+        for tid in ["2", "3", "4"] {
+            let mut worker = threads[0].clone();
+            worker["name"] = format!("worker-{tid}").into();
+            worker["tid"] = tid.into();
+            worker["isMainThread"] = false.into();
+            worker["stringTable"][2] = "futex_wait".into();
+            worker["samples"] = serde_json::json!({
+                "stack": [2, 2], "weight": [1, 1], "time": [1.0, 2.0], "length": 2
+            });
+            worker["markers"] = serde_json::json!({
+                "name": [0, 0, 0],
+                "startTime": [0.5, 1.5, 1.8],
+                "endTime": [null, null, null],
+                "data": [
+                    { "type": "Wakeup", "waker_tid": 1 },
+                    { "type": "Wakeup", "waker_tid": 1 },
+                    { "type": "Wakeup", "waker_tid": 99 }
+                ],
+                "length": 3
+            });
+            threads.push(worker);
+        }
+        let analyzer = ProfileAnalyzer::from_json(&profile.to_string()).unwrap();
+
+        let graph = analyzer.wait_graph(10, None, TimeRange::default());
+        assert_eq!(graph.total_wakeups, 9);
+        let edge = &graph.edges[0];
+        assert_eq!(
+            (edge.thread.as_str(), edge.waker_tid.as_str()),
+            ("worker-2", "1")
+        );
+        assert_eq!(edge.waker_thread.as_deref(), Some("main"));
+        assert_eq!(edge.wakeups, 2);
+        // The workers wait in futex_wait, called from work.
+        assert_eq!(edge.wait_sites[0].function, "work");
+        assert_eq!(graph.convoys.len(), 1);
+        assert_eq!(graph.convoys[0].waker_tid, "1");
+        assert_eq!(
+            graph.convoys[0].woken_threads,
+            ["worker-2", "worker-3", "worker-4"]
+        );
+
+        let empty = test_analyzer().wait_graph(10, None, TimeRange::default());
+        assert!(empty.note.is_some());
+    }
+
+    #[test]
+    fn test_signal_breakdown() {
+        let mut profile: serde_json::Value = serde_json::from_str(TEST_PROFILE).unwrap();
+        let thread = &mut profile["threads"][0];
+        // The leaf functions run in signal handlers.
+        thread["stringTable"][1] = "__restore_rt".into();
+        thread["samples"]["time"] = serde_json::json!([1.0, 2.0, 3.0, 4.0]);
+        let analyzer = ProfileAnalyzer::from_json(&profile.to_string()).unwrap();
+
+        let response = analyzer.signal_breakdown(5, None, TimeRange::default());
+        assert_eq!((response.total_samples, response.handler_samples), (4, 3));
+        assert_eq!(response.signals.len(), 1);
+        assert_eq!(response.signals[0].signal, "unknown");
+        assert!(response.note.is_some());
+
+        let thread = &mut profile["threads"][0];
+        let strings = thread["stringTable"].as_array_mut().unwrap();
+        strings.push("SIGPROF".into());
+        strings.push("SIGSEGV".into());
+        thread["markers"] = serde_json::json!({
+            "name": [6, 7],
+            "startTime": [0.5, 2.5],
+            "endTime": [null, null],
+            "data": [{ "type": "Signal", "signal": 27 }, { "type": "Signal", "signal": 11 }],
+            "length": 2
+        });
+        let analyzer = ProfileAnalyzer::from_json(&profile.to_string()).unwrap();
+
+        let response = analyzer.signal_breakdown(5, None, TimeRange::default());
+        assert!(response.note.is_none());
+        let signals: Vec<(&str, usize, i64)> = response
+            .signals
+            .iter()
+            .map(|s| (s.signal.as_str(), s.deliveries, s.handler_samples))
+            .collect();
+        assert_eq!(signals, [("SIGPROF", 1, 2), ("SIGSEGV", 1, 1)]);
+        assert_eq!(response.signals[0].handlers[0].name, "compute");
+        assert_eq!(response.signals[1].handlers[0].name, "0x1efcfc");
+    }
+
+    #[test]
+    fn test_crash_summary() {
+        let response = test_analyzer().crash_summary(10);
+        assert!(response.thread.is_none());
+        assert!(response.note.is_some());
+
+        let mut profile: serde_json::Value = serde_json::from_str(TEST_PROFILE).unwrap();
+        let thread = &mut profile["threads"][0];
+        thread["stringTable"]
+            .as_array_mut()
+            .unwrap()
+            .extend(["Crash".into(), "SIGSEGV".into()]);
+        thread["markers"] = serde_json::json!({
+            "name": [6],
+            "startTime": [0.0],
+            "endTime": [null],
+            "data": [{ "type": "Crash", "exception": 7, "address": "0x0", "code": 11 }],
+            "length": 1
+        });
+        let analyzer = ProfileAnalyzer::from_json(&profile.to_string()).unwrap();
+
+        // Without sample times, the crashing stack is the last sample's.
+        let response = analyzer.crash_summary(2);
+        assert_eq!(response.thread.as_deref(), Some("main"));
+        assert_eq!(response.exception.as_deref(), Some("SIGSEGV"));
+        assert_eq!(response.address.as_deref(), Some("0x0"));
+        assert!(response.faulting_frame.is_none());
+
+        profile["threads"][0]["samples"]["stack"] = serde_json::json!([2, 2, 3, 2]);
+        let analyzer = ProfileAnalyzer::from_json(&profile.to_string()).unwrap();
+        let response = analyzer.crash_summary(2);
+        let faulting_frame = response.faulting_frame.unwrap();
+        assert_eq!(faulting_frame.function, "compute");
+        assert_eq!(faulting_frame.library.as_deref(), Some("app"));
+        let stack: Vec<&str> = response.stack.iter().map(|f| f.function.as_str()).collect();
+        assert_eq!(stack, ["compute", "work"]);
+        assert_eq!(response.stack_depth, 3);
+    }
+
+    #[test]
+    fn test_check_budgets() {
+        let budgets: Budgets = serde_json::from_value(serde_json::json!({
+            "budget": [
+                { "function": "compute", "match": "exact", "self_percent": 40.0 },
+                { "name": "libc", "library": "libc.so.6", "total_ms": 2.0 },
+                { "function": "work", "total_percent": 80.0, "self_ms": 0.0 }
+            ]
+        }))
+        .unwrap();
+        let response = test_analyzer()
+            .check_budgets(&budgets, TimeRange::default())
+            .unwrap();
+        assert!(!response.passed);
+        assert_eq!(response.failed, 1);
+        let results: Vec<(&str, bool, i64, i64)> = response
+            .budgets
+            .iter()
+            .map(|b| (b.name.as_str(), b.passed, b.self_samples, b.total_samples))
+            .collect();
+        assert_eq!(
+            results,
+            [
+                ("compute", false, 2, 2),
+                ("libc", true, 1, 1),
+                ("work", true, 0, 3)
+            ]
+        );
+        assert_eq!(response.budgets[0].checks[0].actual, 50.0);
+        assert_eq!(response.budgets[2].checks[0].actual, 75.0);
+
+        let budgets: Budgets =
+            serde_json::from_value(serde_json::json!({ "budget": [{ "function": "work" }] }))
+                .unwrap();
+        assert!(test_analyzer()
+            .check_budgets(&budgets, TimeRange::default())
+            .is_err());
+    }
+
+    #[test]
+    fn test_compute_growth() {
+        let profile = TEST_PROFILE.replace(
+            r#""weight": [1, 1, 1, 1], "length": 4"#,
+            r#""weight": [1, 1, 1, 1], "timeDeltas": [1.0, 1.0, 1.0, 1.0], "length": 4"#,
+        );
+        let analyzer = ProfileAnalyzer::from_json(&profile).unwrap();
+
+        // Halves: [1, 2.5) has the samples in compute, [2.5, 4] the one in 0x1efcfc.
+        let response = analyzer.compute_growth(10, 2, None, TimeRange::default());
+        let samples: Vec<i64> = response.windows.iter().map(|w| w.samples).collect();
+        assert_eq!(samples, [2, 2]);
+        let by_self: Vec<(&str, f64)> = response
+            .by_self
+            .iter()
+            .map(|e| (e.function.as_str(), e.self_growth))
+            .collect();
+        assert_eq!(by_self, [("0x1efcfc", 50.0)]);
+        assert_eq!(response.by_total[0].total_percent, [0.0, 50.0]);
+        assert!(response.by_total[0].steady);
+        assert!(response.note.is_none());
+
+        let response = test_analyzer().compute_growth(10, 2, None, TimeRange::default());
+        assert!(response.windows.is_empty());
+        assert!(response.note.is_some());
+    }
+
+    #[test]
+    fn test_allocation_sites() {
+        let mut profile: serde_json::Value = serde_json::from_str(TEST_PROFILE).unwrap();
+        let response = test_analyzer().allocation_sites(5, None, TimeRange::default());
+        assert_eq!(response.total_count, 0);
+        assert!(response.note.is_some());
+
+        let thread = &mut profile["threads"][0];
+        // "compute" is the allocator, so its allocations are attributed to "work".
+        thread["stringTable"][2] = "malloc".into();
+        thread["nativeAllocations"] = serde_json::json!({
+            "stack": [2, 2, 3, 1, 2],
+            "weight": [100, 50, 200, 10, -40],
+            "time": [1.0, 2.0, 3.0, 4.0, 5.0],
+            "length": 5
+        });
+        let analyzer = ProfileAnalyzer::from_json(&profile.to_string()).unwrap();
+
+        let response = analyzer.allocation_sites(5, None, TimeRange::default());
+        assert_eq!((response.total_bytes, response.total_count), (360, 4));
+        assert_eq!(response.freed_bytes, Some(40));
+        assert!(response.note.is_none());
+        let by_bytes: Vec<(&str, i64)> = response
+            .by_bytes
+            .iter()
+            .map(|s| (s.function.as_str(), s.bytes))
+            .collect();
+        assert_eq!(by_bytes, [("0x1efcfc", 200), ("work", 160)]);
+        let by_count: Vec<(&str, usize)> = response
+            .by_count
+            .iter()
+            .map(|s| (s.function.as_str(), s.count))
+            .collect();
+        assert_eq!(by_count, [("work", 3), ("0x1efcfc", 1)]);
+
+        let range = TimeRange {
+            start_ms: Some(2.5),
+            end_ms: None,
+        };
+        let response = analyzer.allocation_sites(1, None, range);
+        assert_eq!((response.total_bytes, response.total_count), (210, 2));
+        assert_eq!(response.by_count.len(), 1);
+        assert_eq!(response.by_count[0].function, "0x1efcfc");
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let analyzer = test_analyzer();
+        assert!(analyzer.check_limits(&ParseLimits::SANDBOX).is_ok());
+        let few_samples = ParseLimits {
+            max_samples: 3,
+            ..ParseLimits::SANDBOX
+        };
+        assert!(matches!(
+            analyzer.check_limits(&few_samples),
+            Err(AnalysisError::InvalidProfile(_))
+        ));
+        let no_threads = ParseLimits {
+            max_threads: 0,
+            ..ParseLimits::SANDBOX
+        };
+        assert!(analyzer.check_limits(&no_threads).is_err());
+    }
+
+    #[test]
+    fn test_compute_diff() {
+        let current = test_analyzer();
+        // In the baseline, all samples are in "work" itself.
+        let baseline = ProfileAnalyzer::from_json(&TEST_PROFILE.replace(
+            r#""stack": [2, 2, 3, null]"#,
+            r#""stack": [1, 1, 1, null]"#,
+        ))
+        .unwrap();
+
+        let diff = current.compute_diff(&baseline, 10);
+        assert_eq!(diff.total_samples, 4);
+        assert_eq!(diff.baseline_total_samples, 4);
+
+        assert_eq!(diff.functions[0].function, "work");
+        assert_eq!(diff.functions[0].status, DiffStatus::Changed);
+        assert_eq!(diff.functions[0].self_percent_delta, -75.0);
+        assert_eq!(diff.functions[0].self_change_percent, Some(-100.0));
+        assert_eq!(diff.functions[0].total_percent_delta, 0.0);
+
+        assert_eq!(diff.new_hotspots, vec!["compute", "0x1efcfc"]);
+        assert!(diff.removed_hotspots.is_empty());
+    }
+
+    #[test]
+    fn test_compute_diff_renamed() {
+        let build = |work: &str, compute: &str, stacks: serde_json::Value| {
+            let mut profile: serde_json::Value = serde_json::from_str(TEST_PROFILE).unwrap();
+            let thread = &mut profile["threads"][0];
+            thread["samples"]["stack"] = stacks;
+            thread["stringTable"][1] = work.into();
+            thread["stringTable"][2] = compute.into();
+            thread["stringTable"]
+                .as_array_mut()
+                .unwrap()
+                .push("src/app.rs".into());
+            thread["funcTable"]["fileName"] = serde_json::json!([6, 6, 6, null]);
+            thread["funcTable"]["lineNumber"] = serde_json::json!([1, 10, 20, null]);
+            ProfileAnalyzer::from_json(&profile.to_string()).unwrap()
+        };
+        let current = build(
+            "do_work",
+            "compute<u32>",
+            serde_json::json!([2, 2, 3, null]),
+        );
+        let baseline = build("work", "compute<u64>", serde_json::json!([2, 2, 2, null]));
+
+        let diff = current.compute_diff(&baseline, 10);
+        assert_eq!(diff.renamed.len(), 2);
+        assert_eq!(diff.renamed[0].function, "compute<u32>");
+        assert_eq!(diff.renamed[0].baseline_function, "compute<u64>");
+        assert_eq!(diff.renamed[0].confidence, 1.0);
+        assert_eq!(diff.renamed[1].function, "do_work");
+        assert_eq!(diff.renamed[1].baseline_function, "work");
+        assert_eq!(diff.renamed[1].reasons, vec!["source_location", "callers"]);
+
+        // A renamed function is compared with its baseline, not added and removed.
+        assert_eq!(diff.functions.len(), 2);
+        let compute = diff
+            .functions
+            .iter()
+            .find(|f| f.function == "compute<u32>")
+            .unwrap();
+        assert_eq!(compute.status, DiffStatus::Renamed);
+        assert_eq!(compute.baseline_function.as_deref(), Some("compute<u64>"));
+        assert_eq!(compute.self_percent_delta, -25.0);
+        assert_eq!(diff.new_hotspots, vec!["0x1efcfc"]);
+        assert!(diff.removed_hotspots.is_empty());
+    }
+
+    #[test]
+    fn test_explain_diff() {
+        let current = test_analyzer();
+        let baseline = ProfileAnalyzer::from_json(&TEST_PROFILE.replace(
+            r#""stack": [2, 2, 3, null]"#,
+            r#""stack": [1, 1, 1, null]"#,
+        ))
+        .unwrap();
+
+        let explain = current.explain_diff(&baseline, 5);
+        assert_eq!(explain.regression_percent, 75.0);
+        assert_eq!(explain.improvement_percent, 75.0);
+
+        // "main" explains the same regression, but "work" is more specific.
+        assert_eq!(explain.clusters.len(), 1);
+        let cluster = &explain.clusters[0];
+        assert_eq!(cluster.function, "work");
+        assert_eq!(cluster.call_path, vec!["main", "work"]);
+        assert_eq!(cluster.share_percent, 100.0);
+        let drivers: Vec<_> = cluster
+            .drivers
+            .iter()
+            .map(|d| (d.function.as_str(), d.status))
+            .collect();
+        assert_eq!(
+            drivers,
+            vec![("compute", DiffStatus::New), ("0x1efcfc", DiffStatus::New)]
+        );
+
+        assert_eq!(explain.modules[0].library, "app");
+        assert_eq!(
+            explain.narrative[1],
+            "100% of the regression (+75.0 points) is under work, driven by new calls to compute and new calls to 0x1efcfc."
+        );
+    }
+
+    #[test]
+    fn test_leaf_module_hotspots() {
+        let analyzer = test_analyzer();
+        let response = analyzer.leaf_module_hotspots(10, None, &[], TimeRange::default());
+        assert_eq!(response.total_samples, 4);
+        assert_eq!(response.own_libraries, ["app"]);
+        let modules: Vec<(&str, bool, i64)> = response
+            .modules
+            .iter()
+            .map(|m| (m.library.as_str(), m.is_own, m.self_samples))
+            .collect();
+        assert_eq!(modules, [("app", true, 2), ("libc.so.6", false, 1)]);
+        assert_eq!(response.boundaries.len(), 1);
+        assert_eq!(response.boundaries[0].function, "work");
+        assert_eq!(response.boundaries[0].library, "app");
+        assert_eq!(response.boundaries[0].leaf_library, "libc.so.6");
+        assert_eq!(response.boundaries[0].percent, 25.0);
+
+        // With only libc as the program's own code, app's leaf samples have
+        // no own frame beneath them.
+        let response = analyzer.leaf_module_hotspots(
+            10,
+            None,
+            &["libc.so.6".to_string()],
+            TimeRange::default(),
+        );
+        assert!(response.boundaries.is_empty());
+        assert_eq!(response.unattributed_samples, 2);
+    }
+
+    #[test]
+    fn test_language_frames() {
+        let profile = TEST_PROFILE.replace(
+            r#"["main", "work", "compute""#,
+            r#"["main", "JS:*work app.js:1", "JS:*compute app.js:2""#,
+        );
+        let raw: RawProfile = serde_json::from_str(&profile).unwrap();
+        let analyzer = ProfileAnalyzer::from_raw_profile(raw).unwrap();
+
+        let response = analyzer.language_hotspots(10, None, TimeRange::default());
+        assert_eq!(response.total_samples, 4);
+        let languages: Vec<(&str, i64, i64)> = response
+            .languages
+            .iter()
+            .map(|l| (l.language.as_str(), l.self_samples, l.total_samples))
+            .collect();
+        assert_eq!(languages, [("JavaScript", 2, 3), (NATIVE, 1, 3)]);
+        let function = &response.languages[0].functions[0];
+        assert_eq!(function.name, "JS:*compute app.js:2");
+
+        let view = analyzer.with_language_frames();
+        let export = view
+            .export(ExportFormat::Collapsed, None, TimeRange::default())
+            .unwrap();
+        assert_eq!(
+            export.output,
+            "main;[JavaScript];JS:*work app.js:1;0x1efcfc 1\n\
+             main;[JavaScript];JS:*work app.js:1;JS:*compute app.js:2 2\n"
+        );
+        // The pseudo-frames don't count as native code, nor get nested.
+        let response = view.language_hotspots(10, None, TimeRange::default());
+        assert_eq!(response.languages[0].total_samples, 3);
+        let twice = view.with_language_frames();
+        let export_twice = twice
+            .export(ExportFormat::Collapsed, None, TimeRange::default())
+            .unwrap();
+        assert_eq!(export_twice.output, export.output);
+    }
+
+    #[test]
+    fn test_hotspot_context() {
+        let analyzer = test_analyzer();
+        let mut hotspots = analyzer.compute_hotspots(10, None, false, false, TimeRange::default());
+        analyzer.add_hotspot_context(&mut hotspots, None, TimeRange::default());
+        let context: Vec<(&str, Option<&str>)> = hotspots
+            .iter()
+            .map(|h| (h.function.name.as_str(), h.context.as_deref()))
+            .collect();
+        assert_eq!(
+            context,
+            [
+                ("compute", Some("main → work → compute")),
+                ("0x1efcfc", Some("main → work → 0x1efcfc")),
+                ("main", None),
+                ("work", None),
+            ]
+        );
+
+        let path: Vec<String> = (1..=10).map(|i| format!("f{i}")).collect();
+        assert_eq!(
+            hotspot_context::shorten_path(&path),
+            "f1 → f2 → … → f8 → f9 → f10 (5 frames elided)"
+        );
+    }
+
+    #[test]
+    fn test_export_collapsed() {
+        let export = test_analyzer()
+            .export(ExportFormat::Collapsed, None, TimeRange::default())
+            .unwrap();
+        assert_eq!(export.output, "main;work;0x1efcfc 1\nmain;work;compute 2\n");
+        assert!(test_analyzer()
+            .export(ExportFormat::Pprof, None, TimeRange::default())
+            .is_err());
+    }
+
+    #[test]
+    fn test_export_speedscope() {
+        let export = test_analyzer()
+            .export(ExportFormat::Speedscope, None, TimeRange::default())
+            .unwrap();
+        let file: serde_json::Value = serde_json::from_str(&export.output).unwrap();
+
+        let frames: Vec<&str> = file["shared"]["frames"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|frame| frame["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(frames, ["main", "work", "compute", "0x1efcfc"]);
+        let profile = &file["profiles"][0];
+        assert_eq!(profile["type"], "sampled");
+        assert_eq!(
+            profile["samples"],
+            serde_json::json!([[0, 1, 2], [0, 1, 2], [0, 1, 3]])
+        );
+        assert_eq!(profile["endValue"], 3.0);
+    }
+
+    #[test]
+    fn test_export_callgrind() {
+        let export = test_analyzer()
+            .export(ExportFormat::Callgrind, None, TimeRange::default())
+            .unwrap();
+        assert!(export.output.starts_with("# callgrind format\n"));
+        assert!(export.output.contains("events: Samples\nsummary: 3\n"));
+        assert!(export
+            .output
+            .contains("fn=(1) main\ncfi=(1)\ncfn=(2) work\ncalls=3 0\n0 3\n"));
+        // "work" was named in main's call to it, so only its id is written.
+        assert!(export
+            .output
+            .contains("fn=(2)\ncfi=(1)\ncfn=(3) compute\ncalls=2 0\n0 2\n"));
+        assert!(export.output.contains("fn=(3)\n0 2\n"));
+    }
+
+    #[test]
+    fn test_export_pprof() {
+        let pprof = test_analyzer().export_bytes(ExportFormat::Pprof, None, TimeRange::default());
+
+        // Split the top-level Profile message into (field number, payload) pairs.
+        fn varint(bytes: &[u8], pos: &mut usize) -> u64 {
+            let mut value = 0;
+            let mut shift = 0;
+            loop {
+                let byte = bytes[*pos];
+                *pos += 1;
+                value |= u64::from(byte & 0x7f) << shift;
+                shift += 7;
+                if byte < 0x80 {
+                    return value;
+                }
+            }
+        }
+        let mut fields: Vec<(u64, Vec<u8>)> = Vec::new();
+        let mut pos = 0;
+        while pos < pprof.len() {
+            let key = varint(&pprof, &mut pos);
+            if key & 7 == 0 {
+                varint(&pprof, &mut pos);
+                fields.push((key >> 3, Vec::new()));
+            } else {
+                let len = varint(&pprof, &mut pos) as usize;
+                fields.push((key >> 3, pprof[pos..pos + len].to_vec()));
+                pos += len;
+            }
+        }
+        let count = |field| fields.iter().filter(|(f, _)| *f == field).count();
+        let strings: Vec<String> = fields
+            .iter()
+            .filter(|(f, _)| *f == 6)
+            .map(|(_, s)| String::from_utf8(s.clone()).unwrap())
+            .collect();
+
+        assert_eq!(count(1), 2, "sample types");
+        assert_eq!(count(2), 2, "one sample per distinct stack");
+        assert_eq!(count(5), 4, "main, work, compute, 0x1efcfc");
+        assert_eq!(strings[0], "");
+        for name in ["samples", "cpu", "nanoseconds", "thread", "main", "compute"] {
+            assert!(strings.iter().any(|s| s == name), "{name} in string table");
+        }
+    }
+
+    #[test]
+    fn test_stack_stats() {
+        let profile = TEST_PROFILE
+            .replace(
+                r#""startTime": 0 }"#,
+                r#""startTime": 0, "categories": [{ "name": "User" }, { "name": "Kernel" }] }"#,
+            )
+            .replace(
+                r#""address": [-1, 4096, 8192, 12288],"#,
+                r#""address": [-1, 4096, 8192, 12288], "category": [0, 0, 0, 1],"#,
+            );
+        let stats = ProfileAnalyzer::from_json(&profile)
+            .unwrap()
+            .stack_stats(None, TimeRange::default());
+
+        let overall = &stats.overall;
+        assert_eq!(overall.samples, 3);
+        assert_eq!(overall.unique_stacks, 2);
+        assert_eq!((overall.depth.p50, overall.depth.max), (3, 3));
+        assert_eq!(overall.depth_histogram.len(), 1);
+        assert_eq!(overall.depth_histogram[0].min_depth, 2);
+        // main -> work -> {compute, 0x1efcfc}
+        assert_eq!(overall.call_tree_nodes, 4);
+        assert_eq!(overall.avg_branching_factor, Some(1.5));
+        // 0x1efcfc is the only kernel frame.
+        assert_eq!((overall.kernel_frames, overall.user_frames), (1, 8));
+        assert_eq!(stats.threads[0].name, "main");
+    }
+
+    #[test]
+    fn test_category_breakdown() {
+        // Only main and 0x1efcfc have a category; compute takes main's.
+        let profile = TEST_PROFILE
+            .replace(
+                r#""startTime": 0 }"#,
+                r#""startTime": 0, "categories": [{ "name": "User" }, { "name": "Kernel" }] }"#,
+            )
+            .replace(
+                r#""address": [-1, 4096, 8192, 12288],"#,
+                r#""address": [-1, 4096, 8192, 12288], "category": [0, null, null, 1],"#,
+            );
+        let analyzer = ProfileAnalyzer::from_json(&profile).unwrap();
+
+        let response = analyzer.category_breakdown(5, None, TimeRange::default());
+        assert_eq!(response.total_samples, 4);
+        assert_eq!(response.uncategorized_samples, 1);
+        let user = &response.categories[0];
+        assert_eq!((user.name.as_str(), user.samples, user.percent), ("User", 2, 50.0));
+        assert_eq!(user.functions[0].name, "compute");
+        assert_eq!(user.functions[0].percent, 100.0);
+        let kernel = &response.categories[1];
+        assert_eq!((kernel.name.as_str(), kernel.samples), ("Kernel", 1));
+        assert_eq!(kernel.functions[0].name, "0x1efcfc");
+
+        let hotspots = analyzer.compute_hotspots(10, None, false, false, TimeRange::default());
+        let category = |name: &str| {
+            let hotspot = hotspots.iter().find(|h| h.function.name == name).unwrap();
+            hotspot.category.clone()
+        };
+        assert_eq!(category("compute").as_deref(), Some("User"));
+        assert_eq!(category("0x1efcfc").as_deref(), Some("Kernel"));
+        // Functions without self samples have no category.
+        assert_eq!(category("main"), None);
+    }
+
+    #[test]
+    fn test_build_flamegraph() {
+        let analyzer = test_analyzer();
+
+        let flamegraph = analyzer.build_flamegraph(None, false, 0.0);
+        let root = &flamegraph.root;
+        assert_eq!(root.value, 4);
+        assert_eq!(root.children.len(), 1);
+        let main = &root.children[0];
+        assert_eq!((main.name.as_str(), main.value), ("main", 3));
+        let work = &main.children[0];
+        assert_eq!((work.name.as_str(), work.value), ("work", 3));
+        let children: Vec<(&str, i64)> = work
+            .children
+            .iter()
+            .map(|c| (c.name.as_str(), c.value))
+            .collect();
+        assert_eq!(children, vec![("compute", 2), ("0x1efcfc", 1)]);
+
+        let inverted = analyzer.build_flamegraph(None, true, 0.0);
+        assert_eq!(inverted.root.children[0].name, "compute");
+        assert_eq!(inverted.root.children[0].children[0].name, "work");
+
+        let pruned = analyzer.build_flamegraph(None, false, 50.0);
+        let work = &pruned.root.children[0].children[0];
+        assert_eq!(work.children.len(), 1);
+        assert_eq!(work.children[0].name, "compute");
+    }
+
+    #[test]
+    fn test_time_range_filtering() {
+        let profile = TEST_PROFILE.replace(
+            r#""weight": [1, 1, 1, 1], "length": 4"#,
+            r#""weight": [1, 1, 1, 1], "timeDeltas": [1.0, 1.0, 1.0, 1.0], "length": 4"#,
+        );
+        let raw: RawProfile = serde_json::from_str(&profile).unwrap();
+        let analyzer = ProfileAnalyzer::from_raw_profile(raw).unwrap();
+
+        let summary = analyzer.get_summary(TimeRange::default());
+        assert_eq!(summary.total_samples, 4);
+        assert_eq!(summary.first_sample_ms, Some(1.0));
+        assert_eq!(summary.last_sample_ms, Some(4.0));
+
+        let late = TimeRange {
+            start_ms: Some(2.5),
+            end_ms: None,
+        };
+        assert_eq!(analyzer.get_summary(late).total_samples, 2);
+        assert_eq!(analyzer.get_summary(late).threads[0].sample_count, 2);
+
+        let early = TimeRange {
+            start_ms: None,
+            end_ms: Some(2.5),
+        };
+        let hotspots = analyzer.compute_hotspots(10, None, false, false, early);
+        assert!(!hotspots.iter().any(|h| h.function.name == "0x1efcfc"));
+        assert_eq!(hotspots[0].function.name, "compute");
+        assert_eq!(hotspots[0].self_samples, 2);
+    }
+
+    #[test]
+    fn test_summary_recording_overhead() {
+        assert!(test_analyzer().get_summary(TimeRange::default()).overhead.is_none());
+
+        let profile = TEST_PROFILE.replace(
+            r#""startTime": 0 }"#,
+            r#""startTime": 0, "recordingOverhead": { "durationMs": 1000.0, "cpuTimeMs": 21.0, "cpuPercentOfOneCore": 2.1, "lostEvents": 0 } }"#,
+        );
+        let raw: RawProfile = serde_json::from_str(&profile).unwrap();
+        let summary = ProfileAnalyzer::from_raw_profile(raw)
+            .unwrap()
+            .get_summary(TimeRange::default());
+        let overhead = summary.overhead.unwrap();
+        assert_eq!(overhead.lost_events, 0);
+        assert_eq!(
+            summary.overhead_summary.as_deref(),
+            Some("samply overhead: 2.1% of one core, 0 lost events")
+        );
+    }
+
+    #[test]
+    fn test_analysis_error_display() {
+        let err = AnalysisError::InvalidProfile("test".to_string());
+        assert!(err.to_string().contains("test"));
+    }
+
+    #[test]
+    fn test_library_symbolication_status() {
+        let libs = test_analyzer().library_symbolication_status();
+        assert_eq!(libs.len(), 2);
+
+        assert_eq!(libs[0].library, "app");
+        assert_eq!(libs[0].state, SymbolicationState::Resolved);
+        assert_eq!(libs[0].source, Some(SymbolSource::Profile));
+        assert_eq!(libs[0].resolved_functions, 3);
+        assert_eq!(libs[0].self_samples, 2);
+
+        assert_eq!(libs[1].library, "libc.so.6");
+        assert_eq!(libs[1].state, SymbolicationState::Pending);
+        assert_eq!(libs[1].source, None);
+        assert_eq!(libs[1].unresolved_functions, 1);
+        assert_eq!(libs[1].self_samples, 1);
+    }
+
+    #[test]
+    fn test_get_source_without_file() {
+        let source = test_analyzer().get_source("work", 3, None);
+        assert_eq!(source.total_samples, 3);
+        assert_eq!(source.self_samples, 0);
+        assert!(source.error.is_some());
+        // Only the sampled line is reported when the file can't be read.
+        assert_eq!(source.lines.len(), 1);
+        assert_eq!(source.lines[0].line, 10);
+        assert_eq!(source.lines[0].total_samples, 3);
+
+        let source = test_analyzer().get_source("compute", 3, None);
+        assert_eq!(source.self_samples, 2);
+        assert_eq!(source.lines[0].line, 20);
+        assert_eq!(source.lines[0].self_samples, 2);
+    }
+
+    #[test]
+    fn test_get_source_hash() {
+        let path =
+            std::env::temp_dir().join(format!("samply-source-test-{}.rs", std::process::id()));
+        let text: String = (1..=12).map(|line| format!("// line {line}\n")).collect();
+        std::fs::write(&path, &text).unwrap();
+        let mut profile: serde_json::Value = serde_json::from_str(TEST_PROFILE).unwrap();
+        let thread = &mut profile["threads"][0];
+        thread["funcTable"]["fileName"] = serde_json::json!([null, 6, null, null]);
+        let strings = thread["stringTable"].as_array_mut().unwrap();
+        strings.push(path.to_str().unwrap().into());
+        let analyzer = ProfileAnalyzer::from_json(&profile.to_string()).unwrap();
+
+        let source = analyzer.get_source("work", 1, None);
+        let hash = source.source_hash.unwrap();
+        assert_eq!(hash.local, md5::to_hex(&md5::md5(text.as_bytes())));
+        assert_eq!(hash.matches, None);
+        assert!(source.warning.is_none());
+        assert_eq!(source.lines[1].text.as_deref(), Some("// line 10"));
+
+        let built = AsmSymbolInfo {
+            file_md5: Some(md5::md5(text.as_bytes())),
+            ..Default::default()
+        };
+        let source = analyzer.get_source("work", 1, Some(&built));
+        assert_eq!(source.source_hash.unwrap().matches, Some(true));
+        assert!(source.warning.is_none());
+
+        let edited = AsmSymbolInfo {
+            file_md5: Some(md5::md5(b"fn work() {}\n")),
+            ..Default::default()
+        };
+        let source = analyzer.get_source("work", 1, Some(&edited));
+        assert_eq!(source.source_hash.unwrap().matches, Some(false));
+        assert!(source.warning.is_some());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
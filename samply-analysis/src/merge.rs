@@ -0,0 +1,110 @@
+//! Merging the threads of a worker pool into one logical thread.
+//!
+//! Pool-based runtimes (rayon, tokio, thread pools) spread the same work over
+//! many threads, so that each thread's share looks small. Threads whose name
+//! matches a regex are treated as one: queries of the view from
+//! [`ProfileAnalyzer::for_merged_threads`] aggregate them, the threads query
+//! lists them as a single entry, and [`MergedThreads`] breaks the total down
+//! per thread.
+
+use std::fmt;
+use std::str::FromStr;
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{ProfileAnalyzer, ThreadData, TimeRange};
+
+/// The threads to merge, as a regex which matches their names
+#[derive(Debug, Clone)]
+pub struct ThreadMerge {
+    pattern: Regex,
+}
+
+impl ThreadMerge {
+    pub fn as_str(&self) -> &str {
+        self.pattern.as_str()
+    }
+
+    pub(crate) fn matches(&self, thread: &ThreadData) -> bool {
+        self.pattern.is_match(&thread.name)
+    }
+}
+
+impl fmt::Display for ThreadMerge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ThreadMerge {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Regex::new(s)
+            .map(|pattern| ThreadMerge { pattern })
+            .map_err(|e| format!("Invalid merge_threads regex '{s}': {e}"))
+    }
+}
+
+/// One thread's contribution to merged threads
+#[derive(Debug, Clone, Serialize)]
+pub struct MergedThread {
+    pub name: String,
+    pub tid: String,
+    pub samples: i64,
+    /// Share of the merged threads' samples
+    pub percent: f64,
+}
+
+/// The threads merged into one logical thread
+#[derive(Debug, Clone, Serialize)]
+pub struct MergedThreads {
+    /// The regex which matches the merged threads' names
+    pub pattern: String,
+    pub samples: i64,
+    /// By samples, largest first
+    pub threads: Vec<MergedThread>,
+}
+
+impl ProfileAnalyzer {
+    /// The threads which `merge` matches, with their samples in `range`
+    pub fn merged_threads(&self, merge: &ThreadMerge, range: TimeRange) -> MergedThreads {
+        let mut threads: Vec<MergedThread> = self
+            .threads
+            .iter()
+            .filter(|thread| merge.matches(thread))
+            .map(|thread| MergedThread {
+                name: thread.name.clone(),
+                tid: thread.tid.clone(),
+                samples: thread.samples_in(range).map(|(_, weight)| weight).sum(),
+                percent: 0.0,
+            })
+            .collect();
+        let samples: i64 = threads.iter().map(|t| t.samples).sum();
+        for thread in &mut threads {
+            if samples > 0 {
+                thread.percent = 100.0 * thread.samples as f64 / samples as f64;
+            }
+        }
+        threads.sort_by(|a, b| {
+            b.samples
+                .cmp(&a.samples)
+                .then_with(|| a.name.cmp(&b.name))
+                .then_with(|| a.tid.cmp(&b.tid))
+        });
+
+        MergedThreads {
+            pattern: merge.to_string(),
+            samples,
+            threads,
+        }
+    }
+
+    /// A view of the profile with only the threads which `merge` matches, so
+    /// that every query of the view aggregates them like one thread. The
+    /// threads' data is shared, not copied.
+    pub fn for_merged_threads(&self, merge: &ThreadMerge) -> ProfileAnalyzer {
+        self.with_threads(|thread| merge.matches(thread))
+    }
+}
@@ -0,0 +1,229 @@
+//! Analysis summaries as OpenTelemetry metrics and logs, in the JSON encoding
+//! of OTLP/HTTP (<https://opentelemetry.io/docs/specs/otlp/#json-protobuf-encoding>),
+//! for sending to an OpenTelemetry collector.
+//!
+//! Every value is a gauge observed at the time of the export: the profile's
+//! sample counts and duration, the samples of each thread, and the self and
+//! total percentages of the hotspots. One log record summarizes the profile
+//! in a sentence.
+
+use serde_json::{json, Value};
+
+use crate::{ProfileAnalyzer, TimeRange};
+
+/// The bodies of the requests to a collector's `/v1/metrics` and `/v1/logs`
+#[derive(Debug, Clone)]
+pub struct OtlpExport {
+    /// An `ExportMetricsServiceRequest`
+    pub metrics: Value,
+    /// An `ExportLogsServiceRequest`
+    pub logs: Value,
+}
+
+/// A `KeyValue` with a string value
+fn string_attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+/// A `KeyValue` with an integer value; 64-bit integers are strings in OTLP JSON
+fn int_attribute(key: &str, value: i64) -> Value {
+    json!({ "key": key, "value": { "intValue": value.to_string() } })
+}
+
+/// A `NumberDataPoint` with an integer value
+fn int_data_point(time_unix_nano: &str, value: i64, attributes: Vec<Value>) -> Value {
+    json!({
+        "timeUnixNano": time_unix_nano,
+        "asInt": value.to_string(),
+        "attributes": attributes
+    })
+}
+
+/// A `NumberDataPoint` with a floating-point value
+fn double_data_point(time_unix_nano: &str, value: f64, attributes: Vec<Value>) -> Value {
+    json!({
+        "timeUnixNano": time_unix_nano,
+        "asDouble": value,
+        "attributes": attributes
+    })
+}
+
+/// A gauge `Metric` with the given data points
+fn gauge(name: &str, unit: &str, description: &str, data_points: Vec<Value>) -> Value {
+    json!({
+        "name": name,
+        "unit": unit,
+        "description": description,
+        "gauge": { "dataPoints": data_points }
+    })
+}
+
+impl ProfileAnalyzer {
+    /// The summary and the `limit` top hotspots of the threads whose name
+    /// contains `thread_filter` in `range`, as OTLP metrics and logs of the
+    /// service `service_name` (by default the profile's product name),
+    /// observed at `time_unix_nano`.
+    pub fn otlp_export(
+        &self,
+        service_name: Option<&str>,
+        limit: usize,
+        thread_filter: Option<&str>,
+        range: TimeRange,
+        time_unix_nano: u64,
+    ) -> OtlpExport {
+        let time = time_unix_nano.to_string();
+        let int_point = |value: i64, attributes| int_data_point(&time, value, attributes);
+        let double_point = |value: f64, attributes| double_data_point(&time, value, attributes);
+
+        let mut total_samples = 0;
+        let mut thread_points = Vec::new();
+        for thread in &self.threads {
+            if !thread.matches_filter(thread_filter) {
+                continue;
+            }
+            let samples: i64 = thread.samples_in(range).map(|(_, weight)| weight).sum();
+            total_samples += samples;
+            thread_points.push(int_point(
+                samples,
+                vec![
+                    string_attribute("thread.name", &thread.name),
+                    string_attribute("thread.id", &thread.tid),
+                    string_attribute("process.pid", &thread.pid),
+                ],
+            ));
+        }
+
+        let summary = self.get_summary(range);
+        let duration_ms = match (range.start_ms, range.end_ms) {
+            (Some(start), Some(end)) => Some(end - start),
+            _ => summary
+                .first_sample_ms
+                .zip(summary.last_sample_ms)
+                .map(|(first, last)| last - first),
+        };
+
+        let hotspots = self.compute_hotspots(limit, thread_filter, false, false, range);
+        let hotspot_attributes = |rank: usize, name: &str, library: Option<&str>| {
+            let mut attributes = vec![
+                string_attribute("code.function.name", name),
+                int_attribute("rank", rank as i64),
+            ];
+            if let Some(library) = library {
+                attributes.push(string_attribute("library", library));
+            }
+            attributes
+        };
+        let (self_points, total_points): (Vec<Value>, Vec<Value>) = hotspots
+            .iter()
+            .map(|h| {
+                let attributes =
+                    || hotspot_attributes(h.rank, &h.function.name, h.function.library.as_deref());
+                (
+                    double_point(h.self_percent, attributes()),
+                    double_point(h.total_percent, attributes()),
+                )
+            })
+            .unzip();
+
+        let mut metrics = vec![
+            gauge(
+                "profile.samples",
+                "{sample}",
+                "Samples in the profile",
+                vec![int_point(total_samples, Vec::new())],
+            ),
+            gauge(
+                "profile.thread.samples",
+                "{sample}",
+                "Samples of each thread",
+                thread_points,
+            ),
+            gauge(
+                "profile.function.self",
+                "%",
+                "Share of the samples with the function as the leaf",
+                self_points,
+            ),
+            gauge(
+                "profile.function.total",
+                "%",
+                "Share of the samples with the function on the stack",
+                total_points,
+            ),
+        ];
+        if let Some(duration_ms) = duration_ms {
+            metrics.insert(
+                1,
+                gauge(
+                    "profile.duration",
+                    "ms",
+                    "Time from the first to the last sample",
+                    vec![double_point(duration_ms, Vec::new())],
+                ),
+            );
+        }
+
+        let mut body = format!(
+            "Profile of {}: {} samples",
+            self.product_name, total_samples
+        );
+        if let Some(duration_ms) = duration_ms {
+            body.push_str(&format!(" over {:.1} ms", duration_ms));
+        }
+        // Hotspots are ordered by self time, but also list functions which
+        // only have total time; those aren't "top self time".
+        let top: Vec<String> = hotspots
+            .iter()
+            .filter(|h| h.self_samples > 0)
+            .take(5)
+            .map(|h| format!("{} ({:.1}%)", h.function.name, h.self_percent))
+            .collect();
+        if !top.is_empty() {
+            body.push_str(&format!("; top self time: {}", top.join(", ")));
+        }
+        let mut log_attributes = vec![
+            string_attribute("event.name", "profile.summary"),
+            int_attribute("profile.samples", total_samples),
+        ];
+        if let Some(hotspot) = hotspots.first() {
+            log_attributes.push(string_attribute(
+                "profile.top_function",
+                &hotspot.function.name,
+            ));
+        }
+
+        let service_name = service_name.unwrap_or(&self.product_name);
+        let resource = json!({
+            "attributes": [
+                string_attribute("service.name", service_name),
+                string_attribute("profile.product", &self.product_name),
+            ]
+        });
+        let scope = json!({ "name": "samply-for-ai", "version": env!("CARGO_PKG_VERSION") });
+
+        OtlpExport {
+            metrics: json!({
+                "resourceMetrics": [{
+                    "resource": resource,
+                    "scopeMetrics": [{ "scope": scope, "metrics": metrics }]
+                }]
+            }),
+            logs: json!({
+                "resourceLogs": [{
+                    "resource": resource,
+                    "scopeLogs": [{
+                        "scope": scope,
+                        "logRecords": [{
+                            "timeUnixNano": time,
+                            "observedTimeUnixNano": time,
+                            "severityNumber": 9,
+                            "severityText": "INFO",
+                            "body": { "stringValue": body },
+                            "attributes": log_attributes
+                        }]
+                    }]
+                }]
+            }),
+        }
+    }
+}
@@ -0,0 +1,357 @@
+//! Pages of the ranked lists of query responses, and budgets for the size of
+//! responses, so that agents can read large results a page at a time instead
+//! of getting more than fits into their context.
+//!
+//! A paged query computes the items of every page up to the requested one,
+//! and one more to tell whether there's a next page; [`paginate`] then keeps
+//! the requested page of the response's ranked lists. The rankings are
+//! deterministic, so that the `next_cursor` of a page resumes exactly where
+//! it ended. A response larger than its `max_bytes` budget loses items from the
+//! end of its lists first, then the deepest levels of its nested lists, and
+//! gets `"truncated": true`.
+
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+use crate::md5;
+
+/// The ranked lists in the `data` of each paged query, which its `limit`
+/// applies to; `""` is `data` itself. Queries whose `limit` is the number of
+/// items per thread, process or category aren't paged.
+const PAGED_LISTS: &[(&str, &[&str])] = &[
+    ("hotspots", &["", "modules", "boundaries", "languages"]),
+    ("markers", &["markers"]),
+    ("callers", &["callers"]),
+    ("callees", &["callees"]),
+    ("wait-graph", &["edges"]),
+    ("allocations", &["by_bytes", "by_count"]),
+    ("growth", &["by_self", "by_total"]),
+    ("diff", &["functions"]),
+];
+
+/// The parameters which select the page rather than the query's results
+const PAGE_PARAMS: &[&str] = &["offset", "limit", "cursor", "max_bytes", "precision"];
+
+/// The page of a query's results to return, from its `offset`, `limit`,
+/// `cursor` and `max_bytes` parameters
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Page {
+    /// The number of ranked items before the page
+    pub offset: usize,
+    /// The number of items per page, once the query has set its default
+    pub limit: Option<usize>,
+    /// The most bytes of JSON the response may have
+    pub max_bytes: Option<usize>,
+    /// Identifies the query and its parameters, so that a cursor can't be
+    /// used for another query
+    fingerprint: String,
+}
+
+impl Page {
+    /// The page of the query at `path` which `params` select. A `cursor`
+    /// from the response of the previous page takes the place of `offset`
+    /// and `limit`.
+    pub fn from_params(path: &str, params: &HashMap<String, String>) -> Result<Page, String> {
+        let number = |name: &str| {
+            params
+                .get(name)
+                .map(|value| {
+                    value.parse::<usize>().map_err(|_| {
+                        format!("{name} must be a non-negative integer, not '{value}'")
+                    })
+                })
+                .transpose()
+        };
+        let mut page = Page {
+            offset: number("offset")?.unwrap_or(0),
+            limit: number("limit")?,
+            max_bytes: number("max_bytes")?,
+            fingerprint: fingerprint(path, params),
+        };
+        if let Some(cursor) = params.get("cursor").filter(|cursor| !cursor.is_empty()) {
+            let invalid = || format!("Invalid cursor '{cursor}'");
+            let mut parts = cursor.splitn(3, '.');
+            let offset = parts
+                .next()
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(invalid)?;
+            let limit = parts
+                .next()
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(invalid)?;
+            if parts.next() != Some(page.fingerprint.as_str()) {
+                return Err(format!(
+                    "The cursor '{cursor}' is from a different query. Pass the same \
+                     parameters as for the page which returned it."
+                ));
+            }
+            page.offset = offset;
+            page.limit = Some(limit);
+        }
+        Ok(page)
+    }
+
+    /// How many items a paged query has to compute: those of this page and
+    /// the pages before it, and one more to tell whether there's a next page.
+    /// `default` is the query's number of items without a `limit`.
+    pub fn fetch_limit(&mut self, default: usize) -> usize {
+        let limit = *self.limit.get_or_insert(default);
+        self.offset.saturating_add(limit).saturating_add(1)
+    }
+
+    /// The cursor of the page which starts at `offset`
+    fn cursor(&self, offset: usize, limit: usize) -> String {
+        format!("{offset}.{limit}.{}", self.fingerprint)
+    }
+}
+
+/// A short hash of the query's path and of its parameters which aren't about
+/// the page
+fn fingerprint(path: &str, params: &HashMap<String, String>) -> String {
+    let mut query: Vec<(&String, &String)> = params
+        .iter()
+        .filter(|(name, _)| !PAGE_PARAMS.contains(&name.as_str()))
+        .collect();
+    query.sort();
+    let mut key = path.to_string();
+    for (name, value) in query {
+        key.push_str(&format!("&{name}={value}"));
+    }
+    md5::to_hex(&md5::md5(key.as_bytes())[..6])
+}
+
+/// The list `field` of `data`, or `data` itself for `""`
+fn paged_list<'a>(data: &'a mut Value, field: &str) -> Option<&'a mut Vec<Value>> {
+    match field {
+        "" => data.as_array_mut(),
+        field => data.get_mut(field)?.as_array_mut(),
+    }
+}
+
+/// Keep the first `limit` items of the lists `field` nested in `item`, as
+/// in the trees of callers and callees
+fn truncate_nested(item: &mut Value, field: &str, limit: usize) {
+    if let Some(list) = item.get_mut(field).and_then(Value::as_array_mut) {
+        list.truncate(limit);
+        for item in list {
+            truncate_nested(item, field, limit);
+        }
+    }
+}
+
+/// The most lists which are nested in each other in `value`
+fn list_depth(value: &Value) -> usize {
+    match value {
+        Value::Array(items) => 1 + items.iter().map(list_depth).max().unwrap_or(0),
+        Value::Object(fields) => fields.values().map(list_depth).max().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+/// Empty the lists which are nested more than `levels` lists deep in
+/// `value`, and mark the objects which held them as `truncated`
+fn cut_lists(value: &mut Value, levels: usize) {
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                cut_lists(item, levels.saturating_sub(1));
+            }
+        }
+        Value::Object(fields) => {
+            let mut cut = false;
+            for value in fields.values_mut() {
+                match value {
+                    Value::Array(items) if levels == 0 => {
+                        cut |= !items.is_empty();
+                        items.clear();
+                    }
+                    value => cut_lists(value, levels),
+                }
+            }
+            if cut {
+                fields.insert("truncated".to_string(), Value::Bool(true));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn json_size(value: &Value) -> usize {
+    serde_json::to_string(value).map_or(0, |json| json.len())
+}
+
+/// Keep the requested page of the ranked lists of a successful response,
+/// cut it down to the page's `max_bytes`, and add the `page` with the
+/// `next_cursor`, if there are more items or it's not the first page.
+pub fn paginate(response: &mut Value, page: &Page) {
+    let Some(Value::Object(response)) = Some(response).filter(|r| r["success"] == true) else {
+        return;
+    };
+    let fields: &[&str] = PAGED_LISTS
+        .iter()
+        .find(|(query, _)| response.get("query").and_then(Value::as_str) == Some(query))
+        .map_or(&[], |(_, fields)| fields);
+    let Some(mut data) = response.remove("data") else {
+        return;
+    };
+
+    // The most items of any of the lists
+    let mut returned: Option<usize> = None;
+    let mut has_more = false;
+    if let Some(limit) = page.limit {
+        for field in fields {
+            let Some(list) = paged_list(&mut data, field) else {
+                continue;
+            };
+            list.drain(..page.offset.min(list.len()));
+            has_more |= list.len() > limit;
+            list.truncate(limit);
+            for item in list.iter_mut() {
+                truncate_nested(item, field, limit);
+            }
+            returned = Some(returned.unwrap_or(0).max(list.len()));
+        }
+    }
+
+    let mut truncated = false;
+    if let Some(max_bytes) = page.max_bytes {
+        let rest_size = json_size(&Value::Object(response.clone()));
+        let fits = |data: &Value| rest_size + json_size(data) <= max_bytes;
+        if !fits(&data) {
+            truncated = true;
+            // First fewer items, down to one per list
+            if let Some(count) = returned.filter(|&count| count > 1) {
+                let truncated_to = |count: usize| {
+                    let mut data = data.clone();
+                    for field in fields {
+                        if let Some(list) = paged_list(&mut data, field) {
+                            list.truncate(count);
+                        }
+                    }
+                    data
+                };
+                let (mut low, mut high) = (1, count);
+                while low < high {
+                    let mid = (low + high).div_ceil(2);
+                    if fits(&truncated_to(mid)) {
+                        low = mid;
+                    } else {
+                        high = mid - 1;
+                    }
+                }
+                data = truncated_to(low);
+                has_more |= low < count;
+                returned = Some(low);
+            }
+            // Then the deepest levels of nested lists
+            for levels in (1..list_depth(&data)).rev() {
+                if fits(&data) {
+                    break;
+                }
+                cut_lists(&mut data, levels);
+            }
+        }
+    }
+
+    response.insert("data".to_string(), data);
+    if let (Some(limit), Some(returned)) = (page.limit, returned) {
+        if has_more || page.offset > 0 {
+            let next_cursor = has_more.then(|| page.cursor(page.offset + returned, limit));
+            let page_info = Map::from_iter([
+                ("offset".to_string(), page.offset.into()),
+                ("limit".to_string(), limit.into()),
+                ("returned".to_string(), returned.into()),
+                ("has_more".to_string(), has_more.into()),
+                ("next_cursor".to_string(), next_cursor.into()),
+            ]);
+            response.insert("page".to_string(), Value::Object(page_info));
+        }
+    }
+    if truncated {
+        response.insert("truncated".to_string(), Value::Bool(true));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn hotspots(count: usize) -> Value {
+        let entries: Vec<Value> = (1..=count)
+            .map(|rank| json!({ "rank": rank, "caller_chain": ["main", "work"] }))
+            .collect();
+        json!({ "success": true, "query": "hotspots", "data": entries })
+    }
+
+    #[test]
+    fn test_paginate() {
+        let mut page = Page::from_params("/query/hotspots", &params(&[("limit", "2")])).unwrap();
+        assert_eq!(page.fetch_limit(20), 3);
+        let mut response = hotspots(3);
+        paginate(&mut response, &page);
+        assert_eq!(response["data"].as_array().unwrap().len(), 2);
+        assert_eq!(response["page"]["has_more"], true);
+        let cursor = response["page"]["next_cursor"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        // The cursor continues after the first page, also with the
+        // parameters in another order.
+        let next = params(&[("cursor", &cursor), ("thread", "main")]);
+        assert!(Page::from_params("/query/hotspots", &next).is_err());
+        let mut page =
+            Page::from_params("/query/hotspots", &params(&[("cursor", &cursor)])).unwrap();
+        assert_eq!((page.offset, page.fetch_limit(20)), (2, 5));
+        let mut response = hotspots(3);
+        paginate(&mut response, &page);
+        assert_eq!(
+            response["data"],
+            json!([{ "rank": 3, "caller_chain": ["main", "work"] }])
+        );
+        assert_eq!(response["page"]["has_more"], false);
+        assert_eq!(response["page"]["next_cursor"], Value::Null);
+
+        // Without more items on the first page, there's no page info.
+        let mut page = Page::from_params("/query/hotspots", &HashMap::new()).unwrap();
+        page.fetch_limit(20);
+        let mut response = hotspots(3);
+        paginate(&mut response, &page);
+        assert!(response.get("page").is_none());
+    }
+
+    #[test]
+    fn test_max_bytes() {
+        let budget = json_size(&hotspots(2)) + 10;
+        let query = params(&[("max_bytes", &budget.to_string())]);
+        let mut page = Page::from_params("/query/hotspots", &query).unwrap();
+        page.fetch_limit(20);
+        let mut response = hotspots(10);
+        paginate(&mut response, &page);
+        assert_eq!(response["data"].as_array().unwrap().len(), 2);
+        assert_eq!(response["truncated"], true);
+        assert_eq!(response["page"]["returned"], 2);
+        assert!(response["page"]["next_cursor"].is_string());
+
+        // A single item which is too large loses its nested lists.
+        let query = params(&[("max_bytes", "60")]);
+        let mut page = Page::from_params("/query/hotspots", &query).unwrap();
+        page.fetch_limit(20);
+        let mut response = hotspots(1);
+        paginate(&mut response, &page);
+        assert_eq!(
+            response["data"],
+            json!([{ "rank": 1, "caller_chain": [], "truncated": true }])
+        );
+    }
+}
@@ -0,0 +1,400 @@
+//! Lenient parsing of profiles, so that a malformed or truncated profile loads
+//! with whatever is usable in it instead of failing as a whole.
+//!
+//! Threads are converted one at a time, and can be handed to a
+//! [`ThreadSink`] instead of being kept, so that a profile which doesn't fit
+//! into memory can still be indexed.
+//!
+//! Each thread's JSON is buffered while it is parsed, so that a thread which
+//! doesn't match the format is skipped without losing the others. A `meta`,
+//! `shared` or library entry which doesn't match falls back to defaults. If the
+//! JSON itself ends early or has a syntax error, the threads before that point
+//! are kept. Threads whose tables refer to rows which don't exist are repaired
+//! where that's possible, so that no query can walk off the end of a table or
+//! loop on a cyclic stack.
+//!
+//! Everything which was skipped or repaired is recorded as a [`ParseWarning`]
+//! and reported in the summary.
+
+use std::fmt;
+
+use serde::de::{DeserializeOwned, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::error::Category;
+use serde_json::value::RawValue;
+
+use crate::column::Column;
+use crate::{
+    AnalysisError, RawLib, RawMeta, RawProfile, RawShared, RawThread, StringInterner, ThreadData,
+};
+
+/// Sample weights beyond this are treated as corrupt, so that summing the
+/// weights of a thread can't overflow.
+const MAX_SAMPLE_WEIGHT: i64 = 1 << 32;
+
+/// Something in the profile which was skipped or repaired while loading it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParseWarning {
+    /// The thread's index in the profile's `threads` array
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread_index: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread: Option<String>,
+    pub message: String,
+}
+
+impl ParseWarning {
+    fn profile(message: String) -> Self {
+        ParseWarning {
+            thread_index: None,
+            thread: None,
+            message,
+        }
+    }
+}
+
+/// Parse `raw` as a `T`, or fall back to the default with a warning
+fn lenient<T: DeserializeOwned + Default>(
+    raw: &RawValue,
+    what: &str,
+    warnings: &mut Vec<ParseWarning>,
+) -> T {
+    serde_json::from_str(raw.get()).unwrap_or_else(|e| {
+        warnings.push(ParseWarning::profile(format!(
+            "Ignored the malformed {what}: {e}"
+        )));
+        T::default()
+    })
+}
+
+/// The name of a thread which couldn't be parsed, if at least that is readable
+fn thread_name(raw: &RawValue) -> Option<String> {
+    #[derive(Deserialize)]
+    struct Named {
+        #[serde(default)]
+        name: String,
+    }
+    serde_json::from_str::<Named>(raw.get())
+        .ok()
+        .map(|named| named.name)
+        .filter(|name| !name.is_empty())
+}
+
+/// Takes each thread as soon as it's parsed, instead of the threads being
+/// collected into the [`RawProfile`]
+pub(crate) type ThreadSink<'a> = &'a mut dyn FnMut(ThreadData) -> std::io::Result<()>;
+
+/// The top-level fields of a profile, as far as they have been parsed
+#[derive(Default)]
+struct PartialProfile<'s> {
+    meta: Option<RawMeta>,
+    libs: Vec<RawLib>,
+    has_threads: bool,
+    threads: Vec<ThreadData>,
+    /// The number of threads which were parsed, whether they were kept in
+    /// `threads` or handed to `sink`
+    thread_count: usize,
+    sink: Option<ThreadSink<'s>>,
+    /// The error of `sink` which ended the parsing
+    sink_error: Option<std::io::Error>,
+    shared: Option<RawShared>,
+    warnings: Vec<ParseWarning>,
+    strings: StringInterner,
+}
+
+impl PartialProfile<'_> {
+    /// The parsed profile. A profile which was cut short may lack the fields
+    /// after its threads; a complete one needs `meta` and `threads`.
+    fn finish(self, complete: bool) -> Result<RawProfile, AnalysisError> {
+        let missing =
+            |field: &str| AnalysisError::InvalidProfile(format!("missing field `{field}`"));
+        let meta = match self.meta {
+            Some(meta) => meta,
+            None if complete => return Err(missing("meta")),
+            None => RawMeta::default(),
+        };
+        if complete && !self.has_threads {
+            return Err(missing("threads"));
+        }
+        Ok(RawProfile {
+            meta,
+            libs: self.libs,
+            threads: self.threads,
+            shared: self.shared,
+            warnings: self.warnings,
+        })
+    }
+}
+
+/// Parses the profile's top-level object into a [`PartialProfile`], which
+/// keeps what was parsed if the JSON turns out to be broken.
+struct ProfileSeed<'a, 's>(&'a mut PartialProfile<'s>);
+
+impl<'de> DeserializeSeed<'de> for ProfileSeed<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ProfileSeed<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a profile object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let profile = self.0;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "meta" => {
+                    let raw: Box<RawValue> = map.next_value()?;
+                    profile.meta = Some(lenient(&raw, "meta", &mut profile.warnings));
+                }
+                "libs" => {
+                    let raw: Box<RawValue> = map.next_value()?;
+                    let libs: Vec<Box<RawValue>> = lenient(&raw, "libs", &mut profile.warnings);
+                    // Libraries are referred to by index, so a malformed one
+                    // keeps its place.
+                    profile.libs = libs
+                        .iter()
+                        .enumerate()
+                        .map(|(i, lib)| {
+                            lenient(lib, &format!("library {i}"), &mut profile.warnings)
+                        })
+                        .collect();
+                }
+                "threads" => {
+                    profile.has_threads = true;
+                    map.next_value_seed(ThreadsSeed(&mut *profile))?;
+                }
+                "shared" => {
+                    let raw: Box<RawValue> = map.next_value()?;
+                    profile.shared = lenient(&raw, "shared", &mut profile.warnings);
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses the `threads` array one thread at a time, converting each one into
+/// its compact [`ThreadData`] before the next one is parsed.
+struct ThreadsSeed<'a, 's>(&'a mut PartialProfile<'s>);
+
+impl<'de> DeserializeSeed<'de> for ThreadsSeed<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ThreadsSeed<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an array of threads")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let profile = self.0;
+        let mut thread_index = 0;
+        while let Some(raw) = seq.next_element::<Box<RawValue>>()? {
+            match serde_json::from_str::<RawThread>(raw.get()) {
+                Ok(raw_thread) => {
+                    let mut problems = Vec::new();
+                    let thread =
+                        ThreadData::from_raw(raw_thread, &mut profile.strings, &mut problems);
+                    profile
+                        .warnings
+                        .extend(problems.into_iter().map(|message| ParseWarning {
+                            thread_index: Some(thread_index),
+                            thread: Some(thread.name.clone()),
+                            message,
+                        }));
+                    profile.thread_count += 1;
+                    match profile.sink.as_mut() {
+                        Some(sink) => {
+                            if let Err(e) = sink(thread) {
+                                profile.sink_error = Some(e);
+                                return Err(serde::de::Error::custom("couldn't store a thread"));
+                            }
+                        }
+                        None => profile.threads.push(thread),
+                    }
+                }
+                Err(e) => profile.warnings.push(ParseWarning {
+                    thread_index: Some(thread_index),
+                    thread: thread_name(&raw),
+                    message: format!("Skipped the malformed thread: {e}"),
+                }),
+            }
+            thread_index += 1;
+        }
+        Ok(())
+    }
+}
+
+impl RawProfile {
+    /// Parse a profile, keeping the threads before the point where the JSON
+    /// ends early or has a syntax error.
+    pub(crate) fn parse<'de, R: serde_json::de::Read<'de>>(
+        deserializer: serde_json::Deserializer<R>,
+    ) -> Result<Self, AnalysisError> {
+        Self::parse_into(deserializer, PartialProfile::default())
+    }
+
+    /// Parse a profile like [`RawProfile::parse`], but hand each thread to
+    /// `sink` as soon as it's parsed. The returned profile has no threads.
+    #[cfg(feature = "native")]
+    pub(crate) fn parse_streaming<'de, R: serde_json::de::Read<'de>>(
+        deserializer: serde_json::Deserializer<R>,
+        sink: ThreadSink<'_>,
+    ) -> Result<Self, AnalysisError> {
+        let profile = PartialProfile {
+            sink: Some(sink),
+            ..Default::default()
+        };
+        Self::parse_into(deserializer, profile)
+    }
+
+    fn parse_into<'de, R: serde_json::de::Read<'de>>(
+        mut deserializer: serde_json::Deserializer<R>,
+        mut profile: PartialProfile<'_>,
+    ) -> Result<Self, AnalysisError> {
+        let result = ProfileSeed(&mut profile)
+            .deserialize(&mut deserializer)
+            .and_then(|()| deserializer.end());
+        if let Some(e) = profile.sink_error.take() {
+            return Err(e.into());
+        }
+        match result {
+            Ok(()) => profile.finish(true),
+            Err(e)
+                if matches!(e.classify(), Category::Syntax | Category::Eof)
+                    && profile.thread_count > 0 =>
+            {
+                profile.warnings.push(ParseWarning::profile(format!(
+                    "The profile is truncated or malformed ({e}); loaded the {} threads before that point",
+                    profile.thread_count
+                )));
+                profile.finish(false)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for RawProfile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut profile = PartialProfile::default();
+        ProfileSeed(&mut profile).deserialize(deserializer)?;
+        profile
+            .finish(true)
+            .map_err(|e| serde::de::Error::custom(e.to_string()))
+    }
+}
+
+impl ThreadData {
+    /// Repair references to rows which don't exist, and describe the problems
+    /// in `problems`.
+    pub(crate) fn repair(&mut self, problems: &mut Vec<String>) {
+        let stack_count = self.stack_frame.len();
+
+        // Stacks only refer to earlier stacks, so walking a stack always ends.
+        let bad_prefixes = self
+            .stack_prefix
+            .iter()
+            .enumerate()
+            .filter(|&(i, prefix)| prefix.is_some_and(|prefix| prefix >= i))
+            .count();
+        if bad_prefixes > 0 {
+            self.stack_prefix = Column::packed(
+                self.stack_prefix
+                    .iter()
+                    .enumerate()
+                    .map(|(i, prefix)| prefix.filter(|&prefix| prefix < i)),
+            );
+            problems.push(format!(
+                "{bad_prefixes} stacks had a prefix which isn't an earlier stack, and were cut off there"
+            ));
+        }
+
+        let bad_stacks = self
+            .sample_stack
+            .iter()
+            .filter(|stack| stack.is_some_and(|stack| stack >= stack_count))
+            .count();
+        if bad_stacks > 0 {
+            self.sample_stack = Column::packed(
+                self.sample_stack
+                    .iter()
+                    .map(|stack| stack.filter(|&stack| stack < stack_count)),
+            );
+            problems.push(format!(
+                "{bad_stacks} samples referred to stacks which don't exist, and count as samples without a stack"
+            ));
+        }
+
+        let mut bad_allocation_stacks = 0;
+        for allocation in &mut self.allocations {
+            if allocation.stack.is_some_and(|stack| stack >= stack_count) {
+                allocation.stack = None;
+                bad_allocation_stacks += 1;
+            }
+        }
+        if bad_allocation_stacks > 0 {
+            problems.push(format!(
+                "{bad_allocation_stacks} allocations referred to stacks which don't exist, and count as allocations without a stack"
+            ));
+        }
+
+        let bad_weights = self
+            .sample_weight
+            .iter()
+            .filter(|weight| weight.unsigned_abs() > MAX_SAMPLE_WEIGHT as u64)
+            .count();
+        if bad_weights > 0 {
+            self.sample_weight = Column::packed(self.sample_weight.iter().map(|weight| {
+                if weight.unsigned_abs() > MAX_SAMPLE_WEIGHT as u64 {
+                    0
+                } else {
+                    weight
+                }
+            }));
+            problems.push(format!(
+                "{bad_weights} samples had an implausible weight, and count as 0"
+            ));
+        }
+
+        let frame_count = self.frame_func.len();
+        let bad_frames = self
+            .stack_frame
+            .iter()
+            .filter(|&frame| frame >= frame_count)
+            .count();
+        if bad_frames > 0 {
+            problems.push(format!(
+                "{bad_frames} stacks refer to frames which don't exist"
+            ));
+        }
+
+        let func_count = self.func_name_idx.len();
+        let bad_funcs = self
+            .frame_func
+            .iter()
+            .filter(|&func| func >= func_count)
+            .count();
+        if bad_funcs > 0 {
+            problems.push(format!(
+                "{bad_funcs} frames refer to functions which don't exist, shown as <func N>"
+            ));
+        }
+    }
+}
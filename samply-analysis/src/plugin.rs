@@ -0,0 +1,122 @@
+//! Custom query endpoints.
+//!
+//! Downstream builds can serve their own `/query/<name>` endpoints (and MCP
+//! tools) without changing the server's routing code: implement
+//! [`QueryPlugin`] in a crate which depends on `samply-analysis` with the
+//! `plugins` feature, register it with [`register_query_plugin!`], and link
+//! that crate into `samply-for-ai` (built with its `query-plugins` feature).
+//!
+//! ```ignore
+//! struct LongestStacks;
+//!
+//! impl QueryPlugin for LongestStacks {
+//!     fn name(&self) -> &'static str {
+//!         "longest-stacks"
+//!     }
+//!
+//!     fn query(
+//!         &self,
+//!         analyzer: &ProfileAnalyzer,
+//!         params: &HashMap<String, String>,
+//!     ) -> Result<serde_json::Value, String> {
+//!         ...
+//!     }
+//! }
+//!
+//! samply_analysis::register_query_plugin!(LongestStacks);
+//! ```
+//!
+//! Built-in endpoints take precedence over plugins with the same name.
+
+use std::collections::HashMap;
+
+use crate::ProfileAnalyzer;
+
+/// A custom `/query/<name>` endpoint.
+pub trait QueryPlugin: Sync {
+    /// The endpoint name; the query is served at `/query/<name>`.
+    fn name(&self) -> &'static str;
+
+    /// A one-line description, used for the MCP tool list.
+    fn description(&self) -> &'static str {
+        ""
+    }
+
+    /// Run the query with the request's query parameters. `Ok` becomes the
+    /// response's `data`, `Err` its `error`.
+    fn query(
+        &self,
+        analyzer: &ProfileAnalyzer,
+        params: &HashMap<String, String>,
+    ) -> Result<serde_json::Value, String>;
+}
+
+#[cfg(feature = "plugins")]
+#[doc(hidden)]
+pub use inventory;
+
+/// A registered plugin; create these with [`register_query_plugin!`].
+#[cfg(feature = "plugins")]
+pub struct PluginRegistration(pub &'static dyn QueryPlugin);
+
+#[cfg(feature = "plugins")]
+inventory::collect!(PluginRegistration);
+
+/// Register a [`QueryPlugin`] value (a constant expression) so the server serves it.
+#[cfg(feature = "plugins")]
+#[macro_export]
+macro_rules! register_query_plugin {
+    ($plugin:expr) => {
+        $crate::plugin::inventory::submit! {
+            $crate::plugin::PluginRegistration(&$plugin)
+        }
+    };
+}
+
+/// All registered plugins.
+#[cfg(feature = "plugins")]
+pub fn query_plugins() -> impl Iterator<Item = &'static dyn QueryPlugin> {
+    inventory::iter::<PluginRegistration>
+        .into_iter()
+        .map(|registration| registration.0)
+}
+
+/// All registered plugins: none, since the `plugins` feature is disabled.
+#[cfg(not(feature = "plugins"))]
+pub fn query_plugins() -> impl Iterator<Item = &'static dyn QueryPlugin> {
+    std::iter::empty()
+}
+
+/// The registered plugin serving `/query/<name>`, if any.
+pub fn find_query_plugin(name: &str) -> Option<&'static dyn QueryPlugin> {
+    query_plugins().find(|plugin| plugin.name() == name)
+}
+
+#[cfg(all(test, feature = "plugins"))]
+mod tests {
+    use super::*;
+
+    struct Echo;
+
+    impl QueryPlugin for Echo {
+        fn name(&self) -> &'static str {
+            "echo"
+        }
+
+        fn query(
+            &self,
+            _analyzer: &ProfileAnalyzer,
+            params: &HashMap<String, String>,
+        ) -> Result<serde_json::Value, String> {
+            Ok(serde_json::json!(params))
+        }
+    }
+
+    crate::register_query_plugin!(Echo);
+
+    #[test]
+    fn test_find_query_plugin() {
+        assert_eq!(find_query_plugin("echo").map(|p| p.name()), Some("echo"));
+        assert!(find_query_plugin("missing").is_none());
+    }
+}
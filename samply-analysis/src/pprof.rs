@@ -0,0 +1,291 @@
+//! Encoding the profile as Google's pprof protobuf (`profile.proto`), as read
+//! by `go tool pprof`, Polar Signals and other pprof-compatible tools.
+//!
+//! The message is small enough to encode by hand, so we don't pull in a
+//! protobuf library. Every sample gets a `samples`/`count` and a
+//! `cpu`/`nanoseconds` value and a `thread` label, locations carry the frame
+//! address and line, and each library becomes a mapping.
+
+use std::collections::HashMap;
+
+use crate::{ProfileAnalyzer, TimeRange};
+
+/// Field numbers of the `perftools.profiles` messages which we write
+mod field {
+    pub const PROFILE_SAMPLE_TYPE: u32 = 1;
+    pub const PROFILE_SAMPLE: u32 = 2;
+    pub const PROFILE_MAPPING: u32 = 3;
+    pub const PROFILE_LOCATION: u32 = 4;
+    pub const PROFILE_FUNCTION: u32 = 5;
+    pub const PROFILE_STRING_TABLE: u32 = 6;
+    pub const PROFILE_PERIOD_TYPE: u32 = 11;
+    pub const PROFILE_PERIOD: u32 = 12;
+
+    pub const VALUE_TYPE_TYPE: u32 = 1;
+    pub const VALUE_TYPE_UNIT: u32 = 2;
+
+    pub const SAMPLE_LOCATION_ID: u32 = 1;
+    pub const SAMPLE_VALUE: u32 = 2;
+    pub const SAMPLE_LABEL: u32 = 3;
+
+    pub const LABEL_KEY: u32 = 1;
+    pub const LABEL_STR: u32 = 2;
+
+    pub const MAPPING_ID: u32 = 1;
+    pub const MAPPING_MEMORY_LIMIT: u32 = 3;
+    pub const MAPPING_FILENAME: u32 = 5;
+    pub const MAPPING_BUILD_ID: u32 = 6;
+    pub const MAPPING_HAS_FUNCTIONS: u32 = 7;
+    pub const MAPPING_HAS_LINE_NUMBERS: u32 = 9;
+
+    pub const LOCATION_ID: u32 = 1;
+    pub const LOCATION_MAPPING_ID: u32 = 2;
+    pub const LOCATION_ADDRESS: u32 = 3;
+    pub const LOCATION_LINE: u32 = 4;
+
+    pub const LINE_FUNCTION_ID: u32 = 1;
+    pub const LINE_LINE: u32 = 2;
+
+    pub const FUNCTION_ID: u32 = 1;
+    pub const FUNCTION_NAME: u32 = 2;
+    pub const FUNCTION_SYSTEM_NAME: u32 = 3;
+    pub const FUNCTION_FILENAME: u32 = 4;
+}
+
+const WIRE_VARINT: u32 = 0;
+const WIRE_LEN: u32 = 2;
+
+/// A protobuf message being written
+#[derive(Default)]
+struct Message(Vec<u8>);
+
+impl Message {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u32) {
+        self.varint(((field << 3) | wire_type) as u64);
+    }
+
+    /// An integer field; zero is the default and is left out.
+    fn uint(&mut self, field: u32, value: u64) {
+        if value != 0 {
+            self.key(field, WIRE_VARINT);
+            self.varint(value);
+        }
+    }
+
+    fn int(&mut self, field: u32, value: i64) {
+        self.uint(field, value as u64);
+    }
+
+    fn bytes(&mut self, field: u32, bytes: &[u8]) {
+        self.key(field, WIRE_LEN);
+        self.varint(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn message(&mut self, field: u32, message: Message) {
+        self.bytes(field, &message.0);
+    }
+
+    /// A packed repeated integer field
+    fn packed(&mut self, field: u32, values: impl IntoIterator<Item = u64>) {
+        let mut packed = Message::default();
+        for value in values {
+            packed.varint(value);
+        }
+        if !packed.0.is_empty() {
+            self.message(field, packed);
+        }
+    }
+}
+
+/// The `string_table` of the profile; index 0 is the empty string.
+struct StringTable {
+    strings: Vec<String>,
+    indexes: HashMap<String, u64>,
+}
+
+impl StringTable {
+    fn new() -> Self {
+        StringTable {
+            strings: vec![String::new()],
+            indexes: HashMap::from([(String::new(), 0)]),
+        }
+    }
+
+    fn index(&mut self, s: &str) -> u64 {
+        if let Some(&index) = self.indexes.get(s) {
+            return index;
+        }
+        let index = self.strings.len() as u64;
+        self.strings.push(s.to_string());
+        self.indexes.insert(s.to_string(), index);
+        index
+    }
+}
+
+fn value_type(strings: &mut StringTable, type_: &str, unit: &str) -> Message {
+    let mut message = Message::default();
+    message.uint(field::VALUE_TYPE_TYPE, strings.index(type_));
+    message.uint(field::VALUE_TYPE_UNIT, strings.index(unit));
+    message
+}
+
+/// A location, deduplicated across threads: (library, address, function, line)
+type LocationKey = (Option<usize>, Option<u64>, u64, Option<u32>);
+
+impl ProfileAnalyzer {
+    /// Encode the samples of the threads whose name contains `thread_filter`
+    /// (all threads if `None`) in `range` as an uncompressed pprof profile.
+    pub(crate) fn export_pprof(&self, thread_filter: Option<&str>, range: TimeRange) -> Vec<u8> {
+        let mut strings = StringTable::new();
+        let mut profile = Message::default();
+
+        let period_ns = (self.sampling_interval_ms * 1_000_000.0).round() as i64;
+        let sample_types = [
+            value_type(&mut strings, "samples", "count"),
+            value_type(&mut strings, "cpu", "nanoseconds"),
+        ];
+        for sample_type in sample_types {
+            profile.message(field::PROFILE_SAMPLE_TYPE, sample_type);
+        }
+        let period_type = value_type(&mut strings, "cpu", "nanoseconds");
+        let thread_key = strings.index("thread");
+
+        // Functions by (name, file), locations by LocationKey; ids start at 1.
+        let mut functions: HashMap<(u64, u64), u64> = HashMap::new();
+        let mut locations: HashMap<LocationKey, u64> = HashMap::new();
+        // The highest address seen in each library, for the mapping's memory limit
+        let mut mapping_limits: HashMap<usize, u64> = HashMap::new();
+
+        for thread in &self.threads {
+            if !thread.matches_filter(thread_filter) {
+                continue;
+            }
+            let thread_name = strings.index(&thread.name);
+
+            let mut stack_weights: HashMap<usize, i64> = HashMap::new();
+            for (stack_idx, weight) in thread.samples_in(range) {
+                if let Some(stack_idx) = stack_idx {
+                    *stack_weights.entry(stack_idx).or_default() += weight;
+                }
+            }
+            let mut stack_weights: Vec<(usize, i64)> = stack_weights.into_iter().collect();
+            stack_weights.sort_unstable();
+
+            for (stack_idx, weight) in stack_weights {
+                if weight <= 0 {
+                    continue;
+                }
+                // pprof wants the leaf first, which is the order of the walk.
+                let mut location_ids = Vec::new();
+                for (func_idx, frame_idx) in thread.walk_stack_with_frames(stack_idx) {
+                    let name = strings.index(&thread.get_func_name(func_idx, &self.global_strings));
+                    let file = thread
+                        .get_func_file(func_idx, &self.global_strings)
+                        .map_or(0, |file| strings.index(&file));
+                    let next_function_id = functions.len() as u64 + 1;
+                    let function_id = *functions.entry((name, file)).or_insert(next_function_id);
+
+                    let lib_idx = thread
+                        .get_func_lib_index(func_idx)
+                        .filter(|&lib_idx| lib_idx < self.libs.len());
+                    let address = thread.get_frame_address(frame_idx);
+                    if let (Some(lib_idx), Some(address)) = (lib_idx, address) {
+                        let limit = mapping_limits.entry(lib_idx).or_default();
+                        *limit = (*limit).max(address + 1);
+                    }
+                    let key = (
+                        lib_idx,
+                        address,
+                        function_id,
+                        thread.get_frame_line(frame_idx),
+                    );
+                    let next_location_id = locations.len() as u64 + 1;
+                    location_ids.push(*locations.entry(key).or_insert(next_location_id));
+                }
+
+                let mut label = Message::default();
+                label.uint(field::LABEL_KEY, thread_key);
+                label.uint(field::LABEL_STR, thread_name);
+
+                let mut sample = Message::default();
+                sample.packed(field::SAMPLE_LOCATION_ID, location_ids);
+                sample.packed(
+                    field::SAMPLE_VALUE,
+                    [weight as u64, (weight * period_ns) as u64],
+                );
+                sample.message(field::SAMPLE_LABEL, label);
+                profile.message(field::PROFILE_SAMPLE, sample);
+            }
+        }
+
+        // Mapping ids are the library indexes plus one.
+        let mut mapping_libs: Vec<usize> = locations.keys().filter_map(|key| key.0).collect();
+        mapping_libs.sort_unstable();
+        mapping_libs.dedup();
+        for lib_idx in mapping_libs {
+            let lib = &self.libs[lib_idx];
+            let filename = if lib.path.is_empty() {
+                &lib.name
+            } else {
+                &lib.path
+            };
+            let mut mapping = Message::default();
+            mapping.uint(field::MAPPING_ID, lib_idx as u64 + 1);
+            mapping.uint(
+                field::MAPPING_MEMORY_LIMIT,
+                mapping_limits.get(&lib_idx).copied().unwrap_or(0),
+            );
+            mapping.uint(field::MAPPING_FILENAME, strings.index(filename));
+            mapping.uint(field::MAPPING_BUILD_ID, strings.index(&lib.debug_id));
+            mapping.uint(field::MAPPING_HAS_FUNCTIONS, 1);
+            mapping.uint(field::MAPPING_HAS_LINE_NUMBERS, 1);
+            profile.message(field::PROFILE_MAPPING, mapping);
+        }
+
+        let mut locations: Vec<(LocationKey, u64)> = locations.into_iter().collect();
+        locations.sort_unstable_by_key(|&(_, id)| id);
+        for ((lib_idx, address, function_id, line), id) in locations {
+            let mut line_message = Message::default();
+            line_message.uint(field::LINE_FUNCTION_ID, function_id);
+            line_message.int(field::LINE_LINE, line.map_or(0, i64::from));
+
+            let mut location = Message::default();
+            location.uint(field::LOCATION_ID, id);
+            location.uint(
+                field::LOCATION_MAPPING_ID,
+                lib_idx.map_or(0, |i| i as u64 + 1),
+            );
+            location.uint(field::LOCATION_ADDRESS, address.unwrap_or(0));
+            location.message(field::LOCATION_LINE, line_message);
+            profile.message(field::PROFILE_LOCATION, location);
+        }
+
+        let mut functions: Vec<((u64, u64), u64)> = functions.into_iter().collect();
+        functions.sort_unstable_by_key(|&(_, id)| id);
+        for ((name, file), id) in functions {
+            let mut function = Message::default();
+            function.uint(field::FUNCTION_ID, id);
+            function.uint(field::FUNCTION_NAME, name);
+            function.uint(field::FUNCTION_SYSTEM_NAME, name);
+            function.uint(field::FUNCTION_FILENAME, file);
+            profile.message(field::PROFILE_FUNCTION, function);
+        }
+
+        for s in &strings.strings {
+            profile.bytes(field::PROFILE_STRING_TABLE, s.as_bytes());
+        }
+        profile.message(field::PROFILE_PERIOD_TYPE, period_type);
+        profile.int(field::PROFILE_PERIOD, period_ns);
+
+        profile.0
+    }
+}
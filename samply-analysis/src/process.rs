@@ -0,0 +1,133 @@
+//! Processes of profiles of multi-process workloads (browsers, forking
+//! servers): the threads and hotspots of each process, and views of the
+//! profile restricted to one process.
+//!
+//! A process is identified by its pid or by a substring of its name, which
+//! comes from the threads' `processName`.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{HotspotEntry, ProfileAnalyzer, ThreadData, TimeRange};
+
+/// One thread of a process, from a processes query
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessThread {
+    pub name: String,
+    pub tid: String,
+    pub is_main: bool,
+    pub samples: i64,
+}
+
+/// One process, from a processes query
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessEntry {
+    pub pid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub samples: i64,
+    /// Share of the profile's samples
+    pub percent: f64,
+    /// By samples, largest first
+    pub threads: Vec<ProcessThread>,
+    /// Percentages are relative to the process's samples
+    pub hotspots: Vec<HotspotEntry>,
+}
+
+/// Response for processes query
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessesResponse {
+    pub total_samples: i64,
+    /// By samples, largest first
+    pub processes: Vec<ProcessEntry>,
+}
+
+impl ThreadData {
+    /// Whether the thread belongs to the process `process`, given as the pid
+    /// or a substring of the process name
+    pub(crate) fn in_process(&self, process: &str) -> bool {
+        self.pid == process
+            || (!self.process_name.is_empty() && self.process_name.contains(process))
+    }
+}
+
+impl ProfileAnalyzer {
+    /// Every process with its threads, sample count and top `limit` hotspots.
+    /// Only samples in `range` are counted.
+    pub fn processes(&self, limit: usize, range: TimeRange) -> ProcessesResponse {
+        let mut by_pid: BTreeMap<&str, Vec<&ThreadData>> = BTreeMap::new();
+        for thread in &self.threads {
+            by_pid.entry(&thread.pid).or_default().push(thread);
+        }
+
+        let mut processes: Vec<ProcessEntry> = by_pid
+            .into_iter()
+            .map(|(pid, threads)| {
+                let (func_stats, samples) = self.aggregate_functions(|t| t.pid == pid, range);
+                // The main thread's process name is the most likely to be set.
+                let name = threads
+                    .iter()
+                    .filter(|t| !t.process_name.is_empty())
+                    .max_by_key(|t| t.is_main_thread)
+                    .map(|t| t.process_name.clone());
+                let mut threads: Vec<ProcessThread> = threads
+                    .into_iter()
+                    .map(|t| ProcessThread {
+                        name: t.name.clone(),
+                        tid: t.tid.clone(),
+                        is_main: t.is_main_thread,
+                        samples: t.samples_in(range).map(|(_, weight)| weight).sum(),
+                    })
+                    .collect();
+                threads.sort_by(|a, b| {
+                    b.samples
+                        .cmp(&a.samples)
+                        .then_with(|| a.name.cmp(&b.name))
+                        .then_with(|| a.tid.cmp(&b.tid))
+                });
+                ProcessEntry {
+                    pid: pid.to_string(),
+                    name,
+                    samples,
+                    percent: 0.0,
+                    threads,
+                    hotspots: self.hotspot_entries(func_stats, samples, limit, false, false),
+                }
+            })
+            .collect();
+
+        let total_samples: i64 = processes.iter().map(|p| p.samples).sum();
+        for process in &mut processes {
+            if total_samples > 0 {
+                process.percent = 100.0 * process.samples as f64 / total_samples as f64;
+            }
+        }
+        processes.sort_by(|a, b| b.samples.cmp(&a.samples).then_with(|| a.pid.cmp(&b.pid)));
+
+        ProcessesResponse {
+            total_samples,
+            processes,
+        }
+    }
+
+    /// A view of the profile with only the threads of the process `process`,
+    /// given as the pid or a substring of the process name. Every query of the
+    /// view only sees those threads. The threads' data is shared, not copied.
+    pub fn for_process(&self, process: &str) -> ProfileAnalyzer {
+        self.with_threads(|thread| thread.in_process(process))
+    }
+
+    /// A view of the profile with only the threads whose name contains one of
+    /// `threads`, in one of the `processes`, like [`for_process`](Self::for_process).
+    /// An empty list leaves the threads unrestricted.
+    pub fn for_threads(&self, threads: &[String], processes: &[String]) -> ProfileAnalyzer {
+        self.with_threads(|thread| {
+            let name_matches = threads
+                .iter()
+                .any(|name| thread.name.contains(name.as_str()));
+            let process_matches = processes.iter().any(|process| thread.in_process(process));
+            (threads.is_empty() || name_matches) && (processes.is_empty() || process_matches)
+        })
+    }
+}
@@ -0,0 +1,225 @@
+//! Time spent in signal handlers, per signal.
+//!
+//! A sample is in a signal handler if its stack goes through a signal
+//! trampoline, the frame which the kernel makes a handler return to
+//! (`__restore_rt` with glibc, `__kernel_rt_sigreturn` in the arm64 vDSO,
+//! `_sigtramp` on macOS). The handler is the function which the innermost
+//! trampoline "calls".
+//!
+//! Which signal a handler sample belongs to comes from the signal delivery
+//! markers ("Signal" markers, from the `signal:signal_deliver` tracepoint):
+//! it is the last signal delivered to the thread before the sample. Without
+//! them, handler time is still reported, as signal "unknown".
+
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::{ProfileAnalyzer, ThreadData, TimeRange};
+
+/// Functions which signal handlers return to
+pub const SIGNAL_TRAMPOLINES: &[&str] = &["__restore_rt", "__kernel_rt_sigreturn", "_sigtramp"];
+
+/// The name of handler samples which follow no delivery marker
+const UNKNOWN_SIGNAL: &str = "unknown";
+
+/// A signal handler function, with its samples
+#[derive(Debug, Clone, Serialize)]
+pub struct SignalHandler {
+    pub name: String,
+    pub samples: i64,
+    /// Share of the signal's handler samples
+    pub percent: f64,
+}
+
+/// One signal, from a signals query
+#[derive(Debug, Clone, Serialize)]
+pub struct SignalEntry {
+    /// e.g. "SIGSEGV"
+    pub signal: String,
+    /// Delivery markers in the range
+    pub deliveries: usize,
+    pub handler_samples: i64,
+    /// Share of all samples
+    pub percent: f64,
+    /// By samples, largest first
+    pub handlers: Vec<SignalHandler>,
+}
+
+/// Response for signals query
+#[derive(Debug, Clone, Serialize)]
+pub struct SignalsResponse {
+    pub total_samples: i64,
+    pub handler_samples: i64,
+    pub handler_percent: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread: Option<String>,
+    /// By handler samples, then deliveries, largest first
+    pub signals: Vec<SignalEntry>,
+    /// Why the signals can't be told apart, if they can't
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+#[derive(Default)]
+struct SignalStats {
+    deliveries: usize,
+    handlers: HashMap<String, i64>,
+}
+
+impl ThreadData {
+    /// The function which the innermost signal trampoline of the stack calls,
+    /// or the trampoline itself if it is the leaf
+    fn signal_handler_func(&self, stack_idx: usize, trampolines: &HashSet<usize>) -> Option<usize> {
+        let mut callee = None;
+        let mut stack = Some(stack_idx);
+        while let Some(idx) = stack {
+            let func_idx = self.get_frame_func(self.get_stack_frame(idx));
+            if trampolines.contains(&func_idx) {
+                return Some(callee.unwrap_or(func_idx));
+            }
+            callee = Some(func_idx);
+            stack = self.get_stack_prefix(idx);
+        }
+        None
+    }
+
+    /// The signal delivery markers in `range`, as (time, signal name), by time
+    fn signal_deliveries(&self, strings: &[String], range: TimeRange) -> Vec<(f64, String)> {
+        let mut deliveries: Vec<(f64, String)> = self
+            .markers
+            .iter()
+            .filter(|marker| {
+                let data = marker.data.as_ref();
+                data.and_then(|data| data.get("type")?.as_str()) == Some("Signal")
+            })
+            .filter_map(|marker| {
+                let time = marker.start_ms.or(marker.end_ms)?;
+                Some((time, self.get_string(marker.name_idx, strings)))
+            })
+            .filter(|&(time, _)| range.contains(time))
+            .collect();
+        deliveries.sort_by(|a, b| a.0.total_cmp(&b.0));
+        deliveries
+    }
+}
+
+impl ProfileAnalyzer {
+    /// The signal deliveries and the samples in signal handlers of the
+    /// threads whose name contains `thread_filter`, per signal, with the top
+    /// `limit` handler functions of each. Only samples in `range` are counted.
+    pub fn signal_breakdown(
+        &self,
+        limit: usize,
+        thread_filter: Option<&str>,
+        range: TimeRange,
+    ) -> SignalsResponse {
+        let mut total_samples = 0;
+        let mut has_deliveries = false;
+        let mut signals: HashMap<String, SignalStats> = HashMap::new();
+
+        for thread in &self.threads {
+            if !thread.matches_filter(thread_filter) {
+                continue;
+            }
+            let deliveries = thread.signal_deliveries(&self.global_strings, range);
+            has_deliveries |= !deliveries.is_empty();
+            for (_, signal) in &deliveries {
+                signals.entry(signal.clone()).or_default().deliveries += 1;
+            }
+
+            let trampolines: HashSet<usize> = (0..thread.func_name_idx.len())
+                .filter(|&func_idx| {
+                    let name = thread.get_func_name(func_idx, &self.global_strings);
+                    SIGNAL_TRAMPOLINES.contains(&name.as_str())
+                })
+                .collect();
+            let mut handler_funcs: HashMap<usize, Option<usize>> = HashMap::new();
+            for (i, (stack_idx, weight)) in thread.samples().enumerate() {
+                let time = thread.sample_time.get(i);
+                if !range.is_unbounded() && !time.is_some_and(|t| range.contains(t)) {
+                    continue;
+                }
+                total_samples += weight;
+                let Some(stack_idx) = stack_idx else {
+                    continue;
+                };
+                if trampolines.is_empty() {
+                    continue;
+                }
+                let handler = *handler_funcs
+                    .entry(stack_idx)
+                    .or_insert_with(|| thread.signal_handler_func(stack_idx, &trampolines));
+                let Some(handler) = handler else {
+                    continue;
+                };
+                // The last signal delivered at or before the sample
+                let delivered = time.and_then(|time| {
+                    let count = deliveries.partition_point(|(t, _)| *t <= time);
+                    count.checked_sub(1).map(|i| deliveries[i].1.as_str())
+                });
+                let name = thread.get_func_name(handler, &self.global_strings);
+                *signals
+                    .entry(delivered.unwrap_or(UNKNOWN_SIGNAL).to_string())
+                    .or_default()
+                    .handlers
+                    .entry(name)
+                    .or_default() += weight;
+            }
+        }
+
+        let percent = |samples: i64, total: i64| {
+            if total > 0 {
+                100.0 * samples as f64 / total as f64
+            } else {
+                0.0
+            }
+        };
+        let mut signals: Vec<SignalEntry> = signals
+            .into_iter()
+            .map(|(signal, stats)| {
+                let handler_samples: i64 = stats.handlers.values().sum();
+                let mut handlers: Vec<SignalHandler> = stats
+                    .handlers
+                    .into_iter()
+                    .map(|(name, samples)| SignalHandler {
+                        name,
+                        samples,
+                        percent: percent(samples, handler_samples),
+                    })
+                    .collect();
+                handlers
+                    .sort_by(|a, b| b.samples.cmp(&a.samples).then_with(|| a.name.cmp(&b.name)));
+                handlers.truncate(limit);
+                SignalEntry {
+                    signal,
+                    deliveries: stats.deliveries,
+                    handler_samples,
+                    percent: percent(handler_samples, total_samples),
+                    handlers,
+                }
+            })
+            .collect();
+        signals.sort_by(|a, b| {
+            b.handler_samples
+                .cmp(&a.handler_samples)
+                .then_with(|| b.deliveries.cmp(&a.deliveries))
+                .then_with(|| a.signal.cmp(&b.signal))
+        });
+        let handler_samples = signals.iter().map(|s| s.handler_samples).sum();
+
+        SignalsResponse {
+            total_samples,
+            handler_samples,
+            handler_percent: percent(handler_samples, total_samples),
+            thread: thread_filter.map(|t| t.to_string()),
+            signals,
+            note: (!has_deliveries).then(|| {
+                "The profile has no signal delivery markers, so handler samples can't be \
+                 attributed to signals. Import a perf.data recorded with \
+                 -e signal:signal_deliver to get them."
+                    .to_string()
+            }),
+        }
+    }
+}
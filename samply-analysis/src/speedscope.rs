@@ -0,0 +1,134 @@
+//! Exporting the profile in speedscope's file format
+//! (<https://www.speedscope.app/file-format-schema.json>), with one "sampled"
+//! profile per thread.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{ProfileAnalyzer, TimeRange};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpeedscopeFile {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    shared: Shared,
+    profiles: Vec<SampledProfile>,
+    name: String,
+    active_profile_index: usize,
+    exporter: &'static str,
+}
+
+#[derive(Serialize)]
+struct Shared {
+    frames: Vec<Frame>,
+}
+
+#[derive(Serialize)]
+struct Frame {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SampledProfile {
+    #[serde(rename = "type")]
+    type_: &'static str,
+    name: String,
+    unit: &'static str,
+    start_value: f64,
+    end_value: f64,
+    /// Frame indexes of each sample's stack, root first
+    samples: Vec<Vec<usize>>,
+    weights: Vec<f64>,
+}
+
+impl ProfileAnalyzer {
+    /// A speedscope JSON file with the samples of the threads whose name
+    /// contains `thread_filter` in `range`, in sample order. Weights are in
+    /// milliseconds if the profile has a sampling interval.
+    pub(crate) fn export_speedscope(
+        &self,
+        thread_filter: Option<&str>,
+        range: TimeRange,
+    ) -> String {
+        let (unit, weight_factor) = if self.sampling_interval_ms > 0.0 {
+            ("milliseconds", self.sampling_interval_ms)
+        } else {
+            ("none", 1.0)
+        };
+
+        let mut frames: Vec<Frame> = Vec::new();
+        let mut frame_indexes: HashMap<(String, Option<String>), usize> = HashMap::new();
+        let mut profiles = Vec::new();
+
+        for thread in &self.threads {
+            if !thread.matches_filter(thread_filter) {
+                continue;
+            }
+
+            // Frame index of each function of this thread, and root-first
+            // stacks of each stack index, filled in as they're needed.
+            let mut func_frames: HashMap<usize, usize> = HashMap::new();
+            let mut stacks: HashMap<usize, Vec<usize>> = HashMap::new();
+            let mut profile = SampledProfile {
+                type_: "sampled",
+                name: format!("{} ({})", thread.name, thread.tid),
+                unit,
+                start_value: 0.0,
+                end_value: 0.0,
+                samples: Vec::new(),
+                weights: Vec::new(),
+            };
+
+            for (stack_idx, weight) in thread.samples_in(range) {
+                let Some(stack_idx) = stack_idx else {
+                    continue;
+                };
+                if weight <= 0 {
+                    continue;
+                }
+                let stack = stacks.entry(stack_idx).or_insert_with(|| {
+                    thread
+                        .walk_stack(stack_idx)
+                        .into_iter()
+                        .rev()
+                        .map(|func_idx| {
+                            *func_frames.entry(func_idx).or_insert_with(|| {
+                                let name = thread.get_func_name(func_idx, &self.global_strings);
+                                let file = thread.get_func_file(func_idx, &self.global_strings);
+                                *frame_indexes
+                                    .entry((name.clone(), file.clone()))
+                                    .or_insert_with(|| {
+                                        frames.push(Frame { name, file });
+                                        frames.len() - 1
+                                    })
+                            })
+                        })
+                        .collect()
+                });
+                let weight = weight as f64 * weight_factor;
+                profile.samples.push(stack.clone());
+                profile.weights.push(weight);
+                profile.end_value += weight;
+            }
+
+            if !profile.samples.is_empty() {
+                profiles.push(profile);
+            }
+        }
+
+        let file = SpeedscopeFile {
+            schema: "https://www.speedscope.app/file-format-schema.json",
+            shared: Shared { frames },
+            profiles,
+            name: self.product_name.clone(),
+            active_profile_index: 0,
+            exporter: concat!("samply-for-ai@", env!("CARGO_PKG_VERSION")),
+        };
+        serde_json::to_string(&file).unwrap_or_default()
+    }
+}
@@ -0,0 +1,282 @@
+//! Structural statistics of the sampled stacks: how deep they are, how bushy
+//! the call tree is, and how much of it is kernel code. They are cheap to
+//! compute and help to pick sensible `depth` / `threshold` parameters for the
+//! other queries.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+
+use crate::{ProfileAnalyzer, ThreadData, TimeRange};
+
+/// Sample-weighted stack depth percentiles
+#[derive(Debug, Clone, Serialize)]
+pub struct DepthStats {
+    pub mean: f64,
+    pub p50: usize,
+    pub p90: usize,
+    pub p99: usize,
+    pub max: usize,
+}
+
+/// Samples whose stack depth is in `min_depth..=max_depth`
+#[derive(Debug, Clone, Serialize)]
+pub struct DepthBucket {
+    pub min_depth: usize,
+    pub max_depth: usize,
+    pub samples: i64,
+}
+
+/// Stack shape statistics of one thread, or of all threads together
+#[derive(Debug, Clone, Serialize)]
+pub struct StackStats {
+    /// Samples which have a stack
+    pub samples: i64,
+    /// Distinct sampled stacks
+    pub unique_stacks: usize,
+    pub depth: DepthStats,
+    /// Samples per depth range, in power-of-two buckets (1, 2-3, 4-7, ...)
+    pub depth_histogram: Vec<DepthBucket>,
+    /// Nodes of the call tree merged by function
+    pub call_tree_nodes: usize,
+    /// Average number of children of the call tree nodes which have any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_branching_factor: Option<f64>,
+    /// Kernel and user frames, summed over all samples
+    pub kernel_frames: i64,
+    pub user_frames: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kernel_to_user_ratio: Option<f64>,
+    /// Percentage of samples whose leaf frame is in the kernel
+    pub kernel_leaf_percent: f64,
+}
+
+/// Stack shape statistics of a thread
+#[derive(Debug, Clone, Serialize)]
+pub struct ThreadStackStats {
+    pub name: String,
+    pub pid: String,
+    pub tid: String,
+    #[serde(flatten)]
+    pub stats: StackStats,
+}
+
+/// Response for stack-stats query
+#[derive(Debug, Clone, Serialize)]
+pub struct StackStatsResponse {
+    pub overall: StackStats,
+    /// Threads with samples, by sample count descending
+    pub threads: Vec<ThreadStackStats>,
+}
+
+/// Library names of kernel code, for profiles without frame categories
+pub(crate) fn is_kernel_library(name: &str) -> bool {
+    name.starts_with("[kernel")
+        || name == "vmlinux"
+        || name.ends_with(".ko")
+        || name.eq_ignore_ascii_case("ntoskrnl.exe")
+}
+
+/// Raw counts which [`StackStats`] are computed from; they can be summed
+/// across threads.
+#[derive(Default)]
+struct StackShape {
+    samples: i64,
+    /// Samples per stack depth
+    depth_samples: BTreeMap<usize, i64>,
+    unique_stacks: usize,
+    call_tree_nodes: usize,
+    /// Call tree nodes which have children, and the number of such children
+    internal_nodes: usize,
+    child_edges: usize,
+    kernel_frames: i64,
+    user_frames: i64,
+    kernel_leaf_samples: i64,
+}
+
+impl StackShape {
+    fn add(&mut self, other: &StackShape) {
+        self.samples += other.samples;
+        for (&depth, &samples) in &other.depth_samples {
+            *self.depth_samples.entry(depth).or_default() += samples;
+        }
+        self.unique_stacks += other.unique_stacks;
+        self.call_tree_nodes += other.call_tree_nodes;
+        self.internal_nodes += other.internal_nodes;
+        self.child_edges += other.child_edges;
+        self.kernel_frames += other.kernel_frames;
+        self.user_frames += other.user_frames;
+        self.kernel_leaf_samples += other.kernel_leaf_samples;
+    }
+
+    /// The smallest depth such that `percent`% of the samples are at most that deep
+    fn depth_percentile(&self, percent: f64) -> usize {
+        let target = ((percent / 100.0) * self.samples as f64).ceil().max(1.0) as i64;
+        let mut cumulative = 0;
+        for (&depth, &samples) in &self.depth_samples {
+            cumulative += samples;
+            if cumulative >= target {
+                return depth;
+            }
+        }
+        self.depth_samples.keys().next_back().copied().unwrap_or(0)
+    }
+
+    fn stats(&self) -> StackStats {
+        let weighted_depth: f64 = self
+            .depth_samples
+            .iter()
+            .map(|(&depth, &samples)| depth as f64 * samples as f64)
+            .sum();
+
+        let mut depth_histogram: Vec<DepthBucket> = Vec::new();
+        for (&depth, &samples) in &self.depth_samples {
+            let min_depth = if depth == 0 {
+                0
+            } else {
+                1 << (usize::BITS - 1 - depth.leading_zeros())
+            };
+            match depth_histogram.last_mut() {
+                Some(bucket) if bucket.min_depth == min_depth => bucket.samples += samples,
+                _ => depth_histogram.push(DepthBucket {
+                    min_depth,
+                    max_depth: (min_depth * 2).saturating_sub(1).max(min_depth),
+                    samples,
+                }),
+            }
+        }
+
+        StackStats {
+            samples: self.samples,
+            unique_stacks: self.unique_stacks,
+            depth: DepthStats {
+                mean: if self.samples > 0 {
+                    weighted_depth / self.samples as f64
+                } else {
+                    0.0
+                },
+                p50: self.depth_percentile(50.0),
+                p90: self.depth_percentile(90.0),
+                p99: self.depth_percentile(99.0),
+                max: self.depth_samples.keys().next_back().copied().unwrap_or(0),
+            },
+            depth_histogram,
+            call_tree_nodes: self.call_tree_nodes,
+            avg_branching_factor: (self.internal_nodes > 0)
+                .then(|| self.child_edges as f64 / self.internal_nodes as f64),
+            kernel_frames: self.kernel_frames,
+            user_frames: self.user_frames,
+            kernel_to_user_ratio: (self.user_frames > 0)
+                .then(|| self.kernel_frames as f64 / self.user_frames as f64),
+            kernel_leaf_percent: if self.samples > 0 {
+                100.0 * self.kernel_leaf_samples as f64 / self.samples as f64
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+impl ProfileAnalyzer {
+    fn is_kernel_frame(&self, thread: &ThreadData, frame_idx: usize, func_idx: usize) -> bool {
+        if let Some(category) = thread
+            .get_frame_category(frame_idx)
+            .and_then(|c| self.categories.get(c))
+        {
+            return category == "Kernel";
+        }
+        thread
+            .get_func_lib_index(func_idx)
+            .and_then(|lib_idx| self.libs.get(lib_idx))
+            .is_some_and(|lib| is_kernel_library(&lib.name))
+    }
+
+    fn thread_stack_shape(&self, thread: &ThreadData, range: TimeRange) -> StackShape {
+        let mut shape = StackShape::default();
+
+        let mut stack_weights: HashMap<usize, i64> = HashMap::new();
+        for (stack_idx, weight) in thread.samples_in(range) {
+            if let Some(stack_idx) = stack_idx {
+                *stack_weights.entry(stack_idx).or_default() += weight;
+            }
+        }
+        shape.unique_stacks = stack_weights.len();
+
+        // Call tree merged by function: (parent node, func) -> node
+        let mut nodes: HashMap<(Option<usize>, usize), usize> = HashMap::new();
+        let mut child_counts: HashMap<usize, usize> = HashMap::new();
+
+        for (stack_idx, weight) in stack_weights {
+            let frames = thread.walk_stack_with_frames(stack_idx);
+            shape.samples += weight;
+            *shape.depth_samples.entry(frames.len()).or_default() += weight;
+
+            let mut kernel_frames = 0;
+            for (i, &(func_idx, frame_idx)) in frames.iter().enumerate() {
+                if self.is_kernel_frame(thread, frame_idx, func_idx) {
+                    kernel_frames += 1;
+                    if i == 0 {
+                        shape.kernel_leaf_samples += weight;
+                    }
+                }
+            }
+            shape.kernel_frames += weight * kernel_frames;
+            shape.user_frames += weight * (frames.len() as i64 - kernel_frames);
+
+            let mut parent = None;
+            for &(func_idx, _) in frames.iter().rev() {
+                let next_id = nodes.len();
+                let node = *nodes.entry((parent, func_idx)).or_insert_with(|| {
+                    if let Some(parent) = parent {
+                        *child_counts.entry(parent).or_default() += 1;
+                    }
+                    next_id
+                });
+                parent = Some(node);
+            }
+        }
+
+        shape.call_tree_nodes = nodes.len();
+        shape.internal_nodes = child_counts.len();
+        shape.child_edges = child_counts.values().sum();
+        shape
+    }
+
+    /// Stack depth distribution, call tree branching, unique stacks and
+    /// kernel/user frame counts, overall and per thread. Only threads whose
+    /// name contains `thread_filter` and samples in `range` are counted.
+    pub fn stack_stats(&self, thread_filter: Option<&str>, range: TimeRange) -> StackStatsResponse {
+        let mut overall = StackShape::default();
+        let mut threads: Vec<ThreadStackStats> = Vec::new();
+
+        for thread in &self.threads {
+            if !thread.matches_filter(thread_filter) {
+                continue;
+            }
+            let shape = self.thread_stack_shape(thread, range);
+            if shape.samples == 0 {
+                continue;
+            }
+            overall.add(&shape);
+            threads.push(ThreadStackStats {
+                name: thread.name.clone(),
+                pid: thread.pid.clone(),
+                tid: thread.tid.clone(),
+                stats: shape.stats(),
+            });
+        }
+
+        threads.sort_by(|a, b| {
+            b.stats
+                .samples
+                .cmp(&a.stats.samples)
+                .then_with(|| a.name.cmp(&b.name))
+                .then_with(|| a.tid.cmp(&b.tid))
+        });
+
+        StackStatsResponse {
+            overall: overall.stats(),
+            threads,
+        }
+    }
+}
@@ -0,0 +1,335 @@
+//! Linking samples to distributed traces: the spans of a trace are the
+//! interval markers which carry its W3C trace context
+//! (<https://www.w3.org/TR/trace-context/>), and the samples of a span are
+//! those taken on the marker's thread while it was open.
+//!
+//! A marker carries a trace context if its name or one of its payload's
+//! string fields contains a `traceparent` value, or if its payload has
+//! `traceId` / `trace_id` (and optionally `spanId` / `span_id`) fields, as
+//! written by tracing-span exporters.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{MarkerData, ProfileAnalyzer, ThreadData, TimeRange};
+
+/// The trace and span id of a W3C `traceparent` value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    /// 32 lowercase hex digits
+    pub trace_id: String,
+    /// 16 lowercase hex digits; tracing payloads may only have a trace id
+    pub span_id: Option<String>,
+}
+
+fn is_hex_id(s: &str, len: usize) -> bool {
+    s.len() == len && s.bytes().all(|b| b.is_ascii_hexdigit()) && s.bytes().any(|b| b != b'0')
+}
+
+/// The first of the string fields `keys` of a marker payload
+fn str_field<'a>(
+    fields: &'a serde_json::Map<String, serde_json::Value>,
+    keys: &[&str],
+) -> Option<&'a str> {
+    keys.iter()
+        .find_map(|key| fields.get(*key).and_then(|v| v.as_str()))
+}
+
+impl TraceContext {
+    /// Parse a `traceparent` value, `<version>-<trace-id>-<parent-id>-<flags>`.
+    /// Versions after 00 may append more fields.
+    pub fn parse(traceparent: &str) -> Option<Self> {
+        let mut parts = traceparent.trim().split('-');
+        let version = parts.next()?;
+        let trace_id = parts.next()?;
+        let span_id = parts.next()?;
+        let flags = parts.next()?;
+        let valid = version.len() == 2
+            && version.bytes().all(|b| b.is_ascii_hexdigit())
+            && !version.eq_ignore_ascii_case("ff")
+            && (parts.next().is_none() || version != "00")
+            && is_hex_id(trace_id, 32)
+            && is_hex_id(span_id, 16)
+            && flags.len() == 2
+            && flags.bytes().all(|b| b.is_ascii_hexdigit());
+        valid.then(|| TraceContext {
+            trace_id: trace_id.to_ascii_lowercase(),
+            span_id: Some(span_id.to_ascii_lowercase()),
+        })
+    }
+
+    /// The first `traceparent` value among the whitespace-separated words of
+    /// `text`, which may be written as `traceparent=<value>`.
+    pub fn find_in(text: &str) -> Option<Self> {
+        text.split_whitespace().find_map(|word| {
+            let word = word.strip_prefix("traceparent=").unwrap_or(word);
+            Self::parse(word.trim_matches(|c: char| !c.is_ascii_alphanumeric()))
+        })
+    }
+
+    /// The text without the `traceparent` value, for the name of a span
+    pub fn strip_from(text: &str) -> String {
+        text.split_whitespace()
+            .filter(|word| Self::find_in(word).is_none())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The trace context of a marker, from its payload or name
+    fn of_marker(name: &str, data: Option<&serde_json::Value>) -> Option<Self> {
+        if let Some(serde_json::Value::Object(fields)) = data {
+            if let Some(context) = str_field(fields, &["traceparent"]).and_then(Self::parse) {
+                return Some(context);
+            }
+            let trace_id = str_field(fields, &["traceId", "trace_id"]);
+            if let Some(trace_id) = trace_id.filter(|id| is_hex_id(id, 32)) {
+                return Some(TraceContext {
+                    trace_id: trace_id.to_ascii_lowercase(),
+                    span_id: str_field(fields, &["spanId", "span_id"])
+                        .filter(|id| is_hex_id(id, 16))
+                        .map(|id| id.to_ascii_lowercase()),
+                });
+            }
+            if let Some(context) = fields
+                .values()
+                .filter_map(|v| v.as_str())
+                .find_map(Self::find_in)
+            {
+                return Some(context);
+            }
+        }
+        Self::find_in(name)
+    }
+}
+
+/// One span of a trace, from a by-trace query
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceSpan {
+    pub name: String,
+    pub thread: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub span_id: Option<String>,
+    pub start_ms: f64,
+    pub end_ms: f64,
+    pub duration_ms: f64,
+    /// Samples of the span's thread while it was open
+    pub samples: i64,
+}
+
+/// A stack sampled while spans of the trace were open
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceStack {
+    /// Function names, root first
+    pub stack: Vec<String>,
+    pub samples: i64,
+    /// Share of the trace's samples
+    pub percent: f64,
+}
+
+/// Response for by-trace query
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceStacksResponse {
+    pub trace_id: String,
+    /// Samples taken while at least one of the trace's spans was open on
+    /// their thread; nested spans don't count a sample twice
+    pub total_samples: i64,
+    /// Interval markers of the trace, by start time
+    pub spans: Vec<TraceSpan>,
+    /// The heaviest stacks, largest first
+    pub stacks: Vec<TraceStack>,
+}
+
+/// Trace ids with the number of spans of each, for a by-trace query without
+/// a trace id
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceEntry {
+    pub trace_id: String,
+    pub spans: usize,
+    pub total_duration_ms: f64,
+}
+
+/// Response for by-trace query without a trace id
+#[derive(Debug, Clone, Serialize)]
+pub struct TracesResponse {
+    /// Traces by total span duration, largest first
+    pub traces: Vec<TraceEntry>,
+}
+
+/// The (start, end) of a marker, if it's an interval marker overlapping `range`
+fn interval_in(marker: &MarkerData, range: TimeRange) -> Option<(f64, f64)> {
+    let (start, end) = marker.start_ms.zip(marker.end_ms)?;
+    range.overlaps(start, end).then_some((start, end))
+}
+
+impl ThreadData {
+    /// The trace context of each interval marker overlapping `range`
+    fn trace_spans<'a>(
+        &'a self,
+        global_strings: &'a [String],
+        range: TimeRange,
+    ) -> impl Iterator<Item = (&'a MarkerData, TraceContext, (f64, f64))> + 'a {
+        self.markers.iter().filter_map(move |marker| {
+            let interval = interval_in(marker, range)?;
+            let name = self.get_string(marker.name_idx, global_strings);
+            let context = TraceContext::of_marker(&name, marker.data.as_ref())?;
+            Some((marker, context, interval))
+        })
+    }
+}
+
+impl ProfileAnalyzer {
+    /// The traces whose spans overlap `range`, on threads whose name contains
+    /// `thread_filter`; at most `limit` of them.
+    pub fn list_traces(
+        &self,
+        limit: usize,
+        thread_filter: Option<&str>,
+        range: TimeRange,
+    ) -> TracesResponse {
+        let mut traces: HashMap<String, (usize, f64)> = HashMap::new();
+        for thread in &self.threads {
+            if !thread.matches_filter(thread_filter) {
+                continue;
+            }
+            for (_, context, (start, end)) in thread.trace_spans(&self.global_strings, range) {
+                let (spans, duration) = traces.entry(context.trace_id).or_default();
+                *spans += 1;
+                *duration += end - start;
+            }
+        }
+        let mut traces: Vec<TraceEntry> = traces
+            .into_iter()
+            .map(|(trace_id, (spans, total_duration_ms))| TraceEntry {
+                trace_id,
+                spans,
+                total_duration_ms,
+            })
+            .collect();
+        traces.sort_by(|a, b| {
+            b.total_duration_ms
+                .total_cmp(&a.total_duration_ms)
+                .then_with(|| a.trace_id.cmp(&b.trace_id))
+        });
+        traces.truncate(limit);
+        TracesResponse { traces }
+    }
+
+    /// The spans of the trace `trace_id` (or of the `traceparent` value), and
+    /// the `limit` heaviest stacks sampled on each span's thread while it was
+    /// open. Only threads whose name contains `thread_filter` and samples in
+    /// `range` are counted.
+    pub fn stacks_by_trace(
+        &self,
+        trace_id: &str,
+        limit: usize,
+        thread_filter: Option<&str>,
+        range: TimeRange,
+    ) -> TraceStacksResponse {
+        let trace_id = TraceContext::parse(trace_id)
+            .map_or_else(|| trace_id.trim().to_ascii_lowercase(), |c| c.trace_id);
+
+        let mut spans = Vec::new();
+        let mut stack_samples: HashMap<Vec<String>, i64> = HashMap::new();
+        let mut total_samples = 0;
+
+        for thread in &self.threads {
+            if !thread.matches_filter(thread_filter) {
+                continue;
+            }
+            let intervals: Vec<(f64, f64, usize)> = thread
+                .trace_spans(&self.global_strings, range)
+                .filter(|(_, context, _)| context.trace_id == trace_id)
+                .map(|(marker, context, (start, end))| {
+                    // Simple markers keep the text of the span in their payload
+                    let name = marker
+                        .data
+                        .as_ref()
+                        .and_then(|data| data.get("name"))
+                        .and_then(|name| name.as_str())
+                        .map_or_else(
+                            || thread.get_string(marker.name_idx, &self.global_strings),
+                            str::to_string,
+                        );
+                    spans.push(TraceSpan {
+                        name: TraceContext::strip_from(&name),
+                        thread: thread.name.clone(),
+                        span_id: context.span_id,
+                        start_ms: start,
+                        end_ms: end,
+                        duration_ms: end - start,
+                        samples: 0,
+                    });
+                    (start, end, spans.len() - 1)
+                })
+                .collect();
+            if intervals.is_empty() {
+                continue;
+            }
+
+            // Samples of this thread in the range, with their time
+            let samples = thread
+                .samples()
+                .zip(thread.sample_time.iter())
+                .filter(|(_, time)| range.contains(*time));
+            let mut stacks: HashMap<usize, i64> = HashMap::new();
+            for ((stack_idx, weight), time) in samples {
+                let mut in_trace = false;
+                for &(start, end, span) in &intervals {
+                    if time >= start && time < end {
+                        spans[span].samples += weight;
+                        in_trace = true;
+                    }
+                }
+                if !in_trace {
+                    continue;
+                }
+                total_samples += weight;
+                if let Some(stack_idx) = stack_idx {
+                    *stacks.entry(stack_idx).or_default() += weight;
+                }
+            }
+            for (stack_idx, weight) in stacks {
+                let stack: Vec<String> = thread
+                    .walk_stack(stack_idx)
+                    .into_iter()
+                    .rev()
+                    .map(|func_idx| thread.get_func_name(func_idx, &self.global_strings))
+                    .collect();
+                *stack_samples.entry(stack).or_default() += weight;
+            }
+        }
+
+        spans.sort_by(|a, b| {
+            a.start_ms
+                .total_cmp(&b.start_ms)
+                .then_with(|| a.thread.cmp(&b.thread))
+        });
+
+        let mut stacks: Vec<TraceStack> = stack_samples
+            .into_iter()
+            .map(|(stack, samples)| TraceStack {
+                stack,
+                samples,
+                percent: if total_samples > 0 {
+                    100.0 * samples as f64 / total_samples as f64
+                } else {
+                    0.0
+                },
+            })
+            .collect();
+        stacks.sort_by(|a, b| {
+            b.samples
+                .cmp(&a.samples)
+                .then_with(|| a.stack.cmp(&b.stack))
+        });
+        stacks.truncate(limit);
+
+        TraceStacksResponse {
+            trace_id,
+            total_samples,
+            spans,
+            stacks,
+        }
+    }
+}
@@ -0,0 +1,268 @@
+//! The wait-for graph: which threads wake which other threads, from the
+//! wakeup markers which are recorded along with context switches (the
+//! "Wakeup" markers written with `--cswitch-markers` on Windows). A wakeup
+//! marker is on the woken thread, and its `waker_tid` is the thread which
+//! made it ready to run, e.g. by releasing a lock or signalling an event.
+//!
+//! An edge from thread A to thread B means that A waited for B. Where A
+//! waited is found from A's first sample at or after the wakeup: with off-CPU
+//! samples, that is the stack it slept on, and the wait site is the caller of
+//! its innermost blocking or idle functions (see `activity.rs`).
+//!
+//! A waker which wakes several threads, and is what most of their wakeups
+//! come from, is reported as a convoy: the threads' work is serialized behind
+//! that one thread, e.g. a pool whose workers all wait for one producer or
+//! one lock holder.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{ProfileAnalyzer, ThreadData, TimeRange};
+
+/// A waker is a convoy if it wakes at least this many threads...
+const CONVOY_MIN_THREADS: usize = 3;
+/// ...and at least this share of their wakeups come from it.
+const CONVOY_MIN_PERCENT: f64 = 50.0;
+/// Wait sites per edge
+const WAIT_SITES_PER_EDGE: usize = 3;
+
+/// Where a thread waited before it was woken
+#[derive(Debug, Clone, Serialize)]
+pub struct WaitSite {
+    pub function: String,
+    pub wakeups: usize,
+}
+
+/// One edge of the wait-for graph: `thread` was woken by `waker_tid`
+#[derive(Debug, Clone, Serialize)]
+pub struct WaitEdge {
+    pub thread: String,
+    pub tid: String,
+    /// Not set if the waker isn't a thread of the profile, e.g. a thread of
+    /// another process
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub waker_thread: Option<String>,
+    pub waker_tid: String,
+    pub wakeups: usize,
+    /// Share of the thread's wakeups which came from this waker
+    pub percent: f64,
+    /// The functions which waited, most wakeups first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub wait_sites: Vec<WaitSite>,
+}
+
+/// A thread which most of several threads' wakeups come from
+#[derive(Debug, Clone, Serialize)]
+pub struct Convoy {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub waker_thread: Option<String>,
+    pub waker_tid: String,
+    /// The threads it wakes, most wakeups first
+    pub woken_threads: Vec<String>,
+    pub wakeups: usize,
+    /// Share of the woken threads' wakeups which came from this waker
+    pub percent: f64,
+}
+
+/// Response for wait-graph query
+#[derive(Debug, Clone, Serialize)]
+pub struct WaitGraphResponse {
+    pub total_wakeups: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread: Option<String>,
+    /// By wakeups, largest first
+    pub edges: Vec<WaitEdge>,
+    /// By wakeups, largest first
+    pub convoys: Vec<Convoy>,
+    /// Why the graph is empty, if it is
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+#[derive(Default)]
+struct EdgeStats {
+    wakeups: usize,
+    wait_sites: HashMap<String, usize>,
+}
+
+/// The waker of a wakeup marker's payload
+fn waker_tid(data: &serde_json::Value) -> Option<String> {
+    if data.get("type")?.as_str()? != "Wakeup" {
+        return None;
+    }
+    match data.get("waker_tid")? {
+        serde_json::Value::Number(tid) => tid
+            .as_u64()
+            .or_else(|| tid.as_f64().map(|tid| tid as u64))
+            .map(|tid| tid.to_string()),
+        serde_json::Value::String(tid) => Some(tid.clone()),
+        _ => None,
+    }
+}
+
+impl ThreadData {
+    /// The first sample at or after `time_ms`. Samples are in time order.
+    fn first_sample_at(&self, time_ms: f64) -> Option<usize> {
+        let (mut low, mut high) = (0, self.sample_time.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            if self.sample_time.get(mid).is_some_and(|t| t < time_ms) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        (low < self.sample_time.len().min(self.sample_count())).then_some(low)
+    }
+}
+
+impl ProfileAnalyzer {
+    /// The caller of the innermost blocking or idle functions on the stack,
+    /// if there are any
+    fn wait_site(&self, thread: &ThreadData, stack_idx: usize) -> Option<String> {
+        let mut stack = Some(stack_idx);
+        let mut in_wait = false;
+        while let Some(idx) = stack {
+            let func_idx = thread.get_frame_func(thread.get_stack_frame(idx));
+            let name = thread.get_func_name(func_idx, &self.global_strings);
+            let waits = self.activity_patterns.classify(&name).is_some();
+            if in_wait && !waits {
+                return Some(name);
+            }
+            in_wait |= waits;
+            stack = thread.get_stack_prefix(idx);
+        }
+        None
+    }
+
+    /// The wait-for graph of the threads whose name contains `thread_filter`,
+    /// from their wakeups in `range`: the top `limit` (thread, waker) edges,
+    /// and the convoys.
+    pub fn wait_graph(
+        &self,
+        limit: usize,
+        thread_filter: Option<&str>,
+        range: TimeRange,
+    ) -> WaitGraphResponse {
+        let names_by_tid: HashMap<&str, &str> = self
+            .threads
+            .iter()
+            .map(|t| (t.tid.as_str(), t.name.as_str()))
+            .collect();
+
+        // (woken thread index, waker tid) -> stats
+        let mut edges: HashMap<(usize, String), EdgeStats> = HashMap::new();
+        let mut thread_wakeups: HashMap<usize, usize> = HashMap::new();
+        for (thread_idx, thread) in self.threads.iter().enumerate() {
+            if !thread.matches_filter(thread_filter) {
+                continue;
+            }
+            for marker in &thread.markers {
+                let Some(waker) = marker.data.as_ref().and_then(waker_tid) else {
+                    continue;
+                };
+                let Some(time) = marker.start_ms.or(marker.end_ms) else {
+                    continue;
+                };
+                if !range.contains(time) {
+                    continue;
+                }
+                let wait_site = thread
+                    .first_sample_at(time)
+                    .and_then(|sample| thread.sample_stack.get(sample).flatten())
+                    .and_then(|stack_idx| self.wait_site(thread, stack_idx));
+                let edge = edges.entry((thread_idx, waker)).or_default();
+                edge.wakeups += 1;
+                if let Some(wait_site) = wait_site {
+                    *edge.wait_sites.entry(wait_site).or_default() += 1;
+                }
+                *thread_wakeups.entry(thread_idx).or_default() += 1;
+            }
+        }
+
+        let total_wakeups = thread_wakeups.values().sum();
+        let mut edges: Vec<WaitEdge> = edges
+            .into_iter()
+            .map(|((thread_idx, waker_tid), stats)| {
+                let thread = &self.threads[thread_idx];
+                let mut wait_sites: Vec<WaitSite> = stats
+                    .wait_sites
+                    .into_iter()
+                    .map(|(function, wakeups)| WaitSite { function, wakeups })
+                    .collect();
+                wait_sites.sort_by(|a, b| {
+                    b.wakeups
+                        .cmp(&a.wakeups)
+                        .then_with(|| a.function.cmp(&b.function))
+                });
+                wait_sites.truncate(WAIT_SITES_PER_EDGE);
+                WaitEdge {
+                    thread: thread.name.clone(),
+                    tid: thread.tid.clone(),
+                    waker_thread: names_by_tid.get(waker_tid.as_str()).map(|n| n.to_string()),
+                    waker_tid,
+                    wakeups: stats.wakeups,
+                    percent: 100.0 * stats.wakeups as f64 / thread_wakeups[&thread_idx] as f64,
+                    wait_sites,
+                }
+            })
+            .collect();
+        edges.sort_by(|a, b| {
+            b.wakeups
+                .cmp(&a.wakeups)
+                .then_with(|| a.thread.cmp(&b.thread))
+                .then_with(|| a.tid.cmp(&b.tid))
+                .then_with(|| a.waker_tid.cmp(&b.waker_tid))
+        });
+
+        let convoys = convoys(&edges);
+        edges.truncate(limit);
+
+        WaitGraphResponse {
+            total_wakeups,
+            thread: thread_filter.map(|t| t.to_string()),
+            edges,
+            convoys,
+            note: (total_wakeups == 0).then(|| {
+                "The profile has no wakeup markers. They are recorded along with the context \
+                 switch markers (--cswitch-markers) on Windows."
+                    .to_string()
+            }),
+        }
+    }
+}
+
+/// The wakers which wake at least `CONVOY_MIN_THREADS` threads, with at least
+/// `CONVOY_MIN_PERCENT` of those threads' wakeups. `edges` are sorted.
+fn convoys(edges: &[WaitEdge]) -> Vec<Convoy> {
+    let mut thread_wakeups: HashMap<&str, usize> = HashMap::new();
+    let mut by_waker: HashMap<&str, Vec<&WaitEdge>> = HashMap::new();
+    for edge in edges {
+        *thread_wakeups.entry(&edge.tid).or_default() += edge.wakeups;
+        by_waker.entry(&edge.waker_tid).or_default().push(edge);
+    }
+
+    let mut convoys: Vec<Convoy> = by_waker
+        .into_iter()
+        .filter(|(_, woken)| woken.len() >= CONVOY_MIN_THREADS)
+        .filter_map(|(waker_tid, woken)| {
+            let wakeups: usize = woken.iter().map(|e| e.wakeups).sum();
+            let woken_total: usize = woken.iter().map(|e| thread_wakeups[e.tid.as_str()]).sum();
+            let percent = 100.0 * wakeups as f64 / woken_total as f64;
+            (percent >= CONVOY_MIN_PERCENT).then(|| Convoy {
+                waker_thread: woken[0].waker_thread.clone(),
+                waker_tid: waker_tid.to_string(),
+                woken_threads: woken.iter().map(|e| e.thread.clone()).collect(),
+                wakeups,
+                percent,
+            })
+        })
+        .collect();
+    convoys.sort_by(|a, b| {
+        b.wakeups
+            .cmp(&a.wakeups)
+            .then_with(|| a.waker_tid.cmp(&b.waker_tid))
+    });
+    convoys
+}
@@ -0,0 +1,122 @@
+//! JavaScript bindings, for running queries on an already-downloaded profile
+//! in the browser without a samply server.
+//!
+//! Build with:
+//!
+//! ```sh
+//! wasm-pack build samply-analysis --target web -- --no-default-features --features wasm
+//! ```
+//!
+//! Every query returns the JSON text of the same `data` object that the
+//! server's `/query/*` endpoint returns, and omitted arguments get the same
+//! defaults.
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::{round_floats, ProfileAnalyzer, TimeRange, DEFAULT_PRECISION};
+
+/// A parsed profile which queries can be run against.
+#[wasm_bindgen]
+pub struct Profile {
+    analyzer: ProfileAnalyzer,
+}
+
+/// The JSON text of `value`, with floats rounded like the server's defaults
+fn to_json<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(mut value) => {
+            round_floats(&mut value, DEFAULT_PRECISION);
+            value.to_string()
+        }
+        Err(e) => format!("{{\"error\":\"{e}\"}}"),
+    }
+}
+
+fn time_range(start_ms: Option<f64>, end_ms: Option<f64>) -> TimeRange {
+    TimeRange { start_ms, end_ms }
+}
+
+#[wasm_bindgen]
+impl Profile {
+    /// Parse a profile from its (uncompressed) JSON text.
+    #[wasm_bindgen(constructor)]
+    pub fn new(json: &str) -> Result<Profile, JsError> {
+        let analyzer =
+            ProfileAnalyzer::from_json(json).map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(Profile { analyzer })
+    }
+
+    pub fn summary(&self, start_ms: Option<f64>, end_ms: Option<f64>) -> String {
+        to_json(&self.analyzer.get_summary(time_range(start_ms, end_ms)))
+    }
+
+    pub fn hotspots(
+        &self,
+        limit: Option<u32>,
+        thread: Option<String>,
+        start_ms: Option<f64>,
+        end_ms: Option<f64>,
+    ) -> String {
+        to_json(&self.analyzer.compute_hotspots(
+            limit.unwrap_or(20) as usize,
+            thread.as_deref(),
+            false,
+            false,
+            time_range(start_ms, end_ms),
+        ))
+    }
+
+    pub fn callers(
+        &self,
+        function: &str,
+        depth: Option<u32>,
+        limit: Option<u32>,
+        start_ms: Option<f64>,
+        end_ms: Option<f64>,
+    ) -> String {
+        to_json(&self.analyzer.find_callers(
+            function,
+            depth.unwrap_or(5) as usize,
+            limit.unwrap_or(20) as usize,
+            time_range(start_ms, end_ms),
+        ))
+    }
+
+    pub fn callees(
+        &self,
+        function: &str,
+        depth: Option<u32>,
+        limit: Option<u32>,
+        start_ms: Option<f64>,
+        end_ms: Option<f64>,
+    ) -> String {
+        to_json(&self.analyzer.find_callees(
+            function,
+            depth.unwrap_or(5) as usize,
+            limit.unwrap_or(20) as usize,
+            time_range(start_ms, end_ms),
+        ))
+    }
+
+    pub fn drilldown(&self, function: &str, depth: Option<u32>, threshold: Option<f64>) -> String {
+        to_json(&self.analyzer.drilldown(
+            function,
+            depth.unwrap_or(10) as usize,
+            threshold.unwrap_or(5.0),
+        ))
+    }
+
+    pub fn flamegraph(
+        &self,
+        thread: Option<String>,
+        inverted: Option<bool>,
+        min_percent: Option<f64>,
+    ) -> String {
+        to_json(&self.analyzer.build_flamegraph(
+            thread.as_deref(),
+            inverted.unwrap_or(false),
+            min_percent.unwrap_or(0.0),
+        ))
+    }
+}
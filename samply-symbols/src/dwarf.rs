@@ -7,7 +7,7 @@ use gimli::{DwarfPackage, EndianSlice, Reader, RunTimeEndian, SectionId};
 use object::read::ReadRef;
 use object::CompressionFormat;
 
-use crate::{demangle, Error, FrameDebugInfo, SymbolMapStringInterner};
+use crate::{demangle, Error, FrameDebugInfo, SourceFileChecksum, SymbolMapStringInterner};
 
 pub fn get_frames<R: Reader>(
     address: u64,
@@ -62,6 +62,70 @@ pub fn convert_stack_frame<R: gimli::Reader>(
     }
 }
 
+/// The MD5 which the line table of `unit` records for the file `path`, if it
+/// has MD5s (DWARF 5). `path` is matched against the full path of each file
+/// entry, as addr2line renders it in frame locations.
+pub fn source_file_checksum<R: gimli::Reader>(
+    unit: gimli::UnitRef<R>,
+    path: &str,
+) -> Option<SourceFileChecksum> {
+    let header = unit.line_program.as_ref()?.header();
+    if !header.file_has_md5() {
+        return None;
+    }
+    header
+        .file_names()
+        .iter()
+        .find(|file| file_entry_path(unit, file, header).is_ok_and(|p| p == path))
+        .map(|file| SourceFileChecksum::Md5(*file.md5()))
+}
+
+/// The full path of a line table file entry: the compilation directory,
+/// joined with the entry's directory and its path.
+fn file_entry_path<R: gimli::Reader>(
+    unit: gimli::UnitRef<R>,
+    file: &gimli::FileEntry<R>,
+    header: &gimli::LineProgramHeader<R>,
+) -> Result<String, gimli::Error> {
+    let mut path = match &unit.comp_dir {
+        Some(comp_dir) => comp_dir.to_string_lossy()?.into_owned(),
+        None => String::new(),
+    };
+    // Directory 0 is the compilation directory.
+    if file.directory_index() != 0 {
+        if let Some(directory) = file.directory(header) {
+            push_path(&mut path, &unit.attr_string(directory)?.to_string_lossy()?);
+        }
+    }
+    push_path(
+        &mut path,
+        &unit.attr_string(file.path_name())?.to_string_lossy()?,
+    );
+    Ok(path)
+}
+
+fn push_path(path: &mut String, component: &str) {
+    let is_absolute = |p: &str| {
+        p.starts_with('/')
+            || p.starts_with('\\')
+            || p.get(1..3) == Some(":/")
+            || p.get(1..3) == Some(":\\")
+    };
+    if is_absolute(component) {
+        *path = component.to_string();
+        return;
+    }
+    let separator = if path.starts_with('\\') || path.get(1..3) == Some(":\\") {
+        '\\'
+    } else {
+        '/'
+    };
+    if !path.is_empty() && !path.ends_with(separator) {
+        path.push(separator);
+    }
+    path.push_str(component);
+}
+
 pub enum SingleSectionData<'data, T: ReadRef<'data>> {
     View {
         data: T,
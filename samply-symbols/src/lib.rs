@@ -260,7 +260,8 @@ pub use crate::shared::{
     ExternalFileRef, FileAndPathHelper, FileAndPathHelperError, FileAndPathHelperResult,
     FileContents, FileContentsWrapper, FileLocation, FrameDebugInfo, FramesLookupResult,
     FunctionNameHandle, FunctionNameIndex, LibraryInfo, LookupAddress, MultiArchDisambiguator,
-    OptionallySendFuture, SymbolInfo, SymbolNameHandle, SymbolNameIndex, SyncAddressInfo,
+    OptionallySendFuture, SourceFileChecksum, SymbolInfo, SymbolNameHandle, SymbolNameIndex,
+    SyncAddressInfo,
 };
 pub use crate::source_file_path::{SourceFilePath, SourceFilePathHandle, SourceFilePathIndex};
 pub use crate::symbol_map::{AccessPatternHint, SymbolMap, SymbolMapTrait};
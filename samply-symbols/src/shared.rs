@@ -319,6 +319,15 @@ pub struct FrameDebugInfo {
     pub line_number: Option<u32>,
 }
 
+/// A checksum of a source file, as recorded in the debug info when the binary
+/// was built. Comparing it to the checksum of a local copy of the file tells
+/// whether the local copy is the one which was compiled.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum SourceFileChecksum {
+    /// DWARF 5 line tables record the MD5 of each file, e.g. with clang.
+    Md5([u8; 16]),
+}
+
 /// A trait which abstracts away the token that's passed to the [`FileAndPathHelper::load_file`]
 /// trait method.
 ///
@@ -5,8 +5,8 @@ use debugid::DebugId;
 
 use crate::{
     AddressInfo, ExternalFileAddressRef, ExternalFileRef, FileAndPathHelper, FileLocation,
-    FrameDebugInfo, FramesLookupResult, FunctionNameHandle, LookupAddress, SourceFilePath,
-    SourceFilePathHandle, SymbolNameHandle, SyncAddressInfo,
+    FrameDebugInfo, FramesLookupResult, FunctionNameHandle, LookupAddress, SourceFileChecksum,
+    SourceFilePath, SourceFilePathHandle, SymbolNameHandle, SyncAddressInfo,
 };
 
 pub trait SymbolMapTrait {
@@ -29,6 +29,17 @@ pub trait SymbolMapTrait {
     fn resolve_symbol_name(&self, handle: SymbolNameHandle) -> Cow<'_, str>;
     fn resolve_source_file_path(&self, handle: SourceFilePathHandle) -> SourceFilePath<'_>;
 
+    /// The checksum which the debug info records for the source file `path`,
+    /// in the compilation unit which contains `address`. `path` is a raw
+    /// path as returned by [`resolve_source_file_path`](Self::resolve_source_file_path).
+    fn source_file_checksum(
+        &self,
+        _address: LookupAddress,
+        _path: &str,
+    ) -> Option<SourceFileChecksum> {
+        None
+    }
+
     fn set_access_pattern_hint(&self, _hint: AccessPatternHint) {}
 }
 
@@ -245,6 +256,14 @@ impl<H: FileAndPathHelper> SymbolMap<H> {
         self.inner().resolve_source_file_path(handle)
     }
 
+    pub fn source_file_checksum(
+        &self,
+        address: LookupAddress,
+        path: &str,
+    ) -> Option<SourceFileChecksum> {
+        self.inner().source_file_checksum(address, path)
+    }
+
     pub fn set_access_pattern_hint(&self, hint: AccessPatternHint) {
         self.inner().set_access_pattern_hint(hint);
     }
@@ -279,6 +298,14 @@ impl<H: FileAndPathHelper> SymbolMapTrait for SymbolMap<H> {
         self.resolve_source_file_path(handle)
     }
 
+    fn source_file_checksum(
+        &self,
+        address: LookupAddress,
+        path: &str,
+    ) -> Option<SourceFileChecksum> {
+        self.source_file_checksum(address, path)
+    }
+
     fn set_access_pattern_hint(&self, hint: AccessPatternHint) {
         self.set_access_pattern_hint(hint);
     }
@@ -13,7 +13,7 @@ use samply_object::relative_address_base;
 use yoke::Yoke;
 use yoke_derive::Yokeable;
 
-use crate::dwarf::convert_frames;
+use crate::dwarf::{convert_frames, source_file_checksum};
 use crate::generation::SymbolMapGeneration;
 use crate::shared::{
     ExternalFileAddressInFileRef, ExternalFileAddressRef, ExternalFileRef, FramesLookupResult,
@@ -24,8 +24,9 @@ use crate::symbol_map::{
     SymbolMapTraitWithExternalFileSupport,
 };
 use crate::{
-    demangle, Error, ExternalFileSymbolMap, FileContents, FunctionNameHandle, SourceFilePath,
-    SourceFilePathHandle, SymbolMapStringInterner, SymbolNameHandle, SyncAddressInfo,
+    demangle, Error, ExternalFileSymbolMap, FileContents, FunctionNameHandle, SourceFileChecksum,
+    SourceFilePath, SourceFilePathHandle, SymbolMapStringInterner, SymbolNameHandle,
+    SyncAddressInfo,
 };
 
 enum FullSymbolListEntry<'a, Symbol> {
@@ -617,6 +618,28 @@ where
             .expect("unknown handle?");
         SourceFilePath::RawPath(raw_path.clone())
     }
+
+    fn source_file_checksum(
+        &self,
+        address: LookupAddress,
+        path: &str,
+    ) -> Option<SourceFileChecksum> {
+        let svma = match address {
+            LookupAddress::Relative(relative_address) => self
+                .image_base_address
+                .checked_add(u64::from(relative_address))?,
+            LookupAddress::Svma(svma) => svma,
+            LookupAddress::FileOffset(offset) => {
+                self.svma_file_ranges.file_offset_to_svma(offset)?
+            }
+        };
+        let context = self.context.as_ref()?.lock().unwrap();
+        // Units in split DWARF files aren't loaded for this.
+        match context.find_dwarf_and_unit(svma) {
+            LookupResult::Output(Some(unit)) => source_file_checksum(unit, path),
+            _ => None,
+        }
+    }
 }
 
 pub struct SymbolMapIter<'data, 'map, Symbol: object::ObjectSymbol<'data>> {
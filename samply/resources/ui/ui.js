@@ -0,0 +1,275 @@
+// The analysis server's built-in UI. It only uses the /query/* endpoints of
+// the server it's served from, so it works without network access.
+
+"use strict";
+
+// The UI is at <prefix>/ui, and the queries at <prefix>/query/...
+const BASE = location.pathname.replace(/\/ui\/?$/, "");
+
+// Nodes narrower than this share of the flame graph's width aren't drawn.
+const MIN_FLAME_WIDTH = 0.001;
+
+// The bearer token of a server started with --auth-token, asked for when a
+// query is rejected without it
+const AUTH_TOKEN_KEY = "samply-auth-token";
+let authToken = sessionStorage.getItem(AUTH_TOKEN_KEY);
+
+const state = {
+  view: "hotspots",
+  profile: null,
+  thread: "",
+  function: "",
+  // Whether `function` is a full name, from clicking a function, or a
+  // substring typed into the filter
+  exact: false,
+  // The flame graph node which is zoomed into
+  zoom: null,
+};
+
+function el(tag, props = {}, ...children) {
+  const element = document.createElement(tag);
+  Object.assign(element, props);
+  for (const child of children) {
+    element.append(child);
+  }
+  return element;
+}
+
+function setStatus(text, isError = false) {
+  const status = document.getElementById("status");
+  status.textContent = text;
+  status.classList.toggle("error", isError);
+}
+
+async function fetchWithAuth(url) {
+  const headers = authToken ? { Authorization: `Bearer ${authToken}` } : {};
+  const response = await fetch(url, { headers });
+  if (response.status !== 401) {
+    return response;
+  }
+  const token = prompt("This server needs its --auth-token:");
+  if (!token) {
+    return response;
+  }
+  authToken = token;
+  sessionStorage.setItem(AUTH_TOKEN_KEY, token);
+  return fetchWithAuth(url);
+}
+
+async function query(name, params = {}) {
+  const search = new URLSearchParams();
+  for (const [key, value] of Object.entries(params)) {
+    if (value !== "" && value !== null && value !== undefined) {
+      search.set(key, value);
+    }
+  }
+  const profile = state.profile ? `${encodeURIComponent(state.profile)}/` : "";
+  const response = await fetchWithAuth(`${BASE}/query/${profile}${name}?${search}`);
+  const json = await response.json();
+  if (!json.success) {
+    let message = json.error || `${name} failed with status ${response.status}`;
+    if (json.candidates && json.candidates.length) {
+      message += ` Did you mean: ${json.candidates.slice(0, 5).join(", ")}?`;
+    }
+    throw new Error(message);
+  }
+  const link = document.getElementById("profiler-link");
+  link.hidden = !json.profiler_url;
+  if (json.profiler_url) {
+    link.href = json.profiler_url;
+  }
+  return json;
+}
+
+function percent(value) {
+  return `${(value ?? 0).toFixed(1)}%`;
+}
+
+function functionLink(name) {
+  const link = el("a", { className: "function", textContent: name, title: "Callers and callees" });
+  link.addEventListener("click", () => {
+    state.function = name;
+    state.exact = true;
+    document.getElementById("function").value = name;
+    showView("calls");
+  });
+  return link;
+}
+
+async function renderHotspots(section) {
+  const json = await query("hotspots", { thread: state.thread, limit: 50, context: "true" });
+  const header = el(
+    "tr",
+    {},
+    el("th", { className: "number", textContent: "#" }),
+    el("th", { textContent: "Function" }),
+    el("th", { textContent: "Library" }),
+    el("th", { className: "number", textContent: "Self" }),
+    el("th", { className: "number", textContent: "Total" })
+  );
+  const rows = json.data.map((entry) => {
+    const cell = el("td", {}, functionLink(entry.function.name));
+    if (entry.context) {
+      cell.append(el("div", { className: "context", textContent: entry.context }));
+    }
+    return el(
+      "tr",
+      {},
+      el("td", { className: "number", textContent: entry.rank }),
+      cell,
+      el("td", { textContent: entry.function.library ?? "" }),
+      el("td", { className: "number", textContent: percent(entry.self_percent) }),
+      el("td", { className: "number", textContent: percent(entry.total_percent) })
+    );
+  });
+  section.replaceChildren(el("table", {}, el("thead", {}, header), el("tbody", {}, ...rows)));
+}
+
+async function renderFlamegraph(section) {
+  const json = await query("flamegraph", { thread: state.thread, min_percent: 0.1 });
+  const root = state.zoom ?? json.data.root;
+  const total = json.data.root.value || 1;
+  const rows = [];
+  // Lay out the nodes as an icicle graph: the root at the top, each node as
+  // wide as its share of the zoomed node.
+  const addNode = (node, depth, left) => {
+    const width = node.value / root.value;
+    if (width < MIN_FLAME_WIDTH) {
+      return;
+    }
+    if (!rows[depth]) {
+      rows[depth] = el("div", { className: "flame-row" });
+    }
+    const box = el("div", {
+      className: "flame-node",
+      textContent: node.name,
+      title: `${node.name}\n${node.value} samples (${percent((node.value * 100) / total)})`,
+    });
+    box.style.left = `${left * 100}%`;
+    box.style.width = `${width * 100}%`;
+    box.addEventListener("click", () => {
+      state.zoom = node === root ? null : node;
+      renderFlamegraph(section).catch((error) => setStatus(error.message, true));
+    });
+    rows[depth].append(box);
+    let childLeft = left;
+    for (const child of node.children ?? []) {
+      addNode(child, depth + 1, childLeft);
+      childLeft += child.value / root.value;
+    }
+  };
+  addNode(root, 0, 0);
+  const hint = el("p", {
+    textContent: state.zoom
+      ? "Click the top node to zoom out."
+      : "Click a node to zoom in. Hover for its samples.",
+  });
+  section.replaceChildren(hint, ...rows);
+}
+
+function callTree(entries, field) {
+  const items = entries.map((entry) => {
+    const label = el(
+      "span",
+      {},
+      el("span", { className: "percent", textContent: percent(entry.percent) }),
+      functionLink(entry.name)
+    );
+    const children = entry[field] ?? [];
+    if (!children.length) {
+      return el("li", {}, label);
+    }
+    return el("li", {}, el("details", {}, el("summary", {}, label), callTree(children, field)));
+  });
+  return el("ul", {}, ...items);
+}
+
+async function renderCalls(section) {
+  if (!state.function) {
+    section.replaceChildren(
+      el("p", { textContent: "Enter a function, or click one in the hotspots or call trees." })
+    );
+    return;
+  }
+  const params = {
+    function: state.function,
+    match: state.exact ? "exact" : "",
+    depth: 5,
+    limit: 20,
+  };
+  const [callers, callees] = await Promise.all([query("callers", params), query("callees", params)]);
+  const column = (title, tree) =>
+    el("div", { className: "call-tree" }, el("h3", { textContent: title }), tree);
+  section.replaceChildren(
+    el("h2", { className: "function", textContent: callers.data.function }),
+    el(
+      "div",
+      { className: "columns" },
+      column("Callers", callTree(callers.data.callers, "callers")),
+      column("Callees", callTree(callees.data.callees, "callees"))
+    )
+  );
+}
+
+const RENDERERS = {
+  hotspots: renderHotspots,
+  flamegraph: renderFlamegraph,
+  calls: renderCalls,
+};
+
+async function showView(view) {
+  state.view = view;
+  for (const button of document.querySelectorAll("nav button")) {
+    button.classList.toggle("selected", button.dataset.view === view);
+  }
+  for (const name of Object.keys(RENDERERS)) {
+    document.getElementById(name).hidden = name !== view;
+  }
+  setStatus("Loading…");
+  try {
+    await RENDERERS[view](document.getElementById(view));
+    setStatus("");
+  } catch (error) {
+    setStatus(error.message, true);
+  }
+}
+
+async function loadProfiles() {
+  const select = document.getElementById("profile");
+  const json = await query("profiles");
+  const profiles = json.data.profiles;
+  for (const profile of profiles) {
+    select.append(el("option", { value: profile.id, textContent: profile.path ?? profile.id }));
+  }
+  state.profile = profiles.length > 1 ? profiles[0].id : null;
+  select.parentElement.hidden = profiles.length <= 1;
+  select.addEventListener("change", () => {
+    state.profile = select.value;
+    state.zoom = null;
+    showView(state.view);
+  });
+}
+
+document.addEventListener("DOMContentLoaded", async () => {
+  for (const button of document.querySelectorAll("nav button")) {
+    button.addEventListener("click", () => showView(button.dataset.view));
+  }
+  document.getElementById("filters").addEventListener("submit", (event) => {
+    event.preventDefault();
+    state.thread = document.getElementById("thread").value.trim();
+    const functionName = document.getElementById("function").value.trim();
+    if (functionName !== state.function) {
+      state.function = functionName;
+      state.exact = false;
+    }
+    state.zoom = null;
+    showView(state.function && state.view === "hotspots" ? "calls" : state.view);
+  });
+  try {
+    await loadProfiles();
+  } catch (error) {
+    setStatus(error.message, true);
+    return;
+  }
+  showView("hotspots");
+});
@@ -4,13 +4,22 @@ use std::str::FromStr;
 use std::time::Duration;
 
 use clap::{Args, Parser, Subcommand, ValueEnum};
+use regex::Regex;
+use samply_analysis::{ExportFormat, HotspotsGroupBy, MatchMode, TimeRange};
 
-use super::cli_utils::{parse_time_range, split_at_first_equals};
-use super::server::{PortSelection, ServerProps};
+use super::cli_utils::{
+    parse_interval, parse_session_name, parse_time_range, parse_timeout_secs, split_at_first_equals,
+};
+use super::server::{PortSelection, ServerProps, ServerToken, TlsFiles};
 use super::shared::included_processes::IncludedProcesses;
+use super::shared::included_threads::IncludedThreads;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use super::shared::live_profile::LiveProfile;
+use super::shared::process_name::{NameRule, NameRules};
+use super::shared::progress::ProgressMode;
 use super::shared::prop_types::{
-    CoreClrProfileProps, ImportProps, ProcessLaunchProps, ProfileCreationProps, RecordingMode,
-    RecordingProps, SymbolProps,
+    CoreClrProfileProps, EtlSampleSource, ImportProps, ProcessLaunchProps, ProfileCreationProps,
+    RecordingMode, RecordingProps, SymbolProps,
 };
 
 #[derive(Debug, Parser)]
@@ -59,8 +68,28 @@ QUERY COMMANDS:
     hotspots         List functions by self-time (often shows stdlib)
     callers FUNC     Who calls this function?
     callees FUNC     What does this function call?
+    source FUNC      Source lines annotated with self/total samples
     asm FUNC         Address-level samples with source line mapping
     summary          Profile overview (duration, threads, total samples)
+    processes        Processes with their threads, samples and hotspots; --process PID|NAME
+                     restricts any query to one of them
+    threads          Per-thread hotspots; --merge-threads REGEX counts the matching threads
+                     (e.g. a worker pool) as one thread in any query
+    calltree         Top-down call tree with self/total percentages, pruned
+    flamegraph       Nested call tree JSON (name, value, children)
+    stack-stats      Stack depth percentiles, branching factor, kernel/user frames
+    categories       Samples per frame category (User, Kernel, GC, ...) and their top functions
+    wait-graph       Which threads wake which, where they waited, and convoys
+    signals          Signal deliveries and the CPU time of their handlers, per signal
+    allocations      Top allocation sites by bytes and count (recorded with --alloc)
+    growth           Functions whose share grew over the recording (leaks, slowdowns)
+    crash            The crashing thread, exception and faulting frame of an imported minidump
+    export           Folded stacks, speedscope or callgrind (--format collapsed|speedscope|callgrind)
+    diff             Per-function changes vs. the --baseline profile
+    diff-explain     Where the regression vs. --baseline comes from, as a short narrative
+    by-trace [ID]    Stacks sampled during a trace's spans (W3C traceparent markers)
+    symbolication-status  Which libraries lack symbols, and why
+    profiles         Ids of the server's profiles, for --profile ID and diff --baseline ID
 
 KEY CONCEPTS:
     self-time    Time in function itself, not callees
@@ -81,8 +110,10 @@ OUTPUT FORMAT (all queries return JSON):
           "line_number": 234,
           "self_percent": 65.0
         }
-      }
+      },
+      "units": { "*percent*": "%", "*samples": "samples", "*_ms": "ms", ... }
     }
+    Floats are rounded to 3 decimals; --precision N changes that.
 
 DRILLDOWN THRESHOLDS:
     --threshold 5.0   Default, catches most bottlenecks
@@ -96,6 +127,7 @@ PLATFORM SETUP:
 TROUBLESHOOTING:
     If function names are hex addresses (0x1efcfc), profile isn't symbolicated.
     Re-record with default settings (presymbolication is on by default).
+    Run 'query symbolication-status' to see which libraries are missing symbols and why.
 
 EXAMPLES:
     # Profile a freshly launched process:
@@ -110,13 +142,60 @@ EXAMPLES:
 
     # Import perf.data files from Linux perf or Android simpleperf:
     samply-for-ai import perf.data
+
+    # Import pprof profiles from Go, gperftools or Parca:
+    samply-for-ai import cpu.pb.gz
+
+    # Import Java Flight Recorder recordings from the JDK or async-profiler:
+    samply-for-ai import recording.jfr
+
+    # Import a minidump, and see where it crashed:
+    samply-for-ai import crash.dmp --serve
+    samply-for-ai query crash
+
+    # Import Instruments Time Profiler traces or dtrace stack aggregations:
+    samply-for-ai import app.trace
+    samply-for-ai import stacks.dtrace
+
+    # See which libraries' symbol lookups are cached:
+    samply-for-ai symbols cache
+
+    # Convert a large symbols sidecar to the memory-mapped binary format:
+    samply-for-ai symbols convert-sidecar profile.syms.json
 "#
 )]
 pub struct Opt {
+    /// Also record a profile of samply-for-ai itself while it runs the
+    /// subcommand, and save it to this path. Useful for reporting performance
+    /// problems in the importer, symbolication or the analyzer.
+    #[arg(long, value_name = "PATH")]
+    pub self_profile: Option<PathBuf>,
+
+    /// Don't show the progress of importing and symbolicating.
+    #[arg(short, long, global = true, conflicts_with = "progress")]
+    pub quiet: bool,
+
+    /// How to show the progress of importing and symbolicating: 'bar' when
+    /// stderr is a terminal, or 'json' for a JSON object per line on stderr,
+    /// with the step, the units done, the total if known and the estimated
+    /// seconds left, for tools which wrap samply-for-ai.
+    #[arg(long, value_enum, global = true, value_name = "FORMAT")]
+    pub progress: Option<ProgressArg>,
+
     #[command(subcommand)]
     pub action: Action,
 }
 
+impl Opt {
+    pub fn progress_mode(&self) -> ProgressMode {
+        match (self.quiet, self.progress) {
+            (true, _) => ProgressMode::Quiet,
+            (false, Some(ProgressArg::Json)) => ProgressMode::Json,
+            (false, _) => ProgressMode::Bar,
+        }
+    }
+}
+
 #[derive(Debug, Subcommand)]
 pub enum Action {
     #[cfg(any(
@@ -131,7 +210,7 @@ pub enum Action {
     /// Load a profile from a file and display it.
     Load(LoadArgs),
 
-    /// Import a perf.data file and display the profile.
+    /// Import a perf.data, ETL, pprof, speedscope, JFR, Instruments, dtrace or minidump file and display the profile.
     Import(ImportArgs),
 
     /// Start or stop the analysis server for a profile.
@@ -142,6 +221,17 @@ pub enum Action {
     /// Start with 'query drilldown main' to find bottlenecks.
     Query(QueryArgs),
 
+    /// Open the profile of the running analysis server in the Firefox Profiler,
+    /// rather than copying the long tokenized URL from the server's output.
+    Open(OpenArgs),
+
+    /// Add the calls of an strace or ltrace log (recorded with -ttt) to a profile,
+    /// as markers on the threads which made them.
+    Overlay(OverlayArgs),
+
+    /// Inspect the symbol caches.
+    Symbols(SymbolsArgs),
+
     #[cfg(target_os = "windows")]
     #[clap(hide = true)]
     /// Used in the elevated helper process.
@@ -167,14 +257,51 @@ pub enum AnalyzeCommand {
     /// Start an analysis server for a profile file.
     Serve(AnalyzeServeArgs),
 
+    /// Serve analysis queries as MCP tools over stdin/stdout, for AI agents
+    /// which speak the Model Context Protocol.
+    Mcp(AnalyzeMcpArgs),
+
+    /// Convert a profile file for other tools, without a server: 'pprof' for
+    /// go tool pprof and Polar Signals, 'speedscope' for speedscope.app,
+    /// 'callgrind' for KCachegrind, 'collapsed' for flamegraph.pl and inferno.
+    Export(AnalyzeExportArgs),
+
+    /// Send the summary and hotspots of a profile file to an OpenTelemetry
+    /// collector as OTLP metrics and logs.
+    Otlp(AnalyzeOtlpArgs),
+
+    /// Check a profile file against the time budgets of a budgets file, e.g. a
+    /// checked-in perf-budgets.toml. Exits with status 1 if a budget is exceeded.
+    Assert(AnalyzeAssertArgs),
+
     /// Stop the running analysis server.
-    Stop,
+    Stop(AnalyzeStopArgs),
+
+    /// List the running analysis servers: the default session and named ones
+    /// ('analyze serve --session NAME').
+    List,
+
+    /// Print the OpenAPI description of the query endpoints, their parameters
+    /// and response shapes, which a server also serves at /query/schema.
+    Schema,
 }
 
 #[derive(Debug, Args)]
 pub struct AnalyzeServeArgs {
-    /// Path to the profile file to analyze.
-    pub file: PathBuf,
+    /// Paths to the profile files to analyze. With several, each one is queried
+    /// with 'query --profile <id>'; the first one is the default.
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+
+    /// Name of the session, so that several servers can run at once; query
+    /// it with 'query --session NAME'.
+    #[arg(long, value_name = "NAME", value_parser = parse_session_name)]
+    pub session: Option<String>,
+
+    /// Baseline profile to compare against with 'query diff' and 'query diff-explain',
+    /// e.g. the profile from before an optimization.
+    #[arg(long)]
+    pub baseline: Option<PathBuf>,
 
     #[command(flatten)]
     pub server_args: ServerArgs,
@@ -185,24 +312,319 @@ pub struct AnalyzeServeArgs {
     /// Run in foreground (don't daemonize).
     #[arg(long)]
     pub foreground: bool,
+
+    /// Memory-map the profile's tables from an on-disk index instead of loading
+    /// them into memory. The index is built next to the profile on first use.
+    #[arg(long)]
+    pub low_memory: bool,
+
+    /// Serve profiles received from untrusted sources: enforce strict size limits
+    /// when loading them, and disable symbolication, the source and asm queries,
+    /// and the server paths in 'query profiles'.
+    #[arg(long, conflicts_with = "low_memory")]
+    pub sandbox: bool,
+
+    /// Only serve the threads whose name, process name or pid matches this regex
+    /// to the profiler UI, e.g. the interesting processes of a system-wide
+    /// recording which is too large for the browser. Queries see the same threads.
+    #[arg(long, value_name = "REGEX")]
+    pub threads: Option<Regex>,
+
+    /// Fail if the profile's symbols sidecar (profile.syms.bin or
+    /// profile.syms.json) is missing or can't be loaded, instead of going on
+    /// without its symbols.
+    #[arg(long, conflicts_with = "sandbox")]
+    pub require_precog: bool,
 }
 
 impl AnalyzeServeArgs {
     pub fn server_props(&self) -> ServerProps {
-        self.server_args.server_props()
+        ServerProps {
+            sandbox: self.sandbox,
+            thread_subset: self.threads.clone(),
+            require_precog: self.require_precog,
+            ..self.server_args.server_props()
+        }
+    }
+
+    pub fn symbol_props(&self) -> SymbolProps {
+        self.symbol_args.symbol_props()
     }
+}
+
+#[derive(Debug, Args)]
+pub struct OpenArgs {
+    /// Open this profile of a server which serves several.
+    #[arg(long, value_name = "ID")]
+    pub profile: Option<String>,
+
+    /// Open the profile of this named session's server.
+    #[arg(long, value_name = "NAME", value_parser = parse_session_name)]
+    pub session: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct OverlayArgs {
+    /// Path to the profile file to add the markers to.
+    pub profile: PathBuf,
+
+    /// Path to the strace or ltrace log, e.g. from 'strace -f -ttt -T -o strace.log'.
+    pub log: PathBuf,
+
+    /// Where to write the profile with the markers. Defaults to overwriting the profile.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// The process whose main thread made the calls on lines without a pid column.
+    /// Only needed if the log was recorded without -f and the profile has several processes.
+    #[arg(long)]
+    pub pid: Option<u32>,
+}
+
+#[derive(Debug, Args)]
+pub struct SymbolsArgs {
+    #[command(subcommand)]
+    pub command: SymbolsCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SymbolsCommand {
+    /// Show the cache of the addresses which presymbolication looked up, which
+    /// is kept across runs so that profiles of the same binaries are
+    /// symbolicated without parsing their debug info again.
+    Cache(SymbolsCacheArgs),
+
+    /// Convert a `.syms.json` symbols sidecar to the binary `.syms.bin`
+    /// format, which is memory-mapped instead of parsed when the profile is
+    /// loaded.
+    ConvertSidecar(SymbolsConvertSidecarArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct SymbolsCacheArgs {
+    /// Show the cached addresses of the libraries with this debug name, e.g.
+    /// 'libxul.so' or 'xul.pdb', and what they resolved to.
+    #[arg(long, value_name = "DEBUG_NAME", conflicts_with = "clear")]
+    pub library: Option<String>,
+
+    /// Delete the cache, e.g. once better symbols are available for a library
+    /// which was symbolicated without debug info.
+    #[arg(long)]
+    pub clear: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct SymbolsConvertSidecarArgs {
+    /// The `.syms.json` sidecar, e.g. 'profile.syms.json'.
+    pub file: PathBuf,
+
+    /// The path of the `.syms.bin` sidecar. Defaults to the input path with
+    /// the `.bin` extension, next to the profile, where it's loaded from.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct AnalyzeStopArgs {
+    /// Stop the server of this named session.
+    #[arg(long, value_name = "NAME", value_parser = parse_session_name)]
+    pub session: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct AnalyzeMcpArgs {
+    /// Path to the profile file to analyze.
+    pub file: PathBuf,
+
+    /// Baseline profile to compare against with the diff and diff_explain tools.
+    #[arg(long)]
+    pub baseline: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub symbol_args: SymbolArgs,
+
+    /// Memory-map the profile's tables from an on-disk index instead of loading
+    /// them into memory. The index is built next to the profile on first use.
+    #[arg(long)]
+    pub low_memory: bool,
+
+    /// Fail if the profile's symbols sidecar (profile.syms.bin or
+    /// profile.syms.json) is missing or can't be loaded, instead of going on
+    /// without its symbols.
+    #[arg(long)]
+    pub require_precog: bool,
+}
 
+impl AnalyzeMcpArgs {
     pub fn symbol_props(&self) -> SymbolProps {
         self.symbol_args.symbol_props()
     }
 }
 
+#[derive(Debug, Args)]
+pub struct AnalyzeExportArgs {
+    /// Path to the profile file to convert.
+    pub file: PathBuf,
+
+    /// Output format.
+    #[arg(long, default_value = "pprof")]
+    pub format: ExportFormat,
+
+    /// Only include threads whose name contains this.
+    #[arg(long)]
+    pub thread: Option<String>,
+
+    /// Write the export to this file instead of stdout. A path ending in .gz is
+    /// gzip-compressed, e.g. profile.pb.gz.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub time_range: TimeRangeArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct AnalyzeOtlpArgs {
+    /// Path to the profile file to summarize.
+    pub file: PathBuf,
+
+    /// Base URL of the collector's OTLP/HTTP receiver, e.g. http://localhost:4318.
+    /// Metrics go to <URL>/v1/metrics and logs to <URL>/v1/logs. Defaults to
+    /// $OTEL_EXPORTER_OTLP_ENDPOINT.
+    #[arg(long, value_name = "URL")]
+    pub otlp_endpoint: Option<String>,
+
+    /// Extra header for the requests, e.g. an API key, as KEY=VALUE. Can be
+    /// repeated; $OTEL_EXPORTER_OTLP_HEADERS is added too.
+    #[arg(long, value_name = "KEY=VALUE")]
+    pub otlp_header: Vec<String>,
+
+    /// The service.name resource attribute. Defaults to $OTEL_SERVICE_NAME, or
+    /// the profile's product name.
+    #[arg(long)]
+    pub service_name: Option<String>,
+
+    /// Number of hotspots to send.
+    #[arg(long, default_value = "20")]
+    pub limit: usize,
+
+    /// Only include threads whose name contains this.
+    #[arg(long)]
+    pub thread: Option<String>,
+
+    /// Print the metrics and logs as JSON instead of sending them.
+    #[arg(long)]
+    pub dry_run: bool,
+
+    #[command(flatten)]
+    pub time_range: TimeRangeArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct AnalyzeAssertArgs {
+    /// Path to the profile file to check.
+    pub file: PathBuf,
+
+    /// The budgets file: a TOML file with a [[budget]] table per function or
+    /// library, see the README.
+    #[arg(long, value_name = "FILE", default_value = "perf-budgets.toml")]
+    pub budgets: PathBuf,
+
+    #[command(flatten)]
+    pub time_range: TimeRangeArgs,
+}
+
 // ============================================================================
 // Query subcommands (analysis queries)
 // ============================================================================
 
 #[derive(Debug, Args)]
 pub struct QueryArgs {
+    /// Query this profile of a server which serves several (see 'query profiles').
+    #[arg(long, global = true, value_name = "ID")]
+    pub profile: Option<String>,
+
+    /// Only count the threads of this process, given as its pid or a substring
+    /// of its name (see 'query processes').
+    #[arg(long, global = true, value_name = "PID|NAME")]
+    pub process: Option<String>,
+
+    /// Treat the threads whose name matches this regex as one thread, e.g. the
+    /// workers of a pool: queries only count those threads, 'query threads'
+    /// lists them as one, and responses include each thread's share.
+    #[arg(long, global = true, value_name = "REGEX")]
+    pub merge_threads: Option<String>,
+
+    /// Insert a pseudo-frame like '[JavaScript]' or '[CPython]' as the caller
+    /// of every run of JIT or interpreter frames of one language, so that
+    /// mixed-runtime call trees show where control passes between runtimes.
+    #[arg(long, global = true)]
+    pub language_frames: bool,
+
+    /// Round the floats in the output (percentages, milliseconds) to this many
+    /// decimals. Default: 3.
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(0..=15)
+    )]
+    pub precision: Option<u32>,
+
+    /// Skip this many of the ranked results (hotspots, markers, callers,
+    /// callees, wait-graph, allocations, growth, diff), to read them a page of
+    /// --limit results at a time.
+    #[arg(long, global = true, value_name = "N", conflicts_with = "cursor")]
+    pub offset: Option<usize>,
+
+    /// Get the page after the one whose response had this 'page.next_cursor'.
+    /// Pass the same arguments as for that page.
+    #[arg(long, global = true, value_name = "CURSOR")]
+    pub cursor: Option<String>,
+
+    /// Cap the response at this many bytes of JSON: leave out results from
+    /// the end and then the deepest levels of trees, and mark the response
+    /// 'truncated'.
+    #[arg(long, global = true, value_name = "BYTES")]
+    pub max_bytes: Option<usize>,
+
+    /// Print the response as JSON (the default), or its data as a
+    /// column-aligned table, CSV or Markdown. 'query export' always writes
+    /// the exported text.
+    #[arg(long, global = true, value_name = "FORMAT", default_value = "json")]
+    pub output_format: QueryOutputArg,
+
+    /// Query the server of this named session ('analyze serve --session NAME').
+    #[arg(long, global = true, value_name = "NAME", value_parser = parse_session_name)]
+    pub session: Option<String>,
+
+    /// Query the analysis server at this URL, including its token prefix, e.g.
+    /// one on a remote machine, instead of the server of the session file.
+    /// Defaults to the SAMPLY_QUERY_SERVER environment variable. A server
+    /// started with --auth-token gets the token from SAMPLY_AUTH_TOKEN.
+    #[arg(long, global = true, value_name = "URL", conflicts_with = "session")]
+    pub server: Option<String>,
+
+    /// Give up on a query whose response takes longer than this many seconds.
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECS",
+        default_value = "30",
+        value_parser = parse_timeout_secs
+    )]
+    pub timeout: Duration,
+
+    /// Give up if the connection to the server takes longer than this many seconds.
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECS",
+        default_value = "10",
+        value_parser = parse_timeout_secs
+    )]
+    pub connect_timeout: Duration,
+
     #[command(subcommand)]
     pub command: QueryCommand,
 }
@@ -213,6 +635,19 @@ pub enum QueryCommand {
     /// Note: Often shows stdlib (malloc, memcpy). Use 'drilldown' to find YOUR bottleneck.
     Hotspots(HotspotsArgs),
 
+    /// Per-thread breakdown: each thread's samples, share of its process, and top hotspots.
+    Threads(ThreadsArgs),
+
+    /// Per-process breakdown: each process's threads, samples and top hotspots.
+    Processes(ProcessesArgs),
+
+    /// Markers (IPC, GC, user annotations, ...) with payloads, and per-name count / duration stats.
+    Markers(MarkersArgs),
+
+    /// Stacks sampled while a distributed trace's spans were active: spans are
+    /// interval markers with a W3C traceparent. Without a trace id, lists the traces.
+    ByTrace(ByTraceArgs),
+
     /// Find callers of a function (who calls this function?).
     Callers(CallersArgs),
 
@@ -220,7 +655,59 @@ pub enum QueryCommand {
     Callees(CalleesArgs),
 
     /// Get profile overview: duration, threads, total samples.
-    Summary,
+    Summary(SummaryArgs),
+
+    /// Top-down call tree from the root functions, with self and total percentages per
+    /// node, pruned by --min-percent, --max-depth and --max-children.
+    Calltree(CalltreeArgs),
+
+    /// Nested call tree JSON ({name, value, children}) for d3-flamegraph or speedscope.
+    Flamegraph(FlamegraphArgs),
+
+    /// Stack depth percentiles and histogram, call tree branching factor, unique stacks
+    /// and kernel/user frame ratio: helps to pick depth/threshold values for other queries.
+    StackStats(StackStatsArgs),
+
+    /// Samples per frame category (User, Kernel, GC, ...) with the top leaf functions of each.
+    Categories(CategoriesArgs),
+
+    /// Which threads wake which (from wakeup markers, recorded with --cswitch-markers on
+    /// Windows), where they waited, and convoys: one thread which a pool's threads all wait for.
+    WaitGraph(WaitGraphArgs),
+
+    /// Signal deliveries (from signal:signal_deliver in imported perf.data files) and the
+    /// samples in signal handlers, per signal, with the top handler functions of each.
+    Signals(SignalsArgs),
+
+    /// Top allocation sites, by bytes and by count, from a profile recorded with --alloc.
+    /// A site is the first function from the leaf which isn't part of the allocator.
+    Allocations(AllocationsArgs),
+
+    /// Functions whose share of the samples grew over the recording, from its first
+    /// window to its last: a cheap detector of leaks and gradual slowdowns which
+    /// needs no baseline.
+    Growth(GrowthArgs),
+
+    /// The crashing thread of a profile imported from a minidump: its exception, the
+    /// exception's address, the faulting frame and the top of its stack.
+    Crash(CrashArgs),
+
+    /// Export the profile for other tools. 'collapsed' prints folded stacks
+    /// ('main;work;compute 123') for flamegraph.pl or inferno, 'speedscope' a
+    /// speedscope.app JSON file, 'callgrind' a call graph for KCachegrind.
+    /// For pprof, use 'analyze export' instead.
+    Export(ExportArgs),
+
+    /// Compare against the baseline profile ('analyze serve --baseline'): per-function
+    /// self/total percentage deltas and new/removed hotspots, sorted by absolute change.
+    Diff(DiffArgs),
+
+    /// Explain the regression against the baseline profile: self-time increases clustered
+    /// by the ancestor functions they share, with their driving functions and a short narrative.
+    DiffExplain(DiffExplainArgs),
+
+    /// Show the source of a function annotated with per-line self/total sample counts.
+    Source(SourceArgs),
 
     /// Get address-level samples with source line mapping for a function.
     /// Returns hot_addresses sorted by code order with source_line for each.
@@ -229,6 +716,12 @@ pub enum QueryCommand {
     /// [START HERE] Follow hottest callee path from a function to find bottleneck.
     /// Stops when self-time > threshold. Returns is_bottleneck: true at the hot function.
     Drilldown(DrilldownArgs),
+
+    /// Per-library symbolication status: resolved/pending/failed, symbol source, and error reasons.
+    SymbolicationStatus,
+
+    /// List the profiles of the server ('analyze serve a.json b.json') with their ids.
+    Profiles,
 }
 
 #[derive(Debug, Args)]
@@ -248,6 +741,123 @@ pub struct HotspotsArgs {
     /// Include per-address sample counts in output.
     #[arg(long)]
     pub show_addresses: bool,
+
+    /// Include each function's heaviest path from the root, shortened to
+    /// its outermost and innermost frames, e.g.
+    /// 'main → run → … → parse (12 frames elided)'.
+    #[arg(long)]
+    pub context: bool,
+
+    /// 'function' (default), 'leaf_module': self time per library of the
+    /// leaf frame, and which of the program's functions spend time in which
+    /// other libraries (FFI boundaries), or 'language': self and total time
+    /// per language runtime (JavaScript, CPython, Java, ...) and native code.
+    #[arg(long, default_value = "function")]
+    pub groupby: HotspotsGroupBy,
+
+    /// With '--groupby leaf_module': a library of the program's own code,
+    /// rather than every library outside of the system's directories.
+    /// Can be given more than once.
+    #[arg(long = "own-library", value_name = "NAME")]
+    pub own_libraries: Vec<String>,
+
+    /// Rank functions by a hardware event recorded with 'record --event',
+    /// e.g. 'cache-misses', instead of by CPU samples ('samples', the default).
+    #[arg(long, default_value = "samples")]
+    pub metric: String,
+
+    /// Re-run the query every INTERVAL, e.g. '5s', '500ms' or '5' seconds, and
+    /// show the hotspots as a table, with how far each function moved up or
+    /// down and how its self time changed since the last run, until Ctrl+C.
+    /// Useful with 'record --live'.
+    #[arg(long, value_name = "INTERVAL", value_parser = parse_interval)]
+    pub watch: Option<Duration>,
+
+    #[command(flatten)]
+    pub time_range: TimeRangeArgs,
+}
+
+/// Restrict a query to the samples in a time range, e.g. the slow part of a run.
+#[derive(Debug, Args)]
+pub struct TimeRangeArgs {
+    /// Only count samples at or after this time (ms since profile start; see 'query summary').
+    #[arg(long)]
+    pub start_ms: Option<f64>,
+
+    /// Only count samples before this time (ms since profile start).
+    #[arg(long)]
+    pub end_ms: Option<f64>,
+}
+
+impl TimeRangeArgs {
+    pub fn time_range(&self) -> TimeRange {
+        TimeRange {
+            start_ms: self.start_ms,
+            end_ms: self.end_ms,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct SummaryArgs {
+    #[command(flatten)]
+    pub time_range: TimeRangeArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ThreadsArgs {
+    /// Maximum number of hotspots to return per thread.
+    #[arg(long, default_value = "10")]
+    pub limit: usize,
+
+    #[command(flatten)]
+    pub time_range: TimeRangeArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ProcessesArgs {
+    /// Maximum number of hotspots to return per process.
+    #[arg(long, default_value = "5")]
+    pub limit: usize,
+
+    #[command(flatten)]
+    pub time_range: TimeRangeArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct MarkersArgs {
+    /// Only include markers whose name contains this.
+    #[arg(long)]
+    pub name: Option<String>,
+
+    /// Only include markers of threads whose name contains this.
+    #[arg(long)]
+    pub thread: Option<String>,
+
+    /// Maximum number of individual markers to return (statistics cover all of them).
+    #[arg(long, default_value = "50")]
+    pub limit: usize,
+
+    #[command(flatten)]
+    pub time_range: TimeRangeArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ByTraceArgs {
+    /// The trace id (32 hex digits) or a whole traceparent value. Lists the
+    /// traces in the profile if omitted.
+    pub trace_id: Option<String>,
+
+    /// Only include spans of threads whose name contains this.
+    #[arg(long)]
+    pub thread: Option<String>,
+
+    /// Maximum number of stacks (or traces) to return.
+    #[arg(long, default_value = "20")]
+    pub limit: usize,
+
+    #[command(flatten)]
+    pub time_range: TimeRangeArgs,
 }
 
 #[derive(Debug, Args)]
@@ -255,6 +865,12 @@ pub struct CallersArgs {
     /// Function name (exact match or substring).
     pub function: String,
 
+    /// How FUNCTION is matched: substring, exact or regex. Names are also
+    /// compared demangled and without generic arguments, so 'Vec::push'
+    /// matches 'alloc::vec::Vec<T,A>::push'.
+    #[arg(long = "match", default_value = "substring")]
+    pub match_mode: MatchMode,
+
     /// Maximum depth of caller chain.
     #[arg(long, default_value = "5")]
     pub depth: usize,
@@ -262,6 +878,9 @@ pub struct CallersArgs {
     /// Maximum number of callers to return at each level.
     #[arg(long, default_value = "20")]
     pub limit: usize,
+
+    #[command(flatten)]
+    pub time_range: TimeRangeArgs,
 }
 
 #[derive(Debug, Args)]
@@ -269,6 +888,12 @@ pub struct CalleesArgs {
     /// Function name (exact match or substring).
     pub function: String,
 
+    /// How FUNCTION is matched: substring, exact or regex. Names are also
+    /// compared demangled and without generic arguments, so 'Vec::push'
+    /// matches 'alloc::vec::Vec<T,A>::push'.
+    #[arg(long = "match", default_value = "substring")]
+    pub match_mode: MatchMode,
+
     /// Maximum depth of callee chain.
     #[arg(long, default_value = "5")]
     pub depth: usize,
@@ -276,12 +901,208 @@ pub struct CalleesArgs {
     /// Maximum number of callees to return at each level.
     #[arg(long, default_value = "20")]
     pub limit: usize,
+
+    #[command(flatten)]
+    pub time_range: TimeRangeArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct CalltreeArgs {
+    /// Only include threads whose name contains this.
+    #[arg(long)]
+    pub thread: Option<String>,
+
+    /// Prune nodes with less than this percentage of all samples.
+    #[arg(long, default_value = "1.0")]
+    pub min_percent: f64,
+
+    /// Levels of the tree to return; the root functions are level 1.
+    #[arg(long, default_value = "20")]
+    pub max_depth: usize,
+
+    /// Keep only this many of each node's children, the largest ones.
+    #[arg(long, default_value = "10")]
+    pub max_children: usize,
+
+    #[command(flatten)]
+    pub time_range: TimeRangeArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct FlamegraphArgs {
+    /// Filter to a specific thread.
+    #[arg(long)]
+    pub thread: Option<String>,
+
+    /// Root the tree at leaf functions, with callers as children.
+    #[arg(long)]
+    pub inverted: bool,
+
+    /// Prune nodes with less than this percentage of all samples.
+    #[arg(long, default_value = "0.0")]
+    pub min_percent: f64,
+}
+
+#[derive(Debug, Args)]
+pub struct StackStatsArgs {
+    /// Only include threads whose name contains this.
+    #[arg(long)]
+    pub thread: Option<String>,
+
+    #[command(flatten)]
+    pub time_range: TimeRangeArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct WaitGraphArgs {
+    /// Maximum number of (thread, waker) edges to return.
+    #[arg(long, default_value = "20")]
+    pub limit: usize,
+
+    /// Only include woken threads whose name contains this.
+    #[arg(long)]
+    pub thread: Option<String>,
+
+    #[command(flatten)]
+    pub time_range: TimeRangeArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct SignalsArgs {
+    /// Maximum number of handler functions to return per signal.
+    #[arg(long, default_value = "5")]
+    pub limit: usize,
+
+    /// Only include threads whose name contains this.
+    #[arg(long)]
+    pub thread: Option<String>,
+
+    #[command(flatten)]
+    pub time_range: TimeRangeArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct AllocationsArgs {
+    /// Maximum number of sites to return, by bytes and by count.
+    #[arg(long, default_value = "10")]
+    pub limit: usize,
+
+    /// Only include threads whose name contains this.
+    #[arg(long)]
+    pub thread: Option<String>,
+
+    #[command(flatten)]
+    pub time_range: TimeRangeArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct GrowthArgs {
+    /// Maximum number of functions to return, by self-time and by total-time growth.
+    #[arg(long, default_value = "10")]
+    pub limit: usize,
+
+    /// Number of equal windows to split the recording into; the default compares
+    /// its first and second half.
+    #[arg(long, default_value = "2")]
+    pub windows: usize,
+
+    /// Only include threads whose name contains this.
+    #[arg(long)]
+    pub thread: Option<String>,
+
+    #[command(flatten)]
+    pub time_range: TimeRangeArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct CrashArgs {
+    /// Maximum number of frames of the crashing stack to return, from the leaf.
+    #[arg(long, default_value = "20")]
+    pub limit: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct CategoriesArgs {
+    /// Maximum number of functions to return per category.
+    #[arg(long, default_value = "5")]
+    pub limit: usize,
+
+    /// Only include threads whose name contains this.
+    #[arg(long)]
+    pub thread: Option<String>,
+
+    #[command(flatten)]
+    pub time_range: TimeRangeArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// Output format.
+    #[arg(long, default_value = "collapsed")]
+    pub format: ExportFormat,
+
+    /// Only include threads whose name contains this.
+    #[arg(long)]
+    pub thread: Option<String>,
+
+    /// Write the export to this file instead of stdout.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub time_range: TimeRangeArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct DiffArgs {
+    /// Maximum number of functions to return.
+    #[arg(long, default_value = "20")]
+    pub limit: usize,
+
+    /// Compare against the server's profile with this id rather than the
+    /// --baseline profile.
+    #[arg(long, value_name = "ID")]
+    pub baseline: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct DiffExplainArgs {
+    /// Maximum number of clusters and modules to return.
+    #[arg(long, default_value = "5")]
+    pub limit: usize,
+
+    /// Compare against the server's profile with this id rather than the
+    /// --baseline profile.
+    #[arg(long, value_name = "ID")]
+    pub baseline: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct SourceArgs {
+    /// Function name.
+    pub function: String,
+
+    /// How FUNCTION is matched: substring, exact or regex. Names are also
+    /// compared demangled and without generic arguments, so 'Vec::push'
+    /// matches 'alloc::vec::Vec<T,A>::push'.
+    #[arg(long = "match", default_value = "substring")]
+    pub match_mode: MatchMode,
+
+    /// Number of extra source lines to show before and after the function's sampled lines.
+    #[arg(long, default_value = "3")]
+    pub context: u32,
 }
 
 #[derive(Debug, Args)]
 pub struct AsmArgs {
     /// Function name.
     pub function: String,
+
+    /// How FUNCTION is matched: substring, exact or regex. Names are also
+    /// compared demangled and without generic arguments, so 'Vec::push'
+    /// matches 'alloc::vec::Vec<T,A>::push'.
+    #[arg(long = "match", default_value = "substring")]
+    pub match_mode: MatchMode,
 }
 
 #[derive(Debug, Args)]
@@ -289,6 +1110,12 @@ pub struct DrilldownArgs {
     /// Function name to start drilling down from.
     pub function: String,
 
+    /// How FUNCTION is matched: substring, exact or regex. Names are also
+    /// compared demangled and without generic arguments, so 'Vec::push'
+    /// matches 'alloc::vec::Vec<T,A>::push'.
+    #[arg(long = "match", default_value = "substring")]
+    pub match_mode: MatchMode,
+
     /// Maximum depth to drill down.
     #[arg(long, default_value = "20")]
     pub depth: usize,
@@ -303,6 +1130,18 @@ pub struct LoadArgs {
     /// Path to the file that should be loaded.
     pub file: PathBuf,
 
+    /// Only serve the threads whose name, process name or pid matches this regex,
+    /// e.g. the interesting processes of a system-wide recording which is too
+    /// large for the browser.
+    #[arg(long, value_name = "REGEX")]
+    pub threads: Option<Regex>,
+
+    /// Fail if the profile's symbols sidecar (profile.syms.bin or
+    /// profile.syms.json) is missing or can't be loaded, instead of going on
+    /// without its symbols.
+    #[arg(long)]
+    pub require_precog: bool,
+
     #[command(flatten)]
     pub server_args: ServerArgs,
 
@@ -313,7 +1152,8 @@ pub struct LoadArgs {
 #[derive(Debug, Args)]
 pub struct ImportArgs {
     /// Path to the profile file that should be imported.
-    pub file: PathBuf,
+    #[arg(required_unless_present = "etw_session")]
+    pub file: Option<PathBuf>,
 
     /// Optional extra paths to ETL files for user sessions.
     pub user_etl: Vec<PathBuf>,
@@ -325,6 +1165,32 @@ pub struct ImportArgs {
     #[arg(short, long)]
     pub save_only: bool,
 
+    /// Follow a perf.data file which is still being written by `perf record`:
+    /// re-import it whenever it grows, until perf has finished writing it.
+    #[arg(long)]
+    pub follow: bool,
+
+    /// Attach to the running ETW real-time session with this name, e.g. one
+    /// started by WPR or xperf, instead of importing a file. Events are
+    /// converted as they arrive, and the served profile is updated every few
+    /// seconds, until the session stops or Ctrl+C is pressed. Windows only.
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["file", "follow"])]
+    pub etw_session: Option<String>,
+
+    /// The events which the samples of an ETL trace are made from, depending
+    /// on the WPR or xperf profile it was recorded with: timer-based CPU
+    /// samples (PROFILE), context switches only (CSWITCH), or both, e.g. WPR's
+    /// CPU profile. The import fails with the missing ETW providers if the
+    /// trace lacks these events or their stacks. Windows only.
+    #[arg(long, value_enum, value_name = "PROFILE")]
+    pub wpr_profile: Option<WprProfileArg>,
+
+    /// Resolve the stacks of an ETL trace recorded with stack caching
+    /// (xperf -stackcaching, WPR <StackCaching>), whose samples refer to
+    /// cached stacks by key. Windows only.
+    #[arg(long)]
+    pub stack_caching: bool,
+
     /// Output filename.
     #[arg(short, long, default_value = "profile.json.gz")]
     pub output: PathBuf,
@@ -363,10 +1229,21 @@ pub struct RecordArgs {
     #[arg(short, long, default_value = "1000")]
     pub rate: f64,
 
-    /// Limit the recorded time to the specified number of seconds
-    #[arg(short, long)]
+    /// Limit the recorded time to the specified number of seconds, then stop
+    /// recording without waiting for Ctrl+C. The time limit starts after the delay.
+    #[arg(short, long, value_name = "SECS")]
     pub duration: Option<f64>,
 
+    /// Drop the samples of the first N seconds of the recording, to skip startup work.
+    #[arg(long, value_name = "SECS")]
+    pub delay: Option<f64>,
+
+    /// Only keep the samples of the last N seconds, to record a long-running process
+    /// until a rare stall happens without producing an enormous profile. The profile
+    /// is written when recording stops, e.g. on Ctrl+C or SIGINT.
+    #[arg(long, value_name = "N")]
+    pub ring_buffer_seconds: Option<f64>,
+
     /// How many times to run the profiled command.
     #[arg(long, default_value = "1")]
     pub iteration_count: u32,
@@ -387,6 +1264,14 @@ pub struct RecordArgs {
     #[arg(long, conflicts_with = "save_only")]
     pub serve: bool,
 
+    /// Start the analysis server right away and serve snapshots of the profile
+    /// while recording (Linux only). The profile at /profile.json and the query
+    /// endpoints are refreshed every 2 seconds; /profile.json?since=MS only has
+    /// the samples and markers from MS on. Implies --serve.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long, conflicts_with = "save_only")]
+    pub live: bool,
+
     /// Output filename.
     #[arg(short, long, default_value = "profile.json.gz")]
     pub output: PathBuf,
@@ -410,8 +1295,10 @@ pub struct RecordArgs {
     #[arg(short, long, conflicts_with = "all")]
     pub pid: Option<u32>,
 
-    /// Profile entire system (all processes). Not supported on macOS.
-    #[arg(short, long, conflicts_with = "pid")]
+    /// Profile all processes on the machine until Ctrl+C or the time limit, with
+    /// a track per process. Requires root on Linux (or perf_event_paranoid set to
+    /// 0 or lower) and admin on Windows. Not supported on macOS.
+    #[arg(short, long = "all-processes", alias = "all", conflicts_with = "pid")]
     pub all: bool,
 
     /// VM hack for arm64 Windows VMs to not try to record PROFILE events (Windows only).
@@ -431,6 +1318,100 @@ pub struct RecordArgs {
     #[cfg(target_os = "windows")]
     #[arg(long)]
     pub keep_etl: bool,
+
+    /// Enable the .NET runtime's ETW providers (Windows only), even if --coreclr
+    /// turned them off, and ask for a rundown of the loaded methods and modules
+    /// when recording stops. Managed frames then get C# method names, also for
+    /// methods which were compiled before recording started, and a subcategory
+    /// per assembly.
+    #[cfg(target_os = "windows")]
+    #[arg(long)]
+    pub clr: bool,
+
+    /// Also record where threads block (Linux only). The stacks of threads which
+    /// are switched out are recorded with the sched_switch tracepoint, and the
+    /// time until they run again shows up as samples in the "Off-CPU" category.
+    /// Usually requires root.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long)]
+    pub off_cpu: bool,
+
+    /// Also record allocations (Linux only): the stack and size of each call to the
+    /// system libc's malloc, with a uprobe. They are stored in the profile's native
+    /// allocations table; see 'query allocations'. Usually requires root.
+    #[arg(long)]
+    pub alloc: bool,
+
+    /// Lower the sampling rate while the recording can't keep up (Linux only):
+    /// when events are lost, the ring buffers fill up or samply itself uses more
+    /// than half a CPU, the rate is halved, down to 10 Hz, and it's raised again
+    /// after 10 calm seconds. Each change is marked on a "samply" track, and the
+    /// samples at a lowered rate are weighted to stay comparable.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long)]
+    pub adaptive_rate: bool,
+
+    /// Read commands from stdin while recording (Linux only), one per line:
+    /// 'mark <name>' adds a marker, 'phase <name>' starts a phase which lasts
+    /// until the next one, and 'stop' stops recording. They show up on a
+    /// "samply" track. A launched command gets /dev/null as its stdin.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long)]
+    pub stdin_commands: bool,
+
+    /// Also sample a hardware event (Linux only), e.g. 'cache-misses'; can be
+    /// given several times. Each thread gets a track per event, weighted by the
+    /// number of events; see 'query hotspots --metric'. Needs hardware
+    /// performance counters, which virtual machines often don't have.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(
+        long = "event",
+        value_name = "EVENT",
+        value_parser = clap::builder::PossibleValuesParser::new(samply_analysis::HARDWARE_EVENTS)
+    )]
+    pub events: Vec<String>,
+
+    /// Ask the JIT runtimes in the launched command to write perf maps and
+    /// jitdump files (Linux only), so that JIT frames get function names: Node.js
+    /// (NODE_OPTIONS), HotSpot JVMs from JDK 17 (JAVA_TOOL_OPTIONS) and .NET
+    /// (DOTNET_PerfMapEnabled). Files which a runtime writes without this, e.g.
+    /// /tmp/perf-<pid>.map from LuaJIT, are always picked up.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[arg(long, requires = "command")]
+    pub jit: bool,
+
+    /// Only sample the threads whose name matches this regex, e.g. the worker
+    /// threads of a database or JVM with hundreds of threads. The other threads
+    /// aren't unwound and are dropped from the profile, except for the main
+    /// thread of each process.
+    #[arg(long, value_name = "REGEX")]
+    pub include_thread: Option<Regex>,
+
+    /// Don't sample the threads whose name matches this regex, even if they
+    /// match --include-thread.
+    #[arg(long, value_name = "REGEX")]
+    pub exclude_thread: Option<Regex>,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProgressArg {
+    Bar,
+    Json,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum QueryOutputArg {
+    Json,
+    Table,
+    Csv,
+    Markdown,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WprProfileArg {
+    Sampled,
+    Cswitch,
+    Mixed,
 }
 
 #[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
@@ -472,6 +1453,38 @@ pub struct ServerArgs {
     /// Print debugging output.
     #[arg(short, long)]
     pub verbose: bool,
+
+    /// Use this token as the secret first path component of the server's URLs
+    /// instead of a random one, so that the URLs stay the same across restarts.
+    #[arg(long, conflicts_with = "no_token")]
+    pub token: Option<String>,
+
+    /// Serve without a secret token. Anyone who can reach the server can then
+    /// read the profile and the symbol files; only use this on localhost.
+    #[arg(long)]
+    pub no_token: bool,
+
+    /// Reject POST requests (e.g. symbolication requests) with bodies larger
+    /// than this many megabytes.
+    #[arg(long, value_name = "MB", default_value = "64")]
+    pub max_body_mb: usize,
+
+    /// Serve HTTPS instead of HTTP, with the certificate chain in this PEM
+    /// file. Use this when the server is reachable from other machines, so
+    /// that profiles and symbols aren't transferred in cleartext.
+    #[arg(long, value_name = "PEM", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// The private key of --tls-cert, as a PEM file.
+    #[arg(long, value_name = "PEM", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Also require this token in an 'Authorization: Bearer' header for
+    /// /profile.json and the /query/* endpoints, on top of the secret path
+    /// prefix. Defaults to the SAMPLY_AUTH_TOKEN environment variable. It's
+    /// stored in the session file, so 'samply query' sends it by itself.
+    #[arg(long, value_name = "TOKEN")]
+    pub auth_token: Option<String>,
 }
 
 /// Arguments describing where to obtain symbol files.
@@ -504,6 +1517,31 @@ pub struct SymbolArgs {
     /// Extra directory containing symbol files, with the directory structure used by simpleperf's scripts
     #[arg(long)]
     pub simpleperf_binary_cache: Option<PathBuf>,
+
+    /// Load the symbols of at most this many libraries at the same time when
+    /// presymbolicating, to go easy on symbol servers.
+    #[arg(long, value_name = "N", default_value = "8", value_parser = clap::value_parser!(u32).range(1..))]
+    pub symbol_jobs: u32,
+
+    /// Retry a library this many times, with a growing delay, when its symbols
+    /// couldn't be fetched from a symbol server or timed out.
+    #[arg(long, value_name = "N", default_value = "2")]
+    pub symbol_retries: u32,
+
+    /// Give up on the symbols of a library after this many seconds, per attempt.
+    #[arg(long, value_name = "SECS", default_value = "120")]
+    pub symbol_timeout: u64,
+
+    /// Don't contact any symbol servers. Symbols only come from local files and
+    /// the symbol caches, and the lookups which would have needed the network
+    /// are listed after symbolication.
+    #[arg(long)]
+    pub offline: bool,
+
+    /// Send at most this many requests to each symbol server, after which
+    /// only its cache is used.
+    #[arg(long, value_name = "N", conflicts_with = "offline")]
+    pub max_symbol_requests: Option<u32>,
 }
 
 #[derive(Debug, Args, Clone)]
@@ -559,6 +1597,18 @@ pub struct ProfileCreationArgs {
     #[arg(long, default_value = "0", num_args=0..=1, require_equals = true, default_missing_value = "100")]
     pub include_args: usize,
 
+    /// Name the processes whose command line matches REGEX after TEMPLATE, in
+    /// which $1 or $name stand for the capture groups, e.g.
+    /// '-jar (\S+)\.jar=>java $1'. Can be given multiple times; the first
+    /// matching rule wins, before the "name_rules" of the config file.
+    #[arg(long, value_name = "REGEX=>TEMPLATE", allow_hyphen_values = true)]
+    pub name_template: Vec<NameRule>,
+
+    /// Name the threads whose name matches REGEX after TEMPLATE, like
+    /// --name-template. Not supported on macOS
+    #[arg(long, value_name = "REGEX=>TEMPLATE", allow_hyphen_values = true)]
+    pub thread_name_template: Vec<NameRule>,
+
     /// Skip pre-symbolication of the profile file.
     ///
     /// By default, samply looks up symbol information before writing the profile.
@@ -574,6 +1624,13 @@ pub struct ProfileCreationArgs {
     /// Enable CoreCLR event conversion.
     #[clap(long, require_equals = true, value_name = "FLAG", value_enum, value_delimiter = ',', num_args = 0.., default_values_t = vec![CoreClrArgs::Enabled])]
     pub coreclr: Vec<CoreClrArgs>,
+
+    /// Post-process the profile before saving it. Can be given multiple times or
+    /// comma-separated; transforms run in order. Available: drop-idle-threads,
+    /// merge-worker-threads, strip-args, rename-by-rules, downsample[=N], keep-last=SECONDS.
+    /// Defaults to the "transforms" list in the config file.
+    #[arg(long, value_name = "TRANSFORM", value_delimiter = ',')]
+    pub transform: Vec<String>,
 }
 
 #[derive(Debug, Args)]
@@ -587,7 +1644,11 @@ pub struct RunElevatedHelperArgs {
 
 impl LoadArgs {
     pub fn server_props(&self) -> ServerProps {
-        self.server_args.server_props()
+        ServerProps {
+            thread_subset: self.threads.clone(),
+            require_precog: self.require_precog,
+            ..self.server_args.server_props()
+        }
     }
 
     pub fn symbol_props(&self) -> SymbolProps {
@@ -608,9 +1669,22 @@ impl ImportArgs {
         self.symbol_args.symbol_props()
     }
 
+    /// The file to import; only `--etw-session` imports don't have one.
+    pub fn input_path(&self) -> &Path {
+        self.file
+            .as_deref()
+            .expect("clap requires a file without --etw-session")
+    }
+
     pub fn profile_creation_props(&self) -> ProfileCreationProps {
-        let filename = self.file.file_name().unwrap_or(self.file.as_os_str());
-        let fallback_profile_name = filename.to_string_lossy().into();
+        let fallback_profile_name = match (&self.file, &self.etw_session) {
+            (Some(file), _) => file
+                .file_name()
+                .unwrap_or(file.as_os_str())
+                .to_string_lossy()
+                .into(),
+            (None, session_name) => session_name.clone().unwrap_or_default(),
+        };
         self.profile_creation_args
             .profile_creation_props_with_fallback_name(fallback_profile_name)
     }
@@ -635,6 +1709,12 @@ impl ImportArgs {
             user_etl: self.user_etl.clone(),
             aux_file_dir: self.aux_file_dir.clone(),
             time_range: self.time_range,
+            etl_sample_source: self.wpr_profile.map(|profile| match profile {
+                WprProfileArg::Sampled => EtlSampleSource::Sampled,
+                WprProfileArg::Cswitch => EtlSampleSource::ContextSwitches,
+                WprProfileArg::Mixed => EtlSampleSource::Mixed,
+            }),
+            etl_stack_caching: self.stack_caching,
         }
     }
 }
@@ -655,7 +1735,26 @@ impl RecordArgs {
 
     #[allow(unused)]
     pub fn recording_props(&self) -> RecordingProps {
-        let time_limit = self.duration.map(Duration::from_secs_f64);
+        let time_limit = match self.duration {
+            Some(seconds) if seconds > 0.0 && seconds.is_finite() => {
+                Some(Duration::from_secs_f64(seconds))
+            }
+            Some(seconds) => {
+                eprintln!("Error: --duration must be greater than zero, got {seconds}");
+                std::process::exit(1);
+            }
+            None => None,
+        };
+        let delay = match self.delay {
+            Some(seconds) if seconds >= 0.0 && seconds.is_finite() => {
+                Some(Duration::from_secs_f64(seconds))
+            }
+            Some(seconds) => {
+                eprintln!("Error: --delay must not be negative, got {seconds}");
+                std::process::exit(1);
+            }
+            None => None,
+        };
         if self.rate <= 0.0 {
             eprintln!(
                 "Error: sampling rate must be greater than zero, got {}",
@@ -664,9 +1763,25 @@ impl RecordArgs {
             std::process::exit(1);
         }
         let interval = Duration::from_secs_f64(1.0 / self.rate);
+        let ring_buffer = match self.ring_buffer_seconds {
+            Some(seconds) if seconds > 0.0 && seconds.is_finite() => {
+                Some(Duration::from_secs_f64(seconds))
+            }
+            Some(seconds) => {
+                eprintln!("Error: --ring-buffer-seconds must be greater than zero, got {seconds}");
+                std::process::exit(1);
+            }
+            None => None,
+        };
+        if self.alloc && !cfg!(any(target_os = "android", target_os = "linux")) {
+            eprintln!("Error: --alloc is only supported on Linux");
+            std::process::exit(1);
+        }
         RecordingProps {
             output_file: self.output.clone(),
             time_limit,
+            delay,
+            ring_buffer,
             interval,
             gfx: self.gfx,
             browsers: self.browsers,
@@ -678,6 +1793,39 @@ impl RecordArgs {
             keep_etl: self.keep_etl,
             #[cfg(not(target_os = "windows"))]
             keep_etl: false,
+            #[cfg(target_os = "windows")]
+            clr: self.clr,
+            #[cfg(not(target_os = "windows"))]
+            clr: false,
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            off_cpu: self.off_cpu,
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            off_cpu: false,
+            alloc: self.alloc,
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            adaptive_rate: self.adaptive_rate,
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            adaptive_rate: false,
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            stdin_commands: self.stdin_commands,
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            stdin_commands: false,
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            events: self.events.clone(),
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            events: Vec::new(),
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            jit: self.jit,
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            jit: false,
+            included_threads: IncludedThreads {
+                include: self.include_thread.clone(),
+                exclude: self.exclude_thread.clone(),
+            },
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            live: self.live.then(|| std::sync::Arc::new(LiveProfile::new())),
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            live: None,
         }
     }
 
@@ -750,6 +1898,15 @@ impl ProfileCreationArgs {
         }
     }
 
+    /// The `--name-template` rules, followed by the ones of the config file
+    fn name_rules(&self) -> NameRules {
+        let config_rules = crate::config::load_config().name_rules();
+        NameRules {
+            process: [&self.name_template[..], &config_rules.process[..]].concat(),
+            thread: [&self.thread_name_template[..], &config_rules.thread[..]].concat(),
+        }
+    }
+
     pub fn profile_creation_props_with_fallback_name(
         &self,
         fallback_profile_name: String,
@@ -763,6 +1920,7 @@ impl ProfileCreationArgs {
             unlink_aux_files: self.unlink_aux_files,
             create_per_cpu_threads: self.per_cpu_threads,
             arg_count_to_include_in_process_name: self.include_args,
+            name_rules: self.name_rules(),
             override_arch: None,
             presymbolicate: !self.no_presymbolicate,
             should_emit_jit_markers: self.jit_markers,
@@ -802,12 +1960,80 @@ impl ServerArgs {
             }
         };
 
+        let auth_token = self.auth_token();
+        let token = match (&self.token, self.no_token) {
+            (_, true) => {
+                if !address.is_loopback() && auth_token.is_none() {
+                    eprintln!("############################################################");
+                    eprintln!("WARNING: --no-token with --address {address}: the server is");
+                    eprintln!("reachable from other machines WITHOUT any access token.");
+                    eprintln!("Anyone who can connect can read the profile, and the file");
+                    eprintln!("paths and symbols of the profiled binaries.");
+                    eprintln!("############################################################");
+                }
+                ServerToken::None
+            }
+            (Some(token), false) => {
+                let is_valid = !token.is_empty()
+                    && token
+                        .bytes()
+                        .all(|b| b.is_ascii_alphanumeric() || b"-_.~".contains(&b));
+                if !is_valid {
+                    eprintln!(
+                        "Invalid --token {token:?}: use letters, digits, '-', '_', '.' and '~' only"
+                    );
+                    std::process::exit(1)
+                }
+                ServerToken::Fixed(token.clone())
+            }
+            (None, false) => ServerToken::Random,
+        };
+
+        let tls = match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => Some(TlsFiles {
+                cert: cert.clone(),
+                key: key.clone(),
+            }),
+            _ => None,
+        };
+        if tls.is_none() && !address.is_loopback() {
+            eprintln!(
+                "Note: the server at {address} uses plain HTTP; use --tls-cert and --tls-key to serve HTTPS."
+            );
+        }
+        if auth_token.is_some() && open_in_browser {
+            eprintln!(
+                "Note: the Firefox Profiler can't send the --auth-token, so it can't load the profile from this server."
+            );
+        }
+
         ServerProps {
             address,
             port_selection,
             verbose: self.verbose,
             open_in_browser,
+            token,
+            max_body_size: self.max_body_mb.saturating_mul(1024 * 1024),
+            sandbox: false,
+            thread_subset: None,
+            require_precog: false,
+            tls,
+            auth_token,
+        }
+    }
+
+    /// The `--auth-token`, or the `SAMPLY_AUTH_TOKEN` environment variable.
+    /// Exits if it can't be sent in a header.
+    pub fn auth_token(&self) -> Option<String> {
+        let auth_token = self
+            .auth_token
+            .clone()
+            .or_else(|| std::env::var("SAMPLY_AUTH_TOKEN").ok())?;
+        if auth_token.is_empty() || !auth_token.bytes().all(|b| b.is_ascii_graphic()) {
+            eprintln!("Invalid --auth-token: use printable ASCII characters without spaces");
+            std::process::exit(1)
         }
+        Some(auth_token)
     }
 }
 
@@ -821,6 +2047,11 @@ impl SymbolArgs {
             breakpad_symbol_dir: self.breakpad_symbol_dir.clone(),
             breakpad_symbol_cache: self.breakpad_symbol_cache.clone(),
             simpleperf_binary_cache: self.simpleperf_binary_cache.clone(),
+            symbol_jobs: self.symbol_jobs as usize,
+            symbol_retries: self.symbol_retries,
+            symbol_timeout: Duration::from_secs(self.symbol_timeout),
+            offline: self.offline,
+            max_symbol_requests: self.max_symbol_requests,
         }
     }
 }
@@ -1,5 +1,7 @@
 use std::ffi::OsStr;
 
+use crate::session::Session;
+
 #[allow(unused)]
 pub fn parse_time_range(
     arg: &str,
@@ -43,3 +45,46 @@ pub fn split_at_first_equals(s: &OsStr) -> Option<(&OsStr, &OsStr)> {
     };
     Some((name, val))
 }
+
+/// A `--session` name, which becomes the file name of the session file
+pub fn parse_session_name(name: &str) -> Result<String, String> {
+    if Session::is_valid_name(name) {
+        Ok(name.to_string())
+    } else {
+        Err("use letters, digits, '-', '_' and '.' only, not starting with '.'".to_string())
+    }
+}
+
+/// A timeout in seconds, like `30` or `0.5`
+pub fn parse_timeout_secs(arg: &str) -> Result<std::time::Duration, String> {
+    parse_positive_secs(arg, "timeout")
+}
+
+/// An interval like `5s`, `500ms` or `1m`, or in seconds, like `5` or `0.5`
+pub fn parse_interval(arg: &str) -> Result<std::time::Duration, String> {
+    if arg.parse::<f64>().is_ok() {
+        return parse_positive_secs(arg, "interval");
+    }
+    let interval = humantime::parse_duration(arg)
+        .map_err(|e| format!("'{arg}' isn't an interval like '5s': {e}"))?;
+    if interval.is_zero() {
+        return Err("the interval must be positive".to_string());
+    }
+    Ok(interval)
+}
+
+fn parse_positive_secs(arg: &str, what: &str) -> Result<std::time::Duration, String> {
+    let secs: f64 = arg.parse().map_err(|_| format!("'{arg}' isn't a number"))?;
+    if secs <= 0.0 {
+        return Err(format!("the {what} must be positive"));
+    }
+    std::time::Duration::try_from_secs_f64(secs).map_err(|e| e.to_string())
+}
+
+/// The width of the terminal, from `COLUMNS`, or 120
+pub fn terminal_width() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .unwrap_or(120)
+}
@@ -0,0 +1,139 @@
+use std::path::PathBuf;
+
+use platform_dirs::AppDirs;
+use regex::Regex;
+use samply_analysis::ActivityPatterns;
+use serde::Deserialize;
+
+use crate::name::SAMPLY_NAME;
+use crate::shared::process_name::{NameRule, NameRules};
+
+/// User configuration, read from `config.json` in samply's config directory
+/// (e.g. `~/.config/samply/config.json` on Linux).
+///
+/// ```json
+/// {
+///   "transforms": ["drop-idle-threads", "rename-by-rules"],
+///   "rename_rules": [
+///     { "pattern": "::h", "replacement": "", "scope": "functions" }
+///   ],
+///   "blocked_functions": ["futex", "pthread_cond_wait", "MyLock::wait"],
+///   "name_rules": [
+///     { "pattern": "-jar (\\S+)\\.jar", "template": "java $1", "scope": "process" }
+///   ]
+/// }
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Transforms to apply to every recorded or imported profile before it is
+    /// saved, if none are given with `--transform`.
+    pub transforms: Vec<String>,
+    /// The rules used by the `rename-by-rules` transform.
+    pub rename_rules: Vec<RenameRule>,
+    /// Functions which make a sample count as idle in the analysis, replacing
+    /// the built-in list.
+    pub idle_functions: Option<Vec<String>>,
+    /// Functions which make a sample count as blocked in the analysis,
+    /// replacing the built-in list.
+    pub blocked_functions: Option<Vec<String>>,
+    /// Rules for the names of processes and threads in recorded or imported
+    /// profiles, after the `--name-template` ones.
+    pub name_rules: Vec<NameRuleConfig>,
+}
+
+impl Config {
+    /// The patterns which classify samples as idle or blocked
+    pub fn activity_patterns(&self) -> ActivityPatterns {
+        let defaults = ActivityPatterns::default();
+        ActivityPatterns {
+            idle: self.idle_functions.clone().unwrap_or(defaults.idle),
+            blocked: self.blocked_functions.clone().unwrap_or(defaults.blocked),
+        }
+    }
+
+    /// The `name_rules`, without the ones whose pattern is invalid, which
+    /// are reported
+    pub fn name_rules(&self) -> NameRules {
+        let mut rules = NameRules::default();
+        for rule in &self.name_rules {
+            let pattern = match Regex::new(&rule.pattern) {
+                Ok(pattern) => pattern,
+                Err(err) => {
+                    eprintln!("Warning: Ignoring name rule {:?}: {err}", rule.pattern);
+                    continue;
+                }
+            };
+            let rule_list = match rule.scope {
+                NameScope::Process => &mut rules.process,
+                NameScope::Thread => &mut rules.thread,
+            };
+            rule_list.push(NameRule {
+                pattern,
+                template: rule.template.clone(),
+            });
+        }
+        rules
+    }
+}
+
+/// Names the processes or threads whose name matches the `pattern` regex
+/// after `template`, in which `$1` or `$name` stand for the capture groups.
+#[derive(Debug, Clone, Deserialize)]
+pub struct NameRuleConfig {
+    pub pattern: String,
+    pub template: String,
+    #[serde(default)]
+    pub scope: NameScope,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NameScope {
+    /// Process names, matched against the command line
+    #[default]
+    Process,
+    /// Thread names
+    Thread,
+}
+
+/// Replaces every occurrence of `pattern` with `replacement` in the names in `scope`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RenameRule {
+    pub pattern: String,
+    pub replacement: String,
+    #[serde(default)]
+    pub scope: RenameScope,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenameScope {
+    /// Thread and process names
+    #[default]
+    Threads,
+    /// Function names
+    Functions,
+}
+
+pub fn config_file_path() -> Option<PathBuf> {
+    AppDirs::new(Some(SAMPLY_NAME), true).map(|dirs| dirs.config_dir.join("config.json"))
+}
+
+/// Load the config file. A missing file gives the default configuration; an
+/// invalid one is reported and ignored.
+pub fn load_config() -> Config {
+    let Some(path) = config_file_path() else {
+        return Config::default();
+    };
+    let Ok(contents) = std::fs::read_to_string(&path) else {
+        return Config::default();
+    };
+    match serde_json::from_str(&contents) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Warning: Ignoring invalid config file {path:?}: {err}");
+            Config::default()
+        }
+    }
+}
@@ -0,0 +1,279 @@
+//! Importing the output of dtrace stack aggregations, e.g. of
+//! `dtrace -n 'profile-997 /pid == $target/ { @[ustack()] = count(); }'`.
+//!
+//! Each aggregation record is a list of frames, leaf first, and a count:
+//!
+//! ```text
+//!               libsystem_kernel.dylib`__psynch_cvwait+0xa
+//!               myapp`main+0x24
+//!               dyld`start+0x7f0
+//!                 5
+//! ```
+//!
+//! Lines before the frames of a record are the other aggregation keys, e.g.
+//! `execname` or `tid` in `@[execname, tid, ustack()]`; records are grouped
+//! into one thread per distinct key. Frames become label frames named after
+//! the function, without the offset, so that the samples of a function add
+//! up. dtrace aggregations have no sample times, so the samples of each
+//! thread are laid out back to back, one sampling interval per count.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use fxprof_processed_profile::{
+    Category, CategoryColor, CategoryHandle, CpuDelta, FrameFlags, FrameHandle, Profile,
+    ReferenceTimestamp, SamplingInterval, ThreadHandle, Timestamp,
+};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("I/O Error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("No stack aggregation records found")]
+    NoStacks,
+}
+
+/// The interval for recordings whose probe isn't known. dtrace writes the
+/// probe description to stderr, so it's often not in the file.
+const DEFAULT_INTERVAL_MS: f64 = 1.0;
+
+/// Whether the file looks like dtrace output: a `.dtrace` file, or text which
+/// starts with dtrace's probe description or contains a `module`function+0x`
+/// frame.
+pub fn is_dtrace_file(path: &Path, first_bytes: &[u8]) -> bool {
+    if path.extension().is_some_and(|ext| ext == "dtrace") {
+        return true;
+    }
+    // The first bytes may end in the middle of a character.
+    let text = match std::str::from_utf8(first_bytes) {
+        Ok(text) => text,
+        Err(err) if err.error_len().is_none() => {
+            std::str::from_utf8(&first_bytes[..err.valid_up_to()]).unwrap()
+        }
+        Err(_) => return false,
+    };
+    text.trim_start().starts_with("dtrace:") || (text.contains('`') && text.contains("+0x"))
+}
+
+/// One aggregation record
+struct Record {
+    /// The other aggregation keys, empty if the stack is the only key
+    key: String,
+    /// Leaf first
+    frames: Vec<String>,
+    count: u64,
+}
+
+/// The sampling interval of a `profile-<rate>` probe, from the description
+/// line `dtrace: description 'profile-997 ' matched 1 probe`. A rate without
+/// a unit is in Hz.
+fn interval_ms_from_description(line: &str) -> Option<f64> {
+    let rate = line.split("profile-").nth(1)?;
+    let rate = rate
+        .split(|c: char| c == ' ' || c == '\'' || c == '/')
+        .next()?;
+    let digits_end = rate
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rate.len());
+    let value: f64 = rate[..digits_end].parse().ok()?;
+    if value == 0.0 {
+        return None;
+    }
+    match &rate[digits_end..] {
+        "" | "hz" => Some(1000.0 / value),
+        "ns" | "nsec" => Some(value / 1_000_000.0),
+        "us" | "usec" => Some(value / 1000.0),
+        "ms" | "msec" => Some(value),
+        "s" | "sec" => Some(value * 1000.0),
+        _ => None,
+    }
+}
+
+fn is_frame_line(line: &str) -> bool {
+    line.contains('`') || (line.starts_with("0x") && u64::from_str_radix(&line[2..], 16).is_ok())
+}
+
+/// Parse the aggregation records, and the sampling interval if the probe
+/// description is included.
+fn parse_records(reader: impl BufRead) -> Result<(Vec<Record>, Option<f64>), Error> {
+    let mut records = Vec::new();
+    let mut interval_ms = None;
+    let mut key_parts: Vec<String> = Vec::new();
+    let mut frames: Vec<String> = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            // A record without a count; dtrace doesn't print those, but
+            // truncated output can end with one.
+            key_parts.clear();
+            frames.clear();
+            continue;
+        }
+        if let Some(description) = line.strip_prefix("dtrace:") {
+            interval_ms = interval_ms.or_else(|| interval_ms_from_description(description));
+            continue;
+        }
+        if line == "^C" {
+            continue;
+        }
+        if !frames.is_empty() {
+            // After the frames, a number ends the record. Frames which aren't
+            // `module`function`, e.g. from jstack(), continue it.
+            if let Ok(count) = line.parse::<u64>() {
+                records.push(Record {
+                    key: key_parts.join(" "),
+                    frames: std::mem::take(&mut frames),
+                    count,
+                });
+                key_parts.clear();
+            } else {
+                frames.push(line.to_string());
+            }
+        } else if is_frame_line(line) {
+            frames.push(line.to_string());
+        } else {
+            key_parts.push(line.split_whitespace().collect::<Vec<_>>().join(" "));
+        }
+    }
+    Ok((records, interval_ms))
+}
+
+/// The function of a frame line, without the offset: `myapp`main+0x24`
+/// becomes `main`. Unsymbolicated frames keep their module and address.
+fn frame_label(frame: &str) -> (&str, Option<&str>) {
+    let Some((module, function)) = frame.split_once('`') else {
+        return (frame, None);
+    };
+    if function.starts_with("0x") {
+        return (frame, Some(module));
+    }
+    let function = match function.rsplit_once("+0x") {
+        Some((name, offset)) if u64::from_str_radix(offset, 16).is_ok() => name,
+        _ => function,
+    };
+    (function, Some(module))
+}
+
+fn is_kernel_module(module: &str) -> bool {
+    module == "kernel" || module.starts_with("mach_kernel") || module.starts_with("unix")
+}
+
+pub fn convert(input_path: &Path, reader: impl Read) -> Result<Profile, Error> {
+    let (records, interval_ms) = parse_records(BufReader::new(reader))?;
+    if records.is_empty() {
+        return Err(Error::NoStacks);
+    }
+    let interval_ms = interval_ms.unwrap_or(DEFAULT_INTERVAL_MS);
+
+    let product = input_path
+        .file_name()
+        .map_or("dtrace".into(), |name| name.to_string_lossy());
+    let mut profile = Profile::new(
+        &product,
+        ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+        SamplingInterval::from_nanos((interval_ms * 1_000_000.0) as u64),
+    );
+    let process = profile.add_process(&product, 0, Timestamp::from_millis_since_reference(0.0));
+    let kernel_category = profile.handle_for_category(Category("Kernel", CategoryColor::Orange));
+
+    // key -> (thread, time of its next sample in ms)
+    let mut threads: HashMap<String, (ThreadHandle, f64)> = HashMap::new();
+    let mut frame_handles: HashMap<(ThreadHandle, String), FrameHandle> = HashMap::new();
+
+    for record in &records {
+        let thread_count = threads.len();
+        let (thread, time_ms) = threads.entry(record.key.clone()).or_insert_with(|| {
+            let thread = profile.add_thread(
+                process,
+                thread_count as u32,
+                Timestamp::from_millis_since_reference(0.0),
+                thread_count == 0,
+            );
+            let name = if record.key.is_empty() {
+                &*product
+            } else {
+                &record.key
+            };
+            profile.set_thread_name(thread, name);
+            (thread, 0.0)
+        });
+        let thread = *thread;
+
+        // Frames are leaf first; stacks are built from the root.
+        let mut stack = None;
+        for frame in record.frames.iter().rev() {
+            let frame_handle = *frame_handles
+                .entry((thread, frame.clone()))
+                .or_insert_with(|| {
+                    let (label, module) = frame_label(frame);
+                    let category = if module.is_some_and(is_kernel_module) {
+                        kernel_category
+                    } else {
+                        CategoryHandle::OTHER
+                    };
+                    let label = profile.handle_for_string(label);
+                    profile.handle_for_frame_with_label(
+                        thread,
+                        label,
+                        category,
+                        FrameFlags::empty(),
+                    )
+                });
+            stack = Some(profile.handle_for_stack(thread, frame_handle, stack));
+        }
+
+        let weight = i32::try_from(record.count).unwrap_or(i32::MAX);
+        let duration_ms = interval_ms * record.count as f64;
+        profile.add_sample(
+            thread,
+            Timestamp::from_millis_since_reference(*time_ms),
+            stack,
+            CpuDelta::from_nanos((duration_ms * 1_000_000.0) as u64),
+            weight,
+        );
+        *time_ms += duration_ms;
+    }
+
+    Ok(profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_records() {
+        let output = "\
+dtrace: description 'profile-997 ' matched 1 probe
+^C
+
+  myapp
+              libsystem_kernel.dylib`__psynch_cvwait+0xa
+              myapp`main+0x24
+                5
+
+  myapp
+              myapp`0x100003f4c
+              0x100003f90
+               12
+";
+        let (records, interval_ms) = parse_records(output.as_bytes()).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].key, "myapp");
+        assert_eq!(records[0].frames.len(), 2);
+        assert_eq!(records[0].count, 5);
+        assert_eq!(records[1].frames, ["myapp`0x100003f4c", "0x100003f90"]);
+        assert_eq!(records[1].count, 12);
+        assert!((interval_ms.unwrap() - 1000.0 / 997.0).abs() < 1e-9);
+
+        assert_eq!(frame_label("myapp`main+0x24"), ("main", Some("myapp")));
+        assert_eq!(
+            frame_label("myapp`0x100003f4c"),
+            ("myapp`0x100003f4c", Some("myapp"))
+        );
+        assert_eq!(interval_ms_from_description(" 'profile-1ms '"), Some(1.0));
+    }
+}
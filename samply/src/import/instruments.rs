@@ -0,0 +1,443 @@
+//! Importing Xcode Instruments Time Profiler recordings, from the XML which
+//! `xctrace export` writes for the `time-profile` table of a `.trace` bundle:
+//!
+//! ```text
+//! xctrace export --input app.trace \
+//!     --xpath '/trace-toc/run[@number="1"]/data/table[@schema="time-profile"]'
+//! ```
+//!
+//! Each `<row>` is a sample with its time, thread, weight and backtrace.
+//! xctrace writes each distinct value once, with an `id` attribute, and
+//! refers back to it with `ref` in later rows, so elements with an ID are
+//! kept until the end of the file. Frames are leaf first, named by
+//! Instruments' symbolication; unsymbolicated frames are named after their
+//! binary and offset, e.g. `myapp + 0x3f4c`.
+//!
+//! A `.trace` bundle is exported with xctrace first, which needs Xcode.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::Command;
+use std::rc::Rc;
+
+use fxprof_processed_profile::{
+    Category, CategoryColor, CategoryHandle, CpuDelta, FrameFlags, FrameHandle, ProcessHandle,
+    Profile, ReferenceTimestamp, SamplingInterval, StackHandle, ThreadHandle, Timestamp,
+};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("I/O Error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid xctrace export: {0}")]
+    Xml(#[from] quick_xml::Error),
+
+    #[error("Invalid xctrace export: {0}")]
+    Attr(#[from] quick_xml::events::attributes::AttrError),
+
+    #[error("Invalid xctrace export: {0}")]
+    Malformed(&'static str),
+
+    #[error("Couldn't export the trace with xctrace, which comes with Xcode: {0}")]
+    Xctrace(String),
+}
+
+/// The table with the Time Profiler's samples in the trace's table of contents
+const TIME_PROFILE_XPATH: &str =
+    r#"/trace-toc/run[@number="1"]/data/table[@schema="time-profile"]"#;
+
+/// Whether the file is an xctrace export: XML with a `<trace-query-result>`.
+pub fn is_xctrace_export(first_bytes: &[u8]) -> bool {
+    String::from_utf8_lossy(first_bytes).contains("<trace-query-result")
+}
+
+/// Whether the path is an Instruments `.trace` bundle, which is a directory.
+pub fn is_trace_bundle(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "trace") && path.is_dir()
+}
+
+/// Export the Time Profiler table of the first run of a `.trace` bundle.
+pub fn export_trace_bundle(path: &Path) -> Result<Vec<u8>, Error> {
+    let output = Command::new("xcrun")
+        .args(["xctrace", "export", "--input"])
+        .arg(path)
+        .args(["--xpath", TIME_PROFILE_XPATH])
+        .output()
+        .map_err(|err| Error::Xctrace(err.to_string()))?;
+    if !output.status.success() {
+        return Err(Error::Xctrace(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(output.stdout)
+}
+
+/// An element of a row, with its children
+#[derive(Debug, Default)]
+struct Node {
+    name: String,
+    attrs: Vec<(String, String)>,
+    text: String,
+    children: Vec<Rc<Node>>,
+}
+
+impl Node {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn child(&self, name: &str) -> Option<&Rc<Node>> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Rc<Node>> + 'a {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    fn number(&self) -> Option<u64> {
+        self.text.trim().parse().ok()
+    }
+}
+
+/// An element whose end tag hasn't been read yet
+enum OpenElement {
+    Node(Node),
+    /// An element with a `ref`, which stands for the element it refers to
+    Ref(Option<Rc<Node>>),
+}
+
+/// Reads the rows of an xctrace export one by one, resolving references.
+struct RowReader<R: BufRead> {
+    reader: Reader<R>,
+    buf: Vec<u8>,
+    /// Elements with an `id`, which later rows may refer to
+    ids: HashMap<String, Rc<Node>>,
+    open: Vec<OpenElement>,
+}
+
+impl<R: BufRead> RowReader<R> {
+    fn new(reader: R) -> Self {
+        RowReader {
+            reader: Reader::from_reader(reader),
+            buf: Vec::new(),
+            ids: HashMap::new(),
+            open: Vec::new(),
+        }
+    }
+
+    fn open_element(
+        ids: &HashMap<String, Rc<Node>>,
+        start: &BytesStart,
+    ) -> Result<OpenElement, Error> {
+        let mut attrs = Vec::new();
+        for attr in start.attributes() {
+            let attr = attr?;
+            let key = String::from_utf8_lossy(attr.key.as_ref()).into_owned();
+            let value = attr.unescape_value()?.into_owned();
+            if key == "ref" {
+                return Ok(OpenElement::Ref(ids.get(&value).cloned()));
+            }
+            attrs.push((key, value));
+        }
+        Ok(OpenElement::Node(Node {
+            name: String::from_utf8_lossy(start.name().as_ref()).into_owned(),
+            attrs,
+            ..Default::default()
+        }))
+    }
+
+    /// Finish an element, and return it if it's a row.
+    fn close_element(&mut self, element: OpenElement) -> Option<Rc<Node>> {
+        let node = match element {
+            OpenElement::Node(node) => {
+                let node = Rc::new(node);
+                // Sample times are unique, so they're never referred to.
+                if node.name != "sample-time" {
+                    if let Some(id) = node.attr("id") {
+                        self.ids.insert(id.to_string(), Rc::clone(&node));
+                    }
+                }
+                node
+            }
+            OpenElement::Ref(node) => node?,
+        };
+        match self.open.last_mut() {
+            Some(OpenElement::Node(parent)) => parent.children.push(node),
+            Some(OpenElement::Ref(_)) => {}
+            None if node.name == "row" => return Some(node),
+            None => {}
+        }
+        None
+    }
+
+    fn next_row(&mut self) -> Result<Option<Rc<Node>>, Error> {
+        loop {
+            self.buf.clear();
+            match self.reader.read_event_into(&mut self.buf)? {
+                Event::Start(start) => {
+                    // Only rows and their contents are kept, not the schema.
+                    if !self.open.is_empty() || start.name().as_ref() == b"row" {
+                        let element = Self::open_element(&self.ids, &start)?;
+                        self.open.push(element);
+                    }
+                }
+                Event::Empty(start) => {
+                    if !self.open.is_empty() || start.name().as_ref() == b"row" {
+                        let element = Self::open_element(&self.ids, &start)?;
+                        if let Some(row) = self.close_element(element) {
+                            return Ok(Some(row));
+                        }
+                    }
+                }
+                Event::Text(text) => {
+                    if let Some(OpenElement::Node(node)) = self.open.last_mut() {
+                        node.text.push_str(&text.unescape()?);
+                    }
+                }
+                Event::End(_) => {
+                    if let Some(element) = self.open.pop() {
+                        if let Some(row) = self.close_element(element) {
+                            return Ok(Some(row));
+                        }
+                    }
+                }
+                Event::Eof => {
+                    if !self.open.is_empty() {
+                        return Err(Error::Malformed("unexpected end of file"));
+                    }
+                    return Ok(None);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// `myapp (1234)` -> `myapp`
+fn process_name(fmt: &str) -> &str {
+    match fmt.rsplit_once(" (") {
+        Some((name, _)) => name,
+        None => fmt,
+    }
+}
+
+/// `Main Thread 0x8f9b (myapp, pid: 1234)` -> `Main Thread`
+fn thread_name(fmt: &str) -> &str {
+    let name = process_name(fmt);
+    match name.rsplit_once(" 0x") {
+        Some((name, tid)) if u64::from_str_radix(tid, 16).is_ok() => name,
+        _ => name,
+    }
+}
+
+fn parse_hex(s: &str) -> Option<u64> {
+    u64::from_str_radix(s.strip_prefix("0x")?, 16).ok()
+}
+
+/// The name of a frame: its symbol if Instruments found one, otherwise its
+/// binary and offset, or only its address.
+fn frame_label(frame: &Node) -> String {
+    if let Some(name) = frame.attr("name") {
+        if !name.is_empty() && !name.starts_with("0x") {
+            return name.to_string();
+        }
+    }
+    let address = frame.attr("addr").and_then(parse_hex);
+    let binary = frame.child("binary");
+    let binary_name = binary.and_then(|binary| binary.attr("name"));
+    let load_address = binary.and_then(|binary| binary.attr("load-addr").and_then(parse_hex));
+    match (address, binary_name, load_address) {
+        (Some(address), Some(binary), Some(load_address)) if address >= load_address => {
+            format!("{binary} + {:#x}", address - load_address)
+        }
+        (Some(address), ..) => format!("{address:#x}"),
+        _ => "<unknown>".to_string(),
+    }
+}
+
+fn is_kernel_frame(frame: &Node) -> bool {
+    frame
+        .child("binary")
+        .and_then(|binary| binary.attr("name"))
+        .is_some_and(|name| name.starts_with("kernel") || name.starts_with("mach_kernel"))
+}
+
+/// Turns rows into samples, creating processes, threads and frames on first
+/// use.
+struct Converter {
+    profile: Profile,
+    kernel_category: CategoryHandle,
+    interval_set: bool,
+    processes: HashMap<u64, ProcessHandle>,
+    threads: HashMap<(u64, u64), ThreadHandle>,
+    /// By the address of the frame's node; refs share the node
+    frames: HashMap<(ThreadHandle, *const Node), FrameHandle>,
+    stacks: HashMap<(ThreadHandle, *const Node), Option<StackHandle>>,
+}
+
+impl Converter {
+    fn thread(&mut self, thread: &Node) -> ThreadHandle {
+        let tid = thread
+            .child("tid")
+            .and_then(|tid| tid.number())
+            .unwrap_or(0);
+        let process = thread.child("process");
+        let pid = process
+            .and_then(|process| process.child("pid"))
+            .and_then(|pid| pid.number())
+            .unwrap_or(0);
+        if let Some(&handle) = self.threads.get(&(pid, tid)) {
+            return handle;
+        }
+        let start_time = Timestamp::from_millis_since_reference(0.0);
+        let process_handle = *self.processes.entry(pid).or_insert_with(|| {
+            let name = process
+                .and_then(|process| process.attr("fmt"))
+                .map_or("", process_name);
+            self.profile.add_process(name, pid as u32, start_time)
+        });
+        let name = thread.attr("fmt").map_or("", thread_name);
+        let is_main = name.starts_with("Main Thread");
+        let handle = self
+            .profile
+            .add_thread(process_handle, tid as u32, start_time, is_main);
+        self.profile.set_thread_name(handle, name);
+        self.threads.insert((pid, tid), handle);
+        handle
+    }
+
+    fn stack(&mut self, thread: ThreadHandle, backtrace: &Rc<Node>) -> Option<StackHandle> {
+        if let Some(&stack) = self.stacks.get(&(thread, Rc::as_ptr(backtrace))) {
+            return stack;
+        }
+        let frames: Vec<&Rc<Node>> = backtrace.children("frame").collect();
+        let mut stack = None;
+        // Frames are leaf first; stacks are built from the root.
+        for frame in frames.into_iter().rev() {
+            let key = (thread, Rc::as_ptr(frame));
+            let frame_handle = match self.frames.get(&key) {
+                Some(&handle) => handle,
+                None => {
+                    let label = self.profile.handle_for_string(&frame_label(frame));
+                    let category = if is_kernel_frame(frame) {
+                        self.kernel_category
+                    } else {
+                        CategoryHandle::OTHER
+                    };
+                    let handle = self.profile.handle_for_frame_with_label(
+                        thread,
+                        label,
+                        category,
+                        FrameFlags::empty(),
+                    );
+                    self.frames.insert(key, handle);
+                    handle
+                }
+            };
+            stack = Some(self.profile.handle_for_stack(thread, frame_handle, stack));
+        }
+        self.stacks.insert((thread, Rc::as_ptr(backtrace)), stack);
+        stack
+    }
+
+    fn add_row(&mut self, row: &Node) {
+        // Rows of other tables, e.g. without a backtrace column, are skipped.
+        let (Some(thread), Some(backtrace)) = (row.child("thread"), row.child("backtrace")) else {
+            return;
+        };
+        let time_nanos = row
+            .child("sample-time")
+            .and_then(|time| time.number())
+            .unwrap_or(0);
+        let weight_nanos = row.child("weight").and_then(|weight| weight.number());
+        if let (false, Some(weight_nanos)) = (self.interval_set, weight_nanos) {
+            // The Time Profiler samples at a fixed interval, 1 ms by default.
+            self.profile
+                .set_interval(SamplingInterval::from_nanos(weight_nanos));
+            self.interval_set = true;
+        }
+        let thread = self.thread(thread);
+        let stack = self.stack(thread, backtrace);
+        let cpu_delta = CpuDelta::from_nanos(weight_nanos.unwrap_or(0));
+        self.profile.add_sample(
+            thread,
+            Timestamp::from_nanos_since_reference(time_nanos),
+            stack,
+            cpu_delta,
+            1,
+        );
+    }
+}
+
+pub fn convert(input_path: &Path, reader: impl Read) -> Result<Profile, Error> {
+    let product = input_path
+        .file_name()
+        .map_or("Instruments".into(), |name| name.to_string_lossy());
+    let mut profile = Profile::new(
+        &product,
+        ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+        SamplingInterval::from_millis(1),
+    );
+    let kernel_category = profile.handle_for_category(Category("Kernel", CategoryColor::Orange));
+    let mut converter = Converter {
+        profile,
+        kernel_category,
+        interval_set: false,
+        processes: HashMap::new(),
+        threads: HashMap::new(),
+        frames: HashMap::new(),
+        stacks: HashMap::new(),
+    };
+
+    let mut rows = RowReader::new(BufReader::new(reader));
+    while let Some(row) = rows.next_row()? {
+        converter.add_row(&row);
+    }
+    if converter.threads.is_empty() {
+        return Err(Error::Malformed(
+            "no Time Profiler samples; export the time-profile table",
+        ));
+    }
+    Ok(converter.profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rows_with_refs() {
+        let xml = r#"<?xml version="1.0"?>
+<trace-query-result>
+<node xpath='//trace-toc[1]/run[1]/data[1]/table[6]'><schema name="time-profile"><col><mnemonic>time</mnemonic></col></schema>
+<row><sample-time id="1" fmt="00:00.001.000">1000000</sample-time><thread id="2" fmt="Main Thread 0x8f9b (myapp, pid: 1234)"><tid id="3" fmt="0x8f9b">36763</tid><process id="4" fmt="myapp (1234)"><pid id="5" fmt="1234">1234</pid></process></thread><weight id="6" fmt="1.00 ms">1000000</weight><backtrace id="7"><frame id="8" name="std::vector&lt;int&gt;::push_back" addr="0x100003f4c"><binary id="9" name="myapp" load-addr="0x100000000"/></frame><frame id="10" name="0x100003f90" addr="0x100003f90"><binary ref="9"/></frame></backtrace></row>
+<row><sample-time id="11" fmt="00:00.002.000">2000000</sample-time><thread ref="2"/><weight ref="6"/><backtrace ref="7"/></row>
+</node></trace-query-result>"#;
+        let mut rows = RowReader::new(xml.as_bytes());
+        let first = rows.next_row().unwrap().unwrap();
+        let second = rows.next_row().unwrap().unwrap();
+        assert!(rows.next_row().unwrap().is_none());
+
+        assert!(Rc::ptr_eq(
+            first.child("backtrace").unwrap(),
+            second.child("backtrace").unwrap()
+        ));
+        assert_eq!(second.child("sample-time").unwrap().number(), Some(2000000));
+        let thread = second.child("thread").unwrap();
+        assert_eq!(thread_name(thread.attr("fmt").unwrap()), "Main Thread");
+        let frames: Vec<String> = first
+            .child("backtrace")
+            .unwrap()
+            .children("frame")
+            .map(|frame| frame_label(frame))
+            .collect();
+        assert_eq!(frames, ["std::vector<int>::push_back", "myapp + 0x3f90"]);
+    }
+}
@@ -0,0 +1,955 @@
+//! Importing Java Flight Recorder recordings (`.jfr`), as written by the JDK's
+//! `-XX:StartFlightRecording` and `jcmd <pid> JFR.start`, and by
+//! async-profiler's `-o jfr` output.
+//!
+//! A recording is a sequence of chunks. Each chunk describes the types of its
+//! events in a metadata event, and the threads, stack traces, methods and
+//! classes which its events refer to in constant pools, whose keys only hold
+//! within the chunk. Events are decoded by the field list of their type;
+//! events of the types which aren't imported are skipped by their size.
+//!
+//! What is imported:
+//! - `jdk.ExecutionSample`: CPU samples, one thread per Java thread
+//! - `jdk.ObjectAllocationSample`, `jdk.ObjectAllocationInNewTLAB` and
+//!   `jdk.ObjectAllocationOutsideTLAB`: the native allocations table of the
+//!   JVM's main thread, weighted by bytes, which the `allocations` query ranks
+//! - `jdk.JavaMonitorEnter`, `jdk.JavaMonitorWait` and `jdk.ThreadPark`:
+//!   interval markers with the stack which waited and the class of the lock
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+
+use fxprof_processed_profile::{
+    Category, CategoryColor, CategoryHandle, CpuDelta, FrameFlags, FrameHandle, Marker,
+    MarkerField, MarkerTiming, Profile, ReferenceTimestamp, SamplingInterval, Schema,
+    SourceLocation, StackHandle, StringHandle, ThreadHandle, Timestamp,
+};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("I/O Error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Malformed JFR recording: {0}")]
+    Malformed(&'static str),
+}
+
+const MAGIC: &[u8] = b"FLR\0";
+const HEADER_SIZE: usize = 68;
+const METADATA_EVENT: u64 = 0;
+const CHECKPOINT_EVENT: u64 = 1;
+/// Values and metadata elements aren't nested deeper than this
+const MAX_DEPTH: usize = 32;
+
+/// The period of `jdk.ExecutionSample` in JFR's default settings, for
+/// recordings without a `jdk.ActiveSetting` event for it
+const DEFAULT_PERIOD_NANOS: u64 = 20_000_000;
+
+/// The event types which are imported; the others aren't decoded
+const IMPORTED_EVENTS: &[&str] = &[
+    "jdk.ExecutionSample",
+    "jdk.ObjectAllocationSample",
+    "jdk.ObjectAllocationInNewTLAB",
+    "jdk.ObjectAllocationOutsideTLAB",
+    "jdk.JavaMonitorEnter",
+    "jdk.JavaMonitorWait",
+    "jdk.ThreadPark",
+    "jdk.ActiveSetting",
+    "jdk.JVMInformation",
+];
+
+/// Whether the file is a JFR recording: it starts with a chunk header, or it's
+/// a `.jfr` file.
+pub fn is_jfr_file(path: &Path, first_bytes: &[u8]) -> bool {
+    first_bytes.starts_with(MAGIC) || path.extension().is_some_and(|ext| ext == "jfr")
+}
+
+/// Reads the values of a chunk. With compressed integers (recordings of JDK 11
+/// and later), integers are varints of 7 bits per byte, whose ninth byte
+/// holds 8 bits; otherwise they are big-endian with their size in Java.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    compressed: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn at(&self, pos: usize) -> Reader<'a> {
+        Reader {
+            data: self.data,
+            pos,
+            compressed: self.compressed,
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or(Error::Malformed("truncated value"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn fixed(&mut self, size: usize) -> Result<u64, Error> {
+        Ok(self
+            .take(size)?
+            .iter()
+            .fold(0, |value, &byte| value << 8 | u64::from(byte)))
+    }
+
+    fn varint(&mut self) -> Result<u64, Error> {
+        let mut value = 0u64;
+        for shift in (0..56).step_by(7) {
+            let byte = self.u8()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte < 0x80 {
+                return Ok(value);
+            }
+        }
+        Ok(value | u64::from(self.u8()?) << 56)
+    }
+
+    /// An integer of `size` bytes in Java: 2 for shorts and chars, 4 for ints
+    /// and 8 for longs
+    fn int(&mut self, size: usize) -> Result<u64, Error> {
+        if self.compressed {
+            self.varint()
+        } else {
+            self.fixed(size)
+        }
+    }
+
+    fn long(&mut self) -> Result<u64, Error> {
+        self.int(8)
+    }
+
+    /// The length of an array or a string, which takes at least a byte per
+    /// element
+    fn len(&mut self) -> Result<usize, Error> {
+        let len = self.int(4)? as usize;
+        if len > self.data.len() - self.pos {
+            return Err(Error::Malformed("length beyond the end of the chunk"));
+        }
+        Ok(len)
+    }
+
+    fn string(&mut self) -> Result<Option<String>, Error> {
+        Ok(match self.u8()? {
+            0 => None,
+            1 => Some(String::new()),
+            // A reference into a constant pool of strings, which the JDK
+            // doesn't write
+            2 => {
+                self.long()?;
+                None
+            }
+            3 => {
+                let len = self.len()?;
+                Some(String::from_utf8_lossy(self.take(len)?).into_owned())
+            }
+            4 => {
+                let len = self.len()?;
+                let mut chars = String::with_capacity(len);
+                for _ in 0..len {
+                    let code = self.int(2)? as u32;
+                    chars.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                }
+                Some(chars)
+            }
+            5 => {
+                let len = self.len()?;
+                Some(
+                    self.take(len)?
+                        .iter()
+                        .map(|&byte| char::from(byte))
+                        .collect(),
+                )
+            }
+            _ => return Err(Error::Malformed("unknown string encoding")),
+        })
+    }
+}
+
+/// A type of the metadata: an event type, a constant pool type, a struct or
+/// a primitive
+struct Class {
+    name: String,
+    fields: Vec<Field>,
+}
+
+struct Field {
+    name: String,
+    class: u64,
+    /// The value is a key into the constant pool of `class`
+    constant_pool: bool,
+    array: bool,
+}
+
+/// An element of the metadata event's tree
+struct Element {
+    name: String,
+    attributes: HashMap<String, String>,
+    children: Vec<Element>,
+}
+
+/// A string of the metadata event, by its index
+fn metadata_string(reader: &mut Reader, strings: &[String]) -> Result<String, Error> {
+    let index = reader.int(4)? as usize;
+    strings
+        .get(index)
+        .cloned()
+        .ok_or(Error::Malformed("metadata string index out of range"))
+}
+
+fn parse_element(reader: &mut Reader, strings: &[String], depth: usize) -> Result<Element, Error> {
+    if depth > MAX_DEPTH {
+        return Err(Error::Malformed("metadata nested too deeply"));
+    }
+    let name = metadata_string(reader, strings)?;
+    let mut attributes = HashMap::new();
+    for _ in 0..reader.len()? {
+        let key = metadata_string(reader, strings)?;
+        let value = metadata_string(reader, strings)?;
+        attributes.insert(key, value);
+    }
+    let children = (0..reader.len()?)
+        .map(|_| parse_element(reader, strings, depth + 1))
+        .collect::<Result<_, _>>()?;
+    Ok(Element {
+        name,
+        attributes,
+        children,
+    })
+}
+
+/// The types of a chunk, by id, from its metadata event
+fn parse_metadata(mut reader: Reader) -> Result<HashMap<u64, Class>, Error> {
+    let _size = reader.int(4)?;
+    if reader.long()? != METADATA_EVENT {
+        return Err(Error::Malformed("no metadata event at the metadata offset"));
+    }
+    let _start_time = reader.long()?;
+    let _duration = reader.long()?;
+    let _metadata_id = reader.long()?;
+    let mut strings = Vec::new();
+    for _ in 0..reader.len()? {
+        strings.push(reader.string()?.unwrap_or_default());
+    }
+    let root = parse_element(&mut reader, &strings, 0)?;
+
+    let attribute = |element: &Element, name: &str| element.attributes.get(name).cloned();
+    let mut classes = HashMap::new();
+    let class_elements = root
+        .children
+        .iter()
+        .filter(|element| element.name == "metadata")
+        .flat_map(|metadata| &metadata.children)
+        .filter(|element| element.name == "class");
+    for class in class_elements {
+        let Some(id) = attribute(class, "id").and_then(|id| id.parse().ok()) else {
+            continue;
+        };
+        let fields = class
+            .children
+            .iter()
+            .filter(|element| element.name == "field")
+            .map(|field| Field {
+                name: attribute(field, "name").unwrap_or_default(),
+                class: attribute(field, "class")
+                    .and_then(|class| class.parse().ok())
+                    .unwrap_or(u64::MAX),
+                constant_pool: attribute(field, "constantPool").as_deref() == Some("true"),
+                array: attribute(field, "dimension").as_deref() == Some("1"),
+            })
+            .collect();
+        let name = attribute(class, "name").unwrap_or_default();
+        classes.insert(id, Class { name, fields });
+    }
+    Ok(classes)
+}
+
+/// A decoded value. References into the constant pools are resolved when
+/// they're used.
+enum Value {
+    Null,
+    /// Booleans, bytes, chars, shorts, ints and longs
+    Int(i64),
+    Float(f64),
+    String(String),
+    /// A key into the constant pool of a type: (type, key)
+    Ref(u64, u64),
+    Array(Vec<Value>),
+    /// The field values of a type, in the order of its fields: (type, values)
+    Object(u64, Vec<Value>),
+}
+
+impl Value {
+    fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Int(value) => Some(*value),
+            Value::Float(value) => Some(*value as i64),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// The types and constant pools of a chunk, and its clock
+struct Chunk {
+    classes: HashMap<u64, Class>,
+    pools: HashMap<(u64, u64), Value>,
+    start_nanos: i64,
+    start_ticks: i64,
+    ticks_per_second: i64,
+}
+
+impl Chunk {
+    fn read_value(&self, reader: &mut Reader, class_id: u64, depth: usize) -> Result<Value, Error> {
+        let class = self
+            .classes
+            .get(&class_id)
+            .ok_or(Error::Malformed("value of an unknown type"))?;
+        Ok(match class.name.as_str() {
+            "boolean" | "byte" => Value::Int(i64::from(reader.u8()? as i8)),
+            "char" => Value::Int(i64::from(reader.int(2)? as u16)),
+            "short" => Value::Int(i64::from(reader.int(2)? as u16 as i16)),
+            "int" => Value::Int(i64::from(reader.int(4)? as u32 as i32)),
+            "long" => Value::Int(reader.long()? as i64),
+            "float" => Value::Float(f64::from(f32::from_bits(reader.fixed(4)? as u32))),
+            "double" => Value::Float(f64::from_bits(reader.fixed(8)?)),
+            "java.lang.String" => reader.string()?.map_or(Value::Null, Value::String),
+            _ => {
+                if depth > MAX_DEPTH {
+                    return Err(Error::Malformed("values nested too deeply"));
+                }
+                let values = class
+                    .fields
+                    .iter()
+                    .map(|field| self.read_field(reader, field, depth + 1))
+                    .collect::<Result<_, _>>()?;
+                Value::Object(class_id, values)
+            }
+        })
+    }
+
+    fn read_field(&self, reader: &mut Reader, field: &Field, depth: usize) -> Result<Value, Error> {
+        if field.array {
+            let values = (0..reader.len()?)
+                .map(|_| self.read_element(reader, field, depth))
+                .collect::<Result<_, _>>()?;
+            Ok(Value::Array(values))
+        } else {
+            self.read_element(reader, field, depth)
+        }
+    }
+
+    /// A value of a field, or an element of an array field
+    fn read_element(
+        &self,
+        reader: &mut Reader,
+        field: &Field,
+        depth: usize,
+    ) -> Result<Value, Error> {
+        if field.constant_pool {
+            Ok(Value::Ref(field.class, reader.long()?))
+        } else {
+            self.read_value(reader, field.class, depth)
+        }
+    }
+
+    /// Read the constant pools of a checkpoint event, after its size and type
+    fn read_checkpoint(&mut self, reader: &mut Reader) -> Result<(), Error> {
+        let _start_time = reader.long()?;
+        let _duration = reader.long()?;
+        let _delta_to_previous = reader.long()?;
+        let _checkpoint_type = reader.u8()?;
+        for _ in 0..reader.len()? {
+            let class = reader.long()?;
+            for _ in 0..reader.len()? {
+                let key = reader.long()?;
+                let value = self.read_value(reader, class, 0)?;
+                self.pools.insert((class, key), value);
+            }
+        }
+        Ok(())
+    }
+
+    /// The value which `value` refers to, if it's a reference
+    fn resolve<'v>(&'v self, value: &'v Value) -> &'v Value {
+        match value {
+            Value::Ref(class, key) => self.pools.get(&(*class, *key)).unwrap_or(&Value::Null),
+            value => value,
+        }
+    }
+
+    /// The value of the field `name` of the object `value`, resolved
+    fn field<'v>(&'v self, value: &'v Value, name: &str) -> &'v Value {
+        let Value::Object(class, values) = self.resolve(value) else {
+            return &Value::Null;
+        };
+        self.classes
+            .get(class)
+            .and_then(|class| class.fields.iter().position(|field| field.name == name))
+            .and_then(|index| values.get(index))
+            .map_or(&Value::Null, |value| self.resolve(value))
+    }
+
+    /// The string of a `jdk.types.Symbol`
+    fn symbol<'v>(&'v self, value: &'v Value) -> Option<&'v str> {
+        match self.resolve(value) {
+            Value::String(string) => Some(string),
+            symbol => self.field(symbol, "string").as_str(),
+        }
+    }
+
+    /// The name of a `java.lang.Class`, e.g. "java.util.HashMap"
+    fn class_name(&self, class: &Value) -> Option<String> {
+        let name = self.symbol(self.field(class, "name"))?;
+        Some(name.replace('/', "."))
+    }
+
+    /// The name of a `jdk.types.Method` with its class, e.g.
+    /// "java.util.HashMap.put". Native frames of async-profiler have no class.
+    fn method_name(&self, method: &Value) -> Option<String> {
+        let name = self.symbol(self.field(method, "name"))?;
+        Some(match self.class_name(self.field(method, "type")) {
+            Some(class) if !class.is_empty() => format!("{class}.{name}"),
+            _ => name.to_string(),
+        })
+    }
+
+    /// Nanoseconds since the Unix epoch of a time in ticks
+    fn nanos(&self, ticks: i64) -> i64 {
+        let elapsed = i128::from(ticks - self.start_ticks) * 1_000_000_000
+            / i128::from(self.ticks_per_second);
+        self.start_nanos + elapsed as i64
+    }
+
+    fn class_id(&self, name: &str) -> Option<u64> {
+        self.classes
+            .iter()
+            .find(|(_, class)| class.name == name)
+            .map(|(id, _)| *id)
+    }
+}
+
+/// A frame of a Java stack, the same in every chunk
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Frame {
+    function: String,
+    line: Option<u32>,
+    /// Native, C++ and kernel frames, which async-profiler records
+    is_native: bool,
+}
+
+struct JavaThread {
+    name: String,
+    tid: u32,
+}
+
+enum EventKind {
+    Sample,
+    Allocation {
+        bytes: i64,
+    },
+    Lock {
+        name: &'static str,
+        class: Option<String>,
+        end_nanos: i64,
+    },
+}
+
+struct Event {
+    nanos: i64,
+    thread: usize,
+    stack: Option<usize>,
+    kind: EventKind,
+}
+
+/// The events of all chunks, with their threads and stacks
+#[derive(Default)]
+struct Recording {
+    start_nanos: Option<i64>,
+    period_nanos: Option<u64>,
+    pid: Option<u32>,
+    threads: Vec<JavaThread>,
+    /// By (OS thread id, Java thread id)
+    thread_indexes: HashMap<(i64, i64), usize>,
+    frames: Vec<Frame>,
+    frame_indexes: HashMap<Frame, usize>,
+    /// Frame indexes, leaf first
+    stacks: Vec<Vec<usize>>,
+    events: Vec<Event>,
+}
+
+impl Recording {
+    /// Read the chunk at the start of `data`, and return its size.
+    fn read_chunk(&mut self, data: &[u8]) -> Result<usize, Error> {
+        if data.len() < HEADER_SIZE || !data.starts_with(MAGIC) {
+            return Err(Error::Malformed("no chunk header"));
+        }
+        let mut header = Reader {
+            data,
+            pos: MAGIC.len(),
+            compressed: false,
+        };
+        let _major_version = header.fixed(2)?;
+        let _minor_version = header.fixed(2)?;
+        let size = header.fixed(8)? as usize;
+        let _constant_pool_offset = header.fixed(8)?;
+        let metadata_offset = header.fixed(8)? as usize;
+        let start_nanos = header.fixed(8)? as i64;
+        let _duration_nanos = header.fixed(8)?;
+        let start_ticks = header.fixed(8)? as i64;
+        let ticks_per_second = header.fixed(8)? as i64;
+        let features = header.fixed(4)?;
+        // A chunk which is still being written has no size yet.
+        let size = if size == 0 { data.len() } else { size };
+        if size < HEADER_SIZE || size > data.len() {
+            return Err(Error::Malformed("chunk size beyond the end of the file"));
+        }
+
+        let reader = Reader {
+            data: &data[..size],
+            pos: 0,
+            compressed: features & 1 != 0,
+        };
+        let mut chunk = Chunk {
+            classes: parse_metadata(reader.at(metadata_offset))?,
+            pools: HashMap::new(),
+            start_nanos,
+            start_ticks,
+            ticks_per_second: ticks_per_second.max(1),
+        };
+
+        // The constant pools first, which the other events refer to.
+        let mut events = Vec::new();
+        let mut pos = HEADER_SIZE;
+        while pos < size {
+            let mut event = reader.at(pos);
+            let event_size = event.int(4)? as usize;
+            if event_size == 0 || event_size > size - pos {
+                return Err(Error::Malformed("event size beyond the end of the chunk"));
+            }
+            match event.long()? {
+                METADATA_EVENT => {}
+                CHECKPOINT_EVENT => chunk.read_checkpoint(&mut event)?,
+                type_id => {
+                    let imported = chunk
+                        .classes
+                        .get(&type_id)
+                        .is_some_and(|class| IMPORTED_EVENTS.contains(&class.name.as_str()));
+                    if imported {
+                        events.push((type_id, event));
+                    }
+                }
+            }
+            pos += event_size;
+        }
+
+        let mut stack_indexes = HashMap::new();
+        for (type_id, mut reader) in events {
+            let event = chunk.read_value(&mut reader, type_id, 0)?;
+            let name = chunk.classes[&type_id].name.as_str();
+            self.add_event(&chunk, &mut stack_indexes, name, &event);
+        }
+        self.start_nanos = Some(self.start_nanos.map_or(start_nanos, |s| s.min(start_nanos)));
+        Ok(size)
+    }
+
+    /// Add an event of the type `name`. `stack_indexes` are the indexes of the
+    /// chunk's stack traces in `stacks`, by key.
+    fn add_event(
+        &mut self,
+        chunk: &Chunk,
+        stack_indexes: &mut HashMap<u64, Option<usize>>,
+        name: &str,
+        event: &Value,
+    ) {
+        let field = |name: &str| chunk.field(event, name);
+        let nanos = chunk.nanos(field("startTime").as_i64().unwrap_or(0));
+        let kind = match name {
+            "jdk.ExecutionSample" => EventKind::Sample,
+            "jdk.ObjectAllocationSample" => EventKind::Allocation {
+                bytes: field("weight").as_i64().unwrap_or(0),
+            },
+            // The size of the new TLAB stands for the allocations in the old one.
+            "jdk.ObjectAllocationInNewTLAB" => EventKind::Allocation {
+                bytes: field("tlabSize")
+                    .as_i64()
+                    .filter(|&size| size > 0)
+                    .or(field("allocationSize").as_i64())
+                    .unwrap_or(0),
+            },
+            "jdk.ObjectAllocationOutsideTLAB" => EventKind::Allocation {
+                bytes: field("allocationSize").as_i64().unwrap_or(0),
+            },
+            "jdk.JavaMonitorEnter" | "jdk.JavaMonitorWait" | "jdk.ThreadPark" => {
+                let (name, class_field) = match name {
+                    "jdk.JavaMonitorEnter" => ("JavaMonitorEnter", "monitorClass"),
+                    "jdk.JavaMonitorWait" => ("JavaMonitorWait", "monitorClass"),
+                    _ => ("ThreadPark", "parkedClass"),
+                };
+                let duration = field("duration").as_i64().unwrap_or(0);
+                EventKind::Lock {
+                    name,
+                    class: chunk.class_name(field(class_field)),
+                    end_nanos: chunk.nanos(field("startTime").as_i64().unwrap_or(0) + duration),
+                }
+            }
+            "jdk.ActiveSetting" => {
+                let is_sample_period = field("name").as_str() == Some("period")
+                    && field("id").as_i64().map(|id| id as u64)
+                        == chunk.class_id("jdk.ExecutionSample");
+                if let Some(period) = field("value").as_str().filter(|_| is_sample_period) {
+                    self.period_nanos = self.period_nanos.or(parse_period(period));
+                }
+                return;
+            }
+            "jdk.JVMInformation" => {
+                self.pid = field("pid")
+                    .as_i64()
+                    .and_then(|pid| u32::try_from(pid).ok());
+                return;
+            }
+            _ => return,
+        };
+        if matches!(kind, EventKind::Allocation { bytes } if bytes <= 0) {
+            return;
+        }
+
+        let thread_field = match kind {
+            EventKind::Sample => "sampledThread",
+            _ => "eventThread",
+        };
+        let Some(thread) = self.thread_index(chunk, field(thread_field)) else {
+            return;
+        };
+        let stack_trace = match event {
+            Value::Object(class, values) => chunk.classes[class]
+                .fields
+                .iter()
+                .position(|field| field.name == "stackTrace")
+                .and_then(|index| values.get(index)),
+            _ => None,
+        };
+        let stack = match stack_trace {
+            Some(reference @ Value::Ref(_, key)) => *stack_indexes
+                .entry(*key)
+                .or_insert_with(|| self.stack_index(chunk, reference)),
+            Some(stack_trace) => self.stack_index(chunk, stack_trace),
+            None => None,
+        };
+        self.events.push(Event {
+            nanos,
+            thread,
+            stack,
+            kind,
+        });
+    }
+
+    fn thread_index(&mut self, chunk: &Chunk, thread: &Value) -> Option<usize> {
+        if matches!(thread, Value::Null) {
+            return None;
+        }
+        let os_id = chunk.field(thread, "osThreadId").as_i64().unwrap_or(0);
+        let java_id = chunk.field(thread, "javaThreadId").as_i64().unwrap_or(0);
+        if let Some(&index) = self.thread_indexes.get(&(os_id, java_id)) {
+            return Some(index);
+        }
+        let name = [
+            chunk.field(thread, "javaName"),
+            chunk.field(thread, "osName"),
+        ]
+        .into_iter()
+        .filter_map(Value::as_str)
+        .find(|name| !name.is_empty())
+        .map_or_else(|| format!("Thread {os_id}"), str::to_string);
+        let tid = if os_id != 0 { os_id } else { java_id };
+        let index = self.threads.len();
+        self.threads.push(JavaThread {
+            name,
+            tid: u32::try_from(tid).unwrap_or(u32::MAX),
+        });
+        self.thread_indexes.insert((os_id, java_id), index);
+        Some(index)
+    }
+
+    /// The index in `stacks` of a `jdk.types.StackTrace`, or of a reference to one
+    fn stack_index(&mut self, chunk: &Chunk, stack_trace: &Value) -> Option<usize> {
+        let Value::Array(frames) = chunk.field(stack_trace, "frames") else {
+            return None;
+        };
+        if frames.is_empty() {
+            return None;
+        }
+        let frames = frames
+            .iter()
+            .map(|frame| {
+                let function = chunk
+                    .method_name(chunk.field(frame, "method"))
+                    .unwrap_or_else(|| "<unknown>".to_string());
+                let line = chunk
+                    .field(frame, "lineNumber")
+                    .as_i64()
+                    .and_then(|line| u32::try_from(line).ok())
+                    .filter(|&line| line != 0);
+                let frame_type = chunk.field(chunk.field(frame, "type"), "description");
+                let is_native = matches!(frame_type.as_str(), Some("Native" | "C++" | "Kernel"));
+                self.frame_index(Frame {
+                    function,
+                    line,
+                    is_native,
+                })
+            })
+            .collect();
+        self.stacks.push(frames);
+        Some(self.stacks.len() - 1)
+    }
+
+    fn frame_index(&mut self, frame: Frame) -> usize {
+        if let Some(&index) = self.frame_indexes.get(&frame) {
+            return index;
+        }
+        let index = self.frames.len();
+        self.frames.push(frame.clone());
+        self.frame_indexes.insert(frame, index);
+        index
+    }
+}
+
+/// Parse a JFR period setting, e.g. "20 ms", into nanoseconds.
+fn parse_period(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let digits = value.find(|c: char| !c.is_ascii_digit())?;
+    let (number, unit) = value.split_at(digits);
+    let factor = match unit.trim() {
+        "ns" => 1,
+        "us" => 1_000,
+        "ms" => 1_000_000,
+        "s" => 1_000_000_000,
+        _ => return None,
+    };
+    number
+        .parse::<u64>()
+        .ok()?
+        .checked_mul(factor)
+        .filter(|&period| period > 0)
+}
+
+/// A thread which waited for a Java monitor or was parked, e.g. in a
+/// `java.util.concurrent` lock. The marker is named after the JFR event, e.g.
+/// "JavaMonitorEnter".
+#[derive(Debug, Clone)]
+struct LockMarker {
+    name: StringHandle,
+    class: StringHandle,
+}
+
+impl Marker for LockMarker {
+    type FieldsType = StringHandle;
+
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "JavaLock";
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.data.class}");
+    const TOOLTIP_LABEL: Option<&'static str> = Some("{marker.name} {marker.data.class}");
+    const TABLE_LABEL: Option<&'static str> = Some("{marker.name} {marker.data.class}");
+
+    const DESCRIPTION: Option<&'static str> = Some(
+        "Emitted for the JavaMonitorEnter, JavaMonitorWait and ThreadPark events of a JFR recording.",
+    );
+
+    const FIELDS: Schema<Self::FieldsType> = Schema(MarkerField::string("class", "Class"));
+
+    fn name(&self, _profile: &mut Profile) -> StringHandle {
+        self.name
+    }
+
+    fn field_values(&self) -> StringHandle {
+        self.class
+    }
+}
+
+pub fn convert(input_path: &Path, mut reader: impl Read) -> Result<Profile, Error> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let mut recording = Recording::default();
+    let mut pos = 0;
+    while pos < data.len() {
+        pos += recording.read_chunk(&data[pos..])?;
+    }
+    recording.events.sort_by_key(|event| event.nanos);
+
+    let period_nanos = recording.period_nanos.unwrap_or(DEFAULT_PERIOD_NANOS);
+    let start_nanos = recording.start_nanos.unwrap_or(0);
+    let reference_timestamp =
+        ReferenceTimestamp::from_millis_since_unix_epoch(start_nanos as f64 / 1_000_000.0);
+    let product = input_path
+        .file_name()
+        .map_or("JFR".into(), |name| name.to_string_lossy());
+    let mut profile = Profile::new(
+        &product,
+        reference_timestamp,
+        SamplingInterval::from_nanos(period_nanos),
+    );
+    let process = profile.add_process(
+        &product,
+        recording.pid.unwrap_or(0),
+        Timestamp::from_millis_since_reference(0.0),
+    );
+
+    // The main thread has the allocations table of the process.
+    let main_index = recording
+        .threads
+        .iter()
+        .position(|thread| thread.name == "main")
+        .unwrap_or(0);
+    let threads: Vec<ThreadHandle> = recording
+        .threads
+        .iter()
+        .enumerate()
+        .map(|(index, thread)| {
+            let handle = profile.add_thread(
+                process,
+                thread.tid,
+                Timestamp::from_millis_since_reference(0.0),
+                index == main_index,
+            );
+            profile.set_thread_name(handle, &thread.name);
+            handle
+        })
+        .collect();
+
+    let java_category = profile.handle_for_category(Category("Java", CategoryColor::Blue));
+    let native_category = profile.handle_for_category(Category("Native", CategoryColor::Orange));
+    let mut frame_handles: HashMap<(ThreadHandle, usize), FrameHandle> = HashMap::new();
+    let mut stack_handles: HashMap<(ThreadHandle, usize), Option<StackHandle>> = HashMap::new();
+    let mut stack_handle = |profile: &mut Profile,
+                            thread: ThreadHandle,
+                            stack: Option<usize>|
+     -> Option<StackHandle> {
+        let stack = stack?;
+        *stack_handles.entry((thread, stack)).or_insert_with(|| {
+            // Frames are leaf first; stacks are built from the root.
+            let mut handle = None;
+            for &frame_index in recording.stacks[stack].iter().rev() {
+                let frame = *frame_handles
+                    .entry((thread, frame_index))
+                    .or_insert_with(|| {
+                        let frame = &recording.frames[frame_index];
+                        let category = if frame.is_native {
+                            native_category
+                        } else {
+                            java_category
+                        };
+                        frame_handle(profile, thread, frame, category)
+                    });
+                handle = Some(profile.handle_for_stack(thread, frame, handle));
+            }
+            handle
+        })
+    };
+
+    for event in &recording.events {
+        let thread = threads[event.thread];
+        let timestamp = Timestamp::from_nanos_since_reference(
+            u64::try_from(event.nanos - start_nanos).unwrap_or(0),
+        );
+        match &event.kind {
+            EventKind::Sample => {
+                let stack = stack_handle(&mut profile, thread, event.stack);
+                let cpu_delta = CpuDelta::from_nanos(period_nanos);
+                profile.add_sample(thread, timestamp, stack, cpu_delta, 1);
+            }
+            EventKind::Allocation { bytes } => {
+                // Allocation stacks are stacks of the main thread.
+                let stack = stack_handle(&mut profile, threads[main_index], event.stack);
+                profile.add_allocation_sample(process, timestamp, stack, 0, *bytes);
+            }
+            EventKind::Lock {
+                name,
+                class,
+                end_nanos,
+            } => {
+                let end = Timestamp::from_nanos_since_reference(
+                    u64::try_from(end_nanos - start_nanos).unwrap_or(0),
+                );
+                let marker = LockMarker {
+                    name: profile.handle_for_string(name),
+                    class: profile.handle_for_string(class.as_deref().unwrap_or("")),
+                };
+                let marker =
+                    profile.add_marker(thread, MarkerTiming::Interval(timestamp, end), marker);
+                let stack = stack_handle(&mut profile, thread, event.stack);
+                profile.set_marker_stack(thread, marker, stack);
+            }
+        }
+    }
+
+    Ok(profile)
+}
+
+fn frame_handle(
+    profile: &mut Profile,
+    thread: ThreadHandle,
+    frame: &Frame,
+    category: CategoryHandle,
+) -> FrameHandle {
+    let name = profile.handle_for_string(&frame.function);
+    let source_location = SourceLocation {
+        file_path: None,
+        line: frame.line,
+        col: None,
+    };
+    profile.handle_for_frame_with_label_and_source_location(
+        thread,
+        name,
+        source_location,
+        category,
+        FrameFlags::empty(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_period() {
+        assert_eq!(parse_period("20 ms"), Some(20_000_000));
+        assert_eq!(parse_period("10ms"), Some(10_000_000));
+        assert_eq!(parse_period("1 s"), Some(1_000_000_000));
+        assert_eq!(parse_period("everyChunk"), None);
+        assert_eq!(parse_period("0 ms"), None);
+    }
+
+    #[test]
+    fn test_compressed_integers() {
+        let data = [
+            0x05, 0xac, 0x02, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        ];
+        let mut reader = Reader {
+            data: &data,
+            pos: 0,
+            compressed: true,
+        };
+        assert_eq!(reader.long().unwrap(), 5);
+        assert_eq!(reader.long().unwrap(), 300);
+        assert_eq!(reader.long().unwrap(), u64::MAX);
+        assert!(reader.long().is_err());
+    }
+}
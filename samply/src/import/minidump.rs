@@ -0,0 +1,653 @@
+//! Importing minidumps (`.dmp`), as written by Windows (`MiniDumpWriteDump`,
+//! Windows Error Reporting, procdump), Breakpad and Crashpad, for post-mortem
+//! analysis next to recorded profiles.
+//!
+//! Each thread of the dump becomes a thread with a single sample: its stack
+//! at the time of the dump. The modules become libraries with their debug
+//! IDs, so the frames are symbolicated by the symbol manager like recorded
+//! frames. The crashing thread, from the exception stream, gets a "Crash"
+//! marker with the exception and its address, and its stack starts at the
+//! exception's context rather than in the handler which wrote the dump.
+//!
+//! Minidumps have no unwind information, so stacks are walked along the
+//! frame pointer chain. Where there is no chain, e.g. in x86_64 Windows
+//! code, which is compiled without frame pointers, return addresses are
+//! found by scanning the stack for values which point into a module; these
+//! stacks can contain stale frames.
+
+use std::io::Read;
+use std::path::Path;
+
+use debugid::DebugId;
+use fxprof_processed_profile::{
+    CategoryHandle, CpuDelta, FrameAddress, FrameFlags, LibraryInfo, Marker, MarkerField,
+    MarkerTiming, Profile, ReferenceTimestamp, SamplingInterval, Schema, StringHandle, Timestamp,
+};
+use samply_debugid::DebugIdExt;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("I/O Error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Malformed minidump: {0}")]
+    Malformed(&'static str),
+
+    #[error(
+        "Unsupported CPU architecture {0:#x} in minidump; only x86_64 and arm64 are supported"
+    )]
+    UnsupportedArch(u16),
+}
+
+const MAGIC: &[u8] = b"MDMP";
+
+const THREAD_LIST_STREAM: u32 = 3;
+const MODULE_LIST_STREAM: u32 = 4;
+const MEMORY_LIST_STREAM: u32 = 5;
+const EXCEPTION_STREAM: u32 = 6;
+const SYSTEM_INFO_STREAM: u32 = 7;
+const MEMORY64_LIST_STREAM: u32 = 9;
+const MISC_INFO_STREAM: u32 = 15;
+const THREAD_NAME_LIST_STREAM: u32 = 24;
+/// Breakpad's copy of `/proc/<pid>/status`
+const LINUX_PROC_STATUS_STREAM: u32 = 0x4767_0003;
+
+const ARCH_AMD64: u16 = 9;
+const ARCH_ARM64: u16 = 12;
+/// Written by older Breakpad versions
+const ARCH_ARM64_OLD: u16 = 0x8003;
+
+const PLATFORM_WINDOWS: u32 = 2;
+const PLATFORM_MACOS: u32 = 0x8101;
+const PLATFORM_IOS: u32 = 0x8102;
+
+/// CodeView record signatures of the modules' debug info
+const CV_SIGNATURE_PDB70: u32 = 0x5344_5352; // "RSDS"
+const CV_SIGNATURE_ELF: u32 = 0x4c45_7042; // "BpEL"
+
+/// Stacks are cut off at this many frames.
+const MAX_FRAMES: usize = 512;
+/// Stack scanning finds at most this many frames, and reads at most this
+/// many words.
+const MAX_SCANNED_FRAMES: usize = 64;
+const MAX_SCANNED_WORDS: usize = 8 * 1024;
+
+/// Whether the file is a minidump: it starts with `MDMP`.
+pub fn is_minidump_file(first_bytes: &[u8]) -> bool {
+    first_bytes.starts_with(MAGIC)
+}
+
+/// A `MINIDUMP_LOCATION_DESCRIPTOR`: where something is in the file
+#[derive(Debug, Clone, Copy)]
+struct Location {
+    size: u32,
+    rva: u32,
+}
+
+/// Reads the little-endian structures of a minidump.
+struct Dump<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Dump<'a> {
+    fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8], Error> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or(Error::Malformed("data out of bounds"))
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(
+            self.bytes(offset, 2)?.try_into().unwrap(),
+        ))
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(
+            self.bytes(offset, 4)?.try_into().unwrap(),
+        ))
+    }
+
+    fn u64(&self, offset: usize) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(
+            self.bytes(offset, 8)?.try_into().unwrap(),
+        ))
+    }
+
+    fn location(&self, offset: usize) -> Result<Location, Error> {
+        Ok(Location {
+            size: self.u32(offset)?,
+            rva: self.u32(offset + 4)?,
+        })
+    }
+
+    fn location_bytes(&self, location: Location) -> Result<&'a [u8], Error> {
+        self.bytes(location.rva as usize, location.size as usize)
+    }
+
+    /// A `MINIDUMP_STRING`: a byte length and UTF-16 code units
+    fn string(&self, rva: usize) -> Result<String, Error> {
+        let len = self.u32(rva)? as usize;
+        let bytes = self.bytes(rva + 4, len)?;
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+            .collect();
+        Ok(String::from_utf16_lossy(&units))
+    }
+}
+
+struct Thread {
+    tid: u32,
+    stack_start: u64,
+    stack: Location,
+    context: Location,
+}
+
+struct Module {
+    base: u64,
+    size: u64,
+    time_date_stamp: u32,
+    path: String,
+    cv_record: Location,
+}
+
+struct Exception {
+    tid: u32,
+    code: u32,
+    address: u64,
+    context: Location,
+}
+
+/// The memory regions of the dump, for reading the stacks
+struct Memory<'a> {
+    /// (start address, contents), sorted by start address
+    regions: Vec<(u64, &'a [u8])>,
+}
+
+impl Memory<'_> {
+    fn read_u64(&self, address: u64) -> Option<u64> {
+        let index = self
+            .regions
+            .partition_point(|(start, _)| *start <= address)
+            .checked_sub(1)?;
+        let (start, bytes) = self.regions[index];
+        let offset = usize::try_from(address - start).ok()?;
+        let word = bytes.get(offset..offset.checked_add(8)?)?;
+        Some(u64::from_le_bytes(word.try_into().unwrap()))
+    }
+}
+
+/// The registers which stack walking starts from
+struct Registers {
+    pc: u64,
+    sp: u64,
+    fp: u64,
+    /// The link register, on arm64
+    lr: Option<u64>,
+}
+
+/// Read the registers from a Windows / Breakpad CPU context.
+fn registers(context: &[u8], arch: u16) -> Result<Registers, Error> {
+    let dump = Dump { data: context };
+    match arch {
+        // CONTEXT (AMD64): Rsp at 152, Rbp at 160, Rip at 248
+        ARCH_AMD64 => Ok(Registers {
+            pc: dump.u64(248)?,
+            sp: dump.u64(152)?,
+            fp: dump.u64(160)?,
+            lr: None,
+        }),
+        // ARM64_NT_CONTEXT and Breakpad's arm64 contexts: x0-x28, fp, lr,
+        // sp and pc from offset 8
+        ARCH_ARM64 | ARCH_ARM64_OLD => Ok(Registers {
+            pc: dump.u64(264)?,
+            sp: dump.u64(256)?,
+            fp: dump.u64(240)?,
+            lr: Some(dump.u64(248)?),
+        }),
+        _ => Err(Error::UnsupportedArch(arch)),
+    }
+}
+
+/// Remove the pointer authentication code of an arm64e return address, and
+/// any other bits above the 47 bits of user space addresses.
+fn strip_pointer_auth(address: u64) -> u64 {
+    address & 0x0000_7fff_ffff_ffff
+}
+
+/// The return addresses of a stack, leaf first, after the instruction
+/// pointer: along the frame pointer chain, or by scanning the stack if
+/// there is no chain.
+fn walk_stack(regs: &Registers, memory: &Memory, modules: &[Module]) -> Vec<u64> {
+    let in_module = |address: u64| {
+        modules
+            .iter()
+            .any(|module| (module.base..module.base + module.size).contains(&address))
+    };
+    let mut return_addresses = Vec::new();
+
+    // Each frame record holds the caller's frame pointer and the return
+    // address. Frame records are further up the stack than the ones they
+    // point from.
+    let mut fp = regs.fp;
+    let mut lowest_fp = regs.sp;
+    while return_addresses.len() < MAX_FRAMES && fp >= lowest_fp && fp % 8 == 0 {
+        let (Some(next_fp), Some(return_address)) = (memory.read_u64(fp), memory.read_u64(fp + 8))
+        else {
+            break;
+        };
+        let return_address = strip_pointer_auth(return_address);
+        if !in_module(return_address) {
+            break;
+        }
+        return_addresses.push(return_address);
+        lowest_fp = fp + 16;
+        fp = next_fp;
+    }
+    if !return_addresses.is_empty() {
+        return return_addresses;
+    }
+
+    // A leaf function on arm64 may not have saved the link register yet.
+    if let Some(lr) = regs.lr.map(strip_pointer_auth).filter(|&lr| in_module(lr)) {
+        return_addresses.push(lr);
+    }
+    for word in 0..MAX_SCANNED_WORDS {
+        if return_addresses.len() >= MAX_SCANNED_FRAMES {
+            break;
+        }
+        let Some(value) = memory.read_u64(regs.sp + 8 * word as u64) else {
+            break;
+        };
+        let value = strip_pointer_auth(value);
+        if in_module(value) {
+            return_addresses.push(value);
+        }
+    }
+    return_addresses
+}
+
+/// The name of an exception code, which depends on the platform: Windows
+/// exception codes, Mach exception types on Apple platforms, and signal
+/// numbers elsewhere.
+fn exception_name(platform: u32, code: u32) -> String {
+    let name = match platform {
+        PLATFORM_WINDOWS => match code {
+            0x8000_0003 => Some("EXCEPTION_BREAKPOINT"),
+            0x8000_0004 => Some("EXCEPTION_SINGLE_STEP"),
+            0xc000_0005 => Some("EXCEPTION_ACCESS_VIOLATION"),
+            0xc000_0006 => Some("EXCEPTION_IN_PAGE_ERROR"),
+            0xc000_001d => Some("EXCEPTION_ILLEGAL_INSTRUCTION"),
+            0xc000_0094 => Some("EXCEPTION_INT_DIVIDE_BY_ZERO"),
+            0xc000_0096 => Some("EXCEPTION_PRIV_INSTRUCTION"),
+            0xc000_00fd => Some("EXCEPTION_STACK_OVERFLOW"),
+            0xc000_0374 => Some("STATUS_HEAP_CORRUPTION"),
+            0xc000_0409 => Some("STATUS_STACK_BUFFER_OVERRUN"),
+            0xe06d_7363 => Some("C++ exception"),
+            _ => None,
+        },
+        PLATFORM_MACOS | PLATFORM_IOS => match code {
+            1 => Some("EXC_BAD_ACCESS"),
+            2 => Some("EXC_BAD_INSTRUCTION"),
+            3 => Some("EXC_ARITHMETIC"),
+            5 => Some("EXC_SOFTWARE"),
+            6 => Some("EXC_BREAKPOINT"),
+            10 => Some("EXC_CRASH"),
+            11 => Some("EXC_RESOURCE"),
+            12 => Some("EXC_GUARD"),
+            _ => None,
+        },
+        _ => match code {
+            4 => Some("SIGILL"),
+            5 => Some("SIGTRAP"),
+            6 => Some("SIGABRT"),
+            7 => Some("SIGBUS"),
+            8 => Some("SIGFPE"),
+            9 => Some("SIGKILL"),
+            11 => Some("SIGSEGV"),
+            13 => Some("SIGPIPE"),
+            15 => Some("SIGTERM"),
+            _ => None,
+        },
+    };
+    match name {
+        Some(name) => name.to_string(),
+        None => format!("{code:#010x}"),
+    }
+}
+
+/// The last component of a Windows or Unix path
+fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+/// The library of a module, with the debug ID from its CodeView record
+fn library_info(dump: &Dump, module: &Module, platform: u32) -> LibraryInfo {
+    let name = file_name(&module.path).to_string();
+    let mut library = LibraryInfo {
+        name: name.clone(),
+        debug_name: name,
+        path: module.path.clone(),
+        debug_path: module.path.clone(),
+        debug_id: DebugId::nil(),
+        code_id: None,
+        arch: None,
+    };
+    if platform == PLATFORM_WINDOWS {
+        library.code_id = Some(format!("{:08X}{:x}", module.time_date_stamp, module.size));
+    }
+    let Ok(cv) = dump.location_bytes(module.cv_record) else {
+        return library;
+    };
+    let cv = Dump { data: cv };
+    match cv.u32(0) {
+        Ok(CV_SIGNATURE_PDB70) => {
+            let (Ok(guid), Ok(age)) = (cv.bytes(4, 16), cv.u32(20)) else {
+                return library;
+            };
+            library.debug_id = DebugId::from_guid_age(guid, age).unwrap_or(DebugId::nil());
+            // The PDB path, on Windows; other platforms repeat the module path.
+            let pdb_path = cv.data.get(24..).unwrap_or_default();
+            let pdb_path = pdb_path.split(|&b| b == 0).next().unwrap_or_default();
+            let pdb_path = String::from_utf8_lossy(pdb_path);
+            if platform == PLATFORM_WINDOWS && !pdb_path.is_empty() {
+                library.debug_name = file_name(&pdb_path).to_string();
+                library.debug_path = pdb_path.into_owned();
+            }
+        }
+        Ok(CV_SIGNATURE_ELF) => {
+            let build_id = &cv.data[4..];
+            if !build_id.is_empty() {
+                library.debug_id = DebugId::from_identifier(build_id, true);
+                library.code_id = Some(build_id.iter().map(|b| format!("{b:02x}")).collect());
+            }
+        }
+        _ => {}
+    }
+    library
+}
+
+/// The pid from the misc info stream, or from Breakpad's copy of
+/// `/proc/<pid>/status`
+fn process_id(dump: &Dump, streams: &[(u32, Location)]) -> Option<u32> {
+    let stream = |stream_type: u32| {
+        let (_, location) = streams.iter().find(|(t, _)| *t == stream_type)?;
+        dump.location_bytes(*location).ok()
+    };
+    if let Some(misc_info) = stream(MISC_INFO_STREAM) {
+        let misc_info = Dump { data: misc_info };
+        // MINIDUMP_MISC1_PROCESS_ID
+        if misc_info.u32(4).is_ok_and(|flags| flags & 1 != 0) {
+            return misc_info.u32(8).ok();
+        }
+    }
+    let status = String::from_utf8_lossy(stream(LINUX_PROC_STATUS_STREAM)?);
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Pid:"))
+        .and_then(|pid| pid.trim().parse().ok())
+}
+
+#[derive(Debug, Clone)]
+struct CrashMarker {
+    exception: StringHandle,
+    address: StringHandle,
+    code: u32,
+}
+
+impl Marker for CrashMarker {
+    type FieldsType = (StringHandle, StringHandle, f64);
+
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "Crash";
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.data.exception}");
+    const TOOLTIP_LABEL: Option<&'static str> =
+        Some("{marker.data.exception} at {marker.data.address}");
+    const TABLE_LABEL: Option<&'static str> =
+        Some("{marker.data.exception} at {marker.data.address}");
+
+    const DESCRIPTION: Option<&'static str> =
+        Some("Emitted for the exception of the crashing thread of a minidump.");
+
+    const FIELDS: Schema<Self::FieldsType> = Schema((
+        MarkerField::string("exception", "Exception"),
+        MarkerField::string("address", "Address"),
+        MarkerField::integer("code", "Exception code"),
+    ));
+
+    fn name(&self, profile: &mut Profile) -> StringHandle {
+        profile.handle_for_string("Crash")
+    }
+
+    fn field_values(&self) -> (StringHandle, StringHandle, f64) {
+        (self.exception, self.address, self.code as f64)
+    }
+}
+
+pub fn convert(input_path: &Path, mut reader: impl Read) -> Result<Profile, Error> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let dump = Dump { data: &data };
+    if !data.starts_with(MAGIC) {
+        return Err(Error::Malformed("no MDMP signature"));
+    }
+    let stream_count = dump.u32(8)? as usize;
+    let directory = dump.u32(12)? as usize;
+    let time_date_stamp = dump.u32(20)?;
+    let streams: Vec<(u32, Location)> = (0..stream_count)
+        .map(|i| {
+            Ok((
+                dump.u32(directory + 12 * i)?,
+                dump.location(directory + 12 * i + 4)?,
+            ))
+        })
+        .collect::<Result<_, Error>>()?;
+    let stream = |stream_type: u32| {
+        streams
+            .iter()
+            .find(|(t, _)| *t == stream_type)
+            .map(|(_, location)| location.rva as usize)
+    };
+
+    // MINIDUMP_SYSTEM_INFO
+    let system_info = stream(SYSTEM_INFO_STREAM).ok_or(Error::Malformed("no system info"))?;
+    let arch = dump.u16(system_info)?;
+    let platform = dump.u32(system_info + 20)?;
+
+    // MINIDUMP_MODULE_LIST: 108 bytes per module
+    let mut modules = Vec::new();
+    if let Some(list) = stream(MODULE_LIST_STREAM) {
+        for i in 0..dump.u32(list)? as usize {
+            let module = list + 4 + 108 * i;
+            modules.push(Module {
+                base: dump.u64(module)?,
+                size: u64::from(dump.u32(module + 8)?),
+                time_date_stamp: dump.u32(module + 16)?,
+                path: dump.string(dump.u32(module + 20)? as usize)?,
+                cv_record: dump.location(module + 76)?,
+            });
+        }
+    }
+
+    // MINIDUMP_THREAD_LIST: 48 bytes per thread
+    let mut threads = Vec::new();
+    if let Some(list) = stream(THREAD_LIST_STREAM) {
+        for i in 0..dump.u32(list)? as usize {
+            let thread = list + 4 + 48 * i;
+            threads.push(Thread {
+                tid: dump.u32(thread)?,
+                stack_start: dump.u64(thread + 24)?,
+                stack: dump.location(thread + 32)?,
+                context: dump.location(thread + 40)?,
+            });
+        }
+    }
+
+    let mut thread_names: Vec<(u32, String)> = Vec::new();
+    if let Some(list) = stream(THREAD_NAME_LIST_STREAM) {
+        for i in 0..dump.u32(list)? as usize {
+            let entry = list + 4 + 12 * i;
+            let name_rva = usize::try_from(dump.u64(entry + 4)?)
+                .map_err(|_| Error::Malformed("thread name out of bounds"))?;
+            thread_names.push((dump.u32(entry)?, dump.string(name_rva)?));
+        }
+    }
+
+    // MINIDUMP_EXCEPTION_STREAM, with the MINIDUMP_EXCEPTION at offset 8
+    let exception = match stream(EXCEPTION_STREAM) {
+        Some(stream) => Some(Exception {
+            tid: dump.u32(stream)?,
+            code: dump.u32(stream + 8)?,
+            address: dump.u64(stream + 24)?,
+            context: dump.location(stream + 160)?,
+        }),
+        None => None,
+    };
+
+    // The thread stacks, and the memory lists of dumps with more memory
+    let mut memory = Memory {
+        regions: Vec::new(),
+    };
+    for thread in &threads {
+        if let Ok(stack) = dump.location_bytes(thread.stack) {
+            memory.regions.push((thread.stack_start, stack));
+        }
+    }
+    if let Some(list) = stream(MEMORY_LIST_STREAM) {
+        for i in 0..dump.u32(list)? as usize {
+            let descriptor = list + 4 + 16 * i;
+            let start = dump.u64(descriptor)?;
+            let bytes = dump.location_bytes(dump.location(descriptor + 8)?)?;
+            memory.regions.push((start, bytes));
+        }
+    }
+    if let Some(list) = stream(MEMORY64_LIST_STREAM) {
+        let count = dump.u64(list)? as usize;
+        let mut rva = dump.u64(list + 8)? as usize;
+        for i in 0..count {
+            let descriptor = list + 16 + 16 * i;
+            let start = dump.u64(descriptor)?;
+            let size = dump.u64(descriptor + 8)? as usize;
+            memory.regions.push((start, dump.bytes(rva, size)?));
+            rva += size;
+        }
+    }
+    memory.regions.sort_by_key(|(start, _)| *start);
+
+    let product = modules.first().map_or_else(
+        || {
+            input_path.file_name().map_or("minidump".into(), |name| {
+                name.to_string_lossy().into_owned()
+            })
+        },
+        |module| file_name(&module.path).to_string(),
+    );
+    let mut profile = Profile::new(
+        &product,
+        ReferenceTimestamp::from_millis_since_unix_epoch(f64::from(time_date_stamp) * 1000.0),
+        SamplingInterval::from_millis(1),
+    );
+    let pid = process_id(&dump, &streams);
+    let start_time = Timestamp::from_millis_since_reference(0.0);
+    let process = profile.add_process(&product, pid.unwrap_or(0), start_time);
+    for module in &modules {
+        let lib = profile.add_lib(library_info(&dump, module, platform));
+        profile.add_lib_mapping(process, lib, module.base, module.base + module.size, 0);
+    }
+
+    for (index, thread) in threads.iter().enumerate() {
+        let is_main = pid.map_or(index == 0, |pid| pid == thread.tid);
+        let handle = profile.add_thread(process, thread.tid, start_time, is_main);
+        let name = thread_names
+            .iter()
+            .find(|(tid, _)| *tid == thread.tid)
+            .map(|(_, name)| name.clone())
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| format!("Thread {}", thread.tid));
+        profile.set_thread_name(handle, &name);
+
+        let crash = exception.as_ref().filter(|e| e.tid == thread.tid);
+        let context = crash.map_or(thread.context, |exception| exception.context);
+        let regs = registers(dump.location_bytes(context)?, arch)?;
+        let return_addresses = walk_stack(&regs, &memory, &modules);
+
+        let frames = std::iter::once(FrameAddress::InstructionPointer(regs.pc))
+            .chain(
+                return_addresses
+                    .into_iter()
+                    .map(FrameAddress::ReturnAddress),
+            )
+            .take(MAX_FRAMES)
+            .collect::<Vec<_>>();
+        let mut stack = None;
+        for address in frames.into_iter().rev() {
+            let frame = profile.handle_for_frame_with_address(
+                handle,
+                address,
+                CategoryHandle::OTHER,
+                FrameFlags::empty(),
+            );
+            stack = Some(profile.handle_for_stack(handle, frame, stack));
+        }
+        profile.add_sample(handle, start_time, stack, CpuDelta::ZERO, 1);
+
+        if let Some(exception) = crash {
+            let marker = CrashMarker {
+                exception: profile.handle_for_string(&exception_name(platform, exception.code)),
+                address: profile.handle_for_string(&format!("{:#x}", exception.address)),
+                code: exception.code,
+            };
+            profile.add_marker(handle, MarkerTiming::Instant(start_time), marker);
+        }
+    }
+
+    Ok(profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_walk_stack() {
+        let module = Module {
+            base: 0x1000,
+            size: 0x1000,
+            time_date_stamp: 0,
+            path: "app".to_string(),
+            cv_record: Location { size: 0, rva: 0 },
+        };
+        // Two frame records at 0x8010 and 0x8030, then the end of the chain.
+        let mut stack = vec![0u8; 0x40];
+        let mut put = |offset: usize, value: u64| {
+            stack[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        };
+        put(0x10, 0x8030);
+        put(0x18, 0x1234);
+        put(0x30, 0);
+        put(0x38, 0x1456);
+        let memory = Memory {
+            regions: vec![(0x8000, &stack[..])],
+        };
+        let regs = Registers {
+            pc: 0x1100,
+            sp: 0x8000,
+            fp: 0x8010,
+            lr: None,
+        };
+        assert_eq!(walk_stack(&regs, &memory, &[module]), [0x1234, 0x1456]);
+    }
+
+    #[test]
+    fn test_exception_name() {
+        assert_eq!(
+            exception_name(PLATFORM_WINDOWS, 0xc000_0005),
+            "EXCEPTION_ACCESS_VIOLATION"
+        );
+        assert_eq!(exception_name(0x8201, 11), "SIGSEGV");
+        assert_eq!(exception_name(PLATFORM_MACOS, 1), "EXC_BAD_ACCESS");
+        assert_eq!(exception_name(PLATFORM_WINDOWS, 0x1234), "0x00001234");
+    }
+}
@@ -1 +1,8 @@
+pub mod dtrace;
+pub mod instruments;
+pub mod jfr;
+pub mod minidump;
 pub mod perf;
+pub mod pprof;
+pub mod speedscope;
+pub mod strace;
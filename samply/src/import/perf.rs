@@ -1,18 +1,25 @@
 use std::collections::HashMap;
 use std::fmt::Write;
-use std::io::{Read, Seek};
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
 use framehop::{Module, Unwinder};
 use fxprof_processed_profile::{Profile, ReferenceTimestamp};
-use linux_perf_data::{linux_perf_event_reader, DsoInfo, DsoKey, PerfFileReader, PerfFileRecord};
+use linux_perf_data::{
+    linux_perf_event_reader, DsoInfo, DsoKey, PerfFile, PerfFileReader, PerfFileRecord,
+    PerfRecordIter,
+};
 use linux_perf_event_reader::EventRecord;
 
 use crate::linux_shared::{
     ConvertRegs, ConvertRegsAarch64, ConvertRegsX86_64, Converter, EventInterpretation, KnownEvent,
     MmapRangeOrVec,
 };
+use crate::shared::progress::Progress;
 use crate::shared::prop_types::ProfileCreationProps;
 
 #[derive(thiserror::Error, Debug)]
@@ -85,58 +92,116 @@ where
         mut perf_file,
         mut record_iter,
     } = file;
-    let mut build_ids = perf_file.build_ids().ok().unwrap_or_default();
-    fixup_perf_jit_build_ids(&mut build_ids);
-    let first_sample_time = perf_file
-        .sample_time_range()
-        .unwrap()
-        .map_or(0, |r| r.first_sample_time);
-    let endian = perf_file.endian();
-    let simpleperf_meta_info = perf_file.simpleperf_meta_info().ok().flatten();
-    let is_simpleperf = simpleperf_meta_info.is_some();
-    let call_chain_return_addresses_are_preadjusted = is_simpleperf;
-
-    let linux_version = perf_file.os_release().unwrap();
-    let attributes = perf_file.event_attributes();
-    if let Ok(Some(cmd_line)) = perf_file.cmdline() {
-        eprintln!("cmd line: {}", cmd_line.join(" "));
-    }
-    for event_name in attributes.iter().filter_map(|attr| attr.name()) {
-        eprintln!("event {event_name}");
-    }
-    let interpretation = EventInterpretation::divine_from_attrs(attributes);
-    let simpleperf_symbol_tables = perf_file.simpleperf_symbol_tables().ok().flatten();
-    let reference_timestamp = if let Some(seconds_since_unix_epoch) =
-        get_simpleperf_timestamp(simpleperf_meta_info.as_ref())
-    {
-        ReferenceTimestamp::from_millis_since_unix_epoch(seconds_since_unix_epoch * 1000.0)
-    } else if let Some(mod_time) = file_mod_time {
-        ReferenceTimestamp::from_system_time(mod_time)
-    } else {
-        ReferenceTimestamp::from_system_time(SystemTime::now())
-    };
+    let mut record_converter = RecordConverter::<U, C>::new(
+        &perf_file,
+        file_mod_time,
+        binary_lookup_dirs,
+        aux_file_lookup_dirs,
+        cache,
+        profile_creation_props,
+    );
+    record_converter.convert_records(&mut record_iter, &mut perf_file);
+    record_converter.converter.finish()
+}
 
-    let (profile_name, mut profile_name_postfix_for_first_process) = if let Some(profile_name) =
-        profile_creation_props.profile_name.clone()
-    {
-        // The user gave us an explicit profile name. Use it.
-        (profile_name, None)
-    } else if let Some(simpleperf_meta_info) = simpleperf_meta_info.as_ref() {
-        // perf.data from simpleperf
-        let mut profile_name_postfix = String::new();
-        if let Some(profile_name_props) = simpleperf_meta_info.get("product_props") {
-            // Example: "Google:Pixel 6:oriole"
-            let fragments: Vec<&str> = profile_name_props.split(':').take(2).collect();
-            if !fragments.is_empty() {
-                let device_name = fragments.join(" ");
-                write!(profile_name_postfix, " on {device_name}").unwrap();
-            }
+/// The [`Converter`] of a perf.data file, together with the state which is
+/// carried from one record to the next.
+struct RecordConverter<U, C>
+where
+    U: Unwinder<Module = Module<MmapRangeOrVec>> + Default,
+{
+    converter: Converter<U>,
+    interpretation: EventInterpretation,
+    profile_name_postfix_for_first_process: Option<String>,
+    last_timestamp: u64,
+    _convert_regs: PhantomData<C>,
+}
+
+impl<U, C> RecordConverter<U, C>
+where
+    U: Unwinder<Module = Module<MmapRangeOrVec>> + Default,
+    C: ConvertRegs<UnwindRegs = U::UnwindRegs>,
+{
+    fn new(
+        perf_file: &PerfFile,
+        file_mod_time: Option<SystemTime>,
+        binary_lookup_dirs: Vec<PathBuf>,
+        aux_file_lookup_dirs: Vec<PathBuf>,
+        cache: U::Cache,
+        profile_creation_props: ProfileCreationProps,
+    ) -> Self {
+        let mut build_ids = perf_file.build_ids().ok().unwrap_or_default();
+        fixup_perf_jit_build_ids(&mut build_ids);
+        let first_sample_time = perf_file
+            .sample_time_range()
+            .unwrap()
+            .map_or(0, |r| r.first_sample_time);
+        let endian = perf_file.endian();
+        let simpleperf_meta_info = perf_file.simpleperf_meta_info().ok().flatten();
+        let is_simpleperf = simpleperf_meta_info.is_some();
+        let call_chain_return_addresses_are_preadjusted = is_simpleperf;
+
+        let linux_version = perf_file.os_release().unwrap();
+        let attributes = perf_file.event_attributes();
+        if let Ok(Some(cmd_line)) = perf_file.cmdline() {
+            eprintln!("cmd line: {}", cmd_line.join(" "));
         }
-        if let Some(app_package_name) = simpleperf_meta_info.get("app_package_name") {
-            // We were profiling a single app.
-            (format!("{app_package_name}{profile_name_postfix}"), None)
+        for event_name in attributes.iter().filter_map(|attr| attr.name()) {
+            eprintln!("event {event_name}");
+        }
+        let interpretation =
+            EventInterpretation::divine_from_attrs(attributes, simpleperf_meta_info.as_ref());
+        let simpleperf_symbol_tables = perf_file.simpleperf_symbol_tables().ok().flatten();
+        let reference_timestamp = if let Some(seconds_since_unix_epoch) =
+            get_simpleperf_timestamp(simpleperf_meta_info.as_ref())
+        {
+            ReferenceTimestamp::from_millis_since_unix_epoch(seconds_since_unix_epoch * 1000.0)
+        } else if let Some(mod_time) = file_mod_time {
+            ReferenceTimestamp::from_system_time(mod_time)
         } else {
-            // We would like the profile name to start with the name of the process / app
+            ReferenceTimestamp::from_system_time(SystemTime::now())
+        };
+
+        let (profile_name, profile_name_postfix_for_first_process) = if let Some(profile_name) =
+            profile_creation_props.profile_name.clone()
+        {
+            // The user gave us an explicit profile name. Use it.
+            (profile_name, None)
+        } else if let Some(simpleperf_meta_info) = simpleperf_meta_info.as_ref() {
+            // perf.data from simpleperf
+            let mut profile_name_postfix = String::new();
+            if let Some(profile_name_props) = simpleperf_meta_info.get("product_props") {
+                // Example: "Google:Pixel 6:oriole"
+                let fragments: Vec<&str> = profile_name_props.split(':').take(2).collect();
+                if !fragments.is_empty() {
+                    let device_name = fragments.join(" ");
+                    write!(profile_name_postfix, " on {device_name}").unwrap();
+                }
+            }
+            if let Some(app_package_name) = simpleperf_meta_info.get("app_package_name") {
+                // We were profiling a single app.
+                (format!("{app_package_name}{profile_name_postfix}"), None)
+            } else {
+                // We would like the profile name to start with the name of the process / app
+                // that has been profiled. However, we don't know this name yet.
+                // Start with the name of the imported perf.data file, but also store the
+                // profile name "postfix" so that we can change the profile name later, once
+                // we see the first profiled process.
+                let imported_file_filename = profile_creation_props.fallback_profile_name.clone();
+                let initial_profile_name =
+                    format!("{imported_file_filename}{profile_name_postfix}");
+                (initial_profile_name, Some(profile_name_postfix))
+            }
+        } else {
+            // perf.data from Linux perf
+            let mut profile_name_postfix = String::new();
+            if let Some(host) = perf_file.hostname().ok().flatten() {
+                write!(profile_name_postfix, " on {host}").unwrap();
+            }
+            if let Some(perf_version) = perf_file.perf_version().ok().flatten() {
+                write!(profile_name_postfix, " (perf version {perf_version})").unwrap();
+            }
+            // We would like the profile name to start with the name of the process / executable
             // that has been profiled. However, we don't know this name yet.
             // Start with the name of the imported perf.data file, but also store the
             // profile name "postfix" so that we can change the profile name later, once
@@ -144,126 +209,439 @@ where
             let imported_file_filename = profile_creation_props.fallback_profile_name.clone();
             let initial_profile_name = format!("{imported_file_filename}{profile_name_postfix}");
             (initial_profile_name, Some(profile_name_postfix))
-        }
-    } else {
-        // perf.data from Linux perf
-        let mut profile_name_postfix = String::new();
-        if let Some(host) = perf_file.hostname().ok().flatten() {
-            write!(profile_name_postfix, " on {host}").unwrap();
-        }
-        if let Some(perf_version) = perf_file.perf_version().ok().flatten() {
-            write!(profile_name_postfix, " (perf version {perf_version})").unwrap();
-        }
-        // We would like the profile name to start with the name of the process / executable
-        // that has been profiled. However, we don't know this name yet.
-        // Start with the name of the imported perf.data file, but also store the
-        // profile name "postfix" so that we can change the profile name later, once
-        // we see the first profiled process.
-        let imported_file_filename = profile_creation_props.fallback_profile_name.clone();
-        let initial_profile_name = format!("{imported_file_filename}{profile_name_postfix}");
-        (initial_profile_name, Some(profile_name_postfix))
-    };
+        };
 
-    let mut converter = Converter::<U>::new(
-        &profile_creation_props,
-        reference_timestamp,
-        &profile_name,
-        build_ids,
-        linux_version,
-        first_sample_time,
-        endian,
-        cache,
-        binary_lookup_dirs,
-        aux_file_lookup_dirs,
-        interpretation.clone(),
-        simpleperf_symbol_tables,
-        call_chain_return_addresses_are_preadjusted,
-    );
+        let mut converter = Converter::<U>::new(
+            &profile_creation_props,
+            reference_timestamp,
+            &profile_name,
+            build_ids,
+            linux_version,
+            first_sample_time,
+            endian,
+            cache,
+            binary_lookup_dirs,
+            aux_file_lookup_dirs,
+            interpretation.clone(),
+            simpleperf_symbol_tables,
+            call_chain_return_addresses_are_preadjusted,
+        );
 
-    if let Some(android_version) = simpleperf_meta_info
-        .as_ref()
-        .and_then(|mi| mi.get("android_version"))
-    {
-        converter.set_os_name(&format!("Android {android_version}"));
+        if let Some(android_version) = simpleperf_meta_info
+            .as_ref()
+            .and_then(|mi| mi.get("android_version"))
+        {
+            converter.set_os_name(&format!("Android {android_version}"));
+        }
+
+        Self {
+            converter,
+            interpretation,
+            profile_name_postfix_for_first_process,
+            last_timestamp: 0,
+            _convert_regs: PhantomData,
+        }
     }
 
-    let mut last_timestamp = 0;
+    /// Convert the records which `record_iter` has left.
+    fn convert_records<R: Read>(
+        &mut self,
+        record_iter: &mut PerfRecordIter<R>,
+        perf_file: &mut PerfFile,
+    ) {
+        let Self {
+            converter,
+            interpretation,
+            profile_name_postfix_for_first_process,
+            last_timestamp,
+            ..
+        } = self;
+        let progress = Progress::new("import", "events", None);
 
-    while let Ok(Some(record)) = record_iter.next_record(&mut perf_file) {
-        let (record, parsed_record, attr_index) = match record {
-            PerfFileRecord::EventRecord { attr_index, record } => match record.parse() {
-                Ok(r) => (record, r, attr_index),
-                Err(_) => continue,
-            },
-            PerfFileRecord::UserRecord(_) => continue,
-        };
-        if let Some(timestamp) = record.timestamp() {
-            if timestamp < last_timestamp {
-                eprintln!(
-                    "bad timestamp ordering; {timestamp} is earlier but arrived after {last_timestamp}"
-                );
+        while let Ok(Some(record)) = record_iter.next_record(perf_file) {
+            progress.inc(1);
+            let (record, parsed_record, attr_index) = match record {
+                PerfFileRecord::EventRecord { attr_index, record } => match record.parse() {
+                    Ok(r) => (record, r, attr_index),
+                    Err(_) => continue,
+                },
+                PerfFileRecord::UserRecord(_) => continue,
+            };
+            if let Some(timestamp) = record.timestamp() {
+                if timestamp < *last_timestamp {
+                    eprintln!(
+                        "bad timestamp ordering; {timestamp} is earlier but arrived after {last_timestamp}"
+                    );
+                }
+                *last_timestamp = timestamp;
             }
-            last_timestamp = timestamp;
-        }
 
-        match parsed_record {
-            EventRecord::Sample(e) => {
-                if attr_index == interpretation.main_event_attr_index {
-                    converter.handle_main_event_sample::<C>(&e);
-                } else if Some(attr_index) == interpretation.sched_switch_attr_index {
-                    converter.handle_sched_switch_sample::<C>(&e);
-                }
+            match parsed_record {
+                EventRecord::Sample(e) => {
+                    if attr_index == interpretation.main_event_attr_index {
+                        converter.handle_main_event_sample::<C>(&e);
+                    } else if Some(attr_index) == interpretation.sched_switch_attr_index {
+                        converter.handle_sched_switch_sample::<C>(&e);
+                    }
 
-                match interpretation.known_event_indices.get(&attr_index) {
-                    Some(KnownEvent::RssStat) => converter.handle_rss_stat_sample::<C>(&e),
-                    _ => {
-                        // the main event and sched_switch are already covered by regular samples so don't add other event markers
-                        if !(attr_index == interpretation.main_event_attr_index
-                            || Some(attr_index) == interpretation.sched_switch_attr_index)
-                        {
-                            converter.handle_other_event_sample::<C>(&e, attr_index)
+                    match interpretation.known_event_indices.get(&attr_index) {
+                        Some(KnownEvent::RssStat) => converter.handle_rss_stat_sample::<C>(&e),
+                        Some(KnownEvent::SignalDeliver) => {
+                            converter.handle_signal_deliver_sample::<C>(&e)
+                        }
+                        _ => {
+                            // the main event and sched_switch are already covered by regular samples so don't add other event markers
+                            if !(attr_index == interpretation.main_event_attr_index
+                                || Some(attr_index) == interpretation.sched_switch_attr_index)
+                            {
+                                converter.handle_other_event_sample::<C>(&e, attr_index)
+                            }
                         }
                     }
                 }
-            }
-            EventRecord::Fork(e) => {
-                converter.handle_fork(e);
-            }
-            EventRecord::Comm(e) => {
-                if profile_name_postfix_for_first_process.is_some()
-                    && &e.name.as_slice()[..] != b"perf-exec"
-                {
-                    let postfix = profile_name_postfix_for_first_process.take().unwrap();
-                    let first_process_name =
-                        String::from_utf8_lossy(&e.name.as_slice()).to_string();
-                    let profile_name = format!("{first_process_name}{postfix}");
-                    converter.set_profile_name(&profile_name);
+                EventRecord::Fork(e) => {
+                    converter.handle_fork(e);
+                }
+                EventRecord::Comm(e) => {
+                    if profile_name_postfix_for_first_process.is_some()
+                        && &e.name.as_slice()[..] != b"perf-exec"
+                    {
+                        let postfix = profile_name_postfix_for_first_process.take().unwrap();
+                        let first_process_name =
+                            String::from_utf8_lossy(&e.name.as_slice()).to_string();
+                        let profile_name = format!("{first_process_name}{postfix}");
+                        converter.set_profile_name(&profile_name);
+                    }
+                    converter.handle_comm(e, record.timestamp());
+                }
+                EventRecord::Exit(e) => {
+                    converter.handle_exit(e);
+                }
+                EventRecord::Mmap(e) => {
+                    converter.handle_mmap(e, *last_timestamp);
+                }
+                EventRecord::Mmap2(e) => {
+                    converter.handle_mmap2(e, *last_timestamp);
+                }
+                EventRecord::ContextSwitch(e) => {
+                    let common = match record.common_data() {
+                        Ok(common) => common,
+                        Err(_) => continue,
+                    };
+                    converter.handle_context_switch(e, common);
+                }
+                _ => {
+                    // println!("{:?}", record.record_type);
                 }
-                converter.handle_comm(e, record.timestamp());
-            }
-            EventRecord::Exit(e) => {
-                converter.handle_exit(e);
-            }
-            EventRecord::Mmap(e) => {
-                converter.handle_mmap(e, last_timestamp);
             }
-            EventRecord::Mmap2(e) => {
-                converter.handle_mmap2(e, last_timestamp);
+        }
+        progress.finish();
+    }
+}
+
+/// Converts a perf.data file which is still being written by `perf record`,
+/// batch by batch: the [`Converter`] is kept between the calls to
+/// [`PerfFollower::convert_new_records`], which only reads the records which
+/// have been written since the previous call.
+pub struct PerfFollower {
+    path: PathBuf,
+    converted_end: u64,
+    has_rounds: bool,
+    converter: Box<dyn FollowedConverter>,
+}
+
+impl PerfFollower {
+    pub fn new(
+        path: &Path,
+        file_mod_time: Option<SystemTime>,
+        binary_lookup_dirs: Vec<PathBuf>,
+        aux_file_lookup_dirs: Vec<PathBuf>,
+        profile_creation_props: ProfileCreationProps,
+    ) -> Result<Self, Error> {
+        let mut file = InProgressPerfFile::open(path)?;
+        let data_offset = file.data_offset();
+        file.set_data_range(data_offset..data_offset);
+        let PerfFileReader { perf_file, .. } = PerfFileReader::parse_file(BufReader::new(file))?;
+
+        // The architecture is in the feature sections, which `perf record`
+        // only writes when it exits. Until then, the file is being written on
+        // this machine.
+        let arch = perf_file.arch().ok().flatten();
+        let converter: Box<dyn FollowedConverter> = match arch.unwrap_or(std::env::consts::ARCH) {
+            "aarch64" => Box::new(RecordConverter::<
+                framehop::aarch64::UnwinderAarch64<MmapRangeOrVec>,
+                ConvertRegsAarch64,
+            >::new(
+                &perf_file,
+                file_mod_time,
+                binary_lookup_dirs,
+                aux_file_lookup_dirs,
+                framehop::aarch64::CacheAarch64::new(),
+                profile_creation_props,
+            )),
+            _ => Box::new(RecordConverter::<
+                framehop::x86_64::UnwinderX86_64<MmapRangeOrVec>,
+                ConvertRegsX86_64,
+            >::new(
+                &perf_file,
+                file_mod_time,
+                binary_lookup_dirs,
+                aux_file_lookup_dirs,
+                framehop::x86_64::CacheX86_64::new(),
+                profile_creation_props,
+            )),
+        };
+
+        Ok(Self {
+            path: path.to_owned(),
+            converted_end: data_offset,
+            has_rounds: false,
+            converter,
+        })
+    }
+
+    /// Convert the records which have been written since the previous call.
+    /// Returns whether there were any.
+    ///
+    /// Records are only converted up to the last `PERF_RECORD_FINISHED_ROUND`,
+    /// because the records after it may still have to be sorted together with
+    /// records which haven't been written yet. Does nothing once `perf record`
+    /// has finished writing the file.
+    pub fn convert_new_records(&mut self) -> Result<bool, Error> {
+        let mut file = InProgressPerfFile::open(&self.path)?;
+        if file.is_complete() {
+            return Ok(false);
+        }
+        let records_end = file.scan_records(self.converted_end)?;
+        self.has_rounds |= records_end.last_round_end.is_some();
+        let end = if self.has_rounds {
+            records_end.last_round_end
+        } else {
+            Some(records_end.last_record_end)
+        };
+        let Some(end) = end.filter(|&end| end > self.converted_end) else {
+            return Ok(false);
+        };
+
+        file.set_data_range(self.converted_end..end);
+        let file = PerfFileReader::parse_file(BufReader::new(file))?;
+        self.converter.convert_batch(file);
+        self.converted_end = end;
+        Ok(true)
+    }
+
+    /// The number of bytes of the file which have been converted.
+    pub fn converted_len(&self) -> u64 {
+        self.converted_end
+    }
+
+    /// The profile with the records converted so far.
+    pub fn snapshot(&self) -> Profile {
+        self.converter.snapshot()
+    }
+}
+
+/// A [`RecordConverter`] for any architecture.
+trait FollowedConverter {
+    fn convert_batch(&mut self, file: PerfFileReader<BufReader<InProgressPerfFile>>);
+    fn snapshot(&self) -> Profile;
+}
+
+impl<U, C> FollowedConverter for RecordConverter<U, C>
+where
+    U: Unwinder<Module = Module<MmapRangeOrVec>> + Default,
+    C: ConvertRegs<UnwindRegs = U::UnwindRegs>,
+{
+    fn convert_batch(&mut self, file: PerfFileReader<BufReader<InProgressPerfFile>>) {
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = file;
+        self.convert_records(&mut record_iter, &mut perf_file);
+    }
+
+    fn snapshot(&self) -> Profile {
+        self.converter.snapshot()
+    }
+}
+
+/// Byte range of the perf.data header which [`InProgressPerfFile`] patches:
+/// everything up to and including the feature bitmap.
+const PERF_HEADER_PATCH_LEN: usize = 104;
+const PERF_HEADER_DATA_OFFSET_POS: usize = 40;
+const PERF_HEADER_DATA_SIZE_POS: usize = 48;
+const PERF_HEADER_FEATURES_POS: usize = 72;
+const PERF_EVENT_HEADER_LEN: usize = 8;
+const PERF_RECORD_FINISHED_ROUND: u32 = 68;
+
+/// Where the complete records of an [`InProgressPerfFile`] end.
+struct RecordsEnd {
+    /// The end of the last record which has been written completely.
+    last_record_end: u64,
+    /// The end of the last `PERF_RECORD_FINISHED_ROUND` record. The records
+    /// after it may still have to be sorted together with records which
+    /// haven't been written yet.
+    last_round_end: Option<u64>,
+}
+
+/// A snapshot of a perf.data file which may still be written by `perf record`.
+///
+/// `perf record` only fills in the size of the data section and writes the
+/// feature sections (build IDs, OS release, ...) when it exits. Until then, this
+/// presents the file as if its data section extended to the end of the file as it
+/// was when opened, and as if it had no feature sections, so that the records
+/// written so far can be converted. A record which is cut off at the end of the
+/// snapshot stops the conversion; it is picked up by the next snapshot.
+pub struct InProgressPerfFile {
+    file: File,
+    header: [u8; PERF_HEADER_PATCH_LEN],
+    len: u64,
+    pos: u64,
+    is_complete: bool,
+    is_little_endian: bool,
+    data_offset: u64,
+}
+
+impl InProgressPerfFile {
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut header = [0; PERF_HEADER_PATCH_LEN];
+        file.read_exact(&mut header)?;
+        file.seek(SeekFrom::Start(0))?;
+
+        let is_little_endian = &header[..8] == b"PERFILE2";
+        let read_u64 = |pos: usize| {
+            let bytes: [u8; 8] = header[pos..pos + 8].try_into().unwrap();
+            if is_little_endian {
+                u64::from_le_bytes(bytes)
+            } else {
+                u64::from_be_bytes(bytes)
             }
-            EventRecord::ContextSwitch(e) => {
-                let common = match record.common_data() {
-                    Ok(common) => common,
-                    Err(_) => continue,
-                };
-                converter.handle_context_switch(e, common);
+        };
+        let data_offset = read_u64(PERF_HEADER_DATA_OFFSET_POS);
+        let is_complete = read_u64(PERF_HEADER_DATA_SIZE_POS) != 0;
+
+        let mut file = Self {
+            file,
+            header,
+            len,
+            pos: 0,
+            is_complete,
+            is_little_endian,
+            data_offset,
+        };
+        if !is_complete {
+            file.set_data_range(data_offset..len.max(data_offset));
+            file.header[PERF_HEADER_FEATURES_POS..PERF_HEADER_PATCH_LEN].fill(0);
+        }
+        Ok(file)
+    }
+
+    /// Present the records in `range` of the file as the data section, so that
+    /// only the records which have been written since the previous snapshot
+    /// are converted. `range` has to start at a record.
+    fn set_data_range(&mut self, range: Range<u64>) {
+        let mut write_u64 = |pos: usize, value: u64| {
+            let bytes = if self.is_little_endian {
+                value.to_le_bytes()
+            } else {
+                value.to_be_bytes()
+            };
+            self.header[pos..pos + 8].copy_from_slice(&bytes);
+        };
+        write_u64(PERF_HEADER_DATA_OFFSET_POS, range.start);
+        write_u64(PERF_HEADER_DATA_SIZE_POS, range.end - range.start);
+    }
+
+    /// The offset of the first record in the file.
+    fn data_offset(&self) -> u64 {
+        self.data_offset
+    }
+
+    /// Walk the record headers from `start`, which has to be the start of a
+    /// record, to the end of the snapshot.
+    fn scan_records(&mut self, start: u64) -> std::io::Result<RecordsEnd> {
+        let mut reader = BufReader::new(&self.file);
+        reader.seek(SeekFrom::Start(start))?;
+        let mut records_end = RecordsEnd {
+            last_record_end: start,
+            last_round_end: None,
+        };
+        let mut offset = start;
+        let mut header = [0; PERF_EVENT_HEADER_LEN];
+        while offset + PERF_EVENT_HEADER_LEN as u64 <= self.len {
+            reader.read_exact(&mut header)?;
+            let (record_type, size) = if self.is_little_endian {
+                (
+                    u32::from_le_bytes(header[..4].try_into().unwrap()),
+                    u16::from_le_bytes(header[6..].try_into().unwrap()),
+                )
+            } else {
+                (
+                    u32::from_be_bytes(header[..4].try_into().unwrap()),
+                    u16::from_be_bytes(header[6..].try_into().unwrap()),
+                )
+            };
+            let end = offset + u64::from(size);
+            if (size as usize) < PERF_EVENT_HEADER_LEN || end > self.len {
+                break;
             }
-            _ => {
-                // println!("{:?}", record.record_type);
+            reader.seek_relative(i64::from(size) - PERF_EVENT_HEADER_LEN as i64)?;
+            records_end.last_record_end = end;
+            if record_type == PERF_RECORD_FINISHED_ROUND {
+                records_end.last_round_end = Some(end);
             }
+            offset = end;
+        }
+        drop(reader);
+        self.file.seek(SeekFrom::Start(self.pos))?;
+        Ok(records_end)
+    }
+
+    /// Whether `perf record` has finished writing this file.
+    pub fn is_complete(&self) -> bool {
+        self.is_complete
+    }
+}
+
+impl Read for InProgressPerfFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.is_complete {
+            return self.file.read(buf);
+        }
+        let end = self.len.min(self.pos + buf.len() as u64);
+        if self.pos >= end {
+            return Ok(0);
+        }
+        let n = (end - self.pos) as usize;
+        if self.pos < PERF_HEADER_PATCH_LEN as u64 {
+            let start = self.pos as usize;
+            let n = n.min(PERF_HEADER_PATCH_LEN - start);
+            buf[..n].copy_from_slice(&self.header[start..start + n]);
+            self.pos += n as u64;
+            self.file.seek(SeekFrom::Start(self.pos))?;
+            return Ok(n);
         }
+        let n = self.file.read(&mut buf[..n])?;
+        self.pos += n as u64;
+        Ok(n)
     }
+}
 
-    converter.finish()
+impl Seek for InProgressPerfFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        if self.is_complete {
+            return self.file.seek(pos);
+        }
+        let pos = match pos {
+            SeekFrom::Start(pos) => pos,
+            SeekFrom::End(delta) => self.len.saturating_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.saturating_add_signed(delta),
+        };
+        self.pos = self.file.seek(SeekFrom::Start(pos))?;
+        Ok(self.pos)
+    }
 }
 
 fn get_simpleperf_timestamp(meta_info: Option<&HashMap<&str, &str>>) -> Option<f64> {
@@ -0,0 +1,585 @@
+//! Importing pprof profiles (`profile.proto`), as written by Go's runtime/pprof,
+//! gperftools, Parca agents and `samply-for-ai analyze export --format pprof`.
+//!
+//! Mappings become libraries with process mappings, so frames without
+//! function information can still be symbolicated. Locations which already
+//! have function names (usually all of them) become label frames with their
+//! source location, one frame per inlined function. pprof samples have no
+//! timestamps, so samples are laid out back to back, one sampling period
+//! apart.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+
+use debugid::DebugId;
+use flate2::read::GzDecoder;
+use fxprof_processed_profile::{
+    CategoryHandle, CpuDelta, FrameAddress, FrameFlags, FrameHandle, LibraryInfo, Profile,
+    ReferenceTimestamp, SamplingInterval, SourceLocation, ThreadHandle, Timestamp, WeightType,
+};
+use samply_debugid::DebugIdExt;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("I/O Error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Malformed pprof profile: {0}")]
+    Malformed(&'static str),
+}
+
+/// Whether the file contents look like a pprof profile: gzip-compressed data
+/// (pprof files are almost always compressed), or a `.pb` / `.pprof` file.
+pub fn is_pprof_file(path: &Path, first_bytes: &[u8]) -> bool {
+    first_bytes.starts_with(&[0x1f, 0x8b])
+        || matches!(
+            path.extension().and_then(|ext| ext.to_str()),
+            Some("pb" | "pprof")
+        )
+}
+
+/// A field value of the protobuf wire format
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// Iterates over the (field number, value) pairs of a protobuf message.
+struct Fields<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Fields<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Fields { data }
+    }
+
+    fn varint(&mut self) -> Result<u64, Error> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self
+                .data
+                .split_first()
+                .ok_or(Error::Malformed("truncated varint"))?;
+            self.data = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte < 0x80 {
+                return Ok(value);
+            }
+        }
+        Err(Error::Malformed("varint too long"))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if len > self.data.len() {
+            return Err(Error::Malformed("truncated field"));
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn next_field(&mut self) -> Result<Option<(u64, Value<'a>)>, Error> {
+        if self.data.is_empty() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let value = match key & 7 {
+            0 => Value::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                Value::Fixed
+            }
+            2 => {
+                let len = self.varint()? as usize;
+                Value::Bytes(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                Value::Fixed
+            }
+            _ => return Err(Error::Malformed("unsupported wire type")),
+        };
+        Ok(Some((key >> 3, value)))
+    }
+}
+
+/// Append a repeated integer field, which can be packed or not.
+fn push_repeated(values: &mut Vec<u64>, value: Value) -> Result<(), Error> {
+    match value {
+        Value::Varint(v) => values.push(v),
+        Value::Bytes(packed) => {
+            let mut fields = Fields::new(packed);
+            while !fields.data.is_empty() {
+                values.push(fields.varint()?);
+            }
+        }
+        Value::Fixed => return Err(Error::Malformed("unexpected fixed-size integer")),
+    }
+    Ok(())
+}
+
+fn varint(value: Value) -> Result<u64, Error> {
+    match value {
+        Value::Varint(v) => Ok(v),
+        _ => Err(Error::Malformed("expected an integer field")),
+    }
+}
+
+fn bytes(value: Value) -> Result<&[u8], Error> {
+    match value {
+        Value::Bytes(bytes) => Ok(bytes),
+        _ => Err(Error::Malformed("expected a length-delimited field")),
+    }
+}
+
+#[derive(Default)]
+struct ValueType {
+    type_: u64,
+    unit: u64,
+}
+
+#[derive(Default)]
+struct Label {
+    key: u64,
+    str: u64,
+}
+
+#[derive(Default)]
+struct Sample {
+    location_ids: Vec<u64>,
+    values: Vec<u64>,
+    labels: Vec<Label>,
+}
+
+#[derive(Default)]
+struct Mapping {
+    memory_start: u64,
+    memory_limit: u64,
+    file_offset: u64,
+    filename: u64,
+    build_id: u64,
+}
+
+#[derive(Default)]
+struct Line {
+    function_id: u64,
+    line: u64,
+}
+
+#[derive(Default)]
+struct Location {
+    id: u64,
+    address: u64,
+    lines: Vec<Line>,
+}
+
+#[derive(Default)]
+struct Function {
+    id: u64,
+    name: u64,
+    filename: u64,
+}
+
+/// The parts of a `perftools.profiles.Profile` message which we import
+#[derive(Default)]
+struct PprofProfile {
+    sample_types: Vec<ValueType>,
+    samples: Vec<Sample>,
+    mappings: Vec<Mapping>,
+    locations: Vec<Location>,
+    functions: Vec<Function>,
+    strings: Vec<String>,
+    time_nanos: u64,
+    period_type: ValueType,
+    period: u64,
+    default_sample_type: u64,
+}
+
+fn parse_value_type(data: &[u8]) -> Result<ValueType, Error> {
+    let mut value_type = ValueType::default();
+    let mut fields = Fields::new(data);
+    while let Some((field, value)) = fields.next_field()? {
+        match field {
+            1 => value_type.type_ = varint(value)?,
+            2 => value_type.unit = varint(value)?,
+            _ => {}
+        }
+    }
+    Ok(value_type)
+}
+
+fn parse_sample(data: &[u8]) -> Result<Sample, Error> {
+    let mut sample = Sample::default();
+    let mut fields = Fields::new(data);
+    while let Some((field, value)) = fields.next_field()? {
+        match field {
+            1 => push_repeated(&mut sample.location_ids, value)?,
+            2 => push_repeated(&mut sample.values, value)?,
+            3 => {
+                let mut label = Label::default();
+                let mut label_fields = Fields::new(bytes(value)?);
+                while let Some((field, value)) = label_fields.next_field()? {
+                    match field {
+                        1 => label.key = varint(value)?,
+                        2 => label.str = varint(value)?,
+                        _ => {}
+                    }
+                }
+                sample.labels.push(label);
+            }
+            _ => {}
+        }
+    }
+    Ok(sample)
+}
+
+fn parse_mapping(data: &[u8]) -> Result<Mapping, Error> {
+    let mut mapping = Mapping::default();
+    let mut fields = Fields::new(data);
+    while let Some((field, value)) = fields.next_field()? {
+        match field {
+            2 => mapping.memory_start = varint(value)?,
+            3 => mapping.memory_limit = varint(value)?,
+            4 => mapping.file_offset = varint(value)?,
+            5 => mapping.filename = varint(value)?,
+            6 => mapping.build_id = varint(value)?,
+            _ => {}
+        }
+    }
+    Ok(mapping)
+}
+
+fn parse_location(data: &[u8]) -> Result<Location, Error> {
+    let mut location = Location::default();
+    let mut fields = Fields::new(data);
+    while let Some((field, value)) = fields.next_field()? {
+        match field {
+            1 => location.id = varint(value)?,
+            3 => location.address = varint(value)?,
+            4 => {
+                let mut line = Line::default();
+                let mut line_fields = Fields::new(bytes(value)?);
+                while let Some((field, value)) = line_fields.next_field()? {
+                    match field {
+                        1 => line.function_id = varint(value)?,
+                        2 => line.line = varint(value)?,
+                        _ => {}
+                    }
+                }
+                location.lines.push(line);
+            }
+            _ => {}
+        }
+    }
+    Ok(location)
+}
+
+fn parse_function(data: &[u8]) -> Result<Function, Error> {
+    let mut function = Function::default();
+    let mut fields = Fields::new(data);
+    while let Some((field, value)) = fields.next_field()? {
+        match field {
+            1 => function.id = varint(value)?,
+            2 => function.name = varint(value)?,
+            4 => function.filename = varint(value)?,
+            _ => {}
+        }
+    }
+    Ok(function)
+}
+
+fn parse_profile(data: &[u8]) -> Result<PprofProfile, Error> {
+    let mut profile = PprofProfile::default();
+    let mut fields = Fields::new(data);
+    while let Some((field, value)) = fields.next_field()? {
+        match field {
+            1 => profile.sample_types.push(parse_value_type(bytes(value)?)?),
+            2 => profile.samples.push(parse_sample(bytes(value)?)?),
+            3 => profile.mappings.push(parse_mapping(bytes(value)?)?),
+            4 => profile.locations.push(parse_location(bytes(value)?)?),
+            5 => profile.functions.push(parse_function(bytes(value)?)?),
+            6 => profile
+                .strings
+                .push(String::from_utf8_lossy(bytes(value)?).into_owned()),
+            9 => profile.time_nanos = varint(value)?,
+            11 => profile.period_type = parse_value_type(bytes(value)?)?,
+            12 => profile.period = varint(value)?,
+            14 => profile.default_sample_type = varint(value)?,
+            _ => {}
+        }
+    }
+    if !profile.strings.first().is_some_and(|s| s.is_empty()) {
+        return Err(Error::Malformed("string table doesn't start with \"\""));
+    }
+    if profile.sample_types.is_empty() {
+        return Err(Error::Malformed("no sample types"));
+    }
+    Ok(profile)
+}
+
+impl PprofProfile {
+    fn string(&self, index: u64) -> &str {
+        self.strings.get(index as usize).map_or("", String::as_str)
+    }
+
+    /// The index of the sample value to use as the sample weight, and whether
+    /// it's a byte count. Time values are converted to sample counts if the
+    /// profile has them, so that the weights stay within `i32`.
+    fn weight_value_index(&self) -> (usize, bool) {
+        let default_index = self
+            .sample_types
+            .iter()
+            .position(|t| t.type_ == self.default_sample_type && self.default_sample_type != 0)
+            .unwrap_or(self.sample_types.len() - 1);
+        let unit = self.string(self.sample_types[default_index].unit);
+        if unit == "bytes" {
+            return (default_index, true);
+        }
+        let count_index = self
+            .sample_types
+            .iter()
+            .position(|t| self.string(t.unit) == "count");
+        (count_index.unwrap_or(default_index), false)
+    }
+
+    /// The sampling period in nanoseconds, if the period is a time
+    fn period_nanos(&self) -> Option<u64> {
+        let factor = match self.string(self.period_type.unit) {
+            "nanoseconds" => 1,
+            "microseconds" => 1_000,
+            "milliseconds" => 1_000_000,
+            "seconds" => 1_000_000_000,
+            _ => return None,
+        };
+        self.period.checked_mul(factor).filter(|&period| period > 0)
+    }
+}
+
+/// Turn a pprof build ID into a debug ID and code ID. ELF build IDs are hex
+/// strings; other build IDs (e.g. Go's) can't be used for symbol lookup.
+fn parse_build_id(build_id: &str) -> (DebugId, Option<String>) {
+    if build_id.len() % 2 != 0 || build_id.is_empty() {
+        return (DebugId::nil(), None);
+    }
+    let bytes: Option<Vec<u8>> = (0..build_id.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(build_id.get(i..i + 2)?, 16).ok())
+        .collect();
+    match bytes {
+        Some(bytes) => (
+            DebugId::from_identifier(&bytes, true),
+            Some(build_id.to_ascii_lowercase()),
+        ),
+        None => (DebugId::nil(), None),
+    }
+}
+
+pub fn convert(input_path: &Path, mut reader: impl Read) -> Result<Profile, Error> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    if data.starts_with(&[0x1f, 0x8b]) {
+        let mut decompressed = Vec::new();
+        GzDecoder::new(&data[..]).read_to_end(&mut decompressed)?;
+        data = decompressed;
+    }
+    let pprof = parse_profile(&data)?;
+
+    let period_nanos = pprof.period_nanos();
+    let interval = match period_nanos {
+        Some(nanos) => SamplingInterval::from_nanos(nanos),
+        None => SamplingInterval::from_millis(1),
+    };
+    let reference_timestamp =
+        ReferenceTimestamp::from_millis_since_unix_epoch(pprof.time_nanos as f64 / 1_000_000.0);
+    let product = input_path
+        .file_name()
+        .map_or("pprof".into(), |name| name.to_string_lossy());
+    let mut profile = Profile::new(&product, reference_timestamp, interval);
+
+    let process = profile.add_process(&product, 0, Timestamp::from_millis_since_reference(0.0));
+
+    for mapping in &pprof.mappings {
+        let path = pprof.string(mapping.filename);
+        if path.is_empty() || mapping.memory_limit <= mapping.memory_start {
+            continue;
+        }
+        let name = Path::new(path)
+            .file_name()
+            .map_or(path.into(), |name| name.to_string_lossy())
+            .into_owned();
+        let (debug_id, code_id) = parse_build_id(pprof.string(mapping.build_id));
+        let lib = profile.add_lib(LibraryInfo {
+            name: name.clone(),
+            debug_name: name,
+            path: path.to_string(),
+            debug_path: path.to_string(),
+            debug_id,
+            code_id,
+            arch: None,
+        });
+        profile.add_lib_mapping(
+            process,
+            lib,
+            mapping.memory_start,
+            mapping.memory_limit,
+            mapping.file_offset as u32,
+        );
+    }
+
+    let locations: HashMap<u64, &Location> = pprof.locations.iter().map(|l| (l.id, l)).collect();
+    let functions: HashMap<u64, &Function> = pprof.functions.iter().map(|f| (f.id, f)).collect();
+    let (weight_index, weight_is_bytes) = pprof.weight_value_index();
+
+    // Samples are grouped into threads by their "thread" / "thread_name" label.
+    let thread_name_keys: Vec<u64> = pprof
+        .strings
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| *s == "thread" || *s == "thread_name")
+        .map(|(i, _)| i as u64)
+        .collect();
+    let mut threads: HashMap<u64, ThreadHandle> = HashMap::new();
+    let mut frames: HashMap<(ThreadHandle, u64, bool), Vec<FrameHandle>> = HashMap::new();
+    let mut time_nanos = 0u64;
+    let sample_duration_nanos = period_nanos.unwrap_or(1_000_000);
+
+    for sample in &pprof.samples {
+        let weight = sample.values.get(weight_index).copied().unwrap_or(0);
+        if weight == 0 {
+            continue;
+        }
+        let thread_name = sample
+            .labels
+            .iter()
+            .find(|label| thread_name_keys.contains(&label.key))
+            .map_or(0, |label| label.str);
+        let thread = *threads.entry(thread_name).or_insert_with(|| {
+            let is_main = thread_name == 0;
+            // pprof has no thread IDs, so the label's string index serves as one.
+            let tid = u32::try_from(thread_name).unwrap_or(u32::MAX);
+            let thread = profile.add_thread(
+                process,
+                tid,
+                Timestamp::from_millis_since_reference(0.0),
+                is_main,
+            );
+            let name = match pprof.string(thread_name) {
+                "" => &*product,
+                name => name,
+            };
+            profile.set_thread_name(thread, name);
+            if weight_is_bytes {
+                profile.set_thread_samples_weight_type(thread, WeightType::Bytes);
+            }
+            thread
+        });
+
+        // location_ids are leaf first; stacks are built from the root.
+        let mut stack = None;
+        for (i, location_id) in sample.location_ids.iter().enumerate().rev() {
+            let is_leaf = i == 0;
+            let frame_handles = frames
+                .entry((thread, *location_id, is_leaf))
+                .or_insert_with(|| {
+                    let Some(location) = locations.get(location_id) else {
+                        return Vec::new();
+                    };
+                    location_frames(&mut profile, &pprof, &functions, thread, location, is_leaf)
+                });
+            for frame in frame_handles.iter() {
+                stack = Some(profile.handle_for_stack(thread, *frame, stack));
+            }
+        }
+
+        let timestamp = Timestamp::from_nanos_since_reference(time_nanos);
+        let weight = i32::try_from(weight).unwrap_or(i32::MAX);
+        if weight_is_bytes {
+            profile.add_sample(thread, timestamp, stack, CpuDelta::ZERO, weight);
+            time_nanos += sample_duration_nanos;
+        } else {
+            let cpu_delta = CpuDelta::from_nanos(sample_duration_nanos * weight as u64);
+            profile.add_sample(thread, timestamp, stack, cpu_delta, weight);
+            time_nanos += sample_duration_nanos * weight as u64;
+        }
+    }
+
+    Ok(profile)
+}
+
+/// The frames of a location, root first: one per inlined function if the
+/// location has function names, otherwise a single address frame.
+fn location_frames(
+    profile: &mut Profile,
+    pprof: &PprofProfile,
+    functions: &HashMap<u64, &Function>,
+    thread: ThreadHandle,
+    location: &Location,
+    is_leaf: bool,
+) -> Vec<FrameHandle> {
+    let named_lines: Vec<(&Function, u64)> = location
+        .lines
+        .iter()
+        .filter_map(|line| {
+            let function = functions.get(&line.function_id)?;
+            (function.name != 0).then_some((*function, line.line))
+        })
+        .collect();
+
+    if named_lines.is_empty() {
+        // Resolved to a library through the process mappings, and symbolicated
+        // later like a recorded frame. Caller addresses in pprof already point
+        // into the call instruction.
+        let address = if is_leaf {
+            FrameAddress::InstructionPointer(location.address)
+        } else {
+            FrameAddress::AdjustedReturnAddress(location.address)
+        };
+        return vec![profile.handle_for_frame_with_address(
+            thread,
+            address,
+            CategoryHandle::OTHER,
+            FrameFlags::empty(),
+        )];
+    }
+
+    // The last line is the outermost function, into which the others were inlined.
+    named_lines
+        .iter()
+        .rev()
+        .map(|(function, line)| {
+            let name = profile.handle_for_string(pprof.string(function.name));
+            let file_path = match pprof.string(function.filename) {
+                "" => None,
+                file => Some(profile.handle_for_string(file)),
+            };
+            let source_location = SourceLocation {
+                file_path,
+                line: u32::try_from(*line).ok().filter(|&line| line != 0),
+                col: None,
+            };
+            profile.handle_for_frame_with_label_and_source_location(
+                thread,
+                name,
+                source_location,
+                CategoryHandle::OTHER,
+                FrameFlags::empty(),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_build_id() {
+        let (debug_id, code_id) = parse_build_id("b8a4fc4d0e5ebdcf01234567");
+        assert!(!debug_id.is_nil());
+        assert_eq!(code_id.as_deref(), Some("b8a4fc4d0e5ebdcf01234567"));
+        assert_eq!(parse_build_id("go-build-id/xyz"), (DebugId::nil(), None));
+    }
+}
@@ -0,0 +1,260 @@
+//! Importing speedscope files (<https://www.speedscope.app/file-format-schema.json>),
+//! as written by speedscope itself, py-spy, rbspy, and
+//! `samply-for-ai analyze export --format speedscope`.
+//!
+//! Each speedscope profile becomes a thread. Sampled profiles keep their
+//! samples; evented profiles (open/close events) get a sample for every span
+//! between two events, weighted by its duration.
+
+use std::io::Read;
+use std::path::Path;
+
+use fxprof_processed_profile::{
+    CategoryHandle, CpuDelta, FrameFlags, FrameHandle, Profile, ReferenceTimestamp,
+    SamplingInterval, SourceLocation, StackHandle, ThreadHandle, Timestamp, WeightType,
+};
+use serde::Deserialize;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("I/O Error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid speedscope file: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Invalid speedscope file: frame index {0} is out of range")]
+    InvalidFrame(usize),
+}
+
+/// Whether the file is a speedscope file: named `*.speedscope.json`, or JSON
+/// which starts with speedscope's `$schema`.
+pub fn is_speedscope_file(path: &Path, first_bytes: &[u8]) -> bool {
+    path.to_string_lossy().ends_with(".speedscope.json")
+        || String::from_utf8_lossy(first_bytes).contains("speedscope.app/file-format-schema")
+}
+
+#[derive(Deserialize)]
+struct SpeedscopeFile {
+    shared: Shared,
+    profiles: Vec<SpeedscopeProfile>,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Shared {
+    frames: Vec<Frame>,
+}
+
+#[derive(Deserialize)]
+struct Frame {
+    name: String,
+    #[serde(default)]
+    file: Option<String>,
+    #[serde(default)]
+    line: Option<u32>,
+    #[serde(default)]
+    col: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum SpeedscopeProfile {
+    #[serde(rename_all = "camelCase")]
+    Sampled {
+        name: String,
+        unit: String,
+        start_value: f64,
+        samples: Vec<Vec<usize>>,
+        weights: Vec<f64>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Evented {
+        name: String,
+        unit: String,
+        start_value: f64,
+        events: Vec<Event>,
+    },
+}
+
+#[derive(Deserialize)]
+struct Event {
+    #[serde(rename = "type")]
+    type_: EventType,
+    at: f64,
+    frame: usize,
+}
+
+#[derive(Deserialize)]
+enum EventType {
+    #[serde(rename = "O")]
+    Open,
+    #[serde(rename = "C")]
+    Close,
+}
+
+/// Milliseconds per unit of a speedscope value unit, `None` for non-time units
+fn unit_in_ms(unit: &str) -> Option<f64> {
+    match unit {
+        "nanoseconds" => Some(1e-6),
+        "microseconds" => Some(1e-3),
+        "milliseconds" => Some(1.0),
+        "seconds" => Some(1e3),
+        _ => None,
+    }
+}
+
+/// Converts the frames and stacks of one speedscope profile into one thread.
+struct ThreadConverter<'a> {
+    frames: &'a [Frame],
+    thread: ThreadHandle,
+    /// Frame handles, created on first use
+    frame_handles: Vec<Option<FrameHandle>>,
+}
+
+impl ThreadConverter<'_> {
+    fn frame(&mut self, profile: &mut Profile, index: usize) -> Result<FrameHandle, Error> {
+        let frame = self.frames.get(index).ok_or(Error::InvalidFrame(index))?;
+        if let Some(handle) = self.frame_handles[index] {
+            return Ok(handle);
+        }
+        let name = profile.handle_for_string(&frame.name);
+        let file_path = frame
+            .file
+            .as_deref()
+            .map(|file| profile.handle_for_string(file));
+        let handle = profile.handle_for_frame_with_label_and_source_location(
+            self.thread,
+            name,
+            SourceLocation {
+                file_path,
+                line: frame.line,
+                col: frame.col,
+            },
+            CategoryHandle::OTHER,
+            FrameFlags::empty(),
+        );
+        self.frame_handles[index] = Some(handle);
+        Ok(handle)
+    }
+
+    /// The stack for root-first frame indexes
+    fn stack(
+        &mut self,
+        profile: &mut Profile,
+        frame_indexes: &[usize],
+    ) -> Result<Option<StackHandle>, Error> {
+        let mut stack = None;
+        for &index in frame_indexes {
+            let frame = self.frame(profile, index)?;
+            stack = Some(profile.handle_for_stack(self.thread, frame, stack));
+        }
+        Ok(stack)
+    }
+}
+
+pub fn convert(input_path: &Path, reader: impl Read) -> Result<Profile, Error> {
+    let file: SpeedscopeFile = serde_json::from_reader(reader)?;
+
+    let product = match &file.name {
+        Some(name) if !name.is_empty() => name.clone(),
+        _ => input_path.file_name().map_or("speedscope".into(), |name| {
+            name.to_string_lossy().into_owned()
+        }),
+    };
+    let mut profile = Profile::new(
+        &product,
+        ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+        SamplingInterval::from_millis(1),
+    );
+    let process = profile.add_process(&product, 0, Timestamp::from_millis_since_reference(0.0));
+
+    for (i, speedscope_profile) in file.profiles.iter().enumerate() {
+        let (name, unit, start_value) = match speedscope_profile {
+            SpeedscopeProfile::Sampled {
+                name,
+                unit,
+                start_value,
+                ..
+            }
+            | SpeedscopeProfile::Evented {
+                name,
+                unit,
+                start_value,
+                ..
+            } => (name, unit, *start_value),
+        };
+        let start_time = Timestamp::from_millis_since_reference(0.0);
+        let thread = profile.add_thread(process, i as u32, start_time, i == 0);
+        profile.set_thread_name(thread, if name.is_empty() { &product } else { name });
+        let time_unit_ms = unit_in_ms(unit);
+        if time_unit_ms.is_none() && unit == "bytes" {
+            profile.set_thread_samples_weight_type(thread, WeightType::Bytes);
+        }
+
+        let mut converter = ThreadConverter {
+            frames: &file.shared.frames,
+            thread,
+            frame_handles: vec![None; file.shared.frames.len()],
+        };
+
+        match speedscope_profile {
+            SpeedscopeProfile::Sampled {
+                samples, weights, ..
+            } => {
+                // Time-weighted samples become one sample each, placed on the
+                // timeline by their weights; other weights are kept as weights.
+                let mut value = start_value;
+                for (sample, &weight) in samples.iter().zip(weights) {
+                    let stack = converter.stack(&mut profile, sample)?;
+                    match time_unit_ms {
+                        Some(ms) => {
+                            let timestamp = Timestamp::from_millis_since_reference(value * ms);
+                            let cpu_delta = CpuDelta::from_nanos((weight * ms * 1e6) as u64);
+                            profile.add_sample(thread, timestamp, stack, cpu_delta, 1);
+                        }
+                        None => {
+                            let timestamp = Timestamp::from_millis_since_reference(value);
+                            let weight = weight.round().clamp(0.0, i32::MAX as f64) as i32;
+                            profile.add_sample(thread, timestamp, stack, CpuDelta::ZERO, weight);
+                        }
+                    }
+                    value += weight;
+                }
+            }
+            SpeedscopeProfile::Evented { events, .. } => {
+                // Each span between two events becomes a sample with the stack of
+                // open frames, weighted by its duration in 1/10000 of the profile.
+                let (Some(first), Some(last)) = (events.first(), events.last()) else {
+                    continue;
+                };
+                let tick = ((last.at - first.at) / 10_000.0).max(f64::MIN_POSITIVE);
+                let ms = time_unit_ms.unwrap_or(1.0);
+                let mut open_frames: Vec<usize> = Vec::new();
+                let mut previous_at = first.at;
+                for event in events {
+                    let weight = ((event.at - previous_at) / tick).round() as i32;
+                    if weight > 0 && !open_frames.is_empty() {
+                        let stack = converter.stack(&mut profile, &open_frames)?;
+                        let timestamp = Timestamp::from_millis_since_reference(previous_at * ms);
+                        let cpu_delta =
+                            CpuDelta::from_nanos(((event.at - previous_at) * ms * 1e6) as u64);
+                        profile.add_sample(thread, timestamp, stack, cpu_delta, weight);
+                    }
+                    previous_at = event.at;
+                    match event.type_ {
+                        EventType::Open => open_frames.push(event.frame),
+                        EventType::Close => {
+                            if let Some(pos) = open_frames.iter().rposition(|&f| f == event.frame) {
+                                open_frames.truncate(pos);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(profile)
+}
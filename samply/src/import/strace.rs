@@ -0,0 +1,439 @@
+//! Overlaying strace and ltrace logs onto a profile, as markers on the
+//! threads which made the calls, for when syscalls can't be traced while
+//! recording (`samply overlay profile.json strace.log`).
+//!
+//! The log needs absolute timestamps (`-ttt`), and a pid column (`-f`) if
+//! more than one thread was traced. With `-T`, or for calls which strace
+//! splits into `<unfinished ...>` and `<... resumed>` lines, the markers
+//! have a duration; otherwise they are instant markers. Signal and exit lines
+//! are skipped.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("I/O Error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid profile: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("The profile has no shared string table; only processed profiles can be overlaid")]
+    NoStringTable,
+
+    #[error("The log has no pid column and the profile has several processes; pass --pid, or trace with strace -f")]
+    NoPid,
+
+    #[error("The log has no calls with -ttt timestamps")]
+    NoCalls,
+}
+
+/// The marker type of the overlaid calls
+const MARKER_TYPE: &str = "Syscall";
+/// Longer arguments are cut off, strace abbreviates strings at 32 bytes anyway
+const MAX_ARGS_LEN: usize = 256;
+
+/// One call from the log
+#[derive(Debug, Clone, PartialEq)]
+struct Call {
+    tid: Option<u32>,
+    name: String,
+    args: String,
+    result: Option<String>,
+    /// Seconds since the epoch
+    start: f64,
+    end: Option<f64>,
+}
+
+/// One line of the log
+#[derive(Debug, Clone, PartialEq)]
+enum Line {
+    Call(Call),
+    /// The first half of a call which another thread interrupted
+    Unfinished(Call),
+    /// The second half
+    Resumed {
+        tid: Option<u32>,
+        name: String,
+        time: f64,
+        /// With `-T`, the duration of the whole call
+        duration: Option<f64>,
+        result: Option<String>,
+    },
+}
+
+/// What was overlaid
+#[derive(Debug, Default)]
+pub struct OverlayStats {
+    pub markers: usize,
+    /// Calls of threads which aren't in the profile, by tid
+    pub unknown_threads: BTreeMap<u32, usize>,
+    /// Lines which aren't calls, e.g. signals and exits
+    pub skipped_lines: usize,
+}
+
+/// Add the calls of the strace or ltrace log at `log_path` to the profile.
+/// Calls on lines without a pid column are put on the main thread of `pid`,
+/// or of the profile's only process.
+pub fn overlay_log_file(
+    profile: &mut Value,
+    log_path: &Path,
+    pid: Option<u32>,
+) -> Result<OverlayStats, Error> {
+    let log = std::fs::read(log_path)?;
+    overlay_log(profile, &String::from_utf8_lossy(&log), pid)
+}
+
+fn overlay_log(profile: &mut Value, log: &str, pid: Option<u32>) -> Result<OverlayStats, Error> {
+    let mut stats = OverlayStats::default();
+    let mut calls = Vec::new();
+    let mut unfinished: HashMap<(Option<u32>, String), usize> = HashMap::new();
+    for line in log.lines() {
+        match parse_line(line) {
+            Some(Line::Call(call)) => calls.push(call),
+            Some(Line::Unfinished(call)) => {
+                unfinished.insert((call.tid, call.name.clone()), calls.len());
+                calls.push(call);
+            }
+            Some(Line::Resumed {
+                tid,
+                name,
+                time,
+                duration,
+                result,
+            }) => {
+                if let Some(index) = unfinished.remove(&(tid, name)) {
+                    let call = &mut calls[index];
+                    call.end = Some(duration.map_or(time, |duration| call.start + duration));
+                    call.result = result;
+                }
+            }
+            None => stats.skipped_lines += 1,
+        }
+    }
+    if calls.is_empty() {
+        return Err(Error::NoCalls);
+    }
+
+    if !profile["shared"]["stringArray"].is_array() {
+        return Err(Error::NoStringTable);
+    }
+    let threads_by_tid = threads_by_tid(profile);
+    let default_tid = pid.or_else(|| single_process_pid(profile));
+    if default_tid.is_none() && calls.iter().any(|call| call.tid.is_none()) {
+        return Err(Error::NoPid);
+    }
+
+    let mut strings = match profile["shared"]["stringArray"].take() {
+        Value::Array(strings) => strings,
+        _ => Vec::new(),
+    };
+    let mut string_indexes: HashMap<String, usize> = HashMap::new();
+    let start_time = profile["meta"]["startTime"].as_f64().unwrap_or(0.0);
+    let category = other_category(profile);
+    add_marker_schema(profile);
+
+    for call in calls {
+        let Some(tid) = call.tid.or(default_tid) else {
+            continue;
+        };
+        let Some(&thread_index) = threads_by_tid.get(&tid) else {
+            *stats.unknown_threads.entry(tid).or_default() += 1;
+            continue;
+        };
+        let name_index = *string_indexes.entry(call.name.clone()).or_insert_with(|| {
+            strings.push(json!(call.name));
+            strings.len() - 1
+        });
+        let start = call.start * 1000.0 - start_time;
+        let end = call.end.map(|end| end * 1000.0 - start_time);
+        let mut data = json!({ "type": MARKER_TYPE, "args": call.args });
+        if let Some(result) = call.result {
+            data["result"] = json!(result);
+        }
+        let markers = &mut profile["threads"][thread_index]["markers"];
+        push_marker(markers, name_index, start, end, category, data);
+        stats.markers += 1;
+    }
+
+    profile["shared"]["stringArray"] = Value::Array(strings);
+    Ok(stats)
+}
+
+/// Parse a line of `strace -ttt` or `ltrace -ttt` output, with or without
+/// `-f` and `-T`.
+fn parse_line(line: &str) -> Option<Line> {
+    let line = line.trim();
+    // "[pid  1234] 1700000000.123456 read(...)" or "1234 1700000000.123456 read(...)"
+    let (tid, rest) = if let Some(rest) = line.strip_prefix("[pid") {
+        let (tid, rest) = rest.split_once(']')?;
+        (Some(tid.trim().parse().ok()?), rest.trim_start())
+    } else {
+        match line.split_once(char::is_whitespace) {
+            Some((tid, rest)) if !tid.contains('.') && tid.parse::<u32>().is_ok() => {
+                (tid.parse().ok(), rest.trim_start())
+            }
+            _ => (None, line),
+        }
+    };
+    let (timestamp, rest) = rest.split_once(char::is_whitespace)?;
+    if !timestamp.contains('.') {
+        return None;
+    }
+    let start: f64 = timestamp.parse().ok()?;
+    let rest = rest.trim_start();
+
+    if let Some(resumed) = rest.strip_prefix("<... ") {
+        let (name, rest) = resumed.split_once(" resumed>")?;
+        let (result, duration) = parse_result(rest);
+        return Some(Line::Resumed {
+            tid,
+            name: name.to_string(),
+            time: start,
+            duration,
+            result,
+        });
+    }
+
+    let (name, rest) = rest.split_once('(')?;
+    let is_name = |c: char| c.is_ascii_alphanumeric() || "_@.:->".contains(c);
+    if name.is_empty() || !name.chars().all(is_name) {
+        return None;
+    }
+    if let Some(args) = rest.strip_suffix("<unfinished ...>") {
+        let args = args.trim_end().trim_end_matches(',');
+        return Some(Line::Unfinished(Call {
+            tid,
+            name: name.to_string(),
+            args: truncate(args),
+            result: None,
+            start,
+            end: None,
+        }));
+    }
+    let (args, result) = match rest.rfind(") = ") {
+        Some(pos) => (&rest[..pos], &rest[pos..]),
+        None => (rest.trim_end_matches(')'), ""),
+    };
+    let (result, duration) = parse_result(result);
+    Some(Line::Call(Call {
+        tid,
+        name: name.to_string(),
+        args: truncate(args),
+        result,
+        start,
+        end: duration.map(|duration| start + duration),
+    }))
+}
+
+/// The end of a call line, after its arguments:
+/// ") = 4096 <0.000012>" -> (Some("4096"), Some(0.000012))
+fn parse_result(rest: &str) -> (Option<String>, Option<f64>) {
+    let mut rest = rest.trim();
+    let mut duration = None;
+    if let Some(start) = rest.rfind(" <").filter(|_| rest.ends_with('>')) {
+        duration = rest[start + 2..rest.len() - 1].parse().ok();
+        if duration.is_some() {
+            rest = rest[..start].trim_end();
+        }
+    }
+    let result = rest
+        .rfind(") = ")
+        .map(|pos| rest[pos + 4..].trim().to_string());
+    (result, duration)
+}
+
+fn truncate(args: &str) -> String {
+    match args.char_indices().nth(MAX_ARGS_LEN) {
+        Some((pos, _)) => format!("{}...", &args[..pos]),
+        None => args.to_string(),
+    }
+}
+
+/// Thread indexes by tid
+fn threads_by_tid(profile: &Value) -> HashMap<u32, usize> {
+    let mut threads = HashMap::new();
+    for (index, thread) in profile["threads"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+    {
+        if let Some(tid) = id(&thread["tid"]) {
+            threads.entry(tid).or_insert(index);
+        }
+    }
+    threads
+}
+
+/// The pid of the profile's process, if it has only one
+fn single_process_pid(profile: &Value) -> Option<u32> {
+    let mut pids = profile["threads"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|thread| id(&thread["pid"]));
+    let pid = pids.next()?;
+    pids.all(|other| other == pid).then_some(pid)
+}
+
+/// Pids and tids are strings or numbers
+fn id(value: &Value) -> Option<u32> {
+    match value {
+        Value::String(id) => id.parse().ok(),
+        _ => value.as_u64().and_then(|id| id.try_into().ok()),
+    }
+}
+
+/// The index of the "Other" category, which is added if there isn't one
+fn other_category(profile: &mut Value) -> usize {
+    if !profile["meta"]["categories"].is_array() {
+        profile["meta"]["categories"] = json!([]);
+    }
+    let categories = profile["meta"]["categories"].as_array_mut().unwrap();
+    if let Some(index) = categories.iter().position(|c| c["name"] == "Other") {
+        return index;
+    }
+    categories.push(json!({ "name": "Other", "color": "grey", "subcategories": ["Other"] }));
+    categories.len() - 1
+}
+
+fn add_marker_schema(profile: &mut Value) {
+    if !profile["meta"]["markerSchema"].is_array() {
+        profile["meta"]["markerSchema"] = json!([]);
+    }
+    let schemas = profile["meta"]["markerSchema"].as_array_mut().unwrap();
+    if schemas.iter().any(|schema| schema["name"] == MARKER_TYPE) {
+        return;
+    }
+    schemas.push(json!({
+        "name": MARKER_TYPE,
+        "display": ["marker-chart", "marker-table"],
+        "chartLabel": "{marker.name}",
+        "tooltipLabel": "{marker.name}({marker.data.args}) = {marker.data.result}",
+        "tableLabel": "{marker.name}({marker.data.args}) = {marker.data.result}",
+        "description": "A call from an strace or ltrace log",
+        "fields": [
+            { "key": "args", "label": "Arguments", "format": "string" },
+            { "key": "result", "label": "Result", "format": "string" }
+        ]
+    }));
+}
+
+/// Append an instant marker, or an interval marker if it has an end
+fn push_marker(
+    markers: &mut Value,
+    name_index: usize,
+    start: f64,
+    end: Option<f64>,
+    category: usize,
+    data: Value,
+) {
+    if !markers.is_object() {
+        *markers = json!({ "length": 0 });
+    }
+    let phase = if end.is_some() { 1 } else { 0 };
+    let row = [
+        ("name", json!(name_index)),
+        ("startTime", json!(start)),
+        ("endTime", json!(end)),
+        ("phase", json!(phase)),
+        ("category", json!(category)),
+        ("data", data),
+    ];
+    let len = markers["length"].as_u64().unwrap_or(0) as usize;
+    for (column, value) in row {
+        if !markers[column].is_array() {
+            markers[column] = Value::Array(vec![Value::Null; len]);
+        }
+        markers[column].as_array_mut().unwrap().push(value);
+    }
+    markers["length"] = json!(len + 1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_line() {
+        let Some(Line::Call(call)) =
+            parse_line("1235  1700000000.500000 read(3, \"abc\", 4096) = 3 <0.000250>")
+        else {
+            panic!("not a call");
+        };
+        assert_eq!(call.tid, Some(1235));
+        assert_eq!(call.name, "read");
+        assert_eq!(call.args, "3, \"abc\", 4096");
+        assert_eq!(call.result.as_deref(), Some("3"));
+        assert!((call.end.unwrap() - 1700000000.50025).abs() < 1e-6);
+
+        let line =
+            "[pid  1236] 1700000000.600000 futex(0x7f00, FUTEX_WAIT, 0, NULL <unfinished ...>";
+        assert!(matches!(parse_line(line), Some(Line::Unfinished(call)) if call.tid == Some(1236)));
+        let line = "1700000000.700000 <... futex resumed>) = 0 <0.100000>";
+        assert_eq!(
+            parse_line(line),
+            Some(Line::Resumed {
+                tid: None,
+                name: "futex".to_string(),
+                time: 1700000000.7,
+                duration: Some(0.1),
+                result: Some("0".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_line("1235 1700000000.8 --- SIGCHLD {si_signo=SIGCHLD} ---"),
+            None
+        );
+        assert_eq!(parse_line("1235 1700000000.9 +++ exited with 0 +++"), None);
+    }
+
+    #[test]
+    fn test_overlay_log() {
+        let thread = |tid: u32, is_main: bool| {
+            json!({
+                "name": "app", "isMainThread": is_main, "pid": "1234", "tid": tid,
+                "markers": { "length": 0, "data": [], "name": [], "startTime": [],
+                             "endTime": [], "phase": [], "category": [] }
+            })
+        };
+        let mut profile = json!({
+            "meta": { "startTime": 1700000000000.0, "categories": [{ "name": "Other" }] },
+            "shared": { "stringArray": ["main"] },
+            "threads": [thread(1234, true), thread(1235, false)]
+        });
+        let log = "\
+1234 1700000000.100000 openat(AT_FDCWD, \"/etc/hosts\", O_RDONLY) = 3 <0.000100>
+1235 1700000000.200000 futex(0x7f00, FUTEX_WAIT, 0, NULL <unfinished ...>
+1234 1700000000.250000 write(1, \"hi\", 2) = 2
+1235 1700000000.300000 <... futex resumed>) = 0
+9999 1700000000.400000 close(3) = 0
+1234 1700000000.500000 +++ exited with 0 +++
+";
+        let stats = overlay_log(&mut profile, log, None).unwrap();
+        assert_eq!(stats.markers, 3);
+        assert_eq!(stats.unknown_threads, BTreeMap::from([(9999, 1)]));
+        assert_eq!(stats.skipped_lines, 1);
+
+        let strings = &profile["shared"]["stringArray"];
+        assert_eq!(strings, &json!(["main", "openat", "write", "futex"]));
+        let main = &profile["threads"][0]["markers"];
+        assert_eq!(main["length"], 2);
+        assert_eq!(main["name"], json!([1, 2]));
+        assert_eq!(main["phase"], json!([1, 0]));
+        assert_eq!(main["endTime"][1], Value::Null);
+        assert_eq!(main["data"][0]["result"], "3");
+        let worker = &profile["threads"][1]["markers"];
+        assert_eq!(worker["name"], json!([3]));
+        let (start, end) = (
+            worker["startTime"][0].as_f64().unwrap(),
+            worker["endTime"][0].as_f64(),
+        );
+        assert!((start - 200.0).abs() < 1e-3 && (end.unwrap() - 300.0).abs() < 1e-3);
+        assert_eq!(profile["meta"]["markerSchema"][0]["name"], MARKER_TYPE);
+    }
+}
@@ -0,0 +1,207 @@
+//! `samply record --adaptive-rate`: lower the sampling frequency while the
+//! recording can't keep up with the events or costs too much CPU, and raise it
+//! back to the requested frequency once things calm down. The frequency is
+//! halved at a time, so that each sample stands for a whole number of samples
+//! at the requested frequency.
+
+use std::time::Duration;
+
+/// How often the frequency is reconsidered
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// The ring buffer fill above which the frequency is lowered, and below which
+/// it may be raised again
+const HIGH_BUFFER_FILL: f64 = 0.5;
+const LOW_BUFFER_FILL: f64 = 0.1;
+
+/// The share of one CPU which samply's own threads may use, and below which
+/// the frequency may be raised again
+const HIGH_CPU_OVERHEAD: f64 = 0.5;
+const LOW_CPU_OVERHEAD: f64 = 0.15;
+
+/// The number of calm windows in a row before the frequency is raised again
+const CALM_WINDOWS_BEFORE_RAISE: u32 = 10;
+
+/// The frequency isn't lowered below this
+const MIN_FREQUENCY: u32 = 10;
+
+/// A change of the sampling frequency, to apply with [`AdaptiveRate::apply`]
+/// once the perf events have it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateChange {
+    pub from: u32,
+    pub to: u32,
+    pub reason: String,
+    shift: u32,
+}
+
+impl RateChange {
+    /// How many samples at the requested frequency each sample stands for
+    pub fn sample_weight(&self) -> i32 {
+        1 << self.shift
+    }
+}
+
+pub struct AdaptiveRate {
+    requested: u32,
+    /// The frequency is `requested >> shift`.
+    shift: u32,
+    window_start: u64,
+    window_start_cpu_time: Option<Duration>,
+    window_start_lost_events: u64,
+    max_buffer_fill: f64,
+    calm_windows: u32,
+    change_count: usize,
+    disabled: bool,
+}
+
+impl AdaptiveRate {
+    /// `now` is a CLOCK_MONOTONIC timestamp in nanoseconds.
+    pub fn new(requested: u32, now: u64, cpu_time: Option<Duration>) -> Self {
+        Self {
+            requested,
+            shift: 0,
+            window_start: now,
+            window_start_cpu_time: cpu_time,
+            window_start_lost_events: 0,
+            max_buffer_fill: 0.0,
+            calm_windows: 0,
+            change_count: 0,
+            disabled: false,
+        }
+    }
+
+    pub fn frequency(&self) -> u32 {
+        self.requested >> self.shift
+    }
+
+    pub fn change_count(&self) -> usize {
+        self.change_count
+    }
+
+    /// Call before the events are consumed, when the buffers are the fullest.
+    pub fn observe_buffer_fill(&mut self, fill: f64) {
+        self.max_buffer_fill = self.max_buffer_fill.max(fill);
+    }
+
+    /// Returns the change of the frequency which the last window calls for,
+    /// once a window has passed.
+    pub fn update(
+        &mut self,
+        now: u64,
+        total_lost_events: u64,
+        cpu_time: Option<Duration>,
+    ) -> Option<RateChange> {
+        let elapsed = now.saturating_sub(self.window_start);
+        if self.disabled || elapsed < WINDOW.as_nanos() as u64 {
+            return None;
+        }
+
+        let cpu_overhead = match (self.window_start_cpu_time, cpu_time) {
+            (Some(start), Some(end)) => {
+                end.saturating_sub(start).as_nanos() as f64 / elapsed as f64
+            }
+            _ => 0.0,
+        };
+        let lost_events = total_lost_events - self.window_start_lost_events;
+        let max_buffer_fill = self.max_buffer_fill;
+
+        self.window_start = now;
+        self.window_start_cpu_time = cpu_time;
+        self.window_start_lost_events = total_lost_events;
+        self.max_buffer_fill = 0.0;
+
+        self.judge_window(max_buffer_fill, lost_events, cpu_overhead)
+    }
+
+    fn judge_window(
+        &mut self,
+        max_buffer_fill: f64,
+        lost_events: u64,
+        cpu_overhead: f64,
+    ) -> Option<RateChange> {
+        let reason = if lost_events > 0 {
+            Some(format!("lost {lost_events} events"))
+        } else if max_buffer_fill > HIGH_BUFFER_FILL {
+            Some(format!(
+                "the ring buffer was {:.0}% full",
+                max_buffer_fill * 100.0
+            ))
+        } else if cpu_overhead > HIGH_CPU_OVERHEAD {
+            Some(format!("samply used {:.0}% of a CPU", cpu_overhead * 100.0))
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            self.calm_windows = 0;
+            if self.requested >> (self.shift + 1) < MIN_FREQUENCY {
+                return None;
+            }
+            return Some(self.change_to(self.shift + 1, reason));
+        }
+
+        if self.shift == 0 || max_buffer_fill > LOW_BUFFER_FILL || cpu_overhead > LOW_CPU_OVERHEAD {
+            self.calm_windows = 0;
+            return None;
+        }
+        self.calm_windows += 1;
+        if self.calm_windows < CALM_WINDOWS_BEFORE_RAISE {
+            return None;
+        }
+        self.calm_windows = 0;
+        let reason = format!("calm for {CALM_WINDOWS_BEFORE_RAISE} seconds");
+        Some(self.change_to(self.shift - 1, reason))
+    }
+
+    fn change_to(&self, shift: u32, reason: String) -> RateChange {
+        RateChange {
+            from: self.frequency(),
+            to: self.requested >> shift,
+            reason,
+            shift,
+        }
+    }
+
+    /// The perf events have the frequency of `change` now.
+    pub fn apply(&mut self, change: &RateChange) {
+        self.shift = change.shift;
+        self.change_count += 1;
+    }
+
+    /// Stop changing the frequency, e.g. because the perf events reject it.
+    pub fn disable(&mut self) {
+        self.disabled = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lower_and_raise() {
+        let mut rate = AdaptiveRate::new(1000, 0, None);
+        let change = rate.judge_window(0.8, 0, 0.1).unwrap();
+        assert_eq!((change.from, change.to), (1000, 500));
+        assert_eq!(change.sample_weight(), 2);
+        rate.apply(&change);
+        let change = rate.judge_window(0.2, 3, 0.1).unwrap();
+        assert_eq!((change.from, change.to), (500, 250));
+        rate.apply(&change);
+
+        for _ in 1..CALM_WINDOWS_BEFORE_RAISE {
+            assert_eq!(rate.judge_window(0.05, 0, 0.05), None);
+        }
+        let change = rate.judge_window(0.05, 0, 0.05).unwrap();
+        assert_eq!((change.from, change.to), (250, 500));
+        assert_eq!(change.sample_weight(), 2);
+
+        let mut rate = AdaptiveRate::new(40, 0, None);
+        for _ in 0..2 {
+            let change = rate.judge_window(0.0, 0, 0.9).unwrap();
+            rate.apply(&change);
+        }
+        assert_eq!(rate.frequency(), 10);
+        assert_eq!(rate.judge_window(0.0, 0, 0.9), None);
+    }
+}
@@ -0,0 +1,54 @@
+//! The environment of a launched command for `samply record --jit`, which asks
+//! the JIT runtimes in it to describe their JIT code: in `/tmp/perf-<pid>.map`
+//! files, which are read when recording stops, or in jitdump files, which are
+//! read as the runtime appends to them once it has mapped them. Either way,
+//! JIT frames get function names instead of addresses.
+//!
+//! LuaJIT has no such switch; a LuaJIT which is built to write perf maps is
+//! picked up without `--jit`.
+
+use std::ffi::OsString;
+
+/// For Node.js: a perf map of the JIT functions, and a frame per interpreted
+/// function instead of one for the whole interpreter.
+const NODE_OPTIONS: &str = "--perf-basic-prof --interpreted-frames-native-stack";
+
+/// For HotSpot JVMs, from JDK 17: a perf map of the JIT functions when the JVM exits.
+const JAVA_TOOL_OPTIONS: &str = "-XX:+UnlockDiagnosticVMOptions -XX:+DumpPerfMapAtExit";
+
+/// Add the variables which turn on the perf maps and jitdump files of Node.js,
+/// the JVM and .NET. Options which are already set are kept.
+pub fn add_jit_env_vars(env_vars: &mut Vec<(OsString, OsString)>) {
+    append_options(env_vars, "NODE_OPTIONS", NODE_OPTIONS);
+    append_options(env_vars, "JAVA_TOOL_OPTIONS", JAVA_TOOL_OPTIONS);
+    // Both the perf map and the jitdump file.
+    if !env_vars
+        .iter()
+        .any(|(name, _)| name == "DOTNET_PerfMapEnabled")
+        && std::env::var_os("DOTNET_PerfMapEnabled").is_none()
+    {
+        env_vars.push(("DOTNET_PerfMapEnabled".into(), "1".into()));
+    }
+}
+
+/// Append `options` to the variable `name`, as it is set in `env_vars` or in
+/// samply's own environment, which the command inherits.
+fn append_options(env_vars: &mut Vec<(OsString, OsString)>, name: &str, options: &str) {
+    let index = env_vars.iter().position(|(var_name, _)| var_name == name);
+    let current = match index {
+        Some(index) => Some(env_vars[index].1.clone()),
+        None => std::env::var_os(name),
+    };
+    let value = match current {
+        Some(mut value) if !value.is_empty() => {
+            value.push(" ");
+            value.push(options);
+            value
+        }
+        _ => options.into(),
+    };
+    match index {
+        Some(index) => env_vars[index].1 = value,
+        None => env_vars.push((name.into(), value)),
+    }
+}
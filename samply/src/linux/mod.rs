@@ -1,7 +1,11 @@
+mod adaptive_rate;
+mod jit_runtimes;
 mod perf_event;
 mod perf_group;
 mod proc_maps;
 mod process;
 pub mod profiler;
 mod sorter;
+mod stdin_commands;
 mod sys;
+mod uprobe;
@@ -1,6 +1,7 @@
 use std::cell::RefCell;
 use std::cmp::max;
 use std::collections::BinaryHeap;
+use std::ffi::CStr;
 use std::ops::Range;
 use std::os::unix::io::RawFd;
 use std::rc::Rc;
@@ -86,6 +87,7 @@ pub struct Perf {
     fd: RawFd,
     position: u64,
     parse_info: RecordParseInfo,
+    event_source: EventSource,
 }
 
 impl Drop for Perf {
@@ -148,11 +150,62 @@ fn next_raw_event(
 pub enum EventSource {
     HwCpuCycles,
     SwCpuClock,
+    /// The sched:sched_switch tracepoint, with its id from tracefs. Every switch
+    /// is sampled, with the stack of the thread which is switched out.
+    SchedSwitch(u64),
+    /// A uprobe on the entry of malloc. Every call is sampled.
+    MallocUprobe(Uprobe),
+    /// A hardware event from `samply record --event`, sampled at the
+    /// frequency of the sampling event.
+    HwEvent(HwEvent),
+}
+
+/// A generic hardware event, by its name in perf, e.g. "cache-misses".
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct HwEvent {
+    pub name: &'static str,
+    /// The PERF_COUNT_HW_* id of the event
+    pub config: u64,
+}
+
+impl EventSource {
+    /// Whether the event is sampled at the requested frequency, rather than
+    /// on every hit.
+    pub fn samples_by_frequency(&self) -> bool {
+        !matches!(
+            self,
+            EventSource::SchedSwitch(_) | EventSource::MallocUprobe(_)
+        )
+    }
+}
+
+impl HwEvent {
+    pub fn from_name(name: &str) -> Option<HwEvent> {
+        let (name, config) = match name {
+            "instructions" => ("instructions", PERF_COUNT_HW_INSTRUCTIONS),
+            "cache-references" => ("cache-references", PERF_COUNT_HW_CACHE_REFERENCES),
+            "cache-misses" => ("cache-misses", PERF_COUNT_HW_CACHE_MISSES),
+            "branch-instructions" => ("branch-instructions", PERF_COUNT_HW_BRANCH_INSTRUCTIONS),
+            "branch-misses" => ("branch-misses", PERF_COUNT_HW_BRANCH_MISSES),
+            _ => return None,
+        };
+        Some(HwEvent { name, config })
+    }
+}
+
+/// A uprobe on the instruction at `offset` in the file at `path`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Uprobe {
+    /// The type of the uprobe PMU, from sysfs
+    pub pmu_type: u32,
+    pub path: &'static CStr,
+    pub offset: u64,
 }
 
 #[derive(Clone, Debug)]
 pub struct PerfBuilder {
-    pid: u32,
+    /// None for all processes
+    pid: Option<u32>,
     cpu: Option<u32>,
     frequency: u64,
     stack_size: u32,
@@ -167,7 +220,14 @@ pub struct PerfBuilder {
 
 impl PerfBuilder {
     pub fn pid(mut self, pid: u32) -> Self {
-        self.pid = pid;
+        self.pid = Some(pid);
+        self
+    }
+
+    /// Sample every process, on the CPU given with `only_cpu`. This requires
+    /// root, or `/proc/sys/kernel/perf_event_paranoid` to be at most `0`.
+    pub fn all_processes(mut self) -> Self {
+        self.pid = None;
         self
     }
 
@@ -228,7 +288,7 @@ impl PerfBuilder {
     }
 
     pub fn open(self) -> io::Result<Perf> {
-        let pid = self.pid;
+        let pid = self.pid.map_or(-1, |pid| pid as pid_t);
         let cpu = self.cpu.map(|cpu| cpu as i32).unwrap_or(-1);
         let frequency = self.frequency;
         let stack_size = self.stack_size;
@@ -251,7 +311,9 @@ impl PerfBuilder {
         //     start_disabled
         // );
 
-        let max_sample_rate = Perf::max_sample_rate();
+        let samples_every_hit = !event_source.samples_by_frequency();
+
+        let max_sample_rate = Perf::max_sample_rate().filter(|_| !samples_every_hit);
         if let Some(max_sample_rate) = max_sample_rate {
             // debug!("Maximum sample rate: {}", max_sample_rate);
             if frequency > max_sample_rate {
@@ -275,6 +337,13 @@ impl PerfBuilder {
             ));
         }
 
+        if pid == -1 && cpu == -1 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "you can't sample all processes and run on all cpus at the same time",
+            ));
+        }
+
         assert_eq!(mem::size_of::<PerfEventMmapPage>(), 1088);
 
         if cfg!(target_arch = "x86_64") {
@@ -295,6 +364,20 @@ impl PerfBuilder {
                 attr.kind = PERF_TYPE_SOFTWARE;
                 attr.config = PERF_COUNT_SW_CPU_CLOCK;
             }
+            EventSource::SchedSwitch(tracepoint_id) => {
+                attr.kind = PERF_TYPE_TRACEPOINT;
+                attr.config = tracepoint_id;
+            }
+            EventSource::MallocUprobe(uprobe) => {
+                // config1 is the path, config2 the offset in the file.
+                attr.kind = uprobe.pmu_type;
+                attr.bp_addr_or_config = uprobe.path.as_ptr() as u64;
+                attr.bp_len_or_config = uprobe.offset;
+            }
+            EventSource::HwEvent(event) => {
+                attr.kind = PERF_TYPE_HARDWARE;
+                attr.config = event.config;
+            }
         }
 
         attr.sample_type = PERF_SAMPLE_IP
@@ -313,18 +396,33 @@ impl PerfBuilder {
 
         attr.sample_regs_user = reg_mask;
         attr.sample_stack_user = stack_size;
-        attr.sample_period_or_freq = frequency;
         attr.clock_id = libc::CLOCK_MONOTONIC;
 
-        attr.flags = PERF_ATTR_FLAG_DISABLED
-            | PERF_ATTR_FLAG_MMAP
-            | PERF_ATTR_FLAG_MMAP2
-            | PERF_ATTR_FLAG_MMAP_DATA
-            | PERF_ATTR_FLAG_COMM
-            | PERF_ATTR_FLAG_FREQ
-            | PERF_ATTR_FLAG_TASK
-            | PERF_ATTR_FLAG_SAMPLE_ID_ALL
-            | PERF_ATTR_FLAG_USE_CLOCKID;
+        attr.flags =
+            PERF_ATTR_FLAG_DISABLED | PERF_ATTR_FLAG_SAMPLE_ID_ALL | PERF_ATTR_FLAG_USE_CLOCKID;
+
+        if samples_every_hit {
+            // Sample every hit.
+            attr.sample_period_or_freq = 1;
+        } else {
+            attr.sample_period_or_freq = frequency;
+            attr.flags |= PERF_ATTR_FLAG_FREQ;
+        }
+        // The mmap, comm and task records of the extra events already come
+        // from the sampling event, so they're not asked for twice.
+        if matches!(
+            event_source,
+            EventSource::HwCpuCycles | EventSource::SwCpuClock
+        ) {
+            attr.flags |= PERF_ATTR_FLAG_MMAP
+                | PERF_ATTR_FLAG_MMAP2
+                | PERF_ATTR_FLAG_MMAP_DATA
+                | PERF_ATTR_FLAG_COMM
+                | PERF_ATTR_FLAG_TASK;
+            if gather_context_switches {
+                attr.flags |= PERF_ATTR_FLAG_CONTEX_SWITCH;
+            }
+        }
 
         if self.enable_on_exec {
             attr.flags |= PERF_ATTR_FLAG_ENABLE_ON_EXEC;
@@ -338,11 +436,7 @@ impl PerfBuilder {
             attr.flags |= PERF_ATTR_FLAG_INHERIT;
         }
 
-        if gather_context_switches {
-            attr.flags |= PERF_ATTR_FLAG_CONTEX_SWITCH;
-        }
-
-        let fd = sys_perf_event_open(&attr, pid as pid_t, cpu as _, -1, PERF_FLAG_FD_CLOEXEC);
+        let fd = sys_perf_event_open(&attr, pid, cpu as _, -1, PERF_FLAG_FD_CLOEXEC);
         if fd == -1 {
             let err = io::Error::last_os_error();
 
@@ -409,6 +503,7 @@ impl PerfBuilder {
             fd,
             position: 0,
             parse_info,
+            event_source,
         };
 
         if !start_disabled {
@@ -425,9 +520,38 @@ impl Perf {
         data.trim().parse::<u64>().ok()
     }
 
+    /// The type of a dynamic PMU, e.g. of "uprobe", from sysfs.
+    pub fn pmu_type(name: &str) -> io::Result<u32> {
+        let data = std::fs::read_to_string(format!("/sys/bus/event_source/devices/{name}/type"))?;
+        data.trim()
+            .parse::<u32>()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// The id of a tracepoint, e.g. of "sched:sched_switch", from tracefs.
+    pub fn tracepoint_id(subsystem: &str, name: &str) -> io::Result<u64> {
+        let paths = [
+            format!("/sys/kernel/tracing/events/{subsystem}/{name}/id"),
+            format!("/sys/kernel/debug/tracing/events/{subsystem}/{name}/id"),
+        ];
+        let mut last_error = None;
+        for path in paths {
+            match std::fs::read_to_string(path) {
+                Ok(data) => {
+                    return data
+                        .trim()
+                        .parse::<u64>()
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap())
+    }
+
     pub fn build() -> PerfBuilder {
         PerfBuilder {
-            pid: 0,
+            pid: Some(0),
             cpu: None,
             frequency: 0,
             stack_size: 0,
@@ -447,6 +571,34 @@ impl Perf {
         assert!(result != -1);
     }
 
+    /// Change the sampling frequency of an event which samples by frequency.
+    pub fn set_frequency(&mut self, frequency: u64) -> io::Result<()> {
+        let result = unsafe {
+            libc::ioctl(
+                self.fd,
+                PERF_EVENT_IOC_PERIOD as _,
+                &frequency as *const u64,
+            )
+        };
+        if result == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn event_source(&self) -> EventSource {
+        self.event_source
+    }
+
+    /// The share of the ring buffer which is taken by events that weren't
+    /// consumed yet, from 0 to 1.
+    #[inline]
+    pub fn buffer_fill(&self) -> f64 {
+        let (head, tail) = unsafe { (read_head(self.buffer), read_tail(self.buffer)) };
+        head.wrapping_sub(tail) as f64 / self.size as f64
+    }
+
     #[inline]
     pub fn are_events_pending(&self) -> bool {
         let head = unsafe { read_head(self.buffer) };
@@ -526,6 +678,7 @@ pub struct EventRef {
     prev_position: u64,
     position: u64,
     parse_info: RecordParseInfo,
+    event_source: EventSource,
 }
 
 impl fmt::Debug for EventRef {
@@ -553,6 +706,11 @@ impl EventRef {
 
         self.event_location.get(buffer, self.parse_info)
     }
+
+    /// The event which this event record comes from
+    pub fn event_source(&self) -> EventSource {
+        self.event_source
+    }
 }
 
 pub struct EventIter<'a> {
@@ -582,6 +740,7 @@ impl Iterator for EventIter<'_> {
             prev_position,
             position: perf.position,
             parse_info: self.perf.parse_info,
+            event_source: self.perf.event_source,
         })
     }
 }
@@ -10,7 +10,7 @@ use linux_perf_data::linux_perf_event_reader::get_record_timestamp;
 use mio::unix::SourceFd;
 use mio::{Events, Interest, Poll, Token};
 
-use super::perf_event::{EventRef, EventSource, Perf};
+use super::perf_event::{EventRef, EventSource, Perf, PerfBuilder};
 use super::sorter::EventSorter;
 
 struct StoppedProcess(u32);
@@ -72,6 +72,9 @@ pub struct PerfGroup {
     stack_size: u32,
     regs_mask: u64,
     event_source: EventSource,
+    /// The events which are recorded along with the samples, e.g. the
+    /// sched_switch tracepoint for off-CPU stacks
+    extra_event_sources: Vec<EventSource>,
     stopped_processes: Vec<StoppedProcess>,
 }
 
@@ -97,8 +100,23 @@ pub enum AttachMode {
     StopAttachEnableResume,
 }
 
+/// The processes which a perf group samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PerfTarget {
+    /// A process and the processes it launches
+    Process(u32, AttachMode),
+    /// Every process on the machine, with events per CPU
+    AllProcesses,
+}
+
 impl PerfGroup {
-    pub fn new(frequency: u32, stack_size: u32, regs_mask: u64, event_source: EventSource) -> Self {
+    pub fn new(
+        frequency: u32,
+        stack_size: u32,
+        regs_mask: u64,
+        event_source: EventSource,
+        extra_event_sources: Vec<EventSource>,
+    ) -> Self {
         PerfGroup {
             event_sorter: EventSorter::new(),
             members: Default::default(),
@@ -108,23 +126,53 @@ impl PerfGroup {
             stack_size,
             event_source,
             regs_mask,
+            extra_event_sources,
             stopped_processes: Vec::new(),
         }
     }
 
     pub fn open(
-        pid: u32,
+        target: PerfTarget,
         frequency: u32,
         stack_size: u32,
         event_source: EventSource,
+        extra_event_sources: Vec<EventSource>,
         regs_mask: u64,
-        attach_mode: AttachMode,
     ) -> Result<Self, io::Error> {
-        let mut group = PerfGroup::new(frequency, stack_size, regs_mask, event_source);
-        group.open_process(pid, attach_mode)?;
+        let mut group = PerfGroup::new(
+            frequency,
+            stack_size,
+            regs_mask,
+            event_source,
+            extra_event_sources,
+        );
+        match target {
+            PerfTarget::Process(pid, attach_mode) => group.open_process(pid, attach_mode)?,
+            PerfTarget::AllProcesses => group.open_all_processes()?,
+        }
         Ok(group)
     }
 
+    /// Open events on every CPU which sample whatever runs there. New
+    /// processes are sampled without further setup.
+    pub fn open_all_processes(&mut self) -> Result<(), io::Error> {
+        let mut perf_events = Vec::new();
+        for cpu in 0..num_cpus::get() as u32 {
+            let builder = Perf::build()
+                .all_processes()
+                .only_cpu(cpu)
+                .frequency(self.frequency as u64)
+                .sample_user_stack(self.stack_size)
+                .sample_user_regs(self.regs_mask)
+                .sample_kernel()
+                .gather_context_switches()
+                .event_source(self.event_source)
+                .start_disabled();
+            self.open_events(builder, Some(cpu), &mut perf_events)?;
+        }
+        self.add_members(perf_events)
+    }
+
     pub fn open_process(&mut self, pid: u32, attach_mode: AttachMode) -> Result<(), io::Error> {
         if attach_mode == AttachMode::StopAttachEnableResume {
             self.stopped_processes.push(StoppedProcess::new(pid)?);
@@ -150,9 +198,7 @@ impl PerfGroup {
                 builder = builder.enable_on_exec();
             }
 
-            let perf = builder.open()?;
-
-            perf_events.push((Some(cpu), perf));
+            self.open_events(builder, Some(cpu), &mut perf_events)?;
         }
 
         if cpu_count * (threads.len() + 1) >= 1000 {
@@ -169,9 +215,7 @@ impl PerfGroup {
                 if attach_mode == AttachMode::AttachWithEnableOnExec {
                     builder = builder.enable_on_exec();
                 }
-                let perf = builder.open()?;
-
-                perf_events.push((None, perf));
+                self.open_events(builder, None, &mut perf_events)?;
             }
         } else {
             for cpu in 0..cpu_count as u32 {
@@ -190,13 +234,15 @@ impl PerfGroup {
                     if attach_mode == AttachMode::AttachWithEnableOnExec {
                         builder = builder.enable_on_exec();
                     }
-                    let perf = builder.open()?;
-
-                    perf_events.push((Some(cpu), perf));
+                    self.open_events(builder, Some(cpu), &mut perf_events)?;
                 }
             }
         }
 
+        self.add_members(perf_events)
+    }
+
+    fn add_members(&mut self, perf_events: Vec<(Option<u32>, Perf)>) -> Result<(), io::Error> {
         for (_cpu, perf) in perf_events {
             let fd = perf.fd();
             self.members.insert(fd, Member::new(perf));
@@ -210,6 +256,22 @@ impl PerfGroup {
         Ok(())
     }
 
+    /// Open the sampling event of `builder`, and the extra events for the same
+    /// thread and CPU.
+    fn open_events(
+        &self,
+        builder: PerfBuilder,
+        cpu: Option<u32>,
+        perf_events: &mut Vec<(Option<u32>, Perf)>,
+    ) -> Result<(), io::Error> {
+        perf_events.push((cpu, builder.clone().open()?));
+        for &event_source in &self.extra_event_sources {
+            let builder = builder.clone().event_source(event_source);
+            perf_events.push((cpu, builder.open()?));
+        }
+        Ok(())
+    }
+
     pub fn is_empty(&self) -> bool {
         self.members.is_empty()
     }
@@ -222,6 +284,30 @@ impl PerfGroup {
         self.stopped_processes.clear();
     }
 
+    pub fn frequency(&self) -> u32 {
+        self.frequency
+    }
+
+    /// The highest ring buffer fill of the members, from 0 to 1.
+    pub fn max_buffer_fill(&self) -> f64 {
+        self.members
+            .values()
+            .map(|member| member.buffer_fill())
+            .fold(0.0, f64::max)
+    }
+
+    /// Change the frequency of the events which sample by frequency, including
+    /// those of the processes which are opened later.
+    pub fn set_frequency(&mut self, frequency: u32) -> Result<(), io::Error> {
+        self.frequency = frequency;
+        for member in self.members.values_mut() {
+            if member.event_source().samples_by_frequency() {
+                member.set_frequency(frequency as u64)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn wait(&mut self) {
         for member in self.members.values() {
             if member.are_events_pending() {
@@ -1,6 +1,6 @@
 use std::collections::BTreeMap;
 use std::ffi::{CString, OsStr, OsString};
-use std::os::fd::OwnedFd;
+use std::os::fd::{AsRawFd, OwnedFd};
 use std::os::raw::c_char;
 use std::os::unix::prelude::OsStrExt;
 
@@ -16,10 +16,12 @@ pub struct SuspendedLaunchedProcess {
 }
 
 impl SuspendedLaunchedProcess {
+    /// With `null_stdin`, the command's stdin is /dev/null instead of samply's.
     pub fn launch_in_suspended_state(
         command_name: &OsStr,
         command_args: &[OsString],
         env_vars: &[(OsString, OsString)],
+        null_stdin: bool,
     ) -> std::io::Result<Self> {
         let argv: Vec<CString> = std::iter::once(command_name)
             .chain(command_args.iter().map(|s| s.as_os_str()))
@@ -49,6 +51,11 @@ impl SuspendedLaunchedProcess {
             None
         };
 
+        let stdin = match null_stdin {
+            true => Some(OwnedFd::from(std::fs::File::open("/dev/null")?)),
+            false => None,
+        };
+
         let (resume_rp, resume_sp) = nix::unistd::pipe2(nix::fcntl::OFlag::O_CLOEXEC).unwrap();
         let (execerr_rp, execerr_sp) = nix::unistd::pipe2(nix::fcntl::OFlag::O_CLOEXEC).unwrap();
 
@@ -57,7 +64,7 @@ impl SuspendedLaunchedProcess {
                 // std::panic::always_abort();
                 drop(resume_sp);
                 drop(execerr_rp);
-                Self::run_child(resume_rp, execerr_sp, &argv, envp)
+                Self::run_child(resume_rp, execerr_sp, stdin, &argv, envp)
             }
             nix::unistd::ForkResult::Parent { child } => {
                 drop(resume_rp);
@@ -132,6 +139,7 @@ impl SuspendedLaunchedProcess {
     fn run_child(
         recv_end_of_resume_pipe: OwnedFd,
         send_end_of_execerr_pipe: OwnedFd,
+        stdin: Option<OwnedFd>,
         argv: &[*const c_char],
         envp: Option<CStringArray>,
     ) -> ! {
@@ -154,6 +162,9 @@ impl SuspendedLaunchedProcess {
                 }
                 Ok(_) => {
                     // The parent signaled that we can start. Exec!
+                    if let Some(stdin) = &stdin {
+                        unsafe { libc::dup2(stdin.as_raw_fd(), libc::STDIN_FILENO) };
+                    }
                     if let Some(envp) = envp {
                         let _ = unsafe { execvpe(argv[0], argv.as_ptr(), envp.as_ptr()) };
                     } else {
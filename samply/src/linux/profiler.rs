@@ -3,6 +3,7 @@ use std::ops::Deref;
 use std::os::unix::process::ExitStatusExt;
 use std::path::Path;
 use std::process::ExitStatus;
+use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, SystemTime};
 
@@ -14,18 +15,25 @@ use linux_perf_data::linux_perf_event_reader::{
 use nix::sys::wait::WaitStatus;
 use tokio::sync::oneshot;
 
-use super::perf_event::EventSource;
-use super::perf_group::{AttachMode, PerfGroup};
+use super::adaptive_rate::AdaptiveRate;
+use super::jit_runtimes;
+use super::perf_event::{EventSource, HwEvent, Perf};
+use super::perf_group::{AttachMode, PerfGroup, PerfTarget};
 use super::proc_maps;
 use super::process::SuspendedLaunchedProcess;
+use super::stdin_commands::{self, StdinCommand};
+use super::uprobe;
 use crate::linux_shared::vdso::VdsoObject;
 use crate::linux_shared::{
     ConvertRegs, Converter, EventInterpretation, MmapRangeOrVec, OffCpuIndicator,
 };
 use crate::shared::ctrl_c::CtrlC;
+use crate::shared::live_profile::LiveProfile;
+use crate::shared::overhead::{process_cpu_time, OverheadMeter};
 use crate::shared::prop_types::{
     ProcessLaunchProps, ProfileCreationProps, RecordingMode, RecordingProps,
 };
+use crate::shared::ring_buffer::RingBuffer;
 
 #[cfg(target_arch = "x86_64")]
 pub type ConvertRegsNative = crate::linux_shared::ConvertRegsX86_64;
@@ -40,13 +48,19 @@ pub fn run(
 ) -> Result<(Profile, ExitStatus), ()> {
     let process_launch_props = match recording_mode {
         RecordingMode::All => {
-            // TODO: Implement, by sudo launching a helper process which opens cpu-wide perf events
-            eprintln!("Error: Profiling all processes is currently not supported on Linux.");
-            eprintln!("You can profile processes which you launch via samply, or attach to a single process.");
-            std::process::exit(1)
+            let profile = start_profiling_target(
+                PerfTarget::AllProcesses,
+                recording_props,
+                profile_creation_props,
+            );
+            return Ok((profile, ExitStatus::from_raw(0)));
         }
         RecordingMode::Pid(pid) => {
-            let profile = start_profiling_pid(pid, recording_props, profile_creation_props);
+            let profile = start_profiling_target(
+                PerfTarget::Process(pid, AttachMode::StopAttachEnableResume),
+                recording_props,
+                profile_creation_props,
+            );
             return Ok((profile, ExitStatus::from_raw(0)));
         }
         RecordingMode::Launch(process_launch_props) => process_launch_props,
@@ -69,6 +83,9 @@ pub fn run(
             env_vars.push(("DOTNET_PerfMapEnabled".into(), "2".into()));
         }
     }
+    if recording_props.jit {
+        jit_runtimes::add_jit_env_vars(&mut env_vars);
+    }
 
     // Ignore Ctrl+C while the subcommand is running. The signal still reaches the process
     // under observation while we continue to record it. (ctrl+c will send the SIGINT signal
@@ -77,9 +94,15 @@ pub fn run(
 
     // Start a new process for the launched command and get its pid.
     // The command will not start running until we tell it to.
-    let process =
-        SuspendedLaunchedProcess::launch_in_suspended_state(&command_name, &args, &env_vars)
-            .unwrap();
+    // With --stdin-commands, samply reads stdin, so the command can't.
+    let stdin_commands = recording_props.stdin_commands;
+    let process = SuspendedLaunchedProcess::launch_in_suspended_state(
+        &command_name,
+        &args,
+        &env_vars,
+        stdin_commands,
+    )
+    .unwrap();
     let pid = process.pid();
 
     // Create a channel for the observer thread to notify the main thread once
@@ -91,7 +114,15 @@ pub fn run(
 
     // Launch the observer thread. This thread will manage the perf events.
     let interval = recording_props.interval;
-    let time_limit = recording_props.time_limit;
+    let off_cpu = recording_props.off_cpu;
+    let alloc = recording_props.alloc;
+    let events = recording_props.events.clone();
+    let stop_after = recording_props.stop_after();
+    let delay = recording_props.delay;
+    let ring_buffer = recording_props.ring_buffer;
+    let adaptive_rate = recording_props.adaptive_rate;
+    let included_threads = recording_props.included_threads.clone();
+    let live = recording_props.live.clone();
     let initial_exec_name = command_name.to_string_lossy().to_string();
     let initial_cmdline: Vec<String> = std::iter::once(initial_exec_name.clone())
         .chain(args.iter().map(|arg| arg.to_string_lossy().to_string()))
@@ -103,6 +134,7 @@ pub fn run(
     let initial_exec_name_and_cmdline = (initial_exec_name, initial_cmdline);
     let observer_thread = thread::spawn(move || {
         let mut converter = make_converter(interval, profile_creation_props);
+        converter.set_included_threads(included_threads);
 
         // Wait for the initial pid to profile.
         let SamplerRequest::StartProfilingAnotherProcess(pid, attach_mode) =
@@ -112,7 +144,14 @@ pub fn run(
         };
 
         // Create the perf events, setting ENABLE_ON_EXEC.
-        let perf_group = init_profiler(interval, pid, attach_mode, &mut converter);
+        let perf_group = init_profiler(
+            interval,
+            off_cpu,
+            alloc,
+            &events,
+            PerfTarget::Process(pid, attach_mode),
+            &mut converter,
+        );
 
         // Tell the main thread to tell the child process to begin executing.
         profile_another_pid_reply_sender.send(true).unwrap();
@@ -128,7 +167,12 @@ pub fn run(
         run_profiler(
             perf_group,
             converter,
-            time_limit,
+            stop_after,
+            delay,
+            ring_buffer,
+            adaptive_rate,
+            stdin_commands,
+            live,
             profile_another_pid_request_receiver,
             profile_another_pid_reply_sender,
             stop_receiver,
@@ -183,19 +227,21 @@ pub fn run(
             break;
         }
         eprintln!("Running iteration {i} of {iteration_count}...");
-        let process =
-            SuspendedLaunchedProcess::launch_in_suspended_state(&command_name, &args, &env_vars)
-                .unwrap();
+        let process = SuspendedLaunchedProcess::launch_in_suspended_state(
+            &command_name,
+            &args,
+            &env_vars,
+            stdin_commands,
+        )
+        .unwrap();
         let pid = process.pid();
 
         // Tell the sampler to start profiling another pid, and wait for it to signal us to go ahead.
-        profile_another_pid_request_sender
-            .send(SamplerRequest::StartProfilingAnotherProcess(
-                pid,
-                AttachMode::AttachWithEnableOnExec,
-            ))
-            .unwrap();
-        let succeeded = profile_another_pid_reply_receiver.recv().unwrap();
+        // The sampler is gone if it has reached the time limit.
+        let sent = profile_another_pid_request_sender.send(
+            SamplerRequest::StartProfilingAnotherProcess(pid, AttachMode::AttachWithEnableOnExec),
+        );
+        let succeeded = sent.is_ok() && profile_another_pid_reply_receiver.recv() == Ok(true);
         if !succeeded {
             break;
         }
@@ -217,9 +263,9 @@ pub fn run(
         wait_status = process.wait().expect("couldn't wait for child");
     }
 
-    profile_another_pid_request_sender
-        .send(SamplerRequest::StopProfilingOncePerfEventsExhausted)
-        .unwrap();
+    // This fails if the sampler has already stopped at the time limit.
+    let _ = profile_another_pid_request_sender
+        .send(SamplerRequest::StopProfilingOncePerfEventsExhausted);
 
     // The launched subprocess is done. From now on, we want to terminate if the user presses Ctrl+C.
     ctrl_c_receiver.close();
@@ -239,8 +285,9 @@ pub fn run(
     Ok((profile, exit_status))
 }
 
-fn start_profiling_pid(
-    pid: u32,
+/// Record an existing process, or all processes, until Ctrl+C or the time limit.
+fn start_profiling_target(
+    target: PerfTarget,
     recording_props: RecordingProps,
     profile_creation_props: ProfileCreationProps,
 ) -> Profile {
@@ -257,14 +304,18 @@ fn start_profiling_pid(
     let observer_thread = thread::spawn({
         move || {
             let interval = recording_props.interval;
-            let time_limit = recording_props.time_limit;
+            let off_cpu = recording_props.off_cpu;
+            let alloc = recording_props.alloc;
+            let events = recording_props.events.clone();
+            let stop_after = recording_props.stop_after();
+            let delay = recording_props.delay;
+            let ring_buffer = recording_props.ring_buffer;
+            let adaptive_rate = recording_props.adaptive_rate;
+            let stdin_commands = recording_props.stdin_commands;
             let mut converter = make_converter(interval, profile_creation_props);
-            let SamplerRequest::StartProfilingAnotherProcess(pid, attach_mode) =
-                profile_another_pid_request_receiver.recv().unwrap()
-            else {
-                panic!("The first message should be a StartProfilingAnotherProcess")
-            };
-            let perf_group = init_profiler(interval, pid, attach_mode, &mut converter);
+            converter.set_included_threads(recording_props.included_threads.clone());
+            let perf_group =
+                init_profiler(interval, off_cpu, alloc, &events, target, &mut converter);
 
             // Tell the main thread that we are now executing.
             profile_another_pid_reply_sender.send(true).unwrap();
@@ -272,7 +323,12 @@ fn start_profiling_pid(
             run_profiler(
                 perf_group,
                 converter,
-                time_limit,
+                stop_after,
+                delay,
+                ring_buffer,
+                adaptive_rate,
+                stdin_commands,
+                recording_props.live,
                 profile_another_pid_request_receiver,
                 profile_another_pid_reply_sender,
                 ctrl_c_receiver,
@@ -283,17 +339,16 @@ fn start_profiling_pid(
 
     // We're on the main thread here and the observer thread has just been launched.
 
-    // Request profiling of our process and wait for profiler initialization.
-    profile_another_pid_request_sender
-        .send(SamplerRequest::StartProfilingAnotherProcess(
-            pid,
-            AttachMode::StopAttachEnableResume,
-        ))
-        .unwrap();
+    // Wait for profiler initialization.
     let _ = profile_another_pid_reply_receiver.recv().unwrap();
 
     // Now that we know that profiler initialization has succeeded, tell the user about it.
-    eprintln!("Recording process with PID {pid} until Ctrl+C...");
+    match target {
+        PerfTarget::Process(pid, _) => {
+            eprintln!("Recording process with PID {pid} until Ctrl+C...")
+        }
+        PerfTarget::AllProcesses => eprintln!("Recording all processes until Ctrl+C..."),
+    }
 
     profile_another_pid_request_sender
         .send(SamplerRequest::StopProfilingOncePerfEventsExhausted)
@@ -369,8 +424,10 @@ fn make_converter(
 
 fn init_profiler(
     interval: Duration,
-    pid: u32,
-    attach_mode: AttachMode,
+    off_cpu: bool,
+    alloc: bool,
+    events: &[String],
+    target: PerfTarget,
     converter: &mut Converter<
         framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>,
     >,
@@ -383,27 +440,69 @@ fn init_profiler(
 
     let frequency = (1_000_000_000 / interval_nanos) as u32;
     let stack_size = 32000;
-    let regs_mask = ConvertRegsNative::regs_mask();
+    let mut regs_mask = ConvertRegsNative::regs_mask();
+
+    let mut extra_event_sources = Vec::new();
+    if off_cpu {
+        match Perf::tracepoint_id("sched", "sched_switch") {
+            Ok(id) => extra_event_sources.push(EventSource::SchedSwitch(id)),
+            Err(error) => {
+                eprintln!("Failed to find the sched:sched_switch tracepoint: {error}");
+                eprintln!("--off-cpu reads it from tracefs, which usually requires root.");
+                std::process::exit(1);
+            }
+        }
+    }
+    if alloc {
+        // All processes are probed in the system's libc, which samply uses too.
+        let pid = match target {
+            PerfTarget::Process(pid, _) => pid,
+            PerfTarget::AllProcesses => std::process::id(),
+        };
+        match uprobe::malloc_uprobe(pid) {
+            Ok(uprobe) => extra_event_sources.push(EventSource::MallocUprobe(uprobe)),
+            Err(error) => {
+                eprintln!("Failed to find malloc for --alloc: {error}");
+                std::process::exit(1);
+            }
+        }
+        // The allocation size is malloc's argument.
+        regs_mask |= 1 << ConvertRegsNative::FIRST_ARGUMENT_REG;
+    }
+    for name in events {
+        match HwEvent::from_name(name) {
+            Some(event) => extra_event_sources.push(EventSource::HwEvent(event)),
+            None => {
+                eprintln!("Unknown hardware event for --event: {name}");
+                std::process::exit(1);
+            }
+        }
+    }
 
     let perf = PerfGroup::open(
-        pid,
+        target,
         frequency,
         stack_size,
         EventSource::HwCpuCycles,
+        extra_event_sources.clone(),
         regs_mask,
-        attach_mode,
     );
 
     if let Err(error) = &perf {
         if error.kind() == std::io::ErrorKind::PermissionDenied {
+            // Sampling all processes needs CPU-wide events, which level 1 doesn't allow.
+            let max_level = match target {
+                PerfTarget::Process(..) => 1,
+                PerfTarget::AllProcesses => 0,
+            };
             if let Some(level) = paranoia_level() {
-                if level > 1 {
+                if level > max_level {
                     eprintln!();
                     eprintln!(
                         "'/proc/sys/kernel/perf_event_paranoid' is currently set to {level}."
                     );
                     eprintln!("In order for samply to work with a non-root user, this level needs");
-                    eprintln!("to be set to 1 or lower.");
+                    eprintln!("to be set to {max_level} or lower.");
                     eprintln!("You can execute the following command and then try again:");
                     eprintln!("    echo '-1' | sudo tee /proc/sys/kernel/perf_event_paranoid");
                     eprintln!();
@@ -423,51 +522,104 @@ fn init_profiler(
             // The "Hardware CPU cycles" event is not supported in some contexts, for example in VMs.
             // Try a different event type.
             let perf = PerfGroup::open(
-                pid,
+                target,
                 frequency,
                 stack_size,
                 EventSource::SwCpuClock,
+                extra_event_sources,
                 regs_mask,
-                attach_mode,
             );
             match perf {
                 Ok(perf) => perf, // Success!
                 Err(error) => {
                     eprintln!("Failed to start profiling: {error}");
+                    if off_cpu || alloc {
+                        eprintln!("--off-cpu and --alloc record tracepoints and uprobes, which");
+                        eprintln!("require root, or perf_event_paranoid set to -1.");
+                    }
+                    if !events.is_empty() {
+                        eprintln!("--event needs hardware performance counters, which virtual");
+                        eprintln!("machines often don't have.");
+                    }
                     std::process::exit(1);
                 }
             }
         }
     };
 
-    let (exe_name, cmdline) = get_process_cmdline(pid).expect("Couldn't read process cmdline");
-    let comm_data = std::fs::read(format!("/proc/{pid}/comm")).expect("Couldn't read process comm");
+    let vdso_file_id = VdsoObject::shared_instance_for_this_process()
+        .map(|vdso| Mmap2FileId::BuildId(vdso.build_id().to_owned()));
+    match target {
+        PerfTarget::Process(pid, _) => {
+            register_existing_process(pid, vdso_file_id.as_ref(), converter)
+                .expect("Couldn't read the process from /proc");
+        }
+        PerfTarget::AllProcesses => {
+            for pid in existing_pids() {
+                // The process may have exited since /proc was listed.
+                let _ = register_existing_process(pid, vdso_file_id.as_ref(), converter);
+            }
+        }
+    }
+
+    // eprintln!("Enabling perf events...");
+    match target {
+        PerfTarget::Process(_, AttachMode::AttachWithEnableOnExec) => {
+            // The perf event will get enabled automatically once the forked child process execs.
+        }
+        PerfTarget::Process(_, AttachMode::StopAttachEnableResume) | PerfTarget::AllProcesses => {
+            perf.enable()
+        }
+    }
+
+    perf
+}
+
+/// The pids of the processes which are running now, from /proc
+fn existing_pids() -> Vec<u32> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_string_lossy().parse().ok())
+        .collect()
+}
+
+/// Tell the converter about a process which was running before recording
+/// started: its name, threads and mappings, which there are no perf events for.
+fn register_existing_process(
+    pid: u32,
+    vdso_file_id: Option<&Mmap2FileId>,
+    converter: &mut Converter<
+        framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>,
+    >,
+) -> std::io::Result<()> {
+    let comm_data = std::fs::read(format!("/proc/{pid}/comm"))?;
     let length = memchr::memchr(b'\0', &comm_data).unwrap_or(comm_data.len());
-    let comm_name = std::str::from_utf8(&comm_data[..length])
-        .unwrap()
-        .trim_end();
+    let comm_name = String::from_utf8_lossy(&comm_data[..length]);
+    let comm_name = comm_name.trim_end();
+    // Kernel threads have no command line.
+    let (exe_name, cmdline) =
+        get_process_cmdline(pid).unwrap_or_else(|_| (comm_name.to_string(), Vec::new()));
     converter.register_existing_process(pid as i32, comm_name, &exe_name, cmdline);
 
     // TODO: Gather threads / processes recursively, here and in PerfGroup setup.
-    for thread_entry in std::fs::read_dir(format!("/proc/{pid}/task"))
-        .unwrap()
-        .flatten()
-    {
-        let tid: u32 = thread_entry.file_name().to_string_lossy().parse().unwrap();
+    for thread_entry in std::fs::read_dir(format!("/proc/{pid}/task"))?.flatten() {
+        let Ok(tid) = thread_entry.file_name().to_string_lossy().parse::<u32>() else {
+            continue;
+        };
         let comm_path = format!("/proc/{pid}/task/{tid}/comm");
         if let Ok(buffer) = std::fs::read(comm_path) {
             let length = memchr::memchr(b'\0', &buffer).unwrap_or(buffer.len());
-            let name = std::str::from_utf8(&buffer[..length]).unwrap().trim_end();
-            converter.register_existing_thread(pid as i32, tid as i32, name);
+            let name = String::from_utf8_lossy(&buffer[..length]);
+            converter.register_existing_thread(pid as i32, tid as i32, name.trim_end());
         }
     }
 
-    let maps = read_string_lossy(format!("/proc/{pid}/maps")).expect("couldn't read proc maps");
+    let maps = read_string_lossy(format!("/proc/{pid}/maps"))?;
     let maps = proc_maps::parse(&maps);
 
-    let vdso_file_id = VdsoObject::shared_instance_for_this_process()
-        .map(|vdso| Mmap2FileId::BuildId(vdso.build_id().to_owned()));
-
     for region in maps {
         let mut protection = 0;
         if region.is_read {
@@ -487,7 +639,7 @@ fn init_profiler(
             flags |= libc::MAP_PRIVATE;
         }
 
-        let file_id = match (region.name.deref(), vdso_file_id.as_ref()) {
+        let file_id = match (region.name.deref(), vdso_file_id) {
             ("[vdso]", Some(vdso_file_id)) => vdso_file_id.clone(),
             _ => Mmap2FileId::InodeAndVersion(Mmap2InodeAndVersion {
                 major: region.major,
@@ -514,15 +666,7 @@ fn init_profiler(
         );
     }
 
-    // eprintln!("Enabling perf events...");
-    match attach_mode {
-        AttachMode::StopAttachEnableResume => perf.enable(),
-        AttachMode::AttachWithEnableOnExec => {
-            // The perf event will get enabled automatically once the forked child process execs.
-        }
-    }
-
-    perf
+    Ok(())
 }
 
 enum SamplerRequest {
@@ -536,7 +680,12 @@ fn run_profiler(
     mut converter: Converter<
         framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>,
     >,
-    _time_limit: Option<Duration>,
+    stop_after: Option<Duration>,
+    delay: Option<Duration>,
+    ring_buffer: Option<Duration>,
+    adaptive_rate: bool,
+    stdin_commands: bool,
+    live: Option<Arc<LiveProfile>>,
     more_processes_request_receiver: Receiver<SamplerRequest>,
     more_processes_reply_sender: Sender<bool>,
     mut stop_receiver: oneshot::Receiver<()>,
@@ -544,15 +693,46 @@ fn run_profiler(
 ) -> Profile {
     // eprintln!("Running...");
 
+    let overhead_meter = OverheadMeter::start();
     let mut should_stop_profiling_once_perf_events_exhausted = false;
     let mut pending_lost_events = 0;
     let mut total_lost_events = 0;
     let mut last_timestamp = 0;
+    let mut ring_buffer = ring_buffer.map(RingBuffer::new);
+    // Perf event timestamps are CLOCK_MONOTONIC timestamps, too.
+    let start = get_monotonic_timestamp();
+    let delay_end = start + delay.map_or(0, |delay| delay.as_nanos() as u64);
+    let stop_at = stop_after.map(|stop_after| start + stop_after.as_nanos() as u64);
+    let mut adaptive_rate =
+        adaptive_rate.then(|| AdaptiveRate::new(perf.frequency(), start, process_cpu_time()));
+    let stdin_commands =
+        stdin_commands.then(|| stdin_commands::spawn_reader(get_monotonic_timestamp));
     loop {
         if stop_receiver.try_recv().is_ok() {
             break;
         }
 
+        if stop_at.is_some_and(|stop_at| get_monotonic_timestamp() >= stop_at) {
+            eprintln!("Time limit reached, stopping recording.");
+            break;
+        }
+
+        let mut stop_command = false;
+        for (timestamp, command) in stdin_commands
+            .iter()
+            .flat_map(|receiver| receiver.try_iter())
+        {
+            match command {
+                StdinCommand::Mark(name) => converter.handle_mark(timestamp, &name),
+                StdinCommand::Phase(name) => converter.handle_phase(timestamp, name.as_deref()),
+                StdinCommand::Stop => stop_command = true,
+            }
+        }
+        if stop_command {
+            eprintln!("Received the stop command, stopping recording.");
+            break;
+        }
+
         match more_processes_request_receiver.try_recv() {
             Ok(SamplerRequest::StartProfilingAnotherProcess(another_pid, attach_mode)) => {
                 match perf.open_process(another_pid, attach_mode) {
@@ -599,7 +779,12 @@ fn run_profiler(
             break;
         }
 
+        if let Some(adaptive_rate) = &mut adaptive_rate {
+            adaptive_rate.observe_buffer_fill(perf.max_buffer_fill());
+        }
+
         perf.consume_events(&mut |event_ref| {
+            let event_source = event_ref.event_source();
             let record = event_ref.get();
             let parsed_record = record.parse().unwrap();
             // debug!("Recording parsed_record: {:#?}", parsed_record);
@@ -614,13 +799,20 @@ fn run_profiler(
             }
 
             match parsed_record {
-                EventRecord::Sample(e) => {
-                    converter.handle_main_event_sample::<ConvertRegsNative>(&e);
-                    /*
-                    } else if interpretation.sched_switch_attr_index == Some(attr_index) {
-                        converter.handle_sched_switch_sample::<C>(e);
-                    }*/
-                }
+                EventRecord::Sample(e) => match event_source {
+                    EventSource::SchedSwitch(_) => {
+                        converter.handle_sched_switch_sample::<ConvertRegsNative>(&e);
+                    }
+                    EventSource::MallocUprobe(_) => {
+                        converter.handle_allocation_sample::<ConvertRegsNative>(&e);
+                    }
+                    EventSource::HwEvent(event) => {
+                        converter.handle_hw_event_sample::<ConvertRegsNative>(&e, event.name);
+                    }
+                    EventSource::HwCpuCycles | EventSource::SwCpuClock => {
+                        converter.handle_main_event_sample::<ConvertRegsNative>(&e);
+                    }
+                },
                 EventRecord::Fork(e) => {
                     converter.handle_fork(e);
                 }
@@ -677,6 +869,44 @@ fn run_profiler(
             }
         });
 
+        if let Some(adaptive_rate) = &mut adaptive_rate {
+            let now = get_monotonic_timestamp();
+            if let Some(change) = adaptive_rate.update(now, total_lost_events, process_cpu_time()) {
+                match perf.set_frequency(change.to) {
+                    Ok(()) => {
+                        eprintln!(
+                            "Changed the sampling frequency from {} Hz to {} Hz: {}.",
+                            change.from, change.to, change.reason
+                        );
+                        adaptive_rate.apply(&change);
+                        converter.handle_sample_rate_change(
+                            now,
+                            change.from,
+                            change.to,
+                            &change.reason,
+                            change.sample_weight(),
+                        );
+                    }
+                    Err(error) => {
+                        eprintln!("Failed to change the sampling frequency: {error}");
+                        adaptive_rate.disable();
+                    }
+                }
+            }
+        }
+
+        if let Some(ring_buffer) = &mut ring_buffer {
+            if let Some(start) = ring_buffer.drop_before(last_timestamp) {
+                converter.drop_samples_before(start);
+            }
+        }
+
+        if let Some(live) = &live {
+            if live.is_snapshot_due() {
+                live.publish(converter.snapshot());
+            }
+        }
+
         perf.wait();
     }
 
@@ -684,7 +914,37 @@ fn run_profiler(
         eprintln!("Lost {total_lost_events} events.");
     }
 
-    converter.finish()
+    // The last phase lasts until the end of the recording.
+    converter.handle_phase(get_monotonic_timestamp(), None);
+
+    if let Some(adaptive_rate) = &adaptive_rate {
+        if adaptive_rate.change_count() > 0 {
+            eprintln!(
+                "Changed the sampling frequency {} times, ending at {} Hz.",
+                adaptive_rate.change_count(),
+                adaptive_rate.frequency()
+            );
+        }
+    }
+
+    if delay.is_some() {
+        converter.drop_samples_before(delay_end);
+    }
+
+    let mut profile = converter.finish();
+    profile.set_profiler_overhead(overhead_meter.finish(total_lost_events));
+    profile
+}
+
+fn get_monotonic_timestamp() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts);
+    }
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
 }
 
 pub fn read_string_lossy<P: AsRef<Path>>(path: P) -> std::io::Result<String> {
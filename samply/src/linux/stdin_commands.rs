@@ -0,0 +1,89 @@
+//! `samply record --stdin-commands`: the commands which a script that drives
+//! the profiled program writes to samply's stdin, one per line, to annotate
+//! the timeline or stop recording.
+
+use std::io::BufRead;
+use std::thread;
+
+use crossbeam_channel::Receiver;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StdinCommand {
+    /// `mark <name>`: an instant marker
+    Mark(String),
+    /// `phase <name>`: the start of a phase, which lasts until the next one;
+    /// `phase` without a name ends the current phase.
+    Phase(Option<String>),
+    /// `stop`: stop recording, like Ctrl+C
+    Stop,
+}
+
+impl StdinCommand {
+    /// Parse a line; `Ok(None)` for an empty line.
+    pub fn parse(line: &str) -> Result<Option<Self>, String> {
+        let line = line.trim();
+        let (command, name) = match line.split_once(char::is_whitespace) {
+            Some((command, name)) => (command, Some(name.trim_start())),
+            None => (line, None),
+        };
+        match (command, name) {
+            ("", _) => Ok(None),
+            ("mark", Some(name)) => Ok(Some(StdinCommand::Mark(name.to_string()))),
+            ("mark", None) => Err("mark needs a name".to_string()),
+            ("phase", name) => Ok(Some(StdinCommand::Phase(name.map(ToOwned::to_owned)))),
+            ("stop", None) => Ok(Some(StdinCommand::Stop)),
+            _ => Err(format!(
+                "unknown command {line:?}; use 'mark <name>', 'phase <name>' or 'stop'"
+            )),
+        }
+    }
+}
+
+/// Read commands from stdin on a thread, until stdin is closed. Each command
+/// comes with the `clock` timestamp of when it was read.
+pub fn spawn_reader(clock: fn() -> u64) -> Receiver<(u64, StdinCommand)> {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let timestamp = clock();
+            match StdinCommand::parse(&line) {
+                Ok(Some(command)) => {
+                    if sender.send((timestamp, command)).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => {}
+                Err(error) => eprintln!("Ignoring stdin command: {error}"),
+            }
+        }
+    });
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            StdinCommand::parse("mark  request 5 sent\n"),
+            Ok(Some(StdinCommand::Mark("request 5 sent".to_string())))
+        );
+        assert_eq!(
+            StdinCommand::parse("phase warmup"),
+            Ok(Some(StdinCommand::Phase(Some("warmup".to_string()))))
+        );
+        assert_eq!(
+            StdinCommand::parse("phase"),
+            Ok(Some(StdinCommand::Phase(None)))
+        );
+        assert_eq!(StdinCommand::parse("stop"), Ok(Some(StdinCommand::Stop)));
+        assert_eq!(StdinCommand::parse("  "), Ok(None));
+        assert!(StdinCommand::parse("mark").is_err());
+        assert!(StdinCommand::parse("pause now").is_err());
+    }
+}
@@ -51,6 +51,11 @@ pub const PERF_ATTR_FLAG_USE_CLOCKID: u64 = flag!(25);
 pub const PERF_ATTR_FLAG_CONTEX_SWITCH: u64 = flag!(26);
 
 pub const PERF_COUNT_HW_CPU_CYCLES: u64 = 0;
+pub const PERF_COUNT_HW_INSTRUCTIONS: u64 = 1;
+pub const PERF_COUNT_HW_CACHE_REFERENCES: u64 = 2;
+pub const PERF_COUNT_HW_CACHE_MISSES: u64 = 3;
+pub const PERF_COUNT_HW_BRANCH_INSTRUCTIONS: u64 = 4;
+pub const PERF_COUNT_HW_BRANCH_MISSES: u64 = 5;
 pub const PERF_COUNT_HW_REF_CPU_CYCLES: u64 = 9;
 
 pub const PERF_COUNT_SW_CPU_CLOCK: u64 = 0;
@@ -222,6 +227,7 @@ mod ioctl {
         pub const IOC_SIZEBITS: c_ulong = 14;
         pub const IOC_DIRBITS: c_ulong = 2;
         pub const IOC_NONE: c_ulong = 0;
+        pub const IOC_WRITE: c_ulong = 1;
     }
 
     #[cfg(any(
@@ -236,6 +242,7 @@ mod ioctl {
         pub const IOC_SIZEBITS: c_ulong = 13;
         pub const IOC_DIRBITS: c_ulong = 3;
         pub const IOC_NONE: c_ulong = 1;
+        pub const IOC_WRITE: c_ulong = 4;
     }
 
     pub use self::arch::*;
@@ -263,8 +270,15 @@ macro_rules! io {
     };
 }
 
+macro_rules! iow {
+    ($kind:expr, $nr:expr, $size:expr) => {
+        ioc!(ioctl::IOC_WRITE, $kind, $nr, $size as c_ulong)
+    };
+}
+
 pub const PERF_EVENT_IOC_ENABLE: c_ulong = io!(b'$', 0);
 pub const PERF_EVENT_IOC_DISABLE: c_ulong = io!(b'$', 1);
+pub const PERF_EVENT_IOC_PERIOD: c_ulong = iow!(b'$', 4, std::mem::size_of::<u64>());
 
 #[repr(C)]
 pub struct PerfEventAttr {
@@ -0,0 +1,60 @@
+//! The uprobe which `samply record --alloc` records allocations with: the
+//! entry of malloc in the libc of the profiled process. Allocators which
+//! replace malloc in the executable itself, e.g. jemalloc, aren't probed.
+
+use std::ffi::CString;
+use std::io;
+
+use object::{Object, ObjectSegment, ObjectSymbol};
+
+use super::perf_event::{Perf, Uprobe};
+use super::proc_maps;
+
+/// A uprobe on malloc in the libc which is mapped into the process `pid`.
+/// A launched process is probed before its exec, so this is samply's own
+/// libc then, which is the system's.
+pub fn malloc_uprobe(pid: u32) -> io::Result<Uprobe> {
+    let pmu_type = Perf::pmu_type("uprobe")?;
+    let maps = std::fs::read_to_string(format!("/proc/{pid}/maps"))?;
+    let path = proc_maps::parse(&maps)
+        .into_iter()
+        .map(|region| region.name)
+        .find(|name| is_libc(name))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "libc is not mapped"))?;
+    let offset = symbol_file_offset(&path, "malloc")?;
+    let path = CString::new(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    Ok(Uprobe {
+        pmu_type,
+        // The path needs to outlive the perf events of processes which are
+        // attached to later, so it's leaked; there's one per recording.
+        path: Box::leak(path.into_boxed_c_str()),
+        offset,
+    })
+}
+
+/// Whether a mapped file is glibc's or musl's libc, e.g. "/lib/x86_64-linux-gnu/libc.so.6"
+fn is_libc(path: &str) -> bool {
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    file_name.starts_with("libc.so") || file_name.starts_with("libc-2.")
+}
+
+/// The offset of the exported function `name` in the ELF file at `path`
+fn symbol_file_offset(path: &str, name: &str) -> io::Result<u64> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let data = std::fs::read(path)?;
+    let file = object::File::parse(&data[..]).map_err(|e| invalid(format!("{path}: {e}")))?;
+    let address = file
+        .dynamic_symbols()
+        .find(|symbol| symbol.name() == Ok(name))
+        .map(|symbol| symbol.address())
+        .ok_or_else(|| invalid(format!("{path} doesn't export {name}")))?;
+    file.segments()
+        .find_map(|segment| {
+            let (file_offset, file_size) = segment.file_range();
+            let start = segment.address();
+            (start..start + file_size)
+                .contains(&address)
+                .then(|| file_offset + (address - start))
+        })
+        .ok_or_else(|| invalid(format!("{name} isn't in a segment of {path}")))
+}
@@ -2,8 +2,8 @@ use framehop::aarch64::UnwindRegsAarch64;
 use framehop::x86_64::UnwindRegsX86_64;
 use linux_perf_data::linux_perf_event_reader;
 use linux_perf_event_reader::constants::{
-    PERF_REG_ARM64_LR, PERF_REG_ARM64_PC, PERF_REG_ARM64_SP, PERF_REG_ARM64_X29, PERF_REG_X86_BP,
-    PERF_REG_X86_IP, PERF_REG_X86_SP,
+    PERF_REG_ARM64_LR, PERF_REG_ARM64_PC, PERF_REG_ARM64_SP, PERF_REG_ARM64_X0, PERF_REG_ARM64_X29,
+    PERF_REG_X86_BP, PERF_REG_X86_DI, PERF_REG_X86_IP, PERF_REG_X86_SP,
 };
 use linux_perf_event_reader::Regs;
 
@@ -13,6 +13,10 @@ pub trait ConvertRegs {
 
     #[allow(unused)]
     fn regs_mask() -> u64;
+
+    /// The register with the first integer argument at the entry of a function
+    #[allow(unused)]
+    const FIRST_ARGUMENT_REG: u64;
 }
 
 pub struct ConvertRegsX86_64;
@@ -29,6 +33,8 @@ impl ConvertRegs for ConvertRegsX86_64 {
     fn regs_mask() -> u64 {
         (1 << PERF_REG_X86_IP) | (1 << PERF_REG_X86_SP) | (1 << PERF_REG_X86_BP)
     }
+
+    const FIRST_ARGUMENT_REG: u64 = PERF_REG_X86_DI;
 }
 
 pub struct ConvertRegsAarch64;
@@ -49,4 +55,6 @@ impl ConvertRegs for ConvertRegsAarch64 {
             | (1 << PERF_REG_ARM64_SP)
             | (1 << PERF_REG_ARM64_X29)
     }
+
+    const FIRST_ARGUMENT_REG: u64 = PERF_REG_ARM64_X0;
 }
@@ -39,16 +39,18 @@ use super::mmap_range_or_vec::MmapRangeOrVec;
 use super::pe_mappings::{PeMappings, SuspectedPeMapping};
 use super::processes::Processes;
 use super::rss_stat::{RssStat, MM_ANONPAGES, MM_FILEPAGES, MM_SHMEMPAGES, MM_SWAPENTS};
+use super::signal_deliver::{signal_name, SignalDeliver};
 use super::svma_file_range::compute_vma_bias;
 use super::vdso::VdsoObject;
 use crate::shared::context_switch::{ContextSwitchHandler, OffCpuSampleGroup};
+use crate::shared::included_threads::IncludedThreads;
 use crate::shared::jit_category_manager::JitCategoryManager;
 use crate::shared::lib_mappings::{AndroidArtInfo, LibMappingInfo};
 use crate::shared::per_cpu::Cpus;
-use crate::shared::process_name::make_process_name;
+use crate::shared::process_name::{make_process_name, NameRules};
 use crate::shared::process_sample_data::{
-    OtherEventMarker, RssStatMarker, RssStatMember, SchedSwitchMarkerOnCpuTrack,
-    SchedSwitchMarkerOnThreadTrack,
+    AnnotationMarker, OtherEventMarker, RssStatMarker, RssStatMember, SampleRateMarker,
+    SchedSwitchMarkerOnCpuTrack, SchedSwitchMarkerOnThreadTrack, SignalMarker,
 };
 use crate::shared::prop_types::ProfileCreationProps;
 use crate::shared::synthetic_jit_library::SyntheticJitLibrary;
@@ -84,9 +86,26 @@ where
     pe_mappings: PeMappings,
     jit_category_manager: JitCategoryManager,
     arg_count_to_include_in_process_name: usize,
+    name_rules: NameRules,
     cpus: Option<Cpus>,
     stack_scratch: Vec<StackFrame>,
 
+    /// The weight of each main event sample: how many samples at the
+    /// requested frequency it stands for, after `samply record
+    /// --adaptive-rate` lowered the frequency.
+    sample_weight: i32,
+
+    /// The track of the markers from samply itself, created on first use
+    samply_thread: Option<ThreadHandle>,
+
+    /// The start timestamp and name of the phase from `samply record
+    /// --stdin-commands` which hasn't ended yet
+    current_phase: Option<(u64, String)>,
+
+    /// The threads whose samples are converted, from `samply record
+    /// --include-thread` and `--exclude-thread`.
+    included_threads: IncludedThreads,
+
     /// Whether repeated frames at the base of the stack should be folded
     /// into one frame.
     fold_recursive_prefix: bool,
@@ -212,8 +231,13 @@ where
             fold_recursive_prefix: profile_creation_props.fold_recursive_prefix,
             arg_count_to_include_in_process_name: profile_creation_props
                 .arg_count_to_include_in_process_name,
+            name_rules: profile_creation_props.name_rules.clone(),
             cpus,
             stack_scratch: Vec::new(),
+            sample_weight: 1,
+            samply_thread: None,
+            current_phase: None,
+            included_threads: IncludedThreads::default(),
             call_chain_return_addresses_are_preadjusted,
             should_emit_jit_markers: profile_creation_props.should_emit_jit_markers,
             should_emit_cswitch_markers: profile_creation_props.should_emit_cswitch_markers,
@@ -234,6 +258,16 @@ where
         profile
     }
 
+    /// A copy of the profile with the samples so far, for the snapshots of
+    /// `samply record --live` and `samply import --follow`. The conversion
+    /// goes on.
+    pub fn snapshot(&self) -> Profile {
+        let mut profile = self.profile.clone();
+        self.processes
+            .snapshot(&mut profile, &self.unresolved_stacks);
+        profile
+    }
+
     pub fn set_profile_name(&mut self, profile_name: &str) {
         self.profile.set_product(profile_name);
     }
@@ -242,6 +276,33 @@ where
         self.profile.set_os_name(os_name);
     }
 
+    /// Drop the samples from before `timestamp`, for the ring buffer mode
+    /// of `samply record`.
+    #[allow(unused)] // Only used when recording on Linux
+    pub fn drop_samples_before(&mut self, timestamp: u64) {
+        self.processes.drop_samples_before(timestamp);
+    }
+
+    /// Only convert the samples of the threads in `included_threads`, for
+    /// `samply record --include-thread` and `--exclude-thread`.
+    #[allow(unused)] // Only used when recording on Linux
+    pub fn set_included_threads(&mut self, included_threads: IncludedThreads) {
+        self.included_threads = included_threads;
+    }
+
+    /// Whether the samples of the thread `tid` are left out, going by the
+    /// thread's name so far. Checked before unwinding, which is the expensive
+    /// part of a sample.
+    fn is_thread_excluded(&mut self, pid: i32, tid: i32) -> bool {
+        if self.included_threads.is_all() {
+            return false;
+        }
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        !self
+            .included_threads
+            .should_include(process.threads.thread_name(tid))
+    }
+
     pub fn handle_main_event_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
         &mut self,
         e: &SampleRecord,
@@ -252,6 +313,9 @@ where
             // Ignore samples in the idle thread.
             return;
         }
+        if self.is_thread_excluded(pid, tid) {
+            return;
+        }
         let timestamp = e
             .timestamp
             .expect("Can't handle samples without timestamps");
@@ -327,7 +391,7 @@ where
             timestamp,
             stack_index,
             cpu_delta,
-            1,
+            self.sample_weight,
             None,
         );
 
@@ -362,7 +426,7 @@ where
                 timestamp,
                 stack_index,
                 cpu_delta,
-                1,
+                self.sample_weight,
                 Some(label_frame),
             );
 
@@ -378,18 +442,195 @@ where
                 timestamp,
                 stack_index,
                 CpuDelta::ZERO,
-                1,
+                self.sample_weight,
                 Some(label_frame),
             );
         }
     }
 
+    /// The sampling frequency of the main event was changed during the
+    /// recording, by `samply record --adaptive-rate`. The later samples count
+    /// `sample_weight` times, so that sample counts stay comparable across
+    /// the change, and the change is marked on a "samply" track.
+    #[allow(unused)] // Only used when recording on Linux
+    pub fn handle_sample_rate_change(
+        &mut self,
+        timestamp: u64,
+        from_hz: u32,
+        to_hz: u32,
+        reason: &str,
+        sample_weight: i32,
+    ) {
+        self.sample_weight = sample_weight;
+        let thread = self.samply_thread();
+        let timestamp = timestamp.max(self.timestamp_converter.reference_raw);
+        let marker = SampleRateMarker {
+            from_hz,
+            to_hz,
+            reason: self.profile.handle_for_string(reason),
+        };
+        self.profile.add_marker(
+            thread,
+            MarkerTiming::Instant(self.timestamp_converter.convert_time(timestamp)),
+            marker,
+        );
+    }
+
+    /// `mark <name>` from `samply record --stdin-commands`: an instant marker
+    /// on the "samply" track.
+    #[allow(unused)] // Only used when recording on Linux
+    pub fn handle_mark(&mut self, timestamp: u64, name: &str) {
+        let thread = self.samply_thread();
+        let timestamp = timestamp.max(self.timestamp_converter.reference_raw);
+        let marker = AnnotationMarker {
+            name: self.profile.handle_for_string(name),
+            kind: self.profile.handle_for_string("mark"),
+        };
+        self.profile.add_marker(
+            thread,
+            MarkerTiming::Instant(self.timestamp_converter.convert_time(timestamp)),
+            marker,
+        );
+    }
+
+    /// `phase <name>` from `samply record --stdin-commands`: the current phase
+    /// ends and the phase `name` starts, if any. Each phase is an interval
+    /// marker on the "samply" track.
+    #[allow(unused)] // Only used when recording on Linux
+    pub fn handle_phase(&mut self, timestamp: u64, name: Option<&str>) {
+        let timestamp = timestamp.max(self.timestamp_converter.reference_raw);
+        if let Some((start, current)) = self.current_phase.take() {
+            let thread = self.samply_thread();
+            let marker = AnnotationMarker {
+                name: self.profile.handle_for_string(&current),
+                kind: self.profile.handle_for_string("phase"),
+            };
+            self.profile.add_marker(
+                thread,
+                MarkerTiming::Interval(
+                    self.timestamp_converter.convert_time(start),
+                    self.timestamp_converter.convert_time(timestamp),
+                ),
+                marker,
+            );
+        }
+        self.current_phase = name.map(|name| (timestamp, name.to_string()));
+    }
+
+    fn samply_thread(&mut self) -> ThreadHandle {
+        *self.samply_thread.get_or_insert_with(|| {
+            let start_time = self
+                .timestamp_converter
+                .convert_time(self.timestamp_converter.reference_raw);
+            let process = self.profile.add_process("samply", 0, start_time);
+            self.profile.add_thread(process, 0, start_time, true)
+        })
+    }
+
+    /// A sample of the uprobe on malloc's entry, from `samply record --alloc`:
+    /// an allocation of the size in the first argument register.
+    #[allow(unused)] // Only used when recording on Linux
+    pub fn handle_allocation_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+    ) {
+        let pid = e.pid.expect("Can't handle samples without pids");
+        let timestamp = e
+            .timestamp
+            .expect("Can't handle samples without timestamps");
+        let Some(bytes) = e
+            .user_regs
+            .as_ref()
+            .and_then(|regs| regs.get(C::FIRST_ARGUMENT_REG))
+        else {
+            return;
+        };
+        if e.tid.is_some_and(|tid| self.is_thread_excluded(pid, tid)) {
+            return;
+        }
+
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        let stack = &mut self.stack_scratch;
+        Self::get_sample_stack::<C>(
+            e,
+            &process.unwinder,
+            &mut self.cache,
+            stack,
+            self.fold_recursive_prefix,
+            self.call_chain_return_addresses_are_preadjusted,
+        );
+
+        let stack_index = self.unresolved_stacks.convert(stack.iter().rev().cloned());
+        let profile_timestamp = self.timestamp_converter.convert_time(timestamp);
+        process.unresolved_samples.add_allocation(
+            process.threads.main_thread.profile_thread,
+            process.profile_process,
+            profile_timestamp,
+            timestamp,
+            stack_index,
+            bytes as i64,
+        );
+    }
+
+    /// A sample of a hardware event from `samply record --event`, which goes
+    /// to the event's track of the thread, weighted by the number of events
+    /// since the previous sample.
+    #[allow(unused)] // Only used when recording on Linux
+    pub fn handle_hw_event_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+        event: &'static str,
+    ) {
+        let pid = e.pid.expect("Can't handle samples without pids");
+        let tid = e.tid.expect("Can't handle samples without tids");
+        if tid == 0 {
+            // Ignore samples in the idle thread.
+            return;
+        }
+        let timestamp = e
+            .timestamp
+            .expect("Can't handle samples without timestamps");
+        if self.is_thread_excluded(pid, tid) {
+            return;
+        }
+        let count = e.period.unwrap_or(1).min(i32::MAX as u64) as i32;
+
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        let stack = &mut self.stack_scratch;
+        Self::get_sample_stack::<C>(
+            e,
+            &process.unwinder,
+            &mut self.cache,
+            stack,
+            self.fold_recursive_prefix,
+            self.call_chain_return_addresses_are_preadjusted,
+        );
+
+        let track = process
+            .threads
+            .get_event_track(tid, event, &mut self.profile);
+        let stack_index = self.unresolved_stacks.convert(stack.iter().rev().cloned());
+        let profile_timestamp = self.timestamp_converter.convert_time(timestamp);
+        process.unresolved_samples.add_sample(
+            track,
+            profile_timestamp,
+            timestamp,
+            stack_index,
+            CpuDelta::from_nanos(0),
+            count,
+            None,
+        );
+    }
+
     pub fn handle_sched_switch_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
         &mut self,
         e: &SampleRecord,
     ) {
         let pid = e.pid.expect("Can't handle samples without pids");
         let tid = e.tid.expect("Can't handle samples without tids");
+        if self.is_thread_excluded(pid, tid) {
+            return;
+        }
         let process = self.processes.get_by_pid(pid, &mut self.profile);
         process.check_jitdump(
             &mut self.jit_category_manager,
@@ -542,6 +783,70 @@ where
         );
     }
 
+    /// A signal delivery, from the signal:signal_deliver tracepoint. The stack
+    /// is where the thread was when the signal interrupted it.
+    pub fn handle_signal_deliver_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+    ) {
+        let pid = e.pid.expect("Can't handle samples without pids");
+        let Some(raw) = e.raw else { return };
+        let Ok(signal_deliver) = SignalDeliver::parse(raw, self.endian) else {
+            return;
+        };
+        let Some(timestamp_mono) = e.timestamp else {
+            eprintln!("signal_deliver record doesn't have a timestamp");
+            return;
+        };
+        let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        process.check_jitdump(
+            &mut self.jit_category_manager,
+            &mut self.profile,
+            &self.timestamp_converter,
+        );
+
+        let stack = &mut self.stack_scratch;
+        Self::get_sample_stack::<C>(
+            e,
+            &process.unwinder,
+            &mut self.cache,
+            stack,
+            self.fold_recursive_prefix,
+            self.call_chain_return_addresses_are_preadjusted,
+        );
+
+        let thread_handle = match e.tid {
+            Some(tid) => {
+                process
+                    .threads
+                    .get_thread_by_tid(tid, &mut self.profile)
+                    .profile_thread
+            }
+            None => process.threads.main_thread.profile_thread,
+        };
+
+        let unresolved_stack = self.unresolved_stacks.convert(stack.iter().rev().cloned());
+        let name = self
+            .profile
+            .handle_for_string(&signal_name(signal_deliver.sig));
+        let marker_handle = self.profile.add_marker(
+            thread_handle,
+            MarkerTiming::Instant(timestamp),
+            SignalMarker {
+                name,
+                signal: signal_deliver.sig,
+            },
+        );
+        process.unresolved_samples.attach_stack_to_marker(
+            thread_handle,
+            timestamp,
+            timestamp_mono,
+            unresolved_stack,
+            marker_handle,
+        );
+    }
+
     pub fn handle_other_event_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
         &mut self,
         e: &SampleRecord,
@@ -1041,9 +1346,16 @@ where
         let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
 
         let name = if let Some((exec_name, args)) = exec_name_and_cmdline {
-            make_process_name(&exec_name, args, self.arg_count_to_include_in_process_name)
+            make_process_name(
+                &exec_name,
+                args,
+                self.arg_count_to_include_in_process_name,
+                &self.name_rules,
+            )
         } else {
-            comm_name.clone()
+            self.name_rules
+                .process_name(&comm_name)
+                .unwrap_or_else(|| comm_name.clone())
         };
 
         // eprintln!("Process execve: pid={}, tid={}, new name: {}", e.pid, e.tid, name);
@@ -1096,17 +1408,19 @@ where
 
         if is_main {
             // eprintln!("Process rename: pid={}, new name: {}", e.pid, name);
+            let name = self
+                .name_rules
+                .process_name(&name)
+                .unwrap_or_else(|| name.to_string());
             self.processes
-                .rename_process(e.pid, timestamp, name.to_string(), &mut self.profile);
+                .rename_process(e.pid, timestamp, name, &mut self.profile);
         } else {
             // eprintln!("Thread rename: pid={}, tid={}, new name: {}", e.pid, e.tid, name);
+            let name = self.name_rules.thread_name(&name).into_owned();
             let process = self.processes.get_by_pid(e.pid, &mut self.profile);
-            process.threads.rename_non_main_thread(
-                e.tid,
-                timestamp,
-                name.to_string(),
-                &mut self.profile,
-            );
+            process
+                .threads
+                .rename_non_main_thread(e.tid, timestamp, name, &mut self.profile);
         }
     }
 
@@ -1121,7 +1435,12 @@ where
         let process = self.processes.get_by_pid(pid, &mut self.profile);
         let process_handle = process.profile_process;
 
-        let name = make_process_name(exe_name, args, self.arg_count_to_include_in_process_name);
+        let name = make_process_name(
+            exe_name,
+            args,
+            self.arg_count_to_include_in_process_name,
+            &self.name_rules,
+        );
         self.profile.set_process_name(process_handle, &name);
         process.name = Some(name.to_owned());
 
@@ -1135,6 +1454,7 @@ where
     #[allow(unused)]
     pub fn register_existing_thread(&mut self, pid: i32, tid: i32, name: &str) {
         let is_main = pid == tid;
+        let name = self.name_rules.thread_name(name);
 
         let process = self.processes.get_by_pid(pid, &mut self.profile);
         let process_handle = process.profile_process;
@@ -1142,8 +1462,8 @@ where
         let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
         let thread_handle = thread.profile_thread;
 
-        self.profile.set_thread_name(thread_handle, name);
-        thread.name = Some(name.to_owned());
+        self.profile.set_thread_name(thread_handle, &name);
+        thread.name = Some(name.into_owned());
 
         // Mark this as the start time of the new thread / process.
         let time = self
@@ -1296,10 +1616,7 @@ where
         start_avma: u64,
     ) -> Option<(String, u32)> {
         let symbols = self.simpleperf.symbol_tables.jit.get(path_slice)?;
-        let index = symbols
-            .binary_search_by_key(&start_avma, |sym| sym.vaddr)
-            .ok()?;
-        let sym = &symbols[index];
+        let sym = jit_symbol_containing(symbols, start_avma)?;
         Some((sym.name.clone(), sym.len))
     }
 
@@ -1835,14 +2152,13 @@ fn process_off_cpu_sample_group(
     let weight = off_cpu_weight_per_sample;
     let stack = off_cpu_stack;
     let profile_timestamp = timestamp_converter.convert_time(begin_timestamp);
-    samples.add_sample(
+    samples.add_off_cpu_sample(
         thread_handle,
         profile_timestamp,
         begin_timestamp,
         stack,
         cpu_delta,
         weight,
-        None,
     );
 
     if sample_count > 1 {
@@ -1850,14 +2166,13 @@ fn process_off_cpu_sample_group(
         let cpu_delta = CpuDelta::from_nanos(0);
         let weight = i32::try_from(sample_count - 1).unwrap_or(0) * off_cpu_weight_per_sample;
         let profile_timestamp = timestamp_converter.convert_time(end_timestamp);
-        samples.add_sample(
+        samples.add_off_cpu_sample(
             thread_handle,
             profile_timestamp,
             begin_timestamp,
             stack,
             cpu_delta,
             weight,
-            None,
         );
     }
 }
@@ -1949,6 +2264,21 @@ struct KernelImageMapping {
     end_address: u64,
 }
 
+/// The symbol of a simpleperf JIT symfile which contains `address`. `symbols`
+/// are sorted by address.
+fn jit_symbol_containing(symbols: &[SimpleperfSymbol], address: u64) -> Option<&SimpleperfSymbol> {
+    match symbols.binary_search_by_key(&address, |sym| sym.vaddr) {
+        Ok(index) => Some(&symbols[index]),
+        Err(0) => None,
+        Err(insertion_index) => {
+            // JIT symfiles can map a function's code starting after its symbol
+            // address, e.g. past a method header. Use the containing symbol.
+            let sym = &symbols[insertion_index - 1];
+            (address < sym.vaddr + u64::from(sym.len)).then_some(sym)
+        }
+    }
+}
+
 #[cfg(unix)]
 fn path_from_unix_bytes(path_slice: &[u8]) -> Option<&Path> {
     use std::os::unix::ffi::OsStrExt;
@@ -1978,3 +2308,37 @@ impl Marker for MmapMarker {
         self.0
     }
 }
+
+#[cfg(test)]
+mod test {
+    use linux_perf_data::SimpleperfSymbol;
+
+    use super::jit_symbol_containing;
+
+    fn symbol(vaddr: u64, len: u32, name: &str) -> SimpleperfSymbol {
+        SimpleperfSymbol {
+            vaddr,
+            len,
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn jit_symbol_lookup_falls_back_to_containing_symbol() {
+        let symbols = [
+            symbol(0x1000, 0x100, "Foo.bar"),
+            symbol(0x1200, 0x80, "Foo.baz"),
+        ];
+        let name = |address| jit_symbol_containing(&symbols, address).map(|sym| &sym.name[..]);
+
+        assert_eq!(name(0x1000), Some("Foo.bar"));
+        assert_eq!(name(0x1200), Some("Foo.baz"));
+        // Code which starts after the symbol address, e.g. past a method header.
+        assert_eq!(name(0x1018), Some("Foo.bar"));
+        assert_eq!(name(0x127f), Some("Foo.baz"));
+        // Between symbols, before the first and after the last one.
+        assert_eq!(name(0x1100), None);
+        assert_eq!(name(0x0fff), None);
+        assert_eq!(name(0x1280), None);
+    }
+}
@@ -11,6 +11,7 @@ pub enum KnownEvent {
     MmapExit,
     MprotectEnter,
     PageFault,
+    SignalDeliver,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -35,7 +36,12 @@ pub struct EventInterpretation {
 }
 
 impl EventInterpretation {
-    pub fn divine_from_attrs(attrs: &[AttributeDescription]) -> Self {
+    /// `simpleperf_meta_info` is the meta info of perf.data files recorded with
+    /// Android's simpleperf, if any.
+    pub fn divine_from_attrs(
+        attrs: &[AttributeDescription],
+        simpleperf_meta_info: Option<&HashMap<&str, &str>>,
+    ) -> Self {
         let main_event_attr_index = 0;
         let main_event_name = attrs[0]
             .name
@@ -71,6 +77,22 @@ impl EventInterpretation {
             (false, Some(_)) => Some(OffCpuIndicator::SchedSwitchAndSamples),
             _ => None,
         };
+        // `simpleperf record --trace-offcpu` records sched_switch samples next to the
+        // main event, so the off-CPU stacks come from those.
+        let simpleperf_traced_offcpu = simpleperf_meta_info
+            .and_then(|meta_info| meta_info.get("trace_offcpu"))
+            .is_some_and(|value| *value == "true");
+        if simpleperf_traced_offcpu && sched_switch_attr_index.is_none() {
+            eprintln!(
+                "Warning: simpleperf recorded with --trace-offcpu, but no sched:sched_switch events were found. Off-CPU time will be missing."
+            );
+        } else if simpleperf_traced_offcpu && sched_switch_attr_index == Some(main_event_attr_index)
+        {
+            // Without on-CPU samples, there's nothing which ends an off-CPU period.
+            eprintln!(
+                "Warning: simpleperf only recorded sched:sched_switch events. They are shown as regular samples, without off-CPU durations."
+            );
+        }
         let mut known_event_indices = HashMap::new();
 
         let known_events = [
@@ -79,6 +101,7 @@ impl EventInterpretation {
             ("syscalls:sys_enter_mprotect", KnownEvent::MprotectEnter),
             ("syscalls:sys_enter_mmap", KnownEvent::MmapEnter),
             ("syscalls:sys_exit_mmap", KnownEvent::MmapExit),
+            ("signal:signal_deliver", KnownEvent::SignalDeliver),
         ];
 
         for (event_name, event) in known_events {
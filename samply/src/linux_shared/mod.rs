@@ -11,6 +11,7 @@ mod process;
 mod process_threads;
 mod processes;
 mod rss_stat;
+mod signal_deliver;
 mod svma_file_range;
 mod thread;
 #[allow(unused)]
@@ -186,6 +186,24 @@ where
         profile.set_process_end_time(self.profile_process, end_time);
     }
 
+    /// The samples of the process so far, for a snapshot of the profile while
+    /// it's still being converted. Unlike [`Process::finish`], this doesn't
+    /// read the process's jitdump, perf map and marker files, so JIT frames
+    /// stay unresolved until the end.
+    pub fn sample_data_snapshot(&self) -> ProcessSampleData {
+        let mut jitdump_ops = Vec::new();
+        if !self.jit_app_cache_mapping_ops.is_empty() {
+            jitdump_ops.push(self.jit_app_cache_mapping_ops.clone());
+        }
+        ProcessSampleData::new(
+            self.unresolved_samples.clone(),
+            self.lib_mapping_ops.clone(),
+            jitdump_ops,
+            None,
+            Vec::new(),
+        )
+    }
+
     pub fn finish(
         mut self,
         profile: &mut Profile,
@@ -1,6 +1,7 @@
 use std::collections::hash_map::Entry;
 
 use fxprof_processed_profile::{ProcessHandle, Profile, StringHandle, ThreadHandle, Timestamp};
+use samply_analysis::event_track_name;
 
 use super::thread::Thread;
 use crate::shared::recycling::ThreadRecycler;
@@ -179,10 +180,47 @@ impl ProcessThreads {
                 off_cpu_stack: None,
                 name: None,
                 thread_label,
+                event_tracks: Vec::new(),
             }
         })
     }
 
+    /// The name of the thread `tid`, if it's known yet.
+    pub fn thread_name(&self, tid: i32) -> Option<&str> {
+        if tid == self.pid {
+            return self.main_thread.name.as_deref();
+        }
+        self.threads_by_tid.get(&tid)?.name.as_deref()
+    }
+
+    /// The track for the samples of the hardware event `event` of the thread
+    /// `tid`, which is created with the event's first sample.
+    pub fn get_event_track(
+        &mut self,
+        tid: i32,
+        event: &'static str,
+        profile: &mut Profile,
+    ) -> ThreadHandle {
+        let process = self.profile_process;
+        let thread = self.get_thread_by_tid(tid, profile);
+        if let Some((_, track)) = thread.event_tracks.iter().find(|(e, _)| *e == event) {
+            return *track;
+        }
+        let track = profile.add_thread(
+            process,
+            tid as u32,
+            Timestamp::from_millis_since_reference(0.0),
+            false,
+        );
+        let name = match &thread.name {
+            Some(name) => event_track_name(name, event),
+            None => event_track_name(&format!("Thread {tid}"), event),
+        };
+        profile.set_thread_name(track, &name);
+        thread.event_tracks.push((event, track));
+        track
+    }
+
     pub fn remove_non_main_thread(&mut self, tid: i32, time: Timestamp, profile: &mut Profile) {
         let Some(mut thread) = self.threads_by_tid.remove(&tid) else {
             return;
@@ -201,6 +201,18 @@ where
         }
     }
 
+    /// Drop the buffered samples from before `timestamp_mono`, of both live
+    /// and removed processes.
+    #[allow(unused)] // Only used when recording on Linux
+    pub fn drop_samples_before(&mut self, timestamp_mono: u64) {
+        for process in self.processes_by_pid.values_mut() {
+            process.unresolved_samples.drop_before(timestamp_mono);
+        }
+        for process_sample_data in &mut self.process_sample_datas {
+            process_sample_data.drop_samples_before(timestamp_mono);
+        }
+    }
+
     pub fn rename_process(
         &mut self,
         pid: i32,
@@ -251,21 +263,45 @@ where
             }
         }
 
-        let user_category = profile
-            .handle_for_category(Category("User", CategoryColor::Yellow))
-            .into();
-        let kernel_category = profile
-            .handle_for_category(Category("Kernel", CategoryColor::Orange))
-            .into();
-        let mut stack_frame_scratch_buf = Vec::new();
-        for process_sample_data in self.process_sample_datas {
-            process_sample_data.flush_samples_to_profile(
-                profile,
-                user_category,
-                kernel_category,
-                &mut stack_frame_scratch_buf,
-                unresolved_stacks,
-            );
-        }
+        flush_sample_datas_to_profile(self.process_sample_datas, profile, unresolved_stacks);
+    }
+
+    /// Add the samples so far to `profile`, a copy of the profile which is
+    /// still being converted.
+    pub fn snapshot(&self, profile: &mut Profile, unresolved_stacks: &UnresolvedStacks) {
+        let live_sample_datas = self
+            .processes_by_pid
+            .values()
+            .map(Process::sample_data_snapshot)
+            .filter(|process_sample_data| !process_sample_data.is_empty());
+        let sample_datas = self
+            .process_sample_datas
+            .iter()
+            .cloned()
+            .chain(live_sample_datas);
+        flush_sample_datas_to_profile(sample_datas, profile, unresolved_stacks);
+    }
+}
+
+fn flush_sample_datas_to_profile(
+    process_sample_datas: impl IntoIterator<Item = ProcessSampleData>,
+    profile: &mut Profile,
+    unresolved_stacks: &UnresolvedStacks,
+) {
+    let user_category = profile
+        .handle_for_category(Category("User", CategoryColor::Yellow))
+        .into();
+    let kernel_category = profile
+        .handle_for_category(Category("Kernel", CategoryColor::Orange))
+        .into();
+    let mut stack_frame_scratch_buf = Vec::new();
+    for process_sample_data in process_sample_datas {
+        process_sample_data.flush_samples_to_profile(
+            profile,
+            user_category,
+            kernel_category,
+            &mut stack_frame_scratch_buf,
+            unresolved_stacks,
+        );
     }
 }
@@ -0,0 +1,115 @@
+use std::fmt::Debug;
+
+use byteorder::ByteOrder;
+use linux_perf_data::{linux_perf_event_reader, Endianness};
+use linux_perf_event_reader::RawData;
+
+/// ```
+/// # cat /sys/kernel/debug/tracing/events/signal/signal_deliver/format
+/// name: signal_deliver
+/// ID: 188
+/// format:
+///         field:unsigned short common_type;       offset:0;       size:2; signed:0;
+///         field:unsigned char common_flags;       offset:2;       size:1; signed:0;
+///         field:unsigned char common_preempt_count;       offset:3;       size:1; signed:0;
+///         field:int common_pid;   offset:4;       size:4; signed:1;
+///
+///         field:int sig;  offset:8;       size:4; signed:1;
+///         field:int errno;        offset:12;      size:4; signed:1;
+///         field:int code; offset:16;      size:4; signed:1;
+///         field:unsigned long sa_handler; offset:24;      size:8; signed:0;
+///         field:unsigned long sa_flags;   offset:32;      size:8; signed:0;
+///
+/// print fmt: "sig=%d errno=%d code=%d sa_handler=%lx sa_flags=%lx", REC->sig, REC->errno, REC->code, REC->sa_handler, REC->sa_flags
+/// ```
+#[repr(C)]
+#[derive(Debug)]
+pub struct SignalDeliver {
+    pub common_type: u16,
+    pub common_flags: u8,
+    pub common_preempt_count: u8,
+    pub common_pid: i32,
+    pub sig: i32,
+    pub errno: i32,
+    pub code: i32,
+    pub sa_handler: u64,
+    pub sa_flags: u64,
+}
+
+impl SignalDeliver {
+    pub fn parse(data: RawData, endian: Endianness) -> Result<Self, std::io::Error> {
+        match endian {
+            Endianness::LittleEndian => Self::parse_impl::<byteorder::LittleEndian>(data),
+            Endianness::BigEndian => Self::parse_impl::<byteorder::BigEndian>(data),
+        }
+    }
+
+    pub fn parse_impl<O: ByteOrder>(mut data: RawData) -> Result<Self, std::io::Error> {
+        let common_type = data.read_u16::<O>()?;
+        let common_flags = data.read_u8()?;
+        let common_preempt_count = data.read_u8()?;
+        let common_pid = data.read_i32::<O>()?;
+        let sig = data.read_i32::<O>()?;
+        let errno = data.read_i32::<O>()?;
+        let code = data.read_i32::<O>()?;
+        let _padding = data.read_u32::<O>()?;
+        let sa_handler = data.read_u64::<O>()?;
+        let sa_flags = data.read_u64::<O>()?;
+        Ok(SignalDeliver {
+            common_type,
+            common_flags,
+            common_preempt_count,
+            common_pid,
+            sig,
+            errno,
+            code,
+            sa_handler,
+            sa_flags,
+        })
+    }
+}
+
+/// The name of a Linux signal number, e.g. "SIGSEGV" for 11
+pub fn signal_name(sig: i32) -> String {
+    const NAMES: [&str; 31] = [
+        "SIGHUP",
+        "SIGINT",
+        "SIGQUIT",
+        "SIGILL",
+        "SIGTRAP",
+        "SIGABRT",
+        "SIGBUS",
+        "SIGFPE",
+        "SIGKILL",
+        "SIGUSR1",
+        "SIGSEGV",
+        "SIGUSR2",
+        "SIGPIPE",
+        "SIGALRM",
+        "SIGTERM",
+        "SIGSTKFLT",
+        "SIGCHLD",
+        "SIGCONT",
+        "SIGSTOP",
+        "SIGTSTP",
+        "SIGTTIN",
+        "SIGTTOU",
+        "SIGURG",
+        "SIGXCPU",
+        "SIGXFSZ",
+        "SIGVTALRM",
+        "SIGPROF",
+        "SIGWINCH",
+        "SIGIO",
+        "SIGPWR",
+        "SIGSYS",
+    ];
+    // glibc reserves the first two real-time signals, so SIGRTMIN is 34.
+    const SIGRTMIN: i32 = 34;
+    match sig {
+        1..=31 => NAMES[sig as usize - 1].to_string(),
+        SIGRTMIN => "SIGRTMIN".to_string(),
+        35..=64 => format!("SIGRTMIN+{}", sig - SIGRTMIN),
+        _ => format!("signal {sig}"),
+    }
+}
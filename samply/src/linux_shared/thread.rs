@@ -1,6 +1,7 @@
 use std::fmt::Debug;
 
 use fxprof_processed_profile::{Profile, StringHandle, ThreadHandle, Timestamp};
+use samply_analysis::event_track_name;
 
 use crate::shared::context_switch::ThreadContextSwitchData;
 use crate::shared::unresolved_samples::UnresolvedStackHandle;
@@ -17,6 +18,9 @@ pub struct Thread {
     pub off_cpu_stack: Option<UnresolvedStackHandle>,
     pub name: Option<String>,
     pub thread_label: StringHandle,
+    /// The tracks of the hardware event samples of `samply record --event`,
+    /// by event name
+    pub event_tracks: Vec<(&'static str, ThreadHandle)>,
 }
 
 impl Thread {
@@ -32,6 +36,7 @@ impl Thread {
             off_cpu_stack: None,
             name,
             thread_label,
+            event_tracks: Vec::new(),
         }
     }
 
@@ -43,6 +48,8 @@ impl Thread {
         let old_thread_handle = std::mem::replace(&mut self.profile_thread, thread_handle);
         let old_thread_label = std::mem::replace(&mut self.thread_label, thread_label);
         let old_name = self.name.replace(name);
+        // The tracks are named after the old name; new ones are made on demand.
+        self.event_tracks.clear();
         (old_name, (old_thread_handle, old_thread_label))
     }
 
@@ -53,12 +60,18 @@ impl Thread {
         profile: &mut Profile,
     ) {
         profile.set_thread_name(self.profile_thread, &name);
+        for (event, track) in &self.event_tracks {
+            profile.set_thread_name(*track, &event_track_name(&name, event));
+        }
         self.thread_label = thread_label;
         self.name = Some(name);
     }
 
     pub fn notify_dead(&mut self, end_time: Timestamp, profile: &mut Profile) {
         profile.set_thread_end_time(self.profile_thread, end_time);
+        for (_, track) in &self.event_tracks {
+            profile.set_thread_end_time(*track, end_time);
+        }
     }
 
     pub fn finish(self) -> (Option<String>, (ThreadHandle, StringHandle)) {
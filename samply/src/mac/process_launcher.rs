@@ -269,6 +269,8 @@ impl AcceptedTask {
 
 pub struct ExistingProcessRunner {
     pid: u32,
+    /// Stop without Ctrl+C after this long
+    stop_after: Option<Duration>,
     aux_child: Option<Child>,
 }
 
@@ -278,9 +280,7 @@ impl RootTaskRunner for ExistingProcessRunner {
 
         eprintln!("Profiling {}, press Ctrl-C to stop...", self.pid);
 
-        ctrl_c_receiver
-            .blocking_recv()
-            .expect("Ctrl+C receiver failed");
+        CtrlC::wait_with_timeout(ctrl_c_receiver, self.stop_after);
 
         if let Some(aux_child) = self.aux_child.as_mut() {
             let aux_pid = aux_child.id();
@@ -315,7 +315,11 @@ impl ExistingProcessRunner {
         descendants
     }
 
-    pub fn new(pid: u32, task_accepter: &mut TaskAccepter) -> ExistingProcessRunner {
+    pub fn new(
+        pid: u32,
+        stop_after: Option<Duration>,
+        task_accepter: &mut TaskAccepter,
+    ) -> ExistingProcessRunner {
         let mut queue_pid = |pid, failure_is_ok| {
             let task = unsafe {
                 let mut task = MACH_PORT_NULL;
@@ -354,6 +358,7 @@ impl ExistingProcessRunner {
 
         ExistingProcessRunner {
             pid,
+            stop_after,
             aux_child: None,
         }
     }
@@ -361,10 +366,11 @@ impl ExistingProcessRunner {
     #[allow(unused)]
     pub fn new_with_aux_child(
         pid: u32,
+        stop_after: Option<Duration>,
         task_accepter: &mut TaskAccepter,
         aux_child: Child,
     ) -> ExistingProcessRunner {
-        let runner = Self::new(pid, task_accepter);
+        let runner = Self::new(pid, stop_after, task_accepter);
 
         ExistingProcessRunner {
             aux_child: Some(aux_child),
@@ -13,6 +13,7 @@ use super::process_launcher::{
 };
 use super::sampler::{ProcessSpecificPath, Sampler, TaskInit, TaskInitOrShutdown};
 use super::time::get_monotonic_timestamp;
+use crate::shared::overhead::OverheadMeter;
 use crate::shared::prop_types::{
     ProcessLaunchProps, ProfileCreationProps, RecordingMode, RecordingProps,
 };
@@ -30,7 +31,11 @@ pub fn run(
             eprintln!("You can only profile processes which you launch via samply, or attach to via --pid.");
             std::process::exit(1)
         }
-        RecordingMode::Pid(pid) => Box::new(ExistingProcessRunner::new(pid, &mut task_accepter)),
+        RecordingMode::Pid(pid) => Box::new(ExistingProcessRunner::new(
+            pid,
+            recording_props.stop_after(),
+            &mut task_accepter,
+        )),
         RecordingMode::Launch(process_launch_props) => {
             let ProcessLaunchProps {
                 mut env_vars,
@@ -77,6 +82,7 @@ pub fn run(
 
     let (task_sender, task_receiver) = unbounded();
 
+    let overhead_meter = OverheadMeter::start();
     let sampler_thread = thread::spawn(move || {
         let sampler = Sampler::new(task_receiver, recording_props, profile_creation_props);
         sampler.run()
@@ -188,7 +194,7 @@ pub fn run(
     // or until the time limit has elapsed.
     let profile_result = sampler_thread.join().expect("couldn't join sampler thread");
 
-    let profile = match profile_result {
+    let mut profile = match profile_result {
         Ok(profile) => profile,
         Err(SamplingError::CouldNotObtainRootTask) => {
             eprintln!("Profiling failed: Could not obtain the root task.");
@@ -203,6 +209,7 @@ pub fn run(
             std::process::exit(1)
         }
     };
+    profile.set_profiler_overhead(overhead_meter.finish(0));
 
     Ok((profile, exit_status))
 }
@@ -14,6 +14,7 @@ use super::task_profiler::TaskProfiler;
 use super::time::get_monotonic_timestamp;
 use crate::shared::prop_types::{ProfileCreationProps, RecordingProps};
 use crate::shared::recycling::ProcessRecycler;
+use crate::shared::ring_buffer::RingBuffer;
 use crate::shared::timestamp_converter::TimestampConverter;
 use crate::shared::unresolved_samples::UnresolvedStacks;
 
@@ -106,6 +107,7 @@ impl Sampler {
             &mut profile,
             process_recycler.as_mut(),
             self.profile_creation_props.clone(),
+            self.recording_props.included_threads.clone(),
         )
         .expect("couldn't create root TaskProfiler");
 
@@ -116,6 +118,7 @@ impl Sampler {
         let mut unresolved_stacks = UnresolvedStacks::default();
         let mut last_sleep_overshoot = 0;
         let mut stop_profiling = false;
+        let mut ring_buffer = self.recording_props.ring_buffer.map(RingBuffer::new);
 
         loop {
             loop {
@@ -147,6 +150,7 @@ impl Sampler {
                     &mut profile,
                     process_recycler.as_mut(),
                     self.profile_creation_props.clone(),
+                    self.recording_props.included_threads.clone(),
                 ) {
                     live_tasks.push(new_task);
                 } else {
@@ -166,8 +170,8 @@ impl Sampler {
             }
 
             let sample_mono = get_monotonic_timestamp();
-            if let Some(time_limit) = self.recording_props.time_limit {
-                if sample_mono - reference_mono >= time_limit.as_nanos() as u64 {
+            if let Some(stop_after) = self.recording_props.stop_after() {
+                if sample_mono - reference_mono >= stop_after.as_nanos() as u64 {
                     // Time limit reached.
                     break;
                 }
@@ -205,6 +209,17 @@ impl Sampler {
                 }
             }
 
+            if let Some(ring_buffer) = &mut ring_buffer {
+                if let Some(start_mono) = ring_buffer.drop_before(sample_mono) {
+                    for task in &mut live_tasks {
+                        task.drop_samples_before(start_mono);
+                    }
+                    for process_sample_data in &mut process_sample_datas {
+                        process_sample_data.drop_samples_before(start_mono);
+                    }
+                }
+            }
+
             let intended_wakeup_time =
                 sample_mono + self.recording_props.interval.as_nanos() as u64;
             let before_sleep = get_monotonic_timestamp();
@@ -224,6 +239,13 @@ impl Sampler {
             process_sample_datas.push(process_sample_data);
         }
 
+        if let Some(delay) = self.recording_props.delay {
+            let delay_end = reference_mono + delay.as_nanos() as u64;
+            for process_sample_data in &mut process_sample_datas {
+                process_sample_data.drop_samples_before(delay_end);
+            }
+        }
+
         let mut stack_frame_scratch_buf = Vec::new();
         for process_sample_data in process_sample_datas {
             process_sample_data.flush_samples_to_profile(
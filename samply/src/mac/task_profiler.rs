@@ -34,6 +34,7 @@ use super::proc_maps::{
 };
 use super::sampler::{ProcessSpecificPath, TaskInit};
 use super::thread_profiler::{get_thread_id, get_thread_name, ThreadProfiler};
+use crate::shared::included_threads::IncludedThreads;
 use crate::shared::jit_category_manager::JitCategoryManager;
 use crate::shared::jit_function_recycler::JitFunctionRecycler;
 use crate::shared::jitdump_manager::JitDumpManager;
@@ -117,6 +118,7 @@ pub struct TaskProfiler {
     jit_function_recycler: Option<JitFunctionRecycler>,
     timestamp_converter: TimestampConverter,
     profile_creation_props: Arc<ProfileCreationProps>,
+    included_threads: IncludedThreads,
 }
 
 impl TaskProfiler {
@@ -127,6 +129,7 @@ impl TaskProfiler {
         profile: &mut Profile,
         mut process_recycler: Option<&mut ProcessRecycler>,
         profile_creation_props: Arc<ProfileCreationProps>,
+        included_threads: IncludedThreads,
     ) -> Result<Self, SamplingError> {
         let TaskInit {
             start_time_mono,
@@ -161,6 +164,7 @@ impl TaskProfiler {
             &executable_name,
             cmdline,
             profile_creation_props.arg_count_to_include_in_process_name,
+            &profile_creation_props.name_rules,
         );
 
         let thread_acts = get_thread_list(task, profile_creation_props.main_thread_only)?;
@@ -300,6 +304,7 @@ impl TaskProfiler {
             jit_function_recycler,
             timestamp_converter,
             profile_creation_props,
+            included_threads,
         };
 
         task_profiler.process_lib_modifications(start_time_mono, initial_lib_mods, profile);
@@ -413,6 +418,11 @@ impl TaskProfiler {
             // Grab a sample from the thread.
             let stackwalker = StackwalkerRef::new(&self.unwinder, unwinder_cache);
             thread.check_thread_name(profile, self.thread_recycler.as_mut());
+            if !self.included_threads.should_include(thread.name()) {
+                // Left out by --include-thread or --exclude-thread; don't unwind it.
+                now_live_threads.insert(thread_act);
+                continue;
+            }
             let still_alive = thread.sample(
                 stackwalker,
                 now,
@@ -615,6 +625,11 @@ impl TaskProfiler {
         );
     }
 
+    /// Drop the buffered samples from before `timestamp_mono`.
+    pub fn drop_samples_before(&mut self, timestamp_mono: u64) {
+        self.unresolved_samples.drop_before(timestamp_mono);
+    }
+
     /// Called when a process has exited, before finish(). Not called if the process
     /// is still alive at the end of the profiling run.
     pub fn notify_dead(&mut self, end_time: Timestamp, profile: &mut Profile) {
@@ -54,6 +54,10 @@ impl ThreadProfiler {
         }
     }
 
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
     /// Called before every call to `sample`.
     pub fn check_thread_name(
         &mut self,
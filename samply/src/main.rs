@@ -9,12 +9,18 @@ mod windows;
 
 mod cli;
 mod cli_utils;
+mod config;
 mod import;
 mod linux_shared;
+mod mcp;
 mod name;
-mod profile_analysis;
+mod otlp;
 mod profile_json_preparse;
 mod query_client;
+mod query_output;
+mod query_schema;
+mod query_watch;
+mod self_profile;
 mod server;
 mod session;
 mod shared;
@@ -22,12 +28,14 @@ mod symbols;
 
 use std::ffi::OsStr;
 use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+use std::io::{BufReader, Read, Seek};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 use fxprof_processed_profile::Profile;
 use shared::ctrl_c::CtrlC;
+use tokio::runtime::Handle;
 
 #[cfg(any(target_os = "android", target_os = "linux"))]
 use linux::profiler;
@@ -37,21 +45,44 @@ use mac::profiler;
 use windows::profiler;
 
 use profile_json_preparse::parse_libinfo_map_from_profile_file;
+use samply_quota_manager::QuotaManager;
 use server::{start_server, RunningServerInfo, ServerProps};
-use shared::prop_types::{ImportProps, SymbolProps};
-use shared::save_profile::save_profile_to_file;
+use shared::live_profile::LiveProfile;
+use shared::prop_types::ImportProps;
+use shared::save_profile::save_transformed_profile_to_file;
+use shared::transform::ProfileTransform;
 use symbols::create_symbol_manager_and_quota_manager;
+use wholesym::SymbolManager;
 
 fn main() {
     env_logger::init();
 
     use clap::Parser;
     let opt = cli::Opt::parse();
+    shared::progress::set_progress_mode(opt.progress_mode());
+
+    if let Some(self_profile_path) = &opt.self_profile {
+        self_profile::run_self_profiled(self_profile_path);
+    }
+
+    // The one runtime of the process. Presymbolication and the servers run on
+    // it, so that a profile which is recorded or imported, symbolicated and
+    // then served keeps the same symbol manager, with its download client
+    // and known libraries.
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let runtime = runtime.handle();
+
     match opt.action {
-        cli::Action::Load(load_args) => do_load_action(load_args),
-        cli::Action::Import(import_args) => do_import_action(import_args),
-        cli::Action::Analyze(analyze_args) => do_analyze_action(analyze_args),
+        cli::Action::Load(load_args) => do_load_action(load_args, runtime),
+        cli::Action::Import(import_args) => do_import_action(import_args, runtime),
+        cli::Action::Analyze(analyze_args) => do_analyze_action(analyze_args, runtime),
         cli::Action::Query(query_args) => do_query_action(query_args),
+        cli::Action::Open(open_args) => do_open_action(open_args),
+        cli::Action::Overlay(overlay_args) => do_overlay_action(overlay_args),
+        cli::Action::Symbols(symbols_args) => do_symbols_action(symbols_args),
 
         #[cfg(any(
             target_os = "android",
@@ -59,7 +90,7 @@ fn main() {
             target_os = "linux",
             target_os = "windows"
         ))]
-        cli::Action::Record(record_args) => do_record_action(record_args),
+        cli::Action::Record(record_args) => do_record_action(record_args, runtime),
 
         #[cfg(target_os = "windows")]
         cli::Action::RunElevatedHelper(args) => {
@@ -71,16 +102,33 @@ fn main() {
     }
 }
 
-fn do_load_action(load_args: cli::LoadArgs) {
+fn do_load_action(load_args: cli::LoadArgs, runtime: &Handle) {
+    let server_props = load_args.server_props();
+    let (symbol_manager, quota_manager) = create_symbol_manager_and_quota_manager(
+        runtime,
+        load_args.symbol_props(),
+        server_props.verbose,
+    );
     run_server_serving_profile(
+        runtime,
         &load_args.file,
-        load_args.server_props(),
-        load_args.symbol_props(),
+        server_props,
+        symbol_manager,
+        quota_manager,
     );
 }
 
-fn do_import_action(import_args: cli::ImportArgs) {
-    let input_path = &import_args.file;
+fn do_import_action(import_args: cli::ImportArgs, runtime: &Handle) {
+    if import_args.follow {
+        do_import_follow(import_args, runtime);
+        return;
+    }
+    if let Some(session_name) = import_args.etw_session.clone() {
+        do_import_etw_session(import_args, &session_name, runtime);
+        return;
+    }
+
+    let input_path = import_args.input_path();
     let input_file = match File::open(input_path) {
         Ok(file) => file,
         Err(err) => {
@@ -89,45 +137,350 @@ fn do_import_action(import_args: cli::ImportArgs) {
         }
     };
 
+    let transforms = profile_transforms(&import_args.profile_creation_args);
     let import_props = import_args.import_props();
     let presymbolicate = import_props.profile_creation_props.presymbolicate;
     let mut profile = convert_file_to_profile(&input_file, input_path, import_props);
 
+    let server_props = import_args.server_props();
+    let symbol_props = import_args.symbol_props();
+    let (mut symbol_manager, quota_manager) = create_symbol_manager_and_quota_manager(
+        runtime,
+        symbol_props.clone(),
+        server_props.as_ref().is_some_and(|props| props.verbose),
+    );
+
     if presymbolicate {
         eprintln!("Symbolicating...");
-        let symbol_info = crate::shared::presymbolicate::get_presymbolicate_info(
-            &profile,
-            import_args.symbol_props(),
-        );
+        let (symbol_info, used_symbol_manager) =
+            crate::shared::presymbolicate::get_presymbolicate_info(
+                runtime,
+                &profile,
+                symbol_manager,
+                symbols::lookup_cache(quota_manager.as_ref()),
+                &symbol_props,
+            );
+        symbol_manager = used_symbol_manager;
         profile = profile.make_symbolicated_profile(&symbol_info);
         profile.set_symbolicated(true);
     }
 
-    save_profile_to_file(&profile, &import_args.output).expect("Couldn't write JSON");
+    save_transformed_profile_to_file(&profile, &transforms, &import_args.output)
+        .expect("Couldn't write JSON");
 
     // Drop the profile so that it doesn't take up memory while the server is running.
     drop(profile);
 
-    if let Some(server_props) = import_args.server_props() {
-        run_server_serving_profile(
+    match server_props {
+        Some(server_props) => run_server_serving_profile(
+            runtime,
             &import_args.output,
             server_props,
-            import_args.symbol_props(),
+            symbol_manager,
+            quota_manager,
+        ),
+        None => finish_quota_manager(runtime, quota_manager),
+    }
+}
+
+/// Import a running ETW real-time session with `--etw-session`. Unless
+/// `--save-only`, the analysis server serves its snapshots while the events
+/// are converted, as with `record --live`, and the final profile once the
+/// session has stopped or Ctrl+C was pressed.
+#[cfg(target_os = "windows")]
+fn do_import_etw_session(import_args: cli::ImportArgs, session_name: &str, runtime: &Handle) {
+    let transforms = profile_transforms(&import_args.profile_creation_args);
+    let symbol_props = import_args.symbol_props();
+    let import_props = import_args.import_props();
+    let presymbolicate = import_props.profile_creation_props.presymbolicate;
+
+    let live_server = (!import_args.save_only).then(|| {
+        check_no_running_session(None);
+        let live = Arc::new(LiveProfile::new());
+        let (symbol_manager, quota_manager) =
+            create_symbol_manager_and_quota_manager(runtime, symbol_props.clone(), false);
+        // Ctrl+C stops the import first, so the server gets its own stop signal.
+        let (stop_sender, stop_receiver) = tokio::sync::oneshot::channel();
+        let server = runtime.spawn(serve_recorded_profile(
+            import_args.output.clone(),
+            Some(live.clone()),
+            symbol_manager,
+            quota_manager,
+            stop_receiver,
+        ));
+        (live, server, stop_sender)
+    });
+
+    let live = live_server.as_ref().map(|(live, ..)| &**live);
+    let mut profile = match windows::import::import_etw_session(session_name, import_props, live) {
+        Ok(profile) => profile,
+        Err(err) => {
+            eprintln!("Could not consume the ETW session {session_name:?}: {err}");
+            std::process::exit(1)
+        }
+    };
+
+    let (symbol_manager, quota_manager) =
+        create_symbol_manager_and_quota_manager(runtime, symbol_props.clone(), false);
+    if presymbolicate {
+        eprintln!("Symbolicating...");
+        let (symbol_info, _symbol_manager) = crate::shared::presymbolicate::get_presymbolicate_info(
+            runtime,
+            &profile,
+            symbol_manager,
+            symbols::lookup_cache(quota_manager.as_ref()),
+            &symbol_props,
         );
+        profile = profile.make_symbolicated_profile(&symbol_info);
+        profile.set_symbolicated(true);
+    }
+    finish_quota_manager(runtime, quota_manager);
+
+    save_transformed_profile_to_file(&profile, &transforms, &import_args.output)
+        .expect("Couldn't write JSON");
+
+    if let Some((live, server, stop_sender)) = live_server {
+        // The final profile is the last snapshot. Now that the import has
+        // ended, Ctrl+C stops the server.
+        live.publish(profile);
+        let ctrl_c_receiver = CtrlC::observe_oneshot();
+        runtime.block_on(async {
+            let _ = ctrl_c_receiver.await;
+            let _ = stop_sender.send(());
+            let _ = server.await;
+        });
     }
 }
 
+#[cfg(not(target_os = "windows"))]
+fn do_import_etw_session(_import_args: cli::ImportArgs, _session_name: &str, _runtime: &Handle) {
+    eprintln!("Error: Importing ETW sessions is only supported on Windows.");
+    std::process::exit(1);
+}
+
+/// Stop the cache eviction of the symbol manager, once nothing is looked up
+/// anymore.
+fn finish_quota_manager(runtime: &Handle, quota_manager: Option<QuotaManager>) {
+    if let Some(quota_manager) = quota_manager {
+        runtime.block_on(quota_manager.finish());
+    }
+}
+
+/// Import a perf.data file which is still being written by `perf record`, and
+/// keep importing the records which are added to it until `perf record` has
+/// finished writing it. The output file is replaced after every import, so
+/// reloading the served profile shows the latest data.
+fn do_import_follow(import_args: cli::ImportArgs, runtime: &Handle) {
+    const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+    let output_path = import_args.output.clone();
+    let server_props = import_args.server_props();
+    let symbol_props = import_args.symbol_props();
+
+    // The follower stays on the thread which polls the file; the server is
+    // started once the records which are there already have been imported.
+    let (imported_sender, imported_receiver) = std::sync::mpsc::channel();
+    let follow_runtime = runtime.clone();
+    let follow_thread = std::thread::spawn(move || {
+        let transforms = profile_transforms(&import_args.profile_creation_args);
+        let input_path = import_args.input_path();
+        let file_mod_time = std::fs::metadata(input_path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        let import_props = import_args.import_props();
+        let (binary_lookup_dirs, aux_file_lookup_dirs) =
+            perf_lookup_dirs(input_path, &import_props);
+        let mut follower = match import::perf::PerfFollower::new(
+            input_path,
+            file_mod_time,
+            binary_lookup_dirs,
+            aux_file_lookup_dirs,
+            import_props.profile_creation_props,
+        ) {
+            Ok(follower) => follower,
+            Err(err) => {
+                eprintln!("Could not open file {input_path:?}: {err}");
+                std::process::exit(1);
+            }
+        };
+
+        let mut is_complete = follow_perf_file(
+            &mut follower,
+            &import_args,
+            &transforms,
+            &follow_runtime,
+            true,
+        );
+        let _ = imported_sender.send(());
+        while !is_complete {
+            std::thread::sleep(POLL_INTERVAL);
+            is_complete = follow_perf_file(
+                &mut follower,
+                &import_args,
+                &transforms,
+                &follow_runtime,
+                false,
+            );
+        }
+        eprintln!("perf record has finished; {:?} is complete.", import_args.output);
+    });
+    let _ = imported_receiver.recv();
+
+    if let Some(server_props) = server_props {
+        let (symbol_manager, quota_manager) =
+            create_symbol_manager_and_quota_manager(runtime, symbol_props, server_props.verbose);
+        run_server_serving_profile(
+            runtime,
+            &output_path,
+            server_props,
+            symbol_manager,
+            quota_manager,
+        );
+    } else {
+        let _ = follow_thread.join();
+    }
+}
+
+fn is_perf_file_complete(path: &Path) -> bool {
+    import::perf::InProgressPerfFile::open(path).is_ok_and(|file| file.is_complete())
+}
+
+/// Import the records which have been added to a perf.data file which may still
+/// be growing, and save the profile to the output path if there were any, or if
+/// `always_save` is set.
+///
+/// Returns whether `perf record` has finished writing the file. The complete
+/// file is converted once more then, because its feature sections, such as the
+/// build IDs, are only there at the end; the final profile is the same as the
+/// one of a plain import.
+fn follow_perf_file(
+    follower: &mut import::perf::PerfFollower,
+    import_args: &cli::ImportArgs,
+    transforms: &[Box<dyn ProfileTransform>],
+    runtime: &Handle,
+    always_save: bool,
+) -> bool {
+    let input_path = import_args.input_path();
+    if is_perf_file_complete(input_path) {
+        let file = match File::open(input_path) {
+            Ok(file) => file,
+            Err(err) => {
+                eprintln!("Could not open file {input_path:?}: {err}");
+                return true;
+            }
+        };
+        let file_mod_time = file
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        let profile = convert_perf_file_to_profile(
+            BufReader::new(file),
+            input_path,
+            file_mod_time,
+            import_args.import_props(),
+        );
+        save_followed_profile(import_args, transforms, runtime, profile);
+        eprintln!("Imported {input_path:?} into {:?}.", import_args.output);
+        return true;
+    }
+
+    match follower.convert_new_records() {
+        Ok(has_new_records) => {
+            if has_new_records || always_save {
+                save_followed_profile(import_args, transforms, runtime, follower.snapshot());
+                eprintln!(
+                    "Imported {} bytes of {input_path:?} into {:?}.",
+                    follower.converted_len(),
+                    import_args.output
+                );
+            }
+        }
+        Err(err) => eprintln!("Error importing perf.data file: {err}"),
+    }
+    false
+}
+
+/// Presymbolicate a profile of `import --follow` if asked to, and replace the
+/// output file with it.
+fn save_followed_profile(
+    import_args: &cli::ImportArgs,
+    transforms: &[Box<dyn ProfileTransform>],
+    runtime: &Handle,
+    mut profile: Profile,
+) {
+    let presymbolicate = import_args
+        .import_props()
+        .profile_creation_props
+        .presymbolicate;
+    if presymbolicate {
+        // The server, if any, has its own symbol manager, which it uses while
+        // this one symbolicates.
+        eprintln!("Symbolicating...");
+        let symbol_props = import_args.symbol_props();
+        let (symbol_manager, quota_manager) =
+            create_symbol_manager_and_quota_manager(runtime, symbol_props.clone(), false);
+        let (symbol_info, _symbol_manager) = crate::shared::presymbolicate::get_presymbolicate_info(
+            runtime,
+            &profile,
+            symbol_manager,
+            symbols::lookup_cache(quota_manager.as_ref()),
+            &symbol_props,
+        );
+        finish_quota_manager(runtime, quota_manager);
+        profile = profile.make_symbolicated_profile(&symbol_info);
+        profile.set_symbolicated(true);
+    }
+
+    // Write to a temporary file first so that the server never serves a
+    // partially written profile.
+    let output_path = &import_args.output;
+    let file_name = output_path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = output_path.with_file_name(format!(".{file_name}"));
+    save_transformed_profile_to_file(&profile, transforms, &temp_path)
+        .expect("Couldn't write JSON");
+    std::fs::rename(&temp_path, output_path).expect("Couldn't replace output file");
+}
+
 #[cfg(any(
     target_os = "android",
     target_os = "macos",
     target_os = "linux",
     target_os = "windows"
 ))]
-fn do_record_action(record_args: cli::RecordArgs) {
+fn do_record_action(record_args: cli::RecordArgs, runtime: &Handle) {
     let recording_props = record_args.recording_props();
     let recording_mode = record_args.recording_mode();
     let profile_creation_props = record_args.profile_creation_props();
     let presymbolicate = profile_creation_props.presymbolicate;
+    let mut transforms = profile_transforms(&record_args.profile_creation_args);
+    if let Some(ring_buffer) = recording_props.ring_buffer {
+        // Trim the profile to the ring buffer before any other transform.
+        transforms.insert(0, shared::transform::keep_last(ring_buffer));
+    }
+    if !recording_props.included_threads.is_all() {
+        transforms.insert(
+            0,
+            shared::transform::drop_excluded_threads(recording_props.included_threads.clone()),
+        );
+    }
+
+    // With --live, the analysis server starts before recording does, and
+    // serves the snapshots which the sampler publishes.
+    let live_server = recording_props.live.clone().map(|live| {
+        check_no_running_session(None);
+        let (symbol_manager, quota_manager) =
+            create_symbol_manager_and_quota_manager(runtime, record_args.symbol_props(), false);
+        // Ctrl+C stops recording first, so the server gets its own stop signal.
+        let (stop_sender, stop_receiver) = tokio::sync::oneshot::channel();
+        let server = runtime.spawn(serve_recorded_profile(
+            record_args.output.clone(),
+            Some(live.clone()),
+            symbol_manager,
+            quota_manager,
+            stop_receiver,
+        ));
+        (live, server, stop_sender)
+    });
 
     let (mut profile, exit_status) =
         match profiler::run(recording_mode, recording_props, profile_creation_props) {
@@ -138,40 +491,88 @@ fn do_record_action(record_args: cli::RecordArgs) {
             }
         };
 
+    if let Some(overhead) = profile.profiler_overhead() {
+        eprintln!("{overhead}");
+    }
+
+    let server_props = record_args.server_props();
+    let symbol_props = record_args.symbol_props();
+    let (mut symbol_manager, quota_manager) = create_symbol_manager_and_quota_manager(
+        runtime,
+        symbol_props.clone(),
+        server_props.as_ref().is_some_and(|props| props.verbose),
+    );
+
     if presymbolicate {
         eprintln!("Symbolicating...");
-        let symbol_info = crate::shared::presymbolicate::get_presymbolicate_info(
-            &profile,
-            record_args.symbol_props(),
-        );
+        let (symbol_info, used_symbol_manager) =
+            crate::shared::presymbolicate::get_presymbolicate_info(
+                runtime,
+                &profile,
+                symbol_manager,
+                symbols::lookup_cache(quota_manager.as_ref()),
+                &symbol_props,
+            );
+        symbol_manager = used_symbol_manager;
         profile = profile.make_symbolicated_profile(&symbol_info);
         profile.set_symbolicated(true);
     }
 
-    save_profile_to_file(&profile, &record_args.output).expect("Couldn't write JSON");
+    save_transformed_profile_to_file(&profile, &transforms, &record_args.output)
+        .expect("Couldn't write JSON");
+
+    if let Some((live, server, stop_sender)) = live_server {
+        // The final profile is the last snapshot. Now that recording has
+        // ended, Ctrl+C stops the server.
+        live.publish(profile);
+        drop(symbol_manager);
+        finish_quota_manager(runtime, quota_manager);
+        let ctrl_c_receiver = CtrlC::observe_oneshot();
+        runtime.block_on(async {
+            let _ = ctrl_c_receiver.await;
+            let _ = stop_sender.send(());
+            let _ = server.await;
+        });
+        return;
+    }
 
     // Drop the profile so that it doesn't take up memory while the server is running.
     drop(profile);
 
     // Handle --serve flag: start analysis server for AI/CLI workflow
     if record_args.serve {
-        run_analysis_server_for_record(&record_args.output, record_args.symbol_props());
+        run_analysis_server_for_record(runtime, &record_args.output, symbol_manager, quota_manager);
         // Don't exit - server keeps running
         return;
     }
 
     // then fire up the server for the profiler front end, if not save-only
-    if let Some(server_props) = record_args.server_props() {
-        run_server_serving_profile(
+    match server_props {
+        Some(server_props) => run_server_serving_profile(
+            runtime,
             &record_args.output,
             server_props,
-            record_args.symbol_props(),
-        );
+            symbol_manager,
+            quota_manager,
+        ),
+        None => finish_quota_manager(runtime, quota_manager),
     }
 
     std::process::exit(exit_status.code().unwrap_or(0));
 }
 
+/// The post-processing transforms from `--transform` or the config file.
+fn profile_transforms(args: &cli::ProfileCreationArgs) -> Vec<Box<dyn ProfileTransform>> {
+    let config = config::load_config();
+    match shared::transform::transform_chain(&args.transform, &config) {
+        Ok(transforms) => transforms,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            std::process::exit(1);
+        }
+    }
+}
+
 fn convert_file_to_profile(
     input_file: &File,
     input_path: &Path,
@@ -180,7 +581,13 @@ fn convert_file_to_profile(
     if input_path.extension() == Some(OsStr::new("etl")) {
         #[cfg(target_os = "windows")]
         {
-            return windows::import::convert_etl_file_to_profile(input_path, import_props);
+            return match windows::import::convert_etl_file_to_profile(input_path, import_props) {
+                Ok(profile) => profile,
+                Err(error) => {
+                    eprintln!("Error importing ETW trace: {error}");
+                    std::process::exit(1);
+                }
+            };
         }
 
         #[cfg(not(target_os = "windows"))]
@@ -194,20 +601,94 @@ fn convert_file_to_profile(
         }
     }
 
+    if import::instruments::is_trace_bundle(input_path) {
+        let result = import::instruments::export_trace_bundle(input_path)
+            .and_then(|exported| import::instruments::convert(input_path, &exported[..]));
+        return match result {
+            Ok(profile) => profile,
+            Err(error) => {
+                eprintln!("Error importing Instruments trace: {error}");
+                std::process::exit(1);
+            }
+        };
+    }
+
+    let mut first_bytes = [0; 256];
+    let mut file = input_file;
+    let first_bytes_len = file.read(&mut first_bytes).unwrap_or(0);
+    let first_bytes = &first_bytes[..first_bytes_len];
+    let _ = file.seek(std::io::SeekFrom::Start(0));
+    if import::speedscope::is_speedscope_file(input_path, first_bytes) {
+        return match import::speedscope::convert(input_path, BufReader::new(input_file)) {
+            Ok(profile) => profile,
+            Err(error) => {
+                eprintln!("Error importing speedscope file: {error}");
+                std::process::exit(1);
+            }
+        };
+    }
+    if import::jfr::is_jfr_file(input_path, first_bytes) {
+        return match import::jfr::convert(input_path, BufReader::new(input_file)) {
+            Ok(profile) => profile,
+            Err(error) => {
+                eprintln!("Error importing JFR recording: {error}");
+                std::process::exit(1);
+            }
+        };
+    }
+    if import::minidump::is_minidump_file(first_bytes) {
+        return match import::minidump::convert(input_path, BufReader::new(input_file)) {
+            Ok(profile) => profile,
+            Err(error) => {
+                eprintln!("Error importing minidump: {error}");
+                std::process::exit(1);
+            }
+        };
+    }
+    if import::pprof::is_pprof_file(input_path, first_bytes) {
+        return match import::pprof::convert(input_path, BufReader::new(input_file)) {
+            Ok(profile) => profile,
+            Err(error) => {
+                eprintln!("Error importing pprof file: {error}");
+                std::process::exit(1);
+            }
+        };
+    }
+
+    if import::instruments::is_xctrace_export(first_bytes) {
+        return match import::instruments::convert(input_path, BufReader::new(input_file)) {
+            Ok(profile) => profile,
+            Err(error) => {
+                eprintln!("Error importing xctrace export: {error}");
+                std::process::exit(1);
+            }
+        };
+    }
+    if import::dtrace::is_dtrace_file(input_path, first_bytes) {
+        return match import::dtrace::convert(input_path, BufReader::new(input_file)) {
+            Ok(profile) => profile,
+            Err(error) => {
+                eprintln!("Error importing dtrace output: {error}");
+                std::process::exit(1);
+            }
+        };
+    }
+
     // Treat all other files as perf.data files from Linux perf / Android simpleperf.
 
-    let path = input_path
-        .canonicalize()
-        .expect("Couldn't form absolute path");
     let file_meta = input_file.metadata().ok();
     let file_mod_time = file_meta.and_then(|metadata| metadata.modified().ok());
-    let mut binary_lookup_dirs = import_props.symbol_props.symbol_dir;
-    let mut aux_file_lookup_dirs = import_props.aux_file_dir;
-    if let Some(parent_dir) = path.parent() {
-        binary_lookup_dirs.push(parent_dir.into());
-        aux_file_lookup_dirs.push(parent_dir.into());
-    }
     let reader = BufReader::new(input_file);
+    convert_perf_file_to_profile(reader, input_path, file_mod_time, import_props)
+}
+
+fn convert_perf_file_to_profile<R: Read + Seek>(
+    reader: R,
+    input_path: &Path,
+    file_mod_time: Option<SystemTime>,
+    import_props: ImportProps,
+) -> Profile {
+    let (binary_lookup_dirs, aux_file_lookup_dirs) = perf_lookup_dirs(input_path, &import_props);
     match import::perf::convert(
         reader,
         file_mod_time,
@@ -223,10 +704,28 @@ fn convert_file_to_profile(
     }
 }
 
+/// The directories in which the binaries and the auxiliary files of a perf.data
+/// file are looked up: the ones from the command line, and the directory of the
+/// perf.data file.
+fn perf_lookup_dirs(input_path: &Path, import_props: &ImportProps) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let path = input_path
+        .canonicalize()
+        .expect("Couldn't form absolute path");
+    let mut binary_lookup_dirs = import_props.symbol_props.symbol_dir.clone();
+    let mut aux_file_lookup_dirs = import_props.aux_file_dir.clone();
+    if let Some(parent_dir) = path.parent() {
+        binary_lookup_dirs.push(parent_dir.into());
+        aux_file_lookup_dirs.push(parent_dir.into());
+    }
+    (binary_lookup_dirs, aux_file_lookup_dirs)
+}
+
 fn run_server_serving_profile(
+    runtime: &Handle,
     profile_path: &Path,
     server_props: ServerProps,
-    symbol_props: SymbolProps,
+    mut symbol_manager: SymbolManager,
+    quota_manager: Option<QuotaManager>,
 ) {
     let libinfo_map = {
         let profile_file = match File::open(profile_path) {
@@ -241,24 +740,21 @@ fn run_server_serving_profile(
             .expect("Couldn't parse libinfo map from profile file")
     };
 
-    let runtime = tokio::runtime::Builder::new_multi_thread()
-        .enable_all()
-        .build()
-        .unwrap();
-
     runtime.block_on(async {
-        let (mut symbol_manager, quota_manager) =
-            create_symbol_manager_and_quota_manager(symbol_props, server_props.verbose);
+        let mut debug_names = Vec::new();
         for lib_info in libinfo_map.into_values() {
+            debug_names.extend(lib_info.debug_name.clone());
             symbol_manager.add_known_library(lib_info);
         }
 
-        let precog_path = profile_path.with_extension("syms.json");
-        if let Some(precog_info) = shared::symbol_precog::PrecogSymbolInfo::try_load(&precog_path) {
-            for symbol_map in precog_info.into_iter() {
-                let lib_info = symbol_map.library_info();
-                symbol_manager.add_known_library_symbols(lib_info, Arc::new(symbol_map));
-            }
+        if let Err(err) = shared::symbol_precog::add_sidecar_symbols(
+            &mut symbol_manager,
+            profile_path,
+            &debug_names,
+            server_props.require_precog,
+        ) {
+            eprintln!("Error: {err}");
+            std::process::exit(1);
         }
 
         let ctrl_c_receiver = CtrlC::observe_oneshot();
@@ -275,6 +771,7 @@ fn run_server_serving_profile(
             server_props,
             symbol_manager,
             ctrl_c_receiver,
+            false,
         )
         .await;
 
@@ -313,153 +810,215 @@ fn run_server_serving_profile(
     target_os = "linux",
     target_os = "windows"
 ))]
-fn run_analysis_server_for_record(profile_path: &Path, symbol_props: shared::prop_types::SymbolProps) {
-    // Check if a session already exists
-    if session::Session::exists() {
-        if let Ok(existing) = session::Session::load() {
-            if existing.is_server_alive() {
-                eprintln!(
-                    "Error: An analysis server is already running (PID {})",
-                    existing.pid
-                );
-                eprintln!("Stop it first with: samply analyze stop");
-                std::process::exit(1);
-            }
-            // Clean up stale session
-            let _ = session::Session::remove();
-        }
-    }
-
-    let runtime = tokio::runtime::Builder::new_multi_thread()
-        .enable_all()
-        .build()
-        .unwrap();
+fn run_analysis_server_for_record(
+    runtime: &Handle,
+    profile_path: &Path,
+    symbol_manager: SymbolManager,
+    quota_manager: Option<QuotaManager>,
+) {
+    check_no_running_session(None);
 
     runtime.block_on(async {
-        let (symbol_manager, quota_manager) =
-            create_symbol_manager_and_quota_manager(symbol_props, false);
-
         let ctrl_c_receiver = shared::ctrl_c::CtrlC::observe_oneshot();
-
-        // Use default server props with no browser opening
-        let server_props = ServerProps {
-            address: std::net::IpAddr::V4(std::net::Ipv4Addr::new(127, 0, 0, 1)),
-            port_selection: server::PortSelection::TryMultiple(3000..3100),
-            verbose: false,
-            open_in_browser: false,
-        };
-
-        let server_result = server::start_analysis_server(
-            profile_path,
-            server_props,
+        serve_recorded_profile(
+            profile_path.to_path_buf(),
+            None,
             symbol_manager,
+            quota_manager,
             ctrl_c_receiver,
         )
         .await;
+    });
+}
 
-        let server_info = match server_result {
-            Ok(info) => info,
-            Err(e) => {
-                eprintln!("Error loading profile: {}", e);
-                std::process::exit(1);
-            }
-        };
+/// Run the analysis server of `record --serve` until `stop_signal` fires.
+/// With `live` (`record --live`), the profile is served from its snapshots
+/// until it's been written, so this runs while recording.
+#[cfg(any(
+    target_os = "android",
+    target_os = "macos",
+    target_os = "linux",
+    target_os = "windows"
+))]
+async fn serve_recorded_profile(
+    profile_path: PathBuf,
+    live: Option<Arc<LiveProfile>>,
+    symbol_manager: SymbolManager,
+    quota_manager: Option<QuotaManager>,
+    stop_signal: shared::ctrl_c::Receiver,
+) {
+    // Use default server props with no browser opening
+    let server_props = ServerProps {
+        address: std::net::IpAddr::V4(std::net::Ipv4Addr::new(127, 0, 0, 1)),
+        port_selection: server::PortSelection::TryMultiple(3000..3100),
+        verbose: false,
+        open_in_browser: false,
+        token: server::ServerToken::Random,
+        max_body_size: server::DEFAULT_MAX_BODY_SIZE,
+        sandbox: false,
+        thread_subset: None,
+        require_precog: false,
+        tls: None,
+        auth_token: None,
+    };
 
-        // Save session file
-        let sess = session::Session::new(
-            server_info.token_url.clone(),
-            profile_path.to_string_lossy().to_string(),
-        );
-        if let Err(e) = sess.save() {
-            eprintln!("Warning: Could not save session file: {}", e);
+    let is_live = live.is_some();
+    let server_result = server::start_analysis_server(
+        &[profile_path.clone()],
+        None,
+        server_props,
+        symbol_manager,
+        stop_signal,
+        false,
+        live,
+    )
+    .await;
+
+    let server_info = match server_result {
+        Ok(info) => info,
+        Err(e) => {
+            eprintln!("Error loading profile: {}", e);
+            std::process::exit(1);
         }
+    };
 
-        // Warn if profile appears unsymbolicated
-        if server_info.is_likely_unsymbolicated {
-            eprintln!();
-            eprintln!("WARNING: Profile appears to be unsymbolicated (function names are hex addresses).");
-            eprintln!("         Query results will show addresses like '0x1efcfc' instead of function names.");
-            eprintln!("         To get readable results, re-record with presymbolication (enabled by default)");
-            eprintln!("         or use 'samply import' to symbolicate an existing profile.");
-            eprintln!();
-        }
+    // Save session file
+    let sess = session::Session::new(
+        server_info.token_url.clone(),
+        profile_path.to_string_lossy().to_string(),
+        server_info.profiler_url.clone(),
+    );
+    if let Err(e) = sess.save() {
+        eprintln!("Warning: Could not save session file: {}", e);
+    }
 
-        eprintln!("Analysis server running at {}", server_info.server_origin);
-        eprintln!("Session file: {:?}", session::Session::session_file_path());
-        eprintln!();
-        eprintln!("Available query commands:");
-        eprint!("{}", cli::get_query_help());
+    // Warn if profile appears unsymbolicated. Live snapshots always are.
+    if server_info.is_likely_unsymbolicated && !is_live {
         eprintln!();
-        eprintln!("Run 'samply query --help' for detailed usage.");
-        eprintln!("Run 'samply analyze stop' to stop the server.");
+        eprintln!(
+            "WARNING: Profile appears to be unsymbolicated (function names are hex addresses)."
+        );
+        eprintln!(
+            "         Query results will show addresses like '0x1efcfc' instead of function names."
+        );
+        eprintln!("         To get readable results, re-record with presymbolication (enabled by default)");
+        eprintln!("         or use 'samply import' to symbolicate an existing profile.");
+        eprintln!(
+            "         Run 'samply query symbolication-status' to see which libraries lack symbols."
+        );
         eprintln!();
+    }
+
+    eprintln!("Analysis server running at {}", server_info.server_origin);
+    eprintln!(
+        "Session file: {:?}",
+        session::Session::session_file_path(None)
+    );
+    if is_live {
+        eprintln!(
+            "Serving a snapshot of the profile every {} seconds while recording.",
+            shared::live_profile::SNAPSHOT_INTERVAL.as_secs()
+        );
+        eprintln!("Function names show as addresses in queries until recording ends.");
+    }
+    eprintln!();
+    eprintln!("Available query commands:");
+    eprint!("{}", cli::get_query_help());
+    eprintln!();
+    eprintln!("Run 'samply query --help' for detailed usage.");
+    eprintln!("Run 'samply analyze stop' to stop the server.");
+    eprintln!();
+    if is_live {
+        eprintln!("Once recording has ended, press Ctrl+C to stop.");
+    } else {
         eprintln!("Press Ctrl+C to stop.");
+    }
 
-        // Run server until stopped
-        if let Err(e) = server_info.server_join_handle.await {
-            eprintln!("Server error: {}", e);
-        }
+    // Run server until stopped
+    if let Err(e) = server_info.server_join_handle.await {
+        eprintln!("Server error: {}", e);
+    }
 
-        // Clean up session file
-        let _ = session::Session::remove();
+    // Clean up session file
+    let _ = session::Session::remove(None);
 
-        if let Some(quota_manager) = quota_manager {
-            quota_manager.finish().await;
+    if let Some(quota_manager) = quota_manager {
+        quota_manager.finish().await;
+    }
+}
+
+/// Exit if the session's server is already running; remove its session file
+/// if the server is gone.
+fn check_no_running_session(name: Option<&str>) {
+    if !session::Session::exists(name) {
+        return;
+    }
+    if let Ok(existing) = session::Session::load(name) {
+        if existing.is_server_alive() {
+            eprintln!(
+                "Error: An analysis server is already running (PID {})",
+                existing.pid
+            );
+            match name {
+                Some(name) => {
+                    eprintln!("Stop it first with: samply analyze stop --session {}", name);
+                    eprintln!("or pick another name with --session.");
+                }
+                None => {
+                    eprintln!("Stop it first with: samply analyze stop");
+                    eprintln!("or run this one as a named session with --session NAME.");
+                }
+            }
+            std::process::exit(1);
         }
-    });
+        // Clean up stale session
+        let _ = session::Session::remove(name);
+    }
 }
 
 // ============================================================================
 // Analyze command handlers
 // ============================================================================
 
-fn do_analyze_action(analyze_args: cli::AnalyzeArgs) {
+fn do_analyze_action(analyze_args: cli::AnalyzeArgs, runtime: &Handle) {
     match analyze_args.command {
-        cli::AnalyzeCommand::Serve(args) => do_analyze_serve(args),
-        cli::AnalyzeCommand::Stop => do_analyze_stop(),
+        cli::AnalyzeCommand::Serve(args) => do_analyze_serve(args, runtime),
+        cli::AnalyzeCommand::Mcp(args) => do_analyze_mcp(args, runtime),
+        cli::AnalyzeCommand::Export(args) => do_analyze_export(args),
+        cli::AnalyzeCommand::Otlp(args) => do_analyze_otlp(args, runtime),
+        cli::AnalyzeCommand::Assert(args) => do_analyze_assert(args),
+        cli::AnalyzeCommand::Stop(args) => do_analyze_stop(args),
+        cli::AnalyzeCommand::List => do_analyze_list(),
+        cli::AnalyzeCommand::Schema => do_analyze_schema(),
     }
 }
 
-fn do_analyze_serve(args: cli::AnalyzeServeArgs) {
-    let profile_path = &args.file;
-
-    if !profile_path.exists() {
-        eprintln!("Error: Profile file not found: {:?}", profile_path);
-        std::process::exit(1);
-    }
-
-    // Check if a session already exists
-    if session::Session::exists() {
-        if let Ok(existing) = session::Session::load() {
-            if existing.is_server_alive() {
-                eprintln!(
-                    "Error: An analysis server is already running (PID {})",
-                    existing.pid
-                );
-                eprintln!("Stop it first with: samply analyze stop");
-                std::process::exit(1);
-            }
-            // Clean up stale session
-            let _ = session::Session::remove();
+fn do_analyze_serve(args: cli::AnalyzeServeArgs, runtime: &Handle) {
+    for profile_path in &args.files {
+        if !profile_path.exists() {
+            eprintln!("Error: Profile file not found: {:?}", profile_path);
+            std::process::exit(1);
         }
     }
 
-    let runtime = tokio::runtime::Builder::new_multi_thread()
-        .enable_all()
-        .build()
-        .unwrap();
+    check_no_running_session(args.session.as_deref());
 
     runtime.block_on(async {
-        let (symbol_manager, quota_manager) =
-            create_symbol_manager_and_quota_manager(args.symbol_props(), args.server_props().verbose);
+        let (symbol_manager, quota_manager) = create_symbol_manager_and_quota_manager(
+            runtime,
+            args.symbol_props(),
+            args.server_props().verbose,
+        );
 
         let ctrl_c_receiver = shared::ctrl_c::CtrlC::observe_oneshot();
 
         let server_result = server::start_analysis_server(
-            profile_path,
+            &args.files,
+            args.baseline.as_deref(),
             args.server_props(),
             symbol_manager,
             ctrl_c_receiver,
+            args.low_memory,
         )
         .await;
 
@@ -472,10 +1031,28 @@ fn do_analyze_serve(args: cli::AnalyzeServeArgs) {
         };
 
         // Save session file
-        let sess = session::Session::new(
+        let mut sess = session::Session::new(
             server_info.token_url.clone(),
-            profile_path.to_string_lossy().to_string(),
+            args.files[0].to_string_lossy().to_string(),
+            server_info.profiler_url.clone(),
         );
+        sess.name = args.session.clone();
+        sess.profiles = server_info
+            .profiles
+            .iter()
+            .map(|profile| session::SessionProfile {
+                id: profile.id.clone(),
+                profile_path: profile.path.to_string_lossy().to_string(),
+                profiler_url: profile.profiler_url.clone(),
+            })
+            .collect();
+        sess.tls_cert = args
+            .server_args
+            .tls_cert
+            .as_ref()
+            .map(|cert| std::path::absolute(cert).unwrap_or_else(|_| cert.clone()))
+            .map(|cert| cert.to_string_lossy().to_string());
+        sess.auth_token = args.server_args.auth_token();
         if let Err(e) = sess.save() {
             eprintln!("Warning: Could not save session file: {}", e);
         }
@@ -487,16 +1064,38 @@ fn do_analyze_serve(args: cli::AnalyzeServeArgs) {
             eprintln!("         Query results will show addresses like '0x1efcfc' instead of function names.");
             eprintln!("         To get readable results, re-record with presymbolication (enabled by default)");
             eprintln!("         or use 'samply import' to symbolicate an existing profile.");
+            eprintln!("         Run 'samply query symbolication-status' to see which libraries lack symbols.");
             eprintln!();
         }
 
         eprintln!("Analysis server running at {}", server_info.server_origin);
-        eprintln!("Session file: {:?}", session::Session::session_file_path());
+        eprintln!(
+            "Session file: {:?}",
+            session::Session::session_file_path(args.session.as_deref())
+        );
+        if let Some(name) = &args.session {
+            eprintln!("Query this server with 'samply query --session {} ...'.", name);
+        }
+        if server_info.profiles.len() > 1 {
+            eprintln!();
+            eprintln!("Profiles (query one with 'samply query --profile <id> ...'):");
+            for profile in &server_info.profiles {
+                eprintln!("    {:<20} {:?}", profile.id, profile.path);
+            }
+        }
         eprintln!();
         eprintln!("Available query commands:");
         eprint!("{}", cli::get_query_help());
         eprintln!();
         eprintln!("Run 'samply query --help' for detailed usage.");
+        eprintln!(
+            "The OpenAPI description of the endpoints is at {}/query/schema.",
+            server_info.token_url
+        );
+        eprintln!(
+            "Browse the hotspots, flame graph and call trees at {}/ui.",
+            server_info.token_url
+        );
         eprintln!();
         eprintln!("Press Ctrl+C to stop.");
 
@@ -506,7 +1105,7 @@ fn do_analyze_serve(args: cli::AnalyzeServeArgs) {
         }
 
         // Clean up session file
-        let _ = session::Session::remove();
+        let _ = session::Session::remove(args.session.as_deref());
 
         if let Some(quota_manager) = quota_manager {
             quota_manager.finish().await;
@@ -514,47 +1113,473 @@ fn do_analyze_serve(args: cli::AnalyzeServeArgs) {
     });
 }
 
-fn do_analyze_stop() {
-    let session = match session::Session::load() {
+fn do_analyze_mcp(args: cli::AnalyzeMcpArgs, runtime: &Handle) {
+    let profile_path = &args.file;
+
+    if !profile_path.exists() {
+        eprintln!("Error: Profile file not found: {:?}", profile_path);
+        std::process::exit(1);
+    }
+
+    let activity_patterns = config::load_config().activity_patterns();
+    let load = |path: &Path| {
+        let result = if args.low_memory {
+            samply_analysis::ProfileAnalyzer::from_file_low_memory(path, server::report_index_build)
+        } else {
+            samply_analysis::ProfileAnalyzer::from_file(path)
+        };
+        let mut analyzer = result.unwrap_or_else(|e| {
+            eprintln!("Error loading profile: {}", e);
+            std::process::exit(1);
+        });
+        analyzer.set_activity_patterns(activity_patterns.clone());
+        analyzer
+    };
+    let analyzer = load(profile_path);
+    let baseline = args.baseline.as_deref().map(load);
+
+    let (mut symbol_manager, _quota_manager) =
+        create_symbol_manager_and_quota_manager(runtime, args.symbol_props(), false);
+    let sidecar_maps =
+        server::register_profile_libraries(&mut symbol_manager, profile_path, args.require_precog)
+            .unwrap_or_else(|err| {
+                eprintln!("Error: {err}");
+                std::process::exit(1);
+            });
+
+    eprintln!("MCP server ready on stdin/stdout for {:?}", profile_path);
+    if let Err(e) = mcp::run(runtime, analyzer, baseline, symbol_manager, sidecar_maps) {
+        eprintln!("MCP server error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn do_analyze_export(args: cli::AnalyzeExportArgs) {
+    let analyzer = samply_analysis::ProfileAnalyzer::from_file(&args.file).unwrap_or_else(|e| {
+        eprintln!("Error loading profile: {}", e);
+        std::process::exit(1);
+    });
+    let bytes = analyzer.export_bytes(
+        args.format,
+        args.thread.as_deref(),
+        args.time_range.time_range(),
+    );
+
+    let written = match &args.output {
+        Some(path) if path.extension() == Some(OsStr::new("gz")) => {
+            File::create(path).and_then(|file| {
+                use std::io::Write;
+                let mut gz = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+                gz.write_all(&bytes)?;
+                gz.finish().map(|_| ())
+            })
+        }
+        Some(path) => std::fs::write(path, &bytes),
+        None => {
+            use std::io::Write;
+            std::io::stdout().write_all(&bytes)
+        }
+    };
+    if let Err(e) = written {
+        eprintln!("Couldn't write the export: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn do_analyze_otlp(args: cli::AnalyzeOtlpArgs, runtime: &Handle) {
+    let endpoint = args
+        .otlp_endpoint
+        .or_else(|| std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok())
+        .filter(|endpoint| !endpoint.is_empty());
+    let Some(endpoint) = endpoint.or(args.dry_run.then(String::new)) else {
+        eprintln!(
+            "No collector to send to: pass --otlp-endpoint URL or set OTEL_EXPORTER_OTLP_ENDPOINT."
+        );
+        std::process::exit(1);
+    };
+    let mut header_values = args.otlp_header;
+    header_values.extend(std::env::var("OTEL_EXPORTER_OTLP_HEADERS").ok());
+    let headers = otlp::parse_headers(&header_values).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let service_name = args
+        .service_name
+        .or_else(|| std::env::var("OTEL_SERVICE_NAME").ok());
+
+    let analyzer = samply_analysis::ProfileAnalyzer::from_file(&args.file).unwrap_or_else(|e| {
+        eprintln!("Error loading profile: {}", e);
+        std::process::exit(1);
+    });
+    let now_unix_nano = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+    let export = analyzer.otlp_export(
+        service_name.as_deref(),
+        args.limit,
+        args.thread.as_deref(),
+        args.time_range.time_range(),
+        now_unix_nano,
+    );
+
+    if args.dry_run {
+        let output = serde_json::json!({ "metrics": export.metrics, "logs": export.logs });
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&output).unwrap_or_default()
+        );
+        return;
+    }
+    if let Err(e) = otlp::send(runtime, &endpoint, &headers, &export) {
+        eprintln!("Couldn't send the OTLP export: {}", e);
+        std::process::exit(1);
+    }
+    eprintln!("Sent metrics and logs to {}", endpoint);
+}
+
+fn do_analyze_assert(args: cli::AnalyzeAssertArgs) {
+    let budgets = std::fs::read_to_string(&args.budgets).unwrap_or_else(|e| {
+        eprintln!("Couldn't read {:?}: {}", args.budgets, e);
+        std::process::exit(1);
+    });
+    let budgets: samply_analysis::Budgets = toml::from_str(&budgets).unwrap_or_else(|e| {
+        eprintln!("Invalid budgets file {:?}: {}", args.budgets, e);
+        std::process::exit(1);
+    });
+    let analyzer = samply_analysis::ProfileAnalyzer::from_file(&args.file).unwrap_or_else(|e| {
+        eprintln!("Error loading profile: {}", e);
+        std::process::exit(1);
+    });
+    let response = analyzer
+        .check_budgets(&budgets, args.time_range.time_range())
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&response).unwrap_or_default()
+    );
+    if !response.passed {
+        eprintln!(
+            "{} of {} budgets exceeded",
+            response.failed,
+            response.budgets.len()
+        );
+        std::process::exit(1);
+    }
+}
+
+fn do_open_action(args: cli::OpenArgs) {
+    let session = match session::Session::load(args.session.as_deref()) {
         Ok(s) => s,
         Err(e) => {
             eprintln!("No active analysis session found: {}", e);
             std::process::exit(1);
         }
     };
+    if !session.is_server_alive() {
+        eprintln!(
+            "The analysis server for {} is no longer running (PID {}).",
+            session.profile_path, session.pid
+        );
+        eprintln!(
+            "Start it again with: samply analyze serve {}",
+            session.profile_path
+        );
+        std::process::exit(1);
+    }
+    let profiler_url = match &args.profile {
+        Some(id) => match session.profiles.iter().find(|profile| profile.id == *id) {
+            Some(profile) => Some(profile.profiler_url.clone()),
+            None => {
+                eprintln!("The analysis server has no profile with id '{}'.", id);
+                std::process::exit(1);
+            }
+        },
+        None => session.profiler_url,
+    };
+    let Some(profiler_url) = profiler_url else {
+        eprintln!("The session file has no profiler URL; restart the analysis server.");
+        std::process::exit(1);
+    };
+
+    eprintln!("Opening {}", profiler_url);
+    if let Err(e) = opener::open_browser(&profiler_url) {
+        eprintln!("Couldn't open the browser: {}", e);
+        println!("{}", profiler_url);
+        std::process::exit(1);
+    }
+}
 
-    // Try to kill the server process
+fn do_overlay_action(args: cli::OverlayArgs) {
+    let mut profile = match read_profile_json(&args.profile) {
+        Ok(profile) => profile,
+        Err(err) => {
+            eprintln!("Could not read profile {:?}: {err}", args.profile);
+            std::process::exit(1)
+        }
+    };
+    let stats = match import::strace::overlay_log_file(&mut profile, &args.log, args.pid) {
+        Ok(stats) => stats,
+        Err(err) => {
+            eprintln!("Could not overlay {:?}: {err}", args.log);
+            std::process::exit(1)
+        }
+    };
+    for (tid, calls) in &stats.unknown_threads {
+        eprintln!("Skipped {calls} calls of thread {tid}, which isn't in the profile.");
+    }
+    if stats.skipped_lines > 0 {
+        eprintln!("Skipped {} lines which aren't calls.", stats.skipped_lines);
+    }
+
+    let output = args.output.as_ref().unwrap_or(&args.profile);
+    shared::save_profile::save_profile_json_to_file(&profile, output).expect("Couldn't write JSON");
+    eprintln!("Added {} markers to {output:?}.", stats.markers);
+}
+
+/// Read a processed profile JSON file, which may be gzipped.
+fn read_profile_json(path: &Path) -> std::io::Result<serde_json::Value> {
+    let file = BufReader::new(File::open(path)?);
+    let profile = if path.extension() == Some(OsStr::new("gz")) {
+        serde_json::from_reader(flate2::read::GzDecoder::new(file))?
+    } else {
+        serde_json::from_reader(file)?
+    };
+    Ok(profile)
+}
+
+fn do_analyze_stop(args: cli::AnalyzeStopArgs) {
+    let session = match session::Session::load(args.session.as_deref()) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("No active analysis session found: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Ask the server to shut down, and kill it if it doesn't.
+    if session.is_server_alive() {
+        let stopped = match query_client::QueryClient::from_session(args.session.as_deref())
+            .and_then(|client| client.shutdown())
+        {
+            Ok(()) => wait_for_server_exit(&session, Duration::from_secs(5)),
+            Err(e) => {
+                eprintln!("Could not ask the server to shut down: {}", e);
+                false
+            }
+        };
+        if !stopped {
+            kill_server(session.pid);
+        }
+    }
+
+    // Remove session file
+    if let Err(e) = session::Session::remove(args.session.as_deref()) {
+        eprintln!("Warning: Could not remove session file: {}", e);
+    }
+
+    eprintln!("Analysis server stopped.");
+}
+
+/// Wait at most `timeout` for the server process of `session` to exit.
+fn wait_for_server_exit(session: &session::Session, timeout: Duration) -> bool {
+    let start = std::time::Instant::now();
+    while session.is_server_alive() {
+        if start.elapsed() > timeout {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    true
+}
+
+fn kill_server(pid: u32) {
     #[cfg(unix)]
     {
         use std::process::Command;
-        let _ = Command::new("kill")
-            .args([&session.pid.to_string()])
-            .status();
+        let _ = Command::new("kill").args([&pid.to_string()]).status();
     }
 
     #[cfg(windows)]
     {
         use std::process::Command;
         let _ = Command::new("taskkill")
-            .args(["/PID", &session.pid.to_string(), "/F"])
+            .args(["/PID", &pid.to_string(), "/F"])
             .status();
     }
+}
 
-    // Remove session file
-    if let Err(e) = session::Session::remove() {
-        eprintln!("Warning: Could not remove session file: {}", e);
+/// Print the sessions whose server is still running, as JSON.
+fn do_analyze_list() {
+    let sessions: Vec<session::Session> = session::Session::list()
+        .into_iter()
+        .filter(|session| session.is_server_alive())
+        .collect();
+    let output = serde_json::json!({ "sessions": sessions });
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&output).unwrap_or_default()
+    );
+}
+
+fn do_analyze_schema() {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&query_schema::openapi()).unwrap_or_default()
+    );
+}
+
+// ============================================================================
+// Symbols command handlers
+// ============================================================================
+
+fn do_symbols_action(symbols_args: cli::SymbolsArgs) {
+    match symbols_args.command {
+        cli::SymbolsCommand::Cache(args) => do_symbols_cache(args),
+        cli::SymbolsCommand::ConvertSidecar(args) => do_symbols_convert_sidecar(args),
     }
+}
 
-    eprintln!("Analysis server stopped.");
+/// Print the libraries in the lookup cache, or the cached addresses of one of
+/// them, as JSON.
+fn do_symbols_cache(args: cli::SymbolsCacheArgs) {
+    let Some(lookup_cache) = symbols::lookup_cache(None) else {
+        eprintln!("Error: Could not find the cache directory.");
+        std::process::exit(1);
+    };
+    let path = lookup_cache.dir();
+    let output = if args.clear {
+        match lookup_cache.clear() {
+            Ok(count) => serde_json::json!({ "path": path, "cleared_libraries": count }),
+            Err(err) => {
+                eprintln!("Could not clear the lookup cache at {path:?}: {err}");
+                std::process::exit(1);
+            }
+        }
+    } else if let Some(library) = &args.library {
+        let entries: Vec<serde_json::Value> = lookup_cache
+            .entries(library)
+            .into_iter()
+            .map(|(debug_id, rva, info)| {
+                serde_json::json!({
+                    "debug_id": debug_id,
+                    "rva": format!("0x{rva:x}"),
+                    "info": info,
+                })
+            })
+            .collect();
+        serde_json::json!({ "path": path, "library": library, "entries": entries })
+    } else {
+        let libraries = lookup_cache.libraries();
+        let total_size_bytes: u64 = libraries.iter().map(|library| library.size_bytes).sum();
+        serde_json::json!({
+            "path": path,
+            "total_size_bytes": total_size_bytes,
+            "libraries": libraries,
+        })
+    };
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&output).unwrap_or_default()
+    );
+}
+
+/// Write a `.syms.json` symbols sidecar in the binary v2 format.
+fn do_symbols_convert_sidecar(args: cli::SymbolsConvertSidecarArgs) {
+    let precog_info = match shared::symbol_precog::PrecogSymbolInfo::try_load(&args.file) {
+        Ok(Some(precog_info)) => precog_info,
+        Ok(None) => {
+            eprintln!("Error: {:?} doesn't exist", args.file);
+            std::process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("Error: Could not read {:?}: {err}", args.file);
+            std::process::exit(1);
+        }
+    };
+    let output_path = args
+        .output
+        .unwrap_or_else(|| args.file.with_extension("bin"));
+    let result = File::create(&output_path).and_then(|file| {
+        use std::io::Write;
+        let mut writer = std::io::BufWriter::new(file);
+        precog_info.write_v2(&mut writer)?;
+        writer.flush()
+    });
+    if let Err(err) = result {
+        eprintln!("Error: Could not write {output_path:?}: {err}");
+        std::process::exit(1);
+    }
+    eprintln!("Wrote {output_path:?}");
 }
 
 // ============================================================================
 // Query command handlers
 // ============================================================================
 
+/// Print (or write to `--output`) the exported text itself rather than the
+/// JSON response, so that it can be piped into other tools.
+fn do_query_export(client: &query_client::QueryClient, args: cli::ExportArgs) {
+    if args.format.is_binary() {
+        eprintln!(
+            "The '{}' format is binary; convert the profile file with 'samply-for-ai analyze export --format {}' instead.",
+            args.format, args.format
+        );
+        std::process::exit(1);
+    }
+    let response = client
+        .query_export(args.format, args.thread.as_deref(), args.time_range.time_range())
+        .and_then(|json| {
+            serde_json::from_str::<serde_json::Value>(&json)
+                .map_err(|e| query_client::QueryError::InvalidResponse(e.to_string()))
+        });
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("Query failed: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let Some(output) = response["data"]["output"].as_str() else {
+        eprintln!(
+            "Export failed: {}",
+            response["error"].as_str().unwrap_or("invalid response")
+        );
+        std::process::exit(1);
+    };
+
+    let written = match &args.output {
+        Some(path) => std::fs::write(path, output),
+        None => {
+            use std::io::Write;
+            std::io::stdout().write_all(output.as_bytes())
+        }
+    };
+    if let Err(e) = written {
+        eprintln!("Couldn't write the export: {}", e);
+        std::process::exit(1);
+    }
+}
+
 fn do_query_action(query_args: cli::QueryArgs) {
-    let client = match query_client::QueryClient::from_session() {
-        Ok(c) => c,
+    let client = query_client::QueryClient::from_server_or_session(
+        query_args.server.as_deref(),
+        query_args.session.as_deref(),
+    )
+    .map(|client| client.with_auth_token(std::env::var("SAMPLY_AUTH_TOKEN").ok()))
+    .and_then(|client| client.with_timeouts(query_args.connect_timeout, query_args.timeout));
+    let client = match client {
+        Ok(c) => c
+            .with_profile(query_args.profile)
+            .with_process(query_args.process)
+            .with_merge_threads(query_args.merge_threads)
+            .with_language_frames(query_args.language_frames)
+            .with_precision(query_args.precision)
+            .with_page(query_args.offset, query_args.cursor)
+            .with_max_bytes(query_args.max_bytes),
         Err(e) => {
             eprintln!("Error: {}", e);
             eprintln!("Make sure an analysis server is running: samply analyze serve <profile>");
@@ -563,32 +1588,147 @@ fn do_query_action(query_args: cli::QueryArgs) {
     };
 
     let result = match query_args.command {
+        cli::QueryCommand::Export(args) => {
+            do_query_export(&client, args);
+            return;
+        }
         cli::QueryCommand::Hotspots(args) => {
-            client.query_hotspots(args.limit, args.thread.as_deref(), args.show_lines, args.show_addresses)
+            let query = || {
+                client.query_hotspots(
+                    args.limit,
+                    args.thread.as_deref(),
+                    args.show_lines,
+                    args.show_addresses,
+                    args.context,
+                    args.groupby,
+                    &args.own_libraries,
+                    &args.metric,
+                    args.time_range.time_range(),
+                )
+            };
+            if let Some(interval) = args.watch {
+                if args.groupby != samply_analysis::HotspotsGroupBy::Function {
+                    eprintln!("Error: --watch only shows hotspots grouped by function.");
+                    std::process::exit(1);
+                }
+                query_watch::watch_hotspots(interval, query);
+                return;
+            }
+            query()
+        }
+        cli::QueryCommand::Threads(args) => {
+            client.query_threads(args.limit, args.time_range.time_range())
         }
+        cli::QueryCommand::Processes(args) => {
+            client.query_processes(args.limit, args.time_range.time_range())
+        }
+        cli::QueryCommand::Markers(args) => client.query_markers(
+            args.name.as_deref(),
+            args.thread.as_deref(),
+            args.limit,
+            args.time_range.time_range(),
+        ),
+        cli::QueryCommand::ByTrace(args) => client.query_by_trace(
+            args.trace_id.as_deref(),
+            args.thread.as_deref(),
+            args.limit,
+            args.time_range.time_range(),
+        ),
         cli::QueryCommand::Callers(args) => {
-            client.query_callers(&args.function, args.depth, args.limit)
+            client.query_callers(&args.function, args.match_mode, args.depth, args.limit, args.time_range.time_range())
         }
         cli::QueryCommand::Callees(args) => {
-            client.query_callees(&args.function, args.depth, args.limit)
+            client.query_callees(&args.function, args.match_mode, args.depth, args.limit, args.time_range.time_range())
+        }
+        cli::QueryCommand::Summary(args) => client.query_summary(args.time_range.time_range()),
+        cli::QueryCommand::StackStats(args) => {
+            client.query_stack_stats(args.thread.as_deref(), args.time_range.time_range())
+        }
+        cli::QueryCommand::Categories(args) => client.query_categories(
+            args.limit,
+            args.thread.as_deref(),
+            args.time_range.time_range(),
+        ),
+        cli::QueryCommand::WaitGraph(args) => client.query_wait_graph(
+            args.limit,
+            args.thread.as_deref(),
+            args.time_range.time_range(),
+        ),
+        cli::QueryCommand::Signals(args) => client.query_signals(
+            args.limit,
+            args.thread.as_deref(),
+            args.time_range.time_range(),
+        ),
+        cli::QueryCommand::Allocations(args) => client.query_allocations(
+            args.limit,
+            args.thread.as_deref(),
+            args.time_range.time_range(),
+        ),
+        cli::QueryCommand::Growth(args) => client.query_growth(
+            args.limit,
+            args.windows,
+            args.thread.as_deref(),
+            args.time_range.time_range(),
+        ),
+        cli::QueryCommand::Crash(args) => client.query_crash(args.limit),
+        cli::QueryCommand::Diff(args) => client.query_diff(args.limit, args.baseline.as_deref()),
+        cli::QueryCommand::DiffExplain(args) => {
+            client.query_diff_explain(args.limit, args.baseline.as_deref())
+        }
+        cli::QueryCommand::Profiles => client.query_profiles(),
+        cli::QueryCommand::Calltree(args) => client.query_calltree(
+            args.thread.as_deref(),
+            args.min_percent,
+            args.max_depth,
+            args.max_children,
+            args.time_range.time_range(),
+        ),
+        cli::QueryCommand::Flamegraph(args) => {
+            client.query_flamegraph(args.thread.as_deref(), args.inverted, args.min_percent)
+        }
+        cli::QueryCommand::Source(args) => {
+            client.query_source(&args.function, args.match_mode, args.context)
         }
-        cli::QueryCommand::Summary => client.query_summary(),
         cli::QueryCommand::Asm(args) => {
-            client.query_asm(&args.function)
+            client.query_asm(&args.function, args.match_mode)
         }
         cli::QueryCommand::Drilldown(args) => {
-            client.query_drilldown(&args.function, args.depth, args.threshold)
+            client.query_drilldown(&args.function, args.match_mode, args.depth, args.threshold)
         }
+        cli::QueryCommand::SymbolicationStatus => client.query_symbolication_status(),
     };
 
     match result {
-        Ok(json) => {
-            // Pretty print the JSON
-            if let Ok(value) = serde_json::from_str::<serde_json::Value>(&json) {
+        Ok(json) => match serde_json::from_str::<serde_json::Value>(&json) {
+            Ok(value) if query_args.output_format == cli::QueryOutputArg::Json => {
+                // Pretty print the JSON
                 println!("{}", serde_json::to_string_pretty(&value).unwrap_or(json));
-            } else {
-                println!("{}", json);
             }
+            Ok(value) if value["success"] == false => {
+                eprintln!(
+                    "Query failed: {}",
+                    value["error"].as_str().unwrap_or("unknown error")
+                );
+                std::process::exit(1);
+            }
+            Ok(value) => print!(
+                "{}",
+                query_output::render(
+                    &value,
+                    query_args.output_format,
+                    cli_utils::terminal_width()
+                )
+            ),
+            Err(_) => println!("{}", json),
+        },
+        // Scripts reading the JSON get the error code and candidates as well.
+        Err(query_client::QueryError::Failed(failure))
+            if query_args.output_format == cli::QueryOutputArg::Json =>
+        {
+            let response: serde_json::Value =
+                serde_json::from_str(&failure.to_json()).unwrap_or_default();
+            println!("{:#}", response);
+            std::process::exit(1);
         }
         Err(e) => {
             eprintln!("Query failed: {}", e);
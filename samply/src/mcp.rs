@@ -0,0 +1,905 @@
+//! MCP (Model Context Protocol) server mode for AI agents.
+//!
+//! `samply analyze mcp` speaks JSON-RPC 2.0 over stdin/stdout, one message per
+//! line, and exposes the analysis queries as MCP tools. Tool calls are answered
+//! by the same handler as the HTTP server's `/query/*` endpoints, so the JSON
+//! an agent gets back is identical to what `samply query` prints.
+//!
+//! stdout carries only protocol messages; all logging goes to stderr.
+
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+
+use samply_analysis::plugin::{find_query_plugin, query_plugins, QueryPlugin};
+use samply_analysis::ProfileAnalyzer;
+use serde_json::{json, Value};
+use wholesym::SymbolManager;
+
+use crate::server::{
+    check_library_symbols, handle_query_request, lookup_asm_symbol, match_mode_param,
+    SymbolicationStatusTracker,
+};
+use crate::shared::symbol_precog::PrecogLibraySymbolMap;
+
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// An MCP tool and the query endpoint which answers it.
+pub(crate) struct Tool {
+    pub name: &'static str,
+    pub query_path: &'static str,
+    pub description: &'static str,
+    /// (name, JSON schema type, description, required)
+    pub params: &'static [(&'static str, &'static str, &'static str, bool)],
+    /// The shape of the response's `data`, for `/query/schema`
+    pub data: Data,
+}
+
+/// The top-level fields of a query response's `data`: (name, JSON schema
+/// type), where `"string|null"` is a string which may also be null or absent.
+pub(crate) enum Data {
+    Object(&'static [(&'static str, &'static str)]),
+    /// An array of objects with these fields
+    Array(&'static [(&'static str, &'static str)]),
+    /// One of these, depending on the parameters
+    OneOf(&'static [Data]),
+}
+
+const FUNCTION_PARAM: (&str, &str, &str, bool) = (
+    "function",
+    "string",
+    "Function name or substring to match",
+    true,
+);
+
+const MATCH_PARAM: (&str, &str, &str, bool) = (
+    "match",
+    "string",
+    "How 'function' is matched: 'substring' (default), 'exact' or 'regex'. \
+     Names are also compared demangled and without generic arguments",
+    false,
+);
+
+const START_MS_PARAM: (&str, &str, &str, bool) = (
+    "start_ms",
+    "number",
+    "Only count samples at or after this time (ms since profile start)",
+    false,
+);
+
+const END_MS_PARAM: (&str, &str, &str, bool) = (
+    "end_ms",
+    "number",
+    "Only count samples before this time (ms since profile start)",
+    false,
+);
+
+const PROCESS_PARAM: (&str, &str, &str, bool) = (
+    "process",
+    "string",
+    "Only include the threads of this process, given as its pid or a substring of its name",
+    false,
+);
+
+const MERGE_THREADS_PARAM: (&str, &str, &str, bool) = (
+    "merge_threads",
+    "string",
+    "Treat the threads whose name matches this regex (e.g. a worker pool) as one thread; \
+     the response breaks their samples down per thread",
+    false,
+);
+
+const LANGUAGE_FRAMES_PARAM: (&str, &str, &str, bool) = (
+    "language_frames",
+    "boolean",
+    "Insert a pseudo-frame like '[JavaScript]' or '[CPython]' as the caller of every run of \
+     JIT or interpreter frames of one language",
+    false,
+);
+const OFFSET_PARAM: (&str, &str, &str, bool) = (
+    "offset",
+    "integer",
+    "Number of ranked items to skip, to read the results a page of 'limit' items at a time",
+    false,
+);
+const CURSOR_PARAM: (&str, &str, &str, bool) = (
+    "cursor",
+    "string",
+    "The 'page.next_cursor' of the previous response, with otherwise the same arguments, \
+     to get the next page",
+    false,
+);
+const MAX_BYTES_PARAM: (&str, &str, &str, bool) = (
+    "max_bytes",
+    "integer",
+    "Most bytes of JSON to return: fewer items and shallower trees, marked 'truncated'",
+    false,
+);
+
+pub(crate) const TOOLS: &[Tool] = &[
+    Tool {
+        name: "drilldown",
+        query_path: "/query/drilldown",
+        description: "Follow the hottest callee path from a function until a bottleneck \
+                      (self time above the threshold) is found. Start here.",
+        params: &[
+            FUNCTION_PARAM,
+            MATCH_PARAM,
+            ("depth", "integer", "Maximum path depth (default 10)", false),
+            ("threshold", "number", "Self-time percentage which counts as a bottleneck (default 5)", false),
+            PROCESS_PARAM,
+            LANGUAGE_FRAMES_PARAM,
+        ],
+        data: Data::Object(&[
+            ("root", "string"),
+            ("total_samples", "integer"),
+            ("path", "array"),
+            ("bottleneck", "object|null"),
+            ("error", "string|null"),
+            ("suggestions", "array|null"),
+        ]),
+    },
+    Tool {
+        name: "hotspots",
+        query_path: "/query/hotspots",
+        description: "List the functions with the most self time. With groupby 'leaf_module', \
+                      list self time per library of the leaf frame instead, and the program's \
+                      functions which spend time in other libraries (FFI boundaries). With \
+                      groupby 'language', list self and total time per language runtime \
+                      (JavaScript, CPython, Java, ...) and native code.",
+        params: &[
+            ("limit", "integer", "Number of functions to return (default 20)", false),
+            OFFSET_PARAM,
+            CURSOR_PARAM,
+            MAX_BYTES_PARAM,
+            ("thread", "string", "Only include threads whose name contains this", false),
+            PROCESS_PARAM,
+            MERGE_THREADS_PARAM,
+            ("include_lines", "boolean", "Include the hottest source lines per function", false),
+            ("include_addresses", "boolean", "Include the hottest addresses per function", false),
+            (
+                "context",
+                "boolean",
+                "Include each function's heaviest root-to-leaf path as 'context', e.g. \
+                 'main → run → … → parse (12 frames elided)'",
+                false,
+            ),
+            ("groupby", "string", "'function' (default), 'leaf_module' or 'language'", false),
+            LANGUAGE_FRAMES_PARAM,
+            (
+                "metric",
+                "string",
+                "'samples' (default), or a hardware event recorded with 'record --event', e.g. \
+                 'cache-misses', to rank functions by",
+                false,
+            ),
+            (
+                "own_libraries",
+                "string",
+                "For leaf_module: comma-separated libraries of the program's own code \
+                 (default: all non-system libraries)",
+                false,
+            ),
+            START_MS_PARAM,
+            END_MS_PARAM,
+        ],
+        data: Data::OneOf(&[
+            Data::Array(&[
+                ("rank", "integer"),
+                ("function", "object"),
+                ("debug_info", "object|null"),
+                ("self_samples", "integer"),
+                ("total_samples", "integer"),
+                ("self_percent", "number"),
+                ("total_percent", "number"),
+                ("category", "string|null"),
+                ("context", "string|null"),
+                ("hot_lines", "array|null"),
+                ("hot_addresses", "array|null"),
+            ]),
+            // groupby=leaf_module
+            Data::Object(&[
+                ("total_samples", "integer"),
+                ("own_libraries", "array"),
+                ("modules", "array"),
+                ("boundaries", "array"),
+                ("unattributed_samples", "integer"),
+            ]),
+            // groupby=language
+            Data::Object(&[("total_samples", "integer"), ("languages", "array")]),
+        ]),
+    },
+    Tool {
+        name: "threads",
+        query_path: "/query/threads",
+        description: "For every thread: its sample count, share of its process, and top hotspots.",
+        params: &[
+            ("limit", "integer", "Number of hotspots per thread (default 10)", false),
+            MAX_BYTES_PARAM,
+            PROCESS_PARAM,
+            MERGE_THREADS_PARAM,
+            START_MS_PARAM,
+            END_MS_PARAM,
+        ],
+        data: Data::Object(&[("total_samples", "integer"), ("threads", "array")]),
+    },
+    Tool {
+        name: "processes",
+        query_path: "/query/processes",
+        description: "For every process: its threads with their sample counts, its share of \
+                      the samples, and its top hotspots. Pass 'process' to other tools to \
+                      restrict them to one process.",
+        params: &[
+            ("limit", "integer", "Number of hotspots per process (default 5)", false),
+            MAX_BYTES_PARAM,
+            PROCESS_PARAM,
+            START_MS_PARAM,
+            END_MS_PARAM,
+        ],
+        data: Data::Object(&[("total_samples", "integer"), ("processes", "array")]),
+    },
+    Tool {
+        name: "markers",
+        query_path: "/query/markers",
+        description: "List markers (IPC, GC, user annotations, ...) with their payloads, \
+                      and per-name count and total/p50/p95 duration.",
+        params: &[
+            ("name", "string", "Only include markers whose name contains this", false),
+            ("thread", "string", "Only include threads whose name contains this", false),
+            PROCESS_PARAM,
+            ("limit", "integer", "Number of individual markers to return (default 50)", false),
+            OFFSET_PARAM,
+            CURSOR_PARAM,
+            MAX_BYTES_PARAM,
+            START_MS_PARAM,
+            END_MS_PARAM,
+        ],
+        data: Data::Object(&[("total_count", "integer"), ("stats", "array"), ("markers", "array")]),
+    },
+    Tool {
+        name: "by_trace",
+        query_path: "/query/by-trace",
+        description: "Stacks sampled while a distributed trace's spans were active, with the \
+                      spans (interval markers carrying a W3C traceparent). Without trace_id, \
+                      lists the traces in the profile.",
+        params: &[
+            ("trace_id", "string", "Trace id (32 hex digits) or traceparent value", false),
+            ("thread", "string", "Only include threads whose name contains this", false),
+            PROCESS_PARAM,
+            ("limit", "integer", "Number of stacks or traces to return (default 20)", false),
+            START_MS_PARAM,
+            END_MS_PARAM,
+        ],
+        data: Data::OneOf(&[
+            Data::Object(&[
+                ("trace_id", "string"),
+                ("total_samples", "integer"),
+                ("spans", "array"),
+                ("stacks", "array"),
+            ]),
+            // Without trace_id
+            Data::Object(&[("traces", "array")]),
+        ]),
+    },
+    Tool {
+        name: "callers",
+        query_path: "/query/callers",
+        description: "Show which functions call a function, with sample counts.",
+        params: &[
+            FUNCTION_PARAM,
+            MATCH_PARAM,
+            ("depth", "integer", "Maximum caller depth (default 5)", false),
+            ("limit", "integer", "Maximum number of callers (default 20)", false),
+            OFFSET_PARAM,
+            CURSOR_PARAM,
+            MAX_BYTES_PARAM,
+            PROCESS_PARAM,
+            LANGUAGE_FRAMES_PARAM,
+            START_MS_PARAM,
+            END_MS_PARAM,
+        ],
+        data: Data::Object(&[("function", "string"), ("callers", "array")]),
+    },
+    Tool {
+        name: "callees",
+        query_path: "/query/callees",
+        description: "Show which functions a function calls, with sample counts.",
+        params: &[
+            FUNCTION_PARAM,
+            MATCH_PARAM,
+            ("depth", "integer", "Maximum callee depth (default 5)", false),
+            ("limit", "integer", "Maximum number of callees (default 20)", false),
+            OFFSET_PARAM,
+            CURSOR_PARAM,
+            MAX_BYTES_PARAM,
+            PROCESS_PARAM,
+            LANGUAGE_FRAMES_PARAM,
+            START_MS_PARAM,
+            END_MS_PARAM,
+        ],
+        data: Data::Object(&[("function", "string"), ("callees", "array")]),
+    },
+    Tool {
+        name: "summary",
+        query_path: "/query/summary",
+        description: "Overview of the profile: threads, sample counts and duration.",
+        params: &[PROCESS_PARAM, START_MS_PARAM, END_MS_PARAM],
+        data: Data::Object(&[
+            ("product_name", "string"),
+            ("total_samples", "integer"),
+            ("sampling_interval_ms", "number"),
+            ("thread_count", "integer"),
+            ("threads", "array"),
+            ("is_symbolicated", "boolean"),
+            ("first_sample_ms", "number|null"),
+            ("last_sample_ms", "number|null"),
+            ("time_range", "object|null"),
+            ("overhead", "object|null"),
+            ("overhead_summary", "string|null"),
+            ("parse_warnings", "array"),
+        ]),
+    },
+    Tool {
+        name: "source",
+        query_path: "/query/source",
+        description: "Show a function's source code annotated with per-line sample counts. \
+                      'warning' is set if the local file was edited since the build, so \
+                      that the line numbers may be off.",
+        params: &[
+            FUNCTION_PARAM,
+            MATCH_PARAM,
+            ("context", "integer", "Lines of context around hot lines (default 3)", false),
+            PROCESS_PARAM,
+        ],
+        data: Data::Object(&[
+            ("function", "object"),
+            ("file_path", "string|null"),
+            ("self_samples", "integer"),
+            ("total_samples", "integer"),
+            ("lines", "array"),
+            ("source_hash", "object|null"),
+            ("warning", "string|null"),
+            ("error", "string|null"),
+        ]),
+    },
+    Tool {
+        name: "asm",
+        query_path: "/query/asm",
+        description: "Show a function's hot instruction addresses, mapped to source lines.",
+        params: &[FUNCTION_PARAM, MATCH_PARAM, PROCESS_PARAM],
+        data: Data::Object(&[
+            ("function", "object"),
+            ("file_path", "string|null"),
+            ("regions", "array|null"),
+            ("self_samples", "integer"),
+            ("error", "string|null"),
+        ]),
+    },
+    Tool {
+        name: "calltree",
+        query_path: "/query/calltree",
+        description: "Top-down call tree from the root functions, with self and total \
+                      samples and percentages per node. Pruned children are summarized in \
+                      'pruned'.",
+        params: &[
+            ("thread", "string", "Only include threads whose name contains this", false),
+            PROCESS_PARAM,
+            MERGE_THREADS_PARAM,
+            LANGUAGE_FRAMES_PARAM,
+            (
+                "min_percent",
+                "number",
+                "Prune nodes below this percentage of samples (default 1)",
+                false,
+            ),
+            ("max_depth", "integer", "Levels of the tree to return (default 20)", false),
+            (
+                "max_children",
+                "integer",
+                "Children to keep per node, largest first (default 10)",
+                false,
+            ),
+            MAX_BYTES_PARAM,
+            START_MS_PARAM,
+            END_MS_PARAM,
+        ],
+        data: Data::Object(&[
+            ("total_samples", "integer"),
+            ("thread", "string|null"),
+            ("roots", "array"),
+            ("pruned", "object|null"),
+            ("node_count", "integer"),
+        ]),
+    },
+    Tool {
+        name: "flamegraph",
+        query_path: "/query/flamegraph",
+        description: "Return the merged call tree as nested nodes.",
+        params: &[
+            ("thread", "string", "Only include threads whose name contains this", false),
+            PROCESS_PARAM,
+            MERGE_THREADS_PARAM,
+            LANGUAGE_FRAMES_PARAM,
+            ("inverted", "boolean", "Root the tree at the leaf functions", false),
+            ("min_percent", "number", "Prune nodes below this percentage of samples", false),
+            MAX_BYTES_PARAM,
+        ],
+        data: Data::Object(&[
+            ("inverted", "boolean"),
+            ("thread", "string|null"),
+            ("root", "object"),
+        ]),
+    },
+    Tool {
+        name: "stack_stats",
+        query_path: "/query/stack-stats",
+        description: "Stack depth percentiles and histogram, call tree branching factor, unique \
+                      stacks and kernel/user frame counts, overall and per thread. Use it to \
+                      choose depth and threshold parameters for other tools.",
+        params: &[
+            ("thread", "string", "Only include threads whose name contains this", false),
+            PROCESS_PARAM,
+            START_MS_PARAM,
+            END_MS_PARAM,
+        ],
+        data: Data::Object(&[("overall", "object"), ("threads", "array")]),
+    },
+    Tool {
+        name: "categories",
+        query_path: "/query/categories",
+        description: "Samples per frame category (e.g. User, Kernel, GC, Layout) with the leaf \
+                      functions with the most samples in each, to tell kernel time apart from \
+                      user code.",
+        params: &[
+            ("limit", "integer", "Number of functions per category (default 5)", false),
+            ("thread", "string", "Only include threads whose name contains this", false),
+            PROCESS_PARAM,
+            START_MS_PARAM,
+            END_MS_PARAM,
+        ],
+        data: Data::Object(&[
+            ("total_samples", "integer"),
+            ("thread", "string|null"),
+            ("categories", "array"),
+            ("uncategorized_samples", "integer"),
+        ]),
+    },
+    Tool {
+        name: "wait_graph",
+        query_path: "/query/wait-graph",
+        description: "Which threads wake which other threads (from wakeup markers), where the \
+                      woken threads waited, and convoys: one thread which most wakeups of \
+                      several threads come from, serializing their work.",
+        params: &[
+            ("limit", "integer", "Number of (thread, waker) edges (default 20)", false),
+            OFFSET_PARAM,
+            CURSOR_PARAM,
+            MAX_BYTES_PARAM,
+            ("thread", "string", "Only include woken threads whose name contains this", false),
+            PROCESS_PARAM,
+            START_MS_PARAM,
+            END_MS_PARAM,
+        ],
+        data: Data::Object(&[
+            ("total_wakeups", "integer"),
+            ("thread", "string|null"),
+            ("edges", "array"),
+            ("convoys", "array"),
+            ("note", "string|null"),
+        ]),
+    },
+    Tool {
+        name: "signals",
+        query_path: "/query/signals",
+        description: "Signal deliveries and the samples in signal handlers, per signal (e.g. \
+                      SIGSEGV, SIGPROF), with the top handler functions of each. Shows the \
+                      cost of profilers, timers and runtimes which rely on signals.",
+        params: &[
+            ("limit", "integer", "Number of handler functions per signal (default 5)", false),
+            ("thread", "string", "Only include threads whose name contains this", false),
+            PROCESS_PARAM,
+            START_MS_PARAM,
+            END_MS_PARAM,
+        ],
+        data: Data::Object(&[
+            ("total_samples", "integer"),
+            ("handler_samples", "integer"),
+            ("handler_percent", "number"),
+            ("thread", "string|null"),
+            ("signals", "array"),
+            ("note", "string|null"),
+        ]),
+    },
+    Tool {
+        name: "allocations",
+        query_path: "/query/allocations",
+        description: "Top allocation sites by bytes and by allocation count, from a profile \
+                      recorded with 'samply record --alloc' on Linux. A site is the first \
+                      function from the leaf which isn't part of the allocator (malloc, \
+                      operator new, Rust's alloc::).",
+        params: &[
+            ("limit", "integer", "Number of sites in each list (default 10)", false),
+            OFFSET_PARAM,
+            CURSOR_PARAM,
+            MAX_BYTES_PARAM,
+            ("thread", "string", "Only include threads whose name contains this", false),
+            PROCESS_PARAM,
+            START_MS_PARAM,
+            END_MS_PARAM,
+        ],
+        data: Data::Object(&[
+            ("total_bytes", "integer"),
+            ("total_count", "integer"),
+            ("freed_bytes", "integer|null"),
+            ("thread", "string|null"),
+            ("by_bytes", "array"),
+            ("by_count", "array"),
+            ("note", "string|null"),
+        ]),
+    },
+    Tool {
+        name: "growth",
+        query_path: "/query/growth",
+        description: "Functions whose share of the samples grew over the recording, from its \
+                      first time window to its last, by self time and by total time. A cheap \
+                      detector of leaks and gradual slowdowns within one run, without a \
+                      baseline. 'steady' marks functions which grew in every window.",
+        params: &[
+            ("limit", "integer", "Number of functions in each list (default 10)", false),
+            OFFSET_PARAM,
+            CURSOR_PARAM,
+            MAX_BYTES_PARAM,
+            (
+                "windows",
+                "integer",
+                "Number of equal windows; 2 (default) compares the halves",
+                false,
+            ),
+            ("thread", "string", "Only include threads whose name contains this", false),
+            PROCESS_PARAM,
+            START_MS_PARAM,
+            END_MS_PARAM,
+        ],
+        data: Data::Object(&[
+            ("windows", "array"),
+            ("thread", "string|null"),
+            ("by_self", "array"),
+            ("by_total", "array"),
+            ("note", "string|null"),
+        ]),
+    },
+    Tool {
+        name: "crash",
+        query_path: "/query/crash",
+        description: "The crash of a profile imported from a minidump: the crashing thread, \
+                      its exception (e.g. EXCEPTION_ACCESS_VIOLATION, SIGSEGV) and address, \
+                      the faulting frame, and the top of the crashing stack.",
+        params: &[(
+            "limit",
+            "integer",
+            "Number of frames of the crashing stack, from the leaf (default 20)",
+            false,
+        )],
+        data: Data::Object(&[
+            ("thread", "string|null"),
+            ("tid", "string|null"),
+            ("exception", "string|null"),
+            ("address", "string|null"),
+            ("faulting_frame", "object|null"),
+            ("stack", "array"),
+            ("stack_depth", "integer"),
+            ("note", "string|null"),
+        ]),
+    },
+    Tool {
+        name: "export",
+        query_path: "/query/export",
+        description: "Export the profile as text for other tools; 'collapsed' gives folded \
+                      stack lines ('main;work;compute 123'), 'speedscope' a speedscope JSON \
+                      file, 'callgrind' a call graph for KCachegrind. The text is in 'output'.",
+        params: &[
+            (
+                "format",
+                "string",
+                "Export format: 'collapsed' (default), 'speedscope' or 'callgrind'",
+                false,
+            ),
+            ("thread", "string", "Only include threads whose name contains this", false),
+            PROCESS_PARAM,
+            LANGUAGE_FRAMES_PARAM,
+            START_MS_PARAM,
+            END_MS_PARAM,
+        ],
+        data: Data::Object(&[("format", "string"), ("output", "string")]),
+    },
+    Tool {
+        name: "diff",
+        query_path: "/query/diff",
+        description: "Compare per-function time against the baseline profile (requires --baseline). Functions renamed between the builds are linked and compared under status 'renamed'.",
+        params: &[
+            ("limit", "integer", "Number of functions to return (default 20)", false),
+            OFFSET_PARAM,
+            CURSOR_PARAM,
+            MAX_BYTES_PARAM,
+            PROCESS_PARAM,
+        ],
+        data: Data::Object(&[
+            ("baseline_total_samples", "integer"),
+            ("total_samples", "integer"),
+            ("functions", "array"),
+            ("new_hotspots", "array"),
+            ("removed_hotspots", "array"),
+            ("renamed", "array"),
+        ]),
+    },
+    Tool {
+        name: "diff_explain",
+        query_path: "/query/diff-explain",
+        description: "Explain the regression against the baseline profile (requires --baseline): clusters by shared ancestor functions, driving functions, per-library shares and a short narrative.",
+        params: &[
+            ("limit", "integer", "Number of clusters and libraries to return (default 5)", false),
+            PROCESS_PARAM,
+        ],
+        data: Data::Object(&[
+            ("baseline_total_samples", "integer"),
+            ("total_samples", "integer"),
+            ("regression_percent", "number"),
+            ("improvement_percent", "number"),
+            ("narrative", "array"),
+            ("clusters", "array"),
+            ("modules", "array"),
+        ]),
+    },
+    Tool {
+        name: "symbolication_status",
+        query_path: "/query/symbolication-status",
+        description: "Report which libraries have symbols, and why the others don't.",
+        params: &[],
+        data: Data::Object(&[
+            ("resolved", "integer"),
+            ("pending", "integer"),
+            ("failed", "integer"),
+            ("libraries", "array"),
+        ]),
+    },
+];
+
+fn tool_definition(tool: &Tool) -> Value {
+    let mut properties = serde_json::Map::new();
+    let mut required = Vec::new();
+    for &(name, ty, description, is_required) in tool.params {
+        properties.insert(
+            name.to_string(),
+            json!({ "type": ty, "description": description }),
+        );
+        if is_required {
+            required.push(name);
+        }
+    }
+    json!({
+        "name": tool.name,
+        "description": tool.description,
+        "inputSchema": {
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        },
+    })
+}
+
+/// Plugins don't describe their parameters, so their tools accept any
+/// arguments, which are passed on as query parameters.
+fn plugin_tool_definition(plugin: &dyn QueryPlugin) -> Value {
+    json!({
+        "name": plugin.name(),
+        "description": plugin.description(),
+        "inputSchema": {
+            "type": "object",
+            "additionalProperties": true,
+        },
+    })
+}
+
+/// Convert tool arguments into the string parameters the query handler takes.
+fn query_params(arguments: Option<&Value>) -> HashMap<String, String> {
+    let Some(Value::Object(arguments)) = arguments else {
+        return HashMap::new();
+    };
+    arguments
+        .iter()
+        .filter_map(|(key, value)| {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                Value::Number(n) => n.to_string(),
+                Value::Bool(b) => b.to_string(),
+                _ => return None,
+            };
+            Some((key.clone(), value))
+        })
+        .collect()
+}
+
+struct McpServer {
+    analyzer: ProfileAnalyzer,
+    baseline: Option<ProfileAnalyzer>,
+    symbol_manager: Arc<SymbolManager>,
+    symbolication_status: Arc<SymbolicationStatusTracker>,
+    runtime: tokio::runtime::Handle,
+}
+
+impl McpServer {
+    /// Handle one JSON-RPC message. Returns `None` for notifications.
+    fn handle_message(&self, message: &Value) -> Option<Value> {
+        let id = message.get("id").cloned();
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            return Some(error_response(id.unwrap_or(Value::Null), INVALID_REQUEST, "Missing 'method'"));
+        };
+        // Notifications (e.g. notifications/initialized) have no id and get no reply.
+        let id = id?;
+
+        let result = match method {
+            "initialize" => json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": { "tools": {} },
+                "serverInfo": {
+                    "name": "samply-for-ai",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            }),
+            "ping" => json!({}),
+            "tools/list" => {
+                let mut tools: Vec<Value> = TOOLS.iter().map(tool_definition).collect();
+                tools.extend(query_plugins().map(plugin_tool_definition));
+                json!({ "tools": tools })
+            }
+            "tools/call" => {
+                let params = message.get("params");
+                let name = params
+                    .and_then(|p| p.get("name"))
+                    .and_then(Value::as_str)
+                    .unwrap_or("");
+                let arguments = params.and_then(|p| p.get("arguments"));
+                if let Some(tool) = TOOLS.iter().find(|tool| tool.name == name) {
+                    self.call_tool(tool.query_path, arguments)
+                } else if let Some(plugin) = find_query_plugin(name) {
+                    self.call_tool(&format!("/query/{}", plugin.name()), arguments)
+                } else {
+                    return Some(error_response(
+                        id,
+                        INVALID_PARAMS,
+                        &format!("Unknown tool: {name}"),
+                    ));
+                }
+            }
+            _ => {
+                return Some(error_response(
+                    id,
+                    METHOD_NOT_FOUND,
+                    &format!("Method not found: {method}"),
+                ))
+            }
+        };
+
+        Some(json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+    }
+
+    fn call_tool(&self, query_path: &str, arguments: Option<&Value>) -> Value {
+        let params = query_params(arguments);
+
+        let asm_symbol = match (query_path, params.get("function")) {
+            ("/query/asm" | "/query/source", Some(function)) => {
+                self.runtime.block_on(lookup_asm_symbol(
+                    &self.symbol_manager,
+                    &self.analyzer,
+                    function,
+                    match_mode_param(&params).unwrap_or_default(),
+                ))
+            }
+            _ => None,
+        };
+
+        let (response, is_error) = match handle_query_request(
+            query_path,
+            &params,
+            Some(&self.analyzer),
+            self.baseline.as_ref(),
+            Some(&*self.symbolication_status),
+            asm_symbol.as_ref(),
+        ) {
+            Ok(response) => (response, false),
+            Err(failure) => (failure.to_json(), true),
+        };
+
+        json!({
+            "content": [{ "type": "text", "text": response }],
+            "isError": is_error,
+        })
+    }
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+/// Serve MCP requests on stdin/stdout until stdin is closed.
+pub fn run(
+    runtime: &tokio::runtime::Handle,
+    analyzer: ProfileAnalyzer,
+    baseline: Option<ProfileAnalyzer>,
+    symbol_manager: SymbolManager,
+    sidecar_maps: Vec<Arc<PrecogLibraySymbolMap>>,
+) -> std::io::Result<()> {
+    let symbol_manager = Arc::new(symbol_manager);
+    let symbolication_status = Arc::new(SymbolicationStatusTracker::new(
+        analyzer.library_symbolication_status(),
+    ));
+    runtime.spawn(check_library_symbols(
+        symbol_manager.clone(),
+        symbolication_status.clone(),
+        sidecar_maps,
+    ));
+
+    let server = McpServer {
+        analyzer,
+        baseline,
+        symbol_manager,
+        symbolication_status,
+        runtime: runtime.clone(),
+    };
+
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout().lock();
+    for line in stdin.lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(message) => server.handle_message(&message),
+            Err(e) => Some(error_response(
+                Value::Null,
+                PARSE_ERROR,
+                &format!("Parse error: {e}"),
+            )),
+        };
+        if let Some(response) = response {
+            serde_json::to_writer(&mut stdout, &response)?;
+            stdout.write_all(b"\n")?;
+            stdout.flush()?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_params() {
+        let arguments = json!({"function": "main", "depth": 3, "inverted": true, "x": null});
+        let params = query_params(Some(&arguments));
+        assert_eq!(params.get("function").map(String::as_str), Some("main"));
+        assert_eq!(params.get("depth").map(String::as_str), Some("3"));
+        assert_eq!(params.get("inverted").map(String::as_str), Some("true"));
+        assert!(!params.contains_key("x"));
+    }
+
+    #[test]
+    fn test_tool_definitions() {
+        let drilldown = tool_definition(&TOOLS[0]);
+        assert_eq!(drilldown["name"], "drilldown");
+        assert_eq!(drilldown["inputSchema"]["required"], json!(["function"]));
+        assert_eq!(drilldown["inputSchema"]["properties"]["depth"]["type"], "integer");
+    }
+}
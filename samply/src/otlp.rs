@@ -0,0 +1,83 @@
+//! Sending analysis summaries to an OpenTelemetry collector over OTLP/HTTP
+//! with JSON bodies, see [`samply_analysis::OtlpExport`].
+
+use std::time::Duration;
+
+use samply_analysis::OtlpExport;
+use tokio::runtime::Handle;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Invalid OTLP header '{0}', expected KEY=VALUE")]
+    InvalidHeader(String),
+
+    #[error("Couldn't create the HTTP client: {0}")]
+    Client(reqwest::Error),
+
+    #[error("Couldn't send to {url}: {source}")]
+    Request { url: String, source: reqwest::Error },
+
+    #[error("{url} responded with {status}: {body}")]
+    Status {
+        url: String,
+        status: reqwest::StatusCode,
+        body: String,
+    },
+}
+
+/// Parse `KEY=VALUE` headers. Each value may hold several comma-separated
+/// headers, like the `OTEL_EXPORTER_OTLP_HEADERS` environment variable.
+pub fn parse_headers(values: &[String]) -> Result<Vec<(String, String)>, Error> {
+    values
+        .iter()
+        .flat_map(|value| value.split(','))
+        .filter(|header| !header.trim().is_empty())
+        .map(|header| match header.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                Ok((key.trim().to_string(), value.trim().to_string()))
+            }
+            _ => Err(Error::InvalidHeader(header.to_string())),
+        })
+        .collect()
+}
+
+/// The URL of a signal (`metrics` or `logs`) for the collector's base URL,
+/// e.g. `http://localhost:4318/v1/metrics`
+fn signal_url(endpoint: &str, signal: &str) -> String {
+    format!("{}/v1/{signal}", endpoint.trim_end_matches('/'))
+}
+
+/// POST the metrics and the logs to the collector at `endpoint`.
+pub fn send(
+    runtime: &Handle,
+    endpoint: &str,
+    headers: &[(String, String)],
+    export: &OtlpExport,
+) -> Result<(), Error> {
+    runtime.block_on(async {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .map_err(Error::Client)?;
+        for (signal, body) in [("metrics", &export.metrics), ("logs", &export.logs)] {
+            let url = signal_url(endpoint, signal);
+            let mut request = client
+                .post(&url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.to_string());
+            for (key, value) in headers {
+                request = request.header(key.as_str(), value.as_str());
+            }
+            let response = request.send().await.map_err(|source| Error::Request {
+                url: url.clone(),
+                source,
+            })?;
+            let status = response.status();
+            if !status.is_success() {
+                let body = response.text().await.unwrap_or_default();
+                return Err(Error::Status { url, status, body });
+            }
+        }
+        Ok(())
+    })
+}
@@ -59,7 +59,7 @@ pub fn parse_libinfo_map_from_profile_file(
     }
 }
 
-fn parse_libinfo_map_from_profile(
+pub fn parse_libinfo_map_from_profile(
     reader: impl std::io::Read,
 ) -> Result<HashMap<(String, DebugId), LibraryInfo>, std::io::Error> {
     let profile: ProfileJsonProcess = serde_json::from_reader(reader)?;
@@ -1,12 +1,13 @@
 //! HTTP client for querying the analysis server.
 //!
-//! This module provides a simple blocking HTTP client for making queries
-//! to a running samply analysis server.
+//! This module provides a blocking HTTP client for making queries to a
+//! running samply analysis server.
 
-use std::io::{self, Read};
-use std::net::TcpStream;
+use std::io;
 use std::time::Duration;
 
+use samply_analysis::{ExportFormat, HotspotsGroupBy, MatchMode, QueryFailure, TimeRange};
+
 use crate::session::Session;
 
 /// Error type for query client operations
@@ -20,6 +21,9 @@ pub enum QueryError {
     RequestFailed(String),
     /// Invalid response
     InvalidResponse(String),
+    /// The server couldn't answer the query, e.g. because no function matches
+    /// the requested one
+    Failed(QueryFailure),
 }
 
 impl std::fmt::Display for QueryError {
@@ -29,6 +33,13 @@ impl std::fmt::Display for QueryError {
             QueryError::ConnectionFailed(e) => write!(f, "Connection failed: {}", e),
             QueryError::RequestFailed(msg) => write!(f, "Request failed: {}", msg),
             QueryError::InvalidResponse(msg) => write!(f, "Invalid response: {}", msg),
+            QueryError::Failed(failure) => {
+                write!(f, "{} ({})", failure.error, failure.code)?;
+                if !failure.candidates.is_empty() {
+                    write!(f, "\nDid you mean: {}", failure.candidates.join(", "))?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -39,12 +50,78 @@ impl std::error::Error for QueryError {}
 pub struct QueryClient {
     /// Full URL including token (e.g., "http://127.0.0.1:3000/abc123")
     server_url: String,
+    /// Id of the profile to query, for servers with several
+    profile: Option<String>,
+    /// Pid or name of the process to restrict the queries to
+    process: Option<String>,
+    /// Regex of the thread names to treat as one thread
+    merge_threads: Option<String>,
+    /// Whether to group JIT and interpreter frames under per-language pseudo-frames
+    language_frames: bool,
+    /// Decimals of the floats in responses, if not the server's default
+    precision: Option<u32>,
+    /// Number of ranked results to skip
+    offset: Option<usize>,
+    /// The `next_cursor` of the previous page
+    cursor: Option<String>,
+    /// Most bytes of JSON in responses
+    max_bytes: Option<usize>,
+    /// The certificates of a server which serves HTTPS, see [`Session::tls_cert`]
+    root_certificates: Vec<reqwest::Certificate>,
+    /// The bearer token of a server which requires one, see [`Session::auth_token`]
+    auth_token: Option<String>,
+    http: reqwest::blocking::Client,
 }
 
+/// Default time to wait for the connection to the server
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Default time to wait for a query's response, including the connection
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The environment variable with the URL of the server to query, for
+/// [`QueryClient::from_server_or_session`]
+pub const SERVER_URL_ENV_VAR: &str = "SAMPLY_QUERY_SERVER";
+
 impl QueryClient {
-    /// Create a client by reading the session file
-    pub fn from_session() -> Result<Self, QueryError> {
-        let session = Session::load().map_err(QueryError::NoSession)?;
+    /// Create a client for the server at `server_url` (`query --server`), e.g.
+    /// an analysis server on a remote machine, without a session file. The URL
+    /// includes the server's token prefix, e.g. "http://host:3000/abc123".
+    pub fn from_url(server_url: &str) -> Result<Self, QueryError> {
+        let url = url::Url::parse(server_url).map_err(|e| {
+            QueryError::RequestFailed(format!("Invalid server URL {server_url:?}: {e}"))
+        })?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(QueryError::RequestFailed(format!(
+                "Invalid server URL {server_url:?}: use http:// or https://"
+            )));
+        }
+        Self::new(
+            server_url.trim_end_matches('/').to_string(),
+            Vec::new(),
+            None,
+        )
+    }
+
+    /// Create a client for `server_url` if given, or else for the URL in the
+    /// `SAMPLY_QUERY_SERVER` environment variable, or else for the server of
+    /// the default or named session
+    pub fn from_server_or_session(
+        server_url: Option<&str>,
+        session: Option<&str>,
+    ) -> Result<Self, QueryError> {
+        let env_server_url = std::env::var(SERVER_URL_ENV_VAR)
+            .ok()
+            .filter(|url| !url.is_empty() && session.is_none());
+        match server_url.or(env_server_url.as_deref()) {
+            Some(server_url) => Self::from_url(server_url),
+            None => Self::from_session(session),
+        }
+    }
+
+    /// Create a client by reading the session file of the default session, or
+    /// of the named session
+    pub fn from_session(name: Option<&str>) -> Result<Self, QueryError> {
+        let session = Session::load(name).map_err(QueryError::NoSession)?;
 
         // Check if server is still alive
         if !session.is_server_alive() {
@@ -54,11 +131,104 @@ impl QueryClient {
             )));
         }
 
+        let root_certificates = match &session.tls_cert {
+            Some(path) => {
+                let pem = std::fs::read(path).map_err(QueryError::NoSession)?;
+                reqwest::Certificate::from_pem_bundle(&pem).map_err(|e| {
+                    QueryError::RequestFailed(format!("Couldn't load the certificate {path}: {e}"))
+                })?
+            }
+            None => Vec::new(),
+        };
+
+        Self::new(session.server_url, root_certificates, session.auth_token)
+    }
+
+    fn new(
+        server_url: String,
+        root_certificates: Vec<reqwest::Certificate>,
+        auth_token: Option<String>,
+    ) -> Result<Self, QueryError> {
         Ok(Self {
-            server_url: session.server_url,
+            server_url,
+            profile: None,
+            process: None,
+            merge_threads: None,
+            language_frames: false,
+            precision: None,
+            offset: None,
+            cursor: None,
+            max_bytes: None,
+            http: http_client(DEFAULT_CONNECT_TIMEOUT, DEFAULT_TIMEOUT, &root_certificates)?,
+            root_certificates,
+            auth_token,
         })
     }
 
+    /// Send this bearer token to a server started with `--auth-token`, instead
+    /// of the one from the session file
+    pub fn with_auth_token(mut self, auth_token: Option<String>) -> Self {
+        if auth_token.is_some() {
+            self.auth_token = auth_token;
+        }
+        self
+    }
+
+    /// Wait at most `connect_timeout` for the connection to the server, and
+    /// `timeout` for each query's response
+    pub fn with_timeouts(
+        mut self,
+        connect_timeout: Duration,
+        timeout: Duration,
+    ) -> Result<Self, QueryError> {
+        self.http = http_client(connect_timeout, timeout, &self.root_certificates)?;
+        Ok(self)
+    }
+
+    /// Send queries to the profile with this id rather than the server's first
+    pub fn with_profile(mut self, profile: Option<String>) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Restrict the queries to the threads of the process with this pid or name
+    pub fn with_process(mut self, process: Option<String>) -> Self {
+        self.process = process;
+        self
+    }
+
+    /// Treat the threads whose name matches this regex as one thread
+    pub fn with_merge_threads(mut self, merge_threads: Option<String>) -> Self {
+        self.merge_threads = merge_threads;
+        self
+    }
+
+    /// Group the frames of each JIT or interpreted language under a pseudo-frame
+    pub fn with_language_frames(mut self, language_frames: bool) -> Self {
+        self.language_frames = language_frames;
+        self
+    }
+
+    /// Round the floats in responses to this many decimals
+    pub fn with_precision(mut self, precision: Option<u32>) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Get the page of the ranked results after `offset` results, or the
+    /// page of this cursor
+    pub fn with_page(mut self, offset: Option<usize>, cursor: Option<String>) -> Self {
+        self.offset = offset;
+        self.cursor = cursor;
+        self
+    }
+
+    /// Cap responses at this many bytes of JSON
+    pub fn with_max_bytes(mut self, max_bytes: Option<usize>) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
     /// Query hotspots
     pub fn query_hotspots(
         &self,
@@ -66,8 +236,14 @@ impl QueryClient {
         thread: Option<&str>,
         include_lines: bool,
         include_addresses: bool,
+        context: bool,
+        group_by: HotspotsGroupBy,
+        own_libraries: &[String],
+        metric: &str,
+        range: TimeRange,
     ) -> Result<String, QueryError> {
         let mut url = format!("{}/query/hotspots?limit={}", self.server_url, limit);
+        push_time_range(&mut url, range);
         if let Some(t) = thread {
             url.push_str(&format!("&thread={}", urlencoding::encode(t)));
         }
@@ -77,46 +253,335 @@ impl QueryClient {
         if include_addresses {
             url.push_str("&include_addresses=true");
         }
+        if context {
+            url.push_str("&context=true");
+        }
+        if group_by != HotspotsGroupBy::default() {
+            url.push_str(&format!("&groupby={}", group_by));
+        }
+        if !own_libraries.is_empty() {
+            url.push_str(&format!(
+                "&own_libraries={}",
+                urlencoding::encode(&own_libraries.join(","))
+            ));
+        }
+        if metric != "samples" {
+            url.push_str(&format!("&metric={}", urlencoding::encode(metric)));
+        }
+        self.get(&url)
+    }
+
+    /// Query the top hotspots of every thread
+    pub fn query_threads(&self, limit: usize, range: TimeRange) -> Result<String, QueryError> {
+        let mut url = format!("{}/query/threads?limit={}", self.server_url, limit);
+        push_time_range(&mut url, range);
+        self.get(&url)
+    }
+
+    /// Query every process with its threads and top hotspots
+    pub fn query_processes(&self, limit: usize, range: TimeRange) -> Result<String, QueryError> {
+        let mut url = format!("{}/query/processes?limit={}", self.server_url, limit);
+        push_time_range(&mut url, range);
+        self.get(&url)
+    }
+
+    /// Query markers and per-name marker statistics
+    pub fn query_markers(
+        &self,
+        name: Option<&str>,
+        thread: Option<&str>,
+        limit: usize,
+        range: TimeRange,
+    ) -> Result<String, QueryError> {
+        let mut url = format!("{}/query/markers?limit={}", self.server_url, limit);
+        if let Some(name) = name {
+            url.push_str(&format!("&name={}", urlencoding::encode(name)));
+        }
+        if let Some(t) = thread {
+            url.push_str(&format!("&thread={}", urlencoding::encode(t)));
+        }
+        push_time_range(&mut url, range);
+        self.get(&url)
+    }
+
+    /// Query the stacks sampled while a trace's spans were active, or the
+    /// traces in the profile if `trace_id` is `None`
+    pub fn query_by_trace(
+        &self,
+        trace_id: Option<&str>,
+        thread: Option<&str>,
+        limit: usize,
+        range: TimeRange,
+    ) -> Result<String, QueryError> {
+        let mut url = format!("{}/query/by-trace?limit={}", self.server_url, limit);
+        if let Some(trace_id) = trace_id {
+            url.push_str(&format!("&trace_id={}", urlencoding::encode(trace_id)));
+        }
+        if let Some(t) = thread {
+            url.push_str(&format!("&thread={}", urlencoding::encode(t)));
+        }
+        push_time_range(&mut url, range);
         self.get(&url)
     }
 
     /// Query callers of a function
-    pub fn query_callers(&self, function: &str, depth: usize, limit: usize) -> Result<String, QueryError> {
-        let url = format!(
+    pub fn query_callers(
+        &self,
+        function: &str,
+        match_mode: MatchMode,
+        depth: usize,
+        limit: usize,
+        range: TimeRange,
+    ) -> Result<String, QueryError> {
+        let mut url = format!(
             "{}/query/callers?function={}&depth={}&limit={}",
             self.server_url,
             urlencoding::encode(function),
             depth,
             limit
         );
+        push_match_mode(&mut url, match_mode);
+        push_time_range(&mut url, range);
         self.get(&url)
     }
 
     /// Query callees of a function
-    pub fn query_callees(&self, function: &str, depth: usize, limit: usize) -> Result<String, QueryError> {
-        let url = format!(
+    pub fn query_callees(
+        &self,
+        function: &str,
+        match_mode: MatchMode,
+        depth: usize,
+        limit: usize,
+        range: TimeRange,
+    ) -> Result<String, QueryError> {
+        let mut url = format!(
             "{}/query/callees?function={}&depth={}&limit={}",
             self.server_url,
             urlencoding::encode(function),
             depth,
             limit
         );
+        push_match_mode(&mut url, match_mode);
+        push_time_range(&mut url, range);
+        self.get(&url)
+    }
+
+    /// Query flame graph call tree
+    pub fn query_flamegraph(
+        &self,
+        thread: Option<&str>,
+        inverted: bool,
+        min_percent: f64,
+    ) -> Result<String, QueryError> {
+        let mut url = format!(
+            "{}/query/flamegraph?min_percent={}",
+            self.server_url, min_percent
+        );
+        if let Some(t) = thread {
+            url.push_str(&format!("&thread={}", urlencoding::encode(t)));
+        }
+        if inverted {
+            url.push_str("&inverted=true");
+        }
+        self.get(&url)
+    }
+
+    /// Query the top-down call tree
+    pub fn query_calltree(
+        &self,
+        thread: Option<&str>,
+        min_percent: f64,
+        max_depth: usize,
+        max_children: usize,
+        range: TimeRange,
+    ) -> Result<String, QueryError> {
+        let mut url = format!(
+            "{}/query/calltree?min_percent={}&max_depth={}&max_children={}",
+            self.server_url, min_percent, max_depth, max_children
+        );
+        if let Some(t) = thread {
+            url.push_str(&format!("&thread={}", urlencoding::encode(t)));
+        }
+        push_time_range(&mut url, range);
+        self.get(&url)
+    }
+
+    /// Query the samples per frame category, with the top functions of each
+    pub fn query_categories(
+        &self,
+        limit: usize,
+        thread: Option<&str>,
+        range: TimeRange,
+    ) -> Result<String, QueryError> {
+        let mut url = format!("{}/query/categories?limit={}", self.server_url, limit);
+        if let Some(t) = thread {
+            url.push_str(&format!("&thread={}", urlencoding::encode(t)));
+        }
+        push_time_range(&mut url, range);
+        self.get(&url)
+    }
+
+    /// Query which threads wake which, and the convoys among them
+    pub fn query_wait_graph(
+        &self,
+        limit: usize,
+        thread: Option<&str>,
+        range: TimeRange,
+    ) -> Result<String, QueryError> {
+        let mut url = format!("{}/query/wait-graph?limit={}", self.server_url, limit);
+        if let Some(t) = thread {
+            url.push_str(&format!("&thread={}", urlencoding::encode(t)));
+        }
+        push_time_range(&mut url, range);
+        self.get(&url)
+    }
+
+    /// Query the signal deliveries and the samples in their handlers
+    pub fn query_signals(
+        &self,
+        limit: usize,
+        thread: Option<&str>,
+        range: TimeRange,
+    ) -> Result<String, QueryError> {
+        let mut url = format!("{}/query/signals?limit={}", self.server_url, limit);
+        if let Some(t) = thread {
+            url.push_str(&format!("&thread={}", urlencoding::encode(t)));
+        }
+        push_time_range(&mut url, range);
+        self.get(&url)
+    }
+
+    /// Query the top allocation sites, by bytes and by count
+    pub fn query_allocations(
+        &self,
+        limit: usize,
+        thread: Option<&str>,
+        range: TimeRange,
+    ) -> Result<String, QueryError> {
+        let mut url = format!("{}/query/allocations?limit={}", self.server_url, limit);
+        if let Some(t) = thread {
+            url.push_str(&format!("&thread={}", urlencoding::encode(t)));
+        }
+        push_time_range(&mut url, range);
+        self.get(&url)
+    }
+
+    /// Query the functions whose share grew over the recording
+    pub fn query_growth(
+        &self,
+        limit: usize,
+        windows: usize,
+        thread: Option<&str>,
+        range: TimeRange,
+    ) -> Result<String, QueryError> {
+        let mut url = format!(
+            "{}/query/growth?limit={}&windows={}",
+            self.server_url, limit, windows
+        );
+        if let Some(t) = thread {
+            url.push_str(&format!("&thread={}", urlencoding::encode(t)));
+        }
+        push_time_range(&mut url, range);
+        self.get(&url)
+    }
+
+    /// Query the crashing thread of an imported minidump
+    pub fn query_crash(&self, limit: usize) -> Result<String, QueryError> {
+        let url = format!("{}/query/crash?limit={}", self.server_url, limit);
+        self.get(&url)
+    }
+
+    /// Query stack depth and shape statistics
+    pub fn query_stack_stats(
+        &self,
+        thread: Option<&str>,
+        range: TimeRange,
+    ) -> Result<String, QueryError> {
+        let mut url = format!("{}/query/stack-stats", self.server_url);
+        if let Some(t) = thread {
+            url.push_str(&format!("?thread={}", urlencoding::encode(t)));
+        }
+        push_time_range(&mut url, range);
+        self.get(&url)
+    }
+
+    /// Export the profile; the exported text is in `data.output`
+    pub fn query_export(
+        &self,
+        format: ExportFormat,
+        thread: Option<&str>,
+        range: TimeRange,
+    ) -> Result<String, QueryError> {
+        let mut url = format!("{}/query/export?format={}", self.server_url, format);
+        if let Some(t) = thread {
+            url.push_str(&format!("&thread={}", urlencoding::encode(t)));
+        }
+        push_time_range(&mut url, range);
         self.get(&url)
     }
 
     /// Query profile summary
-    pub fn query_summary(&self) -> Result<String, QueryError> {
-        let url = format!("{}/query/summary", self.server_url);
+    pub fn query_summary(&self, range: TimeRange) -> Result<String, QueryError> {
+        let mut url = format!("{}/query/summary", self.server_url);
+        push_time_range(&mut url, range);
+        self.get(&url)
+    }
+
+    /// Query per-function differences against the baseline profile, or
+    /// against the server's profile with the id `baseline`
+    pub fn query_diff(&self, limit: usize, baseline: Option<&str>) -> Result<String, QueryError> {
+        let mut url = format!("{}/query/diff?limit={}", self.server_url, limit);
+        if let Some(baseline) = baseline {
+            url.push_str(&format!("&baseline={}", urlencoding::encode(baseline)));
+        }
+        self.get(&url)
+    }
+
+    /// Query the clustered explanation of the regression against the baseline
+    /// profile, or against the server's profile with the id `baseline`
+    pub fn query_diff_explain(
+        &self,
+        limit: usize,
+        baseline: Option<&str>,
+    ) -> Result<String, QueryError> {
+        let mut url = format!("{}/query/diff-explain?limit={}", self.server_url, limit);
+        if let Some(baseline) = baseline {
+            url.push_str(&format!("&baseline={}", urlencoding::encode(baseline)));
+        }
+        self.get(&url)
+    }
+
+    /// List the profiles of the server with their ids
+    pub fn query_profiles(&self) -> Result<String, QueryError> {
+        let url = format!("{}/query/profiles", self.server_url);
+        self.get(&url)
+    }
+
+    /// Query annotated source for a function
+    pub fn query_source(
+        &self,
+        function: &str,
+        match_mode: MatchMode,
+        context: u32,
+    ) -> Result<String, QueryError> {
+        let mut url = format!(
+            "{}/query/source?function={}&context={}",
+            self.server_url,
+            urlencoding::encode(function),
+            context
+        );
+        push_match_mode(&mut url, match_mode);
         self.get(&url)
     }
 
     /// Query assembly for a function
-    pub fn query_asm(&self, function: &str) -> Result<String, QueryError> {
-        let url = format!(
+    pub fn query_asm(&self, function: &str, match_mode: MatchMode) -> Result<String, QueryError> {
+        let mut url = format!(
             "{}/query/asm?function={}",
             self.server_url,
             urlencoding::encode(function)
         );
+        push_match_mode(&mut url, match_mode);
         self.get(&url)
     }
 
@@ -124,80 +589,151 @@ impl QueryClient {
     pub fn query_drilldown(
         &self,
         function: &str,
+        match_mode: MatchMode,
         depth: usize,
         threshold: f64,
     ) -> Result<String, QueryError> {
-        let url = format!(
+        let mut url = format!(
             "{}/query/drilldown?function={}&depth={}&threshold={}",
             self.server_url,
             urlencoding::encode(function),
             depth,
             threshold
         );
+        push_match_mode(&mut url, match_mode);
         self.get(&url)
     }
 
-    /// Make a simple HTTP GET request and return the response body
+    /// Query per-library symbolication status
+    pub fn query_symbolication_status(&self) -> Result<String, QueryError> {
+        let url = format!("{}/query/symbolication-status", self.server_url);
+        self.get(&url)
+    }
+
+    /// Ask the server to shut down, with `POST /control/shutdown`
+    pub fn shutdown(&self) -> Result<(), QueryError> {
+        let url = format!("{}/control/shutdown", self.server_url);
+        let mut request = self.http.post(url).header("X-Samply-Control", "1");
+        if let Some(auth_token) = &self.auth_token {
+            request = request.bearer_auth(auth_token);
+        }
+        let response = request.send().map_err(request_error)?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(QueryError::RequestFailed(format!("HTTP error: {}", status)));
+        }
+        Ok(())
+    }
+
+    /// Make an HTTP GET request and return the response body
     fn get(&self, url: &str) -> Result<String, QueryError> {
-        // Parse the URL to extract host, port, and path
-        let url_parsed = url::Url::parse(url)
+        let mut url = url::Url::parse(url)
             .map_err(|e| QueryError::InvalidResponse(format!("Invalid URL: {}", e)))?;
+        // `/query/<name>` becomes `/query/<profile id>/<name>`; the list of
+        // profiles isn't a query of a profile.
+        if !url.path().ends_with("/query/profiles") {
+            if let Some(profile) = &self.profile {
+                let path = url.path().replacen(
+                    "/query/",
+                    &format!("/query/{}/", urlencoding::encode(profile)),
+                    1,
+                );
+                url.set_path(&path);
+            }
+            if let Some(process) = &self.process {
+                url.query_pairs_mut().append_pair("process", process);
+            }
+            if let Some(merge_threads) = &self.merge_threads {
+                url.query_pairs_mut()
+                    .append_pair("merge_threads", merge_threads);
+            }
+            if self.language_frames {
+                url.query_pairs_mut().append_pair("language_frames", "true");
+            }
+            if let Some(precision) = self.precision {
+                url.query_pairs_mut()
+                    .append_pair("precision", &precision.to_string());
+            }
+            if let Some(offset) = self.offset {
+                url.query_pairs_mut()
+                    .append_pair("offset", &offset.to_string());
+            }
+            if let Some(cursor) = &self.cursor {
+                url.query_pairs_mut().append_pair("cursor", cursor);
+            }
+            if let Some(max_bytes) = self.max_bytes {
+                url.query_pairs_mut()
+                    .append_pair("max_bytes", &max_bytes.to_string());
+            }
+        }
 
-        let host = url_parsed
-            .host_str()
-            .ok_or_else(|| QueryError::InvalidResponse("No host in URL".to_string()))?;
-        let port = url_parsed.port().unwrap_or(80);
-        let path = url_parsed.path();
-        let query = url_parsed.query().unwrap_or("");
-        let full_path = if query.is_empty() {
-            path.to_string()
-        } else {
-            format!("{}?{}", path, query)
-        };
+        let mut request = self.http.get(url);
+        if let Some(auth_token) = &self.auth_token {
+            request = request.bearer_auth(auth_token);
+        }
+        let response = request.send().map_err(request_error)?;
+        let status = response.status();
+        let body = response.text().map_err(request_error)?;
+        if !status.is_success() {
+            if let Ok(failure) = serde_json::from_str::<QueryFailure>(&body) {
+                return Err(QueryError::Failed(failure));
+            }
+            return Err(QueryError::RequestFailed(if body.is_empty() {
+                format!("HTTP error: {}", status)
+            } else {
+                format!("HTTP error: {}: {}", status, body)
+            }));
+        }
 
-        // Connect to the server
-        let addr = format!("{}:{}", host, port);
-        let mut stream = TcpStream::connect(&addr).map_err(QueryError::ConnectionFailed)?;
-        stream
-            .set_read_timeout(Some(Duration::from_secs(30)))
-            .ok();
-        stream
-            .set_write_timeout(Some(Duration::from_secs(10)))
-            .ok();
-
-        // Send HTTP request
-        let request = format!(
-            "GET {} HTTP/1.1\r\nHost: {}:{}\r\nConnection: close\r\n\r\n",
-            full_path, host, port
-        );
+        Ok(body)
+    }
+}
 
-        use std::io::Write;
-        stream
-            .write_all(request.as_bytes())
-            .map_err(|e| QueryError::ConnectionFailed(e))?;
-
-        // Read response
-        let mut response = String::new();
-        stream
-            .read_to_string(&mut response)
-            .map_err(|e| QueryError::ConnectionFailed(e))?;
-
-        // Parse HTTP response - extract body after headers
-        let body_start = response
-            .find("\r\n\r\n")
-            .ok_or_else(|| QueryError::InvalidResponse("No body delimiter found".to_string()))?;
-        let body = &response[body_start + 4..];
-
-        // Check for HTTP error status
-        let first_line = response.lines().next().unwrap_or("");
-        if !first_line.contains("200") {
-            return Err(QueryError::RequestFailed(format!(
-                "HTTP error: {}",
-                first_line
-            )));
+/// The HTTP client for the queries. It keeps connections to the server alive,
+/// so that a client which sends several queries reuses one connection.
+fn http_client(
+    connect_timeout: Duration,
+    timeout: Duration,
+    root_certificates: &[reqwest::Certificate],
+) -> Result<reqwest::blocking::Client, QueryError> {
+    let mut builder = reqwest::blocking::Client::builder()
+        .connect_timeout(connect_timeout)
+        .timeout(timeout);
+    for certificate in root_certificates {
+        builder = builder.add_root_certificate(certificate.clone());
+    }
+    builder
+        .build()
+        .map_err(|e| QueryError::RequestFailed(format!("Couldn't create the HTTP client: {}", e)))
+}
+
+/// Connection failures are kept apart from other failures, for the hint to
+/// start a server.
+fn request_error(e: reqwest::Error) -> QueryError {
+    if e.is_connect() {
+        QueryError::ConnectionFailed(io::Error::other(e))
+    } else if e.is_timeout() {
+        QueryError::RequestFailed(format!("Timed out: {}", e))
+    } else {
+        QueryError::RequestFailed(e.to_string())
+    }
+}
+
+/// Append the `start_ms` / `end_ms` parameters for the bounds which are set.
+fn push_time_range(url: &mut String, range: TimeRange) {
+    let bounds = [("start_ms", range.start_ms), ("end_ms", range.end_ms)];
+    for (name, value) in bounds {
+        if let Some(value) = value {
+            let separator = if url.contains('?') { '&' } else { '?' };
+            url.push_str(&format!("{separator}{name}={value}"));
         }
+    }
+}
 
-        Ok(body.to_string())
+/// Append `match=<mode>` unless it's the server's default (substring).
+fn push_match_mode(url: &mut String, match_mode: MatchMode) {
+    if match_mode != MatchMode::default() {
+        url.push_str(&format!("&match={match_mode}"));
     }
 }
 
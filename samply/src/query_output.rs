@@ -0,0 +1,425 @@
+//! The `--output-format` of `samply-for-ai query`: the JSON response as is,
+//! or its data as tables for people (`table`), spreadsheets (`csv`) or
+//! documents and chat (`markdown`).
+//!
+//! Responses differ from query to query, so they're turned into tables by
+//! their shape. Each list of objects in the response becomes a table, with a
+//! row per object and a column per field; nested objects become columns with
+//! dotted names, like `function.name`. Lists of objects with `children`, like
+//! the nodes of a call tree, become a row per node, depth first, with a
+//! `depth` column. The other fields are shown as `key: value` lines above the
+//! tables.
+
+use std::collections::BTreeSet;
+
+use serde_json::Value;
+
+use crate::cli::QueryOutputArg;
+
+/// A list of objects of the response
+struct Table {
+    /// The dotted path of the list in the response
+    title: String,
+    columns: Vec<String>,
+    rows: Vec<Vec<(String, Value)>>,
+}
+
+/// Columns which identify a row come first, in this order. The others follow
+/// in alphabetical order.
+const LEADING_COLUMNS: &[&str] = &[
+    "depth",
+    "rank",
+    "name",
+    "function",
+    "function.name",
+    "function.library",
+    "library",
+    "thread",
+    "pid",
+    "tid",
+];
+
+/// The response in `format`
+pub fn render(response: &Value, format: QueryOutputArg, width: usize) -> String {
+    let mut fields = Vec::new();
+    let mut tables = Vec::new();
+    if let Value::Object(response) = response {
+        for (key, value) in response {
+            match key.as_str() {
+                // The units are in the column names.
+                "success" | "query" | "units" => {}
+                "data" => collect(String::new(), value, &mut fields, &mut tables),
+                _ => collect(key.clone(), value, &mut fields, &mut tables),
+            }
+        }
+    }
+    match format {
+        QueryOutputArg::Json => serde_json::to_string_pretty(response).unwrap_or_default(),
+        QueryOutputArg::Table => render_text(&fields, &tables, width),
+        QueryOutputArg::Markdown => render_markdown(&fields, &tables),
+        // A CSV file has one table: the longest.
+        QueryOutputArg::Csv => tables
+            .iter()
+            .max_by_key(|table| table.rows.len())
+            .map(render_csv)
+            .unwrap_or_default(),
+    }
+}
+
+fn join_key(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{prefix}.{key}")
+    }
+}
+
+fn is_list_of_objects(value: &Value) -> bool {
+    matches!(value, Value::Array(items) if items.first().is_some_and(Value::is_object))
+}
+
+/// Sort the fields of `value` into `key: value` fields and tables.
+fn collect(
+    prefix: String,
+    value: &Value,
+    fields: &mut Vec<(String, Value)>,
+    tables: &mut Vec<Table>,
+) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                collect(join_key(&prefix, key), value, fields, tables);
+            }
+        }
+        Value::Array(items) if is_list_of_objects(value) => {
+            let mut rows = Vec::new();
+            for item in items {
+                add_rows(item, 0, &mut rows);
+            }
+            let mut columns: BTreeSet<&str> = BTreeSet::new();
+            for row in &rows {
+                columns.extend(row.iter().map(|(column, _)| column.as_str()));
+            }
+            let mut ordered: Vec<String> = LEADING_COLUMNS
+                .iter()
+                .filter(|column| columns.remove(**column))
+                .map(|column| column.to_string())
+                .collect();
+            ordered.extend(columns.into_iter().map(String::from));
+            let title = if prefix.is_empty() {
+                "data".to_string()
+            } else {
+                prefix
+            };
+            tables.push(Table {
+                title,
+                columns: ordered,
+                rows,
+            });
+        }
+        _ => {
+            let key = if prefix.is_empty() {
+                "data".to_string()
+            } else {
+                prefix
+            };
+            fields.push((key, value.clone()));
+        }
+    }
+}
+
+/// Add the row of an object, and the rows of its `children`, if it has any.
+fn add_rows(item: &Value, depth: usize, rows: &mut Vec<Vec<(String, Value)>>) {
+    let mut row = Vec::new();
+    let children = item
+        .get("children")
+        .filter(|children| is_list_of_objects(children));
+    if children.is_some() || depth > 0 {
+        row.push(("depth".to_string(), Value::from(depth)));
+    }
+    flatten_row(String::new(), item, &mut row);
+    rows.push(row);
+    if let Some(Value::Array(children)) = children {
+        for child in children {
+            add_rows(child, depth + 1, rows);
+        }
+    }
+}
+
+/// The columns of a row. Lists of objects, which would be tables of their
+/// own, are left out.
+fn flatten_row(prefix: String, value: &Value, row: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                flatten_row(join_key(&prefix, key), value, row);
+            }
+        }
+        Value::Array(_) if is_list_of_objects(value) => {}
+        _ => row.push((prefix, value.clone())),
+    }
+}
+
+/// The text of a cell
+fn cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| cell(Some(item)))
+            .collect::<Vec<_>>()
+            .join(", "),
+        Some(value) => value.to_string(),
+    }
+}
+
+fn value_in<'a>(row: &'a [(String, Value)], column: &str) -> Option<&'a Value> {
+    row.iter()
+        .find(|(key, _)| key == column)
+        .map(|(_, value)| value)
+}
+
+fn row_cells(table: &Table, row: &[(String, Value)]) -> Vec<String> {
+    table
+        .columns
+        .iter()
+        .map(|column| cell(value_in(row, column)))
+        .collect()
+}
+
+/// Whether every value of the column is a number, so that it's right-aligned
+fn is_numeric_column(table: &Table, column: &str) -> bool {
+    table
+        .rows
+        .iter()
+        .all(|row| value_in(row, column).is_none_or(|value| value.is_number() || value.is_null()))
+}
+
+/// Shorten `s` to `max` characters by cutting out its middle, so that both
+/// the namespace and the function of a long symbol name stay visible.
+fn truncate_middle(s: &str, max: usize) -> String {
+    let len = s.chars().count();
+    if len <= max {
+        return s.to_string();
+    }
+    let kept = max.saturating_sub(1);
+    let head = kept.div_ceil(2);
+    let tail = kept - head;
+    let mut truncated: String = s.chars().take(head).collect();
+    truncated.push('…');
+    truncated.extend(s.chars().skip(len - tail));
+    truncated
+}
+
+/// Column-aligned tables which fit into `width` columns: the widest columns
+/// are shortened until the rows fit, but not below 12 characters.
+fn render_text(fields: &[(String, Value)], tables: &[Table], width: usize) -> String {
+    const MIN_WIDTH: usize = 12;
+    let mut out = String::new();
+    for (key, value) in fields {
+        out.push_str(&format!("{key}: {}\n", cell(Some(value))));
+    }
+    for table in tables {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&format!("{}:\n", table.title));
+        let rows: Vec<Vec<String>> = table.rows.iter().map(|row| row_cells(table, row)).collect();
+        let mut widths: Vec<usize> = table
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| {
+                rows.iter()
+                    .map(|row| row[i].chars().count())
+                    .chain([column.chars().count()])
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        // The name column is indented by depth in trees.
+        let name_column = table
+            .columns
+            .iter()
+            .position(|column| column == "name" || column == "function.name");
+        let indent = |row: &[(String, Value)]| {
+            let depth = value_in(row, "depth").and_then(Value::as_u64).unwrap_or(0);
+            "  ".repeat(depth as usize)
+        };
+        if let Some(name_column) = name_column {
+            for (row, cells) in table.rows.iter().zip(&rows) {
+                let len = indent(row).len() + cells[name_column].chars().count();
+                widths[name_column] = widths[name_column].max(len);
+            }
+        }
+        let separators = 2 * widths.len().saturating_sub(1);
+        while widths.iter().sum::<usize>() + separators > width {
+            let (widest, &max) = widths
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, width)| **width)
+                .unwrap();
+            if max <= MIN_WIDTH {
+                break;
+            }
+            widths[widest] = max - 1;
+        }
+
+        let numeric: Vec<bool> = table
+            .columns
+            .iter()
+            .map(|column| is_numeric_column(table, column))
+            .collect();
+        let mut push_line = |cells: &[String]| {
+            let line: Vec<String> = cells
+                .iter()
+                .enumerate()
+                .map(|(i, cell)| {
+                    let cell = truncate_middle(cell, widths[i]);
+                    if numeric[i] {
+                        format!("{cell:>width$}", width = widths[i])
+                    } else {
+                        format!("{cell:<width$}", width = widths[i])
+                    }
+                })
+                .collect();
+            out.push_str(line.join("  ").trim_end());
+            out.push('\n');
+        };
+        let header: Vec<String> = table.columns.iter().map(|c| c.to_uppercase()).collect();
+        push_line(&header);
+        for (row, mut cells) in table.rows.iter().zip(rows) {
+            if let Some(name_column) = name_column {
+                cells[name_column] = format!("{}{}", indent(row), cells[name_column]);
+            }
+            push_line(&cells);
+        }
+    }
+    out
+}
+
+fn escape_markdown(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', " ")
+}
+
+/// A Markdown list of the fields and a Markdown table per list
+fn render_markdown(fields: &[(String, Value)], tables: &[Table]) -> String {
+    let mut out = String::new();
+    for (key, value) in fields {
+        out.push_str(&format!(
+            "- **{key}**: {}\n",
+            escape_markdown(&cell(Some(value)))
+        ));
+    }
+    for table in tables {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&format!("### {}\n\n", table.title));
+        let header: Vec<String> = table.columns.iter().map(|c| escape_markdown(c)).collect();
+        out.push_str(&format!("| {} |\n", header.join(" | ")));
+        let alignments: Vec<&str> = table
+            .columns
+            .iter()
+            .map(|column| {
+                if is_numeric_column(table, column) {
+                    "---:"
+                } else {
+                    "---"
+                }
+            })
+            .collect();
+        out.push_str(&format!("| {} |\n", alignments.join(" | ")));
+        for row in &table.rows {
+            let cells: Vec<String> = row_cells(table, row)
+                .iter()
+                .map(|cell| escape_markdown(cell))
+                .collect();
+            out.push_str(&format!("| {} |\n", cells.join(" | ")));
+        }
+    }
+    out
+}
+
+/// Quote a CSV field if it needs to be, as in RFC 4180.
+fn escape_csv(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn render_csv(table: &Table) -> String {
+    let mut out = String::new();
+    let header: Vec<String> = table.columns.iter().map(|c| escape_csv(c)).collect();
+    out.push_str(&header.join(","));
+    out.push('\n');
+    for row in &table.rows {
+        let cells: Vec<String> = row_cells(table, row)
+            .iter()
+            .map(|cell| escape_csv(cell))
+            .collect();
+        out.push_str(&cells.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_hotspots() {
+        let response = serde_json::json!({
+            "success": true,
+            "query": "hotspots",
+            "metric": "samples",
+            "data": [
+                {"rank": 1, "function": {"name": "std::collections::hash::map::HashMap<K,V,S>::insert", "library": "app"}, "self_percent": 40.5},
+                {"rank": 2, "function": {"name": "parse, \"quoted\""}, "self_percent": 9.25}
+            ]
+        });
+        let table = render(&response, QueryOutputArg::Table, 60);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "metric: samples");
+        assert_eq!(lines[2], "data:");
+        assert!(lines[3].starts_with("RANK  FUNCTION.NAME"));
+        assert!(lines.iter().all(|line| line.chars().count() <= 60));
+        assert!(lines[4].contains("std::collec…S>::insert  app"));
+
+        let csv = render(&response, QueryOutputArg::Csv, 60);
+        assert_eq!(
+            csv.lines().collect::<Vec<_>>(),
+            [
+                "rank,function.name,function.library,self_percent",
+                "1,\"std::collections::hash::map::HashMap<K,V,S>::insert\",app,40.5",
+                "2,\"parse, \"\"quoted\"\"\",,9.25",
+            ]
+        );
+
+        let markdown = render(&response, QueryOutputArg::Markdown, 60);
+        assert!(markdown.contains("| rank | function.name | function.library | self_percent |"));
+        assert!(markdown.contains("| ---: | --- | --- | ---: |"));
+    }
+
+    #[test]
+    fn test_render_tree() {
+        let response = serde_json::json!({
+            "success": true,
+            "data": {"total_samples": 10, "roots": [
+                {"name": "main", "total_samples": 10, "children": [
+                    {"name": "run", "total_samples": 7}
+                ]}
+            ]}
+        });
+        let table = render(&response, QueryOutputArg::Table, 120);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "total_samples: 10");
+        assert_eq!(lines[2], "roots:");
+        assert_eq!(lines[4], "    0  main              10");
+        assert_eq!(lines[5], "    1    run              7");
+    }
+}
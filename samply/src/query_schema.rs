@@ -0,0 +1,377 @@
+//! The OpenAPI description of the query API, served at `/query/schema` and
+//! printed by `samply-for-ai analyze schema`: every query endpoint with its
+//! parameters and the shape of its response. It's built from the table of
+//! the MCP tools, so that both describe the same queries.
+
+use std::collections::BTreeMap;
+
+use samply_analysis::plugin::query_plugins;
+use samply_analysis::QueryErrorCode;
+use serde_json::{json, Map, Value};
+
+use crate::mcp::{Data, TOOLS};
+
+/// Parameters which every query takes: (name, JSON schema type, description)
+const COMMON_PARAMS: &[(&str, &str, &str)] = &[
+    (
+        "precision",
+        "integer",
+        "Decimals of the floats in the response, 0-15 (default 3)",
+    ),
+    (
+        "baseline",
+        "string",
+        "For diff and diff-explain on a server with several profiles: the id of the \
+         profile to compare against",
+    ),
+];
+
+/// The OpenAPI 3.1 document of the query endpoints
+pub fn openapi() -> Value {
+    let mut paths = Map::new();
+    for tool in TOOLS {
+        let mut parameters: Vec<Value> = tool
+            .params
+            .iter()
+            .map(|&(name, ty, description, required)| parameter(name, ty, description, required))
+            .collect();
+        parameters.extend(
+            COMMON_PARAMS
+                .iter()
+                .map(|&(name, ty, description)| parameter(name, ty, description, false)),
+        );
+        paths.insert(
+            tool.query_path.to_string(),
+            operation(
+                tool.name,
+                tool.description,
+                parameters,
+                data_schema(&tool.data),
+            ),
+        );
+    }
+    // Plugins don't describe their parameters or responses.
+    for plugin in query_plugins() {
+        paths.insert(
+            format!("/query/{}", plugin.name()),
+            operation(plugin.name(), plugin.description(), Vec::new(), json!({})),
+        );
+    }
+    paths.insert(
+        "/query/profiles".to_string(),
+        operation(
+            "profiles",
+            "List the profiles of the server, with the id which selects each one.",
+            Vec::new(),
+            json!({
+                "type": "object",
+                "properties": { "profiles": { "type": "array" } },
+            }),
+        ),
+    );
+    paths.insert("/query/v1".to_string(), post_operation());
+    paths.insert(
+        "/query/schema".to_string(),
+        json!({
+            "get": {
+                "operationId": "schema",
+                "description": "This OpenAPI document.",
+                "responses": {
+                    "200": {
+                        "description": "The OpenAPI document",
+                        "content": { "application/json": { "schema": { "type": "object" } } },
+                    },
+                },
+            },
+        }),
+    );
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "samply-for-ai query API",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Queries over the profiles of an analysis server ('samply-for-ai \
+                            analyze serve'). Every endpoint is a GET with query parameters, \
+                            except for POST /query/v1, which takes any query as JSON. \
+                            On a server with several profiles, /query/<id>/<query> queries \
+                            the profile with that id, and /query/<query> the first one. \
+                            Responses have 'success': false and an 'error' message if the \
+                            query failed, with an HTTP error status and the 'code' of the \
+                            error.",
+        },
+        "paths": paths,
+        "components": {
+            "schemas": {
+                "QueryResponse": {
+                    "type": "object",
+                    "properties": {
+                        "success": { "type": "boolean" },
+                        "query": { "type": "string" },
+                        "error": { "type": "string" },
+                        "units": {
+                            "type": "object",
+                            "description": "The unit of each numeric field of 'data', by field name",
+                        },
+                        "profiler_url": {
+                            "type": "string",
+                            "description": "The matching view of the Firefox Profiler",
+                        },
+                        "merged_threads": {
+                            "type": "object",
+                            "description": "The per-thread breakdown of the merge_threads parameter",
+                        },
+                        "page": {
+                            "type": "object",
+                            "description": "The page of the ranked results, if there are more \
+                                            or it's not the first one",
+                            "properties": {
+                                "offset": { "type": "integer" },
+                                "limit": { "type": "integer" },
+                                "returned": { "type": "integer" },
+                                "has_more": { "type": "boolean" },
+                                "next_cursor": {
+                                    "type": ["string", "null"],
+                                    "description": "Pass as 'cursor' to get the next page",
+                                },
+                            },
+                        },
+                        "truncated": {
+                            "type": "boolean",
+                            "description": "Whether items or nested lists were left out to \
+                                            stay within max_bytes",
+                        },
+                    },
+                    "required": ["success"],
+                },
+                "QueryRequest": {
+                    "type": "object",
+                    "properties": {
+                        "query": {
+                            "type": "string",
+                            "description": "The query's name, e.g. 'hotspots'",
+                        },
+                        "profile": {
+                            "type": "string",
+                            "description": "The id of the profile to query; the first one by default",
+                        },
+                        "filters": {
+                            "type": "object",
+                            "properties": {
+                                "threads": {
+                                    "type": "array",
+                                    "items": { "type": "string" },
+                                    "description": "Only the threads whose name contains one of these",
+                                },
+                                "processes": {
+                                    "type": "array",
+                                    "items": { "type": "string" },
+                                    "description": "Only the threads of the processes with one of these pids or names",
+                                },
+                                "functions": {
+                                    "type": "array",
+                                    "items": { "type": "string" },
+                                    "description": "Answer the query once per function, as its 'function' parameter",
+                                },
+                                "start_ms": { "type": "number" },
+                                "end_ms": { "type": "number" },
+                            },
+                            "additionalProperties": false,
+                        },
+                        "params": {
+                            "type": "object",
+                            "description": "Further parameters of the query, as for its GET endpoint; \
+                                            arrays are joined with commas",
+                        },
+                    },
+                    "required": ["query"],
+                    "additionalProperties": false,
+                },
+                "QueryError": {
+                    "type": "object",
+                    "properties": {
+                        "success": { "const": false },
+                        "code": {
+                            "type": "string",
+                            "enum": QueryErrorCode::ALL.map(QueryErrorCode::as_str),
+                        },
+                        "error": { "type": "string" },
+                        "candidates": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "For UNKNOWN_FUNCTION: similar function names, closest first",
+                        },
+                    },
+                    "required": ["success", "code", "error"],
+                },
+            },
+        },
+    })
+}
+
+fn parameter(name: &str, ty: &str, description: &str, required: bool) -> Value {
+    json!({
+        "name": name,
+        "in": "query",
+        "description": description,
+        "required": required,
+        "schema": { "type": ty },
+    })
+}
+
+/// The GET operation of a query endpoint, whose responses have `data` of the
+/// shape of `data`
+fn operation(name: &str, description: &str, parameters: Vec<Value>, data: Value) -> Value {
+    let mut responses = error_responses();
+    responses.insert(
+        "200".to_string(),
+        json!({
+            "description": "The query response",
+            "content": {
+                "application/json": {
+                    "schema": {
+                        "allOf": [
+                            { "$ref": "#/components/schemas/QueryResponse" },
+                            { "type": "object", "properties": { "data": data } },
+                        ],
+                    },
+                },
+            },
+        }),
+    );
+    json!({
+        "get": {
+            "operationId": name,
+            "description": description,
+            "parameters": parameters,
+            "responses": responses,
+        },
+    })
+}
+
+/// The POST operation of `/query/v1`, which answers the query of a
+/// `QueryRequest` body
+fn post_operation() -> Value {
+    let mut responses = error_responses();
+    responses.insert(
+        "200".to_string(),
+        json!({
+            "description": "The query response. With 'functions' filters, 'data' lists \
+                            the 'response' for each 'function'.",
+            "content": {
+                "application/json": {
+                    "schema": { "$ref": "#/components/schemas/QueryResponse" },
+                },
+            },
+        }),
+    );
+    json!({
+        "post": {
+            "operationId": "query",
+            "description": "Answer any query, with its filters and parameters in a JSON body.",
+            "requestBody": {
+                "required": true,
+                "content": {
+                    "application/json": {
+                        "schema": { "$ref": "#/components/schemas/QueryRequest" },
+                    },
+                },
+            },
+            "responses": responses,
+        },
+    })
+}
+
+/// The responses of failed queries, by HTTP status, with the error codes
+/// which have that status
+fn error_responses() -> Map<String, Value> {
+    let mut codes_by_status: BTreeMap<u16, Vec<&str>> = BTreeMap::new();
+    for code in QueryErrorCode::ALL {
+        codes_by_status
+            .entry(code.http_status())
+            .or_default()
+            .push(code.as_str());
+    }
+    codes_by_status
+        .into_iter()
+        .map(|(status, codes)| {
+            let response = json!({
+                "description": format!("The query failed: {}", codes.join(", ")),
+                "content": {
+                    "application/json": {
+                        "schema": { "$ref": "#/components/schemas/QueryError" },
+                    },
+                },
+            });
+            (status.to_string(), response)
+        })
+        .collect()
+}
+
+fn data_schema(data: &Data) -> Value {
+    match data {
+        Data::Object(fields) => object_schema(fields),
+        Data::Array(fields) => json!({ "type": "array", "items": object_schema(fields) }),
+        Data::OneOf(shapes) => {
+            json!({ "oneOf": shapes.iter().map(data_schema).collect::<Vec<_>>() })
+        }
+    }
+}
+
+/// An object with `fields`, whose types like `"string|null"` become type
+/// lists
+fn object_schema(fields: &[(&str, &str)]) -> Value {
+    let properties: Map<String, Value> = fields
+        .iter()
+        .map(|&(name, ty)| {
+            let ty = match ty.split_once('|') {
+                Some((ty, other)) => json!([ty, other]),
+                None => json!(ty),
+            };
+            (name.to_string(), json!({ "type": ty }))
+        })
+        .collect();
+    json!({ "type": "object", "properties": properties })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openapi() {
+        let schema = openapi();
+        let paths = schema["paths"].as_object().unwrap();
+        for tool in TOOLS {
+            assert!(paths.contains_key(tool.query_path), "{}", tool.query_path);
+        }
+
+        let hotspots = &paths["/query/hotspots"]["get"];
+        assert_eq!(hotspots["operationId"], "hotspots");
+        let parameters = hotspots["parameters"].as_array().unwrap();
+        assert!(parameters
+            .iter()
+            .any(|p| p["name"] == "limit" && p["schema"]["type"] == "integer"));
+        assert!(parameters.iter().any(|p| p["name"] == "precision"));
+        assert!(parameters.iter().any(|p| p["name"] == "cursor"));
+        assert_eq!(
+            hotspots["responses"]["404"]["description"],
+            "The query failed: UNKNOWN_FUNCTION, NOT_FOUND"
+        );
+
+        let query = &paths["/query/v1"]["post"];
+        assert_eq!(
+            query["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/QueryRequest"
+        );
+        assert!(query["responses"]["400"].is_object());
+
+        let data = &paths["/query/drilldown"]["get"]["responses"]["200"]["content"]
+            ["application/json"]["schema"]["allOf"][1]["properties"]["data"];
+        assert_eq!(data["properties"]["total_samples"]["type"], "integer");
+        assert_eq!(
+            data["properties"]["error"]["type"],
+            json!(["string", "null"])
+        );
+    }
+}
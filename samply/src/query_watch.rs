@@ -0,0 +1,251 @@
+//! `samply-for-ai query hotspots --watch INTERVAL`: re-runs the hotspots query
+//! on an interval and redraws a compact table, with how far each function
+//! moved and how its self time changed since the previous run. Meant for
+//! profiles which change while they're served, like the snapshots of
+//! `record --live`.
+
+use std::collections::HashMap;
+use std::io::{IsTerminal, Write};
+use std::time::{Duration, SystemTime};
+
+use crate::cli_utils::terminal_width;
+use crate::query_client::QueryError;
+
+/// A function is the same across runs if its name and library are.
+type FunctionKey = (String, Option<String>);
+
+/// The rank and self percentage of each function of the previous run
+type PreviousRun = HashMap<FunctionKey, (usize, f64)>;
+
+/// Changes of the self percentage by at least this many points are
+/// highlighted.
+const SIGNIFICANT_CHANGE: f64 = 1.0;
+
+/// A row of the table
+#[derive(Debug, Clone, PartialEq)]
+struct WatchRow {
+    key: FunctionKey,
+    self_percent: f64,
+    total_percent: f64,
+    self_samples: i64,
+}
+
+/// Where a function moved since the previous run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Movement {
+    /// Not in the previous run's hotspots
+    New,
+    Up(usize),
+    Down(usize),
+    Same,
+}
+
+/// Run `query` every `interval` until the process is interrupted, and show
+/// the hotspots of each response in place of the previous ones. Failed
+/// queries are shown and retried, e.g. while the server is being restarted.
+pub fn watch_hotspots(interval: Duration, mut query: impl FnMut() -> Result<String, QueryError>) {
+    let mut stdout = std::io::stdout();
+    let styled = stdout.is_terminal();
+    let width = terminal_width();
+    let mut previous_run: Option<PreviousRun> = None;
+    loop {
+        let mut frame = String::new();
+        if styled {
+            // Clear the screen and move the cursor to the top left.
+            frame.push_str("\x1b[2J\x1b[H");
+        }
+        frame.push_str(&format!(
+            "hotspots every {}, at {} (Ctrl+C to stop)\n\n",
+            humantime::format_duration(interval),
+            humantime::format_rfc3339_seconds(SystemTime::now())
+        ));
+        match query().and_then(|json| parse_rows(&json)) {
+            Ok(rows) => {
+                frame.push_str(&render_table(&rows, previous_run.as_ref(), width, styled));
+                previous_run = Some(
+                    rows.into_iter()
+                        .enumerate()
+                        .map(|(rank, row)| (row.key, (rank, row.self_percent)))
+                        .collect(),
+                );
+            }
+            Err(e) => frame.push_str(&format!("Query failed: {e}\n")),
+        }
+        if stdout
+            .write_all(frame.as_bytes())
+            .and_then(|()| stdout.flush())
+            .is_err()
+        {
+            // stdout was closed, e.g. by `| head`.
+            return;
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+/// The hotspots of a hotspots query response, by rank
+fn parse_rows(json: &str) -> Result<Vec<WatchRow>, QueryError> {
+    let response: serde_json::Value =
+        serde_json::from_str(json).map_err(|e| QueryError::InvalidResponse(e.to_string()))?;
+    let Some(entries) = response["data"].as_array() else {
+        let error = response["error"]
+            .as_str()
+            .unwrap_or("no hotspots in the response");
+        return Err(QueryError::InvalidResponse(error.to_string()));
+    };
+    Ok(entries
+        .iter()
+        .map(|entry| WatchRow {
+            key: (
+                entry["function"]["name"]
+                    .as_str()
+                    .unwrap_or("?")
+                    .to_string(),
+                entry["function"]["library"].as_str().map(String::from),
+            ),
+            self_percent: entry["self_percent"].as_f64().unwrap_or(0.0),
+            total_percent: entry["total_percent"].as_f64().unwrap_or(0.0),
+            self_samples: entry["self_samples"].as_i64().unwrap_or(0),
+        })
+        .collect())
+}
+
+fn movement(key: &FunctionKey, rank: usize, previous_run: &PreviousRun) -> Movement {
+    match previous_run.get(key) {
+        None => Movement::New,
+        Some(&(previous, _)) if previous > rank => Movement::Up(previous - rank),
+        Some(&(previous, _)) if previous < rank => Movement::Down(rank - previous),
+        Some(_) => Movement::Same,
+    }
+}
+
+/// The table of the hotspots, one line per function, followed by the
+/// functions which dropped out of the hotspots since the previous run. On the
+/// first run, without a previous run, the movement and change columns are
+/// empty.
+fn render_table(
+    rows: &[WatchRow],
+    previous_run: Option<&PreviousRun>,
+    width: usize,
+    styled: bool,
+) -> String {
+    if rows.is_empty() {
+        return "No samples yet.\n".to_string();
+    }
+    let mut table = format!(
+        "{:>4}  {:<5}  {:>7}  {:>7}  {:>7}  {:>9}  FUNCTION\n",
+        "RANK", "MOVE", "SELF%", "CHANGE", "TOTAL%", "SAMPLES"
+    );
+    // The columns before the function name
+    let name_width = width.saturating_sub(51).max(20);
+    for (rank, row) in rows.iter().enumerate() {
+        let movement = previous_run.map(|previous_run| movement(&row.key, rank, previous_run));
+        let (label, color) = match movement {
+            Some(Movement::New) => ("new".to_string(), "\x1b[33m"),
+            Some(Movement::Up(by)) => (format!("+{by}"), "\x1b[32m"),
+            Some(Movement::Down(by)) => (format!("-{by}"), "\x1b[31m"),
+            Some(Movement::Same) | None => (String::new(), ""),
+        };
+        let label = style(format!("{label:<5}"), color, styled);
+        let change = previous_run
+            .and_then(|previous_run| previous_run.get(&row.key))
+            .map(|&(_, previous_percent)| row.self_percent - previous_percent)
+            .filter(|change| change.abs() >= 0.005);
+        let change = match change {
+            Some(change) if change.abs() >= SIGNIFICANT_CHANGE => {
+                style(format!("{change:>+7.2}"), "\x1b[1m", styled)
+            }
+            Some(change) => format!("{change:>+7.2}"),
+            None => format!("{:>7}", ""),
+        };
+        table.push_str(&format!(
+            "{:>4}  {label}  {:>7.2}  {change}  {:>7.2}  {:>9}  {}\n",
+            rank + 1,
+            row.self_percent,
+            row.total_percent,
+            row.self_samples,
+            truncate(&function_name(&row.key), name_width)
+        ));
+    }
+
+    if let Some(previous_run) = previous_run {
+        let mut gone: Vec<(usize, &FunctionKey)> = previous_run
+            .iter()
+            .filter(|(key, _)| !rows.iter().any(|row| &row.key == *key))
+            .map(|(key, &(rank, _))| (rank, key))
+            .collect();
+        gone.sort();
+        if !gone.is_empty() {
+            let names: Vec<String> = gone.iter().map(|(_, key)| function_name(key)).collect();
+            table.push_str(&format!(
+                "\n{} {}\n",
+                style("gone:".to_string(), "\x1b[2m", styled),
+                truncate(&names.join(", "), width.saturating_sub(6).max(20))
+            ));
+        }
+    }
+    table
+}
+
+fn function_name((name, library): &FunctionKey) -> String {
+    match library {
+        Some(library) => format!("{name} ({library})"),
+        None => name.clone(),
+    }
+}
+
+/// Wrap `s` in the ANSI escape `color` when the output is styled.
+fn style(s: String, color: &str, styled: bool) -> String {
+    if styled && !color.is_empty() {
+        format!("{color}{s}\x1b[0m")
+    } else {
+        s
+    }
+}
+
+/// Shorten `s` to `max` characters, ending in "…" if it's cut.
+fn truncate(s: &str, max: usize) -> String {
+    if s.chars().count() <= max {
+        return s.to_string();
+    }
+    let mut truncated: String = s.chars().take(max.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(name: &str, self_percent: f64) -> WatchRow {
+        WatchRow {
+            key: (name.to_string(), Some("app".to_string())),
+            self_percent,
+            total_percent: self_percent,
+            self_samples: (self_percent * 10.0) as i64,
+        }
+    }
+
+    #[test]
+    fn test_render_movement() {
+        let previous: PreviousRun = [row("parse", 35.0), row("alloc", 32.5), row("lex", 5.0)]
+            .into_iter()
+            .enumerate()
+            .map(|(rank, row)| (row.key, (rank, row.self_percent)))
+            .collect();
+        let rows = [row("alloc", 40.0), row("parse", 30.0), row("hash", 10.0)];
+        let table = render_table(&rows, Some(&previous), 120, false);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert!(lines[1].contains("+1") && lines[1].ends_with("alloc (app)"));
+        assert!(lines[1].contains("  +7.50  "));
+        assert!(lines[2].contains("-1") && lines[2].ends_with("parse (app)"));
+        assert!(lines[2].contains("  -5.00  "));
+        assert!(lines[3].contains("new") && lines[3].ends_with("hash (app)"));
+        assert_eq!(lines[5], "gone: lex (app)");
+
+        let first_run = render_table(&rows, None, 120, false);
+        assert!(!first_run.contains("new"));
+        assert_eq!(truncate("a_very_long_function", 8), "a_very_…");
+    }
+}
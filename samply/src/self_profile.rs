@@ -0,0 +1,113 @@
+//! `--self-profile`: record a profile of samply-for-ai while it runs a subcommand.
+//!
+//! Rather than sampling our own process from the inside, we re-run the same
+//! command line (minus `--self-profile`) under `samply-for-ai record`, which
+//! saves the profile of the inner process to the requested path. The inner
+//! process does the actual work, so its output, interactivity and exit code are
+//! the same as without `--self-profile`.
+
+use std::ffi::OsString;
+use std::path::Path;
+
+/// Remove the `--self-profile <PATH>` / `--self-profile=<PATH>` options from
+/// the arguments (without the program name). The option is only accepted
+/// before the subcommand, so we stop looking at the first other argument.
+fn strip_self_profile_args(args: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    let mut args = args.into_iter();
+    let mut remaining = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == "--self-profile" {
+            args.next();
+            continue;
+        }
+        if arg.to_string_lossy().starts_with("--self-profile=") {
+            continue;
+        }
+        remaining.push(arg);
+        break;
+    }
+    remaining.extend(args);
+    remaining
+}
+
+/// Run this command line again under `samply-for-ai record`, save the profile
+/// to `output_path`, and exit with the exit code of the profiled run.
+#[cfg(any(
+    target_os = "android",
+    target_os = "macos",
+    target_os = "linux",
+    target_os = "windows"
+))]
+pub fn run_self_profiled(output_path: &Path) -> ! {
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(err) => {
+            eprintln!(
+                "Error: Couldn't find the samply-for-ai executable for --self-profile: {err}"
+            );
+            std::process::exit(1);
+        }
+    };
+    let inner_args = strip_self_profile_args(std::env::args_os().skip(1));
+
+    let status = std::process::Command::new(&exe)
+        .arg("record")
+        .arg("--save-only")
+        .arg("--output")
+        .arg(output_path)
+        .arg("--")
+        .arg(&exe)
+        .args(&inner_args)
+        .status();
+    match status {
+        Ok(status) => {
+            if output_path.exists() {
+                eprintln!(
+                    "Saved the profile of samply-for-ai to {}",
+                    output_path.display()
+                );
+            }
+            std::process::exit(status.code().unwrap_or(1));
+        }
+        Err(err) => {
+            eprintln!("Error: Couldn't start the self-profiling recording: {err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(not(any(
+    target_os = "android",
+    target_os = "macos",
+    target_os = "linux",
+    target_os = "windows"
+)))]
+pub fn run_self_profiled(_output_path: &Path) -> ! {
+    eprintln!(
+        "Error: --self-profile needs recording support, which isn't available on this platform."
+    );
+    std::process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_self_profile_args() {
+        let args = |list: &[&str]| list.iter().map(OsString::from).collect::<Vec<_>>();
+        assert_eq!(
+            strip_self_profile_args(args(&["--self-profile", "self.json", "analyze", "p.json"])),
+            args(&["analyze", "p.json"])
+        );
+        assert_eq!(
+            strip_self_profile_args(args(&["--self-profile=self.json", "record", "--", "app"])),
+            args(&["record", "--", "app"])
+        );
+        // Only the leading option is removed, not an argument of the profiled command.
+        assert_eq!(
+            strip_self_profile_args(args(&["record", "app", "--self-profile", "x"])),
+            args(&["record", "app", "--self-profile", "x"])
+        );
+    }
+}
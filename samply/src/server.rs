@@ -1,30 +1,52 @@
 use std::collections::HashMap;
 use std::convert::Infallible;
 use std::ffi::OsStr;
-use std::io::BufWriter;
+use std::fs::File;
+use std::io::{BufWriter, Write};
 use std::net::{IpAddr, SocketAddr};
 use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
+use debugid::DebugId;
 use futures_util::TryStreamExt;
 use http_body_util::combinators::BoxBody;
-use http_body_util::{BodyExt, Either, Full, StreamBody};
+use http_body_util::{BodyExt, Either, Full, Limited, StreamBody};
 use hyper::body::{Bytes, Frame};
-use hyper::server::conn::http1;
 use hyper::service::service_fn;
 use hyper::{header, Method, Request, Response, StatusCode};
-use hyper_util::rt::TokioIo;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
 use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
 use rand::RngCore;
+use regex::Regex;
+use samply_analysis::{
+    paginate, response_units, round_floats, ActivityPatterns, AsmSymbolInfo, ExportFormat,
+    HotspotsGroupBy, LibrarySymbolicationStatus, MatchMode, MergedThreads, Page, ParseLimits,
+    ProfileAnalyzer, QueryErrorCode, QueryFailure, SymbolSource, SymbolicationState,
+    SymbolicationStatusResponse, ThreadMerge, TimeRange, DEFAULT_PRECISION,
+};
+use serde::Deserialize;
+use tempfile::TempPath;
 use tokio::io::BufReader;
 use tokio::net::TcpListener;
+use tokio::sync::{watch, Notify};
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::TlsAcceptor;
 use tokio_util::io::ReaderStream;
-use wholesym::SymbolManager;
+use wholesym::{LookupAddress, SourceFileChecksum, SymbolManager};
 
-use crate::profile_analysis::ProfileAnalyzer;
+use crate::profile_json_preparse::{
+    parse_libinfo_map_from_profile, parse_libinfo_map_from_profile_file,
+};
 use crate::shared::ctrl_c;
+use crate::shared::live_profile::{LiveProfile, LiveSnapshot};
+use crate::shared::symbol_precog::{add_sidecar_symbols, PrecogLibraySymbolMap};
+use crate::shared::transform::retain_matching_threads;
 
 #[derive(Clone, Debug)]
 pub struct ServerProps {
@@ -32,6 +54,58 @@ pub struct ServerProps {
     pub port_selection: PortSelection,
     pub verbose: bool,
     pub open_in_browser: bool,
+    pub token: ServerToken,
+    /// Larger POST bodies (e.g. symbolication requests) are rejected with 413.
+    pub max_body_size: usize,
+    /// Serve profiles from untrusted sources: load them with
+    /// [`ParseLimits::SANDBOX`], and disable everything which reads files named
+    /// by the profile (symbolication, source and asm queries) or discloses
+    /// server paths.
+    pub sandbox: bool,
+    /// Serve only the threads which this matches (`--threads`), see
+    /// [`retain_matching_threads`].
+    pub thread_subset: Option<Regex>,
+    /// Fail if a profile's symbols sidecar is missing or can't be loaded
+    /// (`--require-precog`), see [`add_sidecar_symbols`].
+    pub require_precog: bool,
+    /// Serve HTTPS with this certificate and key (`--tls-cert`, `--tls-key`)
+    /// instead of HTTP.
+    pub tls: Option<TlsFiles>,
+    /// Also require `Authorization: Bearer <token>` for the profiles and the
+    /// queries (`--auth-token`), on top of the secret path prefix.
+    pub auth_token: Option<String>,
+}
+
+/// The PEM files of a TLS certificate chain and its private key
+#[derive(Clone, Debug)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+/// The default for [`ServerProps::max_body_size`], matching the `--max-body-mb` default
+pub const DEFAULT_MAX_BODY_SIZE: usize = 64 * 1024 * 1024;
+
+/// The secret first path component which all URLs of the server need to have
+#[derive(Clone, Debug)]
+pub enum ServerToken {
+    /// A new random token for every server start
+    Random,
+    /// A fixed token from `--token`, so URLs stay the same across restarts
+    Fixed(String),
+    /// No token (`--no-token`): everything is served at the root
+    None,
+}
+
+impl ServerToken {
+    /// The path prefix for this token, e.g. `/<token>`; empty without a token.
+    fn path_prefix(&self) -> String {
+        match self {
+            ServerToken::Random => format!("/{}", generate_token()),
+            ServerToken::Fixed(token) => format!("/{token}"),
+            ServerToken::None => String::new(),
+        }
+    }
 }
 
 const BAD_CHARS: &AsciiSet = &CONTROLS.add(b':').add(b'/');
@@ -62,6 +136,85 @@ pub struct RunningServerInfo {
     pub profiler_url: Option<String>,
     /// Whether the profile appears to be unsymbolicated (function names are hex addresses)
     pub is_likely_unsymbolicated: bool,
+    /// The profiles of an analysis server; empty for other servers
+    pub profiles: Vec<ServedProfile>,
+}
+
+/// A profile of the analysis server
+pub struct ServedProfile {
+    /// Selects the profile in `/query/<id>/<query>` URLs
+    pub id: String,
+    pub path: PathBuf,
+    pub profiler_url: String,
+}
+
+/// A profile loaded by the analysis server
+struct LoadedProfile {
+    id: String,
+    path: PathBuf,
+    analyzer: Arc<ProfileAnalyzer>,
+    symbolication_status: Arc<SymbolicationStatusTracker>,
+    profile_url: String,
+    profiler_url: String,
+    /// The copy with the `--threads` subset of the profile, which is served
+    /// and queried instead of `path`
+    subset: Option<TempPath>,
+    /// For the profile of `samply record --live`, the snapshots which are
+    /// served and queried instead of `path`, which is only written once
+    /// recording has ended
+    live: Option<Arc<LiveProfile>>,
+    activity_patterns: ActivityPatterns,
+}
+
+impl LoadedProfile {
+    /// The file which `/profile.json` serves
+    fn served_path(&self) -> &Path {
+        self.subset.as_deref().unwrap_or(&self.path)
+    }
+
+    /// The analyzer for the queries: that of the latest snapshot for a live
+    /// profile
+    fn analyzer(&self) -> Arc<ProfileAnalyzer> {
+        let snapshot = self.live.as_ref().and_then(|live| live.latest());
+        match snapshot.map(|snapshot| snapshot.analyzer(&self.activity_patterns)) {
+            Some(Ok(analyzer)) => analyzer,
+            Some(Err(err)) => {
+                eprintln!("Couldn't analyze the latest snapshot: {err}");
+                self.analyzer.clone()
+            }
+            None => self.analyzer.clone(),
+        }
+    }
+}
+
+/// Ids for the profiles of an analysis server: their file names without the
+/// `.json` / `.json.gz` extension, made unique with a `-2`, `-3`... suffix.
+fn profile_ids(paths: &[PathBuf]) -> Vec<String> {
+    let mut ids: Vec<String> = Vec::new();
+    for path in paths {
+        let file_name = path
+            .file_name()
+            .map_or(String::new(), |name| name.to_string_lossy().into_owned());
+        let stem = file_name.trim_end_matches(".gz").trim_end_matches(".json");
+        let mut base: String = stem
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+                _ => '-',
+            })
+            .collect();
+        if base.is_empty() {
+            base = "profile".to_string();
+        }
+        let mut id = base.clone();
+        let mut suffix = 2;
+        while ids.contains(&id) {
+            id = format!("{base}-{suffix}");
+            suffix += 1;
+        }
+        ids.push(id);
+    }
+    ids
 }
 
 pub async fn start_server(
@@ -71,19 +224,31 @@ pub async fn start_server(
     stop_signal: ctrl_c::Receiver,
 ) -> RunningServerInfo {
     let (listener, addr) = make_listener(server_props.address, server_props.port_selection).await;
+    let tls_acceptor = server_props.tls.as_ref().map(make_tls_acceptor);
 
-    let token = generate_token();
-    let path_prefix = format!("/{token}");
+    let path_prefix = server_props.token.path_prefix();
     let env_server_override = std::env::var("SAMPLY_SERVER_URL").ok();
     let server_origin = match &env_server_override {
         Some(s) => s.trim_end_matches('/').to_string(),
-        None => format!("http://{addr}"),
+        None => format!("{}://{addr}", url_scheme(&tls_acceptor)),
     };
     let symbol_server_url = format!("{server_origin}{path_prefix}");
     let mut template_values: HashMap<&'static str, String> = HashMap::new();
     template_values.insert("SAMPLY_SERVER_URL", server_origin.clone());
     template_values.insert("PATH_PREFIX", path_prefix.clone());
 
+    let subset = match (&server_props.thread_subset, profile_filename) {
+        (Some(pattern), Some(path)) => match write_thread_subset(path, pattern) {
+            Ok(subset) => Some(subset),
+            Err(err) => {
+                eprintln!("Couldn't serve the --threads subset of {path:?}: {err}");
+                std::process::exit(1)
+            }
+        },
+        _ => None,
+    };
+    let profile_filename = subset.as_deref().or(profile_filename);
+
     let profiler_url = if profile_filename.is_some() {
         let profile_url = format!("{symbol_server_url}/profile.json");
 
@@ -108,15 +273,26 @@ pub async fn start_server(
 
     let template_values = Arc::new(template_values);
 
-    let server_join_handle = tokio::task::spawn(run_server(
+    let server = run_server(
         listener,
-        symbol_manager,
-        None, // No profile analyzer for regular server
+        tls_acceptor,
+        Arc::new(symbol_manager),
+        Arc::new(Vec::new()), // No profile analyzers for regular server
+        None,
         profile_filename.map(PathBuf::from),
         template_values,
         path_prefix.clone(),
+        server_props.auth_token,
+        server_props.max_body_size,
+        server_props.sandbox,
         stop_signal,
-    ));
+    );
+    let server_join_handle = tokio::task::spawn(async move {
+        let result = server.await;
+        // The subset is served until the server stops.
+        drop(subset);
+        result
+    });
 
     RunningServerInfo {
         server_join_handle,
@@ -124,28 +300,116 @@ pub async fn start_server(
         token_url: symbol_server_url,
         profiler_url,
         is_likely_unsymbolicated: false, // Not applicable for regular server
+        profiles: Vec::new(),
     }
 }
 
-/// Start an analysis server with profile loaded for querying
+/// Tell the user that `--low-memory` is indexing a profile, which takes a
+/// while for the large profiles it's meant for.
+pub fn report_index_build(index_dir: &Path) {
+    eprintln!("Building profile index at {}...", index_dir.display());
+}
+
+/// Start an analysis server with profiles loaded for querying
+///
+/// `profile_paths` must not be empty. Each profile is queried at
+/// `/query/<id>/<query>`; the first one also at `/query/<query>`.
+/// With `low_memory`, the profiles are loaded through their on-disk index (built
+/// on first use) instead of being parsed into memory. If `baseline_path` is
+/// given, that profile is loaded too, and the diff query compares against it.
+/// In sandbox mode, the profiles are loaded with [`ParseLimits::SANDBOX`] and
+/// the symbol manager never looks up their libraries.
+/// With `live`, the first profile is the one which `samply record --live` is
+/// recording to its path; it's served and queried from the snapshots, starting
+/// with the first one, which this waits for.
 pub async fn start_analysis_server(
-    profile_path: &Path,
+    profile_paths: &[PathBuf],
+    baseline_path: Option<&Path>,
     server_props: ServerProps,
-    symbol_manager: SymbolManager,
+    mut symbol_manager: SymbolManager,
     stop_signal: ctrl_c::Receiver,
-) -> Result<RunningServerInfo, crate::profile_analysis::AnalysisError> {
-    // Load the profile for analysis
-    let analyzer = ProfileAnalyzer::from_file(profile_path)?;
-    let is_likely_unsymbolicated = analyzer.is_likely_unsymbolicated();
+    low_memory: bool,
+    live: Option<Arc<LiveProfile>>,
+) -> Result<RunningServerInfo, samply_analysis::AnalysisError> {
+    let sandbox = server_props.sandbox;
+    let activity_patterns = crate::config::load_config().activity_patterns();
+    let load = |path: &Path| {
+        let result = if sandbox {
+            ProfileAnalyzer::from_file_with_limits(path, &ParseLimits::SANDBOX)
+        } else if low_memory {
+            ProfileAnalyzer::from_file_low_memory(path, report_index_build)
+        } else {
+            ProfileAnalyzer::from_file(path)
+        };
+        result.map(|mut analyzer| {
+            analyzer.set_activity_patterns(activity_patterns.clone());
+            analyzer
+        })
+    };
+
+    // With --threads, the subsets are queried too, so that the thread indexes
+    // in the queries' profiler URLs are those of the served profiles. The
+    // snapshots of a live profile are served whole.
+    let is_live = |index: usize| index == 0 && live.is_some();
+    let subsets = profile_paths
+        .iter()
+        .enumerate()
+        .map(|(index, path)| {
+            server_props
+                .thread_subset
+                .as_ref()
+                .filter(|_| !is_live(index))
+                .map(|pattern| write_thread_subset(path, pattern))
+                .transpose()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Load the profiles for analysis
+    let mut analyzers = Vec::new();
+    for (index, (path, subset)) in profile_paths.iter().zip(&subsets).enumerate() {
+        let analyzer = match &live {
+            Some(live) if is_live(index) => live
+                .first_snapshot()
+                .await
+                .analyzer(&activity_patterns)
+                .map_err(samply_analysis::AnalysisError::InvalidProfile)?,
+            _ => Arc::new(load(subset.as_deref().unwrap_or(path))?),
+        };
+        analyzers.push(analyzer);
+    }
+    let baseline = baseline_path.map(load).transpose()?;
+    let is_likely_unsymbolicated = analyzers.iter().any(|a| a.is_likely_unsymbolicated());
+
+    let mut sidecar_maps = Vec::new();
+    for (_, profile_path) in profile_paths
+        .iter()
+        .enumerate()
+        .filter(|&(index, _)| !sandbox && !is_live(index))
+    {
+        sidecar_maps.extend(
+            register_profile_libraries(
+                &mut symbol_manager,
+                profile_path,
+                server_props.require_precog,
+            )
+            .map_err(std::io::Error::other)?,
+        );
+    }
+    let symbol_manager = Arc::new(symbol_manager);
+    if let Some(live) = live.as_ref().filter(|_| !sandbox) {
+        let mut snapshots = live.subscribe();
+        snapshots.mark_changed();
+        tokio::task::spawn(register_live_libraries(symbol_manager.clone(), snapshots));
+    }
 
     let (listener, addr) = make_listener(server_props.address, server_props.port_selection.clone()).await;
+    let tls_acceptor = server_props.tls.as_ref().map(make_tls_acceptor);
 
-    let token = generate_token();
-    let path_prefix = format!("/{token}");
+    let path_prefix = server_props.token.path_prefix();
     let env_server_override = std::env::var("SAMPLY_SERVER_URL").ok();
     let server_origin = match &env_server_override {
         Some(s) => s.trim_end_matches('/').to_string(),
-        None => format!("http://{addr}"),
+        None => format!("{}://{addr}", url_scheme(&tls_acceptor)),
     };
     let symbol_server_url = format!("{server_origin}{path_prefix}");
 
@@ -153,31 +417,82 @@ pub async fn start_analysis_server(
     template_values.insert("SAMPLY_SERVER_URL", server_origin.clone());
     template_values.insert("PATH_PREFIX", path_prefix.clone());
 
-    let profile_url = format!("{symbol_server_url}/profile.json");
     let env_profiler_override = std::env::var("PROFILER_URL").ok();
     let profiler_origin = match &env_profiler_override {
         Some(s) => s.trim_end_matches('/'),
         None => "https://profiler.firefox.com",
     };
+    let encoded_symbol_server_url = utf8_percent_encode(&symbol_server_url, BAD_CHARS).to_string();
 
-    let encoded_profile_url = utf8_percent_encode(&profile_url, BAD_CHARS).to_string();
-    let encoded_symbol_server_url =
-        utf8_percent_encode(&symbol_server_url, BAD_CHARS).to_string();
-    let profiler_url = format!(
-        "{profiler_origin}/from-url/{encoded_profile_url}/?symbolServer={encoded_symbol_server_url}"
-    );
+    let mut profiles = Vec::new();
+    for (((id, profile_path), analyzer), subset) in profile_ids(profile_paths)
+        .into_iter()
+        .zip(profile_paths)
+        .zip(analyzers)
+        .zip(subsets)
+    {
+        // The first profile keeps the URL of a single-profile server.
+        let profile_url = if profiles.is_empty() {
+            format!("{symbol_server_url}/profile.json")
+        } else {
+            format!("{symbol_server_url}/profiles/{id}/profile.json")
+        };
+        let encoded_profile_url = utf8_percent_encode(&profile_url, BAD_CHARS).to_string();
+        let profiler_url = format!(
+            "{profiler_origin}/from-url/{encoded_profile_url}/?symbolServer={encoded_symbol_server_url}"
+        );
+
+        let symbolication_status = Arc::new(SymbolicationStatusTracker::new(
+            analyzer.library_symbolication_status(),
+        ));
+        if !sandbox {
+            tokio::task::spawn(check_library_symbols(
+                symbol_manager.clone(),
+                symbolication_status.clone(),
+                sidecar_maps.clone(),
+            ));
+        }
+
+        profiles.push(LoadedProfile {
+            id,
+            path: profile_path.clone(),
+            analyzer,
+            symbolication_status,
+            profile_url,
+            profiler_url,
+            subset,
+            live: live.clone().filter(|_| profiles.is_empty()),
+            activity_patterns: activity_patterns.clone(),
+        });
+    }
+
+    let profiler_url = profiles[0].profiler_url.clone();
     template_values.insert("PROFILER_URL", profiler_url.clone());
-    template_values.insert("PROFILE_URL", profile_url);
+    template_values.insert("PROFILE_URL", profiles[0].profile_url.clone());
 
     let template_values = Arc::new(template_values);
+    let served_profiles = profiles
+        .iter()
+        .map(|profile| ServedProfile {
+            id: profile.id.clone(),
+            path: profile.path.clone(),
+            profiler_url: profile.profiler_url.clone(),
+        })
+        .collect();
+    let profile_filename = profiles[0].served_path().to_path_buf();
 
     let server_join_handle = tokio::task::spawn(run_server(
         listener,
+        tls_acceptor,
         symbol_manager,
-        Some(Arc::new(analyzer)),
-        Some(profile_path.to_path_buf()),
+        Arc::new(profiles),
+        baseline.map(Arc::new),
+        Some(profile_filename),
         template_values,
         path_prefix.clone(),
+        server_props.auth_token,
+        server_props.max_body_size,
+        server_props.sandbox,
         stop_signal,
     ));
 
@@ -187,9 +502,247 @@ pub async fn start_analysis_server(
         token_url: symbol_server_url,
         profiler_url: Some(profiler_url),
         is_likely_unsymbolicated,
+        profiles: served_profiles,
     })
 }
 
+/// Write the threads of the profile at `profile_path` which `pattern` matches
+/// to a temporary file, which is deleted when the returned path is dropped.
+fn write_thread_subset(profile_path: &Path, pattern: &Regex) -> std::io::Result<TempPath> {
+    let mut profile = crate::read_profile_json(profile_path)?;
+    let thread_count = profile["threads"].as_array().map_or(0, Vec::len);
+    let kept = retain_matching_threads(&mut profile, pattern);
+    if kept == 0 {
+        return Err(std::io::Error::other(format!(
+            "none of its {thread_count} threads matches '{pattern}'"
+        )));
+    }
+    eprintln!("Serving {kept} of the {thread_count} threads of {profile_path:?}.");
+
+    let file = tempfile::Builder::new()
+        .prefix("samply-threads-")
+        .suffix(".json")
+        .tempfile()?;
+    let mut writer = BufWriter::new(file.as_file());
+    serde_json::to_writer(&mut writer, &profile)?;
+    writer.flush()?;
+    drop(writer);
+    Ok(file.into_temp_path())
+}
+
+/// Register the profile's libraries and the presymbolication sidecar, so that
+/// the symbolication API and the symbolication status check can find symbols.
+/// Returns the symbol maps of the libraries with sidecar symbols, or an error
+/// if `require_precog` and the sidecar couldn't be loaded.
+pub(crate) fn register_profile_libraries(
+    symbol_manager: &mut SymbolManager,
+    profile_path: &Path,
+    require_precog: bool,
+) -> Result<Vec<Arc<PrecogLibraySymbolMap>>, String> {
+    let mut debug_names = Vec::new();
+    if let Ok(libinfo_map) = File::open(profile_path)
+        .and_then(|file| parse_libinfo_map_from_profile_file(file, profile_path))
+    {
+        for lib_info in libinfo_map.into_values() {
+            debug_names.extend(lib_info.debug_name.clone());
+            symbol_manager.add_known_library(lib_info);
+        }
+    }
+    add_sidecar_symbols(symbol_manager, profile_path, &debug_names, require_precog)
+}
+
+/// Register the libraries of each snapshot of a live profile as it comes in,
+/// since the recorded processes keep loading libraries.
+async fn register_live_libraries(
+    symbol_manager: Arc<SymbolManager>,
+    mut snapshots: watch::Receiver<Option<Arc<LiveSnapshot>>>,
+) {
+    while snapshots.changed().await.is_ok() {
+        let Some(snapshot) = snapshots.borrow_and_update().clone() else {
+            continue;
+        };
+        if let Ok(libinfo_map) = parse_libinfo_map_from_profile(snapshot.json().as_bytes()) {
+            for lib_info in libinfo_map.into_values() {
+                symbol_manager.add_known_library(lib_info);
+            }
+        }
+    }
+}
+
+/// Tracks the symbolication status of each library for `/query/symbolication-status`.
+///
+/// Starts out with the coverage computed from the profile, and is updated by
+/// [`check_library_symbols`] as symbols are looked up for the remaining libraries.
+pub struct SymbolicationStatusTracker {
+    libraries: Mutex<Vec<LibrarySymbolicationStatus>>,
+    /// The sidecar symbol maps of the libraries resolved from the sidecar, by
+    /// index in `libraries`, for their approximate lookup counts
+    sidecar_maps: Mutex<Vec<(usize, Arc<PrecogLibraySymbolMap>)>>,
+}
+
+impl SymbolicationStatusTracker {
+    pub(crate) fn new(libraries: Vec<LibrarySymbolicationStatus>) -> Self {
+        Self {
+            libraries: Mutex::new(libraries),
+            sidecar_maps: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn response(&self) -> SymbolicationStatusResponse {
+        let mut libraries = self.libraries.lock().unwrap().clone();
+        for (index, symbol_map) in self.sidecar_maps.lock().unwrap().iter() {
+            libraries[*index].approximate_lookups = symbol_map.approximate_lookups();
+        }
+        let count = |state| libraries.iter().filter(|lib| lib.state == state).count();
+        SymbolicationStatusResponse {
+            resolved: count(SymbolicationState::Resolved),
+            pending: count(SymbolicationState::Pending),
+            failed: count(SymbolicationState::Failed),
+            libraries,
+        }
+    }
+}
+
+/// Look for symbols for every library which is still pending, and record where
+/// they were found, or why they couldn't be found.
+pub(crate) async fn check_library_symbols(
+    symbol_manager: Arc<SymbolManager>,
+    tracker: Arc<SymbolicationStatusTracker>,
+    sidecar_maps: Vec<Arc<PrecogLibraySymbolMap>>,
+) {
+    let pending: Vec<(usize, String, String)> = tracker
+        .libraries
+        .lock()
+        .unwrap()
+        .iter()
+        .enumerate()
+        .filter(|(_, lib)| lib.state == SymbolicationState::Pending)
+        .map(|(index, lib)| (index, lib.debug_name.clone(), lib.debug_id.clone()))
+        .collect();
+
+    for (index, debug_name, debug_id) in pending {
+        let result = match DebugId::from_breakpad(&debug_id) {
+            Ok(debug_id) => match sidecar_maps
+                .iter()
+                .find(|symbol_map| symbol_map.library_info().debug_id == Some(debug_id))
+            {
+                Some(symbol_map) => {
+                    tracker
+                        .sidecar_maps
+                        .lock()
+                        .unwrap()
+                        .push((index, symbol_map.clone()));
+                    Ok(SymbolSource::Precog)
+                }
+                None => match symbol_manager.load_symbol_map(&debug_name, debug_id).await {
+                    Ok(symbol_map) if symbol_map.symbol_file_origin().is_from_server() => {
+                        Ok(SymbolSource::Server)
+                    }
+                    Ok(_) => Ok(SymbolSource::Local),
+                    Err(e) => Err((symbol_error_kind(&e), e.to_string())),
+                },
+            },
+            Err(e) => Err(("invalid_debug_id", format!("Invalid debug ID {debug_id:?}: {e}"))),
+        };
+
+        let mut libraries = tracker.libraries.lock().unwrap();
+        let lib = &mut libraries[index];
+        match result {
+            Ok(source) => {
+                lib.state = SymbolicationState::Resolved;
+                lib.source = Some(source);
+            }
+            Err((kind, message)) => {
+                lib.state = SymbolicationState::Failed;
+                lib.error_kind = Some(kind.to_string());
+                lib.error = Some(message);
+            }
+        }
+    }
+}
+
+/// Look up the symbol of the function targeted by an asm or source query, along
+/// with the source line of each of its sampled instructions and the checksum
+/// of its source file.
+pub(crate) async fn lookup_asm_symbol(
+    symbol_manager: &SymbolManager,
+    analyzer: &ProfileAnalyzer,
+    function: &str,
+    match_mode: MatchMode,
+) -> Option<AsmSymbolInfo> {
+    let function = analyzer.resolve_function(function, match_mode).ok()?;
+    let request = analyzer.asm_symbol_request(&function)?;
+    let debug_id = DebugId::from_breakpad(&request.debug_id).ok()?;
+    let symbol_map = symbol_manager
+        .load_symbol_map(&request.debug_name, debug_id)
+        .await
+        .ok()?;
+
+    let mut symbol_info: Option<AsmSymbolInfo> = None;
+    let mut file_address = None;
+    for address in request.addresses {
+        let Ok(relative_address) = u32::try_from(address) else {
+            continue;
+        };
+        let Some(address_info) = symbol_map.lookup(LookupAddress::Relative(relative_address)).await
+        else {
+            continue;
+        };
+        let info = symbol_info.get_or_insert_with(|| AsmSymbolInfo {
+            address: address_info.symbol.address as u64,
+            size: address_info.symbol.size,
+            ..Default::default()
+        });
+        // The outermost frame is the function itself; inner frames are inlinees.
+        if let Some(frame) = address_info.frames.as_ref().and_then(|frames| frames.last()) {
+            if let Some(line) = frame.line_number {
+                info.lines.insert(address, line);
+            }
+            if info.file_path.is_none() {
+                info.file_path = frame.file_path.map(|handle| {
+                    symbol_map
+                        .resolve_source_file_path(handle)
+                        .raw_path()
+                        .to_string()
+                });
+                file_address = Some(relative_address);
+            }
+        }
+    }
+    if let (Some(info), Some(address)) = (symbol_info.as_mut(), file_address) {
+        if let Some(path) = &info.file_path {
+            let checksum = symbol_map.source_file_checksum(LookupAddress::Relative(address), path);
+            info.file_md5 = checksum.map(|SourceFileChecksum::Md5(md5)| md5);
+        }
+    }
+    symbol_info
+}
+
+/// Map a symbol lookup error to a short, stable reason string.
+fn symbol_error_kind(error: &wholesym::Error) -> &'static str {
+    use wholesym::Error;
+    match error {
+        Error::UnmatchedDebugId(..)
+        | Error::UnmatchedDebugIdOptional(..)
+        | Error::UnmatchedCodeId(..)
+        | Error::DebugLinkCrcMismatch(..) => "hash_mismatch",
+        Error::NoMatchingPdbForBinary(_) | Error::NoDebugInfoInPeBinary(_) => "missing_pdb",
+        Error::NoCandidatePathForBinary(..)
+        | Error::NoCandidatePathForDebugFile(_)
+        | Error::NoCandidatePathForDyldCache
+        | Error::HelperErrorDuringOpenFile(..) => "not_found",
+        Error::NoSuccessfulCandidate(errors) => {
+            // Report the most specific reason among all the candidates we tried.
+            let kinds: Vec<&'static str> = errors.iter().map(symbol_error_kind).collect();
+            ["hash_mismatch", "missing_pdb", "error"]
+                .into_iter()
+                .find(|kind| kinds.contains(kind))
+                .unwrap_or("not_found")
+        }
+        _ => "error",
+    }
+}
+
 // Returns a base32 string for 24 random bytes.
 fn generate_token() -> String {
     let mut bytes = [0u8; 24];
@@ -233,6 +786,46 @@ async fn make_listener(addr: IpAddr, port_selection: PortSelection) -> (TcpListe
     }
 }
 
+/// The acceptor for the TLS connections of `--tls-cert` and `--tls-key`.
+/// Exits if the files can't be loaded, like [`make_listener`] does if it
+/// can't bind.
+fn make_tls_acceptor(tls: &TlsFiles) -> TlsAcceptor {
+    match load_tls_config(tls) {
+        Ok(config) => TlsAcceptor::from(Arc::new(config)),
+        Err(e) => {
+            eprintln!(
+                "Could not load the TLS certificate {:?} and key {:?}: {e}",
+                tls.cert, tls.key
+            );
+            std::process::exit(1)
+        }
+    }
+}
+
+fn load_tls_config(tls: &TlsFiles) -> Result<rustls::ServerConfig, Box<dyn std::error::Error>> {
+    let certs = CertificateDer::pem_file_iter(&tls.cert)?.collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err("no certificate in the PEM file".into());
+    }
+    let key = PrivateKeyDer::from_pem_file(&tls.key)?;
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut config = rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    // Offer HTTP/2 like over cleartext, where clients use it with prior knowledge.
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+fn url_scheme(tls_acceptor: &Option<TlsAcceptor>) -> &'static str {
+    if tls_acceptor.is_some() {
+        "https"
+    } else {
+        "http"
+    }
+}
+
 const TEMPLATE_WITH_PROFILE: &str = r#"
 <!DOCTYPE html>
 <html lang="en">
@@ -263,16 +856,60 @@ const TEMPLATE_WITHOUT_PROFILE: &str = r#"
 </ul>
 "#;
 
+/// The files of the built-in UI at `PATH_PREFIX/ui`, with their content type.
+/// The UI renders the results of the query endpoints, so that a profile can be
+/// looked at without the Firefox Profiler.
+const UI_FILES: &[(&str, &str, &str)] = &[
+    ("/ui", "text/html; charset=utf-8", UI_HTML),
+    ("/ui/", "text/html; charset=utf-8", UI_HTML),
+    ("/ui/ui.js", "text/javascript; charset=utf-8", UI_JS),
+    ("/ui/ui.css", "text/css; charset=utf-8", UI_CSS),
+];
+const UI_HTML: &str = include_str!("../resources/ui/index.html");
+const UI_JS: &str = include_str!("../resources/ui/ui.js");
+const UI_CSS: &str = include_str!("../resources/ui/ui.css");
+
+/// Idle HTTP/2 connections are pinged this often, and closed if the ping isn't
+/// answered within the timeout, so that connections of vanished clients don't linger.
+const HTTP2_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(20);
+const HTTP2_KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Requests served concurrently on one HTTP/2 connection. The profiler
+/// front-end sends a symbolication request per library, and agents often send
+/// a batch of queries at once.
+const HTTP2_MAX_CONCURRENT_STREAMS: u32 = 256;
+
+/// The connection builder: HTTP/1.1 with keep-alive, or HTTP/2 over cleartext
+/// (h2c with prior knowledge), detected from the first bytes of the connection.
+fn connection_builder() -> auto::Builder<TokioExecutor> {
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    builder.http1().keep_alive(true);
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .keep_alive_interval(Some(HTTP2_KEEP_ALIVE_INTERVAL))
+        .keep_alive_timeout(HTTP2_KEEP_ALIVE_TIMEOUT)
+        .max_concurrent_streams(HTTP2_MAX_CONCURRENT_STREAMS);
+    builder
+}
+
 async fn run_server(
     listener: TcpListener,
-    symbol_manager: SymbolManager,
-    analyzer: Option<Arc<ProfileAnalyzer>>,
+    tls_acceptor: Option<TlsAcceptor>,
+    symbol_manager: Arc<SymbolManager>,
+    profiles: Arc<Vec<LoadedProfile>>,
+    baseline: Option<Arc<ProfileAnalyzer>>,
     profile_filename: Option<PathBuf>,
     template_values: Arc<HashMap<&'static str, String>>,
     path_prefix: String,
+    auth_token: Option<String>,
+    max_body_size: usize,
+    sandbox: bool,
     mut stop_signal: ctrl_c::Receiver,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let symbol_manager = Arc::new(symbol_manager);
+    let builder = Arc::new(connection_builder());
+    // Notified by `POST /control/shutdown`
+    let shutdown = Arc::new(Notify::new());
 
     // We start a loop to continuously accept incoming connections
     loop {
@@ -281,38 +918,67 @@ async fn run_server(
             ctrl_c_result = &mut stop_signal => {
                 return Ok(ctrl_c_result?);
             }
+            _ = shutdown.notified() => {
+                return Ok(());
+            }
         };
 
-        // Use an adapter to access something implementing `tokio::io` traits as if they implement
-        // `hyper::rt` IO traits.
-        let io = TokioIo::new(stream);
+        // Responses are small and requests come in bursts, so don't let Nagle's
+        // algorithm hold them back.
+        let _ = stream.set_nodelay(true);
 
+        let tls_acceptor = tls_acceptor.clone();
+        let builder = builder.clone();
         let symbol_manager = symbol_manager.clone();
-        let analyzer = analyzer.clone();
+        let profiles = profiles.clone();
+        let baseline = baseline.clone();
         let profile_filename = profile_filename.clone();
         let template_values = template_values.clone();
         let path_prefix = path_prefix.clone();
+        let auth_token = auth_token.clone();
+        let shutdown = shutdown.clone();
+
+        // `service_fn` converts our function in a `Service`
+        let service = service_fn(move |req| {
+            symbolication_service(
+                req,
+                template_values.clone(),
+                symbol_manager.clone(),
+                profiles.clone(),
+                baseline.clone(),
+                profile_filename.clone(),
+                path_prefix.clone(),
+                auth_token.clone(),
+                shutdown.clone(),
+                max_body_size,
+                sandbox,
+            )
+        });
 
         // Spawn a tokio task to serve multiple connections concurrently
         tokio::task::spawn(async move {
-            // Finally, we bind the incoming connection to our service
-            if let Err(err) = http1::Builder::new()
-                // `service_fn` converts our function in a `Service`
-                .serve_connection(
-                    io,
-                    service_fn(move |req| {
-                        symbolication_service(
-                            req,
-                            template_values.clone(),
-                            symbol_manager.clone(),
-                            analyzer.clone(),
-                            profile_filename.clone(),
-                            path_prefix.clone(),
-                        )
-                    }),
-                )
-                .await
-            {
+            // Finally, we bind the incoming connection to our service. `TokioIo` is an adapter
+            // to access something implementing `tokio::io` traits as if they implement
+            // `hyper::rt` IO traits.
+            let result = match tls_acceptor {
+                Some(tls_acceptor) => match tls_acceptor.accept(stream).await {
+                    Ok(stream) => {
+                        builder
+                            .serve_connection(TokioIo::new(stream), service)
+                            .await
+                    }
+                    Err(err) => {
+                        println!("TLS handshake failed: {err}");
+                        return;
+                    }
+                },
+                None => {
+                    builder
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await
+                }
+            };
+            if let Err(err) = result {
                 println!("Error serving connection: {err:?}");
             }
         });
@@ -321,20 +987,259 @@ async fn run_server(
 
 type MyBody = Either<String, Either<BoxBody<Bytes, std::io::Error>, BoxBody<Bytes, Infallible>>>;
 
+/// Whether the request's `Authorization` header has the bearer token `token`.
+/// The token is compared in constant time.
+fn has_bearer_token(headers: &header::HeaderMap, token: &str) -> bool {
+    let Some(credentials) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    credentials.len() == token.len()
+        && credentials
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// Turn the response into an error response with a JSON `{"error": ...}` body.
+fn set_error_response(response: &mut Response<MyBody>, status: StatusCode, message: &str) {
+    *response.status_mut() = status;
+    *response.body_mut() = Either::Left(serde_json::json!({ "error": message }).to_string());
+}
+
+/// Turn the response into the error response of a failed query, with the
+/// HTTP status of its error code.
+fn set_query_failure(response: &mut Response<MyBody>, failure: &QueryFailure) {
+    *response.status_mut() = StatusCode::from_u16(failure.code.http_status())
+        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    let response_body = Full::new(Bytes::from(failure.to_json()));
+    *response.body_mut() = Either::Right(Either::Right(response_body.boxed()));
+}
+
+/// Responses smaller than this are sent uncompressed, as compressing them
+/// saves less than it costs.
+const COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Responses at least this large are compressed on a blocking thread, so that
+/// they don't hold up the other requests on the runtime's worker thread.
+const BLOCKING_COMPRESSION_THRESHOLD: usize = 256 * 1024;
+
+/// The compression of a response body which the client accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ContentEncoding {
+    Gzip,
+    Deflate,
+}
+
+impl ContentEncoding {
+    /// The compression which the request's `Accept-Encoding` header prefers,
+    /// gzip rather than deflate if it prefers neither. A coding which is listed
+    /// by name gets its own quality, also if `*` is listed as well; codings
+    /// with `q=0` are refused.
+    fn accepted(headers: &header::HeaderMap) -> Option<Self> {
+        let qualities: Vec<(String, f32)> = headers
+            .get_all(header::ACCEPT_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|coding| {
+                let mut parts = coding.split(';');
+                let name = parts.next()?.trim().to_ascii_lowercase();
+                if name.is_empty() {
+                    return None;
+                }
+                let quality = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+                Some((name, quality))
+            })
+            .collect();
+        let quality = |name: &str| {
+            let listed = |name: &str| {
+                qualities
+                    .iter()
+                    .find(|(coding, _)| coding == name)
+                    .map(|&(_, quality)| quality)
+            };
+            listed(name).or_else(|| listed("*")).unwrap_or(0.0)
+        };
+        let gzip = quality("gzip");
+        let deflate = quality("deflate");
+        if gzip > 0.0 && gzip >= deflate {
+            Some(ContentEncoding::Gzip)
+        } else if deflate > 0.0 {
+            Some(ContentEncoding::Deflate)
+        } else {
+            None
+        }
+    }
+
+    fn compress(self, body: &[u8]) -> std::io::Result<Vec<u8>> {
+        let compression = flate2::Compression::fast();
+        match self {
+            ContentEncoding::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), compression);
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+            // HTTP's deflate is the zlib format.
+            ContentEncoding::Deflate => {
+                let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), compression);
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+        }
+    }
+
+    fn header_value(self) -> header::HeaderValue {
+        header::HeaderValue::from_static(match self {
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Deflate => "deflate",
+        })
+    }
+}
+
+/// Make `body` the response body, compressed with `encoding` if the client
+/// accepts one and the body is at least [`COMPRESSION_THRESHOLD`] bytes.
+async fn set_response_body(
+    response: &mut Response<MyBody>,
+    body: impl Into<Bytes>,
+    encoding: Option<ContentEncoding>,
+) {
+    let mut body: Bytes = body.into();
+    // The body depends on the Accept-Encoding header, also if it's too small
+    // to compress.
+    response.headers_mut().insert(
+        header::VARY,
+        header::HeaderValue::from_static("Accept-Encoding"),
+    );
+    if let Some(encoding) = encoding.filter(|_| body.len() >= COMPRESSION_THRESHOLD) {
+        let compressed = if body.len() >= BLOCKING_COMPRESSION_THRESHOLD {
+            let body = body.clone();
+            tokio::task::spawn_blocking(move || encoding.compress(&body))
+                .await
+                .unwrap_or_else(|err| Err(std::io::Error::other(err)))
+        } else {
+            encoding.compress(&body)
+        };
+        if let Ok(compressed) = compressed {
+            response
+                .headers_mut()
+                .insert(header::CONTENT_ENCODING, encoding.header_value());
+            body = Bytes::from(compressed);
+        }
+    }
+    *response.body_mut() = Either::Right(Either::Right(Full::new(body).boxed()));
+}
+
+/// The header which requests to the control endpoints need. It isn't a
+/// CORS-safelisted header, so a web page can't send it without a preflight,
+/// which the control endpoints don't answer.
+const CONTROL_HEADER: &str = "x-samply-control";
+
+/// Whether a request to a control endpoint, which acts on the server itself,
+/// is allowed: the server needs a secret, either the path prefix or
+/// `--auth-token`, and in sandbox mode the auth token.
+fn check_control_request(
+    headers: &header::HeaderMap,
+    path_prefix: &str,
+    has_auth_token: bool,
+    sandbox: bool,
+) -> Result<(), &'static str> {
+    if path_prefix.is_empty() && !has_auth_token {
+        return Err("The control endpoints need a token prefix or --auth-token");
+    }
+    if sandbox && !has_auth_token {
+        return Err("The control endpoints need --auth-token in sandbox mode");
+    }
+    if !headers.contains_key(CONTROL_HEADER) {
+        return Err("Control requests need an 'X-Samply-Control' header");
+    }
+    Ok(())
+}
+
+fn body_too_large_message(max_body_size: usize) -> String {
+    format!(
+        "Request body is larger than the server's limit of {} MB; restart the server with a larger --max-body-mb",
+        max_body_size / (1024 * 1024)
+    )
+}
+
+/// Collect the request body as a string. If it's larger than `max_body_size`
+/// or not UTF-8, turn the response into an error response and return `None`.
+async fn read_request_body(
+    req: Request<hyper::body::Incoming>,
+    max_body_size: usize,
+    response: &mut Response<MyBody>,
+) -> Result<Option<String>, hyper::Error> {
+    let declared_length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok()?.parse::<u64>().ok());
+    if declared_length.is_some_and(|length| length > max_body_size as u64) {
+        set_error_response(
+            response,
+            StatusCode::PAYLOAD_TOO_LARGE,
+            &body_too_large_message(max_body_size),
+        );
+        return Ok(None);
+    }
+    // The limit bounds how much memory the body can take, also for chunked
+    // bodies without a Content-Length.
+    let request_body = match Limited::new(req.into_body(), max_body_size).collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(err) => match err.downcast::<hyper::Error>() {
+            Ok(err) => return Err(*err),
+            Err(_) => {
+                set_error_response(
+                    response,
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    &body_too_large_message(max_body_size),
+                );
+                return Ok(None);
+            }
+        },
+    };
+    // Take over the collected buffer rather than copying it, so that a large
+    // body is only in memory once.
+    match String::from_utf8(request_body.into()) {
+        Ok(request_body) => Ok(Some(request_body)),
+        Err(_) => {
+            set_error_response(
+                response,
+                StatusCode::BAD_REQUEST,
+                "Request body is not valid UTF-8",
+            );
+            Ok(None)
+        }
+    }
+}
+
 async fn symbolication_service(
     req: Request<hyper::body::Incoming>,
     template_values: Arc<HashMap<&'static str, String>>,
     symbol_manager: Arc<SymbolManager>,
-    analyzer: Option<Arc<ProfileAnalyzer>>,
+    profiles: Arc<Vec<LoadedProfile>>,
+    baseline: Option<Arc<ProfileAnalyzer>>,
     profile_filename: Option<PathBuf>,
     path_prefix: String,
+    auth_token: Option<String>,
+    shutdown: Arc<Notify>,
+    max_body_size: usize,
+    sandbox: bool,
 ) -> Result<Response<MyBody>, hyper::Error> {
     let has_profile = profile_filename.is_some();
+    let encoding = ContentEncoding::accepted(req.headers());
     let method = req.method();
     let path = req.uri().path();
     let mut response = Response::new(Either::Left(String::new()));
 
-    let Some(path_without_prefix) = path.strip_prefix(&path_prefix) else {
+    // Without a token the prefix is empty; "/" still serves the index page.
+    let Some(path_without_prefix) = path.strip_prefix(&path_prefix).filter(|_| path != "/") else {
         // The secret prefix was not part of the URL. Do not send CORS headers.
         match (method, path) {
             (&Method::GET, "/") => {
@@ -360,18 +1265,50 @@ async fn symbolication_service(
     // This part is open to the public: we allow requests across origins.
     // For background on CORS, see this document:
     // https://w3c.github.io/webappsec-cors-for-developers/#cors
-    response.headers_mut().insert(
-        header::ACCESS_CONTROL_ALLOW_ORIGIN,
-        header::HeaderValue::from_static("*"),
-    );
+    // The control endpoints are only for samply's own clients, so web pages
+    // can't call them, not even after a preflight.
+    let is_control = path_without_prefix.starts_with("/control/");
+    if !is_control {
+        response.headers_mut().insert(
+            header::ACCESS_CONTROL_ALLOW_ORIGIN,
+            header::HeaderValue::from_static("*"),
+        );
+    }
+    let has_auth_token = auth_token.is_some();
+
+    // With --auth-token, the profiles, the queries and the control endpoints
+    // also need the bearer token. CORS preflight requests never carry it.
+    let is_protected = path_without_prefix == "/profile.json"
+        || path_without_prefix.starts_with("/profiles/")
+        || path_without_prefix.starts_with("/query/")
+        || is_control;
+    if let Some(auth_token) = auth_token.filter(|_| is_protected && method != Method::OPTIONS) {
+        if !has_bearer_token(req.headers(), &auth_token) {
+            response.headers_mut().insert(
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static("application/json"),
+            );
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                header::HeaderValue::from_static("Bearer"),
+            );
+            set_error_response(
+                &mut response,
+                StatusCode::UNAUTHORIZED,
+                "This server needs the --auth-token in an 'Authorization: Bearer' header",
+            );
+            return Ok(response);
+        }
+    }
 
     match (method, path_without_prefix, profile_filename) {
         (&Method::OPTIONS, _, _) => {
             // https://developer.mozilla.org/en-US/docs/Web/HTTP/Methods/OPTIONS
             *response.status_mut() = StatusCode::NO_CONTENT;
-            if req
-                .headers()
-                .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+            if !is_control
+                && req
+                    .headers()
+                    .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
             {
                 // This is a CORS preflight request.
                 // Reassure the client that we are CORS-aware and that it's free to request whatever.
@@ -398,49 +1335,114 @@ async fn symbolication_service(
                 );
             }
         }
+        (&Method::GET, "/profile.json", _)
+            if profiles.first().is_some_and(|p| p.live.is_some()) =>
+        {
+            set_live_profile_response(&mut response, &profiles[0], req.uri().query());
+        }
         (&Method::GET, "/profile.json", Some(profile_filename)) => {
-            if profile_filename.extension() == Some(OsStr::new("gz")) {
-                response.headers_mut().insert(
-                    header::CONTENT_ENCODING,
-                    header::HeaderValue::from_static("gzip"),
-                );
+            set_profile_file_response(&mut response, &profile_filename).await;
+        }
+        (&Method::GET, path, _) if path.starts_with("/profiles/") => {
+            let profile = path
+                .strip_prefix("/profiles/")
+                .and_then(|path| path.strip_suffix("/profile.json"))
+                .and_then(|id| profiles.iter().find(|profile| profile.id == id));
+            match profile {
+                Some(profile) if profile.live.is_some() => {
+                    set_live_profile_response(&mut response, profile, req.uri().query())
+                }
+                Some(profile) => {
+                    set_profile_file_response(&mut response, profile.served_path()).await
+                }
+                None => *response.status_mut() = StatusCode::NOT_FOUND,
+            }
+        }
+        // The UI only works with profiles to query, so the plain symbol
+        // server doesn't serve it.
+        (&Method::GET, path, _) if !profiles.is_empty() && path.starts_with("/ui") => {
+            let Some(&(_, content_type, content)) =
+                UI_FILES.iter().find(|(file_path, ..)| *file_path == path)
+            else {
+                *response.status_mut() = StatusCode::NOT_FOUND;
+                return Ok(response);
+            };
+            response.headers_mut().insert(
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static(content_type),
+            );
+            // The page links to the other files under the secret prefix.
+            let body = match content_type.starts_with("text/html") {
+                true => substitute_template(content, &template_values),
+                false => content.to_string(),
+            };
+            set_response_body(&mut response, body, encoding).await;
+        }
+        (&Method::POST, "/query/v1", _) => {
+            response.headers_mut().insert(
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static("application/json"),
+            );
+            let Some(request_body) = read_request_body(req, max_body_size, &mut response).await?
+            else {
+                return Ok(response);
+            };
+            let response_json =
+                query_v1_response(&symbol_manager, &profiles, baseline, &request_body, sandbox)
+                    .await;
+            match response_json {
+                Ok(response_json) => {
+                    set_response_body(&mut response, response_json, encoding).await
+                }
+                Err(failure) => set_query_failure(&mut response, &failure),
             }
+        }
+        (&Method::POST, "/control/shutdown", _) => {
+            // `samply analyze stop`. Stop accepting connections once this
+            // response is on its way.
             response.headers_mut().insert(
                 header::CONTENT_TYPE,
-                header::HeaderValue::from_static("application/json; charset=UTF-8"),
+                header::HeaderValue::from_static("application/json"),
             );
-
-            // Stream the file. This follows the send_file example from the hyper repo.
-            // https://github.com/hyperium/hyper/blob/7206fe30302937075c51c16a69d1eb3bbce6a671/examples/send_file.rs
-            let file = tokio::fs::File::open(&profile_filename)
-                .await
-                .expect("couldn't open profile file");
-
-            // Wrap in a buffered tokio_util::io::ReaderStream
-            let reader = BufReader::with_capacity(64 * 1024, file);
-            let reader_stream = ReaderStream::new(reader);
-
-            let stream_body = StreamBody::new(reader_stream.map_ok(Frame::data));
-            *response.body_mut() = Either::Right(Either::Left(stream_body.boxed()));
+            if let Err(message) =
+                check_control_request(req.headers(), &path_prefix, has_auth_token, sandbox)
+            {
+                set_error_response(&mut response, StatusCode::FORBIDDEN, message);
+                return Ok(response);
+            }
+            *response.body_mut() = Either::Left(serde_json::json!({ "success": true }).to_string());
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                shutdown.notify_one();
+            });
         }
         (&Method::POST, path, _) => {
             response.headers_mut().insert(
                 header::CONTENT_TYPE,
                 header::HeaderValue::from_static("application/json"),
             );
+            if sandbox {
+                // The symbolication APIs read the binaries and source files at
+                // paths from the request.
+                set_error_response(
+                    &mut response,
+                    StatusCode::FORBIDDEN,
+                    "Symbolication is disabled in sandbox mode",
+                );
+                return Ok(response);
+            }
             let path = path.to_string();
-            // Await the full body to be concatenated into a `Collected<Bytes>`.
-            let request_body = req.into_body().collect().await?;
-            // Convert the `Collected<Bytes>` into a `String`.
-            let request_body =
-                String::from_utf8(request_body.to_bytes().to_vec()).expect("invalid utf-8");
+            // The symbol manager's JSON API takes the whole request as a string, so the
+            // body has to be collected.
+            let Some(request_body) = read_request_body(req, max_body_size, &mut response).await?
+            else {
+                return Ok(response);
+            };
             let response_json = symbol_manager.query_json_api(&path, &request_body).await;
             let mut response_bytes = Vec::new();
             let response_writer = BufWriter::new(&mut response_bytes);
             serde_json::to_writer(response_writer, &response_json).expect("json writing error");
-            let response_body = Full::new(Bytes::from(response_bytes));
-
-            *response.body_mut() = Either::Right(Either::Right(response_body.boxed()));
+            set_response_body(&mut response, response_bytes, encoding).await;
         }
         // Query endpoints for AI-assisted analysis
         (&Method::GET, path, _) if path.starts_with("/query/") => {
@@ -454,9 +1456,49 @@ async fn symbolication_service(
                 .into_owned()
                 .collect();
 
-            let response_json = handle_query_request(path, &query_params, analyzer.as_deref());
-            let response_body = Full::new(Bytes::from(response_json));
-            *response.body_mut() = Either::Right(Either::Right(response_body.boxed()));
+            if path == "/query/profiles" {
+                let response_json = profiles_response(&profiles, sandbox);
+                set_response_body(&mut response, response_json, encoding).await;
+                return Ok(response);
+            }
+            if path == "/query/schema" {
+                let response_json = crate::query_schema::openapi().to_string();
+                set_response_body(&mut response, response_json, encoding).await;
+                return Ok(response);
+            }
+            let (profile, path, baseline) = match select_profile(&profiles, path, &query_params) {
+                Ok((profile, path, baseline_profile)) => (
+                    profile,
+                    path,
+                    baseline_profile.map(|p| p.analyzer()).or(baseline),
+                ),
+                Err(error) => {
+                    set_query_failure(
+                        &mut response,
+                        &QueryFailure::new(QueryErrorCode::NotFound, error),
+                    );
+                    return Ok(response);
+                }
+            };
+            let profile_analyzer = profile.map(|profile| profile.analyzer());
+            let response_json = match answer_query(
+                &symbol_manager,
+                profile,
+                profile_analyzer.as_deref(),
+                baseline.as_deref(),
+                &path,
+                &query_params,
+                sandbox,
+            )
+            .await
+            {
+                Ok(response_json) => response_json,
+                Err(failure) => {
+                    set_query_failure(&mut response, &failure);
+                    return Ok(response);
+                }
+            };
+            set_response_body(&mut response, response_json, encoding).await;
         }
         _ => {
             *response.status_mut() = StatusCode::NOT_FOUND;
@@ -466,25 +1508,644 @@ async fn symbolication_service(
     Ok(response)
 }
 
-/// Handle query requests for AI-assisted analysis
-fn handle_query_request(
-    path: &str,
-    params: &HashMap<String, String>,
-    analyzer: Option<&ProfileAnalyzer>,
-) -> String {
-    let Some(analyzer) = analyzer else {
-        return serde_json::json!({
-            "success": false,
-            "error": "Analysis not available. Start server with 'samply analyze serve' to enable queries."
-        }).to_string();
+/// Stream a profile file as the response body, with `Content-Encoding: gzip`
+/// if it's compressed, or with an error if it can't be opened.
+async fn set_profile_file_response(response: &mut Response<MyBody>, profile_filename: &Path) {
+    // Stream the file. This follows the send_file example from the hyper repo.
+    // https://github.com/hyperium/hyper/blob/7206fe30302937075c51c16a69d1eb3bbce6a671/examples/send_file.rs
+    let file = match tokio::fs::File::open(profile_filename).await {
+        Ok(file) => file,
+        Err(e) => {
+            let status = match e.kind() {
+                std::io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            let message = format!("couldn't open profile file: {e}");
+            set_error_response(response, status, &message);
+            return;
+        }
+    };
+
+    if profile_filename.extension() == Some(OsStr::new("gz")) {
+        response.headers_mut().insert(
+            header::CONTENT_ENCODING,
+            header::HeaderValue::from_static("gzip"),
+        );
+    }
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("application/json; charset=UTF-8"),
+    );
+
+    // Wrap in a buffered tokio_util::io::ReaderStream
+    let reader = BufReader::with_capacity(64 * 1024, file);
+    let reader_stream = ReaderStream::new(reader);
+
+    let stream_body = StreamBody::new(reader_stream.map_ok(Frame::data));
+    *response.body_mut() = Either::Right(Either::Left(stream_body.boxed()));
+}
+
+/// Respond with the latest snapshot of a live profile, or with its samples,
+/// markers and counter samples from `?since=MS` on.
+fn set_live_profile_response(
+    response: &mut Response<MyBody>,
+    profile: &LoadedProfile,
+    query: Option<&str>,
+) {
+    let since = url::form_urlencoded::parse(query.unwrap_or("").as_bytes())
+        .find(|(key, _)| key == "since")
+        .and_then(|(_, value)| value.parse::<f64>().ok());
+    let Some(snapshot) = profile.live.as_ref().and_then(|live| live.latest()) else {
+        *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        return;
+    };
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("application/json; charset=UTF-8"),
+    );
+    // The snapshot is replaced every few seconds.
+    response.headers_mut().insert(
+        header::CACHE_CONTROL,
+        header::HeaderValue::from_static("no-store"),
+    );
+    let json = match since {
+        Some(since) => snapshot.json_since(since),
+        None => snapshot.json().to_string(),
+    };
+    *response.body_mut() = Either::Left(json);
+}
+
+/// Find the profile which a query is for: `/query/<id>/<query>` selects the
+/// profile with that id, `/query/<query>` the first one. Returns the profile,
+/// the query path without the id, and the profile selected by the `baseline`
+/// parameter, if any.
+fn select_profile<'a>(
+    profiles: &'a [LoadedProfile],
+    path: &str,
+    params: &HashMap<String, String>,
+) -> Result<(Option<&'a LoadedProfile>, String, Option<&'a LoadedProfile>), String> {
+    let find = |id: &str| {
+        profiles
+            .iter()
+            .find(|profile| profile.id == id)
+            .ok_or_else(|| format!("No profile with id '{id}'. Use /query/profiles to list them."))
+    };
+    let (profile, path) = match path.strip_prefix("/query/").and_then(|q| q.split_once('/')) {
+        Some((id, query)) => (Some(find(id)?), format!("/query/{query}")),
+        None => (profiles.first(), path.to_string()),
+    };
+    let baseline = params.get("baseline").map(|id| find(id)).transpose()?;
+    Ok((profile, path, baseline))
+}
+
+/// Queries which read the source files and binaries named by the profile
+const SANDBOX_DISABLED_QUERIES: &[&str] = &["/query/source", "/query/asm"];
+
+/// Answer a query for `profile`, whose threads `analyzer` may be a view of,
+/// with a link to the query's view in the profiler front-end.
+async fn answer_query(
+    symbol_manager: &SymbolManager,
+    profile: Option<&LoadedProfile>,
+    analyzer: Option<&ProfileAnalyzer>,
+    baseline: Option<&ProfileAnalyzer>,
+    path: &str,
+    params: &HashMap<String, String>,
+    sandbox: bool,
+) -> Result<String, QueryFailure> {
+    if sandbox && SANDBOX_DISABLED_QUERIES.contains(&path) {
+        return Err(QueryFailure::new(
+            QueryErrorCode::Disabled,
+            format!("{path} reads files on the server and is disabled in sandbox mode"),
+        ));
+    }
+
+    // The asm and source queries look up the function's symbol first,
+    // which needs the (async) symbol manager.
+    let asm_symbol = match (path, analyzer, params.get("function")) {
+        ("/query/asm" | "/query/source", Some(analyzer), Some(function)) => {
+            let match_mode = match_mode_param(params).unwrap_or_default();
+            lookup_asm_symbol(symbol_manager, analyzer, function, match_mode).await
+        }
+        _ => None,
+    };
+
+    let response_json = handle_query_request(
+        path,
+        params,
+        analyzer,
+        baseline,
+        profile.map(|profile| &*profile.symbolication_status),
+        asm_symbol.as_ref(),
+    )?;
+    let profiler_url = profile.and_then(|profile| {
+        query_profiler_url(&profile.profiler_url, path, params, &profile.analyzer())
+    });
+    Ok(match profiler_url {
+        Some(profiler_url) => add_profiler_url(response_json, profiler_url),
+        None => response_json,
+    })
+}
+
+/// The body of a `POST /query/v1` request: the query, with its filters as
+/// JSON instead of query parameters.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QueryRequest {
+    /// The query's name, e.g. `hotspots`
+    query: String,
+    /// The id of the profile to query; the first one by default
+    #[serde(default)]
+    profile: Option<String>,
+    #[serde(default)]
+    filters: QueryFilters,
+    /// Further parameters of the query, as for its GET endpoint
+    #[serde(default)]
+    params: serde_json::Map<String, serde_json::Value>,
+}
+
+/// The filters of a `POST /query/v1` request. An empty list doesn't filter.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct QueryFilters {
+    /// Only the threads whose name contains one of these
+    threads: Vec<String>,
+    /// Only the threads of the processes with one of these pids or names
+    processes: Vec<String>,
+    /// Answer the query once per function, as its `function` parameter
+    functions: Vec<String>,
+    start_ms: Option<f64>,
+    end_ms: Option<f64>,
+}
+
+/// A JSON parameter as the string of the query parameter it stands for, with
+/// arrays joined by commas
+fn query_param_string(value: serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s,
+        serde_json::Value::Array(values) => values
+            .into_iter()
+            .map(query_param_string)
+            .collect::<Vec<_>>()
+            .join(","),
+        value => value.to_string(),
+    }
+}
+
+/// The response to a `POST /query/v1` request. With `functions` filters, its
+/// data lists the query's response for each function.
+async fn query_v1_response(
+    symbol_manager: &SymbolManager,
+    profiles: &[LoadedProfile],
+    baseline: Option<Arc<ProfileAnalyzer>>,
+    request_body: &str,
+    sandbox: bool,
+) -> Result<String, QueryFailure> {
+    let request: QueryRequest = serde_json::from_str(request_body).map_err(|error| {
+        QueryFailure::new(
+            QueryErrorCode::InvalidParam,
+            format!("Invalid query request: {error}"),
+        )
+    })?;
+    let filters = &request.filters;
+    let mut params: HashMap<String, String> = request
+        .params
+        .into_iter()
+        .map(|(key, value)| (key, query_param_string(value)))
+        .collect();
+    if let Some(start_ms) = filters.start_ms {
+        params.insert("start_ms".to_string(), start_ms.to_string());
+    }
+    if let Some(end_ms) = filters.end_ms {
+        params.insert("end_ms".to_string(), end_ms.to_string());
+    }
+
+    let query = request.query.trim_start_matches('/');
+    let path = match &request.profile {
+        Some(id) => format!("/query/{id}/{query}"),
+        None => format!("/query/{query}"),
+    };
+    let (profile, path, baseline_profile) = select_profile(profiles, &path, &params)
+        .map_err(|error| QueryFailure::new(QueryErrorCode::NotFound, error))?;
+    let baseline = baseline_profile.map(|p| p.analyzer()).or(baseline);
+    let profile_analyzer = profile.map(|profile| profile.analyzer());
+
+    let thread_views = match profile_analyzer.as_deref() {
+        Some(analyzer) if !filters.threads.is_empty() || !filters.processes.is_empty() => {
+            let view = analyzer.for_threads(&filters.threads, &filters.processes);
+            if view.thread_indexes("", None).is_empty() {
+                return Err(QueryFailure::new(
+                    QueryErrorCode::NotFound,
+                    "No thread matches the threads and processes filters. Use /query/threads to list them.",
+                ));
+            }
+            let baseline = baseline
+                .as_deref()
+                .map(|baseline| baseline.for_threads(&filters.threads, &filters.processes));
+            Some((view, baseline))
+        }
+        _ => None,
     };
+    let (analyzer, baseline) = match &thread_views {
+        Some((analyzer, baseline)) => (Some(analyzer), baseline.as_ref()),
+        None => (profile_analyzer.as_deref(), baseline.as_deref()),
+    };
+
+    if filters.functions.is_empty() {
+        return answer_query(
+            symbol_manager,
+            profile,
+            analyzer,
+            baseline,
+            &path,
+            &params,
+            sandbox,
+        )
+        .await;
+    }
+    let mut responses = Vec::new();
+    for function in &filters.functions {
+        let mut params = params.clone();
+        params.insert("function".to_string(), function.clone());
+        let response_json = answer_query(
+            symbol_manager,
+            profile,
+            analyzer,
+            baseline,
+            &path,
+            &params,
+            sandbox,
+        )
+        .await?;
+        let response: serde_json::Value = serde_json::from_str(&response_json).unwrap_or_default();
+        responses.push(serde_json::json!({ "function": function, "response": response }));
+    }
+    Ok(serde_json::json!({ "success": true, "query": query, "data": responses }).to_string())
+}
+
+/// The response of `/query/profiles`: the id, path and URLs of every profile.
+/// In sandbox mode, the path on the server is left out.
+fn profiles_response(profiles: &[LoadedProfile], sandbox: bool) -> String {
+    let profiles: Vec<serde_json::Value> = profiles
+        .iter()
+        .map(|profile| {
+            let mut entry = serde_json::json!({
+                "id": profile.id,
+                "path": profile.path,
+                "profile_url": profile.profile_url,
+                "profiler_url": profile.profiler_url,
+            });
+            if sandbox {
+                entry.as_object_mut().unwrap().remove("path");
+            }
+            entry
+        })
+        .collect();
+    serde_json::json!({
+        "success": true,
+        "query": "profiles",
+        "data": { "profiles": profiles }
+    })
+    .to_string()
+}
 
-    match path {
+/// A link to the profiler front-end view which corresponds to a query, with
+/// the query's threads, time range and function selected, built from the
+/// profile's `profiler_url`. `None` for queries which have no such view.
+fn query_profiler_url(
+    profiler_url: &str,
+    path: &str,
+    params: &HashMap<String, String>,
+    analyzer: &ProfileAnalyzer,
+) -> Option<String> {
+    let (base, symbol_server_query) = profiler_url.split_once("/?")?;
+    // The front-end's search is a substring search, which can't express regexes.
+    let function = params
+        .get("function")
+        .filter(|_| params.get("match").map_or(true, |mode| mode != "regex"));
+    let inverted = params.get("inverted").is_some_and(|s| s == "true");
+
+    let (view, invert, search) = match path {
+        "/query/hotspots" | "/query/allocations" | "/query/growth" => ("calltree", true, None),
+        "/query/callers" => ("calltree", true, function),
+        "/query/callees" | "/query/drilldown" | "/query/source" | "/query/asm" => {
+            ("calltree", false, function)
+        }
+        "/query/threads" | "/query/processes" | "/query/summary" | "/query/categories"
+        | "/query/stack-stats" | "/query/export" | "/query/calltree" | "/query/crash" => {
+            ("calltree", false, None)
+        }
+        // The flame graph view can't be inverted, but the call tree can.
+        "/query/flamegraph" if inverted => ("calltree", true, None),
+        "/query/flamegraph" => ("flame-graph", false, None),
+        "/query/markers" | "/query/wait-graph" | "/query/signals" => ("marker-chart", false, None),
+        _ => return None,
+    };
+
+    let mut query = url::form_urlencoded::Serializer::new(String::new());
+    let thread = params.get("thread");
+    let process = params.get("process").filter(|process| !process.is_empty());
+    // A hardware event's samples are in tracks of their own.
+    let metric = params
+        .get("metric")
+        .filter(|metric| path == "/query/hotspots" && metric.as_str() != "samples");
+    if thread.is_some() || process.is_some() || metric.is_some() {
+        let thread = thread.map_or("", |thread| thread.as_str());
+        let process = process.map(|process| process.as_str());
+        let indexes = match metric {
+            Some(metric) => analyzer.metric_thread_indexes(metric, thread, process),
+            None => analyzer.thread_indexes(thread, process),
+        };
+        if !indexes.is_empty() {
+            let indexes: Vec<String> = indexes.iter().map(|i| i.to_string()).collect();
+            query.append_pair("thread", &indexes.join(","));
+        }
+    }
+    // Committed ranges are written as <start>m<duration>, in whole milliseconds.
+    let range = time_range_params(params);
+    if let (Some(start_ms), Some(end_ms)) = (range.start_ms, range.end_ms) {
+        let start = start_ms.max(0.0).floor();
+        let duration = (end_ms - start).ceil().max(1.0);
+        query.append_pair("range", &format!("{start}m{duration}"));
+    }
+    if invert {
+        query.append_pair("invertCallstack", "");
+    }
+    if let Some(search) = search {
+        query.append_pair("search", search);
+    }
+    if let ("/query/markers", Some(name)) = (path, params.get("name")) {
+        query.append_pair("markerSearch", name);
+    }
+    if path == "/query/allocations" {
+        query.append_pair("ctSummary", "native-allocations");
+    }
+
+    let query = query.finish();
+    let separator = if query.is_empty() { "" } else { "&" };
+    Some(format!(
+        "{base}/{view}/?{symbol_server_query}{separator}{query}"
+    ))
+}
+
+/// Add the `profiler_url` deep link to a successful query response.
+fn add_profiler_url(response_json: String, profiler_url: String) -> String {
+    match serde_json::from_str::<serde_json::Value>(&response_json) {
+        Ok(serde_json::Value::Object(mut response))
+            if response.get("success") == Some(&serde_json::Value::Bool(true)) =>
+        {
+            response.insert("profiler_url".to_string(), profiler_url.into());
+            serde_json::Value::Object(response).to_string()
+        }
+        _ => response_json,
+    }
+}
+
+/// The `start_ms` / `end_ms` query parameters
+fn time_range_params(params: &HashMap<String, String>) -> TimeRange {
+    TimeRange {
+        start_ms: params.get("start_ms").and_then(|s| s.parse().ok()),
+        end_ms: params.get("end_ms").and_then(|s| s.parse().ok()),
+    }
+}
+
+/// The `match` query parameter: `substring` (default), `exact` or `regex`
+pub(crate) fn match_mode_param(params: &HashMap<String, String>) -> Result<MatchMode, String> {
+    params.get("match").map_or(Ok(MatchMode::default()), |s| s.parse())
+}
+
+/// Resolve the `function` parameter to a function in the profile, matching it
+/// according to the `match` parameter. Fails if the parameter is missing or
+/// nothing matches.
+fn resolve_function_param(
+    analyzer: &ProfileAnalyzer,
+    params: &HashMap<String, String>,
+) -> Result<String, QueryFailure> {
+    let function = params.get("function").map(|s| s.as_str()).unwrap_or("");
+    if function.is_empty() {
+        return Err(QueryFailure::new(
+            QueryErrorCode::MissingParam,
+            "Missing 'function' parameter",
+        ));
+    }
+    let match_mode = match_mode_param(params)
+        .map_err(|error| QueryFailure::new(QueryErrorCode::InvalidParam, error))?;
+    Ok(analyzer.resolve_function(function, match_mode)?)
+}
+
+/// Handle query requests for AI-assisted analysis
+pub(crate) fn handle_query_request(
+    path: &str,
+    params: &HashMap<String, String>,
+    analyzer: Option<&ProfileAnalyzer>,
+    baseline: Option<&ProfileAnalyzer>,
+    symbolication_status: Option<&SymbolicationStatusTracker>,
+    asm_symbol: Option<&AsmSymbolInfo>,
+) -> Result<String, QueryFailure> {
+    let Some(analyzer) = analyzer else {
+        return Err(QueryFailure::new(
+            QueryErrorCode::NoAnalyzer,
+            "Analysis not available. Start server with 'samply analyze serve' to enable queries.",
+        ));
+    };
+
+    // The `process` parameter restricts every query to the threads of one
+    // process, in the baseline as well.
+    let process = params.get("process").filter(|process| !process.is_empty());
+    if let Some(process) = process {
+        let threads = analyzer.thread_indexes("", Some(process.as_str()));
+        if threads.is_empty() {
+            return Err(QueryFailure::new(
+                QueryErrorCode::NotFound,
+                format!(
+                    "No process with the pid or name '{}'. Use /query/processes to list them.",
+                    process
+                ),
+            ));
+        }
+    }
+    let process_views = process.map(|process| {
+        (
+            analyzer.for_process(process),
+            baseline.map(|baseline| baseline.for_process(process)),
+        )
+    });
+    let (analyzer, baseline) = match &process_views {
+        Some((analyzer, baseline)) => (analyzer, baseline.as_ref()),
+        None => (analyzer, baseline),
+    };
+
+    // The `merge_threads` parameter treats the threads whose name matches a
+    // regex as one thread: queries only see those threads, and the response
+    // breaks their samples down per thread. The threads query still lists
+    // every thread, with the matching ones as one entry.
+    let merge = merge_threads_param(params)
+        .map_err(|error| QueryFailure::new(QueryErrorCode::InvalidParam, error))?;
+    let merged_threads = merge
+        .as_ref()
+        .map(|merge| analyzer.merged_threads(merge, time_range_params(params)));
+    if let Some(merged_threads) = merged_threads.as_ref().filter(|m| m.threads.is_empty()) {
+        return Err(QueryFailure::new(
+            QueryErrorCode::NotFound,
+            format!(
+                "No thread name matches merge_threads '{}'. Use /query/threads to list them.",
+                merged_threads.pattern
+            ),
+        ));
+    }
+    let merge_views = merge
+        .as_ref()
+        .filter(|_| path != "/query/threads")
+        .map(|merge| {
+            (
+                analyzer.for_merged_threads(merge),
+                baseline.map(|baseline| baseline.for_merged_threads(merge)),
+            )
+        });
+    let (analyzer, baseline) = match &merge_views {
+        Some((analyzer, baseline)) => (analyzer, baseline.as_ref()),
+        None => (analyzer, baseline),
+    };
+
+    // The `language_frames` parameter groups the frames of each JIT or
+    // interpreted language under a pseudo-frame like `[JavaScript]`.
+    let language_views = params
+        .get("language_frames")
+        .filter(|s| *s == "true" || *s == "1")
+        .map(|_| {
+            (
+                analyzer.with_language_frames(),
+                baseline.map(|baseline| baseline.with_language_frames()),
+            )
+        });
+    let (analyzer, baseline) = match &language_views {
+        Some((analyzer, baseline)) => (analyzer, baseline.as_ref()),
+        None => (analyzer, baseline),
+    };
+
+    // The `offset`, `limit` and `cursor` parameters select a page of the
+    // ranked results, and `max_bytes` caps the size of the response.
+    let mut page = Page::from_params(path, params)
+        .map_err(|error| QueryFailure::new(QueryErrorCode::InvalidParam, error))?;
+    let response_json = query_response(
+        path,
+        params,
+        &mut page,
+        analyzer,
+        baseline,
+        symbolication_status,
+        asm_symbol,
+    )?;
+    let precision = params
+        .get("precision")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_PRECISION);
+    Ok(format_response(
+        response_json,
+        precision,
+        merged_threads.as_ref(),
+        &page,
+    ))
+}
+
+/// Round the floats of a successful query response to `precision` decimals,
+/// and add the `units` of its numeric fields and the breakdown of the
+/// `merged_threads`, if any. Then keep its `page`.
+fn format_response(
+    response_json: String,
+    precision: u32,
+    merged_threads: Option<&MergedThreads>,
+    page: &Page,
+) -> String {
+    match serde_json::from_str::<serde_json::Value>(&response_json) {
+        Ok(serde_json::Value::Object(mut response))
+            if response.get("success") == Some(&serde_json::Value::Bool(true)) =>
+        {
+            if let Some(merged_threads) = merged_threads {
+                let merged_threads = serde_json::to_value(merged_threads).unwrap_or_default();
+                response.insert("merged_threads".to_string(), merged_threads);
+            }
+            response.insert("units".to_string(), response_units());
+            let mut response = serde_json::Value::Object(response);
+            round_floats(&mut response, precision);
+            paginate(&mut response, page);
+            response.to_string()
+        }
+        _ => response_json,
+    }
+}
+
+/// The `merge_threads` query parameter: a regex of the thread names to treat
+/// as one thread
+fn merge_threads_param(params: &HashMap<String, String>) -> Result<Option<ThreadMerge>, String> {
+    params
+        .get("merge_threads")
+        .filter(|pattern| !pattern.is_empty())
+        .map(|pattern| pattern.parse())
+        .transpose()
+}
+
+/// The response to a query, before its numbers are formatted and its page
+/// is cut out. Paged queries set the `page`'s limit to their default.
+fn query_response(
+    path: &str,
+    params: &HashMap<String, String>,
+    page: &mut Page,
+    analyzer: &ProfileAnalyzer,
+    baseline: Option<&ProfileAnalyzer>,
+    symbolication_status: Option<&SymbolicationStatusTracker>,
+    asm_symbol: Option<&AsmSymbolInfo>,
+) -> Result<String, QueryFailure> {
+    let response = match path {
         "/query/hotspots" => {
-            let limit = params.get("limit")
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(20);
+            let limit = page.fetch_limit(20);
             let thread = params.get("thread").map(|s| s.as_str());
+            // With a hardware event as the metric, functions are ranked by
+            // that event's samples instead of the CPU samples.
+            let metric = params.get("metric").map_or("samples", |s| s.as_str());
+            let metric_view = match metric {
+                "samples" => None,
+                metric => Some(
+                    analyzer
+                        .for_metric(metric)
+                        .map_err(|error| QueryFailure::new(QueryErrorCode::InvalidParam, error))?,
+                ),
+            };
+            let analyzer = metric_view.as_ref().unwrap_or(analyzer);
+            let group_by = params
+                .get("groupby")
+                .map_or(Ok(HotspotsGroupBy::default()), |s| s.parse())
+                .map_err(|error| QueryFailure::new(QueryErrorCode::InvalidParam, error))?;
+            if group_by == HotspotsGroupBy::LeafModule {
+                let own_libraries: Vec<String> = params
+                    .get("own_libraries")
+                    .map(|s| {
+                        s.split(',')
+                            .filter(|s| !s.is_empty())
+                            .map(String::from)
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let modules = analyzer.leaf_module_hotspots(
+                    limit,
+                    thread,
+                    &own_libraries,
+                    time_range_params(params),
+                );
+                return Ok(serde_json::json!({
+                    "success": true,
+                    "query": "hotspots",
+                    "data": modules
+                }).to_string());
+            }
+            if group_by == HotspotsGroupBy::Language {
+                let languages =
+                    analyzer.language_hotspots(limit, thread, time_range_params(params));
+                return Ok(serde_json::json!({
+                    "success": true,
+                    "query": "hotspots",
+                    "data": languages
+                }).to_string());
+            }
             // By default, don't include hot_lines and hot_addresses (compact output)
             let include_lines = params.get("include_lines")
                 .map(|s| s == "true" || s == "1")
@@ -492,28 +2153,89 @@ fn handle_query_request(
             let include_addresses = params.get("include_addresses")
                 .map(|s| s == "true" || s == "1")
                 .unwrap_or(false);
-            let hotspots = analyzer.compute_hotspots(limit, thread, include_lines, include_addresses);
+            let range = time_range_params(params);
+            let mut hotspots = analyzer.compute_hotspots(
+                limit,
+                thread,
+                include_lines,
+                include_addresses,
+                range,
+            );
+            // The heaviest path to each function, so that the list needs no
+            // callers queries to be understood
+            if params.get("context").is_some_and(|s| s == "true" || s == "1") {
+                analyzer.add_hotspot_context(&mut hotspots, thread, range);
+            }
             serde_json::json!({
                 "success": true,
                 "query": "hotspots",
-                "data": hotspots
+                "metric": metric,
+                "data": hotspots,
+                "activity": analyzer.thread_activity(thread, range)
             }).to_string()
         }
-        "/query/callers" => {
-            let function = params.get("function").map(|s| s.as_str()).unwrap_or("");
-            let depth = params.get("depth")
+        "/query/threads" => {
+            let limit = params.get("limit")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(10);
+            // Already validated by handle_query_request
+            let merge = merge_threads_param(params).ok().flatten();
+            let threads =
+                analyzer.compute_thread_hotspots(limit, merge.as_ref(), time_range_params(params));
+            serde_json::json!({
+                "success": true,
+                "query": "threads",
+                "data": threads
+            }).to_string()
+        }
+        "/query/processes" => {
+            let limit = params.get("limit")
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(5);
+            let processes = analyzer.processes(limit, time_range_params(params));
+            serde_json::json!({
+                "success": true,
+                "query": "processes",
+                "data": processes
+            }).to_string()
+        }
+        "/query/markers" => {
+            let name = params.get("name").map(|s| s.as_str());
+            let thread = params.get("thread").map(|s| s.as_str());
+            let limit = page.fetch_limit(50);
+            let markers = analyzer.get_markers(name, thread, limit, time_range_params(params));
+            serde_json::json!({
+                "success": true,
+                "query": "markers",
+                "data": markers
+            }).to_string()
+        }
+        "/query/by-trace" => {
+            let thread = params.get("thread").map(|s| s.as_str());
             let limit = params.get("limit")
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(20);
-            if function.is_empty() {
-                return serde_json::json!({
-                    "success": false,
-                    "error": "Missing 'function' parameter"
-                }).to_string();
-            }
-            let callers = analyzer.find_callers(function, depth, limit);
+            let range = time_range_params(params);
+            // Without a trace id, list the traces to pick one from
+            let data = match params.get("trace_id").filter(|id| !id.is_empty()) {
+                Some(trace_id) => {
+                    serde_json::to_value(analyzer.stacks_by_trace(trace_id, limit, thread, range))
+                }
+                None => serde_json::to_value(analyzer.list_traces(limit, thread, range)),
+            };
+            serde_json::json!({
+                "success": true,
+                "query": "by-trace",
+                "data": data.unwrap_or_default()
+            }).to_string()
+        }
+        "/query/callers" => {
+            let function = resolve_function_param(analyzer, params)?;
+            let depth = params.get("depth")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(5);
+            let limit = page.fetch_limit(20);
+            let callers = analyzer.find_callers(&function, depth, limit, time_range_params(params));
             serde_json::json!({
                 "success": true,
                 "query": "callers",
@@ -521,43 +2243,202 @@ fn handle_query_request(
             }).to_string()
         }
         "/query/callees" => {
-            let function = params.get("function").map(|s| s.as_str()).unwrap_or("");
+            let function = resolve_function_param(analyzer, params)?;
             let depth = params.get("depth")
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(5);
+            let limit = page.fetch_limit(20);
+            let callees = analyzer.find_callees(&function, depth, limit, time_range_params(params));
+            serde_json::json!({
+                "success": true,
+                "query": "callees",
+                "data": callees
+            }).to_string()
+        }
+        "/query/categories" => {
+            let thread = params.get("thread").map(|s| s.as_str());
+            let limit = params.get("limit")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(5);
+            let categories = analyzer.category_breakdown(limit, thread, time_range_params(params));
+            serde_json::json!({
+                "success": true,
+                "query": "categories",
+                "data": categories
+            }).to_string()
+        }
+        "/query/wait-graph" => {
+            let thread = params.get("thread").map(|s| s.as_str());
+            let limit = page.fetch_limit(20);
+            let graph = analyzer.wait_graph(limit, thread, time_range_params(params));
+            serde_json::json!({
+                "success": true,
+                "query": "wait-graph",
+                "data": graph
+            }).to_string()
+        }
+        "/query/signals" => {
+            let thread = params.get("thread").map(|s| s.as_str());
+            let limit = params.get("limit")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(5);
+            let signals = analyzer.signal_breakdown(limit, thread, time_range_params(params));
+            serde_json::json!({
+                "success": true,
+                "query": "signals",
+                "data": signals
+            }).to_string()
+        }
+        "/query/allocations" => {
+            let thread = params.get("thread").map(|s| s.as_str());
+            let limit = page.fetch_limit(10);
+            let allocations = analyzer.allocation_sites(limit, thread, time_range_params(params));
+            serde_json::json!({
+                "success": true,
+                "query": "allocations",
+                "data": allocations
+            }).to_string()
+        }
+        "/query/growth" => {
+            let thread = params.get("thread").map(|s| s.as_str());
+            let limit = page.fetch_limit(10);
+            let windows = params.get("windows")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(2);
+            let growth = analyzer.compute_growth(limit, windows, thread, time_range_params(params));
+            serde_json::json!({
+                "success": true,
+                "query": "growth",
+                "data": growth
+            }).to_string()
+        }
+        "/query/crash" => {
             let limit = params.get("limit")
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(20);
-            if function.is_empty() {
-                return serde_json::json!({
-                    "success": false,
-                    "error": "Missing 'function' parameter"
-                }).to_string();
-            }
-            let callees = analyzer.find_callees(function, depth, limit);
+            let crash = analyzer.crash_summary(limit);
             serde_json::json!({
                 "success": true,
-                "query": "callees",
-                "data": callees
+                "query": "crash",
+                "data": crash
+            }).to_string()
+        }
+        "/query/stack-stats" => {
+            let thread = params.get("thread").map(|s| s.as_str());
+            let stats = analyzer.stack_stats(thread, time_range_params(params));
+            serde_json::json!({
+                "success": true,
+                "query": "stack-stats",
+                "data": stats
+            }).to_string()
+        }
+        "/query/export" => {
+            let format = params
+                .get("format")
+                .map_or(Ok(ExportFormat::default()), |s| s.parse())
+                .map_err(|error| QueryFailure::new(QueryErrorCode::InvalidParam, error))?;
+            let thread = params.get("thread").map(|s| s.as_str());
+            let export = analyzer
+                .export(format, thread, time_range_params(params))
+                .map_err(|error| QueryFailure::new(QueryErrorCode::InvalidParam, error))?;
+            serde_json::json!({
+                "success": true,
+                "query": "export",
+                "data": export
+            }).to_string()
+        }
+        "/query/calltree" => {
+            let thread = params.get("thread").map(|s| s.as_str());
+            let min_percent = params.get("min_percent")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(1.0);
+            let max_depth = params.get("max_depth")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(20);
+            let max_children = params.get("max_children")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(10);
+            let tree = analyzer.call_tree(
+                thread,
+                min_percent,
+                max_depth,
+                max_children,
+                time_range_params(params),
+            );
+            serde_json::json!({
+                "success": true,
+                "query": "calltree",
+                "data": tree
+            }).to_string()
+        }
+        "/query/flamegraph" => {
+            let thread = params.get("thread").map(|s| s.as_str());
+            let inverted = params.get("inverted").map(|s| s == "true").unwrap_or(false);
+            let min_percent = params.get("min_percent")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0.0);
+            let flamegraph = analyzer.build_flamegraph(thread, inverted, min_percent);
+            serde_json::json!({
+                "success": true,
+                "query": "flamegraph",
+                "data": flamegraph
+            }).to_string()
+        }
+        "/query/diff" => {
+            let Some(baseline) = baseline else {
+                return Err(QueryFailure::new(
+                    QueryErrorCode::NoBaseline,
+                    "No baseline profile. Start server with 'samply analyze serve --baseline <file>' to enable diff.",
+                ));
+            };
+            let limit = page.fetch_limit(20);
+            let diff = analyzer.compute_diff(baseline, limit);
+            serde_json::json!({
+                "success": true,
+                "query": "diff",
+                "data": diff
+            }).to_string()
+        }
+        "/query/diff-explain" => {
+            let Some(baseline) = baseline else {
+                return Err(QueryFailure::new(
+                    QueryErrorCode::NoBaseline,
+                    "No baseline profile. Start server with 'samply analyze serve --baseline <file>' to enable diff-explain.",
+                ));
+            };
+            let limit = params.get("limit")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(5);
+            let explain = analyzer.explain_diff(baseline, limit);
+            serde_json::json!({
+                "success": true,
+                "query": "diff-explain",
+                "data": explain
             }).to_string()
         }
         "/query/summary" => {
-            let summary = analyzer.get_summary();
+            let summary = analyzer.get_summary(time_range_params(params));
             serde_json::json!({
                 "success": true,
                 "query": "summary",
                 "data": summary
             }).to_string()
         }
+        "/query/source" => {
+            let function = resolve_function_param(analyzer, params)?;
+            let context = params.get("context")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(3);
+            let source = analyzer.get_source(&function, context, asm_symbol);
+            serde_json::json!({
+                "success": true,
+                "query": "source",
+                "data": source
+            }).to_string()
+        }
         "/query/asm" => {
-            let function = params.get("function").map(|s| s.as_str()).unwrap_or("");
-            if function.is_empty() {
-                return serde_json::json!({
-                    "success": false,
-                    "error": "Missing 'function' parameter"
-                }).to_string();
-            }
-            let asm = analyzer.get_asm(function);
+            let function = resolve_function_param(analyzer, params)?;
+            let asm = analyzer.get_asm(&function, asm_symbol);
             serde_json::json!({
                 "success": true,
                 "query": "asm",
@@ -565,33 +2446,53 @@ fn handle_query_request(
             }).to_string()
         }
         "/query/drilldown" => {
-            let function = params.get("function").map(|s| s.as_str()).unwrap_or("");
-            if function.is_empty() {
-                return serde_json::json!({
-                    "success": false,
-                    "error": "Missing 'function' parameter"
-                }).to_string();
-            }
+            let function = resolve_function_param(analyzer, params)?;
             let depth: usize = params.get("depth")
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(10);
             let threshold: f64 = params.get("threshold")
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(5.0);
-            let drilldown = analyzer.drilldown(function, depth, threshold);
+            let drilldown = analyzer.drilldown(&function, depth, threshold);
             serde_json::json!({
                 "success": true,
                 "query": "drilldown",
                 "data": drilldown
             }).to_string()
         }
+        "/query/symbolication-status" => {
+            let status = match symbolication_status {
+                Some(tracker) => tracker.response(),
+                None => SymbolicationStatusTracker::new(analyzer.library_symbolication_status())
+                    .response(),
+            };
+            serde_json::json!({
+                "success": true,
+                "query": "symbolication-status",
+                "data": status
+            }).to_string()
+        }
         _ => {
+            let plugin = path
+                .strip_prefix("/query/")
+                .and_then(samply_analysis::plugin::find_query_plugin);
+            let Some(plugin) = plugin else {
+                return Err(QueryFailure::new(
+                    QueryErrorCode::NotFound,
+                    format!("Unknown query endpoint: {}", path),
+                ));
+            };
+            let data = plugin
+                .query(analyzer, params)
+                .map_err(|error| QueryFailure::new(QueryErrorCode::QueryFailed, error))?;
             serde_json::json!({
-                "success": false,
-                "error": format!("Unknown query endpoint: {}", path)
+                "success": true,
+                "query": plugin.name(),
+                "data": data
             }).to_string()
         }
-    }
+    };
+    Ok(response)
 }
 
 fn substitute_template(template: &str, template_values: &HashMap<&'static str, String>) -> String {
@@ -601,3 +2502,69 @@ fn substitute_template(template: &str, template_values: &HashMap<&'static str, S
     }
     s
 }
+
+#[cfg(test)]
+mod test {
+    use hyper::header;
+
+    use super::{check_control_request, ContentEncoding};
+
+    fn accepted(accept_encoding: &str) -> Option<ContentEncoding> {
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            header::ACCEPT_ENCODING,
+            header::HeaderValue::from_str(accept_encoding).unwrap(),
+        );
+        ContentEncoding::accepted(&headers)
+    }
+
+    #[test]
+    fn accepted_encoding_prefers_gzip() {
+        assert_eq!(accepted("gzip, deflate, br"), Some(ContentEncoding::Gzip));
+        assert_eq!(accepted("deflate"), Some(ContentEncoding::Deflate));
+        assert_eq!(accepted("GZIP"), Some(ContentEncoding::Gzip));
+        assert_eq!(accepted("*"), Some(ContentEncoding::Gzip));
+        assert_eq!(accepted("br, identity"), None);
+        assert_eq!(ContentEncoding::accepted(&header::HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn accepted_encoding_honors_qualities() {
+        assert_eq!(
+            accepted("gzip;q=0.5, deflate"),
+            Some(ContentEncoding::Deflate)
+        );
+        assert_eq!(
+            accepted("gzip; q=0.8, deflate;q=0.8"),
+            Some(ContentEncoding::Gzip)
+        );
+        assert_eq!(
+            accepted("gzip;q=0, deflate"),
+            Some(ContentEncoding::Deflate)
+        );
+        assert_eq!(accepted("gzip;q=0.0, deflate;q=0"), None);
+    }
+
+    #[test]
+    fn accepted_encoding_refusals_win_over_wildcard() {
+        assert_eq!(accepted("gzip;q=0, *"), Some(ContentEncoding::Deflate));
+        assert_eq!(accepted("*, gzip;q=0"), Some(ContentEncoding::Deflate));
+        assert_eq!(accepted("gzip;q=0, deflate;q=0, *"), None);
+        assert_eq!(accepted("*;q=0"), None);
+        assert_eq!(accepted("*;q=0, deflate"), Some(ContentEncoding::Deflate));
+    }
+
+    #[test]
+    fn control_requests_need_a_secret_and_the_control_header() {
+        let mut headers = header::HeaderMap::new();
+        assert!(check_control_request(&headers, "/abc", false, false).is_err());
+        headers.insert("x-samply-control", header::HeaderValue::from_static("1"));
+        assert!(check_control_request(&headers, "/abc", false, false).is_ok());
+        assert!(check_control_request(&headers, "", true, false).is_ok());
+        // --no-token without --auth-token
+        assert!(check_control_request(&headers, "", false, false).is_err());
+        // --sandbox needs --auth-token
+        assert!(check_control_request(&headers, "/abc", false, true).is_err());
+        assert!(check_control_request(&headers, "/abc", true, true).is_ok());
+    }
+}
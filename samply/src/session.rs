@@ -1,45 +1,98 @@
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::io;
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
-/// Session information stored in ~/.samply/session.json
+/// Session information stored in ~/.samply/session.json, or in
+/// ~/.samply/sessions/<name>.json for a named session.
 /// This enables the query client to discover the running analysis server.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Session {
+    /// Name of the session (`--session NAME`); `None` for the default session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
     /// Server URL including token prefix (e.g., "http://127.0.0.1:3000/abc123")
     pub server_url: String,
     /// Path to the profile file being served
     pub profile_path: String,
+    /// Firefox Profiler URL which loads the served profile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profiler_url: Option<String>,
     /// Process ID of the server
     pub pid: u32,
     /// ISO 8601 timestamp when session was created
     pub started_at: String,
+    /// All profiles of a server which serves several
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub profiles: Vec<SessionProfile>,
+    /// The `--tls-cert` PEM file of a server which serves HTTPS, which query
+    /// clients trust
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_cert: Option<String>,
+    /// The `--auth-token` of a server which requires a bearer token, which
+    /// query clients send. The session file is then only readable by its owner.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_token: Option<String>,
+}
+
+/// A profile of an analysis server which serves several
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionProfile {
+    /// Selects the profile in `/query/<id>/...` URLs and `--profile <id>`
+    pub id: String,
+    pub profile_path: String,
+    pub profiler_url: String,
 }
 
 impl Session {
     /// Create a new session
-    pub fn new(server_url: String, profile_path: String) -> Self {
+    pub fn new(server_url: String, profile_path: String, profiler_url: Option<String>) -> Self {
         let now = chrono_lite_now();
         Self {
+            name: None,
             server_url,
             profile_path,
+            profiler_url,
             pid: std::process::id(),
             started_at: now,
+            profiles: Vec::new(),
+            tls_cert: None,
+            auth_token: None,
+        }
+    }
+
+    /// Get the path to the session file: ~/.samply/session.json, or
+    /// ~/.samply/sessions/<name>.json for a named session
+    pub fn session_file_path(name: Option<&str>) -> PathBuf {
+        match name {
+            Some(name) => Self::sessions_dir().join(format!("{name}.json")),
+            None => Self::samply_dir().join("session.json"),
         }
     }
 
-    /// Get the path to the session file (~/.samply/session.json)
-    pub fn session_file_path() -> PathBuf {
+    fn samply_dir() -> PathBuf {
         let home = std::env::var("HOME")
             .or_else(|_| std::env::var("USERPROFILE"))
             .unwrap_or_else(|_| ".".to_string());
-        PathBuf::from(home).join(".samply").join("session.json")
+        PathBuf::from(home).join(".samply")
+    }
+
+    fn sessions_dir() -> PathBuf {
+        Self::samply_dir().join("sessions")
+    }
+
+    /// Whether `name` can be used as a session name, which is also a file name
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty()
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
     }
 
     /// Save session to the session file
     pub fn save(&self) -> io::Result<()> {
-        let path = Self::session_file_path();
+        let path = Self::session_file_path(self.name.as_deref());
 
         // Create parent directory if it doesn't exist
         if let Some(parent) = path.parent() {
@@ -48,23 +101,62 @@ impl Session {
 
         let json = serde_json::to_string_pretty(self)
             .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-        fs::write(&path, json)?;
+
+        // The server URL's prefix and the auth token let anyone query the
+        // server, so only the owner may read the file, from its creation on.
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&path)?;
+        // The mode only applies to new files; an older session file may have
+        // been created with the default permissions.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        }
+        file.write_all(json.as_bytes())?;
 
         Ok(())
     }
 
-    /// Load session from the session file
-    pub fn load() -> io::Result<Session> {
-        let path = Self::session_file_path();
-        let content = fs::read_to_string(&path)?;
+    /// Load a session from its session file
+    pub fn load(name: Option<&str>) -> io::Result<Session> {
+        Self::load_file(&Self::session_file_path(name))
+    }
+
+    fn load_file(path: &Path) -> io::Result<Session> {
+        let content = fs::read_to_string(path)?;
         let session: Session = serde_json::from_str(&content)
             .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
         Ok(session)
     }
 
+    /// The default session and all named sessions which have a session file,
+    /// including those whose server is no longer running
+    pub fn list() -> Vec<Session> {
+        let mut sessions: Vec<Session> = Self::load(None).into_iter().collect();
+        let Ok(entries) = fs::read_dir(Self::sessions_dir()) else {
+            return sessions;
+        };
+        let mut named: Vec<Session> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|path| Self::load_file(&path).ok())
+            .collect();
+        named.sort_by(|a, b| a.name.cmp(&b.name));
+        sessions.extend(named);
+        sessions
+    }
+
     /// Remove the session file
-    pub fn remove() -> io::Result<()> {
-        let path = Self::session_file_path();
+    pub fn remove(name: Option<&str>) -> io::Result<()> {
+        let path = Self::session_file_path(name);
         if path.exists() {
             fs::remove_file(&path)?;
         }
@@ -72,8 +164,8 @@ impl Session {
     }
 
     /// Check if a session file exists
-    pub fn exists() -> bool {
-        Self::session_file_path().exists()
+    pub fn exists(name: Option<&str>) -> bool {
+        Self::session_file_path(name).exists()
     }
 
     /// Check if the server process is still running
@@ -91,9 +183,22 @@ impl Session {
 
     #[cfg(windows)]
     pub fn is_server_alive(&self) -> bool {
-        // On Windows, we'd need to use Windows API to check process status
-        // For now, assume it's alive if the session file exists
-        true
+        use windows::Win32::Foundation::{CloseHandle, STILL_ACTIVE};
+        use windows::Win32::System::Threading::{
+            GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+        };
+
+        // On Windows, open the process and check that it hasn't exited yet
+        unsafe {
+            let Ok(handle) = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, self.pid) else {
+                return false;
+            };
+            let mut exit_code = 0;
+            let alive = GetExitCodeProcess(handle, &mut exit_code).is_ok()
+                && exit_code == STILL_ACTIVE.0 as u32;
+            let _ = CloseHandle(handle);
+            alive
+        }
     }
 }
 
@@ -165,6 +270,7 @@ mod tests {
         let session = Session::new(
             "http://127.0.0.1:3000/abc123".to_string(),
             "/path/to/profile.json".to_string(),
+            Some("https://profiler.firefox.com/from-url/abc".to_string()),
         );
 
         let json = serde_json::to_string(&session).unwrap();
@@ -172,9 +278,20 @@ mod tests {
 
         assert_eq!(session.server_url, parsed.server_url);
         assert_eq!(session.profile_path, parsed.profile_path);
+        assert_eq!(session.profiler_url, parsed.profiler_url);
         assert_eq!(session.pid, parsed.pid);
     }
 
+    #[test]
+    fn test_session_names() {
+        assert!(Session::is_valid_name("ci-perf_2.1"));
+        assert!(!Session::is_valid_name(""));
+        assert!(!Session::is_valid_name("../session"));
+        assert!(!Session::is_valid_name("a/b"));
+        assert!(Session::session_file_path(Some("ci"))
+            .ends_with(Path::new(".samply").join("sessions").join("ci.json")));
+    }
+
     #[test]
     fn test_chrono_lite_now() {
         let timestamp = chrono_lite_now();
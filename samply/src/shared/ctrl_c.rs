@@ -1,6 +1,8 @@
 use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
 use tokio::sync::oneshot;
+use tokio::sync::oneshot::error::TryRecvError;
 
 static INSTANCE: OnceLock<Arc<Mutex<CtrlCState>>> = OnceLock::new();
 
@@ -129,6 +131,28 @@ impl CtrlC {
         CtrlCState::get().lock().unwrap().current_sender = Some(tx);
         rx
     }
+
+    /// Waits until Ctrl+C is pressed, or until `timeout` has elapsed, if there
+    /// is one. Returns whether Ctrl+C was pressed.
+    #[allow(dead_code)] // Only used on macOS and Windows
+    pub fn wait_with_timeout(mut receiver: Receiver, timeout: Option<Duration>) -> bool {
+        let Some(timeout) = timeout else {
+            return receiver.blocking_recv().is_ok();
+        };
+        let deadline = Instant::now() + timeout;
+        loop {
+            match receiver.try_recv() {
+                Ok(()) => return true,
+                Err(TryRecvError::Closed) => return false,
+                Err(TryRecvError::Empty) => {}
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            std::thread::sleep((deadline - now).min(Duration::from_millis(100)));
+        }
+    }
 }
 
 struct CtrlCState {
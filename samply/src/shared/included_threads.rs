@@ -0,0 +1,25 @@
+use regex::Regex;
+
+/// Specifies which threads `samply record` should sample, by thread name.
+#[derive(Debug, Clone, Default)]
+pub struct IncludedThreads {
+    /// Only sample the threads whose name matches this.
+    pub include: Option<Regex>,
+    /// Don't sample the threads whose name matches this, even if they match
+    /// `include`.
+    pub exclude: Option<Regex>,
+}
+
+impl IncludedThreads {
+    /// Whether every thread is sampled.
+    pub fn is_all(&self) -> bool {
+        self.include.is_none() && self.exclude.is_none()
+    }
+
+    /// Threads whose name isn't known yet are sampled until they get one.
+    pub fn should_include(&self, name: Option<&str>) -> bool {
+        let Some(name) = name else { return true };
+        self.include.as_ref().map_or(true, |re| re.is_match(name))
+            && !self.exclude.as_ref().is_some_and(|re| re.is_match(name))
+    }
+}
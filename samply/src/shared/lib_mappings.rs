@@ -139,6 +139,13 @@ impl LibMappingOpQueue {
         self.0.push((timestamp, op));
     }
 
+    /// Like `push`, but for an op which may be older than the ops which were
+    /// pushed so far, e.g. from a rundown at the end of a trace.
+    pub fn insert(&mut self, timestamp: u64, op: LibMappingOp) {
+        let index = self.0.partition_point(|(t, _)| *t <= timestamp);
+        self.0.insert(index, (timestamp, op));
+    }
+
     pub fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
@@ -0,0 +1,127 @@
+//! The live mode of `samply record --live`: while recording, the sampler
+//! publishes a snapshot of the profile every [`SNAPSHOT_INTERVAL`], and the
+//! analysis server, which starts before recording does, serves and queries
+//! the latest one. `/profile.json?since=MS` only has the samples and markers
+//! from MS on, so that a client which polls doesn't download the whole
+//! profile again.
+//!
+//! A snapshot is a copy of the profile which is being built, with the samples
+//! so far, so the recording goes on. Its JSON and its analyzer are only made
+//! once they're asked for. When recording ends, the final profile is published
+//! as the last snapshot.
+
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use fxprof_processed_profile::Profile;
+use samply_analysis::{ActivityPatterns, ProfileAnalyzer};
+use tokio::sync::watch;
+
+use super::transform::keep_since;
+
+/// How often a snapshot is taken while recording
+pub const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(2);
+
+pub struct LiveProfile {
+    snapshots: watch::Sender<Option<Arc<LiveSnapshot>>>,
+    last_publish_time: Mutex<Option<Instant>>,
+}
+
+impl std::fmt::Debug for LiveProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LiveProfile").finish_non_exhaustive()
+    }
+}
+
+impl Default for LiveProfile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LiveProfile {
+    pub fn new() -> Self {
+        Self {
+            snapshots: watch::Sender::new(None),
+            last_publish_time: Mutex::new(None),
+        }
+    }
+
+    /// Whether the last snapshot is older than [`SNAPSHOT_INTERVAL`]
+    pub fn is_snapshot_due(&self) -> bool {
+        self.last_publish_time
+            .lock()
+            .unwrap()
+            .is_none_or(|time| time.elapsed() >= SNAPSHOT_INTERVAL)
+    }
+
+    /// Replace the served snapshot.
+    pub fn publish(&self, profile: Profile) {
+        *self.last_publish_time.lock().unwrap() = Some(Instant::now());
+        let snapshot = LiveSnapshot {
+            profile,
+            json: OnceLock::new(),
+            analyzer: OnceLock::new(),
+        };
+        self.snapshots.send_replace(Some(Arc::new(snapshot)));
+    }
+
+    /// The latest snapshot, if there is one yet
+    pub fn latest(&self) -> Option<Arc<LiveSnapshot>> {
+        self.snapshots.borrow().clone()
+    }
+
+    /// A receiver which sees every new snapshot
+    pub fn subscribe(&self) -> watch::Receiver<Option<Arc<LiveSnapshot>>> {
+        self.snapshots.subscribe()
+    }
+
+    /// Wait for the first snapshot, which the sampler publishes once it has
+    /// started recording.
+    pub async fn first_snapshot(&self) -> Arc<LiveSnapshot> {
+        let mut receiver = self.snapshots.subscribe();
+        let snapshot = receiver
+            .wait_for(Option::is_some)
+            .await
+            .expect("the sender lives as long as self");
+        (*snapshot).clone().unwrap()
+    }
+}
+
+pub struct LiveSnapshot {
+    profile: Profile,
+    json: OnceLock<String>,
+    analyzer: OnceLock<Result<Arc<ProfileAnalyzer>, String>>,
+}
+
+impl LiveSnapshot {
+    /// The profile JSON
+    pub fn json(&self) -> &str {
+        self.json.get_or_init(|| {
+            serde_json::to_string(&self.profile).expect("profiles always serialize")
+        })
+    }
+
+    /// The profile JSON with the samples, markers and counter samples from
+    /// `since_ms` on, in milliseconds since the profile's start time
+    pub fn json_since(&self, since_ms: f64) -> String {
+        let mut profile = serde_json::to_value(&self.profile).expect("profiles always serialize");
+        keep_since(&mut profile, since_ms);
+        profile.to_string()
+    }
+
+    /// The analyzer for the queries, made on first use
+    pub fn analyzer(
+        &self,
+        activity_patterns: &ActivityPatterns,
+    ) -> Result<Arc<ProfileAnalyzer>, String> {
+        self.analyzer
+            .get_or_init(|| {
+                let mut analyzer =
+                    ProfileAnalyzer::from_json(self.json()).map_err(|e| e.to_string())?;
+                analyzer.set_activity_patterns(activity_patterns.clone());
+                Ok(Arc::new(analyzer))
+            })
+            .clone()
+    }
+}
@@ -0,0 +1,274 @@
+//! An on-disk cache of the symbols which presymbolication looked up, keyed by
+//! library and address, so that symbolicating another profile of the same
+//! binaries doesn't parse their PDB or DWARF debug info again.
+//!
+//! Each library has a file `<debug_name>/<debug_id>.jsonl` in the cache
+//! directory, which is only ever appended to: one JSON object per line, with
+//! the relative address and what it resolved to, or no `info` if it's in no
+//! symbol. The files are in the symbols directory of the quota manager, which
+//! evicts the least recently used ones along with the downloaded symbols.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use debugid::DebugId;
+use samply_quota_manager::QuotaManagerNotifier;
+use serde::{Deserialize, Serialize};
+
+/// What an address resolved to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedAddressInfo {
+    pub symbol_name: String,
+    pub symbol_start_address: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbol_size: Option<u32>,
+    /// Innermost inlined function first, like [`wholesym::AddressInfo::frames`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub frames: Vec<CachedFrame>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedFrame {
+    pub function_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+}
+
+/// A line of a library's cache file
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    rva: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    info: Option<CachedAddressInfo>,
+}
+
+/// The cached lookups of a library, for `samply symbols cache`
+#[derive(Debug, Serialize)]
+pub struct CachedLibrary {
+    pub debug_name: String,
+    pub debug_id: String,
+    pub addresses: usize,
+    pub size_bytes: u64,
+    /// Seconds since the Unix epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<u64>,
+}
+
+pub struct LookupCache {
+    dir: PathBuf,
+    quota_manager_notifier: Option<QuotaManagerNotifier>,
+}
+
+impl LookupCache {
+    pub fn new(dir: PathBuf, quota_manager_notifier: Option<QuotaManagerNotifier>) -> Self {
+        Self {
+            dir,
+            quota_manager_notifier,
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn lib_path(&self, debug_name: &str, debug_id: DebugId) -> PathBuf {
+        // Debug names are file names, but come from the profile.
+        let debug_name = debug_name.replace(['/', '\\'], "_");
+        let debug_name = match debug_name.as_str() {
+            "" | "." | ".." => "_",
+            name => name,
+        };
+        self.dir
+            .join(debug_name)
+            .join(format!("{}.jsonl", debug_id.breakpad()))
+    }
+
+    /// The cached lookups of a library, by relative address. `None` values
+    /// are addresses which aren't in any symbol.
+    pub fn load(
+        &self,
+        debug_name: &str,
+        debug_id: DebugId,
+    ) -> HashMap<u32, Option<CachedAddressInfo>> {
+        let path = self.lib_path(debug_name, debug_id);
+        let Ok(file) = File::open(&path) else {
+            return HashMap::new();
+        };
+        if let Some(notifier) = &self.quota_manager_notifier {
+            notifier.on_file_accessed(&path, SystemTime::now());
+        }
+        read_entries(file)
+            .map(|entry| (entry.rva, entry.info))
+            .collect()
+    }
+
+    /// Append the lookups of a library. Errors are only logged: the cache
+    /// only saves time.
+    pub fn append(
+        &self,
+        debug_name: &str,
+        debug_id: DebugId,
+        lookups: &[(u32, Option<CachedAddressInfo>)],
+    ) {
+        if lookups.is_empty() {
+            return;
+        }
+        let path = self.lib_path(debug_name, debug_id);
+        // Write all lines at once, so that another process appending to the
+        // same file doesn't interleave with them.
+        let mut lines = Vec::new();
+        for (rva, info) in lookups {
+            let entry = Entry {
+                rva: *rva,
+                info: info.clone(),
+            };
+            serde_json::to_writer(&mut lines, &entry).expect("entries always serialize");
+            lines.push(b'\n');
+        }
+        let result = std::fs::create_dir_all(path.parent().unwrap()).and_then(|()| {
+            let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+            file.write_all(&lines)?;
+            file.metadata()
+        });
+        match result {
+            Ok(metadata) => {
+                if let Some(notifier) = &self.quota_manager_notifier {
+                    notifier.on_file_created(&path, metadata.len(), SystemTime::now());
+                }
+            }
+            Err(err) => log::warn!("Could not write to the lookup cache at {path:?}: {err}"),
+        }
+    }
+
+    /// The libraries with cached lookups, by debug name
+    pub fn libraries(&self) -> Vec<CachedLibrary> {
+        let mut libraries = Vec::new();
+        for (debug_name, path) in lib_files(&self.dir) {
+            let Some(debug_id) = path.file_stem() else {
+                continue;
+            };
+            let metadata = std::fs::metadata(&path).ok();
+            let addresses = File::open(&path).map_or(0, |file| read_entries(file).count());
+            libraries.push(CachedLibrary {
+                debug_name,
+                debug_id: debug_id.to_string_lossy().into_owned(),
+                addresses,
+                size_bytes: metadata.as_ref().map_or(0, |metadata| metadata.len()),
+                last_modified: metadata
+                    .and_then(|metadata| metadata.modified().ok())
+                    .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+                    .map(|duration| duration.as_secs()),
+            });
+        }
+        libraries.sort_by(|a, b| (&a.debug_name, &a.debug_id).cmp(&(&b.debug_name, &b.debug_id)));
+        libraries
+    }
+
+    /// The cached lookups of the libraries with this debug name, sorted by
+    /// address, for inspecting what the cache has for a library
+    pub fn entries(&self, debug_name: &str) -> Vec<(String, u32, Option<CachedAddressInfo>)> {
+        let mut entries = Vec::new();
+        for (name, path) in lib_files(&self.dir) {
+            if name != debug_name {
+                continue;
+            }
+            let Ok(file) = File::open(&path) else {
+                continue;
+            };
+            let debug_id = path.file_stem().unwrap_or_default().to_string_lossy();
+            let lookups: HashMap<u32, Option<CachedAddressInfo>> = read_entries(file)
+                .map(|entry| (entry.rva, entry.info))
+                .collect();
+            entries.extend(
+                lookups
+                    .into_iter()
+                    .map(|(rva, info)| (debug_id.to_string(), rva, info)),
+            );
+        }
+        entries.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+        entries
+    }
+
+    /// Delete the cache. Returns the number of library files deleted.
+    pub fn clear(&self) -> std::io::Result<usize> {
+        let count = lib_files(&self.dir).len();
+        match std::fs::remove_dir_all(&self.dir) {
+            Ok(()) => Ok(count),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// The entries of a cache file. Lines which don't parse, e.g. the last line
+/// of a process which was killed while writing it, are skipped.
+fn read_entries(file: File) -> impl Iterator<Item = Entry> {
+    BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str(&line).ok())
+}
+
+/// The cache files, with the debug names of their libraries
+fn lib_files(dir: &Path) -> Vec<(String, PathBuf)> {
+    let mut files = Vec::new();
+    let Ok(lib_dirs) = std::fs::read_dir(dir) else {
+        return files;
+    };
+    for lib_dir in lib_dirs.flatten() {
+        let debug_name = lib_dir.file_name().to_string_lossy().into_owned();
+        let Ok(lib_files) = std::fs::read_dir(lib_dir.path()) else {
+            continue;
+        };
+        for file in lib_files.flatten() {
+            let path = file.path();
+            if path.extension().is_some_and(|ext| ext == "jsonl") {
+                files.push((debug_name.clone(), path));
+            }
+        }
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_lookup_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = LookupCache::new(dir.path().to_owned(), None);
+        let debug_id = DebugId::from_str("b2ae4a7bf1df4f2db1fb1e2bd1f8a3dd1").unwrap();
+        let info = CachedAddressInfo {
+            symbol_name: "main".to_string(),
+            symbol_start_address: 0x1000,
+            symbol_size: Some(0x40),
+            frames: vec![CachedFrame {
+                function_name: "main".to_string(),
+                file: Some("src/main.rs".to_string()),
+                line: Some(12),
+            }],
+        };
+        cache.append("app", debug_id, &[(0x1010, Some(info)), (0x9000, None)]);
+
+        let lookups = cache.load("app", debug_id);
+        assert_eq!(lookups.len(), 2);
+        assert_eq!(lookups[&0x1010].as_ref().unwrap().frames[0].line, Some(12));
+        assert!(lookups[&0x9000].is_none());
+        assert!(cache.load("other", debug_id).is_empty());
+
+        let libraries = cache.libraries();
+        assert_eq!(libraries.len(), 1);
+        assert_eq!(libraries[0].debug_name, "app");
+        assert_eq!(libraries[0].addresses, 2);
+        assert_eq!(cache.clear().unwrap(), 1);
+        assert!(cache.libraries().is_empty());
+    }
+}
@@ -1,19 +1,26 @@
 pub mod context_switch;
 pub mod ctrl_c;
 pub mod included_processes;
+pub mod included_threads;
 pub mod jit_category_manager;
 pub mod jit_function_add_marker;
 pub mod jit_function_recycler;
 pub mod jitdump_manager;
 pub mod lib_mappings;
+pub mod live_profile;
+pub mod lookup_cache;
 pub mod marker_file;
+pub mod overhead;
 pub mod per_cpu;
 pub mod perf_map;
 pub mod presymbolicate;
 pub mod process_name;
 pub mod process_sample_data;
+pub mod progress;
 pub mod prop_types;
 pub mod recycling;
+#[cfg(not(target_os = "windows"))]
+pub mod ring_buffer;
 pub mod save_profile;
 pub mod stack_converter;
 pub mod stack_depth_limiting_frame_iter;
@@ -21,6 +28,8 @@ pub mod symbol_manager_observer;
 pub mod symbol_precog;
 pub mod synthetic_jit_library;
 pub mod timestamp_converter;
+pub mod transform;
 pub mod types;
 pub mod unresolved_samples;
 pub mod utils;
+pub mod wakeup_marker;
@@ -0,0 +1,105 @@
+use std::time::{Duration, Instant};
+
+use fxprof_processed_profile::ProfilerOverhead;
+
+/// Measures the resources which samply itself uses during a recording.
+///
+/// Create it when recording starts and call [`OverheadMeter::finish`] when it
+/// stops. CPU time is counted for the whole samply process, i.e. all of its
+/// threads, but not for the profiled processes.
+pub struct OverheadMeter {
+    start: Instant,
+    start_cpu_time: Option<Duration>,
+}
+
+impl OverheadMeter {
+    pub fn start() -> Self {
+        Self {
+            start: Instant::now(),
+            start_cpu_time: process_cpu_time(),
+        }
+    }
+
+    pub fn finish(&self, lost_events: u64) -> ProfilerOverhead {
+        let cpu_time = match (self.start_cpu_time, process_cpu_time()) {
+            (Some(start), Some(end)) => end.saturating_sub(start),
+            _ => Duration::ZERO,
+        };
+        ProfilerOverhead {
+            duration: self.start.elapsed(),
+            cpu_time,
+            peak_memory_bytes: peak_memory_bytes(),
+            lost_events,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn rusage_self() -> Option<libc::rusage> {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    let result = unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) };
+    (result == 0).then_some(usage)
+}
+
+#[cfg(unix)]
+pub fn process_cpu_time() -> Option<Duration> {
+    let usage = rusage_self()?;
+    let to_duration = |tv: libc::timeval| {
+        Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+    };
+    Some(to_duration(usage.ru_utime) + to_duration(usage.ru_stime))
+}
+
+#[cfg(unix)]
+fn peak_memory_bytes() -> Option<u64> {
+    let max_rss = rusage_self()?.ru_maxrss as u64;
+    // ru_maxrss is in bytes on macOS and in kilobytes everywhere else.
+    if cfg!(target_os = "macos") {
+        Some(max_rss)
+    } else {
+        Some(max_rss * 1024)
+    }
+}
+
+#[cfg(windows)]
+pub fn process_cpu_time() -> Option<Duration> {
+    use windows::Win32::Foundation::FILETIME;
+    use windows::Win32::System::Threading::{GetCurrentProcess, GetProcessTimes};
+
+    let mut creation = FILETIME::default();
+    let mut exit = FILETIME::default();
+    let mut kernel = FILETIME::default();
+    let mut user = FILETIME::default();
+    unsafe {
+        GetProcessTimes(
+            GetCurrentProcess(),
+            &mut creation,
+            &mut exit,
+            &mut kernel,
+            &mut user,
+        )
+        .ok()?;
+    }
+    // FILETIME values are in units of 100ns.
+    let to_duration = |ft: FILETIME| {
+        Duration::from_nanos(((ft.dwHighDateTime as u64) << 32 | ft.dwLowDateTime as u64) * 100)
+    };
+    Some(to_duration(kernel) + to_duration(user))
+}
+
+#[cfg(windows)]
+fn peak_memory_bytes() -> Option<u64> {
+    use windows::Win32::System::ProcessStatus::{GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS};
+    use windows::Win32::System::Threading::GetCurrentProcess;
+
+    let mut counters = PROCESS_MEMORY_COUNTERS::default();
+    unsafe {
+        GetProcessMemoryInfo(
+            GetCurrentProcess(),
+            &mut counters,
+            std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32,
+        )
+        .ok()?;
+    }
+    Some(counters.PeakWorkingSetSize as u64)
+}
@@ -1,105 +1,146 @@
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use futures_util::future::join_all;
 use fxprof_processed_profile::symbol_info::{
     AddressFrame as ProfileAddressFrame, AddressInfo as ProfileAddressInfo, LibSymbolInfo,
-    ProfileSymbolInfo, SymbolStringIndex, SymbolStringTable,
+    ProfileSymbolInfo, SymbolStringTable,
 };
 use fxprof_processed_profile::LibraryHandle;
-use rustc_hash::FxHashMap;
-use wholesym::{
-    FunctionNameHandle, SourceFilePathHandle, SymbolManager, SymbolMap, SymbolNameHandle,
-};
-
-use crate::symbols::create_symbol_manager_and_quota_manager;
+use tokio::runtime::Handle;
+use tokio::sync::Semaphore;
+use wholesym::{SymbolManager, SymbolMap};
 
+use super::lookup_cache::{CachedAddressInfo, CachedFrame, LookupCache};
+use super::progress::Progress;
 use super::prop_types::SymbolProps;
 
-struct StringTableAdapterForSymbolTable<'a> {
-    symbol_map: &'a SymbolMap,
-    string_table: &'a mut SymbolStringTable,
-    function_name_map: FxHashMap<FunctionNameHandle, SymbolStringIndex>,
-    symbol_name_map: FxHashMap<SymbolNameHandle, SymbolStringIndex>,
-    source_file_path_map: FxHashMap<SourceFilePathHandle, SymbolStringIndex>,
+/// The delay before the first retry of a library; it doubles with every retry.
+const FIRST_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// How often to retry the symbols of a library, and how long to wait for
+/// them per attempt.
+#[derive(Debug, Clone, Copy)]
+struct FetchPolicy {
+    retries: u32,
+    timeout: Duration,
 }
 
-impl<'a> StringTableAdapterForSymbolTable<'a> {
-    pub fn map_function_name(&mut self, handle: FunctionNameHandle) -> SymbolStringIndex {
-        *self.function_name_map.entry(handle).or_insert_with(|| {
-            let function_name = self.symbol_map.resolve_function_name(handle);
-            self.string_table.index_for_string(&function_name)
-        })
+/// Why the symbols of a library couldn't be loaded
+enum LoadFailure {
+    TimedOut,
+    Error(wholesym::Error),
+}
+
+impl LoadFailure {
+    /// Whether another attempt might succeed. Only fetches from a server can
+    /// fail by chance; a missing local file stays missing.
+    fn is_transient(&self) -> bool {
+        match self {
+            LoadFailure::TimedOut => true,
+            LoadFailure::Error(err) => is_transient_error(err),
+        }
     }
+}
 
-    pub fn map_symbol_name(&mut self, handle: SymbolNameHandle) -> SymbolStringIndex {
-        *self.symbol_name_map.entry(handle).or_insert_with(|| {
-            let symbol_name = self.symbol_map.resolve_symbol_name(handle);
-            self.string_table.index_for_string(&symbol_name)
-        })
+impl std::fmt::Display for LoadFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadFailure::TimedOut => write!(f, "timed out"),
+            LoadFailure::Error(err) => write!(f, "{err}"),
+        }
     }
+}
 
-    pub fn map_source_file_path(&mut self, handle: SourceFilePathHandle) -> SymbolStringIndex {
-        *self.source_file_path_map.entry(handle).or_insert_with(|| {
-            let path = self.symbol_map.resolve_source_file_path(handle);
-            let path_str = path
-                .special_path_str()
-                .unwrap_or_else(|| path.raw_path().into());
-            self.string_table.index_for_string(&path_str)
-        })
+fn is_transient_error(err: &wholesym::Error) -> bool {
+    match err {
+        wholesym::Error::HelperErrorDuringOpenFile(location, _) => !location.starts_with("Local"),
+        wholesym::Error::NoSuccessfulCandidate(errors) => errors.iter().any(is_transient_error),
+        _ => false,
     }
 }
 
-fn convert_address_frame(
-    frame: &wholesym::FrameDebugInfo,
-    strtab: &mut StringTableAdapterForSymbolTable,
-) -> Option<ProfileAddressFrame> {
-    let function_handle = frame.function?;
-    let function_name = strtab.map_function_name(function_handle);
-    let file = frame
-        .file_path
-        .map(|handle| strtab.map_source_file_path(handle));
-
-    Some(ProfileAddressFrame {
-        function_name,
-        file,
-        line: frame.line_number,
-    })
+/// The symbol and inlined frames of an address, with their strings resolved,
+/// as they're stored in the lookup cache
+fn cached_address_info(symbol_map: &SymbolMap, info: &wholesym::AddressInfo) -> CachedAddressInfo {
+    let frames = info
+        .frames
+        .iter()
+        .flatten()
+        .filter_map(|frame| {
+            let function_name = symbol_map.resolve_function_name(frame.function?);
+            let file = frame.file_path.map(|handle| {
+                let path = symbol_map.resolve_source_file_path(handle);
+                path.special_path_str()
+                    .unwrap_or_else(|| path.raw_path().into())
+                    .into_owned()
+            });
+            Some(CachedFrame {
+                function_name: function_name.into_owned(),
+                file,
+                line: frame.line_number,
+            })
+        })
+        .collect();
+    CachedAddressInfo {
+        symbol_name: symbol_map
+            .resolve_symbol_name(info.symbol.name)
+            .into_owned(),
+        symbol_start_address: info.symbol.address,
+        symbol_size: info.symbol.size,
+        frames,
+    }
 }
 
 fn convert_address_info(
-    info: &wholesym::AddressInfo,
-    strtab: &mut StringTableAdapterForSymbolTable,
+    info: &CachedAddressInfo,
+    string_table: &mut SymbolStringTable,
 ) -> ProfileAddressInfo {
-    let symbol_name = strtab.map_symbol_name(info.symbol.name);
     let frames = info
         .frames
-        .as_ref()
-        .map(|frames| {
-            frames
-                .iter()
-                .flat_map(|frame| convert_address_frame(frame, strtab))
-                .collect()
+        .iter()
+        .map(|frame| ProfileAddressFrame {
+            function_name: string_table.index_for_string(&frame.function_name),
+            file: frame
+                .file
+                .as_ref()
+                .map(|file| string_table.index_for_string(file)),
+            line: frame.line,
         })
-        .unwrap_or_default();
+        .collect();
     ProfileAddressInfo {
-        symbol_name,
-        symbol_start_address: info.symbol.address,
-        symbol_size: info.symbol.size,
+        symbol_name: string_table.index_for_string(&info.symbol_name),
+        symbol_start_address: info.symbol_start_address,
+        symbol_size: info.symbol_size,
         frames,
     }
 }
 
+/// Look up the symbols of the profile's native frames on `runtime`. The
+/// symbol manager is handed back with the profile's libraries added, so that
+/// a server for the profile can go on using it and its download client.
+/// Addresses in `lookup_cache` aren't looked up again, and the new lookups
+/// are added to it.
 pub fn get_presymbolicate_info(
+    runtime: &Handle,
     profile: &fxprof_processed_profile::Profile,
-    symbol_props: SymbolProps,
-) -> ProfileSymbolInfo {
-    let rt = tokio::runtime::Runtime::new().unwrap();
-
-    rt.block_on(async {
-        let (mut symbol_manager, quota_manager) =
-            create_symbol_manager_and_quota_manager(symbol_props, false);
+    symbol_manager: SymbolManager,
+    lookup_cache: Option<Arc<LookupCache>>,
+    symbol_props: &SymbolProps,
+) -> (ProfileSymbolInfo, SymbolManager) {
+    let symbol_jobs = symbol_props.symbol_jobs;
+    let policy = FetchPolicy {
+        // Without the network, another attempt would find the same files.
+        retries: if symbol_props.offline {
+            0
+        } else {
+            symbol_props.symbol_retries
+        },
+        timeout: symbol_props.symbol_timeout,
+    };
 
+    runtime.block_on(async {
         let native_frame_addresses_per_library = profile.native_frame_addresses_per_library();
         let lib_stuff: Vec<_> = native_frame_addresses_per_library
             .into_iter()
@@ -129,41 +170,65 @@ pub fn get_presymbolicate_info(
 
         let string_table = Arc::new(Mutex::new(SymbolStringTable::new()));
         let symbol_manager = Arc::new(symbol_manager);
+        let lib_count = lib_stuff.len();
+        let progress = Progress::new("symbolicate", "libraries", Some(lib_count as u64));
+        // Spawning all tasks at once would send a request per library to the
+        // symbol servers at the same time.
+        let semaphore = Arc::new(Semaphore::new(symbol_jobs.max(1)));
 
         let symbolication_tasks = lib_stuff.into_iter().map(|(lib_handle, lib, rvas)| {
             let symbol_manager = Arc::clone(&symbol_manager);
+            let lookup_cache = lookup_cache.clone();
             let string_table = Arc::clone(&string_table);
+            let semaphore = Arc::clone(&semaphore);
+            let progress = progress.clone();
             tokio::spawn(async move {
-                get_lib_symbols(
+                let _permit = semaphore.acquire_owned().await.unwrap();
+                let lib_name = lib.debug_name.clone().unwrap_or_default();
+                let lib_symbols = get_lib_symbols(
                     lib_handle,
                     lib,
                     &rvas,
                     &symbol_manager,
+                    lookup_cache.as_deref(),
                     string_table.clone(),
+                    policy,
                 )
                 .await
+                .map_err(|failure| (lib_name, failure));
+                progress.inc(1);
+                lib_symbols
             })
         });
 
         let symbolication_results = join_all(symbolication_tasks).await;
+        progress.finish();
 
-        if let Some(quota_manager) = quota_manager {
-            quota_manager.finish().await;
+        let mut lib_symbols = Vec::new();
+        let mut failures = Vec::new();
+        for result in symbolication_results {
+            match result.unwrap() {
+                Ok(symbols) => lib_symbols.push(symbols),
+                Err(failure) => failures.push(failure),
+            }
         }
+        report_failures(&mut failures, lib_count);
+        report_skipped_lookups(&symbol_manager, symbol_props);
 
-        let lib_symbols: Vec<_> = symbolication_results
-            .into_iter()
-            .filter_map(|x| x.unwrap())
-            .collect();
         let string_table = match Arc::try_unwrap(string_table) {
             Ok(string_table) => string_table.into_inner().unwrap(),
             Err(_string_table) => panic!("String table Arc still in use"),
         };
+        let symbol_manager = match Arc::try_unwrap(symbol_manager) {
+            Ok(symbol_manager) => symbol_manager,
+            Err(_symbol_manager) => panic!("Symbol manager Arc still in use"),
+        };
 
-        ProfileSymbolInfo {
+        let symbol_info = ProfileSymbolInfo {
             string_table,
             lib_symbols,
-        }
+        };
+        (symbol_info, symbol_manager)
     })
 }
 
@@ -172,44 +237,137 @@ async fn get_lib_symbols(
     lib: wholesym::LibraryInfo,
     rvas: &[u32],
     symbol_manager: &SymbolManager,
+    lookup_cache: Option<&LookupCache>,
     string_table: Arc<Mutex<SymbolStringTable>>,
-) -> Option<LibSymbolInfo> {
+    policy: FetchPolicy,
+) -> Result<LibSymbolInfo, LoadFailure> {
     //eprintln!("Library {} ({}) has {} rvas", lib.debug_name, lib.debug_id, rvas.len());
-    let Ok(symbol_map) = symbol_manager
-        .load_symbol_map(lib.debug_name.as_deref().unwrap(), lib.debug_id.unwrap())
-        .await
-    else {
-        //eprintln!("Couldn't load symbol map for {} at {} {} ({})", lib.debug_name, lib.path, lib.debug_path, lib.debug_id);
-        return None;
-    };
+    let debug_name = lib.debug_name.as_deref().unwrap();
+    let debug_id = lib.debug_id.unwrap();
+    let mut lookups = lookup_cache
+        .map(|cache| cache.load(debug_name, debug_id))
+        .unwrap_or_default();
+
+    // Only load the symbol map, which parses the debug info, if some
+    // addresses aren't cached.
+    let uncached_rvas: Vec<u32> = rvas
+        .iter()
+        .copied()
+        .filter(|rva| !lookups.contains_key(rva))
+        .collect();
+    if !uncached_rvas.is_empty() {
+        let symbol_map = load_symbol_map_with_retries(symbol_manager, &lib, policy).await?;
+        let mut new_lookups = Vec::new();
+        for rva in uncached_rvas {
+            let addr_info = symbol_map
+                .lookup(wholesym::LookupAddress::Relative(rva))
+                .await;
+            let info = addr_info.map(|addr_info| cached_address_info(&symbol_map, &addr_info));
+            new_lookups.push((rva, info));
+        }
+        if let Some(lookup_cache) = lookup_cache {
+            lookup_cache.append(debug_name, debug_id, &new_lookups);
+        }
+        lookups.extend(new_lookups);
+    }
 
+    let mut string_table = string_table.lock().unwrap();
     let mut sorted_addresses = Vec::new();
     let mut address_infos = Vec::new();
-    for rva in rvas.iter().cloned() {
-        let Some(addr_info) = symbol_map
-            .lookup(wholesym::LookupAddress::Relative(rva))
-            .await
-        else {
+    for &rva in rvas {
+        let Some(Some(info)) = lookups.get(&rva) else {
             continue;
         };
-        let mut string_table = string_table.lock().unwrap();
-
-        let mut string_table = StringTableAdapterForSymbolTable {
-            symbol_map: &symbol_map,
-            string_table: &mut string_table,
-            function_name_map: Default::default(),
-            symbol_name_map: Default::default(),
-            source_file_path_map: Default::default(),
-        };
-
-        let address_info = convert_address_info(&addr_info, &mut string_table);
         sorted_addresses.push(rva);
-        address_infos.push(address_info);
+        address_infos.push(convert_address_info(info, &mut string_table));
     }
 
-    Some(LibSymbolInfo {
+    Ok(LibSymbolInfo {
         lib_handle,
         sorted_addresses,
         address_infos,
     })
 }
+
+/// Load the symbol map of `lib`, giving up on an attempt after the timeout
+/// and retrying transient failures with exponential backoff.
+async fn load_symbol_map_with_retries(
+    symbol_manager: &SymbolManager,
+    lib: &wholesym::LibraryInfo,
+    policy: FetchPolicy,
+) -> Result<SymbolMap, LoadFailure> {
+    let debug_name = lib.debug_name.as_deref().unwrap();
+    let debug_id = lib.debug_id.unwrap();
+    let mut delay = FIRST_RETRY_DELAY;
+    let mut attempt = 0;
+    loop {
+        let load = symbol_manager.load_symbol_map(debug_name, debug_id);
+        let failure = match tokio::time::timeout(policy.timeout, load).await {
+            Ok(Ok(symbol_map)) => return Ok(symbol_map),
+            Ok(Err(err)) => LoadFailure::Error(err),
+            Err(_elapsed) => LoadFailure::TimedOut,
+        };
+        if attempt == policy.retries || !failure.is_transient() {
+            return Err(failure);
+        }
+        attempt += 1;
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+    }
+}
+
+/// Print which libraries couldn't be symbolicated, and why. Only the first
+/// few are listed, since system libraries without symbols can be many.
+/// List the files which weren't fetched from symbol servers because of
+/// `--offline` or `--max-symbol-requests`, so that the user knows what a
+/// run with the network would add.
+fn report_skipped_lookups(symbol_manager: &SymbolManager, symbol_props: &SymbolProps) {
+    if let Some(max_requests) = symbol_props.max_symbol_requests {
+        for (origin, requests) in symbol_manager.requests_per_origin() {
+            if requests >= max_requests {
+                eprintln!("Used up the budget of {max_requests} requests to {origin}.");
+            }
+        }
+    }
+    let skipped = symbol_manager.skipped_network_lookups();
+    if skipped.is_empty() {
+        return;
+    }
+    eprintln!(
+        "{} symbol files weren't in the local caches and would have needed the network:",
+        skipped.len()
+    );
+    const MAX_LISTED: usize = 20;
+    for lookup in skipped.iter().take(MAX_LISTED) {
+        eprintln!(
+            "  {} ({}, from {})",
+            lookup.file,
+            lookup.reason,
+            lookup.origins.join(", ")
+        );
+    }
+    if skipped.len() > MAX_LISTED {
+        eprintln!("  ... and {} more", skipped.len() - MAX_LISTED);
+    }
+}
+
+fn report_failures(failures: &mut [(String, LoadFailure)], lib_count: usize) {
+    if failures.is_empty() {
+        return;
+    }
+    failures.sort_by(|a, b| a.0.cmp(&b.0));
+    eprintln!(
+        "Couldn't symbolicate {} of {lib_count} libraries:",
+        failures.len()
+    );
+    const MAX_LISTED: usize = 20;
+    for (lib_name, failure) in failures.iter().take(MAX_LISTED) {
+        // Only the first line; the candidate lists of wholesym errors are long.
+        let reason = failure.to_string();
+        let reason = reason.lines().next().unwrap_or_default();
+        eprintln!("  {lib_name}: {reason}");
+    }
+    if failures.len() > MAX_LISTED {
+        eprintln!("  ... and {} more", failures.len() - MAX_LISTED);
+    }
+}
@@ -1,11 +1,95 @@
+use std::borrow::Cow;
+use std::str::FromStr;
+
+use regex::Regex;
+
+/// Names a process or thread whose name matches `pattern` after `template`,
+/// in which `$1` or `$name` stand for the pattern's capture groups.
+///
+/// For processes, the pattern is matched against the executable name
+/// followed by all of its arguments, separated by spaces and without quotes,
+/// e.g. `java -Xmx4g -jar server.jar`, so that the runtimes which run
+/// everything through one executable (Electron, the JVM, busybox) can get
+/// names after what they run: `-jar (\S+)\.jar` with the template `java $1`
+/// names it `java server`.
+#[derive(Debug, Clone)]
+pub struct NameRule {
+    pub pattern: Regex,
+    pub template: String,
+}
+
+impl NameRule {
+    /// The name after the template, if the pattern matches `name`
+    fn apply(&self, name: &str) -> Option<String> {
+        let captures = self.pattern.captures(name)?;
+        let mut expanded = String::new();
+        captures.expand(&self.template, &mut expanded);
+        Some(expanded)
+    }
+}
+
+/// `REGEX=>TEMPLATE`, as given to `--name-template`
+impl FromStr for NameRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pattern, template) = s
+            .split_once("=>")
+            .ok_or_else(|| "expected REGEX=>TEMPLATE".to_string())?;
+        let pattern = Regex::new(pattern).map_err(|e| e.to_string())?;
+        Ok(Self {
+            pattern,
+            template: template.to_string(),
+        })
+    }
+}
+
+/// The rules for the names of processes and threads, from the command line
+/// and the config file. The first matching rule names a process or thread;
+/// without one, it keeps the name samply would give it.
+///
+/// As with `--include-args`, the names decide which processes and threads
+/// `--reuse-threads` matches up.
+#[derive(Debug, Clone, Default)]
+pub struct NameRules {
+    pub process: Vec<NameRule>,
+    pub thread: Vec<NameRule>,
+}
+
+impl NameRules {
+    /// The name of the process with this command line, or of the process
+    /// which renamed itself to this name, if a rule matches it
+    pub fn process_name(&self, command_line: &str) -> Option<String> {
+        self.process
+            .iter()
+            .find_map(|rule| rule.apply(command_line))
+    }
+
+    #[allow(dead_code)]
+    pub fn thread_name<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        match self.thread.iter().find_map(|rule| rule.apply(name)) {
+            Some(name) => Cow::Owned(name),
+            None => Cow::Borrowed(name),
+        }
+    }
+}
+
 #[allow(dead_code)]
 pub fn make_process_name(
     executable: &str,
     args: Vec<String>,
     arg_count_to_include: usize,
+    rules: &NameRules,
 ) -> String {
     let mut args = args.iter().map(std::ops::Deref::deref);
     let _executable = args.next();
+    if !rules.process.is_empty() {
+        let command_line: Vec<&str> = std::iter::once(executable).chain(args.clone()).collect();
+        let command_line = command_line.join(" ");
+        if let Some(name) = rules.process_name(&command_line) {
+            return name;
+        }
+    }
     let mut included_args = args.take(arg_count_to_include).peekable();
     if included_args.peek().is_some() {
         let joined_args = shlex::try_join(included_args).unwrap_or_default();
@@ -14,3 +98,38 @@ pub fn make_process_name(
         executable.to_owned()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_name_rules() {
+        let rules = NameRules {
+            process: vec![
+                r"^electron .*--type=(?<type>\S+)=>electron ($type)"
+                    .parse()
+                    .unwrap(),
+                r"-jar (\S+)\.jar=>java $1".parse().unwrap(),
+            ],
+            thread: vec![r"^pool-\d+-thread-\d+$=>pool worker".parse().unwrap()],
+        };
+        let electron = args(&["/opt/app/electron", "--type=renderer", "--lang=en"]);
+        assert_eq!(
+            make_process_name("electron", electron, 0, &rules),
+            "electron (renderer)"
+        );
+        let java = args(&["java", "-Xmx4g", "-jar", "server.jar"]);
+        assert_eq!(make_process_name("java", java, 0, &rules), "java server");
+        let ls = args(&["ls", "-l"]);
+        assert_eq!(make_process_name("ls", ls, 1, &rules), "ls -l");
+
+        assert_eq!(rules.thread_name("pool-1-thread-12"), "pool worker");
+        assert_eq!(rules.thread_name("main"), "main");
+        assert!("no arrow".parse::<NameRule>().is_err());
+    }
+}
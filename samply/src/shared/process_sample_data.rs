@@ -1,14 +1,16 @@
 use fxprof_processed_profile::{
-    LibMappings, Marker, MarkerField, MarkerTiming, Profile, Schema, StringHandle,
-    SubcategoryHandle, ThreadHandle, Timestamp,
+    Category, CategoryColor, FrameFlags, FrameHandle, LibMappings, Marker, MarkerField,
+    MarkerTiming, Profile, Schema, StringHandle, SubcategoryHandle, ThreadHandle, Timestamp,
 };
+use samply_analysis::TraceContext;
 
 use super::lib_mappings::{LibMappingInfo, LibMappingOpQueue, LibMappingsHierarchy};
 use super::stack_converter::StackConverter;
 use super::stack_depth_limiting_frame_iter::StackDepthLimitingFrameIter;
-use super::types::StackFrame;
+use super::types::{FastHashMap, StackFrame};
 use super::unresolved_samples::{
-    SampleData, SampleOrMarker, UnresolvedSampleOrMarker, UnresolvedSamples, UnresolvedStacks,
+    AllocationData, SampleData, SampleOrMarker, UnresolvedSampleOrMarker, UnresolvedSamples,
+    UnresolvedStacks,
 };
 
 #[derive(Debug, Clone)]
@@ -57,6 +59,11 @@ impl ProcessSampleData {
         self.unresolved_samples.is_empty()
     }
 
+    #[allow(unused)]
+    pub fn drop_samples_before(&mut self, timestamp_mono: u64) {
+        self.unresolved_samples.drop_before(timestamp_mono);
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn flush_samples_to_profile(
         self,
@@ -81,6 +88,7 @@ impl ProcessSampleData {
             lib_mappings_hierarchy.add_perf_map_mappings(perf_map_mappings);
         }
         let mut stack_converter = StackConverter::new(user_category, kernel_category);
+        let mut off_cpu_frames: FastHashMap<ThreadHandle, FrameHandle> = Default::default();
         let samples = unresolved_samples.into_inner();
         for sample in samples {
             lib_mappings_hierarchy.process_ops(sample.timestamp_mono);
@@ -106,9 +114,26 @@ impl ProcessSampleData {
             let stack_handle =
                 profile.handle_for_stack_frames(thread_handle, move |p| frames.next(p));
             match sample_or_marker {
-                SampleOrMarker::Sample(SampleData { cpu_delta, weight }) => {
+                SampleOrMarker::Sample(SampleData {
+                    cpu_delta,
+                    weight,
+                    is_off_cpu,
+                }) => {
+                    let stack_handle = if is_off_cpu {
+                        let frame = *off_cpu_frames
+                            .entry(thread_handle)
+                            .or_insert_with(|| off_cpu_frame(profile, thread_handle));
+                        Some(profile.handle_for_stack(thread_handle, frame, stack_handle))
+                    } else {
+                        stack_handle
+                    };
                     profile.add_sample(thread_handle, timestamp, stack_handle, cpu_delta, weight);
                 }
+                SampleOrMarker::Allocation(AllocationData { process, bytes }) => {
+                    // The address is only needed to match allocations with
+                    // their frees, which aren't recorded.
+                    profile.add_allocation_sample(process, timestamp, stack_handle, 0, bytes);
+                }
                 SampleOrMarker::MarkerHandle(mh) => {
                     profile.set_marker_stack(thread_handle, mh, stack_handle);
                 }
@@ -116,16 +141,42 @@ impl ProcessSampleData {
         }
 
         for marker in marker_spans {
+            let timing = MarkerTiming::Interval(marker.start_time, marker.end_time);
+            // Spans with a W3C traceparent in their name become trace spans, so
+            // that their samples can be found by trace id.
+            if let Some(context) = TraceContext::find_in(&marker.name) {
+                let name = profile.handle_for_string(&TraceContext::strip_from(&marker.name));
+                let trace_id = profile.handle_for_string(&context.trace_id);
+                let span_id = profile.handle_for_string(&context.span_id.unwrap_or_default());
+                profile.add_marker(
+                    marker.thread_handle,
+                    timing,
+                    TraceSpanMarker {
+                        name,
+                        trace_id,
+                        span_id,
+                    },
+                );
+                continue;
+            }
             let marker_name_string_index = profile.handle_for_string(&marker.name);
             profile.add_marker(
                 marker.thread_handle,
-                MarkerTiming::Interval(marker.start_time, marker.end_time),
+                timing,
                 SimpleMarker(marker_name_string_index),
             );
         }
     }
 }
 
+/// The leaf frame of the off-CPU samples of a thread, in a category of its
+/// own, so that blocked time stands out from running time.
+fn off_cpu_frame(profile: &mut Profile, thread: ThreadHandle) -> FrameHandle {
+    let category = profile.handle_for_category(Category("Off-CPU", CategoryColor::LightBlue));
+    let label = profile.handle_for_string("Off-CPU");
+    profile.handle_for_frame_with_label(thread, label, category, FrameFlags::empty())
+}
+
 #[derive(Debug, Clone)]
 pub struct RssStatMarker {
     pub name: StringHandle,
@@ -170,6 +221,107 @@ impl Marker for RssStatMarker {
     }
 }
 
+/// A signal delivery, on the thread which the signal is delivered to. The
+/// marker is named after the signal, e.g. "SIGSEGV".
+#[derive(Debug, Clone)]
+pub struct SignalMarker {
+    pub name: StringHandle,
+    pub signal: i32,
+}
+
+impl Marker for SignalMarker {
+    type FieldsType = f64;
+
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "Signal";
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.name}");
+    const TOOLTIP_LABEL: Option<&'static str> = Some("{marker.name} ({marker.data.signal})");
+    const TABLE_LABEL: Option<&'static str> = Some("{marker.name} ({marker.data.signal})");
+
+    const DESCRIPTION: Option<&'static str> =
+        Some("Emitted when the signal:signal_deliver tracepoint is hit.");
+
+    const FIELDS: Schema<Self::FieldsType> =
+        Schema(MarkerField::integer("signal", "Signal number"));
+
+    fn name(&self, _profile: &mut Profile) -> StringHandle {
+        self.name
+    }
+
+    fn field_values(&self) -> f64 {
+        self.signal as f64
+    }
+}
+
+/// A change of the sampling frequency during the recording, from `samply
+/// record --adaptive-rate`. `reason` is what made the recorder change it.
+#[derive(Debug, Clone)]
+pub struct SampleRateMarker {
+    pub from_hz: u32,
+    pub to_hz: u32,
+    pub reason: StringHandle,
+}
+
+impl Marker for SampleRateMarker {
+    type FieldsType = (f64, f64, StringHandle);
+
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "SampleRate";
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.data.toHz} Hz");
+    const TOOLTIP_LABEL: Option<&'static str> =
+        Some("Sampling rate {marker.data.fromHz} Hz → {marker.data.toHz} Hz: {marker.data.reason}");
+    const TABLE_LABEL: Option<&'static str> =
+        Some("{marker.data.fromHz} Hz → {marker.data.toHz} Hz: {marker.data.reason}");
+
+    const DESCRIPTION: Option<&'static str> =
+        Some("Emitted when the recorder changes the sampling frequency.");
+
+    const FIELDS: Schema<Self::FieldsType> = Schema((
+        MarkerField::integer("fromHz", "From (Hz)"),
+        MarkerField::integer("toHz", "To (Hz)"),
+        MarkerField::string("reason", "Reason"),
+    ));
+
+    fn name(&self, profile: &mut Profile) -> StringHandle {
+        profile.handle_for_string("Sampling rate")
+    }
+
+    fn field_values(&self) -> (f64, f64, StringHandle) {
+        (self.from_hz as f64, self.to_hz as f64, self.reason)
+    }
+}
+
+/// An annotation from `samply record --stdin-commands`, named by the script
+/// which sent it. `kind` is "mark" for an instant and "phase" for an interval.
+#[derive(Debug, Clone)]
+pub struct AnnotationMarker {
+    pub name: StringHandle,
+    pub kind: StringHandle,
+}
+
+impl Marker for AnnotationMarker {
+    type FieldsType = StringHandle;
+
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "Annotation";
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.name}");
+    const TOOLTIP_LABEL: Option<&'static str> = Some("{marker.data.kind}: {marker.name}");
+    const TABLE_LABEL: Option<&'static str> = Some("{marker.data.kind}: {marker.name}");
+
+    const DESCRIPTION: Option<&'static str> =
+        Some("Emitted for the mark and phase commands on samply's stdin.");
+
+    const FIELDS: Schema<Self::FieldsType> = Schema(MarkerField::string("kind", "Kind"));
+
+    fn name(&self, _profile: &mut Profile) -> StringHandle {
+        self.name
+    }
+
+    fn field_values(&self) -> StringHandle {
+        self.kind
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct OtherEventMarker(pub StringHandle);
 
@@ -285,3 +437,39 @@ impl Marker for SimpleMarker {
         self.0
     }
 }
+
+#[derive(Debug, Clone)]
+pub struct TraceSpanMarker {
+    pub name: StringHandle,
+    pub trace_id: StringHandle,
+    pub span_id: StringHandle,
+}
+
+impl Marker for TraceSpanMarker {
+    type FieldsType = (StringHandle, StringHandle, StringHandle);
+
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "TraceSpan";
+
+    const DESCRIPTION: Option<&'static str> =
+        Some("Emitted for marker spans in a markers text file which carry a W3C traceparent.");
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.data.name}");
+    const TOOLTIP_LABEL: Option<&'static str> =
+        Some("{marker.data.name} (trace {marker.data.traceId})");
+    const TABLE_LABEL: Option<&'static str> =
+        Some("{marker.data.name}, trace {marker.data.traceId}, span {marker.data.spanId}");
+
+    const FIELDS: Schema<Self::FieldsType> = Schema((
+        MarkerField::string("name", "Name"),
+        MarkerField::string("traceId", "Trace ID"),
+        MarkerField::string("spanId", "Span ID"),
+    ));
+
+    fn name(&self, _profile: &mut Profile) -> StringHandle {
+        self.name
+    }
+
+    fn field_values(&self) -> (StringHandle, StringHandle, StringHandle) {
+        (self.name, self.trace_id, self.span_id)
+    }
+}
@@ -0,0 +1,144 @@
+//! Progress reporting for the long steps of `import` and `record`: reading
+//! the events of a perf.data or ETL file, and presymbolication. Progress is
+//! shown as a bar on stderr if stderr is a terminal, or as JSON lines on
+//! stderr for wrapper tools with `--progress json`, or not at all with
+//! `--quiet`.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use serde_json::json;
+
+/// How progress is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressMode {
+    Bar,
+    Json,
+    Quiet,
+}
+
+static MODE: OnceLock<ProgressMode> = OnceLock::new();
+
+/// How often a JSON progress line is printed, at most.
+const JSON_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Set the mode for the rest of the process, from the command line. Without
+/// a call, progress is shown as a bar.
+pub fn set_progress_mode(mode: ProgressMode) {
+    let _ = MODE.set(mode);
+}
+
+fn progress_mode() -> ProgressMode {
+    MODE.get().copied().unwrap_or(ProgressMode::Bar)
+}
+
+/// The progress of one step. Clones report to the same step, so that
+/// concurrent tasks can share it.
+#[derive(Clone)]
+pub struct Progress(Arc<ProgressInner>);
+
+struct ProgressInner {
+    /// E.g. "import" or "symbolicate"
+    task: &'static str,
+    /// What is counted, e.g. "events" or "libraries"
+    unit: &'static str,
+    total: Option<u64>,
+    done: AtomicU64,
+    start: Instant,
+    last_json_line: Mutex<Instant>,
+    bar: Option<ProgressBar>,
+}
+
+impl Progress {
+    /// Start a step which counts `unit`s, `total` of them if the total is known.
+    pub fn new(task: &'static str, unit: &'static str, total: Option<u64>) -> Self {
+        let mode = progress_mode();
+        let bar = (mode == ProgressMode::Bar).then(|| {
+            // Hidden if stderr isn't a terminal.
+            let target = ProgressDrawTarget::stderr();
+            let (bar, template) = match total {
+                Some(total) => (
+                    ProgressBar::with_draw_target(Some(total), target),
+                    "{msg} [{bar:30}] {human_pos}/{human_len} {prefix} ({eta} left)",
+                ),
+                None => (
+                    ProgressBar::with_draw_target(None, target),
+                    "{spinner} {msg} {human_pos} {prefix} ({elapsed})",
+                ),
+            };
+            let style = ProgressStyle::with_template(template)
+                .expect("valid template")
+                .progress_chars("=> ");
+            bar.set_style(style);
+            bar.set_message(task);
+            bar.set_prefix(unit);
+            if total.is_none() {
+                bar.enable_steady_tick(Duration::from_millis(100));
+            }
+            bar
+        });
+        let start = Instant::now();
+        let progress = Progress(Arc::new(ProgressInner {
+            task,
+            unit,
+            total,
+            done: AtomicU64::new(0),
+            start,
+            last_json_line: Mutex::new(start),
+            bar,
+        }));
+        if mode == ProgressMode::Json {
+            progress.print_json_line(0, false);
+        }
+        progress
+    }
+
+    /// Count `n` more units as done.
+    pub fn inc(&self, n: u64) {
+        let done = self.0.done.fetch_add(n, Ordering::Relaxed) + n;
+        if let Some(bar) = &self.0.bar {
+            bar.inc(n);
+        } else if progress_mode() == ProgressMode::Json {
+            let now = Instant::now();
+            let mut last_json_line = self.0.last_json_line.lock().unwrap();
+            if now.duration_since(*last_json_line) >= JSON_INTERVAL {
+                *last_json_line = now;
+                drop(last_json_line);
+                self.print_json_line(done, false);
+            }
+        }
+    }
+
+    /// End the step: clear the bar, or print the last JSON line.
+    pub fn finish(&self) {
+        if let Some(bar) = &self.0.bar {
+            bar.finish_and_clear();
+        } else if progress_mode() == ProgressMode::Json {
+            self.print_json_line(self.0.done.load(Ordering::Relaxed), true);
+        }
+    }
+
+    fn print_json_line(&self, done: u64, finished: bool) {
+        let elapsed = self.0.start.elapsed().as_secs_f64();
+        let eta_seconds = match self.0.total {
+            Some(total) if done > 0 && !finished => {
+                Some(elapsed * total.saturating_sub(done) as f64 / done as f64)
+            }
+            _ => None,
+        };
+        eprintln!(
+            "{}",
+            json!({
+                "task": self.0.task,
+                "unit": self.0.unit,
+                "done": done,
+                "total": self.0.total,
+                "elapsed_seconds": elapsed,
+                "eta_seconds": eta_seconds,
+                "finished": finished,
+            })
+        );
+    }
+}
@@ -1,10 +1,14 @@
 use std::ffi::OsString;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
 use super::included_processes::IncludedProcesses;
+use super::included_threads::IncludedThreads;
+use super::live_profile::LiveProfile;
+use super::process_name::NameRules;
 
 #[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 pub struct CoreClrProfileProps {
@@ -31,7 +35,12 @@ impl CoreClrProfileProps {
 pub struct RecordingProps {
     #[allow(dead_code)] // Used on Windows to determine etl file paths
     pub output_file: PathBuf,
+    /// Stop recording after this long, not counting the delay.
     pub time_limit: Option<Duration>,
+    /// Drop the samples of this first part of the recording.
+    pub delay: Option<Duration>,
+    /// Only keep the samples of this last part of the recording.
+    pub ring_buffer: Option<Duration>,
     pub interval: Duration,
     #[allow(dead_code)]
     pub vm_hack: bool,
@@ -41,6 +50,37 @@ pub struct RecordingProps {
     pub browsers: bool,
     #[allow(dead_code)]
     pub keep_etl: bool,
+    /// Enable the CLR providers and ask for a rundown of the .NET methods and
+    /// modules at the end of the trace.
+    #[allow(dead_code)] // Only used on Windows
+    pub clr: bool,
+    /// Record the sched_switch tracepoint for stacks of blocked time.
+    #[allow(dead_code)] // Only used on Linux
+    pub off_cpu: bool,
+    /// Record the stack and size of each malloc call.
+    #[allow(dead_code)] // Only used on Linux
+    pub alloc: bool,
+    /// Lower the sampling frequency while the recording can't keep up, and
+    /// raise it again once it can.
+    #[allow(dead_code)] // Only used on Linux
+    pub adaptive_rate: bool,
+    /// Read `mark`, `phase` and `stop` commands from stdin while recording.
+    #[allow(dead_code)] // Only used on Linux
+    pub stdin_commands: bool,
+    /// Hardware events to sample along with the CPU samples, by their names
+    /// in `samply_analysis::HARDWARE_EVENTS`.
+    #[allow(dead_code)] // Only used on Linux
+    pub events: Vec<String>,
+    /// Ask the JIT runtimes in the launched command to write perf maps and
+    /// jitdump files.
+    #[allow(dead_code)] // Only used on Linux
+    pub jit: bool,
+    /// Which threads the sampler samples, by name.
+    pub included_threads: IncludedThreads,
+    /// Where the sampler publishes snapshots of the profile while recording,
+    /// for `--live`.
+    #[allow(dead_code)] // Only used on Linux
+    pub live: Option<Arc<LiveProfile>>,
 }
 
 /// Which process(es) to record.
@@ -54,6 +94,15 @@ pub enum RecordingMode {
     Launch(ProcessLaunchProps),
 }
 
+impl RecordingProps {
+    /// How long after its start the recording stops: the delay plus the
+    /// time limit, if there is a time limit.
+    pub fn stop_after(&self) -> Option<Duration> {
+        let time_limit = self.time_limit?;
+        Some(self.delay.unwrap_or_default() + time_limit)
+    }
+}
+
 impl RecordingMode {
     #[allow(dead_code)]
     pub fn is_attach_mode(&self) -> bool {
@@ -84,6 +133,8 @@ pub struct ProfileCreationProps {
     pub create_per_cpu_threads: bool,
     /// Include up to N command line arguments in the process name
     pub arg_count_to_include_in_process_name: usize,
+    /// Rules for the names of processes and threads
+    pub name_rules: NameRules,
     /// Override system architecture.
     #[allow(dead_code)]
     pub override_arch: Option<String>,
@@ -132,6 +183,24 @@ pub struct ImportProps {
     pub user_etl: Vec<PathBuf>,
     #[allow(unused)] // todo: respect when converting perf.data
     pub time_range: Option<(std::time::Duration, std::time::Duration)>,
+    /// The samples which an ETL trace should have (`--wpr-profile`)
+    #[allow(unused)] // Windows-only
+    pub etl_sample_source: Option<EtlSampleSource>,
+    /// Resolve the stacks of an ETL trace recorded with stack caching
+    #[allow(unused)] // Windows-only
+    pub etl_stack_caching: bool,
+}
+
+/// Which events the samples of an ETL trace are made from, depending on the
+/// WPR or xperf profile which recorded it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EtlSampleSource {
+    /// Timer-based CPU samples (the PROFILE kernel flag)
+    Sampled,
+    /// Context switches only (CSWITCH), e.g. to see where threads wait
+    ContextSwitches,
+    /// Both, e.g. WPR's CPU profile
+    Mixed,
 }
 
 #[derive(Debug, Clone)]
@@ -150,4 +219,14 @@ pub struct SymbolProps {
     pub breakpad_symbol_cache: Option<PathBuf>,
     /// Extra directory containing symbol files, with the directory structure used by simpleperf's scripts
     pub simpleperf_binary_cache: Option<PathBuf>,
+    /// How many libraries presymbolication loads the symbols of at the same time
+    pub symbol_jobs: usize,
+    /// How often presymbolication retries a library whose symbols couldn't be fetched
+    pub symbol_retries: u32,
+    /// How long presymbolication waits for the symbols of a library, per attempt
+    pub symbol_timeout: Duration,
+    /// Whether to only use local files and caches, and never symbol servers
+    pub offline: bool,
+    /// The most requests to send to each symbol server
+    pub max_symbol_requests: Option<u32>,
 }
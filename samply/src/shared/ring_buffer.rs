@@ -0,0 +1,36 @@
+//! The ring buffer mode of `samply record --ring-buffer-seconds N`: only the
+//! samples of the last N seconds are kept, so that a long-running process can
+//! be recorded until a rare stall happens, without the profile growing.
+//!
+//! While recording, the samplers drop buffered samples once they are older
+//! than N seconds, which bounds memory. When the profile is written, the
+//! `keep-last` transform trims the samples, markers and counters of the
+//! finished profile to the same window. On Windows, where the samples are
+//! only converted from the trace after recording, that trimming is all there is.
+
+use std::time::Duration;
+
+pub struct RingBuffer {
+    duration_ns: u64,
+    last_drop_ns: u64,
+}
+
+impl RingBuffer {
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            duration_ns: duration.as_nanos() as u64,
+            last_drop_ns: 0,
+        }
+    }
+
+    /// The monotonic timestamp before which samples should be dropped at
+    /// `now_ns`, if they should be dropped now. Dropping is a pass over all
+    /// buffered samples, so it's only done once per tenth of the window.
+    pub fn drop_before(&mut self, now_ns: u64) -> Option<u64> {
+        if now_ns < self.last_drop_ns + self.duration_ns / 10 {
+            return None;
+        }
+        self.last_drop_ns = now_ns;
+        Some(now_ns.saturating_sub(self.duration_ns))
+    }
+}
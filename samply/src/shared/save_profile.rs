@@ -5,12 +5,41 @@ use std::path::Path;
 
 use flate2::{Compression, GzBuilder};
 use fxprof_processed_profile::Profile;
+use serde::Serialize;
+
+use super::transform::{apply_transforms, ProfileTransform};
 
 // Level two has an acceptable trade-off between how long compression
 // takes and how much data it saves on the profile JSONs I tested with.
 const GZIP_COMPRESSION_LEVEL: u32 = 2;
 
 pub fn save_profile_to_file(profile: &Profile, output_path: &Path) -> std::io::Result<()> {
+    write_json_to_file(profile, output_path)
+}
+
+/// Like [`save_profile_to_file`], but runs the profile through the transforms first.
+pub fn save_transformed_profile_to_file(
+    profile: &Profile,
+    transforms: &[Box<dyn ProfileTransform>],
+    output_path: &Path,
+) -> std::io::Result<()> {
+    if transforms.is_empty() {
+        return save_profile_to_file(profile, output_path);
+    }
+    let mut profile_json = serde_json::to_value(profile)?;
+    apply_transforms(&mut profile_json, transforms);
+    write_json_to_file(&profile_json, output_path)
+}
+
+/// Save a profile which has been modified as processed profile JSON.
+pub fn save_profile_json_to_file(
+    profile_json: &serde_json::Value,
+    output_path: &Path,
+) -> std::io::Result<()> {
+    write_json_to_file(profile_json, output_path)
+}
+
+fn write_json_to_file<T: Serialize + ?Sized>(value: &T, output_path: &Path) -> std::io::Result<()> {
     let output_file = match File::create(output_path) {
         Ok(output_file) => output_file,
         Err(err) => {
@@ -26,9 +55,9 @@ pub fn save_profile_to_file(profile: &Profile, output_path: &Path) -> std::io::R
         let builder = GzBuilder::new().filename(name_without_gz.as_bytes());
         let gz = builder.write(writer, Compression::new(GZIP_COMPRESSION_LEVEL));
         let gz = BufWriter::new(gz);
-        serde_json::to_writer(gz, &profile)?;
+        serde_json::to_writer(gz, value)?;
     } else {
-        serde_json::to_writer(writer, &profile)?;
+        serde_json::to_writer(writer, value)?;
     }
     Ok(())
 }
@@ -1,12 +1,55 @@
+//! Symbols for the libraries of a profile from a sidecar file next to it,
+//! e.g. for profiles whose binaries aren't around where they're served.
+//!
+//! Sidecars come in two formats. `profile.syms.json` is JSON, which is parsed
+//! in full when it's loaded. `profile.syms.bin` (v2) is memory-mapped, and a
+//! lookup only reads the entries it needs, so that sidecars of hundreds of MB
+//! don't take long to load; `samply-for-ai symbols convert-sidecar` converts
+//! JSON sidecars to it. If both are there, `.syms.bin` is used.
+//!
+//! In both formats, the file of a frame is its raw path from the debug info,
+//! or its special path (e.g. `git:github.com/rust-lang/rust:library/core/src/
+//! num/mod.rs:<rev>`) if it has one, as in Breakpad `.sym` files, so that the
+//! symbolication API answers with the same paths as from the debug info.
+//!
+//! The v2 format is little-endian:
+//!
+//! ```text
+//! header        magic "SYMSBIN\0", version: u32 = 2, library count: u32,
+//!               string table offset: u64
+//! libraries     per library: debug name, debug ID and code ID string indexes,
+//!               symbol, address and frame counts: u32; symbol, address and
+//!               frame table offsets: u64
+//! symbols       per symbol, sorted by rva: rva, size, name string index,
+//!               first frame index, frame count: u32
+//! addresses     per known address, sorted by rva: rva, symbol index: u32
+//! frames        per frame, innermost first: function and file string
+//!               indexes, line: u32
+//! strings       string count: u32, 4 bytes of padding, count + 1 offsets
+//!               into the arena: u64, the arena of UTF-8 strings
+//! ```
+//!
+//! Each library has its own symbol, address and frame tables. `0xffffffff`
+//! stands for an absent size, frame list, function, file or line.
+//!
+//! Addresses which aren't known addresses of the sidecar, e.g. because the
+//! code moved a little since it was written, get the symbol whose range
+//! contains them: the last symbol before them, within its size if it has one.
+//! Such lookups are approximate, and come without frames, since the inlined
+//! functions and lines of the sidecar are those of the known addresses.
+
+use std::io::Write;
 use std::path::Path;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::{borrow::Cow, fs::File};
 
+use memmap2::Mmap;
 use serde::{de::Deserializer, Deserialize};
 use wholesym::{
-    FunctionNameIndex, SourceFilePath, SourceFilePathHandle, SourceFilePathIndex,
-    SymbolMapGeneration, SymbolNameIndex,
+    FunctionNameIndex, MappedPath, SourceFilePath, SourceFilePathHandle, SourceFilePathIndex,
+    SymbolManager, SymbolMapGeneration, SymbolNameIndex,
 };
 
 #[derive(Debug, Copy, Clone, PartialOrd, Ord, PartialEq, Eq, Hash)]
@@ -115,26 +158,157 @@ pub struct PrecogSymbolInfo {
     string_table: StringTable,
 }
 
+/// The symbols of a library, from either sidecar format
+enum LibraryData {
+    /// From a `.syms.json` sidecar, parsed up front
+    Json {
+        data: PrecogLibrarySymbolData,
+        string_table: Arc<StringTable>,
+        /// Indexes into `data.symbol_table`, sorted by rva
+        symbols_by_rva: Vec<usize>,
+    },
+    /// From a `.syms.bin` sidecar, read from the mapped file when looked up
+    Mapped(MappedLibrary),
+}
+
+impl LibraryData {
+    fn debug_name(&self) -> Cow<'_, str> {
+        match self {
+            LibraryData::Json { data, .. } => Cow::Borrowed(&data.debug_name),
+            LibraryData::Mapped(lib) => lib.string(StringTableIndex(lib.entry.debug_name)),
+        }
+    }
+
+    fn debug_id(&self) -> Cow<'_, str> {
+        match self {
+            LibraryData::Json { data, .. } => Cow::Borrowed(&data.debug_id),
+            LibraryData::Mapped(lib) => lib.string(StringTableIndex(lib.entry.debug_id)),
+        }
+    }
+
+    fn code_id(&self) -> Cow<'_, str> {
+        match self {
+            LibraryData::Json { data, .. } => Cow::Borrowed(&data.code_id),
+            LibraryData::Mapped(lib) => lib.string(StringTableIndex(lib.entry.code_id)),
+        }
+    }
+
+    fn string(&self, index: StringTableIndex) -> Cow<'_, str> {
+        match self {
+            LibraryData::Json { string_table, .. } => Cow::Borrowed(string_table.get(index)),
+            LibraryData::Mapped(lib) => lib.string(index),
+        }
+    }
+
+    fn address_count(&self) -> usize {
+        match self {
+            LibraryData::Json { data, .. } => data.known_addresses.len(),
+            LibraryData::Mapped(lib) => lib.entry.address_count as usize,
+        }
+    }
+
+    /// The address and name of each symbol
+    fn symbols(&self) -> Box<dyn Iterator<Item = (u32, StringTableIndex)> + '_> {
+        match self {
+            LibraryData::Json { data, .. } => {
+                Box::new(data.symbol_table.iter().map(|info| (info.rva, info.symbol)))
+            }
+            LibraryData::Mapped(lib) => Box::new(
+                (0..lib.entry.symbol_count)
+                    .filter_map(|index| lib.symbol(index))
+                    .map(|info| (info.rva, info.symbol)),
+            ),
+        }
+    }
+
+    /// The symbol of a known address
+    fn known_symbol(&self, rva: u32) -> Option<Cow<'_, InternedSymbolInfo>> {
+        match self {
+            LibraryData::Json { data, .. } => {
+                let entry_index = data
+                    .known_addresses
+                    .binary_search_by_key(&rva, |ka| ka.0)
+                    .ok()?;
+                let sym_index = data.known_addresses[entry_index].1;
+                //eprintln!("lookup_sync: {:#x} -> {}", rva, sym_index);
+                data.symbol_table.get(sym_index).map(Cow::Borrowed)
+            }
+            LibraryData::Mapped(lib) => lib.symbol_for_address(rva).map(Cow::Owned),
+        }
+    }
+
+    /// The last symbol which starts at or before `rva`
+    fn nearest_symbol(&self, rva: u32) -> Option<Cow<'_, InternedSymbolInfo>> {
+        match self {
+            LibraryData::Json {
+                data,
+                symbols_by_rva,
+                ..
+            } => {
+                let end =
+                    symbols_by_rva.partition_point(|&index| data.symbol_table[index].rva <= rva);
+                let index = symbols_by_rva[end.checked_sub(1)?];
+                Some(Cow::Borrowed(&data.symbol_table[index]))
+            }
+            LibraryData::Mapped(lib) => lib.nearest_symbol(rva).map(Cow::Owned),
+        }
+    }
+
+    /// The symbol of a known address, or else the symbol whose range
+    /// contains `rva`, which is approximate
+    fn symbol_for_address(&self, rva: u32) -> Option<SymbolLookup<'_>> {
+        if let Some(info) = self.known_symbol(rva) {
+            return Some(SymbolLookup {
+                info,
+                approximate: false,
+            });
+        }
+        let info = self.nearest_symbol(rva)?;
+        let contains = info
+            .size
+            .is_none_or(|size| u64::from(rva) < u64::from(info.rva) + u64::from(size));
+        contains.then_some(SymbolLookup {
+            info,
+            approximate: true,
+        })
+    }
+}
+
+/// The symbol found for an address
+struct SymbolLookup<'a> {
+    info: Cow<'a, InternedSymbolInfo>,
+    /// Whether the address isn't a known address, but within the symbol's
+    /// range
+    approximate: bool,
+}
+
 pub struct PrecogLibraySymbolMap {
-    data: PrecogLibrarySymbolData,
-    string_table: Arc<StringTable>,
+    data: LibraryData,
     generation: SymbolMapGeneration,
+    /// How many lookups fell back to the symbol containing the address
+    approximate_lookups: AtomicUsize,
 }
 
 impl PrecogLibraySymbolMap {
-    fn new(data: PrecogLibrarySymbolData, string_table: Arc<StringTable>) -> Self {
+    fn new(data: LibraryData) -> Self {
         Self {
             data,
-            string_table,
             generation: SymbolMapGeneration::new(),
+            approximate_lookups: AtomicUsize::new(0),
         }
     }
 
+    /// How many addresses were looked up which aren't known addresses of the
+    /// sidecar, and got the symbol which contains them
+    pub fn approximate_lookups(&self) -> usize {
+        self.approximate_lookups.load(Ordering::Relaxed)
+    }
+
     pub fn library_info(&self) -> wholesym::LibraryInfo {
         wholesym::LibraryInfo {
-            debug_name: Some(self.data.debug_name.clone()),
-            debug_id: Some(debugid::DebugId::from_str(&self.data.debug_id).unwrap()),
-            code_id: wholesym::CodeId::from_str(&self.data.code_id).ok(),
+            debug_name: Some(self.data.debug_name().into_owned()),
+            debug_id: debugid::DebugId::from_str(&self.data.debug_id()).ok(),
+            code_id: wholesym::CodeId::from_str(&self.data.code_id()).ok(),
             ..wholesym::LibraryInfo::default()
         }
     }
@@ -142,21 +316,19 @@ impl PrecogLibraySymbolMap {
 
 impl wholesym::samply_symbols::SymbolMapTrait for PrecogLibraySymbolMap {
     fn debug_id(&self) -> debugid::DebugId {
-        debugid::DebugId::from_str(&self.data.debug_id).expect("bad debugid")
+        debugid::DebugId::from_str(&self.data.debug_id()).expect("bad debugid")
     }
 
     fn symbol_count(&self) -> usize {
         // not correct but maybe it's OK
-        self.data.known_addresses.len()
+        self.data.address_count()
     }
 
     fn iter_symbols(&self) -> Box<dyn Iterator<Item = (u32, std::borrow::Cow<'_, str>)> + '_> {
-        let iter = self.data.symbol_table.iter().map(move |info| {
-            (
-                info.rva,
-                std::borrow::Cow::Borrowed(self.string_table.get(info.symbol)),
-            )
-        });
+        let iter = self
+            .data
+            .symbols()
+            .map(move |(rva, symbol)| (rva, self.data.string(symbol)));
 
         Box::new(iter)
     }
@@ -164,23 +336,17 @@ impl wholesym::samply_symbols::SymbolMapTrait for PrecogLibraySymbolMap {
     fn lookup_sync(&self, address: wholesym::LookupAddress) -> Option<wholesym::SyncAddressInfo> {
         match address {
             wholesym::LookupAddress::Relative(rva) => {
-                let Ok(entry_index) = self
-                    .data
-                    .known_addresses
-                    .binary_search_by_key(&rva, |ka| ka.0)
-                else {
-                    return None;
-                };
-                let sym_index = self.data.known_addresses[entry_index].1;
-                //eprintln!("lookup_sync: {:#x} -> {}", rva, sym_index);
-                let info = &self.data.symbol_table[sym_index];
+                let SymbolLookup { info, approximate } = self.data.symbol_for_address(rva)?;
+                if approximate {
+                    self.approximate_lookups.fetch_add(1, Ordering::Relaxed);
+                }
                 Some(wholesym::SyncAddressInfo {
                     symbol: wholesym::SymbolInfo {
                         address: info.rva,
                         size: info.size,
                         name: self.generation.symbol_name_handle(info.symbol.into()),
                     },
-                    frames: info.frames.as_ref().map(|frames| {
+                    frames: info.frames.as_ref().filter(|_| !approximate).map(|frames| {
                         wholesym::FramesLookupResult::Available(
                             frames
                                 .iter()
@@ -205,20 +371,22 @@ impl wholesym::samply_symbols::SymbolMapTrait for PrecogLibraySymbolMap {
 
     fn resolve_function_name(&self, handle: wholesym::FunctionNameHandle) -> Cow<'_, str> {
         let index = self.generation.unwrap_function_name_index(handle).into();
-        let s = self.string_table.get(index);
-        Cow::Borrowed(s)
+        self.data.string(index)
     }
 
     fn resolve_symbol_name(&self, handle: wholesym::SymbolNameHandle) -> Cow<'_, str> {
         let index = self.generation.unwrap_symbol_name_index(handle).into();
-        let s = self.string_table.get(index);
-        Cow::Borrowed(s)
+        self.data.string(index)
     }
 
     fn resolve_source_file_path(&self, handle: SourceFilePathHandle) -> SourceFilePath<'_> {
         let index = self.generation.unwrap_source_file_index(handle).into();
-        let s = self.string_table.get(index);
-        SourceFilePath::RawPath(Cow::Borrowed(s))
+        let path = self.data.string(index);
+        if MappedPath::from_special_path_str(&path).is_some() {
+            SourceFilePath::BreakpadSpecialPathStr(path)
+        } else {
+            SourceFilePath::RawPath(path)
+        }
     }
 }
 
@@ -229,16 +397,838 @@ impl std::fmt::Debug for PrecogSymbolInfo {
 }
 
 impl PrecogSymbolInfo {
-    pub fn try_load(path: &Path) -> Option<Self> {
-        let file = File::open(path).ok()?;
+    /// Load a `.syms.json` sidecar. `Ok(None)` if there's no file at `path`.
+    pub fn try_load(path: &Path) -> Result<Option<Self>, String> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.to_string()),
+        };
         let reader = std::io::BufReader::new(file);
-        serde_json::from_reader(reader).expect("failed to parse sidecar syms.json")
+        serde_json::from_reader(reader).map_err(|err| err.to_string())
     }
 
     pub fn into_iter(self) -> impl Iterator<Item = PrecogLibraySymbolMap> {
         let Self { data, string_table } = self;
         let string_table = Arc::new(string_table);
-        data.into_iter()
-            .map(move |lib_data| PrecogLibraySymbolMap::new(lib_data, string_table.clone()))
+        data.into_iter().map(move |data| {
+            let mut symbols_by_rva: Vec<usize> = (0..data.symbol_table.len()).collect();
+            symbols_by_rva.sort_by_key(|&index| data.symbol_table[index].rva);
+            PrecogLibraySymbolMap::new(LibraryData::Json {
+                data,
+                string_table: string_table.clone(),
+                symbols_by_rva,
+            })
+        })
+    }
+
+    /// Write the sidecar in the v2 format. The debug names, debug IDs and
+    /// code IDs are added to the string table, and the symbols are sorted
+    /// by rva.
+    pub fn write_v2(&self, mut writer: impl Write) -> std::io::Result<()> {
+        let mut strings: Vec<&str> = self
+            .string_table
+            .strings
+            .iter()
+            .map(String::as_str)
+            .collect();
+        let mut add_string = |s| {
+            strings.push(s);
+            (strings.len() - 1) as u32
+        };
+
+        // The library table, with the offsets of the tables after it
+        let mut offset = (V2_HEADER_SIZE + self.data.len() * V2_LIBRARY_SIZE) as u64;
+        let mut libraries = Vec::new();
+        for lib in &self.data {
+            let frame_count: usize = lib
+                .symbol_table
+                .iter()
+                .map(|info| info.frames.as_ref().map_or(0, Vec::len))
+                .sum();
+            let symbols = offset;
+            offset += (lib.symbol_table.len() * V2_SYMBOL_SIZE) as u64;
+            let addresses = offset;
+            offset += (lib.known_addresses.len() * V2_ADDRESS_SIZE) as u64;
+            let frames = offset;
+            offset += (frame_count * V2_FRAME_SIZE) as u64;
+            libraries.push((
+                [
+                    add_string(&lib.debug_name),
+                    add_string(&lib.debug_id),
+                    add_string(&lib.code_id),
+                    lib.symbol_table.len() as u32,
+                    lib.known_addresses.len() as u32,
+                    frame_count as u32,
+                ],
+                [symbols, addresses, frames],
+            ));
+        }
+
+        writer.write_all(V2_MAGIC)?;
+        writer.write_all(&V2_VERSION.to_le_bytes())?;
+        writer.write_all(&(self.data.len() as u32).to_le_bytes())?;
+        writer.write_all(&offset.to_le_bytes())?;
+        for (fields, offsets) in &libraries {
+            for field in fields {
+                writer.write_all(&field.to_le_bytes())?;
+            }
+            for offset in offsets {
+                writer.write_all(&offset.to_le_bytes())?;
+            }
+        }
+
+        for lib in &self.data {
+            let mut symbols: Vec<(usize, &InternedSymbolInfo)> =
+                lib.symbol_table.iter().enumerate().collect();
+            symbols.sort_by_key(|(_, info)| info.rva);
+            // The index of each symbol in the sorted table
+            let mut sorted_indexes = vec![0; symbols.len()];
+            for (sorted_index, (index, _)) in symbols.iter().enumerate() {
+                sorted_indexes[*index] = sorted_index as u32;
+            }
+
+            let mut first_frame = 0;
+            for (_, info) in &symbols {
+                let (frames_start, frame_count) = match &info.frames {
+                    Some(frames) => {
+                        let start = first_frame;
+                        first_frame += frames.len() as u32;
+                        (start, frames.len() as u32)
+                    }
+                    None => (V2_NONE, 0),
+                };
+                for field in [
+                    info.rva,
+                    info.size.unwrap_or(V2_NONE),
+                    info.symbol.0,
+                    frames_start,
+                    frame_count,
+                ] {
+                    writer.write_all(&field.to_le_bytes())?;
+                }
+            }
+            let mut known_addresses = lib.known_addresses.clone();
+            known_addresses.sort_unstable();
+            for (rva, symbol_index) in known_addresses {
+                let symbol_index = sorted_indexes.get(symbol_index).copied().unwrap_or(V2_NONE);
+                writer.write_all(&rva.to_le_bytes())?;
+                writer.write_all(&symbol_index.to_le_bytes())?;
+            }
+            for frame in symbols
+                .iter()
+                .flat_map(|(_, info)| info.frames.iter().flatten())
+            {
+                for field in [
+                    frame.function.map_or(V2_NONE, |index| index.0),
+                    frame.file.map_or(V2_NONE, |index| index.0),
+                    frame.line.unwrap_or(V2_NONE),
+                ] {
+                    writer.write_all(&field.to_le_bytes())?;
+                }
+            }
+        }
+
+        writer.write_all(&(strings.len() as u32).to_le_bytes())?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        let mut string_offset = 0u64;
+        writer.write_all(&string_offset.to_le_bytes())?;
+        for s in &strings {
+            string_offset += s.len() as u64;
+            writer.write_all(&string_offset.to_le_bytes())?;
+        }
+        for s in &strings {
+            writer.write_all(s.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// Load the sidecar next to `profile_path`, if there is one, and register its
+/// symbol maps with the symbol manager: `.syms.bin`, or else `.syms.json`.
+/// Returns the symbol maps of the libraries which are covered by the sidecar.
+///
+/// A sidecar which can't be loaded is skipped with a warning, which lists
+/// the libraries which are left without its symbols: those in the corrupt
+/// part of a `.syms.bin`, or else all of `profile_libraries`, the debug names
+/// of the profile's libraries. With `require`, that's an error instead, and
+/// so is a missing sidecar.
+pub fn add_sidecar_symbols(
+    symbol_manager: &mut SymbolManager,
+    profile_path: &Path,
+    profile_libraries: &[String],
+    require: bool,
+) -> Result<Vec<Arc<PrecogLibraySymbolMap>>, String> {
+    let v2_path = profile_path.with_extension("syms.bin");
+    let json_path = profile_path.with_extension("syms.json");
+    let mut errors = Vec::new();
+    let mut lost_libraries = Vec::new();
+    let mut symbol_maps = None;
+    if v2_path.exists() {
+        match map_v2(&v2_path) {
+            Ok(mapped) => {
+                if !mapped.corrupt_libraries.is_empty() {
+                    errors.push(format!(
+                        "{v2_path:?}: the tables of {} libraries are out of bounds",
+                        mapped.corrupt_libraries.len()
+                    ));
+                }
+                lost_libraries = mapped.corrupt_libraries;
+                symbol_maps = Some(mapped.symbol_maps);
+            }
+            Err(err) => errors.push(format!("{v2_path:?}: {err}")),
+        }
+    }
+    if symbol_maps.is_none() {
+        match PrecogSymbolInfo::try_load(&json_path) {
+            Ok(Some(precog_info)) => symbol_maps = Some(precog_info.into_iter().collect()),
+            Ok(None) => {}
+            Err(err) => errors.push(format!("{json_path:?}: {err}")),
+        }
+        if symbol_maps.is_none() && !errors.is_empty() {
+            lost_libraries = profile_libraries.to_vec();
+        }
+    }
+
+    if require {
+        if !errors.is_empty() {
+            return Err(format!(
+                "Couldn't load the symbols sidecar {}",
+                errors.join("; ")
+            ));
+        }
+        if symbol_maps.is_none() {
+            return Err(format!("There's no symbols sidecar at {json_path:?}"));
+        }
+    }
+    for error in &errors {
+        eprintln!("Warning: Ignoring the symbols sidecar {error}");
+    }
+    if !lost_libraries.is_empty() {
+        lost_libraries.sort_unstable();
+        lost_libraries.dedup();
+        eprintln!(
+            "These libraries have no sidecar symbols: {}",
+            lost_libraries.join(", ")
+        );
+    }
+
+    let mut added = Vec::new();
+    for symbol_map in symbol_maps.unwrap_or_default() {
+        let lib_info = symbol_map.library_info();
+        let symbol_map = Arc::new(symbol_map);
+        if lib_info.debug_id.is_some() {
+            added.push(symbol_map.clone());
+        }
+        symbol_manager.add_known_library_symbols(lib_info, symbol_map);
+    }
+    Ok(added)
+}
+
+// ============================================================================
+// Reading the v2 format
+// ============================================================================
+
+/// The magic bytes at the start of a `.syms.bin` sidecar
+const V2_MAGIC: &[u8; 8] = b"SYMSBIN\0";
+const V2_VERSION: u32 = 2;
+
+/// Magic, version, library count and string table offset
+const V2_HEADER_SIZE: usize = 24;
+/// Debug name, debug ID and code ID, the symbol, address and frame counts,
+/// and the offsets of the symbol, address and frame tables
+const V2_LIBRARY_SIZE: usize = 48;
+/// RVA, size, name, first frame and frame count
+const V2_SYMBOL_SIZE: usize = 20;
+/// RVA and symbol index
+const V2_ADDRESS_SIZE: usize = 8;
+/// Function, file and line
+const V2_FRAME_SIZE: usize = 12;
+/// An absent size, frame list, function, file or line
+const V2_NONE: u32 = u32::MAX;
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+fn optional(value: u32) -> Option<u32> {
+    (value != V2_NONE).then_some(value)
+}
+
+/// A mapped `.syms.bin` sidecar, whose tables are known to be within the file
+struct MappedSidecar {
+    mmap: Mmap,
+    string_count: u32,
+    /// The offset of the string offsets
+    string_offsets: usize,
+    /// The offset of the string arena
+    arena: usize,
+}
+
+impl MappedSidecar {
+    fn string(&self, index: StringTableIndex) -> Cow<'_, str> {
+        if index.0 >= self.string_count {
+            return Cow::Borrowed("");
+        }
+        let offset = self.string_offsets + index.0 as usize * 8;
+        let start = read_u64(&self.mmap, offset);
+        let end = read_u64(&self.mmap, offset + 8);
+        let bytes = usize::try_from(start)
+            .ok()
+            .zip(usize::try_from(end).ok())
+            .and_then(|(start, end)| self.mmap[self.arena..].get(start..end))
+            .unwrap_or_default();
+        String::from_utf8_lossy(bytes)
+    }
+}
+
+/// A library's entry in the library table
+#[derive(Debug, Clone, Copy)]
+struct V2Library {
+    debug_name: u32,
+    debug_id: u32,
+    code_id: u32,
+    symbol_count: u32,
+    address_count: u32,
+    frame_count: u32,
+    symbols: usize,
+    addresses: usize,
+    frames: usize,
+}
+
+struct MappedLibrary {
+    file: Arc<MappedSidecar>,
+    entry: V2Library,
+}
+
+impl MappedLibrary {
+    fn string(&self, index: StringTableIndex) -> Cow<'_, str> {
+        self.file.string(index)
+    }
+
+    fn symbol(&self, index: u32) -> Option<InternedSymbolInfo> {
+        if index >= self.entry.symbol_count {
+            return None;
+        }
+        let offset = self.entry.symbols + index as usize * V2_SYMBOL_SIZE;
+        let field = |i: usize| read_u32(&self.file.mmap, offset + i * 4);
+        let frames = optional(field(3)).map(|first_frame| {
+            let end = first_frame
+                .saturating_add(field(4))
+                .min(self.entry.frame_count);
+            (first_frame..end).map(|frame| self.frame(frame)).collect()
+        });
+        Some(InternedSymbolInfo {
+            rva: field(0),
+            size: optional(field(1)),
+            symbol: StringTableIndex(field(2)),
+            frames,
+        })
+    }
+
+    fn frame(&self, index: u32) -> InternedFrameDebugInfo {
+        let offset = self.entry.frames + index as usize * V2_FRAME_SIZE;
+        let field = |i: usize| read_u32(&self.file.mmap, offset + i * 4);
+        InternedFrameDebugInfo {
+            function: optional(field(0)).map(StringTableIndex),
+            file: optional(field(1)).map(StringTableIndex),
+            line: optional(field(2)),
+        }
+    }
+
+    /// Binary search the address table, which is sorted by rva
+    fn symbol_for_address(&self, rva: u32) -> Option<InternedSymbolInfo> {
+        let (mut low, mut high) = (0, self.entry.address_count as usize);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let offset = self.entry.addresses + mid * V2_ADDRESS_SIZE;
+            match read_u32(&self.file.mmap, offset).cmp(&rva) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => {
+                    return self.symbol(read_u32(&self.file.mmap, offset + 4))
+                }
+            }
+        }
+        None
+    }
+
+    /// Binary search the symbol table, which is sorted by rva, for the last
+    /// symbol at or before `rva`
+    fn nearest_symbol(&self, rva: u32) -> Option<InternedSymbolInfo> {
+        let (mut low, mut high) = (0, self.entry.symbol_count as usize);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let offset = self.entry.symbols + mid * V2_SYMBOL_SIZE;
+            if read_u32(&self.file.mmap, offset) <= rva {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        self.symbol(low.checked_sub(1)? as u32)
+    }
+}
+
+/// The offset of `count` entries of `size` bytes at `offset`, if they're
+/// within `bytes`
+fn table_offset(bytes: &[u8], offset: u64, count: u64, size: usize) -> Option<usize> {
+    let offset = usize::try_from(offset).ok()?;
+    let len = usize::try_from(count).ok()?.checked_mul(size)?;
+    (offset.checked_add(len)? <= bytes.len()).then_some(offset)
+}
+
+/// A mapped `.syms.bin` sidecar, as symbol maps
+struct MappedV2 {
+    symbol_maps: Vec<PrecogLibraySymbolMap>,
+    /// The debug names of the libraries whose tables aren't within the file,
+    /// which are left out
+    corrupt_libraries: Vec<String>,
+}
+
+/// Map a `.syms.bin` sidecar, and check that its tables are within the file.
+/// Nothing else is read until it's looked up.
+fn map_v2(path: &Path) -> Result<MappedV2, String> {
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    // Safety: sidecars are written once, next to the profile, and not
+    // modified while they're served.
+    let mmap = unsafe { Mmap::map(&file) }.map_err(|err| err.to_string())?;
+    let bytes = &mmap[..];
+    if bytes.len() < V2_HEADER_SIZE || &bytes[..8] != V2_MAGIC {
+        return Err("not a .syms.bin sidecar".to_string());
+    }
+    let version = read_u32(bytes, 8);
+    if version != V2_VERSION {
+        return Err(format!("unsupported version {version}"));
+    }
+    let library_count = read_u32(bytes, 12);
+    table_offset(
+        bytes,
+        V2_HEADER_SIZE as u64,
+        library_count.into(),
+        V2_LIBRARY_SIZE,
+    )
+    .ok_or("the library table is truncated")?;
+
+    let string_table = table_offset(bytes, read_u64(bytes, 16), 1, 8)
+        .ok_or("the string table is out of bounds")?;
+    let string_count = read_u32(bytes, string_table);
+    let string_offsets = string_table + 8;
+    table_offset(bytes, string_offsets as u64, u64::from(string_count) + 1, 8)
+        .ok_or("the string table is truncated")?;
+    let arena = string_offsets + (string_count as usize + 1) * 8;
+
+    let file = Arc::new(MappedSidecar {
+        mmap,
+        string_count,
+        string_offsets,
+        arena,
+    });
+    let bytes = &file.mmap[..];
+    let mut mapped = MappedV2 {
+        symbol_maps: Vec::new(),
+        corrupt_libraries: Vec::new(),
+    };
+    for index in 0..library_count as usize {
+        let offset = V2_HEADER_SIZE + index * V2_LIBRARY_SIZE;
+        let field = |i: usize| read_u32(bytes, offset + i * 4);
+        let (symbol_count, address_count, frame_count) = (field(3), field(4), field(5));
+        let tables = (
+            table_offset(
+                bytes,
+                read_u64(bytes, offset + 24),
+                symbol_count.into(),
+                V2_SYMBOL_SIZE,
+            ),
+            table_offset(
+                bytes,
+                read_u64(bytes, offset + 32),
+                address_count.into(),
+                V2_ADDRESS_SIZE,
+            ),
+            table_offset(
+                bytes,
+                read_u64(bytes, offset + 40),
+                frame_count.into(),
+                V2_FRAME_SIZE,
+            ),
+        );
+        let (Some(symbols), Some(addresses), Some(frames)) = tables else {
+            let debug_name = file.string(StringTableIndex(field(0)));
+            mapped.corrupt_libraries.push(debug_name.into_owned());
+            continue;
+        };
+        let entry = V2Library {
+            debug_name: field(0),
+            debug_id: field(1),
+            code_id: field(2),
+            symbol_count,
+            address_count,
+            frame_count,
+            symbols,
+            addresses,
+            frames,
+        };
+        mapped
+            .symbol_maps
+            .push(PrecogLibraySymbolMap::new(LibraryData::Mapped(
+                MappedLibrary {
+                    file: file.clone(),
+                    entry,
+                },
+            )));
+    }
+    Ok(mapped)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use debugid::DebugId;
+    use wholesym::samply_symbols::SymbolMapTrait;
+    use wholesym::{
+        FrameDebugInfo, FramesLookupResult, LookupAddress, SymbolInfo, SymbolManagerConfig,
+        SyncAddressInfo,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_v2_round_trip() {
+        let json = r#"{
+            "string_table": ["main", "src/main.rs", "inlined"],
+            "data": [{
+                "debug_name": "app",
+                "debug_id": "B2AE4A7BF1DF4F2DB1FB1E2BD1F8A3DD1",
+                "code_id": "",
+                "symbol_table": [
+                    {"rva": 4096, "size": 64, "symbol": 0, "frames": [
+                        {"function": 2, "file": 1, "line": 3},
+                        {"function": 0, "file": 1, "line": 12}
+                    ]},
+                    {"rva": 8192, "symbol": 2}
+                ],
+                "known_addresses": [[8200, 1], [4100, 0]]
+            }]
+        }"#;
+        let info: PrecogSymbolInfo = serde_json::from_str(json).unwrap();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        info.write_v2(file.as_file()).unwrap();
+
+        let symbol_maps = map_v2(file.path()).unwrap().symbol_maps;
+        assert_eq!(symbol_maps.len(), 1);
+        let symbol_map = &symbol_maps[0];
+        assert_eq!(symbol_map.library_info().debug_name.as_deref(), Some("app"));
+        assert_eq!(symbol_map.symbol_count(), 2);
+
+        let info = symbol_map
+            .lookup_sync(LookupAddress::Relative(4100))
+            .unwrap();
+        assert_eq!(info.symbol.address, 4096);
+        assert_eq!(info.symbol.size, Some(64));
+        assert_eq!(symbol_map.resolve_symbol_name(info.symbol.name), "main");
+        let Some(wholesym::FramesLookupResult::Available(frames)) = info.frames else {
+            panic!("the frames should be available");
+        };
+        assert_eq!(frames.len(), 2);
+        assert_eq!(
+            symbol_map.resolve_function_name(frames[0].function.unwrap()),
+            "inlined"
+        );
+        assert_eq!(frames[1].line_number, Some(12));
+
+        let info = symbol_map
+            .lookup_sync(LookupAddress::Relative(8200))
+            .unwrap();
+        assert_eq!(info.symbol.size, None);
+        assert!(info.frames.is_none());
+        assert_eq!(symbol_map.approximate_lookups(), 0);
+    }
+
+    #[test]
+    fn test_nearest_symbol_fallback() {
+        // The symbol table isn't sorted by rva.
+        let json = r#"{
+            "string_table": ["main", "src/main.rs", "tail"],
+            "data": [{
+                "debug_name": "app",
+                "debug_id": "B2AE4A7BF1DF4F2DB1FB1E2BD1F8A3DD1",
+                "code_id": "",
+                "symbol_table": [
+                    {"rva": 8192, "symbol": 2},
+                    {"rva": 4096, "size": 64, "symbol": 0, "frames": [
+                        {"function": 0, "file": 1, "line": 12}
+                    ]}
+                ],
+                "known_addresses": [[4100, 1], [8200, 0]]
+            }]
+        }"#;
+        let info: PrecogSymbolInfo = serde_json::from_str(json).unwrap();
+        let mut bytes = Vec::new();
+        info.write_v2(&mut bytes).unwrap();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), &bytes).unwrap();
+        let json_maps = info.into_iter();
+        let v2_maps = map_v2(file.path()).unwrap().symbol_maps;
+
+        for symbol_map in json_maps.chain(v2_maps) {
+            let lookup = |rva| symbol_map.lookup_sync(LookupAddress::Relative(rva));
+            // Known addresses keep their frames.
+            let info = lookup(4100).unwrap();
+            assert_eq!(symbol_map.resolve_symbol_name(info.symbol.name), "main");
+            assert!(info.frames.is_some());
+            assert_eq!(symbol_map.approximate_lookups(), 0);
+
+            // Other addresses within main get main, without frames.
+            let info = lookup(4120).unwrap();
+            assert_eq!(info.symbol.address, 4096);
+            assert!(info.frames.is_none());
+            assert_eq!(symbol_map.approximate_lookups(), 1);
+
+            // Past the end of main, and before the first symbol, there's
+            // nothing; the last symbol has no size, so it takes the rest.
+            assert!(lookup(4160).is_none());
+            assert!(lookup(16).is_none());
+            let info = lookup(9000).unwrap();
+            assert_eq!(symbol_map.resolve_symbol_name(info.symbol.name), "tail");
+            assert_eq!(symbol_map.approximate_lookups(), 2);
+        }
+    }
+
+    #[test]
+    fn test_corrupt_sidecars() {
+        let dir = tempfile::tempdir().unwrap();
+        let json_path = dir.path().join("profile.syms.json");
+        assert!(PrecogSymbolInfo::try_load(&json_path).unwrap().is_none());
+        std::fs::write(&json_path, r#"{"string_table": ["main"], "data": [{"#).unwrap();
+        assert!(PrecogSymbolInfo::try_load(&json_path).is_err());
+
+        // A library whose tables were cut off is left out.
+        let info: PrecogSymbolInfo = serde_json::from_str(
+            r#"{"string_table": ["main"], "data": [{"debug_name": "app",
+            "debug_id": "B2AE4A7BF1DF4F2DB1FB1E2BD1F8A3DD1", "code_id": "",
+            "symbol_table": [{"rva": 4096, "symbol": 0}], "known_addresses": [[4096, 0]]}]}"#,
+        )
+        .unwrap();
+        let mut bytes = Vec::new();
+        info.write_v2(&mut bytes).unwrap();
+        // Point the symbol table past the end of the file.
+        let symbols_offset = V2_HEADER_SIZE + 24;
+        bytes[symbols_offset..symbols_offset + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        let v2_path = dir.path().join("profile.syms.bin");
+        std::fs::write(&v2_path, &bytes).unwrap();
+        let mapped = map_v2(&v2_path).unwrap();
+        assert!(mapped.symbol_maps.is_empty());
+        assert_eq!(mapped.corrupt_libraries, ["app"]);
+
+        std::fs::write(&v2_path, &bytes[..V2_HEADER_SIZE + 4]).unwrap();
+        assert!(map_v2(&v2_path).is_err());
+    }
+
+    const DEBUG_ID: &str = "B2AE4A7BF1DF4F2DB1FB1E2BD1F8A3DD1";
+
+    /// Function names and files of [`DebugInfoSymbolMap`], by index
+    const DEBUG_INFO_STRINGS: [&str; 4] = [
+        "main",
+        "core::str::parse",
+        "src/main.rs",
+        "/rustc/0123456789abcdef0123456789abcdef01234567/library/core/src/str/mod.rs",
+    ];
+
+    /// The symbols of a library as its debug info has them: `main` at
+    /// 0x1000..0x1100, with a line per 16 bytes, which inlines a function of
+    /// the standard library at 0x1010..0x1020.
+    struct DebugInfoSymbolMap(SymbolMapGeneration);
+
+    impl SymbolMapTrait for DebugInfoSymbolMap {
+        fn debug_id(&self) -> DebugId {
+            DebugId::from_breakpad(DEBUG_ID).unwrap()
+        }
+
+        fn symbol_count(&self) -> usize {
+            1
+        }
+
+        fn iter_symbols(&self) -> Box<dyn Iterator<Item = (u32, Cow<'_, str>)> + '_> {
+            Box::new(std::iter::once((0x1000, Cow::Borrowed("main"))))
+        }
+
+        fn lookup_sync(&self, address: LookupAddress) -> Option<SyncAddressInfo> {
+            let LookupAddress::Relative(rva) = address else {
+                return None;
+            };
+            if !(0x1000..0x1100).contains(&rva) {
+                return None;
+            }
+            let frame = |function, file, line| FrameDebugInfo {
+                function: Some(self.0.function_name_handle(FunctionNameIndex(function))),
+                file_path: Some(self.0.source_file_handle(SourceFilePathIndex(file))),
+                line_number: Some(line),
+            };
+            let mut frames = vec![frame(0, 2, 10 + (rva - 0x1000) / 16)];
+            if (0x1010..0x1020).contains(&rva) {
+                frames.insert(0, frame(1, 3, 42));
+            }
+            Some(SyncAddressInfo {
+                symbol: SymbolInfo {
+                    address: 0x1000,
+                    size: Some(0x100),
+                    name: self.0.symbol_name_handle(SymbolNameIndex(0)),
+                },
+                frames: Some(FramesLookupResult::Available(frames)),
+            })
+        }
+
+        fn resolve_function_name(&self, handle: wholesym::FunctionNameHandle) -> Cow<'_, str> {
+            let index = self.0.unwrap_function_name_index(handle);
+            Cow::Borrowed(DEBUG_INFO_STRINGS[index.0 as usize])
+        }
+
+        fn resolve_symbol_name(&self, handle: wholesym::SymbolNameHandle) -> Cow<'_, str> {
+            let index = self.0.unwrap_symbol_name_index(handle);
+            Cow::Borrowed(DEBUG_INFO_STRINGS[index.0 as usize])
+        }
+
+        fn resolve_source_file_path(&self, handle: SourceFilePathHandle) -> SourceFilePath<'_> {
+            let index = self.0.unwrap_source_file_index(handle);
+            SourceFilePath::RawPath(Cow::Borrowed(DEBUG_INFO_STRINGS[index.0 as usize]))
+        }
+    }
+
+    /// A `.syms.json` sidecar with the lookups of `addresses` in
+    /// `symbol_map`, whose files are written down as their special path if
+    /// they have one, like the lookup cache does.
+    fn sidecar_json(symbol_map: &dyn SymbolMapTrait, addresses: &[u32]) -> String {
+        let mut strings: Vec<String> = Vec::new();
+        let mut string_indexes = HashMap::new();
+        let mut index_for = |s: &str| {
+            *string_indexes.entry(s.to_string()).or_insert_with(|| {
+                strings.push(s.to_string());
+                strings.len() - 1
+            })
+        };
+        let mut symbol_table = Vec::new();
+        let mut known_addresses = Vec::new();
+        for &rva in addresses {
+            let info = symbol_map
+                .lookup_sync(LookupAddress::Relative(rva))
+                .unwrap();
+            let Some(FramesLookupResult::Available(frames)) = info.frames else {
+                panic!("the frames should be available");
+            };
+            let frames: Vec<serde_json::Value> = frames
+                .iter()
+                .map(|frame| {
+                    let file = symbol_map.resolve_source_file_path(frame.file_path.unwrap());
+                    let file = file
+                        .special_path_str()
+                        .unwrap_or_else(|| file.raw_path().into())
+                        .into_owned();
+                    serde_json::json!({
+                        "function": index_for(
+                            &symbol_map.resolve_function_name(frame.function.unwrap())
+                        ),
+                        "file": index_for(&file),
+                        "line": frame.line_number,
+                    })
+                })
+                .collect();
+            symbol_table.push(serde_json::json!({
+                "rva": info.symbol.address,
+                "size": info.symbol.size,
+                "symbol": index_for(&symbol_map.resolve_symbol_name(info.symbol.name)),
+                "frames": frames,
+            }));
+            known_addresses.push((rva, symbol_table.len() - 1));
+        }
+        serde_json::json!({
+            "string_table": strings,
+            "data": [{
+                "debug_name": "app",
+                "debug_id": DEBUG_ID,
+                "code_id": "",
+                "symbol_table": symbol_table,
+                "known_addresses": known_addresses,
+            }],
+        })
+        .to_string()
+    }
+
+    /// The responses of the symbolication API for a stack of `addresses`, and
+    /// for the source of `src/main.rs`, with the symbols of `symbol_map` for
+    /// the library, whose debug file is at `debug_path`
+    async fn query_api(
+        symbol_map: Arc<dyn SymbolMapTrait + Send + Sync>,
+        debug_path: &Path,
+        addresses: &[u32],
+    ) -> (serde_json::Value, serde_json::Value) {
+        let mut symbol_manager = SymbolManager::with_config(SymbolManagerConfig::default());
+        let lib_info = wholesym::LibraryInfo {
+            debug_name: Some("app".to_string()),
+            debug_id: Some(DebugId::from_breakpad(DEBUG_ID).unwrap()),
+            debug_path: Some(debug_path.to_string_lossy().into_owned()),
+            ..wholesym::LibraryInfo::default()
+        };
+        symbol_manager.add_known_library(lib_info.clone());
+        symbol_manager.add_known_library_symbols(lib_info, symbol_map);
+
+        let stack: Vec<(u32, u32)> = addresses.iter().map(|&rva| (0, rva)).collect();
+        let request = serde_json::json!({
+            "memoryMap": [["app", DEBUG_ID]],
+            "stacks": [stack],
+        });
+        let symbols = symbol_manager
+            .query_json_api("/symbolicate/v5", &request.to_string())
+            .await;
+        let request = serde_json::json!({
+            "debugName": "app",
+            "debugId": DEBUG_ID,
+            "moduleOffset": "0x1000",
+            "file": "src/main.rs",
+        });
+        let source = symbol_manager
+            .query_json_api("/source/v1", &request.to_string())
+            .await;
+        (
+            serde_json::to_value(symbols).unwrap(),
+            serde_json::to_value(source).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_sidecar_symbolicates_like_debug_info() {
+        let dir = tempfile::tempdir().unwrap();
+        let debug_path = dir.path().join("app");
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+
+        let addresses = [0x1000, 0x1014, 0x1040];
+        let debug_info = DebugInfoSymbolMap(SymbolMapGeneration::new());
+        let json = sidecar_json(&debug_info, &addresses);
+        let (expected_symbols, expected_source) =
+            query_api(Arc::new(debug_info), &debug_path, &addresses).await;
+        let inlines = &expected_symbols["results"][0]["stacks"][0][1]["inlines"];
+        assert_eq!(inlines[0]["function"], "core::str::parse");
+        assert!(inlines[0]["file"].as_str().unwrap().starts_with("git:"));
+        assert_eq!(expected_source["source"], "fn main() {}\n");
+
+        let info: PrecogSymbolInfo = serde_json::from_str(&json).unwrap();
+        let mut bytes = Vec::new();
+        info.write_v2(&mut bytes).unwrap();
+        let v2_path = dir.path().join("profile.syms.bin");
+        std::fs::write(&v2_path, &bytes).unwrap();
+        let sidecar_maps = info
+            .into_iter()
+            .chain(map_v2(&v2_path).unwrap().symbol_maps);
+        for symbol_map in sidecar_maps {
+            let (symbols, source) = query_api(Arc::new(symbol_map), &debug_path, &addresses).await;
+            assert_eq!(symbols, expected_symbols);
+            assert_eq!(source, expected_source);
+        }
     }
 }
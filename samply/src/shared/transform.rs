@@ -0,0 +1,848 @@
+//! Post-processing transforms which are applied to a recorded or imported
+//! profile before it is saved.
+//!
+//! Transforms operate on the profile's processed JSON representation, so they
+//! can restructure threads and tables after the fact without needing support
+//! in the profile builder. They are selected with `--transform name[=arg]` or
+//! in the `transforms` list of the config file.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use regex::Regex;
+use serde_json::{json, Value};
+
+use crate::config::{Config, RenameRule, RenameScope};
+use crate::shared::included_threads::IncludedThreads;
+
+pub trait ProfileTransform: Send {
+    /// The name used to select this transform.
+    fn name(&self) -> &'static str;
+
+    /// Modify the processed profile JSON in place.
+    fn apply(&self, profile: &mut Value);
+}
+
+pub const TRANSFORM_NAMES: &[&str] = &[
+    "drop-idle-threads",
+    "merge-worker-threads",
+    "strip-args",
+    "rename-by-rules",
+    "downsample",
+    "keep-last",
+];
+
+/// Parse a transform spec such as `downsample=4`. The config supplies the
+/// rules for `rename-by-rules`.
+pub fn parse_transform(spec: &str, config: &Config) -> Result<Box<dyn ProfileTransform>, String> {
+    let (name, arg) = match spec.split_once('=') {
+        Some((name, arg)) => (name.trim(), Some(arg.trim())),
+        None => (spec.trim(), None),
+    };
+    let no_arg = |transform: Box<dyn ProfileTransform>| match arg {
+        Some(_) => Err(format!("The {name} transform doesn't take an argument")),
+        None => Ok(transform),
+    };
+    match name {
+        "drop-idle-threads" => no_arg(Box::new(DropIdleThreads)),
+        "merge-worker-threads" => no_arg(Box::new(MergeWorkerThreads)),
+        "strip-args" => no_arg(Box::new(StripArgs)),
+        "rename-by-rules" => {
+            if config.rename_rules.is_empty() {
+                eprintln!("Warning: rename-by-rules has no rules; add rename_rules to the config file.");
+            }
+            no_arg(Box::new(RenameByRules {
+                rules: config.rename_rules.clone(),
+            }))
+        }
+        "downsample" => {
+            let factor = match arg {
+                Some(arg) => arg
+                    .parse()
+                    .ok()
+                    .filter(|&factor| factor >= 1)
+                    .ok_or_else(|| format!("Invalid downsample factor {arg:?}"))?,
+                None => 2,
+            };
+            Ok(Box::new(Downsample { factor }))
+        }
+        "keep-last" => {
+            let seconds = arg
+                .and_then(|arg| arg.parse().ok())
+                .filter(|&seconds: &f64| seconds > 0.0)
+                .ok_or_else(|| {
+                    "keep-last needs a number of seconds, e.g. keep-last=30".to_string()
+                })?;
+            Ok(Box::new(KeepLast { seconds }))
+        }
+        _ => Err(format!(
+            "Unknown transform {name:?}. Available transforms: {}",
+            TRANSFORM_NAMES.join(", ")
+        )),
+    }
+}
+
+/// Build the transform chain from the `--transform` arguments, or from the
+/// config file if there are none.
+pub fn transform_chain(
+    specs: &[String],
+    config: &Config,
+) -> Result<Vec<Box<dyn ProfileTransform>>, String> {
+    let specs = if specs.is_empty() {
+        &config.transforms
+    } else {
+        specs
+    };
+    specs
+        .iter()
+        .map(|spec| parse_transform(spec, config))
+        .collect()
+}
+
+/// The `keep-last` transform for the ring buffer of `samply record`.
+pub fn keep_last(duration: Duration) -> Box<dyn ProfileTransform> {
+    Box::new(KeepLast {
+        seconds: duration.as_secs_f64(),
+    })
+}
+
+/// Drops the threads which `samply record --include-thread` and
+/// `--exclude-thread` leave out. The samplers already skip their samples, but
+/// their tracks are created before their names are known.
+pub fn drop_excluded_threads(included_threads: IncludedThreads) -> Box<dyn ProfileTransform> {
+    Box::new(DropExcludedThreads { included_threads })
+}
+
+pub fn apply_transforms(profile: &mut Value, transforms: &[Box<dyn ProfileTransform>]) {
+    for transform in transforms {
+        eprintln!("Applying transform {}...", transform.name());
+        transform.apply(profile);
+    }
+}
+
+/// Drops non-main threads which did no work: threads without any sample with
+/// a stack, or whose CPU deltas are all zero. Threads with markers are kept.
+struct DropIdleThreads;
+
+impl ProfileTransform for DropIdleThreads {
+    fn name(&self) -> &'static str {
+        "drop-idle-threads"
+    }
+
+    fn apply(&self, profile: &mut Value) {
+        let keep: Vec<bool> = threads(profile).iter().map(|t| !is_idle(t)).collect();
+        retain_threads(profile, &keep);
+    }
+}
+
+fn is_idle(thread: &Value) -> bool {
+    if thread["isMainThread"].as_bool() == Some(true) || table_len(&thread["markers"]) > 0 {
+        return false;
+    }
+    let samples = &thread["samples"];
+    let has_stack = samples["stack"]
+        .as_array()
+        .is_some_and(|stacks| stacks.iter().any(|s| !s.is_null()));
+    if !has_stack {
+        return true;
+    }
+    // Without CPU deltas (all null), we can't tell, so the thread is not idle.
+    let mut cpu_deltas = samples["threadCPUDelta"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_f64)
+        .peekable();
+    cpu_deltas.peek().is_some() && cpu_deltas.all(|delta| delta == 0.0)
+}
+
+/// Drops the non-main threads whose name `included_threads` leaves out. Main
+/// threads are kept, so that every recorded process keeps its track.
+struct DropExcludedThreads {
+    included_threads: IncludedThreads,
+}
+
+impl ProfileTransform for DropExcludedThreads {
+    fn name(&self) -> &'static str {
+        "drop-excluded-threads"
+    }
+
+    fn apply(&self, profile: &mut Value) {
+        let keep: Vec<bool> = threads(profile)
+            .iter()
+            .map(|thread| {
+                thread["isMainThread"].as_bool() == Some(true)
+                    || self
+                        .included_threads
+                        .should_include(thread["name"].as_str())
+            })
+            .collect();
+        retain_threads(profile, &keep);
+    }
+}
+
+/// Merges the non-main threads of each process whose names only differ by a
+/// trailing number, e.g. "rayon-worker-0" and "rayon-worker-1", into a single
+/// thread named "rayon-worker".
+struct MergeWorkerThreads;
+
+impl ProfileTransform for MergeWorkerThreads {
+    fn name(&self) -> &'static str {
+        "merge-worker-threads"
+    }
+
+    fn apply(&self, profile: &mut Value) {
+        let mut groups: HashMap<(String, String), Vec<usize>> = HashMap::new();
+        for (i, thread) in threads(profile).iter().enumerate() {
+            if thread["isMainThread"].as_bool() == Some(true) {
+                continue;
+            }
+            let pid = thread["pid"].to_string();
+            let name = worker_base_name(thread["name"].as_str().unwrap_or(""));
+            groups.entry((pid, name.to_string())).or_default().push(i);
+        }
+
+        let Some(thread_list) = profile["threads"].as_array_mut() else {
+            return;
+        };
+        let mut keep = vec![true; thread_list.len()];
+        for ((_, name), indexes) in groups {
+            let [first, rest @ ..] = indexes.as_slice() else {
+                continue;
+            };
+            if rest.is_empty() {
+                continue;
+            }
+            for &i in rest {
+                let thread = thread_list[i].take();
+                merge_thread_into(&mut thread_list[*first], thread);
+                keep[i] = false;
+            }
+            thread_list[*first]["name"] = json!(name);
+        }
+        retain_threads(profile, &keep);
+    }
+}
+
+/// "worker-12" -> "worker", "Worker 3" -> "Worker"
+fn worker_base_name(name: &str) -> &str {
+    let trimmed = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    if trimmed.len() == name.len() {
+        return name;
+    }
+    let trimmed = trimmed.trim_end_matches([' ', '-', '_', '#', ':', '.']);
+    if trimmed.is_empty() {
+        name
+    } else {
+        trimmed
+    }
+}
+
+/// Removes command line arguments from process names, which may contain
+/// paths or secrets (see `--include-args`).
+struct StripArgs;
+
+impl ProfileTransform for StripArgs {
+    fn name(&self) -> &'static str {
+        "strip-args"
+    }
+
+    fn apply(&self, profile: &mut Value) {
+        let Some(thread_list) = profile["threads"].as_array_mut() else {
+            return;
+        };
+        for thread in thread_list {
+            let Some(process_name) = thread["processName"].as_str() else {
+                continue;
+            };
+            let executable = shlex::split(process_name)
+                .and_then(|words| words.into_iter().next())
+                .unwrap_or_else(|| process_name.to_string());
+            if thread["name"].as_str() == Some(process_name) {
+                thread["name"] = json!(executable);
+            }
+            thread["processName"] = json!(executable);
+        }
+    }
+}
+
+/// Applies the `rename_rules` from the config file to thread, process or
+/// function names.
+struct RenameByRules {
+    rules: Vec<RenameRule>,
+}
+
+impl RenameByRules {
+    fn rename(&self, scope: RenameScope, name: &str) -> Option<String> {
+        let mut renamed = None;
+        for rule in self.rules.iter().filter(|rule| rule.scope == scope) {
+            let current = renamed.as_deref().unwrap_or(name);
+            if !rule.pattern.is_empty() && current.contains(&rule.pattern) {
+                renamed = Some(current.replace(&rule.pattern, &rule.replacement));
+            }
+        }
+        renamed
+    }
+}
+
+impl ProfileTransform for RenameByRules {
+    fn name(&self) -> &'static str {
+        "rename-by-rules"
+    }
+
+    fn apply(&self, profile: &mut Value) {
+        let Value::Object(root) = profile else {
+            return;
+        };
+
+        // Function names are indexes into the shared string array. Renamed
+        // names are appended, because the old strings may be used elsewhere.
+        let mut strings = match root.get_mut("shared").map(|s| s["stringArray"].take()) {
+            Some(Value::Array(strings)) => strings,
+            _ => Vec::new(),
+        };
+        let mut renamed_strings: HashMap<u64, Option<u64>> = HashMap::new();
+
+        for thread in root
+            .get_mut("threads")
+            .and_then(Value::as_array_mut)
+            .into_iter()
+            .flatten()
+        {
+            for key in ["name", "processName"] {
+                let renamed = thread[key]
+                    .as_str()
+                    .and_then(|name| self.rename(RenameScope::Threads, name));
+                if let Some(renamed) = renamed {
+                    thread[key] = json!(renamed);
+                }
+            }
+
+            let Some(func_names) = thread["funcTable"]["name"].as_array_mut() else {
+                continue;
+            };
+            for func_name in func_names {
+                let Some(index) = func_name.as_u64() else {
+                    continue;
+                };
+                let new_index = *renamed_strings.entry(index).or_insert_with(|| {
+                    let name = strings.get(index as usize)?.as_str()?;
+                    let renamed = self.rename(RenameScope::Functions, name)?;
+                    strings.push(json!(renamed));
+                    Some(strings.len() as u64 - 1)
+                });
+                if let Some(new_index) = new_index {
+                    *func_name = json!(new_index);
+                }
+            }
+        }
+
+        if let Some(shared) = root.get_mut("shared") {
+            shared["stringArray"] = Value::Array(strings);
+        }
+    }
+}
+
+/// Keeps one of every `factor` samples, and adds the weight and CPU delta of
+/// the dropped samples to the kept one, so that totals are preserved. Samples
+/// without a weight column count once each, so one is created for them.
+struct Downsample {
+    factor: usize,
+}
+
+impl ProfileTransform for Downsample {
+    fn name(&self) -> &'static str {
+        "downsample"
+    }
+
+    fn apply(&self, profile: &mut Value) {
+        if self.factor <= 1 {
+            return;
+        }
+        if let Some(interval) = profile["meta"]["interval"].as_f64() {
+            profile["meta"]["interval"] = json!(interval * self.factor as f64);
+        }
+        let Some(thread_list) = profile["threads"].as_array_mut() else {
+            return;
+        };
+        for thread in thread_list {
+            let samples = &mut thread["samples"];
+            let len = table_len(samples);
+            if len == 0 {
+                continue;
+            }
+            let times = sample_times(samples);
+            let kept: Vec<usize> = (0..len).step_by(self.factor).collect();
+            if !samples["weight"].is_array() {
+                samples["weight"] = json!(vec![1; len]);
+                if samples["weightType"].is_null() {
+                    samples["weightType"] = json!("samples");
+                }
+            }
+            for column in ["weight", "threadCPUDelta"] {
+                let Some(values) = samples[column].as_array_mut() else {
+                    continue;
+                };
+                if values.len() != len {
+                    continue;
+                }
+                for &start in &kept {
+                    let end = (start + self.factor).min(len);
+                    let sum: Option<f64> = values[start..end].iter().map(Value::as_f64).sum();
+                    if let Some(sum) = sum {
+                        values[start] = number_like(&values[start], sum);
+                    }
+                }
+            }
+            select_rows(samples, &kept);
+            let kept_times: Vec<f64> = kept.iter().map(|&i| times[i]).collect();
+            set_sample_times(samples, &kept_times);
+        }
+    }
+}
+
+/// Keeps the samples, markers and counter values of the last `seconds` of the
+/// profile, which ends with its last sample, and drops the older ones.
+struct KeepLast {
+    seconds: f64,
+}
+
+impl ProfileTransform for KeepLast {
+    fn name(&self) -> &'static str {
+        "keep-last"
+    }
+
+    fn apply(&self, profile: &mut Value) {
+        let end = threads(profile)
+            .iter()
+            .flat_map(|thread| sample_times(&thread["samples"]))
+            .reduce(f64::max);
+        let Some(end) = end else {
+            return;
+        };
+        keep_since(profile, end - self.seconds * 1000.0);
+    }
+}
+
+/// Keep the samples, allocations, markers and counter samples from `start`
+/// on, in milliseconds since the profile's start time. The live snapshots of
+/// `samply record --live` are served from a time on with this.
+pub fn keep_since(profile: &mut Value, start: f64) {
+    for thread in profile["threads"].as_array_mut().into_iter().flatten() {
+        keep_rows_since(&mut thread["samples"], start);
+        if thread["nativeAllocations"].is_object() {
+            keep_rows_since(&mut thread["nativeAllocations"], start);
+        }
+        keep_markers_since(&mut thread["markers"], start);
+    }
+    for counter in profile
+        .get_mut("counters")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+    {
+        keep_rows_since(&mut counter["samples"], start);
+    }
+}
+
+/// Keep the rows of a table with sample times which are at or after `start`.
+fn keep_rows_since(table: &mut Value, start: f64) {
+    let times = sample_times(table);
+    let rows: Vec<usize> = (0..times.len()).filter(|&i| times[i] >= start).collect();
+    if rows.len() == times.len() {
+        return;
+    }
+    select_rows(table, &rows);
+    let kept_times: Vec<f64> = rows.iter().map(|&i| times[i]).collect();
+    set_sample_times(table, &kept_times);
+}
+
+/// Keep the markers which end at or after `start`. Instant markers end when
+/// they start, and markers without times are kept.
+fn keep_markers_since(markers: &mut Value, start: f64) {
+    let len = table_len(markers);
+    let time = |column: &str, i: usize| markers[column].get(i).and_then(Value::as_f64);
+    let rows: Vec<usize> = (0..len)
+        .filter(|&i| {
+            time("endTime", i)
+                .or_else(|| time("startTime", i))
+                .map_or(true, |end| end >= start)
+        })
+        .collect();
+    if rows.len() < len {
+        select_rows(markers, &rows);
+    }
+}
+
+/// Keep integer columns integral.
+fn number_like(original: &Value, value: f64) -> Value {
+    if original.is_f64() {
+        json!(value)
+    } else {
+        json!(value as i64)
+    }
+}
+
+fn threads(profile: &Value) -> &[Value] {
+    profile["threads"].as_array().map_or(&[], Vec::as_slice)
+}
+
+fn table_len(table: &Value) -> usize {
+    table["length"].as_u64().unwrap_or(0) as usize
+}
+
+/// Keep the threads whose name, process name or pid `pattern` matches, for
+/// serving a subset of a large profile (`--threads`). The libraries and the
+/// shared string table are left alone, so the kept threads' indexes into them
+/// stay valid. Returns the number of kept threads.
+pub fn retain_matching_threads(profile: &mut Value, pattern: &Regex) -> usize {
+    let keep: Vec<bool> = threads(profile)
+        .iter()
+        .map(|thread| {
+            let pid = match &thread["pid"] {
+                Value::Number(pid) => Some(pid.to_string()),
+                Value::String(pid) => Some(pid.clone()),
+                _ => None,
+            };
+            ["name", "processName"]
+                .iter()
+                .filter_map(|key| thread[key].as_str())
+                .chain(pid.as_deref())
+                .any(|name| pattern.is_match(name))
+        })
+        .collect();
+    let kept = keep.iter().filter(|&&k| k).count();
+    retain_threads(profile, &keep);
+    kept
+}
+
+/// Remove the threads for which `keep` is false, and update the thread
+/// indexes which refer to them.
+fn retain_threads(profile: &mut Value, keep: &[bool]) {
+    let mut new_index = Vec::with_capacity(keep.len());
+    let mut next = 0;
+    for &k in keep {
+        new_index.push(k.then_some(next));
+        next += k as usize;
+    }
+    let remap = |index: &Value| index.as_u64().and_then(|i| *new_index.get(i as usize)?);
+
+    if let Some(thread_list) = profile["threads"].as_array_mut() {
+        let mut i = 0;
+        thread_list.retain(|_| {
+            i += 1;
+            keep.get(i - 1).copied().unwrap_or(true)
+        });
+    }
+    for key in ["initialVisibleThreads", "initialSelectedThreads"] {
+        if let Some(indexes) = profile["meta"].get_mut(key).and_then(Value::as_array_mut) {
+            *indexes = indexes.iter().filter_map(remap).map(|i| json!(i)).collect();
+        }
+    }
+    if let Some(counters) = profile.get_mut("counters").and_then(Value::as_array_mut) {
+        counters.retain_mut(|counter| match remap(&counter["mainThreadIndex"]) {
+            Some(i) => {
+                counter["mainThreadIndex"] = json!(i);
+                true
+            }
+            None => false,
+        });
+    }
+}
+
+/// Append the rows of `src` to `dst`, adding the given offsets to the index
+/// columns of `src`.
+fn append_table(dst: &mut Value, mut src: Value, index_columns: &[(&str, usize)]) {
+    let len = table_len(dst) + table_len(&src);
+    for &(column, offset) in index_columns {
+        for value in src[column].as_array_mut().into_iter().flatten() {
+            offset_index(value, offset);
+        }
+    }
+    let (Some(dst_map), Value::Object(src_map)) = (dst.as_object_mut(), src) else {
+        return;
+    };
+    for (key, src_value) in src_map {
+        if let (Some(Value::Array(dst_column)), Value::Array(src_column)) =
+            (dst_map.get_mut(&key), src_value)
+        {
+            dst_column.extend(src_column);
+        }
+    }
+    dst_map.insert("length".to_string(), json!(len));
+}
+
+/// Negative and null indexes mean "none" and are left alone.
+fn offset_index(value: &mut Value, offset: usize) {
+    if let Some(index) = value.as_u64() {
+        *value = json!(index + offset as u64);
+    }
+}
+
+/// Make row `i` of the table a copy of row `rows[i]`.
+fn select_rows(table: &mut Value, rows: &[usize]) {
+    let len = table_len(table);
+    let Some(map) = table.as_object_mut() else {
+        return;
+    };
+    for value in map.values_mut() {
+        if let Value::Array(column) = value {
+            if column.len() == len {
+                let old = std::mem::take(column);
+                *column = rows.iter().map(|&row| old[row].clone()).collect();
+            }
+        }
+    }
+    map.insert("length".to_string(), json!(rows.len()));
+}
+
+/// Absolute sample times, from either the `time` or the `timeDeltas` column
+fn sample_times(samples: &Value) -> Vec<f64> {
+    if let Some(times) = samples["time"].as_array() {
+        return times.iter().map(|t| t.as_f64().unwrap_or(0.0)).collect();
+    }
+    let mut time = 0.0;
+    samples["timeDeltas"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|delta| {
+            time += delta.as_f64().unwrap_or(0.0);
+            time
+        })
+        .collect()
+}
+
+fn set_sample_times(samples: &mut Value, times: &[f64]) {
+    if samples.get("time").is_some() {
+        samples["time"] = json!(times);
+        return;
+    }
+    let mut previous = 0.0;
+    let deltas: Vec<f64> = times
+        .iter()
+        .map(|&time| {
+            let delta = time - previous;
+            previous = time;
+            delta
+        })
+        .collect();
+    samples["timeDeltas"] = json!(deltas);
+}
+
+/// Sort the rows of a table with per-row `times`, keeping the order of rows
+/// with equal times.
+fn sort_rows_by_time(table: &mut Value, times: &[f64]) -> Vec<f64> {
+    let mut rows: Vec<usize> = (0..times.len()).collect();
+    rows.sort_by(|&a, &b| times[a].total_cmp(&times[b]));
+    select_rows(table, &rows);
+    rows.iter().map(|&row| times[row]).collect()
+}
+
+/// Move all samples, stacks, frames, functions and markers of `src` into `dst`.
+fn merge_thread_into(dst: &mut Value, mut src: Value) {
+    let stack_offset = table_len(&dst["stackTable"]);
+    let frame_offset = table_len(&dst["frameTable"]);
+    let func_offset = table_len(&dst["funcTable"]);
+    let resource_offset = table_len(&dst["resourceTable"]);
+    let native_symbol_offset = table_len(&dst["nativeSymbols"]);
+
+    append_table(
+        &mut dst["stackTable"],
+        src["stackTable"].take(),
+        &[("prefix", stack_offset), ("frame", frame_offset)],
+    );
+    append_table(
+        &mut dst["frameTable"],
+        src["frameTable"].take(),
+        &[("func", func_offset), ("nativeSymbol", native_symbol_offset)],
+    );
+    append_table(
+        &mut dst["funcTable"],
+        src["funcTable"].take(),
+        &[("resource", resource_offset)],
+    );
+    append_table(&mut dst["resourceTable"], src["resourceTable"].take(), &[]);
+    append_table(&mut dst["nativeSymbols"], src["nativeSymbols"].take(), &[]);
+
+    // Sample times have to be read before the time deltas are concatenated.
+    let mut times = sample_times(&dst["samples"]);
+    times.extend(sample_times(&src["samples"]));
+    append_table(
+        &mut dst["samples"],
+        src["samples"].take(),
+        &[("stack", stack_offset)],
+    );
+    let times = sort_rows_by_time(&mut dst["samples"], &times);
+    set_sample_times(&mut dst["samples"], &times);
+
+    if dst["nativeAllocations"].is_object() {
+        let mut allocation_times = sample_times(&dst["nativeAllocations"]);
+        allocation_times.extend(sample_times(&src["nativeAllocations"]));
+        append_table(
+            &mut dst["nativeAllocations"],
+            src["nativeAllocations"].take(),
+            &[("stack", stack_offset)],
+        );
+        let allocation_times = sort_rows_by_time(&mut dst["nativeAllocations"], &allocation_times);
+        set_sample_times(&mut dst["nativeAllocations"], &allocation_times);
+    }
+
+    for data in src["markers"]["data"].as_array_mut().into_iter().flatten() {
+        if let Some(stack) = data.pointer_mut("/cause/stack") {
+            offset_index(stack, stack_offset);
+        }
+    }
+    append_table(&mut dst["markers"], src["markers"].take(), &[]);
+
+    // The merged thread lives from the first registration to the last
+    // unregistration; null means it was still alive at the end.
+    let register_time = [&dst["registerTime"], &src["registerTime"]]
+        .into_iter()
+        .filter_map(Value::as_f64)
+        .reduce(f64::min);
+    if let Some(register_time) = register_time {
+        dst["registerTime"] = json!(register_time);
+    }
+    dst["unregisterTime"] = match (dst["unregisterTime"].as_f64(), src["unregisterTime"].as_f64()) {
+        (Some(a), Some(b)) => json!(a.max(b)),
+        _ => Value::Null,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_thread(name: &str, is_main: bool, stacks: Value, cpu_deltas: Value) -> Value {
+        let len = stacks.as_array().unwrap().len();
+        json!({
+            "name": name, "isMainThread": is_main, "pid": "1", "tid": 1, "processName": "app --flag",
+            "registerTime": 0.0, "unregisterTime": null,
+            "samples": {
+                "length": len, "stack": stacks, "timeDeltas": vec![1.0; len],
+                "weight": vec![1; len], "threadCPUDelta": cpu_deltas
+            },
+            "stackTable": { "length": 1, "prefix": [null], "frame": [0] },
+            "frameTable": { "length": 1, "func": [0], "nativeSymbol": [null] },
+            "funcTable": { "length": 1, "name": [0], "resource": [-1] },
+            "resourceTable": { "length": 0, "lib": [], "name": [] },
+            "nativeSymbols": { "length": 0, "address": [] },
+            "markers": { "length": 0, "data": [], "name": [] }
+        })
+    }
+
+    fn test_profile() -> Value {
+        json!({
+            "meta": { "interval": 1.0, "initialVisibleThreads": [0, 2, 3] },
+            "shared": { "stringArray": ["work"] },
+            "threads": [
+                test_thread("app --flag", true, json!([0, 0]), json!([10, 10])),
+                test_thread("idle", false, json!([0, 0]), json!([0, 0])),
+                test_thread("worker-1", false, json!([0, null]), json!([null, null])),
+                test_thread("worker-2", false, json!([0]), json!([5])),
+            ],
+            "counters": [{ "mainThreadIndex": 0 }]
+        })
+    }
+
+    #[test]
+    fn test_drop_and_merge_threads() {
+        let config = Config::default();
+        let transforms =
+            transform_chain(&["drop-idle-threads".into(), "merge-worker-threads".into()], &config)
+                .unwrap();
+        let mut profile = test_profile();
+        apply_transforms(&mut profile, &transforms);
+
+        let names: Vec<&str> = threads(&profile).iter().map(|t| t["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["app --flag", "worker"]);
+        assert_eq!(profile["meta"]["initialVisibleThreads"], json!([0, 1]));
+
+        let worker = &profile["threads"][1];
+        assert_eq!(worker["samples"]["length"], 3);
+        assert_eq!(worker["samples"]["stack"], json!([0, 1, null]));
+        assert_eq!(worker["samples"]["timeDeltas"], json!([1.0, 0.0, 1.0]));
+        assert_eq!(worker["stackTable"]["frame"], json!([0, 1]));
+        assert_eq!(worker["funcTable"]["resource"], json!([-1, -1]));
+    }
+
+    #[test]
+    fn test_strip_args_and_downsample() {
+        let transforms = transform_chain(&[], &Config {
+            transforms: vec!["strip-args".into(), "downsample=2".into()],
+            ..Default::default()
+        })
+        .unwrap();
+        let mut profile = test_profile();
+        apply_transforms(&mut profile, &transforms);
+
+        let main = &profile["threads"][0];
+        assert_eq!(main["name"], "app");
+        assert_eq!(main["processName"], "app");
+        assert_eq!(main["samples"]["length"], 1);
+        assert_eq!(main["samples"]["weight"], json!([2]));
+        assert_eq!(main["samples"]["threadCPUDelta"], json!([20]));
+        assert_eq!(profile["meta"]["interval"], 2.0);
+
+        assert!(parse_transform("downsample=0", &Config::default()).is_err());
+        assert!(parse_transform("no-such-transform", &Config::default()).is_err());
+    }
+
+    #[test]
+    fn test_downsample_without_weight_column() {
+        let mut profile = test_profile();
+        profile["threads"][0] = test_thread("app", true, json!([0, 0, 0]), json!([1, 2, 3]));
+        let samples = profile["threads"][0]["samples"].as_object_mut().unwrap();
+        samples.remove("weight");
+        let transforms = transform_chain(&["downsample=2".into()], &Config::default()).unwrap();
+        apply_transforms(&mut profile, &transforms);
+
+        let samples = &profile["threads"][0]["samples"];
+        assert_eq!(samples["length"], 2);
+        assert_eq!(samples["weight"], json!([2, 1]));
+        assert_eq!(samples["weightType"], "samples");
+        assert_eq!(samples["threadCPUDelta"], json!([3, 3]));
+    }
+
+    #[test]
+    fn test_keep_last() {
+        let mut profile = test_profile();
+        profile["threads"][0]["markers"] = json!({
+            "length": 2, "name": [0, 0], "startTime": [0.5, 1.5], "endTime": [null, null],
+            "data": [null, null]
+        });
+        apply_transforms(&mut profile, &[keep_last(Duration::from_micros(500))]);
+
+        // The profile ends with the last samples, at 2ms.
+        let main = &profile["threads"][0];
+        assert_eq!(main["samples"]["length"], 1);
+        assert_eq!(main["samples"]["timeDeltas"], json!([2.0]));
+        assert_eq!(main["samples"]["threadCPUDelta"], json!([10]));
+        assert_eq!(main["markers"]["startTime"], json!([1.5]));
+        assert_eq!(profile["threads"][3]["samples"]["length"], 0);
+        assert!(parse_transform("keep-last", &Config::default()).is_err());
+
+        let mut profile = test_profile();
+        keep_since(&mut profile, 1.5);
+        assert_eq!(profile["threads"][0]["samples"]["timeDeltas"], json!([2.0]));
+        assert_eq!(profile["threads"][3]["samples"]["length"], 0);
+    }
+
+    #[test]
+    fn test_drop_excluded_threads() {
+        let mut profile = test_profile();
+        let included_threads = IncludedThreads {
+            include: Some(Regex::new("^worker").unwrap()),
+            exclude: Some(Regex::new("-2$").unwrap()),
+        };
+        apply_transforms(&mut profile, &[drop_excluded_threads(included_threads)]);
+
+        // The main thread is kept, as the process's track.
+        let names: Vec<&str> = threads(&profile)
+            .iter()
+            .map(|t| t["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["app --flag", "worker-1"]);
+    }
+}
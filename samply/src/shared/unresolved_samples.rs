@@ -1,6 +1,8 @@
 use std::collections::hash_map::Entry;
 
-use fxprof_processed_profile::{CpuDelta, FrameHandle, MarkerHandle, ThreadHandle, Timestamp};
+use fxprof_processed_profile::{
+    CpuDelta, FrameHandle, MarkerHandle, ProcessHandle, ThreadHandle, Timestamp,
+};
 
 use super::types::{FastHashMap, StackFrame, StackMode};
 
@@ -25,6 +27,28 @@ impl UnresolvedSamples {
         self.samples_and_markers.is_empty()
     }
 
+    /// Drop the samples and marker stacks from before `timestamp_mono`.
+    #[allow(unused)]
+    pub fn drop_before(&mut self, timestamp_mono: u64) {
+        let mut new_index = Vec::with_capacity(self.samples_and_markers.len());
+        let mut kept = 0;
+        for sample in &self.samples_and_markers {
+            let keep = sample.timestamp_mono >= timestamp_mono;
+            new_index.push(keep.then_some(kept));
+            kept += keep as usize;
+        }
+        if kept == self.samples_and_markers.len() {
+            return;
+        }
+        self.samples_and_markers
+            .retain(|sample| sample.timestamp_mono >= timestamp_mono);
+        for info in self.prev_sample_info_per_thread.values_mut() {
+            info.prev_sample_index_if_zero_cpu = info
+                .prev_sample_index_if_zero_cpu
+                .and_then(|index| new_index[index]);
+        }
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn add_sample(
         &mut self,
@@ -36,6 +60,57 @@ impl UnresolvedSamples {
         weight: i32,
         extra_label_frame: Option<FrameHandle>,
     ) {
+        self.push_sample(
+            thread_handle,
+            timestamp,
+            timestamp_mono,
+            stack,
+            extra_label_frame,
+            SampleData {
+                cpu_delta,
+                weight,
+                is_off_cpu: false,
+            },
+        );
+    }
+
+    /// Add a sample for time which the thread spent blocked, with `stack` being
+    /// the stack it was switched out with. It gets an "Off-CPU" leaf frame.
+    pub fn add_off_cpu_sample(
+        &mut self,
+        thread_handle: ThreadHandle,
+        timestamp: Timestamp,
+        timestamp_mono: u64,
+        stack: UnresolvedStackHandle,
+        cpu_delta: CpuDelta,
+        weight: i32,
+    ) {
+        self.push_sample(
+            thread_handle,
+            timestamp,
+            timestamp_mono,
+            stack,
+            None,
+            SampleData {
+                cpu_delta,
+                weight,
+                is_off_cpu: true,
+            },
+        );
+    }
+
+    fn push_sample(
+        &mut self,
+        thread_handle: ThreadHandle,
+        timestamp: Timestamp,
+        timestamp_mono: u64,
+        stack: UnresolvedStackHandle,
+        extra_label_frame: Option<FrameHandle>,
+        sample_data: SampleData,
+    ) {
+        // Off-CPU samples have a leaf frame of their own, so later samples can't
+        // be folded into them.
+        let is_foldable = sample_data.cpu_delta == CpuDelta::ZERO && !sample_data.is_off_cpu;
         let sample_index = self.samples_and_markers.len();
         self.samples_and_markers.push(UnresolvedSampleOrMarker {
             thread_handle,
@@ -43,14 +118,13 @@ impl UnresolvedSamples {
             timestamp_mono,
             stack,
             extra_label_frame,
-            sample_or_marker: SampleOrMarker::Sample(SampleData { weight, cpu_delta }),
+            sample_or_marker: SampleOrMarker::Sample(sample_data),
         });
         self.prev_sample_info_per_thread.insert(
             thread_handle,
             PreviousSampleInfo {
                 stack,
-                prev_sample_index_if_zero_cpu: (cpu_delta == CpuDelta::ZERO)
-                    .then_some(sample_index),
+                prev_sample_index_if_zero_cpu: is_foldable.then_some(sample_index),
             },
         );
     }
@@ -86,6 +160,7 @@ impl UnresolvedSamples {
                         sample_or_marker: SampleOrMarker::Sample(SampleData {
                             weight,
                             cpu_delta: CpuDelta::ZERO,
+                            is_off_cpu: false,
                         }),
                     });
                     sample_info.prev_sample_index_if_zero_cpu = Some(sample_index);
@@ -103,6 +178,7 @@ impl UnresolvedSamples {
                     sample_or_marker: SampleOrMarker::Sample(SampleData {
                         weight,
                         cpu_delta: CpuDelta::ZERO,
+                        is_off_cpu: false,
                     }),
                 });
                 entry.insert(PreviousSampleInfo {
@@ -113,6 +189,27 @@ impl UnresolvedSamples {
         }
     }
 
+    /// Add an allocation of `bytes` with `stack`. Allocations are per process,
+    /// on the thread of the process which holds them, its main thread.
+    pub fn add_allocation(
+        &mut self,
+        main_thread_handle: ThreadHandle,
+        process: ProcessHandle,
+        timestamp: Timestamp,
+        timestamp_mono: u64,
+        stack: UnresolvedStackHandle,
+        bytes: i64,
+    ) {
+        self.samples_and_markers.push(UnresolvedSampleOrMarker {
+            thread_handle: main_thread_handle,
+            timestamp,
+            timestamp_mono,
+            stack,
+            extra_label_frame: None,
+            sample_or_marker: SampleOrMarker::Allocation(AllocationData { process, bytes }),
+        });
+    }
+
     pub fn attach_stack_to_marker(
         &mut self,
         thread_handle: ThreadHandle,
@@ -145,6 +242,7 @@ pub struct UnresolvedSampleOrMarker {
 #[derive(Debug, Clone)]
 pub enum SampleOrMarker {
     Sample(SampleData),
+    Allocation(AllocationData),
     MarkerHandle(MarkerHandle),
 }
 
@@ -152,6 +250,14 @@ pub enum SampleOrMarker {
 pub struct SampleData {
     pub cpu_delta: CpuDelta,
     pub weight: i32,
+    /// Whether the sample stands for time which the thread spent switched out
+    pub is_off_cpu: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct AllocationData {
+    pub process: ProcessHandle,
+    pub bytes: i64,
 }
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
@@ -0,0 +1,39 @@
+use fxprof_processed_profile::{
+    Category, CategoryColor, Marker, MarkerField, MarkerLocations, Profile, Schema, StringHandle,
+};
+
+/// A thread was made ready to run by another thread, e.g. by releasing a lock
+/// or signalling a condition variable it was waiting on. The marker is on the
+/// woken thread. `/query/wait-graph` builds the wait-for graph from these.
+#[derive(Debug, Clone)]
+pub struct WakeupMarker {
+    pub waker_tid: u32,
+}
+
+impl Marker for WakeupMarker {
+    type FieldsType = f64;
+
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "Wakeup";
+
+    const CATEGORY: Category<'static> = Category("Scheduling", CategoryColor::Grey);
+    const DESCRIPTION: Option<&'static str> =
+        Some("Emitted when the thread is made ready to run by another thread.");
+
+    const LOCATIONS: MarkerLocations =
+        MarkerLocations::MARKER_CHART.union(MarkerLocations::MARKER_TABLE);
+
+    const CHART_LABEL: Option<&'static str> = Some("{marker.data.waker_tid}");
+    const TOOLTIP_LABEL: Option<&'static str> = Some("Woken by thread {marker.data.waker_tid}");
+    const TABLE_LABEL: Option<&'static str> = Some("Woken by thread {marker.data.waker_tid}");
+
+    const FIELDS: Schema<Self::FieldsType> =
+        Schema(MarkerField::integer("waker_tid", "Woken by thread"));
+
+    fn name(&self, profile: &mut Profile) -> StringHandle {
+        profile.handle_for_string("Wakeup")
+    }
+
+    fn field_values(&self) -> f64 {
+        self.waker_tid as f64
+    }
+}
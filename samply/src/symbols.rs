@@ -1,11 +1,13 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use platform_dirs::AppDirs;
 use samply_quota_manager::QuotaManager;
+use tokio::runtime::Handle;
 use wholesym::{SymbolManager, SymbolManagerConfig};
 
 use crate::name::SAMPLY_NAME;
+use crate::shared::lookup_cache::LookupCache;
 use crate::shared::prop_types::SymbolProps;
 use crate::shared::symbol_manager_observer::SamplySymbolManagerObserver;
 
@@ -33,18 +35,33 @@ fn create_quota_manager(symbols_dir: &Path) -> Option<QuotaManager> {
     Some(quota_manager)
 }
 
+/// The directory of the symbol cache, which the quota manager manages
+fn symbols_dir() -> Option<PathBuf> {
+    let cache_base_dir = AppDirs::new(Some(SAMPLY_NAME), false).map(|dirs| dirs.cache_dir);
+    cache_base_dir.map(|cache_base_dir| cache_base_dir.join("symbols"))
+}
+
+/// The cache of presymbolication's lookups, in the symbol cache, so that the
+/// quota manager evicts its files too.
+pub fn lookup_cache(quota_manager: Option<&QuotaManager>) -> Option<Arc<LookupCache>> {
+    let dir = symbols_dir()?.join("lookups");
+    let notifier = quota_manager.map(QuotaManager::notifier);
+    Some(Arc::new(LookupCache::new(dir, notifier)))
+}
+
 fn create_symbol_manager_config_and_quota_manager(
     symbol_props: SymbolProps,
 ) -> (SymbolManagerConfig, Option<QuotaManager>) {
     let _config_dir = AppDirs::new(Some(SAMPLY_NAME), true).map(|dirs| dirs.config_dir);
-    let cache_base_dir = AppDirs::new(Some(SAMPLY_NAME), false).map(|dirs| dirs.cache_dir);
-    let symbols_dir = cache_base_dir.map(|cache_base_dir| cache_base_dir.join("symbols"));
+    let symbols_dir = symbols_dir();
     let symbols_dir = symbols_dir.as_deref();
 
     let mut config = SymbolManagerConfig::new()
         .respect_nt_symbol_path(true)
         .use_debuginfod(std::env::var("SAMPLY_USE_DEBUGINFOD").is_ok())
-        .use_spotlight(true);
+        .use_spotlight(true)
+        .offline(symbol_props.offline)
+        .max_requests_per_origin(symbol_props.max_symbol_requests);
 
     let quota_manager = match &symbols_dir {
         Some(symbols_dir) => create_quota_manager(symbols_dir),
@@ -96,11 +113,17 @@ fn create_symbol_manager_config_and_quota_manager(
     (config, quota_manager)
 }
 
+/// Create the symbol manager, and the quota manager of its cache, whose
+/// eviction task runs on `runtime`.
 pub fn create_symbol_manager_and_quota_manager(
+    runtime: &Handle,
     symbol_props: SymbolProps,
     verbose: bool,
 ) -> (SymbolManager, Option<QuotaManager>) {
-    let (config, quota_manager) = create_symbol_manager_config_and_quota_manager(symbol_props);
+    let (config, quota_manager) = {
+        let _guard = runtime.enter();
+        create_symbol_manager_config_and_quota_manager(symbol_props)
+    };
     let mut symbol_manager = SymbolManager::with_config(config);
     let notifiers = match &quota_manager {
         Some(mgr) => vec![mgr.notifier()],
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::fmt::Display;
 
@@ -16,6 +16,19 @@ use crate::windows::profile_context::ProfileContext;
 
 const CORE_CLR_GC_CATEGORY: Category<'static> = Category("CoreCLR GC", CategoryColor::Red);
 
+/// A method from MethodLoadVerbose or a rundown, which is added to the
+/// profile once all events are processed: the rundown lists the modules after
+/// their methods.
+struct PendingMethodLoad {
+    timestamp_raw: u64,
+    pid: u32,
+    method_name: String,
+    module_id: u64,
+    start_address: u64,
+    size: u32,
+    loaded_before_trace: bool,
+}
+
 struct SavedMarkerInfo {
     start_timestamp_raw: u64,
     name: String,
@@ -27,6 +40,12 @@ pub struct CoreClrContext {
     last_marker_on_thread: HashMap<u32, (ThreadHandle, MarkerHandle)>,
     gc_markers_on_thread: HashMap<u32, HashMap<&'static str, SavedMarkerInfo>>,
     unknown_event_markers: bool,
+    /// Assembly names by (pid, ModuleID), from the loader events.
+    module_names: HashMap<(u32, u64), String>,
+    /// (pid, start address) of the methods which were loaded so far, so that
+    /// the end rundown only adds the methods which were loaded before the trace.
+    loaded_methods: HashSet<(u32, u64)>,
+    pending_method_loads: Vec<PendingMethodLoad>,
 }
 
 impl CoreClrContext {
@@ -36,6 +55,28 @@ impl CoreClrContext {
             last_marker_on_thread: HashMap::new(),
             gc_markers_on_thread: HashMap::new(),
             unknown_event_markers: profile_creation_props.unknown_event_markers,
+            module_names: HashMap::new(),
+            loaded_methods: HashSet::new(),
+            pending_method_loads: Vec::new(),
+        }
+    }
+
+    /// Add the methods, now that the names of their modules are known.
+    pub fn finish(self, context: &mut ProfileContext) {
+        for method in self.pending_method_loads {
+            let module_name = self
+                .module_names
+                .get(&(method.pid, method.module_id))
+                .map(String::as_str);
+            context.handle_coreclr_method_load(
+                method.timestamp_raw,
+                method.pid,
+                method.method_name,
+                module_name,
+                method.start_address,
+                method.size,
+                method.loaded_before_trace,
+            );
         }
     }
 
@@ -115,6 +156,7 @@ mod constants {
     pub const CORECLR_JIT_KEYWORD: u64 = 0x10; // https://learn.microsoft.com/en-us/dotnet/fundamentals/diagnostics/runtime-method-events
     pub const CORECLR_NGEN_KEYWORD: u64 = 0x20; // https://learn.microsoft.com/en-us/dotnet/fundamentals/diagnostics/runtime-method-events
     pub const CORECLR_RUNDOWN_START_KEYWORD: u64 = 0x00000040;
+    pub const CORECLR_RUNDOWN_END_KEYWORD: u64 = 0x00000100;
     pub const CORECLR_INTEROP_KEYWORD: u64 = 0x2000; // https://learn.microsoft.com/en-us/dotnet/fundamentals/diagnostics/runtime-interop-events
     pub const CORECLR_CONTENTION_KEYWORD: u64 = 0x4000;
     pub const CORECLR_EXCEPTION_KEYWORD: u64 = 0x8000; // https://learn.microsoft.com/en-us/dotnet/fundamentals/diagnostics/runtime-exception-events
@@ -288,7 +330,7 @@ impl<T: Display> Display for DisplayUnknownIfNone<'_, T> {
 pub fn coreclr_xperf_args(props: &ElevatedRecordingProps) -> Vec<String> {
     let mut providers = vec![];
 
-    if !props.coreclr.any_enabled() {
+    if !props.coreclr.any_enabled() && !props.clr {
         return providers;
     }
 
@@ -312,11 +354,17 @@ pub fn coreclr_xperf_args(props: &ElevatedRecordingProps) -> Vec<String> {
     let verbose_keywords = CORECLR_JIT_KEYWORD | CORECLR_NGEN_KEYWORD;
 
     // if we're attaching, ask for a rundown of method info at the start of collection
-    let rundown_verbose_keywords = if props.is_attach {
+    let mut rundown_verbose_keywords = if props.is_attach {
         CORECLR_LOADER_KEYWORD | CORECLR_JIT_KEYWORD | CORECLR_RUNDOWN_START_KEYWORD
     } else {
         0
     };
+    // with --clr, also ask for one at the end, which lists the methods that were
+    // compiled before collection started and the modules of all methods
+    if props.clr {
+        rundown_verbose_keywords |=
+            CORECLR_LOADER_KEYWORD | CORECLR_JIT_KEYWORD | CORECLR_RUNDOWN_END_KEYWORD;
+    }
 
     if props.coreclr.gc_detailed_allocs {
         info_keywords |= CORECLR_GC_SAMPLED_OBJECT_ALLOCATION_HIGH_KEYWORD
@@ -351,6 +399,19 @@ pub fn coreclr_xperf_args(props: &ElevatedRecordingProps) -> Vec<String> {
     providers
 }
 
+/// `C:\app\bin\MyApp.Core.dll` -> "MyApp.Core"
+fn assembly_name(module_il_path: &str) -> String {
+    let file_name = module_il_path
+        .rsplit(['\\', '/'])
+        .next()
+        .unwrap_or(module_il_path);
+    let name = match file_name.rsplit_once('.') {
+        Some((stem, "dll" | "exe" | "DLL" | "EXE")) => stem,
+        _ => file_name,
+    };
+    name.to_string()
+}
+
 pub fn handle_coreclr_event(
     context: &mut ProfileContext,
     coreclr_context: &mut CoreClrContext,
@@ -406,7 +467,7 @@ pub fn handle_coreclr_event(
             // there's MethodDCStart & MethodDCStartVerbose & MethodLoad
             // difference between *Verbose and not, is Verbose includes the names
 
-            "MethodLoadVerbose" | "MethodDCStartVerbose"
+            "MethodLoadVerbose" | "MethodDCStartVerbose" | "MethodDCEndVerbose"
             // | "R2RGetEntryPoint" // not sure we need this? R2R methods should be covered by PDB files
             => {
                 // R2RGetEntryPoint shares a lot of fields with MethodLoadVerbose
@@ -414,6 +475,7 @@ pub fn handle_coreclr_event(
 
                 //let method_id: u64 = parser.parse("MethodID");
                 //let clr_instance_id: u32 = parser.parse("ClrInstanceID"); // v1/v2 only
+                let module_id: u64 = parser.parse("ModuleID");
 
                 let method_basename: String = parser.parse("MethodName");
                 let method_namespace: String = parser.parse("MethodNamespace");
@@ -434,21 +496,22 @@ pub fn handle_coreclr_event(
 
                 let method_name = format!("{method_basename} [{method_namespace}] \u{2329}{method_signature}\u{232a}");
 
-                context.handle_coreclr_method_load(timestamp_raw, pid, method_name, method_start_address, method_size);
-                handled = true;
-            }
-            "ModuleLoad" | "ModuleDCStart" |
-            "ModuleUnload" | "ModuleDCEnd" => {
-                // do we need this for ReadyToRun code?
-
-                //let module_id: u64 = parser.parse("ModuleID");
-                //let assembly_id: u64 = parser.parse("AssemblyId");
-                //let managed_pdb_signature: u?? = parser.parse("ManagedPdbSignature");
-                //let managed_pdb_age: u?? = parser.parse("ManagedPdbAge");
-                //let managed_pdb_path: String = parser.parse("ManagedPdbPath");
-                //let native_pdb_signature: u?? = parser.parse("NativePdbSignature");
-                //let native_pdb_age: u?? = parser.parse("NativePdbAge");
-                //let native_pdb_path: String = parser.parse("NativePdbPath");
+                // The end rundown lists every method which is still loaded when the trace stops;
+                // only the ones which were loaded before the trace started are new. They were
+                // there all along, so they are mapped from the start.
+                let is_new = coreclr_context.loaded_methods.insert((pid, method_start_address));
+                if method_event == "MethodDCEndVerbose" && !is_new {
+                    return;
+                }
+                coreclr_context.pending_method_loads.push(PendingMethodLoad {
+                    timestamp_raw,
+                    pid,
+                    method_name,
+                    module_id,
+                    start_address: method_start_address,
+                    size: method_size,
+                    loaded_before_trace: method_event == "MethodDCEndVerbose",
+                });
                 handled = true;
             }
             _ => {
@@ -457,6 +520,25 @@ pub fn handle_coreclr_event(
             }
         }
         }
+        ("CLRLoader" | "CLRLoaderRundown", "ModuleLoad" | "ModuleDCStart" | "ModuleDCEnd") => {
+            // ReadyToRun code is covered by the PDB files of the images. For JIT-compiled
+            // methods, the module gives the assembly which they belong to.
+
+            let module_id: u64 = parser.parse("ModuleID");
+            let module_il_path: String = parser.parse("ModuleILPath");
+            //let assembly_id: u64 = parser.parse("AssemblyID");
+            //let managed_pdb_signature: u?? = parser.parse("ManagedPdbSignature");
+            //let managed_pdb_age: u?? = parser.parse("ManagedPdbAge");
+            //let managed_pdb_path: String = parser.parse("ManagedPdbPath");
+            //let native_pdb_signature: u?? = parser.parse("NativePdbSignature");
+            //let native_pdb_age: u?? = parser.parse("NativePdbAge");
+            //let native_pdb_path: String = parser.parse("NativePdbPath");
+            coreclr_context
+                .module_names
+                .entry((pid, module_id))
+                .or_insert_with(|| assembly_name(&module_il_path));
+            handled = true;
+        }
         ("Type", "BulkType") => {
             //         <template tid="BulkType">
             // <data name="Count" inType="win:UInt32"    />
@@ -24,6 +24,7 @@ pub struct ElevatedRecordingProps {
     pub time_limit_seconds: Option<f64>,
     pub interval_nanos: u64,
     pub coreclr: CoreClrProfileProps,
+    pub clr: bool,
     pub vm_hack: bool,
     pub is_attach: bool,
     pub gfx: bool,
@@ -40,6 +41,7 @@ impl ElevatedRecordingProps {
             time_limit_seconds: recording_props.time_limit.map(|l| l.as_secs_f64()),
             interval_nanos: recording_props.interval.as_nanos().try_into().unwrap(),
             coreclr: profile_creation_props.coreclr,
+            clr: recording_props.clr,
             vm_hack: recording_props.vm_hack,
             is_attach: recording_mode.is_attach_mode(),
             gfx: recording_props.gfx,
@@ -8,32 +8,47 @@ use fxprof_processed_profile::debugid;
 use uuid::Uuid;
 
 use super::coreclr::CoreClrContext;
+use super::etw_reader::etw_types::EventRecord;
 use super::etw_reader::parser::{Address, Parser, TryParse};
 use super::etw_reader::schema::SchemaLocator;
 use super::etw_reader::{
-    add_custom_schemas, event_properties_to_string, open_trace, print_property, GUID,
+    add_custom_schemas, event_properties_to_string, open_realtime_trace, open_trace,
+    print_property, GUID,
 };
 use super::profile_context::ProfileContext;
+use super::stack_cache::StackCache;
+use super::trace_contents::TraceContents;
+use crate::shared::live_profile::LiveProfile;
+use crate::shared::progress::Progress;
 use crate::windows::coreclr;
 use crate::windows::profile_context::PeInfo;
 
+/// Convert the events of the ETL files. With `stack_caching`, the stacks of
+/// samples which refer to cached stacks are resolved, see [`StackCache`].
 pub fn process_etl_files(
     context: &mut ProfileContext,
     etl_file: &Path,
     extra_etl_filenames: &[PathBuf],
-) {
+    stack_caching: bool,
+) -> TraceContents {
     let mut schema_locator = SchemaLocator::new();
     add_custom_schemas(&mut schema_locator);
 
     let processing_start_timestamp = Instant::now();
 
     let mut core_clr_context = CoreClrContext::new(context.creation_props());
+    let progress = Progress::new("import", "events", None);
+    let mut contents = TraceContents::default();
 
     let result = process_trace(
-        etl_file,
+        TraceSource::File(etl_file),
         context,
         &mut schema_locator,
         &mut core_clr_context,
+        &progress,
+        None,
+        stack_caching,
+        &mut contents,
     );
     if result.is_err() {
         dbg!(etl_file);
@@ -43,10 +58,14 @@ pub fn process_etl_files(
 
     for extra_etl_file in extra_etl_filenames {
         let result = process_trace(
-            extra_etl_file,
+            TraceSource::File(extra_etl_file),
             context,
             &mut schema_locator,
             &mut core_clr_context,
+            &progress,
+            None,
+            stack_caching,
+            &mut contents,
         );
         if result.is_err() {
             dbg!(extra_etl_file);
@@ -54,11 +73,55 @@ pub fn process_etl_files(
             std::process::exit(1);
         }
     }
+    core_clr_context.finish(context);
+    progress.finish();
 
     log::info!(
         "Took {} seconds",
         (Instant::now() - processing_start_timestamp).as_secs_f32()
     );
+    contents
+}
+
+/// Convert the events of the running real-time session `session_name` as
+/// they arrive, until the session stops or `stop` returns. With `live`, a
+/// snapshot of the profile is published every few seconds meanwhile.
+pub fn process_etw_session(
+    context: &mut ProfileContext,
+    session_name: &str,
+    live: Option<&LiveProfile>,
+    stack_caching: bool,
+    stop: impl FnOnce() + Send + 'static,
+) -> Result<TraceContents, std::io::Error> {
+    let mut schema_locator = SchemaLocator::new();
+    add_custom_schemas(&mut schema_locator);
+
+    let mut core_clr_context = CoreClrContext::new(context.creation_props());
+    let progress = Progress::new("import", "events", None);
+    let mut contents = TraceContents::default();
+
+    let result = process_trace(
+        TraceSource::Session(session_name, Box::new(stop)),
+        context,
+        &mut schema_locator,
+        &mut core_clr_context,
+        &progress,
+        live,
+        stack_caching,
+        &mut contents,
+    );
+    core_clr_context.finish(context);
+    progress.finish();
+    result.map(|()| contents)
+}
+
+/// Where the events of a trace come from
+enum TraceSource<'a> {
+    /// An .etl file
+    File(&'a Path),
+    /// A running real-time session, by name, and the function which returns
+    /// when to stop consuming it
+    Session(&'a str, Box<dyn FnOnce() + Send>),
 }
 
 struct PendingImageInfo {
@@ -67,19 +130,30 @@ struct PendingImageInfo {
 }
 
 fn process_trace(
-    etl_file: &Path,
+    source: TraceSource,
     context: &mut ProfileContext,
     schema_locator: &mut SchemaLocator,
     core_clr_context: &mut CoreClrContext,
+    progress: &Progress,
+    live: Option<&LiveProfile>,
+    stack_caching: bool,
+    contents: &mut TraceContents,
 ) -> Result<(), std::io::Error> {
     let is_arm64 = context.is_arm64();
+    let mut stack_cache = StackCache::default();
     let demand_zero_faults = false; //pargs.contains("--demand-zero-faults");
     let mut pending_image_info: Option<((u32, u64), PendingImageInfo)> = None;
 
     // Cache for Chrome measure names by (tid, traceId).
     let mut measure_name_cache: HashMap<(u32, u64), String> = HashMap::new();
 
-    open_trace(etl_file, |e| {
+    let on_event = |e: &EventRecord| {
+        progress.inc(1);
+        if let Some(live) = live {
+            if live.is_snapshot_due() {
+                live.publish(context.snapshot());
+            }
+        }
         let Ok(s) = schema_locator.event_schema(e) else {
             return;
         };
@@ -139,6 +213,7 @@ fn process_trace(
                 context.handle_thread_dcend(timestamp_raw, tid);
             }
             "MSNT_SystemTrace/Process/DCStart" => {
+                contents.processes += 1;
                 // note: the event's e.EventHeader.process_id here is the parent (i.e. the process that spawned
                 // a new one. The process_id in ProcessId is the new process id.
                 // XXXmstange then what about "ParentId"? Is it the same as e.EventHeader.process_id?
@@ -155,6 +230,7 @@ fn process_trace(
                 );
             }
             "MSNT_SystemTrace/Process/Start" => {
+                contents.processes += 1;
                 // note: the event's e.EventHeader.process_id here is the parent (i.e. the process that spawned
                 // a new one. The process_id in ProcessId is the new process id.
                 // XXXmstange then what about "ParentId"? Is it the same as e.EventHeader.process_id?
@@ -182,6 +258,7 @@ fn process_trace(
                 // nothing, but we don't want a marker for it
             }
             "MSNT_SystemTrace/StackWalk/Stack" => {
+                contents.stacks += 1;
                 if !context.is_in_time_range(timestamp_raw) {
                     return;
                 }
@@ -213,7 +290,43 @@ fn process_trace(
                     );
                 }
             }
+            "MSNT_SystemTrace/StackWalk/KeyKernel" | "MSNT_SystemTrace/StackWalk/KeyUser" => {
+                contents.stack_key_refs += 1;
+                if !stack_caching || !context.is_in_time_range(timestamp_raw) {
+                    return;
+                }
+                let tid: u32 = parser.parse("StackThread");
+                let pid: u32 = parser.parse("StackProcess");
+                let referenced_timestamp_raw: u64 = parser.parse("EventTimeStamp");
+                let key: Address = parser.parse("StackKey");
+                stack_cache.add_ref(referenced_timestamp_raw, pid, tid, key.as_u64());
+            }
+            "MSNT_SystemTrace/StackWalk/KeyCreate"
+            | "MSNT_SystemTrace/StackWalk/KeyDelete"
+            | "MSNT_SystemTrace/StackWalk/KeyRundown" => {
+                contents.stack_key_defs += 1;
+                if !stack_caching {
+                    return;
+                }
+                let key: Address = parser.parse("StackKey");
+                let frames = parser
+                    .buffer
+                    .chunks_exact(8)
+                    .map(|a| u64::from_ne_bytes(a.try_into().unwrap()))
+                    .collect();
+                match s.name() {
+                    "MSNT_SystemTrace/StackWalk/KeyCreate" => {
+                        stack_cache.create_key(key.as_u64(), frames)
+                    }
+                    name => stack_cache.define_key(
+                        key.as_u64(),
+                        frames,
+                        name == "MSNT_SystemTrace/StackWalk/KeyDelete",
+                    ),
+                }
+            }
             "MSNT_SystemTrace/PerfInfo/SampleProf" => {
+                contents.cpu_samples += 1;
                 if !context.is_in_time_range(timestamp_raw) {
                     return;
                 }
@@ -332,6 +445,7 @@ fn process_trace(
                 context.handle_vsync(timestamp_raw);
             }
             "MSNT_SystemTrace/Thread/CSwitch" => {
+                contents.context_switches += 1;
                 if !context.is_in_time_range(timestamp_raw) {
                     return;
                 }
@@ -346,7 +460,8 @@ fn process_trace(
                     return;
                 }
                 // these events can give us the unblocking stack
-                let _thread_id: u32 = parser.parse("TThreadId");
+                let readied_tid: u32 = parser.parse("TThreadId");
+                context.handle_ready_thread(timestamp_raw, readied_tid, s.thread_id());
             }
             "V8.js/SourceLoad/Start"
             | "Microsoft-JScript/ScriptContextRuntime/SourceLoad"
@@ -586,5 +701,31 @@ fn process_trace(
                 context.handle_unknown_event(timestamp_raw, tid, task_and_op, text);
             }
         }
-    })
+    };
+    let result = match source {
+        TraceSource::File(etl_file) => open_trace(etl_file, on_event),
+        TraceSource::Session(session_name, stop) => {
+            open_realtime_trace(session_name, on_event, stop)
+        }
+    };
+
+    // The stacks of cached keys are only known once the keys are deleted or
+    // rundown, so the samples get their stacks after the whole trace.
+    let unresolved = stack_cache.resolve(|timestamp_raw, pid, tid, frames| {
+        if is_arm64 {
+            context.handle_stack_arm64(timestamp_raw, pid, tid, frames.iter().copied());
+        } else {
+            context.handle_stack_x86(
+                timestamp_raw,
+                pid,
+                tid,
+                frames.len(),
+                frames.iter().copied(),
+            );
+        }
+    });
+    if unresolved != 0 {
+        log::warn!("{unresolved} samples refer to cached stacks which the trace doesn't define");
+    }
+    result
 }
@@ -6,7 +6,7 @@ use memoffset::offset_of;
 pub use windows::core::GUID;
 use windows::core::{h, HSTRING, PWSTR};
 use windows::Win32::Foundation::{
-    GetLastError, ERROR_INSUFFICIENT_BUFFER, ERROR_MORE_DATA, MAX_PATH,
+    GetLastError, ERROR_CANCELLED, ERROR_INSUFFICIENT_BUFFER, ERROR_MORE_DATA, MAX_PATH,
 };
 use windows::Win32::System::Diagnostics::Etw;
 use windows::Win32::System::Diagnostics::Etw::{
@@ -93,6 +93,50 @@ pub fn open_trace<F: FnMut(&EventRecord)>(
         .map_err(|e| std::io::Error::from_raw_os_error(e.code().0))
 }
 
+/// Consume the events of the running real-time session `session_name`, e.g.
+/// one started by WPR or xperf, as they arrive. Returns when the session
+/// stops, or once `stop`, which is called on another thread, returns.
+pub fn open_realtime_trace<F: FnMut(&EventRecord)>(
+    session_name: &str,
+    mut callback: F,
+    stop: impl FnOnce() + Send + 'static,
+) -> Result<(), std::io::Error> {
+    let mut log_file = EventTraceLogfile::default();
+
+    let session_name = HSTRING::from(session_name);
+    log_file.0.LoggerName = PWSTR(session_name.as_ptr() as *mut _);
+    log_file.0.Anonymous1.ProcessTraceMode = Etw::PROCESS_TRACE_MODE_REAL_TIME
+        | Etw::PROCESS_TRACE_MODE_EVENT_RECORD
+        | Etw::PROCESS_TRACE_MODE_RAW_TIMESTAMP;
+    let mut cb: &mut dyn FnMut(&EventRecord) = &mut callback;
+    log_file.0.Context = unsafe { std::mem::transmute(&mut cb) };
+    log_file.0.Anonymous2.EventRecordCallback = Some(trace_callback_thunk);
+
+    let session_handle = unsafe { Etw::OpenTraceW(&mut *log_file) };
+    if session_handle.Value == INVALID_TRACE_HANDLE {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    // Closing the trace makes ProcessTrace return once it has delivered the
+    // buffered events.
+    let handle_value = session_handle.Value;
+    std::thread::spawn(move || {
+        stop();
+        let handle = Etw::PROCESSTRACE_HANDLE {
+            Value: handle_value,
+        };
+        let _ = unsafe { Etw::CloseTrace(handle) };
+    });
+
+    let result = unsafe { Etw::ProcessTrace(&[session_handle], None, None) };
+    if result == ERROR_CANCELLED {
+        return Ok(());
+    }
+    result
+        .ok()
+        .map_err(|e| std::io::Error::from_raw_os_error(e.code().0))
+}
+
 /// Complete Trace Properties struct
 ///
 /// The [EventTraceProperties] struct contains the information about a tracing session, this struct
@@ -3,10 +3,19 @@ use std::path::Path;
 use fxprof_processed_profile::{Profile, ReferenceTimestamp, SamplingInterval};
 
 use super::etw_gecko;
+use super::trace_contents::Error;
+use crate::shared::ctrl_c::CtrlC;
+use crate::shared::live_profile::LiveProfile;
 use crate::shared::prop_types::ImportProps;
 use crate::windows::profile_context::ProfileContext;
 
-pub fn convert_etl_file_to_profile(filename: &Path, import_props: ImportProps) -> Profile {
+/// Convert the ETL file and the `user_etl` files of `import_props`. Fails if
+/// the trace lacks the events which the samples are made from, see
+/// [`TraceContents::check`](super::trace_contents::TraceContents::check).
+pub fn convert_etl_file_to_profile(
+    filename: &Path,
+    import_props: ImportProps,
+) -> Result<Profile, Error> {
     let timebase = std::time::SystemTime::now();
     let timebase = ReferenceTimestamp::from_system_time(timebase);
 
@@ -29,9 +38,62 @@ pub fn convert_etl_file_to_profile(filename: &Path, import_props: ImportProps) -
         import_props.time_range,
     );
 
-    etw_gecko::process_etl_files(&mut context, filename, &import_props.user_etl);
+    let contents = etw_gecko::process_etl_files(
+        &mut context,
+        filename,
+        &import_props.user_etl,
+        import_props.etl_stack_caching,
+    );
+    contents.check(
+        import_props.etl_sample_source,
+        import_props.etl_stack_caching,
+    )?;
+
+    Ok(context.finish())
+}
+
+/// Import the events of the running ETW real-time session `session_name` as
+/// they arrive, until the session stops or Ctrl+C is pressed. With `live`, a
+/// snapshot of the profile is published every few seconds meanwhile.
+///
+/// Processes and libraries which were loaded before the import attached are
+/// only known if the session logs their rundown events, e.g. when it stops.
+pub fn import_etw_session(
+    session_name: &str,
+    import_props: ImportProps,
+    live: Option<&LiveProfile>,
+) -> Result<Profile, Error> {
+    let timebase = std::time::SystemTime::now();
+    let timebase = ReferenceTimestamp::from_system_time(timebase);
+
+    let interval_8khz = SamplingInterval::from_nanos(122100); // 8192Hz
+    let profile = Profile::new(
+        import_props.profile_creation_props.profile_name(),
+        timebase,
+        interval_8khz,
+    );
+
+    let etl_sample_source = import_props.etl_sample_source;
+    let stack_caching = import_props.etl_stack_caching;
+    let mut context = ProfileContext::new(
+        profile,
+        get_native_arch(),
+        import_props.included_processes,
+        import_props.profile_creation_props,
+        import_props.time_range,
+    );
+
+    eprintln!("Consuming the events of the ETW session {session_name:?}.");
+    eprintln!("Press Ctrl+C to stop.");
+    let ctrl_c_receiver = CtrlC::observe_oneshot();
+    let stop = move || {
+        let _ = ctrl_c_receiver.blocking_recv();
+    };
+    let contents =
+        etw_gecko::process_etw_session(&mut context, session_name, live, stack_caching, stop)?;
+    contents.check(etl_sample_source, stack_caching)?;
 
-    context.finish()
+    Ok(context.finish())
 }
 
 #[cfg(target_arch = "x86")]
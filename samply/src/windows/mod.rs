@@ -9,6 +9,8 @@ mod gfx;
 pub mod import;
 mod profile_context;
 pub mod profiler;
+mod stack_cache;
+mod trace_contents;
 mod utility_process;
 mod winutils;
 mod xperf;
@@ -17,6 +17,7 @@ use crate::shared::context_switch::{
     ContextSwitchHandler, OffCpuSampleGroup, ThreadContextSwitchData,
 };
 use crate::shared::included_processes::IncludedProcesses;
+use crate::shared::included_threads::IncludedThreads;
 use crate::shared::jit_category_manager::{JitCategoryManager, JsFrame};
 use crate::shared::jit_function_add_marker::JitFunctionAddMarker;
 use crate::shared::jit_function_recycler::JitFunctionRecycler;
@@ -32,6 +33,7 @@ use crate::shared::types::{StackFrame, StackMode};
 use crate::shared::unresolved_samples::{
     UnresolvedSamples, UnresolvedStackHandle, UnresolvedStacks,
 };
+use crate::shared::wakeup_marker::WakeupMarker;
 use crate::windows::firefox::{
     PHASE_INSTANT, PHASE_INTERVAL, PHASE_INTERVAL_END, PHASE_INTERVAL_START,
 };
@@ -220,6 +222,29 @@ impl Processes {
             .collect()
     }
 
+    /// The sample data of all processes so far, for a snapshot of a profile
+    /// which is still being imported
+    pub fn sample_data_snapshot(&self) -> Vec<ProcessSampleData> {
+        self.processes
+            .iter()
+            .map(|process| {
+                let jitdump_lib_mapping_op_queues = if !process.jit_lib_mapping_ops.is_empty() {
+                    vec![process.jit_lib_mapping_ops.clone()]
+                } else {
+                    Vec::new()
+                };
+
+                ProcessSampleData::new(
+                    process.unresolved_samples.clone(),
+                    process.regular_lib_mapping_ops.clone(),
+                    jitdump_lib_mapping_op_queues,
+                    None,
+                    Vec::new(),
+                )
+            })
+            .collect()
+    }
+
     pub fn has(&self, pid: u32) -> bool {
         self.processes_by_pid.contains_key(&pid)
     }
@@ -347,7 +372,7 @@ impl Process {
     ) {
         let relative_address = jit_lib.add_function(name, size);
 
-        self.jit_lib_mapping_ops.push(
+        self.jit_lib_mapping_ops.insert(
             timestamp_raw,
             LibMappingOp::Add(LibMappingAdd {
                 start_avma,
@@ -462,6 +487,10 @@ pub struct ProfileContext {
     // the profile.json. If it's None, include everything.
     included_processes: Option<IncludedProcesses>,
 
+    // The threads whose samples go into the profile.json, from
+    // --include-thread and --exclude-thread.
+    included_threads: IncludedThreads,
+
     categories: KnownCategories,
 
     known_images: HashMap<(String, u32, u32), (LibraryHandle, KnownCategory)>,
@@ -569,6 +598,7 @@ impl ProfileContext {
             process_recycler,
             gpu_thread_handle: None,
             included_processes,
+            included_threads: IncludedThreads::default(),
             categories,
             known_images: HashMap::new(),
             js_category_manager,
@@ -729,6 +759,7 @@ impl ProfileContext {
             Shlex::new(cmdline).collect(),
             self.profile_creation_props
                 .arg_count_to_include_in_process_name,
+            &self.profile_creation_props.name_rules,
         )
     }
 
@@ -1022,6 +1053,11 @@ impl ProfileContext {
         if name.is_empty() {
             return;
         }
+        let name = self
+            .profile_creation_props
+            .name_rules
+            .thread_name(&name)
+            .into_owned();
         let Some(thread) = self.threads.get_by_tid(tid) else {
             return;
         };
@@ -1116,6 +1152,9 @@ impl ProfileContext {
         let Some(thread) = self.threads.get_by_tid(tid) else {
             return;
         };
+        if !self.included_threads.should_include(thread.name.as_deref()) {
+            return;
+        }
 
         // On ARM64, this seems to be simpler -- stacks come in with full kernel and user frames.
         // At least, I've never seen a kernel stack come in separately.
@@ -1363,6 +1402,10 @@ impl ProfileContext {
         let Some(thread) = self.threads.get_by_tid(tid) else {
             return;
         };
+        if !self.included_threads.should_include(thread.name.as_deref()) {
+            // The stack which follows the sample finds no pending sample and is dropped.
+            return;
+        }
 
         let off_cpu_sample_group = self
             .context_switch_handler
@@ -1568,6 +1611,24 @@ impl ProfileContext {
             .add_marker(*gpu_thread, MarkerTiming::Instant(timestamp), VSyncMarker);
     }
 
+    /// `waker_tid` made `readied_tid` ready to run, e.g. by releasing a lock it
+    /// was waiting on. Emitted as a wakeup marker along with the context
+    /// switch markers.
+    pub fn handle_ready_thread(&mut self, timestamp_raw: u64, readied_tid: u32, waker_tid: u32) {
+        if !self.profile_creation_props.should_emit_cswitch_markers {
+            return;
+        }
+        let Some(thread_handle) = self.threads.get_by_tid(readied_tid).map(|t| t.handle) else {
+            return;
+        };
+        let timestamp = self.timestamp_converter.convert_time(timestamp_raw);
+        self.profile.add_marker(
+            thread_handle,
+            MarkerTiming::Instant(timestamp),
+            WakeupMarker { waker_tid },
+        );
+    }
+
     pub fn handle_cswitch(
         &mut self,
         timestamp_raw: u64,
@@ -1765,23 +1826,43 @@ impl ProfileContext {
         );
     }
 
+    /// The method's frames get a subcategory for its assembly, `module_name`.
+    /// Methods from the end rundown which were `loaded_before_trace` are mapped
+    /// from the start of the trace.
+    #[allow(clippy::too_many_arguments)]
     pub fn handle_coreclr_method_load(
         &mut self,
         timestamp_raw: u64,
         pid: u32,
         method_name: String,
+        module_name: Option<&str>,
         method_start_address: u64,
         method_size: u32,
+        loaded_before_trace: bool,
     ) {
         let Some(process) = self.processes.get_by_pid_and_timestamp(pid, timestamp_raw) else {
             return;
         };
 
         let lib = &mut self.coreclr_jit_lib;
-        let info = LibMappingInfo::new_jit_function(lib.lib_handle(), lib.default_category(), None);
+        let category = match module_name {
+            Some(module_name) => {
+                let category = self
+                    .categories
+                    .get(KnownCategory::CoreClrJit, &mut self.profile);
+                self.profile.handle_for_subcategory(category, module_name)
+            }
+            None => lib.default_category(),
+        };
+        let info = LibMappingInfo::new_jit_function(lib.lib_handle(), category, None);
+        let mapping_timestamp_raw = if loaded_before_trace {
+            0
+        } else {
+            timestamp_raw
+        };
 
         process.add_jit_function(
-            timestamp_raw,
+            mapping_timestamp_raw,
             lib,
             method_name,
             method_start_address,
@@ -2005,6 +2086,33 @@ impl ProfileContext {
         self.profile.set_os_name(os_name);
     }
 
+    pub fn set_included_threads(&mut self, included_threads: IncludedThreads) {
+        self.included_threads = included_threads;
+    }
+
+    /// A copy of the profile with the samples so far, for `samply import
+    /// --etw-session`, which serves the profile while it's being imported.
+    /// JIT functions don't have their names yet.
+    pub fn snapshot(&mut self) -> Profile {
+        let user_category = self.categories.get(KnownCategory::User, &mut self.profile);
+        let kernel_category = self
+            .categories
+            .get(KnownCategory::Kernel, &mut self.profile);
+
+        let mut profile = self.profile.clone();
+        let mut stack_frame_scratch_buf = Vec::new();
+        for process_sample_data in self.processes.sample_data_snapshot() {
+            process_sample_data.flush_samples_to_profile(
+                &mut profile,
+                user_category.into(),
+                kernel_category.into(),
+                &mut stack_frame_scratch_buf,
+                &self.unresolved_stacks,
+            )
+        }
+        profile
+    }
+
     pub fn finish(mut self) -> Profile {
         // Push queued samples into the profile.
         // We queue them so that we can get symbolicated JIT function names. To get symbolicated JIT function names,
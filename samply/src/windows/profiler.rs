@@ -1,5 +1,6 @@
 use std::os::windows::process::ExitStatusExt;
 use std::process::ExitStatus;
+use std::time::Duration;
 
 use fxprof_processed_profile::{Profile, ReferenceTimestamp, SamplingInterval};
 
@@ -7,6 +8,7 @@ use super::etw_gecko;
 use super::profile_context::ProfileContext;
 use crate::shared::ctrl_c::CtrlC;
 use crate::shared::included_processes::IncludedProcesses;
+use crate::shared::overhead::OverheadMeter;
 use crate::shared::prop_types::{ProfileCreationProps, RecordingMode, RecordingProps};
 use crate::windows::elevated_helper::ElevatedHelperSession;
 
@@ -50,6 +52,10 @@ pub fn run(
         SamplingInterval::from_nanos(1000000), // will be replaced with correct interval from file later
     );
 
+    // This only measures samply itself. The cost of ETW event collection is
+    // paid in the kernel and by xperf, and is not included.
+    let overhead_meter = OverheadMeter::start();
+
     // Start xperf.
     let mut elevated_helper = ElevatedHelperSession::new(recording_props.output_file.clone())
         .unwrap_or_else(|e| panic!("Couldn't start elevated helper process: {e:?}"));
@@ -62,9 +68,8 @@ pub fn run(
             let ctrl_c_receiver = CtrlC::observe_oneshot();
             eprintln!("Profiling all processes...");
             eprintln!("Press Ctrl+C to stop.");
-            // TODO: Respect recording_props.time_limit, if specified
-            // Wait for Ctrl+C.
-            let _ = ctrl_c_receiver.blocking_recv();
+            // Wait for Ctrl+C, or for the time limit.
+            CtrlC::wait_with_timeout(ctrl_c_receiver, recording_props.stop_after());
             None
         }
         RecordingMode::Pid(pid) => {
@@ -72,9 +77,8 @@ pub fn run(
             // TODO: check that process with this pid exists
             eprintln!("Profiling process with pid {pid}...");
             eprintln!("Press Ctrl+C to stop.");
-            // TODO: Respect recording_props.time_limit, if specified
-            // Wait for Ctrl+C.
-            let _ = ctrl_c_receiver.blocking_recv();
+            // Wait for Ctrl+C, or for the time limit.
+            CtrlC::wait_with_timeout(ctrl_c_receiver, recording_props.stop_after());
             Some(IncludedProcesses {
                 name_substrings: Vec::new(),
                 pids: vec![pid],
@@ -128,6 +132,7 @@ pub fn run(
         .expect("Should have produced a merged ETL file");
 
     elevated_helper.shutdown();
+    let overhead = overhead_meter.finish(0);
 
     eprintln!("Processing ETL trace...");
 
@@ -136,24 +141,35 @@ pub fn run(
         .clone()
         .unwrap_or(get_native_arch().to_string());
 
+    // Launched processes are recorded until they exit, so the time limit is
+    // applied to the trace, along with the delay.
+    let time_range = match (recording_props.delay, recording_props.stop_after()) {
+        (None, None) => None,
+        (delay, stop_after) => Some((
+            delay.unwrap_or_default(),
+            stop_after.unwrap_or(Duration::MAX),
+        )),
+    };
     let mut context = ProfileContext::new(
         profile,
         &arch,
         included_processes,
         profile_creation_props,
-        None,
+        time_range,
     );
+    context.set_included_threads(recording_props.included_threads.clone());
     let extra_etls = match &user_output_file {
         Some(user_etl) => vec![user_etl.clone()],
         None => Vec::new(),
     };
-    etw_gecko::process_etl_files(&mut context, &kernel_output_file, &extra_etls);
+    etw_gecko::process_etl_files(&mut context, &kernel_output_file, &extra_etls, false);
 
     if let Some(win_version) = winver::WindowsVersion::detect() {
         context.set_os_name(&format!("Windows {win_version}"))
     }
 
-    let profile = context.finish();
+    let mut profile = context.finish();
+    profile.set_profiler_overhead(overhead);
 
     if !recording_props.keep_etl {
         std::fs::remove_file(&kernel_output_file).unwrap_or_else(|_| {
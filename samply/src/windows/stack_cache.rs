@@ -0,0 +1,122 @@
+//! The stacks of ETL traces recorded with stack caching (`xperf -stackcaching`
+//! or WPR's `<StackCaching>`). Instead of a StackWalk/Stack event after each
+//! sample, the kernel logs a reference to a key (StackWalk/KeyKernel and
+//! KeyUser), and the stack of the key only once: when the key is evicted from
+//! the cache (KeyDelete), or at the end of the trace (KeyRundown).
+
+use std::collections::HashMap;
+
+/// A sample's reference to a cached stack
+struct StackRef {
+    timestamp_raw: u64,
+    pid: u32,
+    tid: u32,
+    key: u64,
+    /// Index into `StackCache::stacks`, once the key's stack is known
+    stack: Option<usize>,
+}
+
+#[derive(Default)]
+pub struct StackCache {
+    refs: Vec<StackRef>,
+    stacks: Vec<Vec<u64>>,
+    /// The indexes into `refs` of the references which wait for their key's stack
+    pending: HashMap<u64, Vec<usize>>,
+    /// The stacks of the keys which were created and not deleted yet
+    live_keys: HashMap<u64, usize>,
+}
+
+impl StackCache {
+    pub fn add_ref(&mut self, timestamp_raw: u64, pid: u32, tid: u32, key: u64) {
+        let stack = self.live_keys.get(&key).copied();
+        if stack.is_none() {
+            self.pending.entry(key).or_default().push(self.refs.len());
+        }
+        self.refs.push(StackRef {
+            timestamp_raw,
+            pid,
+            tid,
+            key,
+            stack,
+        });
+    }
+
+    /// KeyCreate: the key has this stack until it's deleted.
+    pub fn create_key(&mut self, key: u64, frames: Vec<u64>) {
+        let index = self.add_stack(key, frames);
+        self.live_keys.insert(key, index);
+    }
+
+    /// KeyDelete and KeyRundown: this is the stack of the earlier references
+    /// to the key. A deleted key can be reused for another stack.
+    pub fn define_key(&mut self, key: u64, frames: Vec<u64>, is_delete: bool) {
+        self.add_stack(key, frames);
+        if is_delete {
+            self.live_keys.remove(&key);
+        }
+    }
+
+    fn add_stack(&mut self, key: u64, frames: Vec<u64>) -> usize {
+        let index = self.stacks.len();
+        self.stacks.push(frames);
+        for ref_index in self.pending.remove(&key).unwrap_or_default() {
+            self.refs[ref_index].stack = Some(index);
+        }
+        index
+    }
+
+    /// Call `f` with the timestamp, pid, tid and stack of each reference, in
+    /// the order of the trace. Returns the number of references whose key
+    /// never got a stack, which are left out.
+    pub fn resolve(self, mut f: impl FnMut(u64, u32, u32, &[u64])) -> usize {
+        let mut unresolved = 0;
+        for stack_ref in &self.refs {
+            match stack_ref.stack {
+                Some(index) => f(
+                    stack_ref.timestamp_raw,
+                    stack_ref.pid,
+                    stack_ref.tid,
+                    &self.stacks[index],
+                ),
+                None => {
+                    log::debug!("No stack for stack key {:#x}", stack_ref.key);
+                    unresolved += 1;
+                }
+            }
+        }
+        unresolved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reused_key() {
+        let mut cache = StackCache::default();
+        cache.add_ref(1, 10, 11, 0xa);
+        cache.add_ref(2, 10, 11, 0xa);
+        cache.define_key(0xa, vec![0x100, 0x200], true);
+        cache.add_ref(3, 10, 11, 0xa);
+        cache.create_key(0xb, vec![0x300]);
+        cache.add_ref(4, 10, 11, 0xb);
+        cache.define_key(0xa, vec![0x400], false);
+        cache.add_ref(5, 10, 11, 0xc);
+
+        let mut resolved = Vec::new();
+        let unresolved = cache.resolve(|timestamp_raw, _pid, _tid, frames| {
+            resolved.push((timestamp_raw, frames.to_vec()))
+        });
+        assert_eq!(
+            resolved,
+            vec![
+                (1, vec![0x100, 0x200]),
+                (2, vec![0x100, 0x200]),
+                (3, vec![0x400]),
+                (4, vec![0x300]),
+            ]
+        );
+        assert_eq!(unresolved, 1);
+    }
+}
@@ -0,0 +1,168 @@
+//! What an ETL trace contains, to tell which ETW providers are missing from
+//! a trace which would otherwise import as an empty profile, e.g. because the
+//! WPR profile it was recorded with doesn't sample the CPU or walk stacks.
+
+use std::fmt;
+
+use crate::shared::prop_types::EtlSampleSource;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("I/O Error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("The trace lacks the events which the samples are made from. Missing ETW providers:{}", list(.0))]
+    MissingEvents(Vec<MissingEvents>),
+
+    #[error("The trace was recorded with stack caching: its samples refer to cached stacks by key. Import it with --stack-caching")]
+    StackCaching,
+}
+
+fn list(missing: &[MissingEvents]) -> String {
+    missing
+        .iter()
+        .map(|events| format!("\n  - {events}"))
+        .collect()
+}
+
+/// Events which a trace needs and doesn't have
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissingEvents {
+    ProcessesAndThreads,
+    CpuSamples,
+    ContextSwitches,
+    CpuSamplesOrContextSwitches,
+    Stacks,
+    StackKeyDefinitions,
+}
+
+impl fmt::Display for MissingEvents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MissingEvents::ProcessesAndThreads => write!(
+                f,
+                "NT Kernel Logger, PROC_THREAD: process and thread start and rundown events"
+            ),
+            MissingEvents::CpuSamples => {
+                write!(f, "NT Kernel Logger, PROFILE: CPU samples (PerfInfo/SampleProf)")
+            }
+            MissingEvents::ContextSwitches => {
+                write!(f, "NT Kernel Logger, CSWITCH: context switches (Thread/CSwitch)")
+            }
+            MissingEvents::CpuSamplesOrContextSwitches => write!(
+                f,
+                "NT Kernel Logger, PROFILE or CSWITCH: CPU samples or context switches"
+            ),
+            MissingEvents::Stacks => write!(
+                f,
+                "NT Kernel Logger stack walks (xperf -stackwalk Profile+CSwitch, WPR <Stacks>): the stacks of the samples"
+            ),
+            MissingEvents::StackKeyDefinitions => write!(
+                f,
+                "NT Kernel Logger stack cache rundown: the stacks of the cached stack keys (StackWalk/KeyDelete, KeyRundown)"
+            ),
+        }
+    }
+}
+
+/// Counts of the events which samples are made from
+#[derive(Debug, Default, Clone)]
+pub struct TraceContents {
+    /// Process/Start and Process/DCStart
+    pub processes: usize,
+    /// PerfInfo/SampleProf
+    pub cpu_samples: usize,
+    /// Thread/CSwitch
+    pub context_switches: usize,
+    /// StackWalk/Stack
+    pub stacks: usize,
+    /// StackWalk/KeyKernel and KeyUser
+    pub stack_key_refs: usize,
+    /// StackWalk/KeyCreate, KeyDelete and KeyRundown
+    pub stack_key_defs: usize,
+}
+
+impl TraceContents {
+    /// Check that the trace has the events which the samples of `expected`
+    /// are made from, or any samples if the recording profile isn't known.
+    pub fn check(
+        &self,
+        expected: Option<EtlSampleSource>,
+        stack_caching: bool,
+    ) -> Result<(), Error> {
+        if self.stack_key_refs > 0 && !stack_caching {
+            return Err(Error::StackCaching);
+        }
+
+        let mut missing = Vec::new();
+        if self.processes == 0 {
+            missing.push(MissingEvents::ProcessesAndThreads);
+        }
+        let (needs_cpu_samples, needs_context_switches) = match expected {
+            None => {
+                if self.cpu_samples == 0 && self.context_switches == 0 {
+                    missing.push(MissingEvents::CpuSamplesOrContextSwitches);
+                }
+                (false, false)
+            }
+            Some(EtlSampleSource::Sampled) => (true, false),
+            Some(EtlSampleSource::ContextSwitches) => (false, true),
+            Some(EtlSampleSource::Mixed) => (true, true),
+        };
+        if needs_cpu_samples && self.cpu_samples == 0 {
+            missing.push(MissingEvents::CpuSamples);
+        }
+        if needs_context_switches && self.context_switches == 0 {
+            missing.push(MissingEvents::ContextSwitches);
+        }
+        if self.stacks == 0 && self.stack_key_refs == 0 {
+            missing.push(MissingEvents::Stacks);
+        } else if self.stack_key_refs > 0 && self.stack_key_defs == 0 {
+            missing.push(MissingEvents::StackKeyDefinitions);
+        }
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::MissingEvents(missing))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let cswitch_only = TraceContents {
+            processes: 10,
+            context_switches: 1000,
+            stacks: 1000,
+            ..Default::default()
+        };
+        assert!(cswitch_only.check(None, false).is_ok());
+        assert!(cswitch_only
+            .check(Some(EtlSampleSource::ContextSwitches), false)
+            .is_ok());
+        assert!(matches!(
+            cswitch_only.check(Some(EtlSampleSource::Mixed), false),
+            Err(Error::MissingEvents(missing)) if missing == [MissingEvents::CpuSamples]
+        ));
+
+        let cached = TraceContents {
+            processes: 10,
+            cpu_samples: 1000,
+            stack_key_refs: 2000,
+            ..Default::default()
+        };
+        assert!(matches!(
+            cached.check(None, false),
+            Err(Error::StackCaching)
+        ));
+        assert!(matches!(
+            cached.check(Some(EtlSampleSource::Sampled), true),
+            Err(Error::MissingEvents(missing)) if missing == [MissingEvents::StackKeyDefinitions]
+        ));
+    }
+}
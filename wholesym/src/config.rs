@@ -21,6 +21,8 @@ pub struct SymbolManagerConfig {
     pub(crate) debuginfod_servers: Vec<(String, PathBuf)>,
     pub(crate) extra_symbol_directories: Vec<PathBuf>,
     pub(crate) simpleperf_binary_cache_directories: Vec<PathBuf>,
+    pub(crate) offline: bool,
+    pub(crate) max_requests_per_origin: Option<u32>,
 }
 
 impl SymbolManagerConfig {
@@ -184,4 +186,23 @@ impl SymbolManagerConfig {
         self.simpleperf_binary_cache_directories.push(dir.into());
         self
     }
+
+    /// Whether to never contact symbol servers, debuginfod servers or source
+    /// file URLs. Files are then only found in local directories and in the
+    /// caches of the servers.
+    ///
+    /// The lookups which would have needed the network are listed by
+    /// [`SymbolManager::skipped_network_lookups`](crate::SymbolManager::skipped_network_lookups).
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Send at most this many requests to each server origin
+    /// (`scheme://host:port`). Once every server which could have a file has
+    /// used up its budget, the file is only looked up in the caches.
+    pub fn max_requests_per_origin(mut self, max_requests: Option<u32>) -> Self {
+        self.max_requests_per_origin = max_requests;
+        self
+    }
 }
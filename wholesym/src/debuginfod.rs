@@ -43,7 +43,6 @@ impl DebuginfodDownloader {
         }
     }
 
-    #[allow(unused)]
     pub async fn get_file_only_cached(&self, buildid: &str, file_type: &str) -> Option<PathBuf> {
         match &self.0 {
             DebuginfodDownloaderInner::Official(official) => {
@@ -11,13 +11,14 @@ use samply_symbols::{
     CandidatePathInfo, FileAndPathHelper, FileAndPathHelperResult, FileLocation, LibraryInfo,
     OptionallySendFuture, SymbolMapTrait,
 };
-use symsrv::{SymsrvDownloader, SymsrvObserver};
+use symsrv::{NtSymbolPathEntry, SymsrvDownloader, SymsrvObserver};
 use uuid::Uuid;
 
 use crate::breakpad::BreakpadSymbolDownloader;
 use crate::config::SymbolManagerConfig;
 use crate::debuginfod::DebuginfodDownloader;
 use crate::downloader::{Downloader, DownloaderObserver};
+use crate::network::{origin, NetworkPolicy, SkippedLookup};
 use crate::vdso::get_vdso_data;
 use crate::{DownloadError, SymbolManagerObserver};
 
@@ -252,6 +253,11 @@ pub struct Helper {
     config: SymbolManagerConfig,
     precog_symbol_data: Mutex<HashMap<DebugId, Arc<dyn SymbolMapTrait + Send + Sync>>>,
     observer: Arc<HelperDownloaderObserver>,
+    network: Arc<NetworkPolicy>,
+    /// The origins of the servers of each kind, for the request budgets
+    symsrv_origins: Vec<String>,
+    breakpad_origins: Vec<String>,
+    debuginfod_origins: Vec<String>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -264,9 +270,39 @@ struct KnownLibs {
 
 impl Helper {
     pub fn with_config(config: SymbolManagerConfig) -> Self {
-        let observer = Arc::new(HelperDownloaderObserver::new());
+        let network = Arc::new(NetworkPolicy::new(
+            config.offline,
+            config.max_requests_per_origin,
+        ));
+        let observer = Arc::new(HelperDownloaderObserver::new(network.clone()));
         let downloader = Arc::new(Downloader::new());
-        let symsrv_downloader = match config.effective_nt_symbol_path() {
+        let nt_symbol_path = config.effective_nt_symbol_path();
+        let symsrv_origins = nt_symbol_path
+            .iter()
+            .flatten()
+            .flat_map(|entry| match entry {
+                NtSymbolPathEntry::Chain { urls, .. } => {
+                    urls.iter().map(|url| origin(url)).collect()
+                }
+                _ => Vec::new(),
+            })
+            .collect();
+        let breakpad_origins = config
+            .breakpad_servers
+            .iter()
+            .map(|(url, _)| origin(url))
+            .collect();
+        let mut debuginfod_origins: Vec<String> = config
+            .debuginfod_servers
+            .iter()
+            .map(|(url, _)| origin(url))
+            .collect();
+        if config.use_debuginfod {
+            if let Ok(urls) = std::env::var("DEBUGINFOD_URLS") {
+                debuginfod_origins.extend(urls.split_ascii_whitespace().map(origin));
+            }
+        }
+        let symsrv_downloader = match nt_symbol_path {
             Some(nt_symbol_path) => {
                 let mut downloader = SymsrvDownloader::new(nt_symbol_path);
                 downloader.set_default_downstream_store(symsrv::get_home_sym_dir());
@@ -302,9 +338,21 @@ impl Helper {
             config,
             precog_symbol_data: Mutex::new(Default::default()),
             observer,
+            network,
+            symsrv_origins,
+            breakpad_origins,
+            debuginfod_origins,
         }
     }
 
+    pub fn skipped_network_lookups(&self) -> Vec<SkippedLookup> {
+        self.network.skipped_lookups()
+    }
+
+    pub fn requests_per_origin(&self) -> Vec<(String, u32)> {
+        self.network.requests_per_origin()
+    }
+
     pub fn set_observer(&self, observer: Option<Arc<dyn SymbolManagerObserver>>) {
         self.observer.set_observer(observer);
     }
@@ -381,6 +429,12 @@ impl Helper {
                 .await
                 .ok_or("Not found on breakpad symbol server")?,
             WholesymFileLocation::UrlForSourceFile(url) => {
+                let origins = [origin(&url)];
+                if let Some(reason) = self.network.blocked(&origins) {
+                    self.network
+                        .record_skipped(url.clone(), &origins, reason.clone());
+                    return Err(format!("Not downloading {url}: {reason}").into());
+                }
                 let download = self
                     .downloader
                     .initiate_download(&url, Some(self.observer.clone()))
@@ -389,17 +443,45 @@ impl Helper {
                 return Ok(WholesymFileContents::Bytes(bytes.into()));
             }
             WholesymFileLocation::SymsrvFile(filename, hash) => {
-                self.symsrv_downloader
-                    .as_ref()
-                    .unwrap()
-                    .get_file(&filename, &hash)
-                    .await?
+                let symsrv_downloader = self.symsrv_downloader.as_ref().unwrap();
+                match self.network.blocked(&self.symsrv_origins) {
+                    None => symsrv_downloader.get_file(&filename, &hash).await?,
+                    Some(reason) => match symsrv_downloader
+                        .get_file_no_download(&filename, &hash)
+                        .await
+                    {
+                        Ok(path) => path,
+                        Err(_) => {
+                            let file = format!("{filename}/{hash}/{filename}");
+                            self.network
+                                .record_skipped(file, &self.symsrv_origins, reason.clone());
+                            return Err(format!("{filename} is not cached ({reason})").into());
+                        }
+                    },
+                }
+            }
+            WholesymFileLocation::BreakpadSymbolServerFile(path) => {
+                match self.network.blocked(&self.breakpad_origins) {
+                    None => self
+                        .breakpad_downloader
+                        .get_file(&path)
+                        .await
+                        .ok_or("Not found on breakpad symbol server")?,
+                    Some(reason) => {
+                        match self.breakpad_downloader.get_file_no_download(&path).await {
+                            Some(path) => path,
+                            None => {
+                                self.network.record_skipped(
+                                    path.clone(),
+                                    &self.breakpad_origins,
+                                    reason.clone(),
+                                );
+                                return Err(format!("{path} is not cached ({reason})").into());
+                            }
+                        }
+                    }
+                }
             }
-            WholesymFileLocation::BreakpadSymbolServerFile(path) => self
-                .breakpad_downloader
-                .get_file(&path)
-                .await
-                .ok_or("Not found on breakpad symbol server")?,
             WholesymFileLocation::BreakpadSymindexFile(rel_path) => {
                 let sym_path = self
                     .breakpad_downloader
@@ -411,17 +493,11 @@ impl Helper {
                     .await?
             }
             WholesymFileLocation::DebuginfodDebugFile(build_id) => self
-                .debuginfod_downloader
-                .as_ref()
-                .unwrap()
-                .get_file(&build_id.to_string(), "debuginfo")
+                .get_debuginfod_file(&build_id.to_string(), "debuginfo")
                 .await
                 .ok_or("Debuginfod could not find debuginfo")?,
             WholesymFileLocation::DebuginfodExecutable(build_id) => self
-                .debuginfod_downloader
-                .as_ref()
-                .unwrap()
-                .get_file(&build_id.to_string(), "executable")
+                .get_debuginfod_file(&build_id.to_string(), "executable")
                 .await
                 .ok_or("Debuginfod could not find executable")?,
             WholesymFileLocation::VdsoLoadedIntoThisProcess => {
@@ -442,6 +518,24 @@ impl Helper {
         }))
     }
 
+    /// Get a file from debuginfod, or only from its caches if the network
+    /// policy doesn't allow downloading it.
+    async fn get_debuginfod_file(&self, build_id: &str, file_type: &str) -> Option<PathBuf> {
+        let debuginfod_downloader = self.debuginfod_downloader.as_ref().unwrap();
+        let Some(reason) = self.network.blocked(&self.debuginfod_origins) else {
+            return debuginfod_downloader.get_file(build_id, file_type).await;
+        };
+        let path = debuginfod_downloader
+            .get_file_only_cached(build_id, file_type)
+            .await;
+        if path.is_none() {
+            let file = format!("{build_id}/{file_type}");
+            self.network
+                .record_skipped(file, &self.debuginfod_origins, reason);
+        }
+        path
+    }
+
     fn fill_in_library_info_details(&self, info: &mut LibraryInfo) {
         let known_libs = self.known_libs.lock().unwrap();
 
@@ -869,15 +963,24 @@ impl FileAndPathHelper for Helper {
         &self,
         info: &LibraryInfo,
     ) -> Option<(Self::FL, Arc<dyn SymbolMapTrait + Send + Sync>)> {
-        let precog_symbol_data = self.precog_symbol_data.lock().unwrap();
-        let symbol_map = precog_symbol_data.get(&info.debug_id?)?;
+        let symbol_map = self
+            .precog_symbol_data
+            .lock()
+            .unwrap()
+            .get(&info.debug_id?)?
+            .clone();
+        // Requests from the symbolication API only have the debug name and ID.
+        // Take the debug path from the known library, so that relative source
+        // file paths are resolved next to its debug file, as they are for
+        // symbols from the debug file itself.
+        let mut info = info.clone();
+        self.fill_in_library_info_details(&mut info);
         let location = WholesymFileLocation::LocalFile(
             info.debug_path
-                .clone()
                 .unwrap_or_else(|| "UNKNOWN".to_string())
                 .into(),
         );
-        Some((location, symbol_map.clone()))
+        Some((location, symbol_map))
     }
 }
 
@@ -929,6 +1032,8 @@ fn might_be_fake_jit_file(info: &LibraryInfo) -> bool {
 
 struct HelperDownloaderObserver {
     inner: Mutex<HelperDownloaderObserverInner>,
+    /// Counts the requests against the budgets of their origins
+    network: Arc<NetworkPolicy>,
 }
 
 struct HelperDownloaderObserverInner {
@@ -938,7 +1043,7 @@ struct HelperDownloaderObserverInner {
 }
 
 impl HelperDownloaderObserver {
-    pub fn new() -> Self {
+    pub fn new(network: Arc<NetworkPolicy>) -> Self {
         let inner = HelperDownloaderObserverInner {
             observer: None,
             symsrv_download_id_mapping: HashMap::new(),
@@ -946,6 +1051,7 @@ impl HelperDownloaderObserver {
         };
         Self {
             inner: Mutex::new(inner),
+            network,
         }
     }
 
@@ -977,6 +1083,7 @@ static NEXT_DOWNLOAD_ID: AtomicU64 = AtomicU64::new(0);
 
 impl SymsrvObserver for HelperDownloaderObserver {
     fn on_new_download_before_connect(&self, symsrv_download_id: u64, url: &str) {
+        self.network.on_request(url);
         let download_id = NEXT_DOWNLOAD_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
         let mut inner = self.inner.lock().unwrap();
         inner
@@ -1124,6 +1231,7 @@ impl SymsrvObserver for HelperDownloaderObserver {
 
 impl DownloaderObserver for HelperDownloaderObserver {
     fn on_new_download_before_connect(&self, downloader_download_id: u64, url: &str) {
+        self.network.on_request(url);
         let download_id = NEXT_DOWNLOAD_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
         let mut inner = self.inner.lock().unwrap();
         inner
@@ -144,6 +144,7 @@ mod helper;
 mod moria_mac;
 #[cfg(target_os = "macos")]
 mod moria_mac_spotlight;
+mod network;
 mod symbol_manager;
 mod symbol_manager_observer;
 mod vdso;
@@ -151,14 +152,15 @@ mod verbose_symbol_manager_observer;
 
 pub use config::SymbolManagerConfig;
 pub use download_error::DownloadError;
+pub use network::{SkipReason, SkippedLookup};
 pub use samply_debugid::{CodeId, ElfBuildId, PeCodeId};
 pub use samply_symbols;
 pub use samply_symbols::{
     AddressInfo, Error, ExternalFileAddressInFileRef, ExternalFileAddressRef, ExternalFileRef,
     ExternalFileSymbolMap, FrameDebugInfo, FramesLookupResult, FunctionNameHandle,
     FunctionNameIndex, LibraryInfo, LookupAddress, MappedPath, MultiArchDisambiguator,
-    SourceFilePath, SourceFilePathHandle, SourceFilePathIndex, SymbolInfo, SymbolMapGeneration,
-    SymbolNameHandle, SymbolNameIndex, SyncAddressInfo,
+    SourceFileChecksum, SourceFilePath, SourceFilePathHandle, SourceFilePathIndex, SymbolInfo,
+    SymbolMapGeneration, SymbolNameHandle, SymbolNameIndex, SyncAddressInfo,
 };
 pub use symbol_manager::{SymbolFileOrigin, SymbolManager, SymbolMap};
 pub use symbol_manager_observer::SymbolManagerObserver;
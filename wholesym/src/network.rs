@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+/// Why a lookup of a symbol file didn't go to a symbol server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The symbol manager is in offline mode.
+    Offline,
+    /// Every server the file could have come from has used up its request
+    /// budget.
+    BudgetExhausted,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::Offline => write!(f, "offline mode"),
+            SkipReason::BudgetExhausted => write!(f, "request budget used up"),
+        }
+    }
+}
+
+/// A lookup which would have needed the network: the file wasn't in any local
+/// directory or cache, and the symbol servers weren't asked for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedLookup {
+    /// The file, e.g. `xul.pdb/<hash>/xul.pdb`, or the URL of a source file
+    pub file: String,
+    /// The origins (`scheme://host:port`) of the servers which could have
+    /// had the file
+    pub origins: Vec<String>,
+    pub reason: SkipReason,
+}
+
+/// Decides which lookups may go to symbol servers: none in offline mode, and
+/// at most `max_requests_per_origin` requests to each server. The requests
+/// are counted as they're made, for all kinds of servers.
+#[derive(Debug, Default)]
+pub(crate) struct NetworkPolicy {
+    offline: bool,
+    max_requests_per_origin: Option<u32>,
+    requests: Mutex<HashMap<String, u32>>,
+    skipped: Mutex<Vec<SkippedLookup>>,
+}
+
+impl NetworkPolicy {
+    pub fn new(offline: bool, max_requests_per_origin: Option<u32>) -> Self {
+        Self {
+            offline,
+            max_requests_per_origin,
+            ..Default::default()
+        }
+    }
+
+    /// Why a lookup which could go to any of the servers at `origins` may
+    /// not use the network, if it may not.
+    pub fn blocked(&self, origins: &[String]) -> Option<SkipReason> {
+        if self.offline {
+            return Some(SkipReason::Offline);
+        }
+        let max = self.max_requests_per_origin?;
+        let requests = self.requests.lock().unwrap();
+        let exhausted = |origin: &String| requests.get(origin).is_some_and(|&count| count >= max);
+        if !origins.is_empty() && origins.iter().all(exhausted) {
+            Some(SkipReason::BudgetExhausted)
+        } else {
+            None
+        }
+    }
+
+    /// Remember a lookup which `blocked` kept from the network and which
+    /// failed without it.
+    pub fn record_skipped(&self, file: String, origins: &[String], reason: SkipReason) {
+        self.skipped.lock().unwrap().push(SkippedLookup {
+            file,
+            origins: origins.to_vec(),
+            reason,
+        });
+    }
+
+    /// Count a request which is about to be sent to `url`.
+    pub fn on_request(&self, url: &str) {
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry(origin(url))
+            .or_insert(0) += 1;
+    }
+
+    pub fn skipped_lookups(&self) -> Vec<SkippedLookup> {
+        self.skipped.lock().unwrap().clone()
+    }
+
+    pub fn requests_per_origin(&self) -> Vec<(String, u32)> {
+        let mut requests: Vec<(String, u32)> = self
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(origin, &count)| (origin.clone(), count))
+            .collect();
+        requests.sort();
+        requests
+    }
+}
+
+/// The `scheme://host:port` of a URL, which budgets are counted per
+pub(crate) fn origin(url: &str) -> String {
+    match url.parse::<http::Uri>() {
+        Ok(uri) => match (uri.scheme_str(), uri.authority()) {
+            (Some(scheme), Some(authority)) => format!("{scheme}://{authority}"),
+            _ => url.to_string(),
+        },
+        Err(_) => url.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget_per_origin() {
+        let policy = NetworkPolicy::new(false, Some(2));
+        let msdl = origin("https://msdl.microsoft.com/download/symbols");
+        let mozilla = origin("https://symbols.mozilla.org/");
+        assert_eq!(msdl, "https://msdl.microsoft.com");
+        let both = [msdl.clone(), mozilla.clone()];
+
+        policy.on_request("https://msdl.microsoft.com/download/symbols/a.pdb/1/a.pdb");
+        policy.on_request("https://msdl.microsoft.com/download/symbols/b.pdb/1/b.pdb");
+        assert_eq!(policy.blocked(&both), None);
+        assert_eq!(
+            policy.blocked(&[msdl.clone()]),
+            Some(SkipReason::BudgetExhausted)
+        );
+        policy.record_skipped("a.pdb".to_string(), &[msdl], SkipReason::BudgetExhausted);
+        assert_eq!(policy.skipped_lookups().len(), 1);
+
+        let offline = NetworkPolicy::new(true, None);
+        assert_eq!(offline.blocked(&[mozilla]), Some(SkipReason::Offline));
+    }
+}
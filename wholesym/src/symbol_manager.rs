@@ -5,19 +5,35 @@ use std::sync::Arc;
 use debugid::DebugId;
 use samply_symbols::{
     self, AddressInfo, Error, ExternalFileAddressInFileRef, ExternalFileAddressRef, FrameDebugInfo,
-    FunctionNameHandle, LibraryInfo, LookupAddress, MultiArchDisambiguator, SourceFilePath,
-    SourceFilePathHandle, SymbolMapStringInterner, SymbolMapTrait, SymbolNameHandle,
-    SyncAddressInfo,
+    FunctionNameHandle, LibraryInfo, LookupAddress, MultiArchDisambiguator, SourceFileChecksum,
+    SourceFilePath, SourceFilePathHandle, SymbolMapStringInterner, SymbolMapTrait,
+    SymbolNameHandle, SyncAddressInfo,
 };
 
 use crate::config::SymbolManagerConfig;
 use crate::helper::{FileReadOnlyHelper, Helper, WholesymFileContents, WholesymFileLocation};
+use crate::network::SkippedLookup;
 use crate::SymbolManagerObserver;
 
 /// Used in [`SymbolManager::load_external_file`] and returned by [`SymbolMap::symbol_file_origin`].
 #[derive(Debug, Clone)]
 pub struct SymbolFileOrigin(WholesymFileLocation);
 
+impl SymbolFileOrigin {
+    /// Whether the symbol file was obtained from a symbol server or from debuginfod
+    /// (possibly through a local cache of such downloads), as opposed to a local path.
+    pub fn is_from_server(&self) -> bool {
+        matches!(
+            self.0,
+            WholesymFileLocation::SymsrvFile(..)
+                | WholesymFileLocation::BreakpadSymbolServerFile(_)
+                | WholesymFileLocation::BreakpadSymindexFile(_)
+                | WholesymFileLocation::DebuginfodDebugFile(_)
+                | WholesymFileLocation::DebuginfodExecutable(_)
+        )
+    }
+}
+
 /// Contains the symbols for a binary, and allows querying them by address and iterating over them.
 ///
 /// Symbols can be looked up by three types of addresses:
@@ -132,6 +148,17 @@ impl SymbolMap {
         self.0.resolve_source_file_path(handle)
     }
 
+    /// The checksum which the debug info records for the source file `path`
+    /// (a raw path from a lookup), in the compilation unit containing `address`.
+    /// Only DWARF 5 debug info has checksums.
+    pub fn source_file_checksum(
+        &self,
+        address: LookupAddress,
+        path: &str,
+    ) -> Option<SourceFileChecksum> {
+        self.0.source_file_checksum(address, path)
+    }
+
     pub fn resolve_function_name(&self, handle: FunctionNameHandle) -> Cow<'_, str> {
         self.0.resolve_function_name(handle)
     }
@@ -243,6 +270,20 @@ impl SymbolManager {
         self.symbol_manager.helper().set_observer(observer);
     }
 
+    /// The lookups which didn't go to a symbol server because of
+    /// [`offline`](SymbolManagerConfig::offline) mode or an exhausted
+    /// [request budget](SymbolManagerConfig::max_requests_per_origin), and
+    /// which couldn't be served from the local caches.
+    pub fn skipped_network_lookups(&self) -> Vec<SkippedLookup> {
+        self.symbol_manager.helper().skipped_network_lookups()
+    }
+
+    /// The number of requests made to each symbol server origin so far,
+    /// sorted by origin.
+    pub fn requests_per_origin(&self) -> Vec<(String, u32)> {
+        self.symbol_manager.helper().requests_per_origin()
+    }
+
     /// Tell the `SymbolManager` about a known library. This allows it to find
     /// debug files or binaries later based on a subset of the library information.
     ///
@@ -252,7 +293,7 @@ impl SymbolManager {
     /// information which allows us to find the right debug files for the request.
     /// The list of "known libraries" is this auxiliary information.
     #[cfg(feature = "api")]
-    pub fn add_known_library(&mut self, lib_info: LibraryInfo) {
+    pub fn add_known_library(&self, lib_info: LibraryInfo) {
         self.symbol_manager.helper().add_known_lib(lib_info);
     }
 